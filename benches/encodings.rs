@@ -0,0 +1,156 @@
+//! Encoding and decoding throughput of each column format, on data shaped
+//! like that found in real tables.
+//!
+//! Before measuring, the size of the data in each format that can hold it is
+//! printed relative to its raw size, alongside the format that
+//! [`RawColumn::write_values`] picks.  Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use equilia::{ColumnFormat, RawColumn, RawKind, RawValue};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ROWS: u64 = 100_000;
+
+/// Generates the values of a column
+type Distribution = fn(&mut StdRng) -> Vec<RawValue>;
+
+/// Ids of rows inserted in order, with the odd gap left by a deleted row
+fn sorted_ids(rng: &mut StdRng) -> Vec<RawValue> {
+    let mut id = 1_000_000;
+    (0..ROWS)
+        .map(|_| {
+            id += if rng.gen_ratio(1, 50) { 2 } else { 1 };
+            RawValue::U64(id)
+        })
+        .collect()
+}
+
+/// Timestamps in microseconds, of events arriving a few milliseconds apart
+fn timestamps(rng: &mut StdRng) -> Vec<RawValue> {
+    let mut t = 1_700_000_000_000_000;
+    (0..ROWS)
+        .map(|_| {
+            t += rng.gen_range(0..5_000);
+            RawValue::U64(t)
+        })
+        .collect()
+}
+
+/// Small counts, with the occasional very large one
+fn skewed_counts(rng: &mut StdRng) -> Vec<RawValue> {
+    (0..ROWS)
+        .map(|_| {
+            if rng.gen_ratio(1, 1000) {
+                RawValue::U64(rng.gen_range(0..1 << 40))
+            } else {
+                RawValue::U64(rng.gen_range(0..20))
+            }
+        })
+        .collect()
+}
+
+/// A column that is almost always zero
+fn mostly_default(rng: &mut StdRng) -> Vec<RawValue> {
+    (0..ROWS)
+        .map(|_| RawValue::U64(if rng.gen_ratio(1, 200) { rng.gen() } else { 0 }))
+        .collect()
+}
+
+/// Words drawn from a vocabulary with a Zipf distribution, as the words of
+/// text or the pages of a web site are
+fn zipfian_strings(rng: &mut StdRng) -> Vec<RawValue> {
+    let words = (0..5_000)
+        .map(|i| format!("/page/{i}/{}", i * 7919 % 1000))
+        .collect::<Vec<_>>();
+    let mut cumulative = Vec::with_capacity(words.len());
+    let mut total = 0.0;
+    for rank in 1..=words.len() {
+        total += 1.0 / rank as f64;
+        cumulative.push(total);
+    }
+    (0..ROWS)
+        .map(|_| {
+            let x = rng.gen_range(0.0..total);
+            let i = cumulative.partition_point(|c| *c < x);
+            RawValue::Bytes(words[i.min(words.len() - 1)].clone().into_bytes())
+        })
+        .collect()
+}
+
+fn raw_size(values: &[RawValue]) -> usize {
+    values
+        .iter()
+        .map(|v| match v {
+            RawValue::U64(_) => 8,
+            RawValue::Bool(_) => 1,
+            RawValue::Bytes(b) => b.len(),
+        })
+        .sum()
+}
+
+fn encodings(c: &mut Criterion) {
+    let distributions: [(&str, Distribution); 5] = [
+        ("sorted_ids", sorted_ids),
+        ("timestamps", timestamps),
+        ("skewed_counts", skewed_counts),
+        ("mostly_default", mostly_default),
+        ("zipfian_strings", zipfian_strings),
+    ];
+    for (name, generate) in distributions {
+        let values = generate(&mut StdRng::seed_from_u64(0));
+        let kind = values[0].kind();
+        let formats = ColumnFormat::ALL
+            .iter()
+            .copied()
+            .filter(|f| f.kind() == kind && f.estimate_size(&values).is_some())
+            .collect::<Vec<_>>();
+
+        let mut picked = Vec::new();
+        RawColumn::write_values(&mut picked, kind, &values).unwrap();
+        let picked = RawColumn::decode(picked).unwrap().format();
+        eprintln!("{name}: {} raw bytes", raw_size(&values));
+        for &format in formats.iter() {
+            let size = format.estimate_size(&values).unwrap();
+            let ratio = size as f64 / raw_size(&values) as f64;
+            let mark = if format == picked { " (picked)" } else { "" };
+            eprintln!("  {format:?}: {size} bytes, {ratio:.3} of raw{mark}");
+        }
+
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(ROWS));
+        group.sample_size(20);
+        group.bench_function("encode/picked", |b| {
+            b.iter(|| {
+                let mut out = Vec::new();
+                RawColumn::write_values(&mut out, kind, black_box(&values)).unwrap();
+                out
+            })
+        });
+        for format in formats {
+            let id = format!("{format:?}");
+            group.bench_with_input(BenchmarkId::new("encode", &id), &values, |b, values| {
+                b.iter(|| {
+                    let mut out = Vec::new();
+                    format.encode(&mut out, black_box(values)).unwrap();
+                    out
+                })
+            });
+            let mut encoded = Vec::new();
+            format.encode(&mut encoded, &values).unwrap();
+            group.bench_with_input(BenchmarkId::new("decode", &id), &encoded, |b, encoded| {
+                b.iter(|| {
+                    let column = RawColumn::decode(black_box(encoded.clone())).unwrap();
+                    match kind {
+                        RawKind::U64 => column.read_u64().unwrap().len(),
+                        _ => column.read_values().unwrap().len(),
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, encodings);
+criterion_main!(benches);
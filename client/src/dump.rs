@@ -0,0 +1,218 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use equilia::column::encoding::StorageError;
+use equilia::column::format::describe;
+use equilia::{load_db_schema, Table, TableFiles};
+
+const USAGE: &str = "usage: equilia-dump [--values] PATH...
+
+  --values    print each chunk of each column, with its value
+
+Print what each PATH holds.  For a column file, that is the version of its
+encoding, its format and magic number, its numbers of rows and chunks, and
+its smallest and largest values.  For the directory of a table, it is the
+schema of the table, taken from the database holding it or else pieced
+together from its column files, followed by each column file of each
+segment.  For the directory of a database, it is the schema of each table.
+The exit status is 1 if any PATH could not be read, and 2 if the arguments
+make no sense.";
+
+/// What to dump, from the command line
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    paths: Vec<PathBuf>,
+    values: bool,
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        for arg in args {
+            match arg.as_str() {
+                "--values" => options.values = true,
+                _ if arg.starts_with('-') => return Err(format!("unexpected argument {arg}")),
+                _ => options.paths.push(arg.into()),
+            }
+        }
+        if options.paths.is_empty() {
+            return Err("no PATH given".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Describe the column file, table or database at `path`
+fn dump(path: &Path, values: bool) -> Result<String, String> {
+    let error = |e: StorageError| format!("unable to read {}: {e}", path.display());
+    if path.is_file() {
+        let bytes = std::fs::read(path).map_err(|e| error(e.into()))?;
+        let description = describe(&bytes).map_err(error)?;
+        return Ok(if values {
+            format!("{description:#}")
+        } else {
+            description.to_string()
+        });
+    }
+    match Table::inspect(path) {
+        Ok(files) => Ok(dump_table(path, &files, values)),
+        Err(StorageError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            let schemas = load_db_schema(path).map_err(error)?;
+            if schemas.is_empty() {
+                return Err(format!(
+                    "{} is not a column file, table or database",
+                    path.display()
+                ));
+            }
+            Ok(schemas.iter().map(|s| s.to_string()).collect())
+        }
+        Err(e) => Err(error(e)),
+    }
+}
+
+/// Describe the files of the table in `dir`, with its schema if the database
+/// holding it has one
+fn dump_table(dir: &Path, files: &TableFiles, values: bool) -> String {
+    let schemas = dir
+        .parent()
+        .and_then(|db| load_db_schema(db).ok())
+        .unwrap_or_default();
+    let schema = files.schema_in(&schemas);
+    let mut out = String::new();
+    match schema {
+        Some(schema) => write!(out, "{schema}").unwrap(),
+        None => {
+            writeln!(out, "raw columns, pieced together from the column files:").unwrap();
+            for (id, field, kind) in files.raw_columns() {
+                let kind = kind.map_or("unknown".to_string(), |k| format!("{k:?}"));
+                writeln!(out, "    {id}.{field}: {kind}").unwrap();
+            }
+        }
+    }
+    writeln!(
+        out,
+        "version {}, {} segments",
+        files.version(),
+        files.segments().len()
+    )
+    .unwrap();
+    for s in files.segments() {
+        writeln!(out, "segment {}: {} rows", s.id(), s.num_rows()).unwrap();
+        for c in s.columns() {
+            let name = schema
+                .and_then(|schema| {
+                    schema
+                        .raw_columns()
+                        .find(|r| r.id() == c.column() && r.fieldname() == c.fieldname())
+                })
+                .map_or_else(
+                    || format!("{}.{}", c.column(), c.fieldname()),
+                    |r| r.display_name(),
+                );
+            let place = c.file().unwrap_or("the manifest");
+            writeln!(out, "  {name} in {place}").unwrap();
+            let described = match c.description() {
+                Ok(d) if values => format!("{d:#}"),
+                Ok(d) => d.to_string(),
+                Err(e) => format!("error: {e}\n"),
+            };
+            for line in described.lines() {
+                writeln!(out, "    {line}").unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let mut status = ExitCode::SUCCESS;
+    for path in options.paths.iter() {
+        match dump(path, options.values) {
+            Ok(dumped) => {
+                if options.paths.len() > 1 {
+                    println!("{}:", path.display());
+                }
+                print!("{dumped}");
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+#[test]
+fn parse_options() {
+    let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+    assert_eq!(
+        parse(&["a.col", "--values", "table"]),
+        Ok(Options {
+            paths: vec!["a.col".into(), "table".into()],
+            values: true,
+        })
+    );
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["--verbose", "a.col"]).is_err());
+}
+
+#[test]
+fn dump_files() {
+    use equilia::{ColumnSchema, Database, RawColumn, RawValue, TableSchema};
+
+    let dir = tempfile::tempdir().unwrap();
+    let column = dir.path().join("ages.col");
+    let ages = [30, 30, 48].map(RawValue::U64);
+    let mut bytes = Vec::new();
+    RawColumn::write_values(&mut bytes, equilia::RawKind::U64, &ages).unwrap();
+    std::fs::write(&column, bytes).unwrap();
+    let dumped = dump(&column, false).unwrap();
+    assert!(
+        dumped.contains("3 rows in 3 chunks, from 30 to 48"),
+        "{dumped}"
+    );
+    assert_eq!(dumped.lines().count(), 2);
+    assert_eq!(dump(&column, true).unwrap().lines().count(), 5);
+
+    let path = dir.path().join("db");
+    let mut db = Database::open(&path).unwrap();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::<u64>::new("age").raw());
+    db.create_table(schema).unwrap();
+    db.execute("insert into people (name, age) values ('David', 48), ('Alice', 30)")
+        .unwrap();
+    drop(db);
+
+    let dumped = dump(&path, false).unwrap();
+    assert!(dumped.starts_with("CREATE TABLE people"), "{dumped}");
+    let table = std::fs::read_dir(&path)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.is_dir() && dump(p, false).unwrap().contains("TABLE people"))
+        .expect("the table has a directory");
+    let dumped = dump(&table, true).unwrap();
+    assert!(dumped.contains("  age in "), "{dumped}");
+    assert!(dumped.contains("'Alice'"), "{dumped}");
+
+    // Without its database, the columns of a table are named by their ids.
+    let moved = dir.path().join("moved");
+    std::fs::rename(&table, &moved).unwrap();
+    let dumped = dump(&moved, false).unwrap();
+    assert!(
+        dumped.starts_with("raw columns, pieced together"),
+        "{dumped}"
+    );
+    assert!(dumped.contains(": Bytes\n"), "{dumped}");
+
+    assert!(dump(&dir.path().join("nothing"), false).is_err());
+}
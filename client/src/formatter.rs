@@ -0,0 +1,315 @@
+//! Rendering the rows of query results.
+//!
+//! Rows are rendered as an aligned table for people to read, or as CSV or
+//! newline-delimited JSON for other programs, in the same forms `\copy` loads,
+//! or as an Arrow IPC stream for dataframe libraries.
+
+use std::io::Write;
+
+use equilia::RawValue;
+
+/// How rows are rendered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Columns padded to line up, under a header of their names
+    #[default]
+    Table,
+    /// Comma-separated values, with a header line of column names
+    Csv,
+    /// One JSON object per row, keyed by column name
+    Json,
+    /// An Arrow IPC stream, see [`equilia::write_arrow_ipc`]
+    Arrow,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "arrow" => Ok(Format::Arrow),
+            _ => Err(format!(
+                "unknown format {s}, expected table, csv, json or arrow"
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// Write `rows`, whose values are in the order of `columns`.
+    ///
+    /// Nothing is written when there are no columns.
+    pub fn write(
+        self,
+        out: &mut impl Write,
+        columns: &[String],
+        rows: &[Vec<RawValue>],
+    ) -> std::io::Result<()> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Format::Table => write_table(out, columns, rows),
+            Format::Csv => write_csv(out, columns, rows),
+            Format::Json => write_json(out, columns, rows),
+            Format::Arrow => equilia::write_arrow_ipc(out, columns, rows),
+        }
+    }
+}
+
+/// The text of a value in a table, with bytes that would disturb the layout
+/// escaped.
+fn table_cell(value: &RawValue) -> String {
+    let bytes = match value {
+        RawValue::Bytes(bytes) => bytes,
+        _ => return value.to_string(),
+    };
+    let mut cell = String::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(rest) {
+            Ok(valid) => (valid, 0),
+            Err(e) => (
+                std::str::from_utf8(&rest[..e.valid_up_to()]).expect("valid"),
+                e.error_len().unwrap_or(rest.len() - e.valid_up_to()),
+            ),
+        };
+        for c in valid.chars() {
+            match c {
+                '\\' => cell.push_str("\\\\"),
+                '\n' => cell.push_str("\\n"),
+                '\t' => cell.push_str("\\t"),
+                '\r' => cell.push_str("\\r"),
+                c if c.is_control() => cell.push_str(&format!("\\x{:02x}", c as u32)),
+                c => cell.push(c),
+            }
+        }
+        for b in &rest[valid.len()..valid.len() + invalid] {
+            cell.push_str(&format!("\\x{b:02x}"));
+        }
+        rest = &rest[valid.len() + invalid..];
+    }
+    cell
+}
+
+fn write_table(
+    out: &mut impl Write,
+    columns: &[String],
+    rows: &[Vec<RawValue>],
+) -> std::io::Result<()> {
+    let cells = rows
+        .iter()
+        .map(|row| row.iter().map(table_cell).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let width = |s: &str| s.chars().count();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            cells
+                .iter()
+                .map(|row| width(&row[i]))
+                .chain([width(name)])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    // Numbers are aligned on the right, and everything else on the left.
+    let numeric = (0..columns.len())
+        .map(|i| {
+            rows.first()
+                .map(|row| matches!(row[i], RawValue::U64(_)))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    let line = |values: &[String], numeric: &[bool]| {
+        let padded = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let padding = " ".repeat(widths[i] - width(v));
+                if numeric[i] {
+                    format!("{padding}{v}")
+                } else {
+                    format!("{v}{padding}")
+                }
+            })
+            .collect::<Vec<_>>();
+        padded.join(" | ").trim_end().to_string()
+    };
+    writeln!(out, "{}", line(columns, &numeric))?;
+    let rule = widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>();
+    writeln!(out, "{}", rule.join("-+-"))?;
+    for row in cells.iter() {
+        writeln!(out, "{}", line(row, &numeric))?;
+    }
+    Ok(())
+}
+
+/// Write a CSV field, quoting it if it holds a comma, quote or line break.
+///
+/// Bytes are written as they are, so that they load back unchanged.
+fn write_csv_field(out: &mut impl Write, field: &[u8]) -> std::io::Result<()> {
+    if field.iter().any(|b| b",\"\r\n".contains(b)) {
+        out.write_all(b"\"")?;
+        for (i, part) in field.split(|&b| b == b'"').enumerate() {
+            if i > 0 {
+                out.write_all(b"\"\"")?;
+            }
+            out.write_all(part)?;
+        }
+        out.write_all(b"\"")
+    } else {
+        out.write_all(field)
+    }
+}
+
+fn write_csv_line<'a>(
+    out: &mut impl Write,
+    fields: impl Iterator<Item = std::borrow::Cow<'a, [u8]>>,
+) -> std::io::Result<()> {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        write_csv_field(out, &field)?;
+    }
+    out.write_all(b"\n")
+}
+
+fn write_csv(
+    out: &mut impl Write,
+    columns: &[String],
+    rows: &[Vec<RawValue>],
+) -> std::io::Result<()> {
+    write_csv_line(out, columns.iter().map(|c| c.as_bytes().into()))?;
+    for row in rows {
+        write_csv_line(
+            out,
+            row.iter().map(|v| match v {
+                RawValue::Bytes(b) => b.as_slice().into(),
+                v => v.to_string().into_bytes().into(),
+            }),
+        )?;
+    }
+    Ok(())
+}
+
+/// A JSON string holding `s`
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// The JSON of a value.
+///
+/// Bytes that are UTF-8 become a string, and any others an array of numbers,
+/// since JSON strings cannot hold them.
+fn json_value(value: &RawValue) -> String {
+    match value {
+        RawValue::Bytes(bytes) => match std::str::from_utf8(bytes) {
+            Ok(s) => json_string(s),
+            Err(_) => {
+                let numbers = bytes.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+                format!("[{}]", numbers.join(","))
+            }
+        },
+        _ => value.to_string(),
+    }
+}
+
+fn write_json(
+    out: &mut impl Write,
+    columns: &[String],
+    rows: &[Vec<RawValue>],
+) -> std::io::Result<()> {
+    for row in rows {
+        let entries = columns
+            .iter()
+            .zip(row)
+            .map(|(c, v)| format!("{}: {}", json_string(c), json_value(v)))
+            .collect::<Vec<_>>();
+        writeln!(out, "{{{}}}", entries.join(", "))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn formats() {
+    let columns = ["name", "age", "happy"].map(String::from);
+    let rows = [
+        ("David", 48, true),
+        ("Dave, \"Jr\"\n\t\\", 7, false),
+        ("é", 123456, false),
+    ]
+    .iter()
+    .map(|&(name, age, happy)| {
+        vec![
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(age),
+            RawValue::Bool(happy),
+        ]
+    })
+    .chain([vec![
+        RawValue::Bytes(vec![b'a', 0xff, 1]),
+        RawValue::U64(0),
+        RawValue::Bool(true),
+    ]])
+    .collect::<Vec<_>>();
+    let render = |format: Format| {
+        let mut out = Vec::new();
+        format.write(&mut out, &columns, &rows).unwrap();
+        String::from_utf8_lossy(&out).into_owned()
+    };
+    expect_test::expect![[r#"
+        name             |    age | happy
+        -----------------+--------+------
+        David            |     48 | true
+        Dave, "Jr"\n\t\\ |      7 | false
+        é                | 123456 | false
+        a\xff\x01        |      0 | true
+    "#]]
+    .assert_eq(&render(Format::Table));
+    expect_test::expect![[r#"
+        name,age,happy
+        David,48,true
+        "Dave, ""Jr""
+        	\",7,false
+        é,123456,false
+        a�,0,true
+    "#]]
+    .assert_eq(&render(Format::Csv));
+    expect_test::expect![[r#"
+        {"name": "David", "age": 48, "happy": true}
+        {"name": "Dave, \"Jr\"\n\t\\", "age": 7, "happy": false}
+        {"name": "é", "age": 123456, "happy": false}
+        {"name": [97,255,1], "age": 0, "happy": true}
+    "#]]
+    .assert_eq(&render(Format::Json));
+    // An Arrow stream starts with a marker and ends with an empty message.
+    let mut out = Vec::new();
+    Format::Arrow.write(&mut out, &columns, &rows).unwrap();
+    assert_eq!(out[..4], [0xff; 4]);
+    assert_eq!(out[out.len() - 8..], [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+
+    let mut out = Vec::new();
+    Format::Table.write(&mut out, &[], &[]).unwrap();
+    assert!(out.is_empty());
+    assert_eq!("csv".parse(), Ok(Format::Csv));
+    assert_eq!("arrow".parse(), Ok(Format::Arrow));
+    assert!("xml".parse::<Format>().is_err());
+}
@@ -1,42 +1,521 @@
+use std::collections::BTreeSet;
 use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-enum Statement {
-    Select,
-    Insert,
-    Unknown,
+use equilia::{CsvLoader, Database, JsonLoader};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+mod formatter;
+
+use formatter::Format;
+
+const USAGE: &str =
+    "usage: client [--path DIR] [-f SCRIPT | -c SQL] [--format FORMAT] [--continue-on-error]
+
+  --path DIR             the database to use, or an in-memory one if not given
+  -f SCRIPT              run the statements in the file SCRIPT, or those read
+                         from standard input if SCRIPT is -, and exit
+  -c SQL                 run the statements in SQL and exit
+  --format FORMAT        print rows as a table, csv, json or arrow
+  --continue-on-error    keep running statements after one fails
+
+Without -f or -c, statements are read interactively, each ending with a
+semicolon, and may span several lines.  Tab completes the names of tables and
+columns, and the statements are remembered in ~/.equilia_history.  A script
+exits with status 1 if any statement failed, and 2 if it could not be run at
+all.
+
+Besides SQL statements, `\\copy TABLE from FILE [csv|json]` loads a file
+into a table, either CSV with a header line or JSON with one object per line.
+Without a format, files ending in .json, .jsonl or .ndjson are read as JSON.
+`\\format table|csv|json|arrow` chooses how the rows of later queries are printed.
+
+The tables __tables, __columns, __segments, __statistics and __queries
+describe the database, as in `SELECT * FROM __tables`.";
+
+/// What to run, from the command line
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    path: Option<PathBuf>,
+    script: Option<Script>,
+    format: Format,
+    continue_on_error: bool,
 }
 
-fn main() -> Result<(), std::io::Error> {
-    println!("welcome to equilia client.");
+/// Statements to run without asking for them
+#[derive(Debug, PartialEq, Eq)]
+enum Script {
+    File(PathBuf),
+    Command(String),
+}
 
-    // TODO: handle connection :)
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{name} needs an argument"))
+            };
+            match arg.as_str() {
+                "--path" => options.path = Some(value("--path")?.into()),
+                "-f" | "-c" if options.script.is_some() => {
+                    return Err("only one of -f and -c may be given".to_string())
+                }
+                "-f" => options.script = Some(Script::File(value("-f")?.into())),
+                "-c" => options.script = Some(Script::Command(value("-c")?)),
+                "--format" => options.format = value("--format")?.parse()?,
+                "--continue-on-error" => options.continue_on_error = true,
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+        Ok(options)
+    }
+}
 
-    loop {
-        print!("equilia > ");
-        std::io::stdout().flush().unwrap();
-        let mut buffer = String::new();
-        std::io::stdin().read_line(&mut buffer)?;
-        let b = buffer.trim();
+/// Split SQL into statements at each semicolon that is not within a string,
+/// leaving out empty statements.
+fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in sql.char_indices() {
+        match c {
+            '\'' => in_string = !in_string,
+            ';' if !in_string => {
+                statements.push(&sql[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    statements.push(&sql[start..]);
+    statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
 
-        if "exit".eq(b) || "quit".eq(b) {
-            break;
+/// Whether `sql` ends with a semicolon that is not within a string, so
+/// that no more lines need be read to run it
+fn ends_statement(sql: &str) -> bool {
+    let mut in_string = false;
+    let mut ended = false;
+    for c in sql.chars() {
+        match c {
+            '\'' => in_string = !in_string,
+            ';' if !in_string => ended = true,
+            _ if c.is_whitespace() => (),
+            _ => ended = false,
         }
+    }
+    ended && !in_string
+}
+
+/// The names completed by tab: those of the tables of a database and of
+/// their columns
+#[derive(Default)]
+struct Names(BTreeSet<String>);
+
+impl Names {
+    fn of(db: &Database) -> Self {
+        let mut names = BTreeSet::new();
+        for schema in db.schemas() {
+            names.insert(schema.name().to_string());
+            names.extend(schema.raw_columns().map(|c| c.name().to_string()));
+        }
+        Names(names)
+    }
+
+    /// Where the word before `pos` in `line` starts, and the names that
+    /// could complete it
+    fn complete_word(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_word(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &line[start..pos];
+        let names = self.0.iter().filter(|n| n.starts_with(word)).cloned();
+        (start, names.collect())
+    }
+}
+
+impl Completer for Names {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.complete_word(line, pos))
+    }
+}
+
+// Names only complete, and otherwise lines are read as they are typed.
+impl Helper for Names {}
+impl Highlighter for Names {}
+impl Validator for Names {}
+impl Hinter for Names {
+    type Hint = String;
+}
+
+/// Run a command starting with a backslash, returning what to print
+fn meta_command(db: &Database, format: &mut Format, command: &str) -> Result<String, String> {
+    let words = command.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        ["\\copy", table, from, ..] if from.eq_ignore_ascii_case("from") => {
+            // The file name may hold spaces, so it is the rest of the command
+            // after the words before it, not a word of its own.
+            let file = words[..3]
+                .iter()
+                .fold(command, |rest, word| &rest.trim_start()[word.len()..])
+                .trim();
+            let (file, format) = match file.rsplit_once(char::is_whitespace) {
+                Some((f, format @ ("csv" | "json"))) => (f.trim_end(), format),
+                _ if [".json", ".jsonl", ".ndjson"]
+                    .iter()
+                    .any(|e| file.trim_end_matches('\'').ends_with(e)) =>
+                {
+                    (file, "json")
+                }
+                _ => (file, "csv"),
+            };
+            let file = file
+                .strip_prefix('\'')
+                .and_then(|f| f.strip_suffix('\''))
+                .unwrap_or(file);
+            let table = db.table(table).map_err(|e| e.to_string())?;
+            let reader = std::fs::File::open(file)
+                .map(std::io::BufReader::new)
+                .map_err(|e| format!("unable to read {file}: {e}"))?;
+            let rows = if format == "json" {
+                table.load_json(&JsonLoader::new(table.schema()), reader)
+            } else {
+                table.load_csv(&CsvLoader::new(table.schema()), reader)
+            }
+            .map_err(|e| e.to_string())?;
+            Ok(format!("COPY {rows}\n"))
+        }
+        ["\\copy", ..] => Err("usage: \\copy TABLE from FILE [csv|json]".to_string()),
+        ["\\format", name] => {
+            *format = name.parse()?;
+            Ok(String::new())
+        }
+        ["\\format", ..] => Err("usage: \\format table|csv|json|arrow".to_string()),
+        _ => Err(format!("unrecognized command {}", words[0])),
+    }
+}
 
-        let statement = if b.starts_with("select") {
-            Statement::Select
-        } else if b.starts_with("select") {
-            Statement::Insert
+/// Run each statement of `sql`, printing rows in `format`, and returning
+/// whether they all succeeded.
+///
+/// Errors are reported on stderr, and unless `continue_on_error` the first
+/// one stops the script.
+fn run_script(
+    db: &Database,
+    format: &mut Format,
+    sql: &str,
+    continue_on_error: bool,
+    out: &mut impl Write,
+) -> std::io::Result<bool> {
+    let mut succeeded = true;
+    for statement in split_statements(sql) {
+        let result = if statement.starts_with('\\') {
+            meta_command(db, format, statement).map(|printed| out.write_all(printed.as_bytes()))
         } else {
-            Statement::Unknown
+            db.execute(statement)
+                .map(|result| format.write(out, result.columns(), result.rows()))
+                .map_err(|e| e.to_string())
         };
-
-        match statement {
-            Statement::Select => todo!(),
-            Statement::Insert => todo!(),
-            Statement::Unknown => println!("unrecognized statement."),
+        match result {
+            Ok(printed) => printed?,
+            Err(e) => {
+                eprintln!("error: {e}");
+                succeeded = false;
+                if !continue_on_error {
+                    break;
+                }
+            }
         }
     }
+    Ok(succeeded)
+}
 
+fn interactive(db: &Database, mut format: Format) -> Result<(), ReadlineError> {
+    let mut editor = Editor::<Names, FileHistory>::new()?;
+    editor.set_helper(Some(Names::of(db)));
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".equilia_history"));
+    if let Some(history) = &history {
+        // There is no history the first time the client is run.
+        editor.load_history(history).ok();
+    }
+    println!("welcome to equilia client.");
+    let mut statement = String::new();
+    loop {
+        let prompt = if statement.is_empty() {
+            "equilia > "
+        } else {
+            "     ... > "
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                statement.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        let b = line.trim();
+        if statement.is_empty() && ("exit".eq(b) || "quit".eq(b)) {
+            break;
+        }
+        // Commands starting with a backslash take just one line.
+        if statement.is_empty() && b.starts_with('\\') {
+            editor.add_history_entry(b)?;
+            run_script(db, &mut format, b, true, &mut std::io::stdout())?;
+            continue;
+        }
+        statement.push_str(&line);
+        statement.push('\n');
+        if ends_statement(&statement) {
+            editor.add_history_entry(statement.trim())?;
+            run_script(db, &mut format, &statement, true, &mut std::io::stdout())?;
+            statement.clear();
+            // The statement may have created or altered a table.
+            editor.set_helper(Some(Names::of(db)));
+        }
+    }
+    if let Some(history) = &history {
+        if let Err(e) = editor.save_history(history) {
+            eprintln!("error: unable to save {}: {e}", history.display());
+        }
+    }
     println!("bye.");
     Ok(())
 }
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let db = match &options.path {
+        Some(path) => match Database::open(path) {
+            Ok(db) => db,
+            Err(e) => {
+                eprintln!("error: unable to open {}: {e}", path.display());
+                return ExitCode::from(2);
+            }
+        },
+        None => Database::in_memory(),
+    };
+    let sql = match options.script {
+        None => {
+            return match interactive(&db, options.format) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some(Script::Command(sql)) => sql,
+        Some(Script::File(file)) if file.as_os_str() == "-" => {
+            match std::io::read_to_string(std::io::stdin()) {
+                Ok(sql) => sql,
+                Err(e) => {
+                    eprintln!("error: unable to read standard input: {e}");
+                    return ExitCode::from(2);
+                }
+            }
+        }
+        Some(Script::File(file)) => match std::fs::read_to_string(&file) {
+            Ok(sql) => sql,
+            Err(e) => {
+                eprintln!("error: unable to read {}: {e}", file.display());
+                return ExitCode::from(2);
+            }
+        },
+    };
+    let mut format = options.format;
+    match run_script(
+        &db,
+        &mut format,
+        &sql,
+        options.continue_on_error,
+        &mut std::io::stdout().lock(),
+    ) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[test]
+fn parse_options() {
+    let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+    assert_eq!(parse(&[]), Ok(Options::default()));
+    assert_eq!(
+        parse(&[
+            "--path",
+            "db",
+            "-c",
+            "select * from t",
+            "--continue-on-error",
+            "--format",
+            "json",
+        ]),
+        Ok(Options {
+            path: Some("db".into()),
+            script: Some(Script::Command("select * from t".to_string())),
+            format: Format::Json,
+            continue_on_error: true,
+        })
+    );
+    assert!(parse(&["--format", "xml"]).is_err());
+    assert!(parse(&["-f"]).is_err());
+    assert!(parse(&["-f", "a.sql", "-c", "select"]).is_err());
+    assert!(parse(&["--verbose"]).is_err());
+}
+
+#[test]
+fn read_statements() {
+    use equilia::{ColumnSchema, TableSchema};
+
+    assert!(ends_statement("select * from people;\n"));
+    assert!(ends_statement("select 1; select 2 ;"));
+    assert!(!ends_statement("select * from people\n"));
+    assert!(!ends_statement("insert into people (name) values ('a;\n"));
+    assert!(ends_statement(
+        "insert into people (name) values ('a;\nb');"
+    ));
+    assert!(!ends_statement("select 1; select 2"));
+
+    let mut db = Database::in_memory();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::<u64>::new("age").raw());
+    db.create_table(schema).unwrap();
+    let names = Names::of(&db);
+    assert_eq!(
+        names.complete_word("select na", 9),
+        (7, vec!["name".into()])
+    );
+    assert_eq!(
+        names.complete_word("select name from pe where", 19),
+        (17, vec!["people".into()])
+    );
+    assert_eq!(names.complete_word("select x", 8), (7, vec![]));
+    assert_eq!(names.complete_word("", 0).1.len(), 3);
+}
+
+#[test]
+fn run_scripts() {
+    use equilia::{ColumnSchema, TableSchema};
+
+    assert_eq!(
+        split_statements("select 1; insert ';' ;; select 2\n"),
+        ["select 1", "insert ';'", "select 2"]
+    );
+
+    let mut db = Database::in_memory();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::<u64>::new("age").raw());
+    db.create_table(schema).unwrap();
+    let mut format = Format::default();
+
+    let script = "insert into people (name, age) values ('David', 48);
+        select * from nobody;
+        insert into people (name, age) values ('Alice', 30);
+        select name, age from people";
+    let mut out = Vec::new();
+    assert!(!run_script(&db, &mut format, script, false, &mut out).unwrap());
+    assert!(out.is_empty());
+    assert!(!run_script(&db, &mut format, script, true, &mut out).unwrap());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "name  | age\n------+----\nAlice |  30\nDavid |  48\n"
+    );
+    let mut out = Vec::new();
+    assert!(run_script(&db, &mut format, "select age from people;", false, &mut out).unwrap());
+    assert_eq!(String::from_utf8(out).unwrap(), "age\n---\n 30\n 48\n");
+
+    let dir = tempfile::tempdir().unwrap();
+    let csv = dir.path().join("people; and more.csv");
+    std::fs::write(&csv, "age,name\n7,Bob\n").unwrap();
+    let mut out = Vec::new();
+    let copy = format!("\\copy people from '{}'", csv.display());
+    assert!(run_script(&db, &mut format, &copy, false, &mut out).unwrap());
+    let json = dir.path().join("people.jsonl");
+    std::fs::write(&json, "{\"name\": \"Carol\", \"age\": 7}\n").unwrap();
+    let copy = format!("\\copy people from '{}'", json.display());
+    assert!(run_script(&db, &mut format, &copy, false, &mut out).unwrap());
+    let renamed = dir.path().join("people.txt");
+    std::fs::rename(&json, &renamed).unwrap();
+    let copy = format!("\\copy people from {} json", renamed.display());
+    assert!(run_script(&db, &mut format, &copy, false, &mut out).unwrap());
+    assert!(!run_script(
+        &db,
+        &mut format,
+        "\\copy people from nowhere.csv",
+        false,
+        &mut out
+    )
+    .unwrap());
+    assert!(!run_script(&db, &mut format, "\\copy people", false, &mut out).unwrap());
+    assert!(!run_script(&db, &mut format, "\\help", false, &mut out).unwrap());
+    assert!(run_script(
+        &db,
+        &mut format,
+        "select name from people where age = 7",
+        false,
+        &mut out
+    )
+    .unwrap());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "COPY 1\nCOPY 1\nCOPY 1\nname\n-----\nBob\nCarol\n"
+    );
+
+    let script = "\\format csv; select * from people where age = 7;
+        \\format json; select * from people where name = 'Bob'";
+    let mut out = Vec::new();
+    assert!(run_script(&db, &mut format, script, false, &mut out).unwrap());
+    assert_eq!(format, Format::Json);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "name,age\nBob,7\nCarol,7\n{\"name\": \"Bob\", \"age\": 7}\n"
+    );
+    assert!(!run_script(&db, &mut format, "\\format xml", false, &mut Vec::new()).unwrap());
+    assert_eq!(format, Format::Json);
+
+    // The file follows the word from, not the first text spelling it.
+    let mut schema = TableSchema::new("fromage");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::<u64>::new("age").raw());
+    db.create_table(schema).unwrap();
+    let copy = format!("\\copy fromage from '{}'", csv.display());
+    let mut out = Vec::new();
+    assert!(run_script(&db, &mut format, &copy, false, &mut out).unwrap());
+    assert_eq!(String::from_utf8(out).unwrap(), "COPY 1\n");
+}
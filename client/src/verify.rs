@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use equilia::{verify_db, DbLayout, FlatLayout, NestedLayout, VerifyReport};
+
+const USAGE: &str = "usage: equilia-verify [--nested] DIR...
+
+  --nested    the databases were opened with the nested layout
+
+Check that the files of every table of the database in each DIR agree with
+each other: that each manifest can be read, each column file starts with the
+magic number of a format and matches its checksum, each column of a segment
+holds as many rows as the segment, and the rows of each segment are sorted
+by their primary keys.  Each problem found is printed on a line of its own.
+The exit status is 1 if any problem was found, and 2 if the arguments make
+no sense.";
+
+/// What to verify, from the command line
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    dirs: Vec<PathBuf>,
+    nested: bool,
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        for arg in args {
+            match arg.as_str() {
+                "--nested" => options.nested = true,
+                _ if arg.starts_with('-') => return Err(format!("unexpected argument {arg}")),
+                _ => options.dirs.push(arg.into()),
+            }
+        }
+        if options.dirs.is_empty() {
+            return Err("no DIR given".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Verify the database in `dir`, which must exist
+fn verify(dir: &Path, layout: &dyn DbLayout) -> Result<VerifyReport, String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    Ok(verify_db(dir, layout))
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let layout: &dyn DbLayout = if options.nested {
+        &NestedLayout
+    } else {
+        &FlatLayout
+    };
+    let mut status = ExitCode::SUCCESS;
+    for dir in options.dirs.iter() {
+        match verify(dir, layout) {
+            Ok(report) => {
+                for i in report.inconsistencies() {
+                    println!("{}: {i}", dir.display());
+                }
+                eprintln!(
+                    "{}: {} tables, {} segments, {} columns, {} problems",
+                    dir.display(),
+                    report.tables(),
+                    report.segments(),
+                    report.columns(),
+                    report.inconsistencies().len()
+                );
+                if !report.is_clean() {
+                    status = ExitCode::FAILURE;
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+#[test]
+fn verify_databases() {
+    use equilia::{ColumnSchema, Database, TableSchema};
+
+    let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+    assert_eq!(
+        parse(&["--nested", "db"]),
+        Ok(Options {
+            dirs: vec!["db".into()],
+            nested: true,
+        })
+    );
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["--flat", "db"]).is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::<u64>::new("age").raw());
+    db.create_table(schema).unwrap();
+    db.execute("insert into people (name, age) values ('David', 48), ('Alice', 30)")
+        .unwrap();
+    drop(db);
+    let report = verify(dir.path(), &FlatLayout).unwrap();
+    assert!(report.is_clean(), "{:?}", report.inconsistencies());
+    assert!(verify(&dir.path().join("nothing"), &FlatLayout).is_err());
+}
@@ -0,0 +1,22 @@
+//! Decodes arbitrary bytes as a column, and reads it every way there is.
+//! Reading may fail, but any panic is a bug.
+//!
+//! Run with `cargo fuzz run decode_column` from the root of the repository.
+#![no_main]
+
+use equilia::RawColumn;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(column) = RawColumn::decode(data.to_vec()) else {
+        return;
+    };
+    let _ = (column.num_chunks(), column.min(), column.max());
+    // A few bytes can hold runs of more rows than fit in memory.
+    if column.num_rows() > 1 << 20 {
+        return;
+    }
+    let _ = column.read_values();
+    let _ = column.read_codes();
+    let _ = column.to_arrow();
+});
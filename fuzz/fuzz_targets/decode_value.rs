@@ -0,0 +1,17 @@
+//! Decodes arbitrary bytes as a value, which may fail but must not panic.
+//!
+//! Run with `cargo fuzz run decode_value` from the root of the repository.
+#![no_main]
+
+use equilia::RawValue;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut rest = data;
+    while let Ok((_, remaining)) = RawValue::decode(rest) {
+        if remaining.len() == rest.len() {
+            break;
+        }
+        rest = remaining;
+    }
+});
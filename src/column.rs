@@ -2,25 +2,65 @@
 //!
 //! This module will eventually be private.
 
+use std::borrow::Cow;
+
 use encoding::{ReadEncoded, StorageError};
 use storage::Storage;
 
+use crate::value::{RawKind, RawValue};
+
 use self::encoding::WriteEncoded;
 
+pub mod arrow;
+mod bitmap;
+mod bitpacked;
+mod bitset;
 mod boolcolumn;
 pub mod bytes;
+pub(crate) mod cache;
+pub mod custom;
+mod dictionary;
+pub mod digest;
 pub mod encoding;
+pub mod format;
+mod metrics;
+mod sparse;
 pub mod storage;
 pub mod u64_generic;
+mod version;
 
 pub(crate) use boolcolumn::BoolColumn;
+pub(crate) use cache::{CacheKey, ColumnCache};
+pub use custom::{register_format, CustomFormat};
+pub use format::ColumnFormat;
+pub use metrics::Metrics;
+pub use storage::HttpOptions;
+pub use version::{migrate_column, FormatVersion};
 
 /// A raw column
 pub struct RawColumn {
     inner: RawColumnInner,
+    /// The version of the encoding the column was written in
+    version: FormatVersion,
+    /// Counts the reads and decoding done on the column
+    metrics: Metrics,
+    /// Where the column was read from, if it can be cached
+    cache_key: Option<CacheKey>,
+    /// The number of bytes of the encoded column
+    byte_len: u64,
 }
 
 fn run_length_encode<T: PartialEq + Clone>(elems: &[T]) -> Vec<(T, u64)> {
+    run_length_encode_with(elems, |_, _| ())
+}
+
+/// The runs of identical values, calling `each` with the value and length of
+/// each run as it is found, so that stats of the values need no pass of
+/// their own
+fn run_length_encode_with<T: PartialEq + Clone>(
+    elems: &[T],
+    mut each: impl FnMut(&T, u64),
+) -> Vec<(T, u64)> {
     let mut out = Vec::new();
     if let Some(mut previous) = elems.first() {
         let mut count = 0;
@@ -28,87 +68,159 @@ fn run_length_encode<T: PartialEq + Clone>(elems: &[T]) -> Vec<(T, u64)> {
             if v == previous {
                 count += 1;
             } else {
+                each(previous, count);
                 out.push((previous.clone(), count));
                 count = 1;
                 previous = v;
             }
         }
         if count > 0 {
+            each(previous, count);
             out.push((previous.clone(), count));
         }
     }
     out
 }
 
+/// Options controlling how a column is encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    max_chunk_rows: Option<u64>,
+    format: Option<ColumnFormat>,
+    preferred: &'static [ColumnFormat],
+}
+
+impl EncodeOptions {
+    /// Split runs of identical values so no chunk holds more than `rows` rows.
+    ///
+    /// A long run compresses to a single chunk, which cannot be skipped into
+    /// or pruned part way through.  Smaller chunks cost a little space but
+    /// give finer grained access.  Columns of bools are never split, because
+    /// their encoding relies on each run differing from the last, and columns
+    /// are never sparse encoded, because that cannot split up its defaults.
+    pub fn max_chunk_rows(self, rows: u64) -> Self {
+        EncodeOptions {
+            max_chunk_rows: Some(rows.max(1)),
+            ..self
+        }
+    }
+
+    /// Encode the column in `format`, rather than the one that is smallest.
+    ///
+    /// Encoding fails if the format cannot hold the values of the column.
+    pub fn format(self, format: ColumnFormat) -> Self {
+        EncodeOptions {
+            format: Some(format),
+            ..self
+        }
+    }
+
+    /// Pick the smallest of `formats` that can hold the values of the
+    /// column, rather than the smallest of all formats, unless none of them
+    /// can hold the values.
+    pub fn prefer(self, formats: &'static [ColumnFormat]) -> Self {
+        EncodeOptions {
+            preferred: formats,
+            ..self
+        }
+    }
+
+    /// Whether the format may be picked for a column
+    fn allows(&self, format: ColumnFormat) -> bool {
+        self.max_chunk_rows.is_none() || !format.is_sparse()
+    }
+
+    /// The formats that `holds` and that may be picked, or only the preferred
+    /// ones of those, if there are any
+    fn candidates(&self, holds: impl Fn(ColumnFormat) -> bool) -> Vec<ColumnFormat> {
+        let allowed = ColumnFormat::ALL
+            .iter()
+            .copied()
+            .filter(|f| holds(*f) && self.allows(*f))
+            .collect::<Vec<_>>();
+        let preferred = allowed
+            .iter()
+            .copied()
+            .filter(|f| self.preferred.contains(f))
+            .collect::<Vec<_>>();
+        if preferred.is_empty() {
+            allowed
+        } else {
+            preferred
+        }
+    }
+
+    /// The format to encode bools in, if one is asked for or preferred,
+    /// since every format of bools can hold any bools
+    pub(crate) fn bool_format(&self) -> Option<ColumnFormat> {
+        self.format.or_else(|| {
+            self.preferred
+                .iter()
+                .copied()
+                .find(|f| f.kind() == RawKind::Bool)
+        })
+    }
+
+    /// The length of the longest run once split by [`EncodeOptions::split_runs`]
+    fn split_len(&self, longest_run: u64) -> u64 {
+        match self.max_chunk_rows {
+            Some(max) => longest_run.min(max),
+            None => longest_run,
+        }
+    }
+
+    /// The runs of a sample of `vals`, whose split runs are `runs`, on which
+    /// to try out formats.  A column small enough to be its own sample is
+    /// not run length encoded again.
+    fn sample_runs<'a, T: PartialEq + Clone>(
+        &self,
+        vals: &[T],
+        runs: &'a [(T, u64)],
+    ) -> Cow<'a, [(T, u64)]> {
+        match format::sample(vals) {
+            Cow::Borrowed(_) => Cow::Borrowed(runs),
+            Cow::Owned(sample) => Cow::Owned(self.split_runs(run_length_encode(&sample))),
+        }
+    }
+
+    /// Split any run that is longer than the target number of rows
+    fn split_runs<T: Clone>(&self, runs: Vec<(T, u64)>) -> Vec<(T, u64)> {
+        let Some(max) = self.max_chunk_rows else {
+            return runs;
+        };
+        let mut out = Vec::with_capacity(runs.len());
+        for (v, mut num) in runs {
+            while num > max {
+                out.push((v.clone(), max));
+                num -= max;
+            }
+            out.push((v, num));
+        }
+        out
+    }
+}
+
 impl From<&[bool]> for RawColumn {
     fn from(bools: &[bool]) -> Self {
-        RawColumn {
-            inner: RawColumnInner::Bool(BoolColumn::from(bools)),
-        }
+        let mut bytes = Vec::new();
+        RawColumn::write_bools(&mut bytes, bools).expect("error encoding");
+        RawColumn::decode(bytes).unwrap()
     }
 }
 
 impl From<&[u64]> for RawColumn {
     fn from(vals: &[u64]) -> Self {
-        let max = vals.iter().copied().max().unwrap_or_default();
-        let min = vals.iter().copied().min().unwrap_or_default();
-        let longest_run = run_length_encode(vals)
-            .into_iter()
-            .map(|x| x.1)
-            .max()
-            .unwrap_or_default();
-        let inner = if max - min > u32::MAX as u64 {
-            if longest_run < 2 {
-                RawColumnInner::U64V1(u64_generic::VariableOne::from(vals))
-            } else {
-                RawColumnInner::U64VV(u64_generic::VariableVariable::from(vals))
-            }
-        } else if max - min > u16::MAX as u64 {
-            if longest_run < 2 {
-                RawColumnInner::U64_32_1(u64_generic::U32One::from(vals))
-            } else {
-                RawColumnInner::U64_32(u64_generic::U32Variable::from(vals))
-            }
-        } else if max - min > u8::MAX as u64 {
-            if longest_run < 2 {
-                RawColumnInner::U64_16_1(u64_generic::U16One::from(vals))
-            } else {
-                RawColumnInner::U64_16(u64_generic::U16Variable::from(vals))
-            }
-        } else {
-            if longest_run < 2 {
-                RawColumnInner::U64_8_1(u64_generic::U8One::from(vals))
-            } else {
-                RawColumnInner::U64_8(u64_generic::U8Variable::from(vals))
-            }
-        };
-        RawColumn { inner }
+        let mut bytes = Vec::new();
+        RawColumn::write_u64(&mut bytes, vals).expect("error encoding");
+        RawColumn::decode(bytes).unwrap()
     }
 }
 
 impl From<&[Vec<u8>]> for RawColumn {
     fn from(vals: &[Vec<u8>]) -> Self {
-        let longest_run = run_length_encode(vals)
-            .into_iter()
-            .map(|x| x.1)
-            .max()
-            .unwrap_or_default();
-        let mx = vals.iter().map(|v| v.len()).max();
-        let mn = vals.iter().map(|v| v.len()).min();
-        let inner = if mx == mn {
-            if longest_run == 1 {
-                RawColumnInner::BytesF1V(bytes::F1V::from(vals))
-            } else {
-                RawColumnInner::BytesFVV(bytes::FVV::from(vals))
-            }
-        } else {
-            if longest_run == 1 {
-                RawColumnInner::BytesV10(bytes::V10::from(vals))
-            } else {
-                RawColumnInner::BytesVVV(bytes::VVV::from(vals))
-            }
-        };
-        RawColumn { inner }
+        let mut bytes = Vec::new();
+        RawColumn::write_bytes(&mut bytes, vals).expect("error encoding");
+        RawColumn::decode(bytes).unwrap()
     }
 }
 
@@ -116,28 +228,608 @@ const BOOL_MAGIC: u64 = u64::from_be_bytes(*b"__bool__");
 const U64_GENERIC_MAGIC: u64 = u64::from_be_bytes(*b"00u64gen");
 const BYTES_GENERIC_MAGIC: u64 = u64::from_be_bytes(*b"000bytes");
 
+/// Merge the runs of sorted `inputs`, converted by `typed`, taking the
+/// smallest head each time from the first input holding it and combining
+/// runs of equal values
+fn merge_runs<T: Ord>(
+    inputs: &[RawColumn],
+    typed: fn(RawValue) -> Option<T>,
+) -> Result<Vec<(T, u64)>, StorageError> {
+    let mut chunks = inputs.iter().map(|c| c.runs()).collect::<Vec<_>>();
+    let mut heads = chunks
+        .iter_mut()
+        .map(|c| c.next().transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut runs: Vec<(T, u64)> = Vec::new();
+    while let Some(i) = (0..heads.len())
+        .filter(|&i| heads[i].is_some())
+        .min_by(|&a, &b| heads[a].cmp(&heads[b]))
+    {
+        let next = chunks[i].next().transpose()?;
+        let (value, num) = std::mem::replace(&mut heads[i], next).expect("a head");
+        let found = value.kind();
+        let value = typed(value).ok_or(StorageError::KindMismatch {
+            expected: inputs[i].kind(),
+            found,
+        })?;
+        match runs.last_mut() {
+            Some((last, n)) if *last == value => *n += num,
+            Some((last, _)) if *last > value => {
+                return Err(StorageError::InvalidRow("a column to merge is not sorted"));
+            }
+            _ => runs.push((value, num)),
+        }
+    }
+    Ok(runs)
+}
+
 impl RawColumn {
+    /// Encode a column of bools, as runs or as a bitmap if that is smaller
+    ///
+    /// A bitmap is smaller when most runs are only a few rows long.
+    pub fn write_bools<W: WriteEncoded>(out: &mut W, vals: &[bool]) -> Result<(), StorageError> {
+        Self::write_bools_with(out, vals, None)
+    }
+
+    /// Encode a column of bools in `format`, or in the smallest format
+    pub(crate) fn write_bools_with<W: WriteEncoded>(
+        out: &mut W,
+        vals: &[bool],
+        format: Option<ColumnFormat>,
+    ) -> Result<(), StorageError> {
+        Self::write_bool_runs(out, &run_length_encode(vals), format)
+    }
+
+    /// Encode runs of bools in `format`, or in the smallest format
+    fn write_bool_runs<W: WriteEncoded>(
+        out: &mut W,
+        runs: &[(bool, u64)],
+        format: Option<ColumnFormat>,
+    ) -> Result<(), StorageError> {
+        let format = format.unwrap_or_else(|| {
+            [ColumnFormat::Bools, ColumnFormat::Bitmap]
+                .into_iter()
+                .min_by_key(|f| f.estimate_bools(runs).unwrap_or(usize::MAX))
+                .expect("there are formats")
+        });
+        if format.kind() != RawKind::Bool {
+            return Err(StorageError::InvalidRow("format does not hold bools"));
+        }
+        version::write_header(out)?;
+        format.encode_bools(out, runs)
+    }
+
+    /// Encode a column of u64, picking a format based on the data
+    ///
+    /// Each format that can hold the values is tried on a sample of them, and
+    /// the one that encodes the sample smallest is picked.
+    pub fn write_u64<W: WriteEncoded>(out: &mut W, vals: &[u64]) -> Result<(), StorageError> {
+        Self::write_u64_with(out, vals, EncodeOptions::default())
+    }
+
+    /// Encode a column of u64 with the given options
+    pub fn write_u64_with<W: WriteEncoded>(
+        out: &mut W,
+        vals: &[u64],
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        version::write_header(out)?;
+        let (mut min, mut max, mut longest_run) = (u64::MAX, 0, 0);
+        let runs = run_length_encode_with(vals, |&v, n| {
+            min = min.min(v);
+            max = max.max(v);
+            longest_run = longest_run.max(n);
+        });
+        let runs = options.split_runs(runs);
+        if let Some(format) = options.format {
+            return format.encode_u64(out, &runs);
+        }
+        let sample = options.sample_runs(vals, &runs);
+        let spread = max.saturating_sub(min);
+        let longest_run = options.split_len(longest_run);
+        Self::write_u64_runs(out, &runs, &sample, spread, longest_run, options)
+    }
+
+    /// Encode runs of u64, whose values span `spread`, in the format that
+    /// encodes the runs of `sample` smallest
+    fn write_u64_runs<W: WriteEncoded>(
+        out: &mut W,
+        runs: &[(u64, u64)],
+        sample: &[(u64, u64)],
+        spread: u64,
+        longest_run: u64,
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        let format = options
+            .candidates(|f| f.holds_u64(spread, longest_run))
+            .into_iter()
+            .min_by_key(|f| f.estimate_u64(sample))
+            .expect("some format holds any u64");
+        format.encode_u64(out, runs)
+    }
+
+    /// Encode a column of bytes, picking a format based on the data
+    ///
+    /// As with [`RawColumn::write_u64`], the format that encodes a sample of
+    /// the values smallest is picked.
+    pub fn write_bytes<W: WriteEncoded>(out: &mut W, vals: &[Vec<u8>]) -> Result<(), StorageError> {
+        Self::write_bytes_with(out, vals, EncodeOptions::default())
+    }
+
+    /// Encode a column of bytes with the given options
+    pub fn write_bytes_with<W: WriteEncoded>(
+        out: &mut W,
+        vals: &[Vec<u8>],
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        version::write_header(out)?;
+        let first_len = vals.first().map(|v| v.len());
+        let (mut same_length, mut longest_run) = (true, 0);
+        let runs = run_length_encode_with(vals, |v, n| {
+            same_length &= Some(v.len()) == first_len;
+            longest_run = longest_run.max(n);
+        });
+        let runs = options.split_runs(runs);
+        if let Some(format) = options.format {
+            return format.encode_bytes(out, &runs);
+        }
+        let sample = options.sample_runs(vals, &runs);
+        let longest_run = options.split_len(longest_run);
+        Self::write_bytes_runs(out, &runs, &sample, same_length, longest_run, options)
+    }
+
+    /// Encode runs of bytes in the format that encodes the runs of `sample`
+    /// smallest
+    fn write_bytes_runs<W: WriteEncoded>(
+        out: &mut W,
+        runs: &[(Vec<u8>, u64)],
+        sample: &[(Vec<u8>, u64)],
+        same_length: bool,
+        longest_run: u64,
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        let format = options
+            .candidates(|f| f.holds_bytes(same_length, longest_run))
+            .into_iter()
+            .min_by_key(|f| f.estimate_bytes(sample))
+            .expect("some format holds any bytes");
+        format.encode_bytes(out, runs)
+    }
+
+    /// Encode a column of values, which must all be of the same kind
+    pub fn write_values<W: WriteEncoded>(
+        out: &mut W,
+        kind: RawKind,
+        vals: &[RawValue],
+    ) -> Result<(), StorageError> {
+        Self::write_values_with(out, kind, vals, EncodeOptions::default())
+    }
+
+    /// Encode a column of values with the given options
+    pub fn write_values_with<W: WriteEncoded>(
+        out: &mut W,
+        kind: RawKind,
+        vals: &[RawValue],
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        match kind {
+            RawKind::Bool => {
+                let vals = vals
+                    .iter()
+                    .map(|v| match v {
+                        RawValue::Bool(b) => Ok(*b),
+                        _ => Err(StorageError::InvalidRow("expected a bool")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::write_bools_with(out, &vals, options.bool_format())
+            }
+            RawKind::U64 => {
+                let vals = vals
+                    .iter()
+                    .map(|v| match v {
+                        RawValue::U64(n) => Ok(*n),
+                        _ => Err(StorageError::InvalidRow("expected a u64")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::write_u64_with(out, &vals, options)
+            }
+            RawKind::Bytes => {
+                let vals = vals
+                    .iter()
+                    .map(|v| match v {
+                        RawValue::Bytes(b) => Ok(b.clone()),
+                        _ => Err(StorageError::InvalidRow("expected bytes")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::write_bytes_with(out, &vals, options)
+            }
+        }
+    }
+
+    /// Merge sorted columns of the same kind into one sorted column.
+    ///
+    /// The chunks of the inputs are merged as they are decoded, and chunks
+    /// of equal values are combined, so no input is decoded into a vector of
+    /// its values.  The format is picked as by [`RawColumn::write_values`].
+    /// An input that is not sorted is an error.
+    pub fn merge<W: WriteEncoded>(inputs: &[RawColumn], out: &mut W) -> Result<(), StorageError> {
+        let kind = inputs
+            .first()
+            .ok_or(StorageError::InvalidRow("no columns to merge"))?
+            .kind();
+        if let Some(c) = inputs.iter().find(|c| c.kind() != kind) {
+            return Err(StorageError::KindMismatch {
+                expected: kind,
+                found: c.kind(),
+            });
+        }
+        let options = EncodeOptions::default();
+        match kind {
+            RawKind::Bool => {
+                let runs = merge_runs(inputs, |v| match v {
+                    RawValue::Bool(b) => Some(b),
+                    _ => None,
+                })?;
+                Self::write_bool_runs(out, &runs, None)
+            }
+            RawKind::U64 => {
+                let runs = merge_runs(inputs, |v| match v {
+                    RawValue::U64(n) => Some(n),
+                    _ => None,
+                })?;
+                // The runs are sorted, so the first and last are the extremes.
+                let min = runs.first().map(|r| r.0).unwrap_or_default();
+                let max = runs.last().map(|r| r.0).unwrap_or_default();
+                let longest_run = runs.iter().map(|r| r.1).max().unwrap_or_default();
+                version::write_header(out)?;
+                let sample = format::sample(&runs);
+                Self::write_u64_runs(out, &runs, &sample, max - min, longest_run, options)
+            }
+            RawKind::Bytes => {
+                let runs = merge_runs(inputs, |v| match v {
+                    RawValue::Bytes(b) => Some(b),
+                    _ => None,
+                })?;
+                let same_length = runs.iter().all(|r| r.0.len() == runs[0].0.len());
+                let longest_run = runs.iter().map(|r| r.1).max().unwrap_or_default();
+                version::write_header(out)?;
+                let sample = format::sample(&runs);
+                Self::write_bytes_runs(out, &runs, &sample, same_length, longest_run, options)
+            }
+        }
+    }
+
+    /// The number of rows in this column
+    pub fn num_rows(&self) -> u64 {
+        match &self.inner {
+            RawColumnInner::Bool(c) => c.num_rows(),
+            RawColumnInner::BoolBitmap(c) => c.num_rows(),
+            RawColumnInner::BytesVVV(c) => c.num_rows(),
+            RawColumnInner::BytesV10(c) => c.num_rows(),
+            RawColumnInner::BytesFVV(c) => c.num_rows(),
+            RawColumnInner::BytesF1V(c) => c.num_rows(),
+            RawColumnInner::BytesDict(c) => c.num_rows(),
+            RawColumnInner::BytesSparse(c) => c.num_rows(),
+            RawColumnInner::U64VV(c) => c.num_rows(),
+            RawColumnInner::U64V1(c) => c.num_rows(),
+            RawColumnInner::U64_32(c) => c.num_rows(),
+            RawColumnInner::U64_32_1(c) => c.num_rows(),
+            RawColumnInner::U64_16(c) => c.num_rows(),
+            RawColumnInner::U64_16_1(c) => c.num_rows(),
+            RawColumnInner::U64_8(c) => c.num_rows(),
+            RawColumnInner::U64_8_1(c) => c.num_rows(),
+            RawColumnInner::U64Sparse(c) => c.num_rows(),
+            RawColumnInner::U64BitPacked(c) => c.num_rows(),
+            RawColumnInner::Custom(c) => c.num_rows(),
+        }
+    }
+
+    /// The number of chunks of identical values in this column
+    pub fn num_chunks(&self) -> u64 {
+        match &self.inner {
+            RawColumnInner::Bool(c) => c.num_chunks(),
+            RawColumnInner::BoolBitmap(c) => c.num_chunks(),
+            RawColumnInner::BytesVVV(c) => c.num_chunks(),
+            RawColumnInner::BytesV10(c) => c.num_chunks(),
+            RawColumnInner::BytesFVV(c) => c.num_chunks(),
+            RawColumnInner::BytesF1V(c) => c.num_chunks(),
+            RawColumnInner::BytesDict(c) => c.num_chunks(),
+            RawColumnInner::BytesSparse(c) => c.num_chunks(),
+            RawColumnInner::U64VV(c) => c.num_chunks(),
+            RawColumnInner::U64V1(c) => c.num_chunks(),
+            RawColumnInner::U64_32(c) => c.num_chunks(),
+            RawColumnInner::U64_32_1(c) => c.num_chunks(),
+            RawColumnInner::U64_16(c) => c.num_chunks(),
+            RawColumnInner::U64_16_1(c) => c.num_chunks(),
+            RawColumnInner::U64_8(c) => c.num_chunks(),
+            RawColumnInner::U64_8_1(c) => c.num_chunks(),
+            RawColumnInner::U64Sparse(c) => c.num_chunks(),
+            RawColumnInner::U64BitPacked(c) => c.num_chunks(),
+            RawColumnInner::Custom(c) => c.num_chunks(),
+        }
+    }
+
+    /// The number of bytes of the encoded column, including its header
+    pub fn byte_len(&self) -> u64 {
+        self.byte_len
+    }
+
+    /// The reads and decoding done on the column, and on any other column
+    /// sharing its metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// The kind of values in this column
+    pub fn kind(&self) -> RawKind {
+        match &self.inner {
+            RawColumnInner::Bool(_) | RawColumnInner::BoolBitmap(_) => RawKind::Bool,
+            RawColumnInner::BytesVVV(_)
+            | RawColumnInner::BytesV10(_)
+            | RawColumnInner::BytesFVV(_)
+            | RawColumnInner::BytesF1V(_)
+            | RawColumnInner::BytesDict(_)
+            | RawColumnInner::BytesSparse(_) => RawKind::Bytes,
+            RawColumnInner::U64VV(_)
+            | RawColumnInner::U64V1(_)
+            | RawColumnInner::U64_32(_)
+            | RawColumnInner::U64_32_1(_)
+            | RawColumnInner::U64_16(_)
+            | RawColumnInner::U64_16_1(_)
+            | RawColumnInner::U64_8(_)
+            | RawColumnInner::U64_8_1(_)
+            | RawColumnInner::U64Sparse(_)
+            | RawColumnInner::U64BitPacked(_) => RawKind::U64,
+            RawColumnInner::Custom(c) => c.kind(),
+        }
+    }
+
+    /// Read every value in the column, taking them from `cache` if they
+    /// were decoded before, and adding them to it if not
+    pub(crate) fn read_values_cached(
+        &self,
+        cache: Option<&ColumnCache>,
+    ) -> Result<std::sync::Arc<Vec<RawValue>>, StorageError> {
+        let cached = cache.zip(self.cache_key);
+        if let Some(values) = cached.and_then(|(cache, key)| cache.get(key)) {
+            self.metrics.record_cache_hit();
+            return Ok(values);
+        }
+        let values = std::sync::Arc::new(self.read_values()?);
+        if let Some((cache, key)) = cached {
+            cache.insert(key, values.clone());
+        }
+        Ok(values)
+    }
+
+    /// Read every value in the column, whatever its kind
+    pub fn read_values(&self) -> Result<Vec<RawValue>, StorageError> {
+        Ok(match &self.inner {
+            RawColumnInner::Bool(_) | RawColumnInner::BoolBitmap(_) => {
+                self.read_bools()?.into_iter().map(RawValue::Bool).collect()
+            }
+            RawColumnInner::BytesVVV(_)
+            | RawColumnInner::BytesV10(_)
+            | RawColumnInner::BytesFVV(_)
+            | RawColumnInner::BytesF1V(_)
+            | RawColumnInner::BytesDict(_)
+            | RawColumnInner::BytesSparse(_) => self
+                .read_bytes()?
+                .into_iter()
+                .map(RawValue::Bytes)
+                .collect(),
+            RawColumnInner::U64VV(_)
+            | RawColumnInner::U64V1(_)
+            | RawColumnInner::U64_32(_)
+            | RawColumnInner::U64_32_1(_)
+            | RawColumnInner::U64_16(_)
+            | RawColumnInner::U64_16_1(_)
+            | RawColumnInner::U64_8(_)
+            | RawColumnInner::U64_8_1(_)
+            | RawColumnInner::U64Sparse(_)
+            | RawColumnInner::U64BitPacked(_) => {
+                self.read_u64()?.into_iter().map(RawValue::U64).collect()
+            }
+            RawColumnInner::Custom(c) => {
+                self.metrics.record_chunks(c.num_chunks());
+                c.read(c.kind(), |v| Some(v.clone()))?
+            }
+        })
+    }
+
+    /// The smallest value in the column
+    pub fn min(&self) -> RawValue {
+        match &self.inner {
+            RawColumnInner::Bool(c) => RawValue::Bool(c.min()),
+            RawColumnInner::BoolBitmap(c) => RawValue::Bool(c.min()),
+            RawColumnInner::BytesVVV(c) => RawValue::Bytes(c.min()),
+            RawColumnInner::BytesV10(c) => RawValue::Bytes(c.min()),
+            RawColumnInner::BytesFVV(c) => RawValue::Bytes(c.min()),
+            RawColumnInner::BytesF1V(c) => RawValue::Bytes(c.min()),
+            RawColumnInner::BytesDict(c) => RawValue::Bytes(c.min()),
+            RawColumnInner::BytesSparse(c) => RawValue::Bytes(c.min()),
+            RawColumnInner::U64VV(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64V1(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64_32(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64_32_1(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64_16(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64_16_1(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64_8(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64_8_1(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64Sparse(c) => RawValue::U64(c.min()),
+            RawColumnInner::U64BitPacked(c) => RawValue::U64(c.min()),
+            RawColumnInner::Custom(c) => c.min(),
+        }
+    }
+
+    /// The largest value in the column
+    pub fn max(&self) -> RawValue {
+        match &self.inner {
+            RawColumnInner::Bool(c) => RawValue::Bool(c.max()),
+            RawColumnInner::BoolBitmap(c) => RawValue::Bool(c.max()),
+            RawColumnInner::BytesVVV(c) => RawValue::Bytes(c.max()),
+            RawColumnInner::BytesV10(c) => RawValue::Bytes(c.max()),
+            RawColumnInner::BytesFVV(c) => RawValue::Bytes(c.max()),
+            RawColumnInner::BytesF1V(c) => RawValue::Bytes(c.max()),
+            RawColumnInner::BytesDict(c) => RawValue::Bytes(c.max()),
+            RawColumnInner::BytesSparse(c) => RawValue::Bytes(c.max()),
+            RawColumnInner::U64VV(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64V1(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64_32(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64_32_1(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64_16(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64_16_1(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64_8(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64_8_1(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64Sparse(c) => RawValue::U64(c.max()),
+            RawColumnInner::U64BitPacked(c) => RawValue::U64(c.max()),
+            RawColumnInner::Custom(c) => c.max(),
+        }
+    }
+
+    /// The value of row `row`, found by skipping over the chunks before it
+    /// rather than decoding the whole column
+    pub fn value_at(&self, row: u64) -> Result<RawValue, StorageError> {
+        Ok(self.values_at(&[row])?.remove(0))
+    }
+
+    /// The values of `rows`, which must be in increasing order, found by
+    /// skipping over the chunks holding none of them, and stopping at the
+    /// chunk holding the last
+    pub fn values_at(&self, rows: &[u64]) -> Result<Vec<RawValue>, StorageError> {
+        let mut values = Vec::with_capacity(rows.len());
+        let mut rows = rows.iter().peekable();
+        let mut end = 0;
+        for run in self.runs() {
+            if rows.peek().is_none() {
+                break;
+            }
+            let (value, num) = run?;
+            end += num;
+            while rows.next_if(|&&row| row < end).is_some() {
+                values.push(value.clone());
+            }
+        }
+        if rows.peek().is_some() {
+            return Err(StorageError::OutOfBounds("row past the end of the column"));
+        }
+        Ok(values)
+    }
+
+    /// Iterate over the chunks of identical values in the column, giving
+    /// each value with the number of rows it repeats for
+    pub(crate) fn runs(&self) -> ChunkValues {
+        let chunks = match &self.inner {
+            RawColumnInner::Bool(c) => chunk_values(c, RawValue::Bool),
+            RawColumnInner::BoolBitmap(c) => chunk_values(c, RawValue::Bool),
+            RawColumnInner::BytesVVV(c) => chunk_values(c, RawValue::Bytes),
+            RawColumnInner::BytesV10(c) => chunk_values(c, RawValue::Bytes),
+            RawColumnInner::BytesFVV(c) => chunk_values(c, RawValue::Bytes),
+            RawColumnInner::BytesF1V(c) => chunk_values(c, RawValue::Bytes),
+            RawColumnInner::BytesDict(c) => chunk_values(c, RawValue::Bytes),
+            RawColumnInner::BytesSparse(c) => chunk_values(c, RawValue::Bytes),
+            RawColumnInner::U64VV(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64V1(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64_32(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64_32_1(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64_16(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64_16_1(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64_8(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64_8_1(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64Sparse(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::U64BitPacked(c) => chunk_values(c, RawValue::U64),
+            RawColumnInner::Custom(c) => c.runs(),
+        };
+        let metrics = self.metrics.clone();
+        Box::new(chunks.inspect(move |_| metrics.record_chunks(1)))
+    }
+
+    /// Iterate over the values in the column, decoding a chunk at a time
+    pub(crate) fn values(&self) -> Values {
+        Values {
+            chunks: self.runs(),
+            current: None,
+        }
+    }
+
+    /// The distinct values of a dictionary-encoded column, in sorted order.
+    ///
+    /// Dictionary codes are assigned in sorted value order, so comparing codes
+    /// is equivalent to comparing the values themselves.
+    pub fn dictionary(&self) -> Option<&[Vec<u8>]> {
+        match &self.inner {
+            RawColumnInner::BytesDict(c) => Some(c.entries()),
+            _ => None,
+        }
+    }
+
+    /// The dictionary codes of the values that lie within `range`, if this
+    /// column is dictionary encoded.
+    pub fn code_range<'a>(
+        &self,
+        range: impl std::ops::RangeBounds<&'a [u8]>,
+    ) -> Option<std::ops::Range<u64>> {
+        match &self.inner {
+            RawColumnInner::BytesDict(c) => Some(c.code_range(range)),
+            _ => None,
+        }
+    }
+
+    /// Read the dictionary codes of a dictionary-encoded column
+    pub fn read_codes(&self) -> Result<Vec<u64>, StorageError> {
+        let RawColumnInner::BytesDict(c) = &self.inner else {
+            return Err(StorageError::OutOfBounds(
+                "column is not dictionary encoded",
+            ));
+        };
+        let mut c = c.clone();
+        let mut out = Vec::new();
+        while let Some(chunk) = c.next_code()? {
+            self.metrics.record_chunks(1);
+            for _ in chunk.range {
+                out.push(chunk.value);
+            }
+        }
+        Ok(out)
+    }
+
     /// This isn't what we'll really want to use, but might be useful for
     /// testing?
     ///
     /// It also illustrates how some common logic can be abstracted away into a
     /// helper function like the `column_to_vec` below.
     pub fn read_bools(&self) -> Result<Vec<bool>, StorageError> {
-        match &self.inner {
+        let out = match &self.inner {
             RawColumnInner::Bool(b) => column_to_vec(b),
-            RawColumnInner::BytesVVV(_) => panic!("does not hold bools"),
-            RawColumnInner::BytesV10(_) => panic!("does not hold bools"),
-            RawColumnInner::BytesFVV(_) => panic!("does not hold bools"),
-            RawColumnInner::BytesF1V(_) => panic!("does not hold bools"),
-            RawColumnInner::U64VV(_) => panic!("does not hold bools"),
-            RawColumnInner::U64_8(_) => panic!("does not hold bools"),
-            RawColumnInner::U64_8_1(_) => panic!("does not hold bools"),
-            RawColumnInner::U64_16(_) => panic!("does not hold bools"),
-            RawColumnInner::U64_16_1(_) => panic!("does not hold bools"),
-            RawColumnInner::U64_32(_) => panic!("does not hold bools"),
-            RawColumnInner::U64_32_1(_) => panic!("does not hold bools"),
-            RawColumnInner::U64V1(_) => panic!("does not hold bools"),
-        }
+            RawColumnInner::BoolBitmap(b) => column_to_vec(b),
+            RawColumnInner::Custom(c) => c.read(RawKind::Bool, |v| match v {
+                RawValue::Bool(b) => Some(*b),
+                _ => None,
+            }),
+            RawColumnInner::BytesVVV(_)
+            | RawColumnInner::BytesV10(_)
+            | RawColumnInner::BytesFVV(_)
+            | RawColumnInner::BytesF1V(_)
+            | RawColumnInner::BytesDict(_)
+            | RawColumnInner::BytesSparse(_)
+            | RawColumnInner::U64VV(_)
+            | RawColumnInner::U64_8(_)
+            | RawColumnInner::U64_8_1(_)
+            | RawColumnInner::U64Sparse(_)
+            | RawColumnInner::U64BitPacked(_)
+            | RawColumnInner::U64_16(_)
+            | RawColumnInner::U64_16_1(_)
+            | RawColumnInner::U64_32(_)
+            | RawColumnInner::U64_32_1(_)
+            | RawColumnInner::U64V1(_) => {
+                return Err(StorageError::KindMismatch {
+                    expected: RawKind::Bool,
+                    found: self.kind(),
+                })
+            }
+        }?;
+        self.metrics.record_chunks(self.num_chunks());
+        Ok(out)
     }
     /// This isn't what we'll really want to use, but might be useful for
     /// testing?
@@ -145,21 +837,37 @@ impl RawColumn {
     /// It also illustrates how some common logic can be abstracted away into a
     /// helper function like the `column_to_vec` below.
     pub fn read_u64(&self) -> Result<Vec<u64>, StorageError> {
-        match &self.inner {
-            RawColumnInner::U64VV(b) => column_to_vec(b),
-            RawColumnInner::U64_32(b) => column_to_vec(b),
-            RawColumnInner::U64_32_1(b) => column_to_vec(b),
-            RawColumnInner::U64_16(b) => column_to_vec(b),
-            RawColumnInner::U64_16_1(b) => column_to_vec(b),
-            RawColumnInner::U64_8(b) => column_to_vec(b),
-            RawColumnInner::U64_8_1(b) => column_to_vec(b),
-            RawColumnInner::U64V1(b) => column_to_vec(b),
-            RawColumnInner::Bool(_) => panic!("does not hold u64"),
-            RawColumnInner::BytesVVV(_) => panic!("does not hold u64"),
-            RawColumnInner::BytesV10(_) => panic!("does not hold u64"),
-            RawColumnInner::BytesFVV(_) => panic!("does not hold u64"),
-            RawColumnInner::BytesF1V(_) => panic!("does not hold u64"),
-        }
+        let out = match &self.inner {
+            RawColumnInner::U64VV(b) => decode_to_vec(b),
+            RawColumnInner::U64_32(b) => decode_to_vec(b),
+            RawColumnInner::U64_32_1(b) => decode_to_vec(b),
+            RawColumnInner::U64_16(b) => decode_to_vec(b),
+            RawColumnInner::U64_16_1(b) => decode_to_vec(b),
+            RawColumnInner::U64_8(b) => decode_to_vec(b),
+            RawColumnInner::U64_8_1(b) => decode_to_vec(b),
+            RawColumnInner::U64Sparse(b) => decode_to_vec(b),
+            RawColumnInner::U64BitPacked(b) => decode_to_vec(b),
+            RawColumnInner::U64V1(b) => decode_to_vec(b),
+            RawColumnInner::Custom(c) => c.read(RawKind::U64, |v| match v {
+                RawValue::U64(n) => Some(*n),
+                _ => None,
+            }),
+            RawColumnInner::Bool(_)
+            | RawColumnInner::BoolBitmap(_)
+            | RawColumnInner::BytesVVV(_)
+            | RawColumnInner::BytesV10(_)
+            | RawColumnInner::BytesFVV(_)
+            | RawColumnInner::BytesF1V(_)
+            | RawColumnInner::BytesDict(_)
+            | RawColumnInner::BytesSparse(_) => {
+                return Err(StorageError::KindMismatch {
+                    expected: RawKind::U64,
+                    found: self.kind(),
+                })
+            }
+        }?;
+        self.metrics.record_chunks(self.num_chunks());
+        Ok(out)
     }
     /// This isn't what we'll really want to use, but might be useful for
     /// testing?
@@ -167,21 +875,94 @@ impl RawColumn {
     /// It also illustrates how some common logic can be abstracted away into a
     /// helper function like the `column_to_vec` below.
     pub fn read_bytes(&self) -> Result<Vec<Vec<u8>>, StorageError> {
-        match &self.inner {
-            RawColumnInner::U64VV(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64_32(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64_32_1(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64_16(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64_16_1(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64_8(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64_8_1(_) => panic!("does not hold bytes"),
-            RawColumnInner::U64V1(_) => panic!("does not hold bytes"),
-            RawColumnInner::Bool(_) => panic!("does not hold bytes"),
+        let out = match &self.inner {
             RawColumnInner::BytesVVV(c) => column_to_vec(c),
             RawColumnInner::BytesV10(c) => column_to_vec(c),
             RawColumnInner::BytesFVV(c) => column_to_vec(c),
             RawColumnInner::BytesF1V(c) => column_to_vec(c),
-        }
+            RawColumnInner::BytesDict(c) => column_to_vec(c),
+            RawColumnInner::BytesSparse(c) => column_to_vec(c),
+            RawColumnInner::Custom(c) => c.read(RawKind::Bytes, |v| match v {
+                RawValue::Bytes(b) => Some(b.clone()),
+                _ => None,
+            }),
+            RawColumnInner::U64VV(_)
+            | RawColumnInner::U64_32(_)
+            | RawColumnInner::U64_32_1(_)
+            | RawColumnInner::U64_16(_)
+            | RawColumnInner::U64_16_1(_)
+            | RawColumnInner::U64_8(_)
+            | RawColumnInner::U64_8_1(_)
+            | RawColumnInner::U64Sparse(_)
+            | RawColumnInner::U64BitPacked(_)
+            | RawColumnInner::U64V1(_)
+            | RawColumnInner::Bool(_)
+            | RawColumnInner::BoolBitmap(_) => {
+                return Err(StorageError::KindMismatch {
+                    expected: RawKind::Bytes,
+                    found: self.kind(),
+                })
+            }
+        }?;
+        self.metrics.record_chunks(self.num_chunks());
+        Ok(out)
+    }
+
+    /// Iterate over the chunks of a bytes column without copying values.
+    ///
+    /// Each value is borrowed from the encoded column when the column is held
+    /// in memory and the value is stored whole.  Values of columns read from
+    /// a file, or stored as the suffix of the value before them, are copied.
+    pub fn bytes_chunks(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<BytesChunk<'_>, StorageError>>, StorageError> {
+        let chunks = match &self.inner {
+            RawColumnInner::BytesVVV(c) => c.borrowed_chunks(),
+            RawColumnInner::BytesV10(c) => c.borrowed_chunks(),
+            RawColumnInner::BytesFVV(c) => c.borrowed_chunks(),
+            RawColumnInner::BytesF1V(c) => c.borrowed_chunks(),
+            RawColumnInner::BytesDict(c) => owned_chunks(c),
+            RawColumnInner::BytesSparse(c) => owned_chunks(c),
+            RawColumnInner::Custom(c) if c.kind() == RawKind::Bytes => c.bytes_chunks(),
+            _ => {
+                return Err(StorageError::KindMismatch {
+                    expected: RawKind::Bytes,
+                    found: self.kind(),
+                })
+            }
+        };
+        let metrics = self.metrics.clone();
+        Ok(chunks.inspect(move |_| metrics.record_chunks(1)))
+    }
+
+    /// Decode a `u64` or bool column into Arrow buffers, without building a
+    /// vector of values first.
+    ///
+    /// Returns `None` for bytes columns, which have no fixed width.
+    pub fn to_arrow(&self) -> Result<Option<arrow::ArrowArray>, StorageError> {
+        let out = match &self.inner {
+            RawColumnInner::Bool(c) => arrow::from_bools(c).map(Some),
+            RawColumnInner::BoolBitmap(c) => arrow::from_bools(c).map(Some),
+            RawColumnInner::U64VV(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64_32(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64_32_1(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64_16(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64_16_1(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64_8(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64_8_1(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64Sparse(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64BitPacked(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::U64V1(c) => arrow::from_u64(c).map(Some),
+            RawColumnInner::BytesVVV(_) => Ok(None),
+            RawColumnInner::BytesV10(_) => Ok(None),
+            RawColumnInner::BytesFVV(_) => Ok(None),
+            RawColumnInner::BytesF1V(_) => Ok(None),
+            RawColumnInner::BytesDict(_) => Ok(None),
+            RawColumnInner::BytesSparse(_) => Ok(None),
+            RawColumnInner::Custom(c) => arrow::from_runs(c.kind(), c.runs_slice()),
+        }?;
+        self.metrics.record_chunks(self.num_chunks());
+        Ok(out)
     }
 
     /// Decode these bytes as a `RawColumn`
@@ -194,16 +975,66 @@ impl RawColumn {
         Self::open_storage(Storage::open(path)?)
     }
 
+    /// Open a column file, reading at most `bytes` bytes ahead of small
+    /// reads, where zero turns reading ahead off.
+    ///
+    /// Reading ahead serves a scan through the column from memory rather
+    /// than reading the file for each small header; it is on by default.
+    pub fn open_with_read_ahead<P: AsRef<std::path::Path>>(
+        path: P,
+        bytes: u64,
+    ) -> Result<Self, StorageError> {
+        Self::open_storage(Storage::open(path)?.with_read_ahead(bytes))
+    }
+
+    /// Open a column file served over HTTP, reading it with range requests;
+    /// `https://` URLs need the `https` feature
+    pub fn open_url(url: &str, options: HttpOptions) -> Result<Self, StorageError> {
+        Self::open_storage(Storage::http(url, options)?)
+    }
+
+    /// Decode these bytes as a `RawColumn`, counting its reads in `metrics`
+    pub(crate) fn decode_with_metrics(
+        buf: Vec<u8>,
+        metrics: &Metrics,
+    ) -> Result<Self, StorageError> {
+        Self::open_storage(Storage::from(buf).with_metrics(metrics))
+    }
+
+    /// Open a column file, counting its reads in `metrics`
+    pub(crate) fn open_with_metrics(
+        path: &std::path::Path,
+        metrics: &Metrics,
+    ) -> Result<Self, StorageError> {
+        Self::open_storage(Storage::open(path)?.with_metrics(metrics))
+    }
+
     pub(crate) fn open_storage(mut storage: Storage) -> Result<Self, StorageError> {
-        let magic = storage.read_u64()?;
-        storage.seek(0)?;
+        let read_magic = |storage: &Storage| -> Result<u64, StorageError> {
+            let mut magic = [0; 8];
+            storage.read_exact_at(&mut magic, storage.tell()?)?;
+            Ok(u64::from_be_bytes(magic))
+        };
+        let mut magic = read_magic(&storage)?;
+        let mut version = FormatVersion::Unversioned;
+        if magic == version::COLUMN_MAGIC {
+            version = version::read_version(&mut storage)?;
+            magic = read_magic(&storage)?;
+        }
+        let metrics = storage.metrics().clone();
+        let cache_key = storage.file_id().map(|file| CacheKey { file });
+        let byte_len = storage.len();
         let inner = match magic {
             BOOL_MAGIC => RawColumnInner::Bool(BoolColumn::open(storage)?),
+            bitmap::Bitmap::MAGIC => RawColumnInner::BoolBitmap(bitmap::Bitmap::open(storage)?),
 
             bytes::VVV::MAGIC => RawColumnInner::BytesVVV(bytes::VVV::open(storage)?),
             bytes::V10::MAGIC => RawColumnInner::BytesV10(bytes::V10::open(storage)?),
             bytes::FVV::MAGIC => RawColumnInner::BytesFVV(bytes::FVV::open(storage)?),
             bytes::F1V::MAGIC => RawColumnInner::BytesF1V(bytes::F1V::open(storage)?),
+            dictionary::Dictionary::MAGIC => {
+                RawColumnInner::BytesDict(dictionary::Dictionary::open(storage)?)
+            }
 
             u64_generic::U32Variable::MAGIC => {
                 RawColumnInner::U64_32(u64_generic::U32Variable::open(storage)?)
@@ -229,9 +1060,59 @@ impl RawColumn {
             u64_generic::VariableVariable::MAGIC => {
                 RawColumnInner::U64VV(u64_generic::VariableVariable::open(storage)?)
             }
-            _ => return Err(StorageError::BadMagic(magic)),
+            bitpacked::BitPacked::MAGIC => {
+                RawColumnInner::U64BitPacked(bitpacked::BitPacked::open(storage)?)
+            }
+            sparse::SparseU64::MAGIC => {
+                RawColumnInner::U64Sparse(sparse::SparseU64::open(storage)?)
+            }
+            sparse::SparseBytes::MAGIC => {
+                RawColumnInner::BytesSparse(sparse::SparseBytes::open(storage)?)
+            }
+            _ => match custom::CustomColumn::open(magic, storage)? {
+                Some(c) => RawColumnInner::Custom(c),
+                None => return Err(StorageError::BadMagic(magic)),
+            },
         };
-        Ok(RawColumn { inner })
+        Ok(RawColumn {
+            inner,
+            version,
+            metrics,
+            cache_key,
+            byte_len,
+        })
+    }
+
+    /// The version of the encoding the column was written in
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+
+    /// Whether `magic` starts columns in one of the built in formats, or
+    /// their header
+    pub(crate) fn is_built_in(magic: u64) -> bool {
+        matches!(
+            magic,
+            version::COLUMN_MAGIC
+                | BOOL_MAGIC
+                | bitmap::Bitmap::MAGIC
+                | bytes::VVV::MAGIC
+                | bytes::V10::MAGIC
+                | bytes::FVV::MAGIC
+                | bytes::F1V::MAGIC
+                | dictionary::Dictionary::MAGIC
+                | u64_generic::U32Variable::MAGIC
+                | u64_generic::U32One::MAGIC
+                | u64_generic::U16Variable::MAGIC
+                | u64_generic::U16One::MAGIC
+                | u64_generic::U8Variable::MAGIC
+                | u64_generic::U8One::MAGIC
+                | u64_generic::VariableOne::MAGIC
+                | u64_generic::VariableVariable::MAGIC
+                | bitpacked::BitPacked::MAGIC
+                | sparse::SparseU64::MAGIC
+                | sparse::SparseBytes::MAGIC
+        )
     }
 }
 
@@ -254,13 +1135,70 @@ fn column_to_vec<C: IsRawColumn>(column: &C) -> Result<Vec<C::Element>, StorageE
     Ok(out)
 }
 
+pub(crate) type ChunkValues = Box<dyn Iterator<Item = Result<(RawValue, u64), StorageError>>>;
+
+fn chunk_values<C: IsRawColumn + 'static>(
+    column: &C,
+    to_value: fn(C::Element) -> RawValue,
+) -> ChunkValues {
+    Box::new(
+        column
+            .clone()
+            .map(move |c| c.map(|c| (to_value(c.value), c.range.end - c.range.start))),
+    )
+}
+
+/// A chunk of a bytes column, see [`RawColumn::bytes_chunks`]
+pub type BytesChunk<'a> = Chunk<Cow<'a, [u8]>>;
+
+pub(crate) type BorrowedChunks<'a> =
+    Box<dyn Iterator<Item = Result<BytesChunk<'a>, StorageError>> + 'a>;
+
+/// The chunks of a bytes column, with each value copied
+fn owned_chunks<C: IsRawColumn<Element = Vec<u8>>>(column: &C) -> BorrowedChunks<'_> {
+    Box::new(column.clone().map(|c| {
+        c.map(|c| Chunk {
+            value: Cow::Owned(c.value),
+            range: c.range,
+        })
+    }))
+}
+
+/// The values of a column, see [`RawColumn::values`]
+pub(crate) struct Values {
+    chunks: ChunkValues,
+    /// The value of the current chunk, and how many more times it repeats
+    current: Option<(RawValue, u64)>,
+}
+
+impl Iterator for Values {
+    type Item = Result<RawValue, StorageError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((value, remaining)) = &mut self.current {
+                if *remaining > 0 {
+                    *remaining -= 1;
+                    return Some(Ok(value.clone()));
+                }
+            }
+            match self.chunks.next()? {
+                Ok(chunk) => self.current = Some(chunk),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
 pub(crate) enum RawColumnInner {
     Bool(BoolColumn),
+    BoolBitmap(bitmap::Bitmap),
 
     BytesVVV(bytes::VVV),
     BytesV10(bytes::V10),
     BytesFVV(bytes::FVV),
     BytesF1V(bytes::F1V),
+    BytesDict(dictionary::Dictionary),
+    BytesSparse(sparse::SparseBytes),
 
     U64VV(u64_generic::VariableVariable),
     U64V1(u64_generic::VariableOne),
@@ -270,19 +1208,36 @@ pub(crate) enum RawColumnInner {
     U64_16_1(u64_generic::U16One),
     U64_8(u64_generic::U8Variable),
     U64_8_1(u64_generic::U8One),
+    U64Sparse(sparse::SparseU64),
+    U64BitPacked(bitpacked::BitPacked),
+
+    Custom(custom::CustomColumn),
 }
 
 /// A chunk of identical values.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Chunk<T> {
     value: T,
     range: std::ops::Range<u64>,
 }
 
+impl<T> Chunk<T> {
+    /// The value held by every row of the chunk
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// The rows of the chunk
+    pub fn range(&self) -> std::ops::Range<u64> {
+        self.range.clone()
+    }
+}
+
 /// A specific format for a [`RawColumn`].
 ///
 /// Note that this type doubles as a kind of iterator, but a weird one where the
 /// values are borrowed from the iterator not the data itself.
+#[allow(dead_code)]
 pub(crate) trait IsRawColumn:
     Sized + Clone + Iterator<Item = Result<Chunk<Self::Element>, StorageError>> + TryFrom<Storage>
 {
@@ -323,3 +1278,274 @@ pub(crate) trait IsRawColumn:
     /// Returns the (cached) minimum value
     fn min(&self) -> Self::Element;
 }
+
+/// The row after a chunk of `num` rows starting at `start`, which must not
+/// be past the last of the `n_rows` rows of its column
+fn chunk_end(start: u64, num: u64, n_rows: u64) -> Result<u64, StorageError> {
+    start
+        .checked_add(num)
+        .filter(|end| *end <= n_rows)
+        .ok_or(StorageError::OutOfBounds("chunk past the last row"))
+}
+
+/// A format of u64 column that can decode the values of many rows at once
+pub(crate) trait DecodeBlock: IsRawColumn<Element = u64> {
+    /// Decode the values of the next rows into `out`, returning how many
+    /// were decoded, which is fewer than fit only at the end of the column
+    fn decode_block(&mut self, out: &mut [u64]) -> Result<usize, StorageError>;
+}
+
+/// Decode the values of the next chunks of `column` into `out`, keeping any
+/// part of a chunk that does not fit in `carry`.
+///
+/// This is [`DecodeBlock::decode_block`] for formats that store runs, whose
+/// iterators must return the chunk in `carry` before reading another.
+fn decode_chunks<C: IsRawColumn<Element = u64>>(
+    column: &mut C,
+    carry: &mut Option<Chunk<u64>>,
+    out: &mut [u64],
+) -> Result<usize, StorageError> {
+    let mut n = 0;
+    while n < out.len() {
+        let Some(chunk) = carry.take().map(Ok).or_else(|| column.next()) else {
+            break;
+        };
+        let chunk = chunk?;
+        let num = chunk.range.end - chunk.range.start;
+        let fits = num.min((out.len() - n) as u64);
+        out[n..n + fits as usize].fill(chunk.value);
+        n += fits as usize;
+        if fits < num {
+            *carry = Some(Chunk {
+                value: chunk.value,
+                range: chunk.range.start + fits..chunk.range.end,
+            });
+        }
+    }
+    Ok(n)
+}
+
+/// Decode every value of a u64 column, a block at a time
+fn decode_to_vec<C: DecodeBlock>(column: &C) -> Result<Vec<u64>, StorageError> {
+    // The number of rows is read from the column, so rather than allocate
+    // them all up front, the vector grows as blocks are decoded.
+    const BLOCK: usize = 1 << 16;
+    let n_rows = column.num_rows();
+    let mut column = column.clone();
+    let mut out = Vec::new();
+    while (out.len() as u64) < n_rows {
+        let start = out.len();
+        out.resize(
+            start + (n_rows - start as u64).min(BLOCK as u64) as usize,
+            0,
+        );
+        if column.decode_block(&mut out[start..])? < out.len() - start {
+            return Err(StorageError::OutOfBounds(
+                "column ended before its last row",
+            ));
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn split_long_runs() {
+    let options = EncodeOptions::default().max_chunk_rows(100);
+    let columns = [
+        (
+            RawKind::U64,
+            (0..1000).map(|i| RawValue::U64(i / 450)).collect(),
+        ),
+        (
+            RawKind::Bytes,
+            (0..1000)
+                .map(|i| RawValue::Bytes(format!("value {}", i / 450).into_bytes()))
+                .collect(),
+        ),
+        (
+            RawKind::Bytes,
+            (0..1000)
+                .map(|i| RawValue::Bytes(vec![(i / 450) as u8; 3]))
+                .collect(),
+        ),
+        (
+            RawKind::Bool,
+            (0..1000).map(|i| RawValue::Bool(i < 450)).collect(),
+        ),
+    ];
+    for (kind, values) in columns {
+        let values: Vec<RawValue> = values;
+        let mut whole = Vec::new();
+        RawColumn::write_values(&mut whole, kind, &values).unwrap();
+        let whole = RawColumn::decode(whole).unwrap();
+        let mut split = Vec::new();
+        RawColumn::write_values_with(&mut split, kind, &values, options).unwrap();
+        let split = RawColumn::decode(split).unwrap();
+        assert_eq!(split.read_values().unwrap(), values);
+        assert_eq!(split.num_rows(), 1000);
+        if kind == RawKind::Bool {
+            assert_eq!(split.num_chunks(), whole.num_chunks());
+        } else {
+            assert_eq!((whole.num_chunks(), split.num_chunks()), (3, 11));
+        }
+    }
+}
+
+#[test]
+fn read_wrong_kind() {
+    let column = RawColumn::from(&[true, false][..]);
+    let err = column.read_u64().unwrap_err();
+    assert!(matches!(
+        err,
+        StorageError::KindMismatch {
+            expected: RawKind::U64,
+            found: RawKind::Bool
+        }
+    ));
+    assert_eq!(err.to_string(), "Kind mismatch: expected U64, found Bool");
+    let column = RawColumn::from(&[5u64, 6][..]);
+    assert!(column.read_bools().is_err());
+    assert!(column.read_bytes().is_err());
+    assert_eq!(column.read_u64().unwrap(), [5, 6]);
+}
+
+#[test]
+fn fuzz_decoders() {
+    use rand::{Rng, SeedableRng};
+
+    /// Read the column every way there is, which may fail but must not panic
+    fn read_all(column: &RawColumn) {
+        let _ = (
+            column.num_rows(),
+            column.num_chunks(),
+            column.min(),
+            column.max(),
+        );
+        // A few bytes can hold runs of more rows than fit in memory.
+        if column.num_rows() > 1 << 20 {
+            return;
+        }
+        let _ = column.read_values();
+        let _ = column.to_arrow();
+        let _ = column.read_codes();
+        for run in column.runs().take(10_000) {
+            if run.is_err() {
+                break;
+            }
+        }
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(2576);
+    let u64s = (0..300)
+        .map(|i| RawValue::U64(i * 7 % 200))
+        .collect::<Vec<_>>();
+    let bytes = (0..300)
+        .map(|i| RawValue::Bytes(format!("{:04}", i * 7 % 20).into_bytes()))
+        .collect::<Vec<_>>();
+    let bools = (0..300)
+        .map(|i| RawValue::Bool(i % 7 < 3))
+        .collect::<Vec<_>>();
+    for format in ColumnFormat::ALL {
+        let values = match format.kind() {
+            RawKind::U64 => &u64s,
+            RawKind::Bytes => &bytes,
+            RawKind::Bool => &bools,
+        };
+        let mut encoded = Vec::new();
+        format.encode(&mut encoded, values).unwrap();
+        for _ in 0..500 {
+            let mut mutated = encoded.clone();
+            match rng.gen_range(0..3) {
+                0 => mutated.truncate(rng.gen_range(0..encoded.len())),
+                1 => {
+                    for _ in 0..rng.gen_range(1..4) {
+                        let i = rng.gen_range(0..mutated.len());
+                        mutated[i] = rng.gen();
+                    }
+                }
+                _ => {
+                    // Keep the magic, and garble the header after it.
+                    let i = rng.gen_range(8..encoded.len().min(40));
+                    mutated[i] = rng.gen();
+                }
+            }
+            if let Ok(column) = RawColumn::decode(mutated) {
+                read_all(&column);
+            }
+        }
+    }
+    for _ in 0..1000 {
+        let len = rng.gen_range(0..64);
+        let data = (0..len).map(|_| rng.gen()).collect::<Vec<u8>>();
+        let _ = RawValue::decode(&data);
+        if let Ok(column) = RawColumn::decode(data) {
+            read_all(&column);
+        }
+    }
+}
+
+#[test]
+fn merge() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(2616);
+    let mut inputs = Vec::new();
+    let mut all = Vec::new();
+    for format in [
+        ColumnFormat::VarintRuns,
+        ColumnFormat::BitPacked,
+        ColumnFormat::SparseU64,
+    ] {
+        let mut vals = (0..rng.gen_range(0..3000))
+            .map(|_| rng.gen_range(0..100u64) * 1000)
+            .collect::<Vec<_>>();
+        vals.sort_unstable();
+        all.extend_from_slice(&vals);
+        let mut bytes = Vec::new();
+        let options = EncodeOptions::default().format(format);
+        RawColumn::write_u64_with(&mut bytes, &vals, options).unwrap();
+        inputs.push(RawColumn::decode(bytes).unwrap());
+    }
+    let mut merged = Vec::new();
+    RawColumn::merge(&inputs, &mut merged).unwrap();
+    let merged = RawColumn::decode(merged).unwrap();
+    all.sort_unstable();
+    assert_eq!(merged.read_u64().unwrap(), all);
+    // Equal values from different inputs make one chunk.
+    let mut distinct = all.clone();
+    distinct.dedup();
+    assert_eq!(merged.runs().count(), distinct.len());
+
+    let column = |vals: &[&str]| {
+        let vals = vals
+            .iter()
+            .map(|v| v.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let mut bytes = Vec::new();
+        RawColumn::write_bytes(&mut bytes, &vals).unwrap();
+        RawColumn::decode(bytes).unwrap()
+    };
+    let inputs = [
+        column(&["a", "c", "c"]),
+        column(&["c"]),
+        column(&["b", "c", "d"]),
+    ];
+    let mut merged = Vec::new();
+    RawColumn::merge(&inputs, &mut merged).unwrap();
+    let merged = RawColumn::decode(merged).unwrap();
+    let expected = ["a", "b", "c", "c", "c", "c", "d"].map(|v| v.as_bytes().to_vec());
+    assert_eq!(merged.read_bytes().unwrap(), expected);
+
+    let mut bools = Vec::new();
+    RawColumn::write_bools(&mut bools, &[false, true]).unwrap();
+    let bools = RawColumn::decode(bools).unwrap();
+    assert!(matches!(
+        RawColumn::merge(&[column(&["a"]), bools], &mut Vec::new()),
+        Err(StorageError::KindMismatch { .. })
+    ));
+    assert!(RawColumn::merge(&[], &mut Vec::new()).is_err());
+    assert!(matches!(
+        RawColumn::merge(&[column(&["a", "c"]), column(&["b", "a"])], &mut Vec::new()),
+        Err(StorageError::InvalidRow(_))
+    ));
+}
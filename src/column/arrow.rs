@@ -0,0 +1,274 @@
+//! Decoding fixed-width columns into the memory layout of Apache Arrow.
+//!
+//! An Arrow array of a primitive type is a validity bitmap plus a buffer of
+//! values, each 64-byte aligned and padded to a multiple of 64 bytes.  A `u64`
+//! is stored little-endian, while bools (like validity) are packed as bits,
+//! least significant bit first.  Raw columns hold no nulls, so every validity
+//! bit is set.
+//!
+//! The buffers are filled straight from the run-length encoded chunks, so no
+//! intermediate vector of values is built.
+
+use super::{DecodeBlock, IsRawColumn, StorageError};
+use crate::value::{RawKind, RawValue};
+
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct Block([u8; 64]);
+
+/// A 64-byte aligned buffer, zero padded to a multiple of 64 bytes
+#[derive(Clone)]
+pub struct ArrowBuffer {
+    blocks: Vec<Block>,
+    len: usize,
+}
+
+impl ArrowBuffer {
+    fn zeroed(len: usize) -> Self {
+        ArrowBuffer {
+            blocks: vec![Block([0; 64]); div_ceil(len, 64)],
+            len,
+        }
+    }
+
+    /// The number of bytes in the buffer, not counting padding
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the buffer holds no bytes
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bytes of the buffer, including the padding
+    pub fn padded(&self) -> &[u8] {
+        // SAFETY: `Block` is a `repr(C)` wrapper around `[u8; 64]`, so the
+        // blocks are contiguous initialized bytes.
+        unsafe {
+            std::slice::from_raw_parts(self.blocks.as_ptr() as *const u8, self.blocks.len() * 64)
+        }
+    }
+
+    fn padded_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as in `padded`.
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.blocks.as_mut_ptr() as *mut u8,
+                self.blocks.len() * 64,
+            )
+        }
+    }
+
+    fn set_bits(&mut self, range: std::ops::Range<usize>) {
+        let bytes = self.padded_mut();
+        for i in range {
+            bytes[i / 8] |= 1 << (i % 8);
+        }
+    }
+}
+
+impl std::ops::Deref for ArrowBuffer {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.padded()[..self.len]
+    }
+}
+
+impl std::fmt::Debug for ArrowBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ArrowBuffer({:?})", &**self)
+    }
+}
+
+/// A column decoded as an Arrow array
+#[derive(Debug, Clone)]
+pub struct ArrowArray {
+    kind: RawKind,
+    len: usize,
+    validity: ArrowBuffer,
+    values: ArrowBuffer,
+}
+
+impl ArrowArray {
+    /// The kind of the values, which is never [`RawKind::Bytes`]
+    pub fn kind(&self) -> RawKind {
+        self.kind
+    }
+    /// The number of values
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Whether there are no values
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The number of nulls, which is always zero
+    pub fn null_count(&self) -> usize {
+        0
+    }
+    /// The validity bitmap
+    pub fn validity(&self) -> &ArrowBuffer {
+        &self.validity
+    }
+    /// The values
+    pub fn values(&self) -> &ArrowBuffer {
+        &self.values
+    }
+    /// Take the validity bitmap and values buffers
+    pub fn into_buffers(self) -> (ArrowBuffer, ArrowBuffer) {
+        (self.validity, self.values)
+    }
+}
+
+// `usize::div_ceil` needs rust 1.73
+#[allow(unknown_lints, clippy::manual_div_ceil)]
+pub(super) fn div_ceil(n: usize, d: usize) -> usize {
+    (n + d - 1) / d
+}
+
+fn bitmap_len(len: usize) -> usize {
+    div_ceil(len, 8)
+}
+
+fn all_valid(len: usize) -> ArrowBuffer {
+    let mut validity = ArrowBuffer::zeroed(bitmap_len(len));
+    validity.set_bits(0..len);
+    validity
+}
+
+/// The number of rows of a column, if a buffer of 8 bytes for each fits in
+/// memory
+fn checked_len(num_rows: u64) -> Result<usize, StorageError> {
+    usize::try_from(num_rows)
+        .ok()
+        .filter(|len| len.checked_mul(8).is_some())
+        .ok_or(StorageError::OutOfBounds(
+            "too many rows for an arrow array",
+        ))
+}
+
+pub(crate) fn from_u64<C: DecodeBlock>(column: &C) -> Result<ArrowArray, StorageError> {
+    let len = checked_len(column.num_rows())?;
+    let mut values = ArrowBuffer::zeroed(8 * len);
+    let bytes = values.padded_mut();
+    let mut column = column.clone();
+    let mut block = [0; 1024];
+    let mut start = 0;
+    loop {
+        let n = column.decode_block(&mut block)?;
+        if n == 0 {
+            break;
+        }
+        let out = bytes
+            .get_mut(8 * start..8 * (start + n))
+            .ok_or(StorageError::OutOfBounds("row past the end of the column"))?;
+        for (b, v) in out.chunks_exact_mut(8).zip(&block[..n]) {
+            b.copy_from_slice(&v.to_le_bytes());
+        }
+        start += n;
+    }
+    Ok(ArrowArray {
+        kind: RawKind::U64,
+        len,
+        validity: all_valid(len),
+        values,
+    })
+}
+
+pub(crate) fn from_bools<C: IsRawColumn<Element = bool>>(
+    column: &C,
+) -> Result<ArrowArray, StorageError> {
+    let len = checked_len(column.num_rows())?;
+    let mut values = ArrowBuffer::zeroed(bitmap_len(len));
+    for chunk in column.clone() {
+        let chunk = chunk?;
+        if chunk.range.end > len as u64 {
+            return Err(StorageError::OutOfBounds("row past the end of the column"));
+        }
+        if chunk.value {
+            values.set_bits(chunk.range.start as usize..chunk.range.end as usize);
+        }
+    }
+    Ok(ArrowArray {
+        kind: RawKind::Bool,
+        len,
+        validity: all_valid(len),
+        values,
+    })
+}
+
+/// An array of the runs of a column of `kind`, or `None` for bytes
+pub(crate) fn from_runs(
+    kind: RawKind,
+    runs: &[(RawValue, u64)],
+) -> Result<Option<ArrowArray>, StorageError> {
+    let len = checked_len(runs.iter().map(|(_, n)| n).sum())?;
+    let mut values = match kind {
+        RawKind::U64 => ArrowBuffer::zeroed(8 * len),
+        RawKind::Bool => ArrowBuffer::zeroed(bitmap_len(len)),
+        RawKind::Bytes => return Ok(None),
+    };
+    let mut start = 0;
+    for (v, num) in runs {
+        let range = start..start + *num as usize;
+        match v {
+            RawValue::U64(n) => {
+                for b in values.padded_mut()[8 * range.start..8 * range.end].chunks_exact_mut(8) {
+                    b.copy_from_slice(&n.to_le_bytes());
+                }
+            }
+            RawValue::Bool(true) => values.set_bits(range.clone()),
+            _ => (),
+        }
+        start = range.end;
+    }
+    Ok(Some(ArrowArray {
+        kind,
+        len,
+        validity: all_valid(len),
+        values,
+    }))
+}
+
+#[test]
+fn u64_to_arrow() {
+    use super::RawColumn;
+
+    let data: Vec<u64> = (0..100).map(|i| i / 7 * 1000).collect();
+    let array = RawColumn::from(data.as_slice())
+        .to_arrow()
+        .unwrap()
+        .unwrap();
+    assert_eq!(array.kind(), RawKind::U64);
+    assert_eq!(array.len(), 100);
+    assert_eq!(array.values().len(), 800);
+    assert_eq!(array.values().padded().len(), 832);
+    assert_eq!(array.values().padded().as_ptr() as usize % 64, 0);
+    let decoded: Vec<u64> = array
+        .values()
+        .chunks(8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+    assert_eq!(decoded, data);
+    assert_eq!(array.validity().len(), 13);
+    assert!(array.validity()[..12].iter().all(|b| *b == 0xff));
+    assert_eq!(array.validity()[12], 0x0f);
+
+    let bytes = [b"hello".to_vec()];
+    assert!(RawColumn::from(&bytes[..]).to_arrow().unwrap().is_none());
+}
+
+#[test]
+fn bools_to_arrow() {
+    use super::RawColumn;
+
+    let data = [
+        true, true, false, true, false, false, false, false, true, true,
+    ];
+    let array = RawColumn::from(&data[..]).to_arrow().unwrap().unwrap();
+    assert_eq!(array.kind(), RawKind::Bool);
+    assert_eq!(array.len(), 10);
+    assert_eq!(&**array.values(), &[0b0000_1011, 0b11]);
+    assert_eq!(&**array.validity(), &[0xff, 0b11]);
+}
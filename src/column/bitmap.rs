@@ -0,0 +1,287 @@
+//! Bitmap encoding for columns of bools that change often.
+//!
+//! Each row is stored as one bit, the first row in the lowest bit of the
+//! first byte, as Arrow stores bools.  The rows are split into blocks of
+//! [`BLOCK_ROWS`], and the header holds the number of true rows of each
+//! block, so that blocks holding just one value are skipped over without
+//! reading their bits.  Run length encoding needs at least a byte for each
+//! run, so a bitmap is smaller once runs are shorter than about eight rows.
+//!
+//! ```text
+//! MAGIC n_rows n_chunks n_true n_true_in_block* bits
+//! ```
+
+use super::{Chunk, IsRawColumn, ReadEncoded, Storage, StorageError, WriteEncoded};
+
+/// The number of rows in each block but the last
+pub(crate) const BLOCK_ROWS: u64 = 4096;
+
+/// The number of bytes of the bits of `rows` rows
+fn bits_len(rows: u64) -> u64 {
+    super::arrow::div_ceil(rows as usize, 8) as u64
+}
+
+#[derive(Clone)]
+pub(crate) struct Bitmap {
+    storage: Storage,
+    current_row: u64,
+    n_rows: u64,
+    n_chunks: u64,
+    n_true: u64,
+    /// The number of true rows in each block
+    block_true: Vec<u64>,
+    /// The offset of the bits of the first block
+    bits_start: u64,
+    /// The block holding the current row, if it has been read
+    block: Option<u64>,
+    /// The bits of that block, unless it holds just one value
+    bits: Vec<u8>,
+    /// The value of every row of that block, if it holds just one
+    uniform: Option<bool>,
+}
+
+impl From<&[bool]> for Bitmap {
+    fn from(bools: &[bool]) -> Self {
+        let mut bytes = Vec::<u8>::new();
+        Bitmap::encode(&mut bytes, &super::run_length_encode(bools)).expect("error encoding");
+        Bitmap::open(Storage::from(bytes)).unwrap()
+    }
+}
+
+impl Iterator for Bitmap {
+    type Item = Result<Chunk<bool>, StorageError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.transposed_next().transpose()
+    }
+}
+
+impl Bitmap {
+    pub(crate) const MAGIC: u64 = u64::from_be_bytes(*b"boolbmap");
+
+    /// Read the block holding the current row, unless it was already read.
+    ///
+    /// Blocks are read in order, so the storage is at the start of its bits.
+    fn read_block(&mut self) -> Result<(), StorageError> {
+        let block = self.current_row / BLOCK_ROWS;
+        if self.block == Some(block) {
+            return Ok(());
+        }
+        let rows = (self.n_rows - block * BLOCK_ROWS).min(BLOCK_ROWS);
+        let n_true = self.block_true[block as usize];
+        self.block = Some(block);
+        if n_true == 0 || n_true == rows {
+            self.uniform = Some(n_true == rows);
+            self.storage.advance(bits_len(rows))?;
+        } else {
+            self.uniform = None;
+            self.bits.resize(bits_len(rows) as usize, 0);
+            self.storage.read_exact(&mut self.bits)?;
+        }
+        Ok(())
+    }
+
+    /// The value of the current row, whose block has been read
+    fn current(&self) -> bool {
+        let row = self.current_row % BLOCK_ROWS;
+        self.uniform
+            .unwrap_or_else(|| self.bits[(row / 8) as usize] >> (row % 8) & 1 == 1)
+    }
+
+    fn transposed_next(&mut self) -> Result<Option<Chunk<bool>>, StorageError> {
+        if self.current_row == self.n_rows {
+            return Ok(None);
+        }
+        self.read_block()?;
+        let value = self.current();
+        let start = self.current_row;
+        let same = if value { u8::MAX } else { 0 };
+        while self.current_row < self.n_rows {
+            self.read_block()?;
+            let block_end = (self.current_row / BLOCK_ROWS + 1) * BLOCK_ROWS;
+            let row = self.current_row % BLOCK_ROWS;
+            if self.uniform == Some(value) {
+                self.current_row = block_end.min(self.n_rows);
+            } else if row & 7 == 0
+                && self.current_row + 8 <= self.n_rows
+                && self.uniform.is_none()
+                && self.bits[(row / 8) as usize] == same
+            {
+                self.current_row += 8;
+            } else if self.current() == value {
+                self.current_row += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(Some(Chunk {
+            value,
+            range: start..self.current_row,
+        }))
+    }
+}
+
+impl IsRawColumn for Bitmap {
+    type Element = bool;
+
+    fn num_rows(&self) -> u64 {
+        self.n_rows
+    }
+    fn num_chunks(&self) -> u64 {
+        self.n_chunks
+    }
+    fn max(&self) -> Self::Element {
+        self.n_true > 0
+    }
+    fn min(&self) -> Self::Element {
+        self.n_true == self.n_rows
+    }
+
+    fn encode<W: WriteEncoded>(
+        out: &mut W,
+        input: &[(Self::Element, u64)],
+    ) -> Result<(), StorageError> {
+        if input.is_empty() {
+            return Ok(());
+        }
+        let n_rows: u64 = input.iter().map(|x| x.1).sum();
+        let mut bits = vec![0u8; bits_len(n_rows) as usize];
+        let mut block_true = vec![0; super::arrow::div_ceil(n_rows as usize, BLOCK_ROWS as usize)];
+        let mut row = 0;
+        for (v, num) in input.iter() {
+            if *v {
+                for r in row..row + num {
+                    bits[(r / 8) as usize] |= 1 << (r % 8);
+                    block_true[(r / BLOCK_ROWS) as usize] += 1;
+                }
+            }
+            row += num;
+        }
+        out.write_u64(Self::MAGIC)?;
+        out.write_u64(n_rows)?;
+        out.write_u64(input.len() as u64)?;
+        out.write_u64(block_true.iter().sum())?;
+        for n in block_true {
+            out.write_unsigned(n)?;
+        }
+        out.write_all(&bits)?;
+        Ok(())
+    }
+
+    fn open(mut storage: Storage) -> Result<Self, StorageError> {
+        let magic = storage.read_u64()?;
+        if magic != Self::MAGIC {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let n_rows = storage.read_u64()?;
+        let n_chunks = storage.read_u64()?;
+        let n_true = storage.read_u64()?;
+        if n_chunks > n_rows || n_true > n_rows {
+            return Err(StorageError::OutOfBounds("bitmap header"));
+        }
+        // The vector grows as the counts are read, in case the number of
+        // rows is corrupt.
+        let mut block_true = Vec::new();
+        let n_blocks = super::arrow::div_ceil(n_rows as usize, BLOCK_ROWS as usize) as u64;
+        for block in 0..n_blocks {
+            let n = storage.read_usigned()?;
+            if n > (n_rows - block * BLOCK_ROWS).min(BLOCK_ROWS) {
+                return Err(StorageError::OutOfBounds("bitmap block count"));
+            }
+            block_true.push(n);
+        }
+        if block_true.iter().sum::<u64>() != n_true {
+            return Err(StorageError::OutOfBounds("bitmap block counts"));
+        }
+        let bits_start = storage.tell()?;
+        Ok(Bitmap {
+            storage,
+            current_row: 0,
+            n_rows,
+            n_chunks,
+            n_true,
+            block_true,
+            bits_start,
+            block: None,
+            bits: Vec::new(),
+            uniform: None,
+        })
+    }
+
+    fn tell(&self) -> Result<u64, StorageError> {
+        Ok(self.bits_start + self.current_row / BLOCK_ROWS * bits_len(BLOCK_ROWS))
+    }
+
+    /// Rows are found by their number alone, so the offset is ignored
+    fn seek(
+        &mut self,
+        _offset: u64,
+        row_number: u64,
+        _value: impl AsRef<Self::Element>,
+    ) -> Result<(), StorageError> {
+        self.current_row = row_number;
+        self.block = None;
+        let block = row_number / BLOCK_ROWS;
+        self.storage
+            .seek(self.bits_start + block * bits_len(BLOCK_ROWS))
+    }
+}
+
+impl TryFrom<Storage> for Bitmap {
+    type Error = StorageError;
+    fn try_from(storage: Storage) -> Result<Self, Self::Error> {
+        Self::open(storage)
+    }
+}
+
+#[test]
+fn encode_bitmap() {
+    use super::{run_length_encode, BoolColumn, RawColumn, RawValue};
+
+    // Alternating values cost a bit each, rather than a byte for each run.
+    let bools = (0..10_000).map(|i| i % 3 == 0).collect::<Vec<_>>();
+    let c = RawColumn::from(bools.as_slice());
+    assert!(matches!(c.inner, super::RawColumnInner::BoolBitmap(_)));
+    assert_eq!(c.read_bools().unwrap(), bools);
+    assert_eq!(c.num_chunks(), run_length_encode(&bools).len() as u64);
+    assert_eq!(c.runs().count() as u64, c.num_chunks());
+    assert!(c.byte_len() < 10_000 / 8 + 100);
+    assert_eq!(
+        (c.min(), c.max()),
+        (RawValue::Bool(false), RawValue::Bool(true))
+    );
+
+    // Long runs are still run length encoded.
+    let runs = (0..10_000).map(|i| i % 3000 < 1000).collect::<Vec<_>>();
+    let c = RawColumn::from(runs.as_slice());
+    assert!(matches!(c.inner, super::RawColumnInner::Bool(_)));
+
+    // Blocks holding one value are skipped, and runs may cross blocks.
+    let mut mixed = vec![true; 3 * BLOCK_ROWS as usize + 10];
+    for i in (0..BLOCK_ROWS as usize).step_by(3) {
+        mixed[i] = false;
+    }
+    mixed[2 * BLOCK_ROWS as usize + 17] = false;
+    let bitmap = Bitmap::from(mixed.as_slice());
+    assert_eq!(
+        bitmap.clone().map(|c| c.unwrap().range).collect::<Vec<_>>(),
+        BoolColumn::from(mixed.as_slice())
+            .map(|c| c.unwrap().range)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(
+        (IsRawColumn::min(&bitmap), IsRawColumn::max(&bitmap)),
+        (false, true)
+    );
+    for b in [false, true] {
+        let c = Bitmap::from(&[b; 100][..]);
+        assert_eq!((IsRawColumn::min(&c), IsRawColumn::max(&c)), (b, b));
+        let ranges = c.map(|c| c.unwrap().range).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..100]);
+    }
+
+    // A block count larger than the block is rejected.
+    let mut bad = Vec::new();
+    Bitmap::encode(&mut bad, &[(true, 1), (false, 1)]).unwrap();
+    bad[32] = 3;
+    assert!(Bitmap::open(Storage::from(bad)).is_err());
+}
@@ -0,0 +1,290 @@
+//! Frame of reference encoding for columns of u64, bit packed in blocks.
+//!
+//! The rows are split into blocks of [`BLOCK_ROWS`] values.  Each block
+//! stores its smallest value as a base, and each of its values as the
+//! difference from that base, packed using just as many bits as the largest
+//! difference needs, anywhere from 0 to 64.  A few large values then only
+//! cost space in the blocks holding them, where the fixed width formats
+//! store every value with the width needed by the largest in the column.
+//!
+//! ```text
+//! MAGIC n_rows min max (base-min width packed)*
+//! ```
+//!
+//! Each row is a chunk of its own, so this suits columns without runs.
+
+use super::{Chunk, DecodeBlock, IsRawColumn, ReadEncoded, Storage, StorageError, WriteEncoded};
+
+/// The number of values in each block but the last
+pub(crate) const BLOCK_ROWS: usize = 1024;
+
+/// The number of bits needed to store `v`
+fn bit_width(v: u64) -> u32 {
+    64 - v.leading_zeros()
+}
+
+fn packed_len(n: usize, width: u32) -> usize {
+    super::arrow::div_ceil(n * width as usize, 8)
+}
+
+fn pack(out: &mut Vec<u8>, vals: &[u64], base: u64, width: u32) {
+    let mut acc: u128 = 0;
+    let mut bits = 0;
+    for v in vals {
+        acc |= ((v - base) as u128) << bits;
+        bits += width;
+        while bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    }
+    if bits > 0 {
+        out.push(acc as u8);
+    }
+}
+
+fn unpack(out: &mut [u64], packed: &[u8], base: u64, width: u32) -> Result<(), StorageError> {
+    let mask = (1u128 << width) - 1;
+    let mut acc: u128 = 0;
+    let mut bits = 0;
+    let mut bytes = packed.iter();
+    for v in out.iter_mut() {
+        while bits < width {
+            let Some(b) = bytes.next() else {
+                return Err(StorageError::OutOfBounds("bit packed block is too short"));
+            };
+            acc |= (*b as u128) << bits;
+            bits += 8;
+        }
+        *v = base
+            .checked_add((acc & mask) as u64)
+            .ok_or(StorageError::OutOfBounds("bit packed value overflows"))?;
+        acc >>= width;
+        bits -= width;
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub(crate) struct BitPacked {
+    storage: Storage,
+    current_row: u64,
+    n_rows: u64,
+    v_min: u64,
+    v_max: u64,
+    /// The decoded values of the current block
+    block: Vec<u64>,
+    /// The position in `block` of the value of the current row
+    position: usize,
+}
+
+impl From<&[u64]> for BitPacked {
+    fn from(vals: &[u64]) -> Self {
+        let mut bytes = Vec::<u8>::new();
+        Self::encode(&mut bytes, &super::run_length_encode(vals)).expect("error encoding");
+        let storage = Storage::from(bytes);
+        Self::open(storage).unwrap()
+    }
+}
+
+impl Iterator for BitPacked {
+    type Item = Result<Chunk<u64>, StorageError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.transposed_next().transpose()
+    }
+}
+
+impl BitPacked {
+    pub(crate) const MAGIC: u64 = u64::from_be_bytes(*b"u64bitpk");
+
+    fn transposed_next(&mut self) -> Result<Option<Chunk<u64>>, StorageError> {
+        if self.current_row == self.n_rows {
+            return Ok(None);
+        }
+        if self.position == self.block.len() {
+            self.read_block()?;
+        }
+        let value = self.block[self.position];
+        self.position += 1;
+        self.current_row += 1;
+        Ok(Some(Chunk {
+            value,
+            range: self.current_row - 1..self.current_row,
+        }))
+    }
+
+    /// Decode the block starting at the current row
+    fn read_block(&mut self) -> Result<(), StorageError> {
+        let len = (self.n_rows - self.current_row).min(BLOCK_ROWS as u64) as usize;
+        let base = self
+            .v_min
+            .checked_add(self.storage.read_usigned()?)
+            .ok_or(StorageError::OutOfBounds("bit packed base overflows"))?;
+        let width = self.storage.read_u8()? as u32;
+        if width > 64 {
+            return Err(StorageError::OutOfBounds("bit packed width"));
+        }
+        let mut packed = vec![0; packed_len(len, width)];
+        self.storage.read_exact(&mut packed)?;
+        self.block.resize(len, 0);
+        self.position = 0;
+        unpack(&mut self.block, &packed, base, width)
+    }
+}
+
+impl IsRawColumn for BitPacked {
+    type Element = u64;
+
+    fn num_rows(&self) -> u64 {
+        self.n_rows
+    }
+    fn num_chunks(&self) -> u64 {
+        self.n_rows
+    }
+    fn max(&self) -> Self::Element {
+        self.v_max
+    }
+    fn min(&self) -> Self::Element {
+        self.v_min
+    }
+
+    fn encode<W: WriteEncoded>(
+        out: &mut W,
+        input: &[(Self::Element, u64)],
+    ) -> Result<(), StorageError> {
+        if input.is_empty() {
+            return Ok(());
+        }
+        let mut vals = Vec::new();
+        for &(v, num) in input.iter() {
+            for _ in 0..num {
+                vals.push(v);
+            }
+        }
+        let min = vals.iter().copied().min().unwrap_or_default();
+        let max = vals.iter().copied().max().unwrap_or_default();
+        out.write_u64(Self::MAGIC)?;
+        out.write_u64(vals.len() as u64)?;
+        out.write_u64(min)?;
+        out.write_u64(max)?;
+        let mut packed = Vec::new();
+        for block in vals.chunks(BLOCK_ROWS) {
+            let base = block.iter().copied().min().unwrap_or_default();
+            let width = bit_width(block.iter().copied().max().unwrap_or_default() - base);
+            out.write_unsigned(base - min)?;
+            out.write_u8(width as u8)?;
+            packed.clear();
+            pack(&mut packed, block, base, width);
+            out.write_all(&packed)?;
+        }
+        Ok(())
+    }
+
+    fn open(mut storage: Storage) -> Result<Self, StorageError> {
+        let magic = storage.read_u64()?;
+        if magic != Self::MAGIC {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let n_rows = storage.read_u64()?;
+        let v_min = storage.read_u64()?;
+        let v_max = storage.read_u64()?;
+        Ok(BitPacked {
+            storage,
+            current_row: 0,
+            n_rows,
+            v_min,
+            v_max,
+            block: Vec::new(),
+            position: 0,
+        })
+    }
+
+    fn tell(&self) -> Result<u64, StorageError> {
+        self.storage.tell()
+    }
+
+    /// Seek to the start of a block, which must hold `row_number`
+    fn seek(
+        &mut self,
+        offset: u64,
+        row_number: u64,
+        _value: impl AsRef<Self::Element>,
+    ) -> Result<(), StorageError> {
+        self.current_row = row_number;
+        self.block.clear();
+        self.position = 0;
+        self.storage.seek(offset)
+    }
+}
+
+impl DecodeBlock for BitPacked {
+    fn decode_block(&mut self, out: &mut [u64]) -> Result<usize, StorageError> {
+        let mut n = 0;
+        while n < out.len() && self.current_row < self.n_rows {
+            if self.position == self.block.len() {
+                self.read_block()?;
+            }
+            let num = (self.block.len() - self.position).min(out.len() - n);
+            out[n..n + num].copy_from_slice(&self.block[self.position..self.position + num]);
+            self.position += num;
+            self.current_row += num as u64;
+            n += num;
+        }
+        Ok(n)
+    }
+}
+
+impl TryFrom<Storage> for BitPacked {
+    type Error = StorageError;
+    fn try_from(storage: Storage) -> Result<Self, Self::Error> {
+        Self::open(storage)
+    }
+}
+
+#[test]
+fn encode_bit_packed() {
+    use rand::{Rng, SeedableRng};
+
+    use super::{ColumnFormat, RawColumn};
+
+    // Mostly small values, with the odd large one.
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let vals = (0..5000)
+        .map(|i| {
+            if i % 2500 == 3 {
+                1_000_000 + rng.gen_range(0..1000)
+            } else {
+                rng.gen_range(0..16)
+            }
+        })
+        .collect::<Vec<u64>>();
+    let c = RawColumn::from(vals.as_slice());
+    assert_eq!(c.format(), ColumnFormat::BitPacked);
+    assert_eq!(c.read_u64().unwrap(), vals);
+    assert_eq!((c.num_rows(), c.num_chunks()), (5000, 5000));
+    assert_eq!(c.runs().count(), 5000);
+    let runs = super::run_length_encode(&vals);
+    let packed = ColumnFormat::BitPacked.estimate_u64(&runs).unwrap();
+    assert!(2 * packed < ColumnFormat::U32Runs.estimate_u64(&runs).unwrap());
+
+    // Every width round trips, including the full 64 bits.
+    for width in 0..=64 {
+        let top = if width == 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        let vals = [5, top, 17, top / 3, u64::MAX - top]
+            .iter()
+            .map(|v| v & top)
+            .collect::<Vec<_>>();
+        let c = BitPacked::from(vals.as_slice());
+        assert_eq!(super::column_to_vec(&c).unwrap(), vals, "width {width}");
+    }
+
+    // Values in runs are left run length encoded.
+    let vals = (0..3000).map(|i| i / 3 * 7 % 256).collect::<Vec<u64>>();
+    let c = RawColumn::from(vals.as_slice());
+    assert_eq!(c.format(), ColumnFormat::U8Runs);
+}
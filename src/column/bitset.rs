@@ -0,0 +1,147 @@
+//! Set operations between columns of bools.
+//!
+//! Each column of bools is read as its runs of identical values, whether it
+//! is run length encoded or a bitmap, and the runs of two columns are walked
+//! side by side, so the rows passing `a AND b` are found a run at a time
+//! rather than by decoding either column into a vector of bools.
+
+use super::{RawColumn, StorageError};
+use crate::expr::Selection;
+use crate::{RawKind, RawValue};
+
+impl RawColumn {
+    /// The runs of a column of bools, as each value with its number of rows
+    fn bool_runs(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<(bool, u64), StorageError>>, StorageError> {
+        if self.kind() != RawKind::Bool {
+            return Err(StorageError::KindMismatch {
+                expected: RawKind::Bool,
+                found: self.kind(),
+            });
+        }
+        Ok(self.runs().map(|run| match run? {
+            (RawValue::Bool(b), num) => Ok((b, num)),
+            _ => Err(StorageError::OutOfBounds("bool column holds another kind")),
+        }))
+    }
+
+    /// The rows of a column of bools that hold true
+    pub fn true_rows(&self) -> Result<Selection, StorageError> {
+        let mut selection = Selection::default();
+        let mut row = 0;
+        for run in self.bool_runs()? {
+            let (value, num) = run?;
+            if value {
+                selection.push(row..row + num);
+            }
+            row += num;
+        }
+        Ok(selection)
+    }
+
+    /// The rows of a column of bools that hold false
+    pub fn false_rows(&self) -> Result<Selection, StorageError> {
+        Ok(self.true_rows()?.complement(self.num_rows()))
+    }
+
+    /// The rows in which both this column of bools and `other` hold true
+    pub fn and(&self, other: &RawColumn) -> Result<Selection, StorageError> {
+        self.combine_bools(other, |a, b| a && b)
+    }
+
+    /// The rows in which either this column of bools or `other` holds true
+    pub fn or(&self, other: &RawColumn) -> Result<Selection, StorageError> {
+        self.combine_bools(other, |a, b| a || b)
+    }
+
+    /// The rows for which `op` holds of the values of this column of bools
+    /// and of `other`, which must have as many rows
+    pub(crate) fn combine_bools(
+        &self,
+        other: &RawColumn,
+        op: impl Fn(bool, bool) -> bool,
+    ) -> Result<Selection, StorageError> {
+        if self.num_rows() != other.num_rows() {
+            return Err(StorageError::OutOfBounds(
+                "columns have different numbers of rows",
+            ));
+        }
+        let (mut a_runs, mut b_runs) = (self.bool_runs()?, other.bool_runs()?);
+        let (mut a, mut b) = ((false, 0), (false, 0));
+        let mut selection = Selection::default();
+        let mut row = 0;
+        while row < self.num_rows() {
+            next_run(&mut a, &mut a_runs)?;
+            next_run(&mut b, &mut b_runs)?;
+            let num = a.1.min(b.1);
+            if op(a.0, b.0) {
+                selection.push(row..row + num);
+            }
+            row += num;
+            a.1 -= num;
+            b.1 -= num;
+        }
+        Ok(selection)
+    }
+}
+
+/// Read the next run into `run` once every row of it has been used
+fn next_run(
+    run: &mut (bool, u64),
+    runs: &mut impl Iterator<Item = Result<(bool, u64), StorageError>>,
+) -> Result<(), StorageError> {
+    if run.1 == 0 {
+        *run = runs.next().ok_or(StorageError::OutOfBounds(
+            "column ended before its last row",
+        ))??;
+    }
+    Ok(())
+}
+
+#[test]
+fn combine_bool_columns() {
+    use crate::ColumnFormat;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let flips = (0..5000).map(|_| rng.gen_bool(0.5)).collect::<Vec<_>>();
+    let runs = (0..5000).map(|i| i % 700 < 300).collect::<Vec<_>>();
+    let flips_column = RawColumn::from(flips.as_slice());
+    let runs_column = RawColumn::from(runs.as_slice());
+    assert_eq!(flips_column.format(), ColumnFormat::Bitmap);
+    assert_eq!(runs_column.format(), ColumnFormat::Bools);
+
+    let rows =
+        |f: &dyn Fn(usize) -> bool| (0..5000).filter(|i| f(*i as usize)).collect::<Vec<u64>>();
+    let selected = |s: Selection| s.rows().collect::<Vec<_>>();
+    assert_eq!(
+        selected(flips_column.true_rows().unwrap()),
+        rows(&|i| flips[i])
+    );
+    assert_eq!(
+        selected(runs_column.false_rows().unwrap()),
+        rows(&|i| !runs[i])
+    );
+    assert_eq!(
+        selected(flips_column.and(&runs_column).unwrap()),
+        rows(&|i| flips[i] && runs[i])
+    );
+    assert_eq!(
+        selected(runs_column.or(&flips_column).unwrap()),
+        rows(&|i| flips[i] || runs[i])
+    );
+    let both = flips_column.and(&flips_column).unwrap();
+    assert_eq!(both, flips_column.true_rows().unwrap());
+    assert_eq!(both.len(), flips.iter().filter(|b| **b).count() as u64);
+
+    // Columns of other kinds or lengths cannot be combined.
+    let numbers = RawColumn::from(&[1u64, 2][..]);
+    assert!(matches!(
+        numbers.true_rows(),
+        Err(StorageError::KindMismatch { .. })
+    ));
+    assert!(flips_column
+        .and(&RawColumn::from(&[true, false][..]))
+        .is_err());
+}
@@ -13,7 +13,6 @@ impl From<&[bool]> for BoolColumn {
     fn from(bools: &[bool]) -> Self {
         let mut bytes = Vec::<u8>::new();
         BoolColumn::encode(&mut bytes, &super::run_length_encode(bools)).unwrap();
-        println!("encoded is {bytes:?}");
         let storage = Storage::from(bytes);
         BoolColumn::open(storage).unwrap()
     }
@@ -32,7 +31,7 @@ impl BoolColumn {
         }
         let num = self.storage.read_usigned()?;
         let current_row = self.current_row;
-        self.current_row = current_row + num;
+        self.current_row = super::chunk_end(current_row, num, self.n_rows)?;
         self.last = !self.last;
         Ok(Some(Chunk {
             value: self.last,
@@ -53,7 +52,7 @@ impl IsRawColumn for BoolColumn {
         self.n_chunks > 1 || !self.last
     }
     fn min(&self) -> Self::Element {
-        !(self.n_chunks > 1) && self.last
+        self.n_chunks <= 1 && !self.last
     }
 
     fn encode<W: WriteEncoded>(
@@ -74,9 +73,7 @@ impl IsRawColumn for BoolColumn {
     }
 
     fn open(mut storage: Storage) -> Result<Self, StorageError> {
-        println!("offset starts at {}", storage.tell().unwrap());
         let magic = storage.read_u64()?;
-        println!("after magic {}", storage.tell().unwrap());
         if magic != BOOL_MAGIC {
             return Err(StorageError::BadMagic(magic));
         }
@@ -117,13 +114,11 @@ impl TryFrom<Storage> for BoolColumn {
 
 #[test]
 fn encode_bools() {
-    use super::{RawColumn, RawColumnInner};
+    use super::RawColumn;
 
     let bools = [true, true, false, true, true, true];
     let bc = BoolColumn::from(&bools[..]);
-    let c = RawColumn {
-        inner: RawColumnInner::Bool(bc.clone()),
-    };
+    let c = RawColumn::from(&bools[..]);
     assert_eq!(c.read_bools().unwrap().as_slice(), &bools);
 
     let mut encoded: Vec<u8> = Vec::new();
@@ -149,4 +144,14 @@ fn encode_bools() {
     <BoolColumn as IsRawColumn>::encode(&mut f, chunks.as_slice()).unwrap();
     let c = RawColumn::try_from(f).unwrap();
     assert_eq!(c.read_bools().unwrap().as_slice(), &bools);
+
+    use crate::RawValue;
+    assert_eq!(
+        (c.min(), c.max()),
+        (RawValue::Bool(false), RawValue::Bool(true))
+    );
+    for b in [false, true] {
+        let c = RawColumn::from(&[b, b][..]);
+        assert_eq!((c.min(), c.max()), (RawValue::Bool(b), RawValue::Bool(b)));
+    }
 }
@@ -1,7 +1,9 @@
 //! Will be private
+use std::borrow::Cow;
+
 use super::{
-    encoding::BitWidth, Chunk, IsRawColumn, ReadEncoded, Storage, StorageError, WriteEncoded,
-    BYTES_GENERIC_MAGIC,
+    encoding::BitWidth, BorrowedChunks, Chunk, IsRawColumn, ReadEncoded, Storage, StorageError,
+    WriteEncoded, BYTES_GENERIC_MAGIC,
 };
 
 #[derive(Clone)]
@@ -50,6 +52,7 @@ impl Format {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) type VVV = Bytes<
     {
         Format {
@@ -72,6 +75,7 @@ pub(crate) type V10 = Bytes<
     },
 >;
 
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) type FVV = Bytes<
     {
         Format {
@@ -126,28 +130,105 @@ impl<const F: u64> Bytes<F> {
         if self.current_row == self.n_rows {
             return Ok(None);
         }
+        let (num, length, prefix) = self.read_header()?;
+        let value = self.read_suffix(length, prefix)?;
+        Ok(Some(Chunk {
+            value,
+            range: self.next_range(num)?,
+        }))
+    }
+
+    /// Read the run length, length and shared prefix of the next chunk
+    fn read_header(&mut self) -> Result<(u64, u64, u64), StorageError> {
         let format = Format::from_bytes(F)?;
         let num = self.storage.read_bitwidth(format.runlength)?;
-        let length = self.l_min + self.storage.read_bitwidth(format.length)?;
+        let length = self
+            .l_min
+            .checked_add(self.storage.read_bitwidth(format.length)?)
+            .ok_or(StorageError::OutOfBounds("bytes length overflows"))?;
         let prefix = self.storage.read_bitwidth(format.prefix)?;
+        if prefix > length {
+            return Err(StorageError::OutOfBounds("bytes prefix"));
+        }
+        Ok((num, length, prefix))
+    }
 
-        self.previous.truncate(prefix as usize);
-        for _ in 0..(length - prefix) as usize {
-            self.previous.push(0);
+    /// Read the rest of a value that shares `prefix` bytes with the previous
+    fn read_suffix(&mut self, length: u64, prefix: u64) -> Result<Vec<u8>, StorageError> {
+        if prefix > self.previous.len() as u64 {
+            return Err(StorageError::OutOfBounds("bytes prefix"));
         }
-        self.storage
-            .read_exact(&mut self.previous[prefix as usize..length as usize])?;
+        self.previous.truncate(prefix as usize);
+        let suffix = self.storage.read_vec(length - prefix)?;
+        self.previous.extend(suffix);
+        Ok(self.previous.clone())
+    }
 
-        let value = self.previous.clone();
+    /// The rows of the next chunk, which repeats for `num` rows
+    fn next_range(&mut self, num: u64) -> Result<std::ops::Range<u64>, StorageError> {
         let current_row = self.current_row;
-        self.current_row = current_row + num;
+        self.current_row = super::chunk_end(current_row, num, self.n_rows)?;
+        Ok(current_row..self.current_row)
+    }
 
+    /// The chunks of the column, with each value that is stored whole
+    /// borrowed from the encoded column, if it is held in memory
+    pub(crate) fn borrowed_chunks(&self) -> BorrowedChunks<'_> {
+        match self.storage.as_slice() {
+            Some(buffer) => Box::new(Borrowed {
+                column: self.clone(),
+                buffer,
+                previous: None,
+            }),
+            None => super::owned_chunks(self),
+        }
+    }
+}
+
+/// A bytes column held in memory, read a chunk at a time
+struct Borrowed<'a, const F: u64> {
+    column: Bytes<F>,
+    buffer: &'a [u8],
+    /// Where the previous value lies in the buffer, if it was borrowed
+    /// rather than copied into the column
+    previous: Option<std::ops::Range<usize>>,
+}
+
+impl<'a, const F: u64> Borrowed<'a, F> {
+    fn transposed_next(&mut self) -> Result<Option<Chunk<Cow<'a, [u8]>>>, StorageError> {
+        if self.column.current_row == self.column.n_rows {
+            return Ok(None);
+        }
+        let (num, length, prefix) = self.column.read_header()?;
+        let value = if prefix == 0 {
+            let start = self.column.storage.advance(length)? as usize;
+            let range = start..start + length as usize;
+            let value = self
+                .buffer
+                .get(range.clone())
+                .ok_or(StorageError::OutOfBounds("bytes value"))?;
+            self.previous = Some(range);
+            Cow::Borrowed(value)
+        } else {
+            // The value shares a prefix with the previous, so it is copied.
+            if let Some(range) = self.previous.take() {
+                self.column.previous = self.buffer[range].to_vec();
+            }
+            Cow::Owned(self.column.read_suffix(length, prefix)?)
+        };
         Ok(Some(Chunk {
             value,
-            range: current_row..self.current_row,
+            range: self.column.next_range(num)?,
         }))
     }
 }
+
+impl<'a, const F: u64> Iterator for Borrowed<'a, F> {
+    type Item = Result<Chunk<Cow<'a, [u8]>>, StorageError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.transposed_next().transpose()
+    }
+}
 impl<const F: u64> IsRawColumn for Bytes<F> {
     type Element = Vec<u8>;
 
@@ -228,13 +309,15 @@ impl<const F: u64> IsRawColumn for Bytes<F> {
         let n_chunks = storage.read_u64()?;
         let l_min = storage.read_u64()?;
 
-        let len_min = storage.read_bitwidth(format.length)? + l_min;
-        let mut v_min = vec![0; len_min as usize];
-        storage.read_exact(v_min.as_mut_slice())?;
-
-        let len_max = storage.read_bitwidth(format.length)? + l_min;
-        let mut v_max = vec![0; len_max as usize];
-        storage.read_exact(v_max.as_mut_slice())?;
+        let mut read_value = || -> Result<Vec<u8>, StorageError> {
+            let len = storage
+                .read_bitwidth(format.length)?
+                .checked_add(l_min)
+                .ok_or(StorageError::OutOfBounds("bytes length overflows"))?;
+            storage.read_vec(len)
+        };
+        let v_min = read_value()?;
+        let v_max = read_value()?;
         Ok(Bytes {
             storage,
             n_chunks,
@@ -433,3 +516,55 @@ fn test_encode_f1v() {
     let rc = RawColumn::try_from(f).unwrap();
     assert_eq!(rc.read_bytes().unwrap().as_slice(), &data);
 }
+
+#[test]
+fn borrow_chunks() {
+    use super::{ColumnFormat, EncodeOptions, RawColumn, RawKind, RawValue};
+    use std::borrow::Cow;
+
+    let data = [b"apple".to_vec(), b"apricot".to_vec(), b"banana".to_vec()];
+    let values = data
+        .iter()
+        .cloned()
+        .map(RawValue::Bytes)
+        .collect::<Vec<_>>();
+    let encode = |format| {
+        let mut bytes = Vec::new();
+        let options = EncodeOptions::default().format(format);
+        RawColumn::write_values_with(&mut bytes, RawKind::Bytes, &values, options).unwrap();
+        bytes
+    };
+    let borrowed = |column: &RawColumn| {
+        column
+            .bytes_chunks()
+            .unwrap()
+            .map(|c| {
+                let c = c.unwrap();
+                assert_eq!(c.range().end - c.range().start, 1);
+                (c.value().to_vec(), matches!(c.value(), Cow::Borrowed(_)))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Values stored whole are borrowed, but "apricot" shares a prefix with
+    // "apple" and is copied.
+    let column = RawColumn::decode(encode(ColumnFormat::BytesRuns)).unwrap();
+    let chunks = borrowed(&column);
+    assert_eq!(
+        chunks.iter().map(|c| c.1).collect::<Vec<_>>(),
+        [true, false, true]
+    );
+    assert_eq!(chunks.into_iter().map(|c| c.0).collect::<Vec<_>>(), data);
+    let column = RawColumn::decode(encode(ColumnFormat::Bytes)).unwrap();
+    assert!(borrowed(&column).iter().all(|c| c.1));
+
+    // Columns read from a file are copied.
+    let mut f = tempfile::tempfile().unwrap();
+    std::io::Write::write_all(&mut f, &encode(ColumnFormat::Bytes)).unwrap();
+    let column = RawColumn::try_from(f).unwrap();
+    let chunks = borrowed(&column);
+    assert!(chunks.iter().all(|c| !c.1));
+    assert_eq!(chunks.into_iter().map(|c| c.0).collect::<Vec<_>>(), data);
+
+    assert!(RawColumn::from(&[1u64][..]).bytes_chunks().is_err());
+}
@@ -0,0 +1,207 @@
+//! A cache of decoded columns, so that reading the same columns again need
+//! not decode them again.
+//!
+//! Each entry holds every value of a column decoded from a file, keyed by an
+//! id of the file.  Column files are never modified once written, so an
+//! entry never goes stale.  When the
+//! entries hold more than the capacity of the cache, the least recently used
+//! are evicted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::RawValue;
+
+/// The file a column was decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    /// The id of the file, which differs for any file written in its place
+    pub(crate) file: u64,
+}
+
+/// The capacity of the cache of a database, unless it is changed with
+/// [`Database::set_cache_capacity`](crate::Database::set_cache_capacity)
+pub(crate) const DEFAULT_CAPACITY: usize = 64 << 20;
+
+/// A least recently used cache of decoded columns, shared by its clones
+#[derive(Debug, Clone)]
+pub(crate) struct ColumnCache {
+    lru: Arc<Mutex<Lru>>,
+}
+
+#[derive(Debug)]
+struct Lru {
+    /// The most bytes the entries may hold
+    capacity: usize,
+    /// The bytes the entries hold
+    used: usize,
+    /// Counts the uses of entries, to order them by when they were last used
+    tick: u64,
+    entries: HashMap<CacheKey, Entry>,
+    /// The key of each entry, by when it was last used
+    by_use: BTreeMap<u64, CacheKey>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    values: Arc<Vec<RawValue>>,
+    size: usize,
+    last_used: u64,
+}
+
+/// Roughly the bytes of memory taken by `values`
+fn size_of(values: &[RawValue]) -> usize {
+    values
+        .iter()
+        .map(|v| match v {
+            RawValue::Bytes(b) => std::mem::size_of::<RawValue>() + b.len(),
+            _ => std::mem::size_of::<RawValue>(),
+        })
+        .sum()
+}
+
+impl ColumnCache {
+    /// A cache holding at most `capacity` bytes of decoded values
+    pub(crate) fn new(capacity: usize) -> Self {
+        ColumnCache {
+            lru: Arc::new(Mutex::new(Lru {
+                capacity,
+                used: 0,
+                tick: 0,
+                entries: HashMap::new(),
+                by_use: BTreeMap::new(),
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The most bytes of decoded values the cache holds
+    pub(crate) fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// Change the capacity, evicting entries until they fit in it
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let mut lru = self.lock();
+        lru.capacity = capacity;
+        lru.evict();
+    }
+
+    /// The values of the column at `key`, if they are cached
+    pub(crate) fn get(&self, key: CacheKey) -> Option<Arc<Vec<RawValue>>> {
+        let mut lru = self.lock();
+        lru.tick += 1;
+        let tick = lru.tick;
+        let entry = lru.entries.get_mut(&key)?;
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let values = entry.values.clone();
+        lru.by_use.remove(&previous);
+        lru.by_use.insert(tick, key);
+        Some(values)
+    }
+
+    /// Cache the values of the column at `key`, unless they alone would
+    /// not fit
+    pub(crate) fn insert(&self, key: CacheKey, values: Arc<Vec<RawValue>>) {
+        let size = size_of(&values);
+        let mut lru = self.lock();
+        if size > lru.capacity {
+            return;
+        }
+        lru.tick += 1;
+        let last_used = lru.tick;
+        let entry = Entry {
+            values,
+            size,
+            last_used,
+        };
+        if let Some(old) = lru.entries.insert(key, entry) {
+            lru.used -= old.size;
+            lru.by_use.remove(&old.last_used);
+        }
+        lru.used += size;
+        lru.by_use.insert(last_used, key);
+        lru.evict();
+    }
+}
+
+impl Lru {
+    /// Evict the least recently used entries until the rest fit
+    fn evict(&mut self) {
+        while self.used > self.capacity {
+            let Some((&tick, &key)) = self.by_use.iter().next() else {
+                break;
+            };
+            self.by_use.remove(&tick);
+            if let Some(entry) = self.entries.remove(&key) {
+                self.used -= entry.size;
+            }
+        }
+    }
+}
+
+#[test]
+fn evict_least_recently_used() {
+    let cache = ColumnCache::new(10 * std::mem::size_of::<RawValue>());
+    let key = |file| CacheKey { file };
+    let values = |n: u64| Arc::new((0..n).map(RawValue::U64).collect::<Vec<_>>());
+    cache.insert(key(1), values(4));
+    cache.insert(key(2), values(4));
+    assert!(cache.get(key(1)).is_some());
+
+    // The column used least recently is evicted to make room.
+    cache.insert(key(3), values(4));
+    assert!(cache.get(key(2)).is_none());
+    assert_eq!(cache.get(key(1)), Some(values(4)));
+    assert_eq!(cache.get(key(3)), Some(values(4)));
+
+    // A column bigger than the whole cache is never cached.
+    cache.insert(key(4), values(11));
+    assert!(cache.get(key(4)).is_none());
+    assert!(cache.get(key(1)).is_some());
+
+    cache.set_capacity(0);
+    assert!(cache.get(key(1)).is_none());
+    assert_eq!(cache.capacity(), 0);
+}
+
+#[test]
+fn cached_reads() {
+    use crate::{ColumnSchema, Database, TableSchema};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("pages");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(ColumnSchema::<String>::new("path").raw());
+    let pages = db.create_table(schema).unwrap();
+    pages
+        .insert_raw_rows((0..5_000u64).map(|i| {
+            let path = RawValue::Bytes(format!("/page/{}", i % 300).into_bytes());
+            [RawValue::U64(i), path].into_iter().collect()
+        }))
+        .unwrap();
+
+    let first = pages.read().unwrap();
+    let rows = first.to_rows().unwrap();
+    assert_eq!(first.metrics().cache_hits(), 0);
+
+    // Reading again takes both columns from the cache, without decoding.
+    let second = pages.read().unwrap();
+    assert_eq!(second.to_rows().unwrap(), rows);
+    assert_eq!(second.metrics().cache_hits(), 2);
+    assert_eq!(second.metrics().chunks_decoded(), 0);
+    let result = db.execute("select path from pages where id < 10").unwrap();
+    assert_eq!(result.rows().len(), 10);
+    assert_eq!(result.metrics().cache_hits(), 2);
+
+    // Without room for them, the columns are decoded each time.
+    db.set_cache_capacity(0);
+    let third = pages.read().unwrap();
+    assert_eq!(third.to_rows().unwrap(), rows);
+    assert_eq!(third.metrics().cache_hits(), 0);
+    assert!(third.metrics().chunks_decoded() > 0);
+}
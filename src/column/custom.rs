@@ -0,0 +1,397 @@
+//! Column formats defined outside this crate.
+//!
+//! A [`CustomFormat`] encodes and decodes a column of one kind of raw value,
+//! such as strings compressed with a table of common substrings.  Once it is
+//! registered with [`register_format`], columns are written in it by asking
+//! for its [`ColumnFormat`] with [`EncodeOptions::format`], and open like
+//! any other column, since a magic number that is not one of the built in
+//! formats is looked up among those registered.  Custom formats are never
+//! picked for a column on their own.
+//!
+//! A column in a custom format is decoded in full when it is opened, rather
+//! than a chunk at a time as it is read.
+//!
+//! ```
+//! use equilia::{register_format, CustomFormat, EncodeOptions, RawColumn, RawKind, RawValue};
+//! use equilia::column::encoding::StorageError;
+//!
+//! /// Bytes stored reversed, each after its length and number of rows
+//! struct Reversed;
+//!
+//! impl CustomFormat for Reversed {
+//!     fn magic(&self) -> u64 {
+//!         u64::from_be_bytes(*b"reversed")
+//!     }
+//!     fn kind(&self) -> RawKind {
+//!         RawKind::Bytes
+//!     }
+//!     fn encode(
+//!         &self,
+//!         out: &mut dyn std::io::Write,
+//!         runs: &[(RawValue, u64)],
+//!     ) -> Result<(), StorageError> {
+//!         for (value, num) in runs {
+//!             let RawValue::Bytes(b) = value else {
+//!                 return Err(StorageError::InvalidRow("expected bytes"));
+//!             };
+//!             out.write_all(&(b.len() as u64).to_be_bytes())?;
+//!             out.write_all(&num.to_be_bytes())?;
+//!             out.write_all(&b.iter().rev().copied().collect::<Vec<u8>>())?;
+//!         }
+//!         Ok(())
+//!     }
+//!     fn decode(&self, mut bytes: &[u8]) -> Result<Vec<(RawValue, u64)>, StorageError> {
+//!         let mut runs = Vec::new();
+//!         while !bytes.is_empty() {
+//!             let truncated = || StorageError::TruncatedRow("run ends early");
+//!             let header = bytes.get(..16).ok_or_else(truncated)?;
+//!             let len = u64::from_be_bytes(header[..8].try_into().unwrap()) as usize;
+//!             let num = u64::from_be_bytes(header[8..].try_into().unwrap());
+//!             let b = bytes.get(16..16 + len).ok_or_else(truncated)?;
+//!             runs.push((RawValue::Bytes(b.iter().rev().copied().collect()), num));
+//!             bytes = &bytes[16 + len..];
+//!         }
+//!         Ok(runs)
+//!     }
+//! }
+//!
+//! static REVERSED: Reversed = Reversed;
+//! register_format(&REVERSED).unwrap();
+//!
+//! let values = vec![b"hello".to_vec(), b"hello".to_vec(), b"world".to_vec()];
+//! let mut encoded = Vec::new();
+//! let options = EncodeOptions::default().format(REVERSED.format());
+//! RawColumn::write_bytes_with(&mut encoded, &values, options).unwrap();
+//! let column = RawColumn::decode(encoded).unwrap();
+//! assert_eq!(column.format(), REVERSED.format());
+//! assert_eq!(column.read_bytes().unwrap(), values);
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use super::encoding::ReadEncoded;
+use super::storage::Storage;
+use super::{BorrowedChunks, Chunk, ChunkValues, ColumnFormat, StorageError, WriteEncoded};
+use crate::value::{RawKind, RawValue};
+
+/// A format for columns of one kind of raw value, see the [module](self)
+/// docs
+pub trait CustomFormat: Send + Sync {
+    /// The magic number starting each column in this format, which must
+    /// differ from those of every other format
+    fn magic(&self) -> u64;
+
+    /// The kind of values the format holds
+    fn kind(&self) -> RawKind;
+
+    /// Encode runs of values, each with the number of rows it repeats for.
+    ///
+    /// The magic number is written before this by the caller.  Consecutive
+    /// runs may hold the same value if the column is split into chunks, see
+    /// [`EncodeOptions::max_chunk_rows`](super::EncodeOptions::max_chunk_rows).
+    fn encode(
+        &self,
+        out: &mut dyn std::io::Write,
+        runs: &[(RawValue, u64)],
+    ) -> Result<(), StorageError>;
+
+    /// Decode the runs written by [`CustomFormat::encode`] from the bytes
+    /// following the magic number
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<(RawValue, u64)>, StorageError>;
+
+    /// The [`ColumnFormat`] naming this format
+    fn format(&self) -> ColumnFormat {
+        ColumnFormat::Custom {
+            magic: self.magic(),
+            kind: self.kind(),
+        }
+    }
+}
+
+/// The custom formats registered
+static REGISTERED: Mutex<Vec<&'static dyn CustomFormat>> = Mutex::new(Vec::new());
+
+/// Register a custom format, so columns can be written in it and opened.
+///
+/// Registering the same format again does nothing, but a magic number taken
+/// by a built in format or by another custom format is refused.
+pub fn register_format(format: &'static dyn CustomFormat) -> Result<(), StorageError> {
+    let magic = format.magic();
+    if super::RawColumn::is_built_in(magic) {
+        return Err(StorageError::MagicTaken(magic));
+    }
+    let mut registered = REGISTERED.lock().expect("poisoned registry");
+    match registered.iter().find(|f| f.magic() == magic) {
+        Some(f) if same_format(*f, format) => Ok(()),
+        Some(_) => Err(StorageError::MagicTaken(magic)),
+        None => {
+            registered.push(format);
+            Ok(())
+        }
+    }
+}
+
+/// Whether two registered formats are the same value
+fn same_format(a: &'static dyn CustomFormat, b: &'static dyn CustomFormat) -> bool {
+    std::ptr::eq(
+        a as *const dyn CustomFormat as *const u8,
+        b as *const dyn CustomFormat as *const u8,
+    )
+}
+
+/// The custom format registered with `magic`
+fn registered(magic: u64) -> Option<&'static dyn CustomFormat> {
+    let registered = REGISTERED.lock().expect("poisoned registry");
+    registered.iter().copied().find(|f| f.magic() == magic)
+}
+
+/// Encode runs of `kind` in the custom format registered with `magic`, after
+/// its magic number
+pub(crate) fn encode<W: WriteEncoded>(
+    out: &mut W,
+    magic: u64,
+    kind: RawKind,
+    runs: Vec<(RawValue, u64)>,
+) -> Result<(), StorageError> {
+    let format = registered(magic).ok_or(StorageError::BadMagic(magic))?;
+    if format.kind() != kind {
+        return Err(StorageError::KindMismatch {
+            expected: kind,
+            found: format.kind(),
+        });
+    }
+    out.write_u64(magic)?;
+    format.encode(out, &runs)
+}
+
+/// A column in a custom format, decoded when it was opened
+#[derive(Clone)]
+pub(crate) struct CustomColumn {
+    format: &'static dyn CustomFormat,
+    runs: Arc<Vec<(RawValue, u64)>>,
+    num_rows: u64,
+}
+
+impl CustomColumn {
+    /// Open a column in the custom format registered with `magic`, if there
+    /// is one
+    pub(crate) fn open(magic: u64, storage: Storage) -> Result<Option<Self>, StorageError> {
+        let Some(format) = registered(magic) else {
+            return Ok(None);
+        };
+        let start = storage.tell()? + 8;
+        let len = storage
+            .len()
+            .checked_sub(start)
+            .ok_or(StorageError::OutOfBounds("column ends in its magic"))?;
+        let mut bytes = vec![0; len as usize];
+        storage.read_exact_at(&mut bytes, start)?;
+        let runs = format.decode(&bytes)?;
+        if let Some(v) = runs.iter().find(|(v, _)| v.kind() != format.kind()) {
+            return Err(StorageError::KindMismatch {
+                expected: format.kind(),
+                found: v.0.kind(),
+            });
+        }
+        let num_rows = runs
+            .iter()
+            .try_fold(0u64, |total, (_, n)| total.checked_add(*n))
+            .ok_or(StorageError::OutOfBounds("number of rows overflows"))?;
+        Ok(Some(CustomColumn {
+            format,
+            runs: Arc::new(runs),
+            num_rows,
+        }))
+    }
+
+    pub(crate) fn format(&self) -> ColumnFormat {
+        self.format.format()
+    }
+
+    pub(crate) fn kind(&self) -> RawKind {
+        self.format.kind()
+    }
+
+    pub(crate) fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    pub(crate) fn num_chunks(&self) -> u64 {
+        self.runs.len() as u64
+    }
+
+    /// The smallest value, or the default of the kind if there are none
+    pub(crate) fn min(&self) -> RawValue {
+        let min = self.runs.iter().map(|(v, _)| v).min();
+        min.cloned().unwrap_or_else(|| self.empty())
+    }
+
+    /// The largest value, or the default of the kind if there are none
+    pub(crate) fn max(&self) -> RawValue {
+        let max = self.runs.iter().map(|(v, _)| v).max();
+        max.cloned().unwrap_or_else(|| self.empty())
+    }
+
+    fn empty(&self) -> RawValue {
+        match self.kind() {
+            RawKind::U64 => RawValue::U64(0),
+            RawKind::Bool => RawValue::Bool(false),
+            RawKind::Bytes => RawValue::Bytes(Vec::new()),
+        }
+    }
+
+    /// Every value, converted by `from`, which takes values of `expected`
+    pub(crate) fn read<T: Clone>(
+        &self,
+        expected: RawKind,
+        from: fn(&RawValue) -> Option<T>,
+    ) -> Result<Vec<T>, StorageError> {
+        let mismatch = || StorageError::KindMismatch {
+            expected,
+            found: self.kind(),
+        };
+        let num_rows = usize::try_from(self.num_rows)
+            .map_err(|_| StorageError::OutOfBounds("too many rows to read"))?;
+        let mut out = Vec::with_capacity(num_rows);
+        for (v, num) in self.runs.iter() {
+            let v = from(v).ok_or_else(mismatch)?;
+            out.extend((0..*num).map(|_| v.clone()));
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn runs(&self) -> ChunkValues {
+        let runs = self.runs.clone();
+        Box::new((0..runs.len()).map(move |i| Ok(runs[i].clone())))
+    }
+
+    pub(crate) fn bytes_chunks(&self) -> BorrowedChunks<'_> {
+        let mut start = 0;
+        Box::new(self.runs.iter().map(move |(v, num)| {
+            let range = start..start + num;
+            start += num;
+            match v {
+                RawValue::Bytes(b) => Ok(Chunk {
+                    value: b.as_slice().into(),
+                    range,
+                }),
+                v => Err(StorageError::KindMismatch {
+                    expected: RawKind::Bytes,
+                    found: v.kind(),
+                }),
+            }
+        }))
+    }
+
+    pub(crate) fn runs_slice(&self) -> &[(RawValue, u64)] {
+        &self.runs
+    }
+}
+
+#[test]
+fn custom_formats() {
+    use super::{EncodeOptions, RawColumn};
+
+    /// u64 values stored as the low byte of each, after its number of rows
+    struct LowBytes(u64);
+
+    impl CustomFormat for LowBytes {
+        fn magic(&self) -> u64 {
+            self.0
+        }
+        fn kind(&self) -> RawKind {
+            RawKind::U64
+        }
+        fn encode(
+            &self,
+            out: &mut dyn std::io::Write,
+            runs: &[(RawValue, u64)],
+        ) -> Result<(), StorageError> {
+            for (v, num) in runs {
+                match v {
+                    RawValue::U64(n) if *n < 256 => {
+                        out.write_all(&num.to_be_bytes())?;
+                        out.write_all(&[*n as u8])?;
+                    }
+                    _ => return Err(StorageError::InvalidRow("value too large")),
+                }
+            }
+            Ok(())
+        }
+        fn decode(&self, bytes: &[u8]) -> Result<Vec<(RawValue, u64)>, StorageError> {
+            let runs = bytes.chunks(9).map(|run| {
+                let num = u64::from_be_bytes(run[..8].try_into().unwrap());
+                (RawValue::U64(run[8] as u64), num)
+            });
+            Ok(runs.collect())
+        }
+    }
+
+    static LOW: LowBytes = LowBytes(u64::from_be_bytes(*b"lowbytes"));
+    static TAKEN: LowBytes = LowBytes(u64::from_be_bytes(*b"lowbytes"));
+    static BUILT_IN: LowBytes = LowBytes(u64::from_be_bytes(*b"dictbyte"));
+    static UNREGISTERED: LowBytes = LowBytes(u64::from_be_bytes(*b"unregist"));
+
+    let vals = (0..100).map(|i| i / 10 * 7).collect::<Vec<u64>>();
+    let options = EncodeOptions::default().format(LOW.format());
+    let mut encoded = Vec::new();
+    assert!(matches!(
+        RawColumn::write_u64_with(&mut encoded, &vals, options),
+        Err(StorageError::BadMagic(_))
+    ));
+
+    register_format(&LOW).unwrap();
+    register_format(&LOW).unwrap();
+    for f in [&TAKEN, &BUILT_IN] {
+        assert!(matches!(
+            register_format(f),
+            Err(StorageError::MagicTaken(_))
+        ));
+    }
+
+    for (options, chunks) in [(options, 10), (options.max_chunk_rows(3), 40)] {
+        let mut encoded = Vec::new();
+        RawColumn::write_u64_with(&mut encoded, &vals, options).unwrap();
+        let column = RawColumn::decode(encoded).unwrap();
+        assert_eq!(column.format(), LOW.format());
+        assert_eq!(column.kind(), RawKind::U64);
+        assert_eq!(column.num_rows(), 100);
+        assert_eq!(column.num_chunks(), chunks);
+        assert_eq!(column.read_u64().unwrap(), vals);
+        assert_eq!(
+            (column.min(), column.max()),
+            (RawValue::U64(0), RawValue::U64(63))
+        );
+        assert_eq!(column.value_at(55).unwrap(), RawValue::U64(35));
+        let array = column.to_arrow().unwrap().unwrap();
+        assert_eq!(array.values().len(), 800);
+        assert_eq!(array.values().padded()[8 * 99], 63);
+        assert!(matches!(
+            column.read_bytes(),
+            Err(StorageError::KindMismatch { .. })
+        ));
+    }
+
+    // The format refuses values it cannot hold, and holds no other kind.
+    let mut encoded = Vec::new();
+    assert!(RawColumn::write_u64_with(&mut encoded, &[1000], options).is_err());
+    assert!(RawColumn::write_bytes_with(&mut encoded, &[vec![1]], options).is_err());
+    assert!(RawColumn::write_u64_with(
+        &mut encoded,
+        &vals,
+        EncodeOptions::default().format(UNREGISTERED.format())
+    )
+    .is_err());
+
+    // Runs claiming more rows than can be counted are refused.
+    let mut encoded = Vec::new();
+    RawColumn::write_u64_with(&mut encoded, &[1, 2], options).unwrap();
+    let end = encoded.len();
+    for run in [end - 18, end - 9] {
+        encoded[run..run + 8].copy_from_slice(&u64::MAX.to_be_bytes());
+    }
+    assert!(matches!(
+        RawColumn::decode(encoded),
+        Err(StorageError::OutOfBounds(_))
+    ));
+}
@@ -0,0 +1,280 @@
+//! Dictionary encoding for bytes columns.
+//!
+//! The header holds a dictionary of every distinct value in the column, and
+//! each chunk stores a run length and an integer code indexing into it.  The
+//! dictionary is stored in strictly increasing order, so codes compare the
+//! same way as the values they stand for.  That lets range predicates and
+//! sorting work on the codes without looking up any values.
+//!
+//! ```text
+//! MAGIC n_rows n_chunks n_entries (length bytes)* (runlength code)*
+//! ```
+//!
+//! Opening a column whose dictionary is not strictly increasing fails.
+use std::ops::{Bound, Range, RangeBounds};
+use std::sync::Arc;
+
+use super::{Chunk, IsRawColumn, ReadEncoded, Storage, StorageError, WriteEncoded};
+
+#[derive(Clone)]
+pub(crate) struct Dictionary {
+    storage: Storage,
+    current_row: u64,
+    n_rows: u64,
+    n_chunks: u64,
+    entries: Arc<[Vec<u8>]>,
+}
+
+impl From<&[Vec<u8>]> for Dictionary {
+    fn from(vals: &[Vec<u8>]) -> Self {
+        let mut bytes = Vec::<u8>::new();
+        Self::encode(&mut bytes, &super::run_length_encode(vals)).expect("error encoding");
+        let storage = Storage::from(bytes);
+        Self::open(storage).unwrap()
+    }
+}
+
+impl Iterator for Dictionary {
+    type Item = Result<Chunk<Vec<u8>>, StorageError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.transposed_next().transpose()
+    }
+}
+
+impl Dictionary {
+    pub(crate) const MAGIC: u64 = u64::from_be_bytes(*b"dictbyte");
+
+    fn transposed_next(&mut self) -> Result<Option<Chunk<Vec<u8>>>, StorageError> {
+        Ok(self.next_code()?.map(|chunk| Chunk {
+            value: self.entries[chunk.value as usize].clone(),
+            range: chunk.range,
+        }))
+    }
+
+    /// Read the next chunk without looking up its value.
+    pub(crate) fn next_code(&mut self) -> Result<Option<Chunk<u64>>, StorageError> {
+        if self.current_row == self.n_rows {
+            return Ok(None);
+        }
+        let num = self.storage.read_usigned()?;
+        let value = self.storage.read_usigned()?;
+        if value >= self.entries.len() as u64 {
+            return Err(StorageError::OutOfBounds("dictionary code"));
+        }
+        let current_row = self.current_row;
+        self.current_row = super::chunk_end(current_row, num, self.n_rows)?;
+        Ok(Some(Chunk {
+            value,
+            range: current_row..self.current_row,
+        }))
+    }
+
+    /// The distinct values in the column, in increasing order
+    pub(crate) fn entries(&self) -> &[Vec<u8>] {
+        &self.entries
+    }
+
+    /// The codes of the values that lie within `range`.
+    pub(crate) fn code_range<'a>(&self, range: impl RangeBounds<&'a [u8]>) -> Range<u64> {
+        let start = match range.start_bound() {
+            Bound::Included(v) => self.entries.partition_point(|e| e.as_slice() < *v),
+            Bound::Excluded(v) => self.entries.partition_point(|e| e.as_slice() <= *v),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(v) => self.entries.partition_point(|e| e.as_slice() <= *v),
+            Bound::Excluded(v) => self.entries.partition_point(|e| e.as_slice() < *v),
+            Bound::Unbounded => self.entries.len(),
+        };
+        start as u64..std::cmp::max(start, end) as u64
+    }
+}
+
+impl IsRawColumn for Dictionary {
+    type Element = Vec<u8>;
+
+    fn num_rows(&self) -> u64 {
+        self.n_rows
+    }
+    fn num_chunks(&self) -> u64 {
+        self.n_chunks
+    }
+    fn max(&self) -> Self::Element {
+        self.entries.last().cloned().unwrap_or_default()
+    }
+    fn min(&self) -> Self::Element {
+        self.entries.first().cloned().unwrap_or_default()
+    }
+
+    fn encode<W: WriteEncoded>(
+        out: &mut W,
+        input: &[(Self::Element, u64)],
+    ) -> Result<(), StorageError> {
+        if input.is_empty() {
+            return Ok(());
+        }
+        let mut entries: Vec<&[u8]> = input.iter().map(|(v, _)| v.as_slice()).collect();
+        entries.sort_unstable();
+        entries.dedup();
+
+        out.write_u64(Self::MAGIC)?;
+        out.write_u64(input.iter().map(|x| x.1).sum())?;
+        out.write_u64(input.len() as u64)?;
+        out.write_u64(entries.len() as u64)?;
+        for e in entries.iter() {
+            out.write_unsigned(e.len() as u64)?;
+            out.write_all(e)?;
+        }
+        for (v, num) in input.iter() {
+            let code = entries
+                .binary_search(&v.as_slice())
+                .expect("every value is in the dictionary");
+            out.write_unsigned(*num)?;
+            out.write_unsigned(code as u64)?;
+        }
+        Ok(())
+    }
+
+    fn open(mut storage: Storage) -> Result<Self, StorageError> {
+        let magic = storage.read_u64()?;
+        if magic != Self::MAGIC {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let n_rows = storage.read_u64()?;
+        let n_chunks = storage.read_u64()?;
+        let n_entries = storage.read_u64()?;
+        let mut entries: Vec<Vec<u8>> = Vec::new();
+        for _ in 0..n_entries {
+            let len = storage.read_usigned()?;
+            let entry = storage.read_vec(len)?;
+            if let Some(previous) = entries.last() {
+                if *previous >= entry {
+                    return Err(StorageError::OutOfBounds("dictionary is not sorted"));
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(Dictionary {
+            storage,
+            current_row: 0,
+            n_rows,
+            n_chunks,
+            entries: entries.into(),
+        })
+    }
+
+    fn tell(&self) -> Result<u64, StorageError> {
+        self.storage.tell()
+    }
+
+    fn seek(
+        &mut self,
+        offset: u64,
+        row_number: u64,
+        _value: impl AsRef<Self::Element>,
+    ) -> Result<(), StorageError> {
+        self.current_row = row_number;
+        self.storage.seek(offset)
+    }
+}
+
+impl TryFrom<Storage> for Dictionary {
+    type Error = StorageError;
+    fn try_from(storage: Storage) -> Result<Self, Self::Error> {
+        Self::open(storage)
+    }
+}
+
+#[test]
+fn test_encode_dictionary() {
+    use super::RawColumn;
+
+    let data = [
+        b"pear".to_vec(),
+        b"apple".to_vec(),
+        b"pear".to_vec(),
+        b"apple".to_vec(),
+        b"apple".to_vec(),
+        b"fig".to_vec(),
+        b"pear".to_vec(),
+    ];
+    let c = Dictionary::from(data.as_slice());
+    assert_eq!(
+        c.entries(),
+        &[b"apple".to_vec(), b"fig".to_vec(), b"pear".to_vec()]
+    );
+    let rc = RawColumn::from(data.as_slice());
+    assert!(matches!(rc.inner, super::RawColumnInner::BytesDict(_)));
+    assert_eq!(rc.read_bytes().unwrap().as_slice(), &data);
+    assert_eq!(rc.dictionary(), Some(c.entries()));
+    assert_eq!(rc.code_range(&b"b"[..]..), Some(1..3));
+    assert_eq!(rc.read_codes().unwrap(), vec![2, 0, 2, 0, 0, 1, 2]);
+
+    let mut encoded: Vec<u8> = Vec::new();
+    let chunks: Vec<(Vec<u8>, u64)> = c
+        .clone()
+        .map(|chunk| {
+            let chunk = chunk.unwrap();
+            (chunk.value, chunk.range.end - chunk.range.start)
+        })
+        .collect();
+    <Dictionary as IsRawColumn>::encode(&mut encoded, chunks.as_slice()).unwrap();
+
+    let storage = Storage::from(encoded.clone());
+    let c2 = Dictionary::open(storage.clone()).unwrap();
+    assert_eq!(
+        c2.map(|x| x.unwrap()).collect::<Vec<_>>(),
+        c.map(|x| x.unwrap()).collect::<Vec<_>>()
+    );
+    let rc2 = RawColumn::decode(encoded).unwrap();
+    assert_eq!(rc2.read_bytes().unwrap().as_slice(), &data);
+
+    let mut f = tempfile::tempfile().unwrap();
+    <Dictionary as IsRawColumn>::encode(&mut f, chunks.as_slice()).unwrap();
+    let rc = RawColumn::try_from(f).unwrap();
+    assert_eq!(rc.read_bytes().unwrap().as_slice(), &data);
+}
+
+#[test]
+fn dictionary_codes_preserve_order() {
+    let data = [
+        b"pear".to_vec(),
+        b"apple".to_vec(),
+        b"pear".to_vec(),
+        b"fig".to_vec(),
+    ];
+    let mut c = Dictionary::from(data.as_slice());
+    assert_eq!(c.code_range(..), 0..3);
+    assert_eq!(c.code_range(&b"b"[..]..&b"g"[..]), 1..2);
+    assert_eq!(c.code_range(&b"fig"[..]..), 1..3);
+    assert_eq!(
+        c.code_range((Bound::Excluded(&b"fig"[..]), Bound::Unbounded)),
+        2..3
+    );
+    assert_eq!(c.code_range(..=&b"fig"[..]), 0..2);
+    assert_eq!(c.code_range(&b"q"[..]..&b"a"[..]), 3..3);
+
+    let mut codes = Vec::new();
+    while let Some(chunk) = c.next_code().unwrap() {
+        codes.push(chunk.value);
+    }
+    assert_eq!(codes, vec![2, 0, 2, 1]);
+}
+
+#[test]
+fn unsorted_dictionary_is_rejected() {
+    let mut encoded: Vec<u8> = Vec::new();
+    encoded.write_u64(Dictionary::MAGIC).unwrap();
+    encoded.write_u64(2).unwrap();
+    encoded.write_u64(2).unwrap();
+    encoded.write_u64(2).unwrap();
+    for e in [&b"pear"[..], &b"apple"[..]] {
+        encoded.write_unsigned(e.len() as u64).unwrap();
+        encoded.extend_from_slice(e);
+    }
+    for code in [0, 1] {
+        encoded.write_unsigned(1).unwrap();
+        encoded.write_unsigned(code).unwrap();
+    }
+    assert!(Dictionary::open(Storage::from(encoded)).is_err());
+}
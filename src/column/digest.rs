@@ -0,0 +1,150 @@
+//! Digests of the values of a column, for comparing copies of it.
+//!
+//! A column is split into blocks of [`DIGEST_BLOCK_ROWS`] rows, and each
+//! block is hashed from its decoded values, so two copies compare equal
+//! however they were encoded.  The digests of the blocks are hashed in turn
+//! into a single root, so matching copies are recognized from the root alone,
+//! while copies that differ can be repaired a block at a time.
+
+use std::ops::Range;
+
+use super::encoding::StorageError;
+use super::RawColumn;
+
+/// The number of rows hashed into each block of a [`ColumnDigest`]
+pub const DIGEST_BLOCK_ROWS: u64 = 4096;
+
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continue a 64-bit FNV-1a hash with `bytes`
+pub(crate) fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The digests of the blocks of a column, see [`RawColumn::checksum_chunks`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDigest {
+    num_rows: u64,
+    blocks: Vec<u64>,
+    root: u64,
+}
+
+impl ColumnDigest {
+    /// The number of rows in the column
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    /// The digest of each block of [`DIGEST_BLOCK_ROWS`] rows, in order
+    pub fn blocks(&self) -> &[u64] {
+        &self.blocks
+    }
+
+    /// A digest of the whole column, made from the digests of its blocks
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// The ranges of rows that differ between the two copies of a column.
+    ///
+    /// Adjacent blocks that differ are joined into one range, and any rows
+    /// that only one copy has are counted as differing.
+    pub fn differing_rows(&self, other: &ColumnDigest) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        if self.root == other.root && self.num_rows == other.num_rows {
+            return ranges;
+        }
+        let num_rows = std::cmp::max(self.num_rows, other.num_rows);
+        let num_blocks = std::cmp::max(self.blocks.len(), other.blocks.len());
+        for i in 0..num_blocks {
+            let start = i as u64 * DIGEST_BLOCK_ROWS;
+            let end = std::cmp::min(start + DIGEST_BLOCK_ROWS, num_rows);
+            let same = self.blocks.get(i).is_some()
+                && self.blocks.get(i) == other.blocks.get(i)
+                && std::cmp::min(end, self.num_rows) == std::cmp::min(end, other.num_rows);
+            if same {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        ranges
+    }
+}
+
+impl RawColumn {
+    /// Hash the values of the column a block at a time.
+    ///
+    /// Comparing the digests of two copies with
+    /// [`ColumnDigest::differing_rows`] finds the rows that need to be copied
+    /// to make them match.
+    pub fn checksum_chunks(&self) -> Result<ColumnDigest, StorageError> {
+        let mut blocks = Vec::new();
+        let mut hash = FNV_OFFSET;
+        let mut rows_in_block = 0;
+        for value in self.values() {
+            hash = fnv(hash, &value?.encode());
+            rows_in_block += 1;
+            if rows_in_block == DIGEST_BLOCK_ROWS {
+                blocks.push(hash);
+                hash = FNV_OFFSET;
+                rows_in_block = 0;
+            }
+        }
+        if rows_in_block > 0 {
+            blocks.push(hash);
+        }
+        let root = blocks
+            .iter()
+            .fold(FNV_OFFSET, |h, b| fnv(h, &b.to_be_bytes()));
+        Ok(ColumnDigest {
+            num_rows: self.num_rows(),
+            blocks,
+            root,
+        })
+    }
+}
+
+#[test]
+fn find_differing_rows() {
+    let values = (0..20_000).map(|i| i / 3).collect::<Vec<u64>>();
+    let digest = RawColumn::from(&values[..]).checksum_chunks().unwrap();
+    assert_eq!(digest.blocks().len(), 5);
+    assert_eq!(digest.num_rows(), 20_000);
+
+    // A copy encoded differently has the same digest.
+    let mut split = Vec::new();
+    let options = super::EncodeOptions::default().max_chunk_rows(2);
+    RawColumn::write_u64_with(&mut split, &values, options).unwrap();
+    let copy = RawColumn::decode(split).unwrap().checksum_chunks().unwrap();
+    assert_eq!(copy, digest);
+    assert!(digest.differing_rows(&copy).is_empty());
+
+    let mut changed = values.clone();
+    changed[5000] += 1;
+    changed[9000] += 1;
+    changed[19_999] += 1;
+    let changed = RawColumn::from(&changed[..]).checksum_chunks().unwrap();
+    assert_ne!(changed.root(), digest.root());
+    assert_eq!(
+        digest.differing_rows(&changed),
+        [4096..12_288, 16_384..20_000]
+    );
+
+    let shorter = RawColumn::from(&values[..10_000])
+        .checksum_chunks()
+        .unwrap();
+    let missing = 8192..20_000;
+    assert_eq!(
+        digest.differing_rows(&shorter),
+        std::slice::from_ref(&missing)
+    );
+    assert_eq!(
+        shorter.differing_rows(&digest),
+        std::slice::from_ref(&missing)
+    );
+}
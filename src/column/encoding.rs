@@ -65,9 +65,93 @@ pub enum StorageError {
     /// Bad magic
     #[error("Bad magic: {}", pretty_magic(.0))]
     BadMagic(u64),
+    /// A custom format registered with the magic of another format
+    #[error("Magic already taken: {}", pretty_magic(.0))]
+    MagicTaken(u64),
     /// Out of bounds
     #[error("Out of bounds: {0}")]
     OutOfBounds(&'static str),
+    /// A row that does not match its schema
+    #[error("Invalid row: {0}")]
+    InvalidRow(&'static str),
+    /// A column written in a version of the encoding that cannot be read
+    #[error("Unsupported column format version: {0}")]
+    UnsupportedVersion(u64),
+    /// Values of one kind asked of a column holding another
+    #[error("Kind mismatch: expected {expected:?}, found {found:?}")]
+    KindMismatch {
+        /// The kind of values asked for
+        expected: crate::RawKind,
+        /// The kind of values the column holds
+        found: crate::RawKind,
+    },
+    /// Encoded values that end part way through a value
+    #[error("Truncated row: {0}")]
+    TruncatedRow(&'static str),
+    /// A value that could not be interpreted through its lens
+    #[error("Lens error: {0}")]
+    Lens(#[from] crate::LensError),
+    /// A schema that does not make sense
+    #[error("Schema error: {0}")]
+    Schema(String),
+    /// A table read with a schema other than the one it was saved with
+    #[error("Schema mismatch: column {0} was not saved with this schema")]
+    SchemaMismatch(String),
+    /// Rows sharing a primary key in a table that forbids it
+    #[error("Duplicate primary key in table {0}")]
+    DuplicateKey(String),
+    /// A row that breaks a constraint of a column of its table
+    #[error(
+        "Row with primary key {key:?} of table {table} breaks {constraint} on column {column}"
+    )]
+    Constraint {
+        /// The name of the table
+        table: String,
+        /// The name of the column
+        column: String,
+        /// The constraint broken
+        constraint: crate::Constraint,
+        /// The primary key of the row
+        key: Vec<crate::RawValue>,
+    },
+    /// A value of a column that refers to no row of another table
+    #[error("Dangling reference: {0}")]
+    DanglingReference(crate::DanglingReference),
+    /// A sum that overflowed in a column that forbids it
+    #[error("Sum overflowed in column {0}")]
+    Overflow(String),
+    /// A version of a table that was never made or has been forgotten
+    #[error("No such version: {0}")]
+    NoSuchVersion(String),
+    /// A database that another writer has open
+    #[error("Database {0} is locked by another writer")]
+    Locked(String),
+    /// A change to a database that was opened read only
+    #[error("Database was opened read only")]
+    ReadOnly,
+    /// A backup that is cut short, or whose files do not match its manifests
+    #[error("Backup error: {0}")]
+    Backup(String),
+    /// A statement that could not be parsed or run
+    #[error("Query error: {0}")]
+    Query(String),
+    /// A CSV file that could not be loaded
+    #[error("CSV row {row}: {message}")]
+    Csv {
+        /// The row that could not be loaded, counting from 1 after the
+        /// header, which is row 0
+        row: u64,
+        /// What was wrong with it
+        message: String,
+    },
+    /// A line of newline-delimited JSON that could not be loaded
+    #[error("JSON line {line}: {message}")]
+    Json {
+        /// The line that could not be loaded, counting from 1
+        line: u64,
+        /// What was wrong with it
+        message: String,
+    },
 }
 
 fn pretty_magic(m: &u64) -> String {
@@ -97,10 +181,30 @@ pub trait ReadEncoded {
     /// Increment the current offset
     fn advance(&mut self, size: u64) -> Result<u64, StorageError> {
         let offset = self.tell()?;
-        self.seek(offset + size)?;
+        self.seek(
+            offset
+                .checked_add(size)
+                .ok_or(StorageError::OutOfBounds("offset overflows"))?,
+        )?;
         Ok(offset)
     }
 
+    /// Reads `len` bytes into a new vector.
+    ///
+    /// The length is usually read from the encoded data, so rather than
+    /// trusting it with one big allocation, the vector grows as the bytes
+    /// are read, and a length longer than the data fails when it runs out.
+    fn read_vec(&mut self, len: u64) -> Result<Vec<u8>, StorageError> {
+        const PIECE: u64 = 1 << 16;
+        let mut v = Vec::new();
+        while (v.len() as u64) < len {
+            let start = v.len();
+            v.resize(start + (len - start as u64).min(PIECE) as usize, 0);
+            self.read_exact(&mut v[start..])?;
+        }
+        Ok(v)
+    }
+
     /// Reads a single `u8` value.
     fn read_u8(&mut self) -> Result<u8, StorageError> {
         let mut v = [0];
@@ -0,0 +1,835 @@
+//! The formats a [`RawColumn`] can be encoded in, and how they are laid out.
+//!
+//! Each format is named by a [`ColumnFormat`], which can encode values in
+//! that format or estimate how large they would be in it.  A column is
+//! encoded in whichever format that can hold its values encodes a sample of
+//! them smallest.  The sample is made of a few slices of the column spread
+//! along it, which keeps the runs of each slice whole.  [`describe`] shows
+//! how a column file is laid out, which helps when one will not decode, and
+//! [`RawColumn::salvage_values`] reads what it can of one.
+//!
+//! # Layout
+//!
+//! Numbers of a fixed width are stored big endian, whatever the byte order
+//! of the machine writing or reading them, so a column file reads the same
+//! everywhere.  Numbers of a variable width (`var` below) are stored as one
+//! byte when less than 253, and otherwise as a byte of 253, 254 or 255
+//! followed by the number in 2, 4 or 8 bytes.  Each column starts with a
+//! header giving its [`FormatVersion`], followed by the magic of its format
+//! in 8 bytes, and then by the layout of the format:
+//!
+//! ```text
+//! eqcolumn version:u64 magic:u64 ...
+//! ```
+//!
+//! - [`ColumnFormat::Bools`], magic `__bool__`:
+//!   `n_rows:var n_chunks:var first_is_false:u8 runlength:var*`, with
+//!   each run holding the opposite of the one before it.
+//! - [`ColumnFormat::Bitmap`], magic `boolbmap`: a bit for each row, in
+//!   blocks whose number of true rows is in the header.
+//! - The u64 formats of a fixed or variable width, magic `00u64gen` with
+//!   the widths of values and of run lengths added to its first two bytes:
+//!   `n_rows:u64 n_chunks:u64 min:u64 max:u64 (runlength value-min)*`, where
+//!   the formats without runs leave out each run length of one.
+//! - [`ColumnFormat::BitPacked`], magic `u64bitpk`: values less the
+//!   smallest of their block, packed in as many bits as the block needs.
+//! - The bytes formats, magic `000bytes` with the widths of lengths, run
+//!   lengths and prefixes added to its first three bytes:
+//!   `n_rows:u64 n_chunks:u64 min_len:u64 len min len max (runlength len
+//!   prefix suffix)*`, where each `len` is less `min_len`, and `prefix` is
+//!   the number of bytes shared with the value before.  The formats of a
+//!   fixed length leave out the lengths, and those without runs each run
+//!   length of one.  [`ColumnFormat::Bytes`] leaves out the prefixes too.
+//! - [`ColumnFormat::Dictionary`], magic `dictbyte`: the sorted distinct
+//!   values, then a run length and code for each run.
+//! - [`ColumnFormat::SparseU64`] and [`ColumnFormat::SparseBytes`], magics
+//!   `sparse64` and `sparsebt`: the default, then each row that differs
+//!   from it, after the number of defaults since the last.
+//! - [`ColumnFormat::Custom`]: whatever the format registered with its magic
+//!   writes, see [`CustomFormat`](super::CustomFormat).
+//!
+//! Columns of each format, in every version, are kept in `src/column/golden`
+//! along with their descriptions, and are checked to decode as they did.  A
+//! change to any layout needs a new version, see [`FormatVersion`].
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use super::{
+    bitmap, bitpacked, bytes, custom, dictionary, run_length_encode, sparse, u64_generic,
+    BoolColumn, EncodeOptions, FormatVersion, IsRawColumn, RawColumn, RawColumnInner, StorageError,
+    WriteEncoded,
+};
+use crate::value::{RawKind, RawValue};
+
+/// A format of [`RawColumn`]
+///
+/// The formats storing values of a fixed width cannot hold values further
+/// apart than that width allows, and those without runs cannot hold a value
+/// repeated in consecutive rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ColumnFormat {
+    /// Runs of bools
+    Bools,
+    /// Bools packed one to a bit, for those that change often
+    Bitmap,
+    /// u64 values, each stored as one byte
+    U8,
+    /// Runs of u64 values, each stored as one byte
+    U8Runs,
+    /// u64 values, each stored as two bytes
+    U16,
+    /// Runs of u64 values, each stored as two bytes
+    U16Runs,
+    /// u64 values, each stored as four bytes
+    U32,
+    /// Runs of u64 values, each stored as four bytes
+    U32Runs,
+    /// u64 values, each stored in as few bytes as it needs
+    Varint,
+    /// Runs of u64 values, each stored in as few bytes as it needs
+    VarintRuns,
+    /// u64 values bit packed in blocks, each with its own width
+    BitPacked,
+    /// u64 values that are mostly the same, storing only those that differ
+    SparseU64,
+    /// Bytes of any length
+    Bytes,
+    /// Runs of bytes of any length
+    BytesRuns,
+    /// Bytes that are all the same length
+    FixedBytes,
+    /// Runs of bytes that are all the same length
+    FixedBytesRuns,
+    /// Runs of codes for bytes in a sorted dictionary
+    Dictionary,
+    /// Bytes that are mostly the same, storing only those that differ
+    SparseBytes,
+    /// A format registered from outside this crate, see
+    /// [`CustomFormat`](super::CustomFormat)
+    Custom {
+        /// The magic number starting each column in the format
+        magic: u64,
+        /// The kind of values the format holds
+        kind: RawKind,
+    },
+}
+
+/// The number of slices of a column in its sample
+const SAMPLE_SLICES: usize = 8;
+
+/// The number of rows in each slice of a sample
+const SAMPLE_SLICE_ROWS: usize = 512;
+
+/// A sample of `vals` to trial encode, which is all of them for a short
+/// column
+pub(crate) fn sample<T: Clone>(vals: &[T]) -> Cow<'_, [T]> {
+    if vals.len() <= SAMPLE_SLICES * SAMPLE_SLICE_ROWS {
+        return Cow::Borrowed(vals);
+    }
+    let stride = vals.len() / SAMPLE_SLICES;
+    let mut sample = Vec::with_capacity(SAMPLE_SLICES * SAMPLE_SLICE_ROWS);
+    for i in 0..SAMPLE_SLICES {
+        sample.extend_from_slice(&vals[i * stride..i * stride + SAMPLE_SLICE_ROWS]);
+    }
+    Cow::Owned(sample)
+}
+
+/// A writer that only counts the bytes written to it
+#[derive(Default)]
+struct ByteCount(usize);
+
+impl std::io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ColumnFormat {
+    /// Every built in format, which leaves out those registered as a
+    /// [`CustomFormat`](super::CustomFormat)
+    pub const ALL: [ColumnFormat; 18] = [
+        ColumnFormat::Bools,
+        ColumnFormat::Bitmap,
+        ColumnFormat::U8,
+        ColumnFormat::U8Runs,
+        ColumnFormat::U16,
+        ColumnFormat::U16Runs,
+        ColumnFormat::U32,
+        ColumnFormat::U32Runs,
+        ColumnFormat::Varint,
+        ColumnFormat::VarintRuns,
+        ColumnFormat::BitPacked,
+        ColumnFormat::SparseU64,
+        ColumnFormat::Bytes,
+        ColumnFormat::BytesRuns,
+        ColumnFormat::FixedBytes,
+        ColumnFormat::FixedBytesRuns,
+        ColumnFormat::Dictionary,
+        ColumnFormat::SparseBytes,
+    ];
+
+    /// The kind of values the format holds
+    pub fn kind(self) -> RawKind {
+        match self {
+            ColumnFormat::Bools | ColumnFormat::Bitmap => RawKind::Bool,
+            ColumnFormat::U8
+            | ColumnFormat::U8Runs
+            | ColumnFormat::U16
+            | ColumnFormat::U16Runs
+            | ColumnFormat::U32
+            | ColumnFormat::U32Runs
+            | ColumnFormat::Varint
+            | ColumnFormat::VarintRuns
+            | ColumnFormat::BitPacked
+            | ColumnFormat::SparseU64 => RawKind::U64,
+            ColumnFormat::Bytes
+            | ColumnFormat::BytesRuns
+            | ColumnFormat::FixedBytes
+            | ColumnFormat::FixedBytesRuns
+            | ColumnFormat::Dictionary
+            | ColumnFormat::SparseBytes => RawKind::Bytes,
+            ColumnFormat::Custom { kind, .. } => kind,
+        }
+    }
+
+    /// Whether this is one of the sparse formats
+    pub(crate) fn is_sparse(self) -> bool {
+        matches!(self, ColumnFormat::SparseU64 | ColumnFormat::SparseBytes)
+    }
+
+    /// Whether the format can hold u64 values differing by up to `spread`,
+    /// with runs as long as `longest_run`
+    pub(crate) fn holds_u64(self, spread: u64, longest_run: u64) -> bool {
+        let (max_spread, runs) = match self {
+            ColumnFormat::U8 => (u8::MAX as u64, false),
+            ColumnFormat::U8Runs => (u8::MAX as u64, true),
+            ColumnFormat::U16 => (u16::MAX as u64, false),
+            ColumnFormat::U16Runs => (u16::MAX as u64, true),
+            ColumnFormat::U32 => (u32::MAX as u64, false),
+            ColumnFormat::U32Runs => (u32::MAX as u64, true),
+            ColumnFormat::Varint => (u64::MAX, false),
+            ColumnFormat::VarintRuns | ColumnFormat::BitPacked | ColumnFormat::SparseU64 => {
+                (u64::MAX, true)
+            }
+            _ => return false,
+        };
+        spread <= max_spread && (runs || longest_run < 2)
+    }
+
+    /// Whether the format can hold bytes that may all have the same length,
+    /// with runs as long as `longest_run`
+    pub(crate) fn holds_bytes(self, same_length: bool, longest_run: u64) -> bool {
+        match self {
+            ColumnFormat::Bytes => longest_run < 2,
+            ColumnFormat::FixedBytes => same_length && longest_run < 2,
+            ColumnFormat::FixedBytesRuns => same_length,
+            ColumnFormat::BytesRuns | ColumnFormat::Dictionary | ColumnFormat::SparseBytes => true,
+            _ => false,
+        }
+    }
+
+    /// Encode runs of bools in this format
+    pub(crate) fn encode_bools<W: WriteEncoded>(
+        self,
+        out: &mut W,
+        runs: &[(bool, u64)],
+    ) -> Result<(), StorageError> {
+        match self {
+            ColumnFormat::Bools => BoolColumn::encode(out, runs),
+            ColumnFormat::Bitmap => bitmap::Bitmap::encode(out, runs),
+            ColumnFormat::Custom { magic, .. } => {
+                let runs = runs.iter().map(|&(v, n)| (RawValue::Bool(v), n));
+                custom::encode(out, magic, RawKind::Bool, runs.collect())
+            }
+            _ => Err(StorageError::InvalidRow("format does not hold bools")),
+        }
+    }
+
+    /// Encode runs of u64 values in this format
+    pub(crate) fn encode_u64<W: WriteEncoded>(
+        self,
+        out: &mut W,
+        runs: &[(u64, u64)],
+    ) -> Result<(), StorageError> {
+        match self {
+            ColumnFormat::U8 => u64_generic::U8One::encode(out, runs),
+            ColumnFormat::U8Runs => u64_generic::U8Variable::encode(out, runs),
+            ColumnFormat::U16 => u64_generic::U16One::encode(out, runs),
+            ColumnFormat::U16Runs => u64_generic::U16Variable::encode(out, runs),
+            ColumnFormat::U32 => u64_generic::U32One::encode(out, runs),
+            ColumnFormat::U32Runs => u64_generic::U32Variable::encode(out, runs),
+            ColumnFormat::Varint => u64_generic::VariableOne::encode(out, runs),
+            ColumnFormat::VarintRuns => u64_generic::VariableVariable::encode(out, runs),
+            ColumnFormat::BitPacked => bitpacked::BitPacked::encode(out, runs),
+            ColumnFormat::SparseU64 => sparse::SparseU64::encode(out, runs),
+            ColumnFormat::Custom { magic, .. } => {
+                let runs = runs.iter().map(|&(v, n)| (RawValue::U64(v), n));
+                custom::encode(out, magic, RawKind::U64, runs.collect())
+            }
+            _ => Err(StorageError::InvalidRow("format does not hold u64")),
+        }
+    }
+
+    /// Encode runs of bytes in this format
+    pub(crate) fn encode_bytes<W: WriteEncoded>(
+        self,
+        out: &mut W,
+        runs: &[(Vec<u8>, u64)],
+    ) -> Result<(), StorageError> {
+        match self {
+            ColumnFormat::Bytes => bytes::V10::encode(out, runs),
+            ColumnFormat::BytesRuns => bytes::VVV::encode(out, runs),
+            ColumnFormat::FixedBytes => bytes::F1V::encode(out, runs),
+            ColumnFormat::FixedBytesRuns => bytes::FVV::encode(out, runs),
+            ColumnFormat::Dictionary => dictionary::Dictionary::encode(out, runs),
+            ColumnFormat::SparseBytes => sparse::SparseBytes::encode(out, runs),
+            ColumnFormat::Custom { magic, .. } => {
+                let runs = runs.iter().map(|(v, n)| (RawValue::Bytes(v.clone()), *n));
+                custom::encode(out, magic, RawKind::Bytes, runs.collect())
+            }
+            _ => Err(StorageError::InvalidRow("format does not hold bytes")),
+        }
+    }
+
+    /// The size of runs of bools encoded in this format, if it can hold them
+    pub(crate) fn estimate_bools(self, runs: &[(bool, u64)]) -> Option<usize> {
+        let mut count = ByteCount::default();
+        self.encode_bools(&mut count, runs).ok()?;
+        Some(count.0)
+    }
+
+    /// The size of runs of u64 values encoded in this format, if it can hold
+    /// them
+    pub(crate) fn estimate_u64(self, runs: &[(u64, u64)]) -> Option<usize> {
+        let mut count = ByteCount::default();
+        self.encode_u64(&mut count, runs).ok()?;
+        Some(count.0)
+    }
+
+    /// The size of runs of bytes encoded in this format, if it can hold them
+    pub(crate) fn estimate_bytes(self, runs: &[(Vec<u8>, u64)]) -> Option<usize> {
+        let mut count = ByteCount::default();
+        self.encode_bytes(&mut count, runs).ok()?;
+        Some(count.0)
+    }
+
+    /// Encode `values` in this format, which must be able to hold them
+    pub fn encode<W: WriteEncoded>(
+        self,
+        out: &mut W,
+        values: &[RawValue],
+    ) -> Result<(), StorageError> {
+        match self.kind() {
+            RawKind::Bool => RawColumn::write_values_with(
+                out,
+                RawKind::Bool,
+                values,
+                EncodeOptions::default().format(self),
+            ),
+            RawKind::U64 => {
+                super::version::write_header(out)?;
+                self.encode_u64(out, &run_length_encode(&u64_values(values)?))
+            }
+            RawKind::Bytes => {
+                super::version::write_header(out)?;
+                self.encode_bytes(out, &run_length_encode(&bytes_values(values)?))
+            }
+        }
+    }
+
+    /// The number of bytes `values` would take encoded in this format, or
+    /// `None` if the format cannot hold them
+    pub fn estimate_size(self, values: &[RawValue]) -> Option<usize> {
+        let mut count = ByteCount::default();
+        self.encode(&mut count, values).ok()?;
+        Some(count.0)
+    }
+}
+
+fn u64_values(values: &[RawValue]) -> Result<Vec<u64>, StorageError> {
+    values
+        .iter()
+        .map(|v| match v {
+            RawValue::U64(n) => Ok(*n),
+            _ => Err(StorageError::InvalidRow("expected a u64")),
+        })
+        .collect()
+}
+
+fn bytes_values(values: &[RawValue]) -> Result<Vec<Vec<u8>>, StorageError> {
+    values
+        .iter()
+        .map(|v| match v {
+            RawValue::Bytes(b) => Ok(b.clone()),
+            _ => Err(StorageError::InvalidRow("expected bytes")),
+        })
+        .collect()
+}
+
+impl RawColumn {
+    /// The format the column is encoded in
+    pub fn format(&self) -> ColumnFormat {
+        match &self.inner {
+            RawColumnInner::Bool(_) => ColumnFormat::Bools,
+            RawColumnInner::BoolBitmap(_) => ColumnFormat::Bitmap,
+            RawColumnInner::BytesVVV(_) => ColumnFormat::BytesRuns,
+            RawColumnInner::BytesV10(_) => ColumnFormat::Bytes,
+            RawColumnInner::BytesFVV(_) => ColumnFormat::FixedBytesRuns,
+            RawColumnInner::BytesF1V(_) => ColumnFormat::FixedBytes,
+            RawColumnInner::BytesDict(_) => ColumnFormat::Dictionary,
+            RawColumnInner::BytesSparse(_) => ColumnFormat::SparseBytes,
+            RawColumnInner::U64VV(_) => ColumnFormat::VarintRuns,
+            RawColumnInner::U64V1(_) => ColumnFormat::Varint,
+            RawColumnInner::U64_32(_) => ColumnFormat::U32Runs,
+            RawColumnInner::U64_32_1(_) => ColumnFormat::U32,
+            RawColumnInner::U64_16(_) => ColumnFormat::U16Runs,
+            RawColumnInner::U64_16_1(_) => ColumnFormat::U16,
+            RawColumnInner::U64_8(_) => ColumnFormat::U8Runs,
+            RawColumnInner::U64_8_1(_) => ColumnFormat::U8,
+            RawColumnInner::U64Sparse(_) => ColumnFormat::SparseU64,
+            RawColumnInner::U64BitPacked(_) => ColumnFormat::BitPacked,
+            RawColumnInner::Custom(c) => c.format(),
+        }
+    }
+}
+
+/// How a column file is laid out, see [`describe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDescription {
+    version: FormatVersion,
+    format: ColumnFormat,
+    magic: u64,
+    byte_len: u64,
+    num_rows: u64,
+    num_chunks: u64,
+    min: RawValue,
+    max: RawValue,
+    chunks: Vec<ChunkLayout>,
+    error: Option<String>,
+}
+
+/// Where a chunk of a column is stored, see [`ColumnDescription::chunks`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkLayout {
+    offset: Option<u64>,
+    rows: Range<u64>,
+    value: RawValue,
+}
+
+impl ChunkLayout {
+    /// The offset in the file at which the chunk is read, which is not
+    /// known for custom formats
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// The rows of the chunk
+    pub fn rows(&self) -> Range<u64> {
+        self.rows.clone()
+    }
+
+    /// The value held by every row of the chunk
+    pub fn value(&self) -> &RawValue {
+        &self.value
+    }
+}
+
+impl ColumnDescription {
+    /// The version of the encoding the column was written in
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+
+    /// The format the column is encoded in
+    pub fn format(&self) -> ColumnFormat {
+        self.format
+    }
+
+    /// The magic number of the format, which follows the header
+    pub fn magic(&self) -> u64 {
+        self.magic
+    }
+
+    /// The number of bytes of the column, including its header
+    pub fn byte_len(&self) -> u64 {
+        self.byte_len
+    }
+
+    /// The number of rows, as recorded in the header of the format
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    /// The number of chunks, as recorded in the header of the format
+    pub fn num_chunks(&self) -> u64 {
+        self.num_chunks
+    }
+
+    /// The smallest and largest values, as recorded in the header of the
+    /// format
+    pub fn range(&self) -> (&RawValue, &RawValue) {
+        (&self.min, &self.max)
+    }
+
+    /// The chunks that could be decoded, in order
+    pub fn chunks(&self) -> &[ChunkLayout] {
+        &self.chunks
+    }
+
+    /// Why the chunks after the last in [`ColumnDescription::chunks`] could
+    /// not be decoded, if they could not
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// Displaying a description writes its header and format, and the alternate
+/// form, as in `{:#}`, writes each chunk too.
+impl std::fmt::Display for ColumnDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "version {:?}, format {:?}, magic {:016x}, {} bytes",
+            self.version, self.format, self.magic, self.byte_len
+        )?;
+        writeln!(
+            f,
+            "{} rows in {} chunks, from {} to {}",
+            self.num_rows, self.num_chunks, self.min, self.max
+        )?;
+        if !f.alternate() {
+            return Ok(());
+        }
+        for c in self.chunks.iter() {
+            let offset = c.offset.map(|o| o.to_string()).unwrap_or_default();
+            let rows = format!("{}..{}", c.rows.start, c.rows.end);
+            writeln!(f, "{offset:>8} {rows:>12} {}", c.value)?;
+        }
+        if let Some(e) = &self.error {
+            writeln!(f, "error: {e}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Describe the column encoded in `bytes`: its header, its format, and the
+/// offset, rows and value of each of its chunks.
+///
+/// Chunks are decoded until one fails, whose error is kept in the
+/// description, so a corrupt column is described as far as it can be.  A
+/// column whose header cannot be read is an error.
+pub fn describe(bytes: &[u8]) -> Result<ColumnDescription, StorageError> {
+    let column = RawColumn::decode(bytes.to_vec())?;
+    let mut chunks = Vec::new();
+    let error = column
+        .chunk_layout(&mut chunks)
+        .err()
+        .map(|e| e.to_string());
+    let start = match column.version() {
+        FormatVersion::Unversioned => 0,
+        _ => super::version::HEADER_LEN as usize,
+    };
+    let mut magic = [0; 8];
+    magic.copy_from_slice(&bytes[start..start + 8]);
+    Ok(ColumnDescription {
+        version: column.version(),
+        format: column.format(),
+        magic: u64::from_be_bytes(magic),
+        byte_len: column.byte_len(),
+        num_rows: column.num_rows(),
+        num_chunks: column.num_chunks(),
+        min: column.min(),
+        max: column.max(),
+        chunks,
+        error,
+    })
+}
+
+impl RawColumn {
+    /// Push the layout of each chunk of the column to `chunks`, until one
+    /// cannot be decoded
+    fn chunk_layout(&self, chunks: &mut Vec<ChunkLayout>) -> Result<(), StorageError> {
+        match &self.inner {
+            RawColumnInner::Bool(c) => layout(c, RawValue::Bool, chunks),
+            RawColumnInner::BoolBitmap(c) => layout(c, RawValue::Bool, chunks),
+            RawColumnInner::BytesVVV(c) => layout(c, RawValue::Bytes, chunks),
+            RawColumnInner::BytesV10(c) => layout(c, RawValue::Bytes, chunks),
+            RawColumnInner::BytesFVV(c) => layout(c, RawValue::Bytes, chunks),
+            RawColumnInner::BytesF1V(c) => layout(c, RawValue::Bytes, chunks),
+            RawColumnInner::BytesDict(c) => layout(c, RawValue::Bytes, chunks),
+            RawColumnInner::BytesSparse(c) => layout(c, RawValue::Bytes, chunks),
+            RawColumnInner::U64VV(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64V1(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64_32(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64_32_1(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64_16(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64_16_1(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64_8(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64_8_1(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64Sparse(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::U64BitPacked(c) => layout(c, RawValue::U64, chunks),
+            RawColumnInner::Custom(c) => {
+                let mut start = 0;
+                for (value, num) in c.runs_slice() {
+                    chunks.push(ChunkLayout {
+                        offset: None,
+                        rows: start..start + num,
+                        value: value.clone(),
+                    });
+                    start += num;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The values of the chunks of the column that decode, up to the first
+    /// that does not, along with the error it gives.
+    ///
+    /// A column cut short in the middle of a chunk gives the values of the
+    /// chunks before it, and one that decodes completely gives all of its
+    /// values and no error.
+    pub fn salvage_values(&self) -> (Vec<RawValue>, Option<StorageError>) {
+        let mut chunks = Vec::new();
+        let error = self.chunk_layout(&mut chunks).err();
+        let values = chunks
+            .into_iter()
+            .flat_map(|c| vec![c.value; (c.rows.end - c.rows.start) as usize])
+            .collect();
+        (values, error)
+    }
+}
+
+/// Push the layout of each chunk of `column` to `chunks`, with the offset
+/// at which it is read
+fn layout<C: IsRawColumn>(
+    column: &C,
+    to_value: fn(C::Element) -> RawValue,
+    chunks: &mut Vec<ChunkLayout>,
+) -> Result<(), StorageError> {
+    let mut column = column.clone();
+    loop {
+        let offset = column.tell()?;
+        let Some(chunk) = column.next().transpose()? else {
+            return Ok(());
+        };
+        chunks.push(ChunkLayout {
+            offset: Some(offset),
+            rows: chunk.range,
+            value: to_value(chunk.value),
+        });
+    }
+}
+
+#[test]
+fn formats_round_trip() {
+    let columns: [Vec<RawValue>; 3] = [
+        (0..100).map(|i| RawValue::Bool(i % 7 < 3)).collect(),
+        (0..100).map(|i| RawValue::U64(i * 3 % 50)).collect(),
+        (0..100)
+            .map(|i| RawValue::Bytes(format!("{:03}", i * 3 % 50).into_bytes()))
+            .collect(),
+    ];
+    for values in columns {
+        let kind = values[0].kind();
+        for format in ColumnFormat::ALL {
+            let Some(size) = format.estimate_size(&values) else {
+                assert!(format.kind() != kind || format.encode(&mut Vec::new(), &values).is_err());
+                continue;
+            };
+            assert_eq!(format.kind(), kind);
+            let mut encoded = Vec::new();
+            format.encode(&mut encoded, &values).unwrap();
+            assert_eq!(encoded.len(), size, "{format:?}");
+            let column = RawColumn::decode(encoded).unwrap();
+            assert_eq!(column.format(), format);
+            assert_eq!(column.read_values().unwrap(), values, "{format:?}");
+        }
+    }
+
+    // Formats without runs cannot hold repeated values, nor those of a fixed
+    // width values too far apart.
+    let repeated = [RawValue::U64(1), RawValue::U64(1), RawValue::U64(300)];
+    assert_eq!(ColumnFormat::U16.estimate_size(&repeated), None);
+    assert_eq!(ColumnFormat::U8Runs.estimate_size(&repeated), None);
+    assert!(ColumnFormat::U16Runs.estimate_size(&repeated).is_some());
+}
+
+#[test]
+fn pick_smallest_format() {
+    use super::EncodeOptions;
+
+    let columns: [Vec<u64>; 4] = [
+        (0..20_000).map(|i| i / 1000).collect(),
+        (0..20_000).map(|i| 1_000_000 + 3 * i).collect(),
+        (0..20_000)
+            .map(|i| if i % 97 == 0 { i } else { 0 })
+            .collect(),
+        // The sample misses the large values, which only fit a wider format.
+        (0..20_000)
+            .map(|i| if i == 2_000 { 1 << 40 } else { i % 200 })
+            .collect(),
+    ];
+    for vals in columns {
+        let mut picked = Vec::new();
+        RawColumn::write_u64(&mut picked, &vals).unwrap();
+        let column = RawColumn::decode(picked.clone()).unwrap();
+        assert_eq!(column.read_u64().unwrap(), vals);
+        let runs = run_length_encode(&vals);
+        for format in ColumnFormat::ALL {
+            if let Some(size) = format.estimate_u64(&runs) {
+                let size = size + super::version::HEADER_LEN as usize;
+                assert!(picked.len() <= size + size / 10, "{:?}", column.format());
+            }
+        }
+
+        // The format can be forced, as long as it holds the values.
+        let options = EncodeOptions::default().format(ColumnFormat::VarintRuns);
+        let mut forced = Vec::new();
+        RawColumn::write_u64_with(&mut forced, &vals, options).unwrap();
+        let forced = RawColumn::decode(forced).unwrap();
+        assert_eq!(forced.format(), ColumnFormat::VarintRuns);
+        assert_eq!(forced.read_u64().unwrap(), vals);
+    }
+    let options = EncodeOptions::default().format(ColumnFormat::U8);
+    assert!(RawColumn::write_u64_with(&mut Vec::new(), &[1, 1, 2], options).is_err());
+    assert!(RawColumn::write_values_with(
+        &mut Vec::new(),
+        RawKind::Bool,
+        &[RawValue::Bool(true)],
+        options
+    )
+    .is_err());
+    let options = EncodeOptions::default().format(ColumnFormat::Dictionary);
+    let mut forced = Vec::new();
+    RawColumn::write_bytes_with(&mut forced, &[b"a".to_vec(), b"b".to_vec()], options).unwrap();
+    assert_eq!(
+        RawColumn::decode(forced).unwrap().format(),
+        ColumnFormat::Dictionary
+    );
+}
+
+/// The values kept encoded in `format` in `src/column/golden`, which are
+/// the first of a few columns that the format holds
+#[cfg(test)]
+fn golden_values(format: ColumnFormat) -> Vec<RawValue> {
+    let bytes = |vals: &[&str]| {
+        vals.iter()
+            .map(|v| RawValue::Bytes(v.as_bytes().to_vec()))
+            .collect::<Vec<_>>()
+    };
+    let columns = match format.kind() {
+        RawKind::Bool => vec![[true, true, false, true, false, false, false, true]
+            .map(RawValue::Bool)
+            .to_vec()],
+        RawKind::U64 => vec![
+            [3, 3, 3, 7, 7, 200, 0, 0, 0, 0, 5]
+                .map(RawValue::U64)
+                .to_vec(),
+            [3, 7, 200, 0, 5, 1].map(RawValue::U64).to_vec(),
+        ],
+        RawKind::Bytes => vec![
+            bytes(&["apple", "apple", "apricot", "", "", "", "kiwi", "kiwi"]),
+            bytes(&["apple", "apricot", "", "kiwi", "kiwis"]),
+            bytes(&["pear", "pear", "plum", "plum", "lime"]),
+            bytes(&["pear", "plum", "lime", "pear"]),
+        ],
+    };
+    columns
+        .into_iter()
+        .find(|c| format.estimate_size(c).is_some())
+        .expect("the format holds a column")
+}
+
+#[test]
+fn golden_columns() {
+    use std::path::Path;
+
+    // Setting UPDATE_EXPECT writes the descriptions and the columns of the
+    // current version anew, and the columns of older versions only if they
+    // are missing, since those must keep decoding as they were written.
+    let update = std::env::var_os("UPDATE_EXPECT").is_some();
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/column/golden");
+    let check = |path: &Path, bytes: &[u8], rewrite: bool| {
+        if update && (rewrite || !path.exists()) {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, bytes).unwrap();
+        }
+        let kept = std::fs::read(path)
+            .unwrap_or_else(|e| panic!("{}: {e}, run with UPDATE_EXPECT=1", path.display()));
+        if rewrite {
+            assert!(
+                kept == bytes,
+                "{} differs from what is written now, and a changed layout needs a new version",
+                path.display()
+            );
+        }
+        kept
+    };
+    for format in ColumnFormat::ALL {
+        let values = golden_values(format);
+        let mut encoded = Vec::new();
+        format.encode(&mut encoded, &values).unwrap();
+        let header = super::version::HEADER_LEN as usize;
+        for version in FormatVersion::ALL {
+            let dir = golden.join(format!("v{}", version.number()));
+            let written = match version {
+                FormatVersion::Unversioned => &encoded[header..],
+                FormatVersion::V1 => &encoded[..],
+            };
+            let current = version == FormatVersion::CURRENT;
+            let kept = check(&dir.join(format!("{format:?}.col")), written, current);
+            let column = RawColumn::decode(kept.clone()).unwrap();
+            assert_eq!(column.version(), version);
+            assert_eq!(column.format(), format);
+            assert_eq!(column.read_values().unwrap(), values, "{format:?}");
+
+            let description = describe(&kept).unwrap();
+            assert_eq!(description.error(), None);
+            let text = format!("{description:#}");
+            check(&dir.join(format!("{format:?}.txt")), text.as_bytes(), true);
+        }
+    }
+}
+
+#[test]
+fn describe_corrupt() {
+    let values = (0..10).map(|i| RawValue::U64(i * 1000)).collect::<Vec<_>>();
+    let mut encoded = Vec::new();
+    ColumnFormat::VarintRuns
+        .encode(&mut encoded, &values)
+        .unwrap();
+    let description = describe(&encoded).unwrap();
+    assert_eq!(description.chunks().len(), 10);
+    assert_eq!(description.chunks()[3].rows(), 3..4);
+    assert_eq!(description.chunks()[3].value(), &RawValue::U64(3000));
+
+    // A column cut short is described up to the chunk it ends in.
+    let cut = &encoded[..encoded.len() - 4];
+    let description = describe(cut).unwrap();
+    assert_eq!(description.num_rows(), 10);
+    assert!(description.chunks().len() < 10);
+    assert!(description.error().is_some());
+    expect_test::expect![[r#"
+        version V1, format VarintRuns, magic 393975363467656e, 90 bytes
+        10 rows in 10 chunks, from 0 to 9000
+              56         0..1 0
+              58         1..2 1000
+              62         2..3 2000
+              66         3..4 3000
+              70         4..5 4000
+              74         5..6 5000
+              78         6..7 6000
+              82         7..8 7000
+              86         8..9 8000
+        error: Io error: failed to seek
+    "#]]
+    .assert_eq(&format!("{description:#}"));
+
+    assert!(describe(&encoded[..20]).is_err());
+}
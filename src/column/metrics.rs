@@ -0,0 +1,130 @@
+//! Counters of the work done reading columns.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Counters of the work done reading columns, to see why a read was slow.
+///
+/// Clones share their counters, so the metrics of a [`Table`](crate::Table)
+/// go on counting as its rows are decoded after it has been opened.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    bytes_read: AtomicU64,
+    seeks: AtomicU64,
+    chunks_decoded: AtomicU64,
+    cache_hits: AtomicU64,
+}
+
+impl Metrics {
+    /// The number of encoded bytes read, from files or from memory
+    pub fn bytes_read(&self) -> u64 {
+        self.counters.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// The number of times reading jumped to another place within a column
+    pub fn seeks(&self) -> u64 {
+        self.counters.seeks.load(Ordering::Relaxed)
+    }
+
+    /// The number of chunks of identical values decoded
+    pub fn chunks_decoded(&self) -> u64 {
+        self.counters.chunks_decoded.load(Ordering::Relaxed)
+    }
+
+    /// The number of reads served from a cache, rather than decoded again
+    pub fn cache_hits(&self) -> u64 {
+        self.counters.cache_hits.load(Ordering::Relaxed)
+    }
+
+    /// Add the counts of `other` to these
+    pub fn add(&self, other: &Metrics) {
+        let (to, from) = (&self.counters, &other.counters);
+        to.bytes_read
+            .fetch_add(from.bytes_read.load(Ordering::Relaxed), Ordering::Relaxed);
+        to.seeks
+            .fetch_add(from.seeks.load(Ordering::Relaxed), Ordering::Relaxed);
+        to.chunks_decoded.fetch_add(
+            from.chunks_decoded.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        to.cache_hits
+            .fetch_add(from.cache_hits.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_bytes_read(&self, bytes: u64) {
+        self.counters.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_seek(&self) {
+        self.counters.seeks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cache_hit(&self) {
+        self.counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_chunks(&self, chunks: u64) {
+        self.counters
+            .chunks_decoded
+            .fetch_add(chunks, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes read, {} seeks, {} chunks decoded, {} cache hits",
+            self.bytes_read(),
+            self.seeks(),
+            self.chunks_decoded(),
+            self.cache_hits()
+        )
+    }
+}
+
+#[test]
+fn count_reads() {
+    use crate::{ColumnSchema, Database, RawValue, TableSchema};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    // Every read decodes the columns, rather than taking them from the cache.
+    db.set_cache_capacity(0);
+    let mut schema = TableSchema::new("counts");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(ColumnSchema::<u64>::new("count").raw());
+    let counts = db.create_table(schema).unwrap();
+    counts
+        .insert_raw_rows((0..10_000u64).map(|i| {
+            [RawValue::U64(i), RawValue::U64(i % 7)]
+                .into_iter()
+                .collect()
+        }))
+        .unwrap();
+
+    // Opening the table reads just the headers of its columns.
+    let table = counts.read().unwrap();
+    let opened = table.metrics().bytes_read();
+    assert!(opened > 0);
+    assert_eq!(table.metrics().chunks_decoded(), 0);
+    assert_eq!(table.to_rows().unwrap().len(), 10_000);
+    assert!(table.metrics().bytes_read() > opened);
+    assert_eq!(table.metrics().chunks_decoded(), 20_000);
+    assert_eq!(table.metrics().seeks(), 0);
+
+    // A query adds up the work of reading each table it selects from.
+    let result = db.execute("select count from counts").unwrap();
+    assert_eq!(result.rows().len(), 10_000);
+    assert_eq!(result.metrics().to_string(), table.metrics().to_string());
+    let total = Metrics::default();
+    total.add(result.metrics());
+    total.add(table.metrics());
+    assert_eq!(total.bytes_read(), 2 * table.metrics().bytes_read());
+}
@@ -0,0 +1,312 @@
+//! Sparse encoding for columns that almost always hold one value.
+//!
+//! The header holds the default value of the column, and the body holds an
+//! entry for each row that does not hold it.  Each entry stores the row
+//! index, as the number of rows of the default since the previous entry,
+//! followed by the value.  Scattered values cost one entry each, where run
+//! length encoding would need a chunk for them and another for the defaults
+//! in between.
+//!
+//! ```text
+//! MAGIC n_rows n_chunks n_entries default min max (gap value)*
+//! ```
+//!
+//! Reading the column gives a chunk for each entry, and one for each stretch
+//! of defaults between them.
+use std::collections::BTreeMap;
+
+use super::{
+    decode_chunks, Chunk, DecodeBlock, IsRawColumn, ReadEncoded, Storage, StorageError,
+    WriteEncoded,
+};
+
+/// A value that can be stored in a sparse column
+pub(crate) trait SparseElement: Clone + Ord {
+    const MAGIC: u64;
+    fn write<W: WriteEncoded>(&self, out: &mut W) -> Result<(), StorageError>;
+    fn read(storage: &mut Storage) -> Result<Self, StorageError>;
+}
+
+impl SparseElement for u64 {
+    const MAGIC: u64 = u64::from_be_bytes(*b"sparse64");
+    fn write<W: WriteEncoded>(&self, out: &mut W) -> Result<(), StorageError> {
+        out.write_unsigned(*self)
+    }
+    fn read(storage: &mut Storage) -> Result<Self, StorageError> {
+        storage.read_usigned()
+    }
+}
+
+impl SparseElement for Vec<u8> {
+    const MAGIC: u64 = u64::from_be_bytes(*b"sparsebt");
+    fn write<W: WriteEncoded>(&self, out: &mut W) -> Result<(), StorageError> {
+        out.write_unsigned(self.len() as u64)?;
+        out.write_all(self)?;
+        Ok(())
+    }
+    fn read(storage: &mut Storage) -> Result<Self, StorageError> {
+        let len = storage.read_usigned()?;
+        storage.read_vec(len)
+    }
+}
+
+/// The value held by the most rows, which is stored as the default
+fn most_common<T: Ord>(runs: &[(T, u64)]) -> Option<&T> {
+    let mut counts = BTreeMap::new();
+    for (v, num) in runs {
+        *counts.entry(v).or_insert(0) += num;
+    }
+    counts.into_iter().max_by_key(|x| x.1).map(|x| x.0)
+}
+
+#[derive(Clone)]
+pub(crate) struct Sparse<T> {
+    storage: Storage,
+    current_row: u64,
+    n_rows: u64,
+    n_chunks: u64,
+    /// The number of entries not yet read
+    remaining: u64,
+    /// The value of an entry that has been read, but not yet returned
+    pending: Option<T>,
+    /// The rest of a chunk only partly decoded by [`DecodeBlock::decode_block`]
+    carry: Option<Chunk<T>>,
+    default: T,
+    v_min: T,
+    v_max: T,
+}
+
+pub(crate) type SparseU64 = Sparse<u64>;
+pub(crate) type SparseBytes = Sparse<Vec<u8>>;
+
+impl<T: SparseElement> From<&[T]> for Sparse<T> {
+    fn from(vals: &[T]) -> Self {
+        let mut bytes = Vec::<u8>::new();
+        Self::encode(&mut bytes, &super::run_length_encode(vals)).expect("error encoding");
+        let storage = Storage::from(bytes);
+        Self::open(storage).unwrap()
+    }
+}
+
+impl<T: SparseElement> Iterator for Sparse<T> {
+    type Item = Result<Chunk<T>, StorageError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.transposed_next().transpose()
+    }
+}
+
+impl<T: SparseElement> Sparse<T> {
+    pub(crate) const MAGIC: u64 = T::MAGIC;
+
+    fn transposed_next(&mut self) -> Result<Option<Chunk<T>>, StorageError> {
+        if let Some(chunk) = self.carry.take() {
+            return Ok(Some(chunk));
+        }
+        let start = self.current_row;
+        if start == self.n_rows {
+            return Ok(None);
+        }
+        if let Some(value) = self.pending.take() {
+            self.current_row += 1;
+            return Ok(Some(Chunk {
+                value,
+                range: start..self.current_row,
+            }));
+        }
+        if self.remaining == 0 {
+            self.current_row = self.n_rows;
+            return Ok(Some(Chunk {
+                value: self.default.clone(),
+                range: start..self.n_rows,
+            }));
+        }
+        self.remaining -= 1;
+        let gap = self.storage.read_usigned()?;
+        let value = T::read(&mut self.storage)?;
+        if gap >= self.n_rows - start {
+            return Err(StorageError::OutOfBounds("sparse row index"));
+        }
+        if gap == 0 {
+            self.current_row += 1;
+            return Ok(Some(Chunk {
+                value,
+                range: start..self.current_row,
+            }));
+        }
+        self.pending = Some(value);
+        self.current_row += gap;
+        Ok(Some(Chunk {
+            value: self.default.clone(),
+            range: start..self.current_row,
+        }))
+    }
+}
+
+impl<T: SparseElement> IsRawColumn for Sparse<T> {
+    type Element = T;
+
+    fn num_rows(&self) -> u64 {
+        self.n_rows
+    }
+    fn num_chunks(&self) -> u64 {
+        self.n_chunks
+    }
+    fn max(&self) -> Self::Element {
+        self.v_max.clone()
+    }
+    fn min(&self) -> Self::Element {
+        self.v_min.clone()
+    }
+
+    fn encode<W: WriteEncoded>(
+        out: &mut W,
+        input: &[(Self::Element, u64)],
+    ) -> Result<(), StorageError> {
+        if input.is_empty() {
+            return Ok(());
+        }
+        let default = most_common(input).unwrap_or(&input[0].0);
+        let mut entries = Vec::new();
+        let mut gap = 0;
+        let mut n_chunks = 0;
+        for (v, num) in input.iter() {
+            if v == default {
+                gap += num;
+                continue;
+            }
+            for _ in 0..*num {
+                n_chunks += 1 + (gap > 0) as u64;
+                entries.push((gap, v));
+                gap = 0;
+            }
+        }
+        n_chunks += (gap > 0) as u64;
+        let min = input.iter().map(|(v, _)| v).min().unwrap_or(default);
+        let max = input.iter().map(|(v, _)| v).max().unwrap_or(default);
+
+        out.write_u64(Self::MAGIC)?;
+        out.write_u64(input.iter().map(|x| x.1).sum())?;
+        out.write_u64(n_chunks)?;
+        out.write_u64(entries.len() as u64)?;
+        default.write(out)?;
+        min.write(out)?;
+        max.write(out)?;
+        for (gap, v) in entries {
+            out.write_unsigned(gap)?;
+            v.write(out)?;
+        }
+        Ok(())
+    }
+
+    fn open(mut storage: Storage) -> Result<Self, StorageError> {
+        let magic = storage.read_u64()?;
+        if magic != Self::MAGIC {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let n_rows = storage.read_u64()?;
+        let n_chunks = storage.read_u64()?;
+        let remaining = storage.read_u64()?;
+        if remaining > n_rows {
+            return Err(StorageError::OutOfBounds("too many sparse entries"));
+        }
+        let default = T::read(&mut storage)?;
+        let v_min = T::read(&mut storage)?;
+        let v_max = T::read(&mut storage)?;
+        Ok(Sparse {
+            storage,
+            current_row: 0,
+            n_rows,
+            n_chunks,
+            remaining,
+            pending: None,
+            carry: None,
+            default,
+            v_min,
+            v_max,
+        })
+    }
+
+    fn tell(&self) -> Result<u64, StorageError> {
+        self.storage.tell()
+    }
+
+    fn seek(
+        &mut self,
+        offset: u64,
+        row_number: u64,
+        _value: impl AsRef<Self::Element>,
+    ) -> Result<(), StorageError> {
+        self.current_row = row_number;
+        self.pending = None;
+        self.carry = None;
+        self.storage.seek(offset)
+    }
+}
+
+impl DecodeBlock for Sparse<u64> {
+    fn decode_block(&mut self, out: &mut [u64]) -> Result<usize, StorageError> {
+        let mut carry = self.carry.take();
+        let n = decode_chunks(self, &mut carry, out)?;
+        self.carry = carry;
+        Ok(n)
+    }
+}
+
+impl<T: SparseElement> TryFrom<Storage> for Sparse<T> {
+    type Error = StorageError;
+    fn try_from(storage: Storage) -> Result<Self, Self::Error> {
+        Self::open(storage)
+    }
+}
+
+#[test]
+fn encode_sparse() {
+    use super::{run_length_encode, RawColumn, RawValue};
+
+    let mut u64s = vec![0u64; 1000];
+    for i in [3, 4, 500, 999] {
+        u64s[i] = i as u64 * 7;
+    }
+    let c = RawColumn::from(u64s.as_slice());
+    assert!(matches!(c.inner, super::RawColumnInner::U64Sparse(_)));
+    assert_eq!(c.read_u64().unwrap(), u64s);
+    assert_eq!((c.num_rows(), c.num_chunks()), (1000, 7));
+    assert_eq!(
+        (c.min(), c.max()),
+        (RawValue::U64(0), RawValue::U64(999 * 7))
+    );
+
+    let mut bytes = vec![b"none".to_vec(); 3000];
+    for i in (0..3000).step_by(37) {
+        bytes[i] = format!("row {i}").into_bytes();
+    }
+    let c = RawColumn::from(bytes.as_slice());
+    assert!(matches!(c.inner, super::RawColumnInner::BytesSparse(_)));
+    assert_eq!(c.read_bytes().unwrap(), bytes);
+    assert_eq!(c.runs().count() as u64, c.num_chunks());
+
+    // The sparse column is smaller than the run length encoded one.
+    let mut sparse = Vec::new();
+    SparseU64::encode(&mut sparse, &run_length_encode(&u64s)).unwrap();
+    let mut dense = Vec::new();
+    super::u64_generic::U16Variable::encode(&mut dense, &run_length_encode(&u64s)).unwrap();
+    assert!(sparse.len() < dense.len());
+
+    // Columns with a long run of other values are left run length encoded.
+    let mut runs = vec![0u64; 1000];
+    runs[10..30].fill(5);
+    let c = RawColumn::from(runs.as_slice());
+    assert!(matches!(c.inner, super::RawColumnInner::U64_8(_)));
+
+    // An entry past the last row is rejected.
+    let mut bad = Vec::new();
+    bad.write_u64(SparseU64::MAGIC).unwrap();
+    for v in [2, 2, 1] {
+        bad.write_u64(v).unwrap();
+    }
+    for v in [0, 0, 5, 2, 5] {
+        bad.write_unsigned(v).unwrap();
+    }
+    let c = RawColumn::decode(bad).unwrap();
+    assert!(c.read_values().is_err());
+}
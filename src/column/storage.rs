@@ -4,20 +4,45 @@
 
 mod bytes;
 mod file;
+mod http;
 use bytes::Bytes;
 use file::File;
+use http::Http;
+pub use http::HttpOptions;
 
 use super::encoding::StorageError;
+use super::Metrics;
 
+/// Where the encoded bytes of a column are read from
 #[derive(Debug, Clone)]
-pub(crate) enum Storage {
+enum Backend {
     Bytes(Bytes),
     File(File),
+    Http(Http),
+}
+
+/// The encoded bytes of a column, counting the reads made in its [`Metrics`]
+#[derive(Debug, Clone)]
+pub(crate) struct Storage {
+    backend: Backend,
+    metrics: Metrics,
 }
 
 impl From<Vec<u8>> for Storage {
     fn from(value: Vec<u8>) -> Self {
-        Storage::Bytes(value.into())
+        Storage {
+            backend: Backend::Bytes(value.into()),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+impl From<std::sync::Arc<[u8]>> for Storage {
+    fn from(value: std::sync::Arc<[u8]>) -> Self {
+        Storage {
+            backend: Backend::Bytes(value.into()),
+            metrics: Metrics::default(),
+        }
     }
 }
 
@@ -29,30 +54,114 @@ impl From<&[u8]> for Storage {
 
 impl Storage {
     pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, StorageError> {
-        Ok(Self::File(File::open(path)?))
+        Ok(Storage {
+            backend: Backend::File(File::open(path)?),
+            metrics: Metrics::default(),
+        })
+    }
+
+    /// Read the file at `url` with HTTP range requests, see [`HttpOptions`]
+    pub fn http(url: &str, options: HttpOptions) -> Result<Self, StorageError> {
+        Ok(Storage {
+            backend: Backend::Http(Http::open(url, options)?),
+            metrics: Metrics::default(),
+        })
+    }
+
+    /// Read at most `bytes` bytes ahead of small reads of a file, where zero
+    /// turns reading ahead off
+    pub(crate) fn with_read_ahead(self, bytes: u64) -> Self {
+        match self.backend {
+            Backend::File(f) => Storage {
+                backend: Backend::File(f.with_read_ahead(bytes)),
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    /// Count the reads made from this storage, and its clones, in `metrics`
+    pub(crate) fn with_metrics(self, metrics: &Metrics) -> Self {
+        Storage {
+            metrics: metrics.clone(),
+            ..self
+        }
+    }
+
+    /// The metrics counting the reads made from this storage
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// The number of encoded bytes
+    pub(crate) fn len(&self) -> u64 {
+        match &self.backend {
+            Backend::Bytes(b) => b.len(),
+            Backend::File(f) => f.len(),
+            Backend::Http(h) => h.len(),
+        }
+    }
+
+    /// The encoded bytes, if they are held in memory rather than read from
+    /// a file
+    pub(crate) fn as_slice(&self) -> Option<&[u8]> {
+        match &self.backend {
+            Backend::Bytes(b) => Some(b.as_slice()),
+            Backend::File(_) | Backend::Http(_) => None,
+        }
+    }
+
+    /// The id of the file read from, if this is a file, see [`File::id`] and
+    /// [`Http::id`]
+    pub(crate) fn file_id(&self) -> Option<u64> {
+        match &self.backend {
+            Backend::Bytes(_) => None,
+            Backend::File(f) => Some(f.id()),
+            Backend::Http(h) => Some(h.id()),
+        }
     }
 }
 
 impl TryFrom<std::fs::File> for Storage {
     type Error = StorageError;
     fn try_from(value: std::fs::File) -> Result<Self, Self::Error> {
-        Ok(Self::File(File::try_from(value)?))
+        Ok(Storage {
+            backend: Backend::File(File::try_from(value)?),
+            metrics: Metrics::default(),
+        })
     }
 }
 
 impl super::encoding::ReadEncoded for Storage {
     fn seek(&mut self, offset: u64) -> Result<(), super::encoding::StorageError> {
-        match self {
-            Storage::Bytes(b) => b.seek(offset),
-            Storage::File(f) => f.seek(offset),
+        self.metrics.record_seek();
+        match &mut self.backend {
+            Backend::Bytes(b) => b.seek(offset),
+            Backend::File(f) => f.seek(offset),
+            Backend::Http(h) => h.seek(offset),
         }
     }
 
     fn tell(&self) -> Result<u64, super::encoding::StorageError> {
-        match self {
-            Storage::Bytes(b) => b.tell(),
-            Storage::File(f) => f.tell(),
+        match &self.backend {
+            Backend::Bytes(b) => b.tell(),
+            Backend::File(f) => f.tell(),
+            Backend::Http(h) => h.tell(),
+        }
+    }
+
+    /// Move past bytes as they are read, which is not counted as a seek
+    fn advance(&mut self, size: u64) -> Result<u64, StorageError> {
+        let offset = self.tell()?;
+        let end = offset
+            .checked_add(size)
+            .ok_or(StorageError::OutOfBounds("offset overflows"))?;
+        match &mut self.backend {
+            Backend::Bytes(b) => b.seek(end)?,
+            Backend::File(f) => f.seek(end)?,
+            Backend::Http(h) => h.seek(end)?,
         }
+        Ok(offset)
     }
 
     fn read_exact_at(
@@ -60,9 +169,12 @@ impl super::encoding::ReadEncoded for Storage {
         buf: &mut [u8],
         offset: u64,
     ) -> Result<(), super::encoding::StorageError> {
-        match self {
-            Storage::Bytes(b) => b.read_exact_at(buf, offset),
-            Storage::File(f) => f.read_exact_at(buf, offset),
+        match &self.backend {
+            Backend::Bytes(b) => b.read_exact_at(buf, offset)?,
+            Backend::File(f) => f.read_exact_at(buf, offset)?,
+            Backend::Http(h) => h.read_exact_at(buf, offset)?,
         }
+        self.metrics.record_bytes_read(buf.len() as u64);
+        Ok(())
     }
 }
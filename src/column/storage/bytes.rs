@@ -20,12 +20,30 @@ impl From<Vec<u8>> for Bytes {
     }
 }
 
+impl From<Arc<[u8]>> for Bytes {
+    fn from(buffer: Arc<[u8]>) -> Self {
+        Bytes { buffer, offset: 0 }
+    }
+}
+
 impl From<&[u8]> for Bytes {
     fn from(value: &[u8]) -> Self {
         Self::from(value.to_vec())
     }
 }
 
+impl Bytes {
+    /// The number of bytes in the buffer
+    pub fn len(&self) -> u64 {
+        self.buffer.len() as u64
+    }
+
+    /// The whole buffer
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
 impl crate::column::encoding::ReadEncoded for Bytes {
     fn seek(&mut self, offset: u64) -> Result<(), crate::column::encoding::StorageError> {
         if offset <= self.buffer.len() as u64 {
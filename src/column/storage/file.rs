@@ -1,32 +1,124 @@
 //! A byte buffer for reading
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use crate::column::encoding::StorageError;
 
-/// A read-only file that supports concurrent reads. (unix-only)
-#[derive(Debug, Clone)]
+/// The size of the window read after a read that does not follow on from
+/// the one before
+const MIN_WINDOW: u64 = 4 << 10;
+
+/// The most bytes read ahead, unless set with [`File::with_read_ahead`]
+const DEFAULT_READ_AHEAD: u64 = 256 << 10;
+
+/// A read-only file that supports concurrent reads.
+///
+/// Small reads are served from a window of the file read ahead of them.
+/// While each read follows on from the one before, the window doubles in
+/// size up to the read-ahead limit, so a sequential scan makes few reads of
+/// the file however small its reads are.  Each clone has its own window.
+///
+/// Reads are only supported on unix.  Elsewhere, such as on wasm32 where
+/// columns are fetched into memory instead, reads fail as unsupported.
+#[derive(Debug)]
 pub struct File {
     file: Arc<std::fs::File>,
     offset: u64,
     length: u64,
+    /// Identifies the file, see [`File::id`]
+    id: u64,
+    /// The most bytes read ahead, where zero reads only what is asked for
+    read_ahead: u64,
+    window: Mutex<Window>,
+}
+
+/// Bytes read ahead from a file
+#[derive(Debug)]
+struct Window {
+    /// The offset of the bytes within the file
+    start: u64,
+    bytes: Vec<u8>,
+    /// The size of the next window to read
+    size: u64,
+    /// Where the last read ended
+    last_end: u64,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window {
+            start: 0,
+            bytes: Vec::new(),
+            size: MIN_WINDOW,
+            last_end: 0,
+        }
+    }
+}
+
+impl Clone for File {
+    fn clone(&self) -> Self {
+        File {
+            file: self.file.clone(),
+            offset: self.offset,
+            length: self.length,
+            id: self.id,
+            read_ahead: self.read_ahead,
+            window: Mutex::default(),
+        }
+    }
 }
 
 impl File {
     pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, StorageError> {
         Self::try_from(std::fs::File::open(path)?)
     }
+
+    /// Read at most `bytes` bytes ahead of small reads, where zero turns
+    /// reading ahead off
+    pub fn with_read_ahead(self, bytes: u64) -> Self {
+        File {
+            read_ahead: bytes,
+            window: Mutex::default(),
+            ..self
+        }
+    }
+
+    /// An id of the file, made from its inode, size and modification time,
+    /// so a file written in place of another gets a different id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The number of bytes in the file
+    pub fn len(&self) -> u64 {
+        self.length
+    }
 }
 
 impl TryFrom<std::fs::File> for File {
     type Error = StorageError;
     fn try_from(value: std::fs::File) -> Result<Self, Self::Error> {
+        use std::hash::{Hash, Hasher};
+
         let file = Arc::new(value);
-        let length = file.metadata()?.len();
+        let metadata = file.metadata()?;
+        let length = metadata.len();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.dev(), metadata.ino(), length).hash(&mut hasher);
+            (metadata.mtime(), metadata.mtime_nsec()).hash(&mut hasher);
+        }
+        #[cfg(not(unix))]
+        (length, metadata.modified().ok()).hash(&mut hasher);
         Ok(File {
             file,
             length,
             offset: 0,
+            id: hasher.finish(),
+            read_ahead: DEFAULT_READ_AHEAD,
+            window: Mutex::default(),
         })
     }
 }
@@ -48,15 +140,100 @@ impl crate::column::encoding::ReadEncoded for File {
         buf: &mut [u8],
         offset: u64,
     ) -> Result<(), crate::column::encoding::StorageError> {
-        if offset + buf.len() as u64 > self.length {
-            Err(StorageError::Io(std::io::Error::new(
+        let end = offset + buf.len() as u64;
+        if end > self.length {
+            return Err(StorageError::Io(std::io::Error::new(
                 std::io::ErrorKind::UnexpectedEof,
                 "failed to read_exact",
-            )))
+            )));
+        }
+        if self.read_ahead == 0 {
+            return self.read_file(buf, offset);
+        }
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let in_window = offset.checked_sub(window.start).map(|from| from as usize);
+        if let Some(from) = in_window.filter(|from| from + buf.len() <= window.bytes.len()) {
+            buf.copy_from_slice(&window.bytes[from..from + buf.len()]);
+            window.last_end = end;
+            return Ok(());
+        }
+        window.size = if offset == window.last_end {
+            (window.size * 2).min(self.read_ahead)
         } else {
+            MIN_WINDOW.min(self.read_ahead)
+        };
+        window.last_end = end;
+        if buf.len() as u64 >= window.size {
+            return self.read_file(buf, offset);
+        }
+        let size = window.size.min(self.length - offset) as usize;
+        let mut bytes = std::mem::take(&mut window.bytes);
+        bytes.resize(size, 0);
+        self.read_file(&mut bytes, offset)?;
+        buf.copy_from_slice(&bytes[..buf.len()]);
+        window.start = offset;
+        window.bytes = bytes;
+        Ok(())
+    }
+}
+
+impl File {
+    /// Read from the file itself, bypassing the window
+    fn read_file(&self, buf: &mut [u8], offset: u64) -> Result<(), StorageError> {
+        #[cfg(unix)]
+        {
             use std::os::unix::fs::FileExt;
             self.file.read_exact_at(buf, offset)?;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(target: "equilia::io", bytes = buf.len(), offset, "read");
             Ok(())
         }
+        #[cfg(not(unix))]
+        Err(StorageError::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "files are only read on unix",
+        )))
     }
 }
+
+#[test]
+fn read_ahead() {
+    use crate::column::encoding::ReadEncoded;
+    use std::io::Write;
+
+    let bytes = (0..1_000_000u32)
+        .map(|i| (i * 7 % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut tmp = tempfile::NamedTempFile::new().unwrap();
+    tmp.write_all(&bytes).unwrap();
+    let file = File::open(tmp.path()).unwrap();
+
+    // A sequential scan doubles the window up to the limit.
+    let mut scanned = Vec::new();
+    let mut reader = file.clone();
+    let mut buf = [0; 10];
+    for _ in 0..bytes.len() / buf.len() {
+        reader.read_exact(&mut buf).unwrap();
+        scanned.extend_from_slice(&buf);
+    }
+    assert_eq!(scanned, bytes);
+    assert_eq!(reader.window.lock().unwrap().size, DEFAULT_READ_AHEAD);
+
+    // Jumping about shrinks it again.
+    let mut big = vec![0; 5000];
+    for offset in [999_990, 3, 500_000, 4, 20] {
+        reader.read_exact_at(&mut buf, offset).unwrap();
+        assert_eq!(buf, bytes[offset as usize..offset as usize + 10]);
+        reader.read_exact_at(&mut big, offset / 2).unwrap();
+        assert_eq!(big, bytes[offset as usize / 2..offset as usize / 2 + 5000]);
+    }
+    assert_eq!(reader.window.lock().unwrap().size, MIN_WINDOW);
+    assert!(reader.read_exact_at(&mut buf, 999_995).is_err());
+
+    // Clones have windows of their own.
+    assert_eq!(file.window.lock().unwrap().bytes, b"");
+    let off = file.with_read_ahead(0);
+    off.read_exact_at(&mut buf, 7).unwrap();
+    assert_eq!(buf, bytes[7..17]);
+    assert_eq!(off.window.lock().unwrap().bytes, b"");
+}
@@ -0,0 +1,707 @@
+//! A column file read over HTTP, so that static hosting can serve columns
+//! to remote readers.
+//!
+//! The file is read in blocks of [`HttpOptions::read_ahead`] bytes, each
+//! fetched with an HTTP range request, so reading a few bytes also fetches
+//! the bytes after them.  Fetched blocks are kept in a least recently used
+//! cache shared by the clones of the storage, up to
+//! [`HttpOptions::cache_bytes`].  A server that ignores ranges sends the
+//! whole file instead, which is cached as blocks as far as it fits.
+//!
+//! With the `https` feature, requests are sent with ureq, which reads
+//! `https://` URLs too.  Without it, only plain `http://` URLs can be read,
+//! over connections made here.  Either way, redirects are followed.
+//!
+//! Every block must come from the file that was opened: a response giving
+//! another length, or another ETag or modification time, than the first
+//! one did is an error, rather than mixing the bytes of two files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::column::encoding::StorageError;
+
+/// Options controlling how a column is read over HTTP, see
+/// [`RawColumn::open_url`](crate::RawColumn::open_url)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpOptions {
+    read_ahead: u64,
+    cache_bytes: u64,
+    timeout: Duration,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            read_ahead: 64 << 10,
+            cache_bytes: 16 << 20,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Fetch at least `bytes` bytes with each request.
+    ///
+    /// Each request costs a round trip, so a larger block makes fewer of
+    /// them when reading through a column, at the cost of fetching bytes
+    /// that may never be read.
+    pub fn read_ahead(self, bytes: u64) -> Self {
+        HttpOptions {
+            read_ahead: bytes.max(1),
+            ..self
+        }
+    }
+
+    /// Keep at most `bytes` bytes of fetched blocks, beyond the one being
+    /// read
+    pub fn cache_bytes(self, bytes: u64) -> Self {
+        HttpOptions {
+            cache_bytes: bytes,
+            ..self
+        }
+    }
+
+    /// Give up on connecting to the server, or on waiting for it to send or
+    /// accept more bytes, after `timeout`
+    pub fn timeout(self, timeout: Duration) -> Self {
+        HttpOptions { timeout, ..self }
+    }
+}
+
+/// The most redirects followed by one request
+const MAX_REDIRECTS: usize = 5;
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// What a server answered to a request
+struct Reply {
+    status: u16,
+    /// The headers, by their names in lower case
+    headers: HashMap<String, String>,
+    body: Box<dyn Read>,
+}
+
+/// Sends requests, with ureq when the `https` feature is on
+#[derive(Debug, Clone)]
+struct Client {
+    #[cfg(not(feature = "https"))]
+    timeout: Duration,
+    #[cfg(feature = "https")]
+    agent: ureq::Agent,
+}
+
+impl Client {
+    fn new(timeout: Duration) -> Self {
+        Client {
+            #[cfg(not(feature = "https"))]
+            timeout,
+            #[cfg(feature = "https")]
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(timeout)
+                .timeout_read(timeout)
+                .timeout_write(timeout)
+                .redirects(MAX_REDIRECTS as u32)
+                .build(),
+        }
+    }
+
+    /// Get the `range` of bytes of the file at `url`
+    #[cfg(feature = "https")]
+    fn get(&self, url: &str, range: &str) -> Result<Reply, StorageError> {
+        let response = match self.agent.get(url).set("Range", range).call() {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(ureq::Error::Transport(e)) => {
+                let kind = match e.kind() {
+                    ureq::ErrorKind::InvalidUrl | ureq::ErrorKind::UnknownScheme => {
+                        std::io::ErrorKind::InvalidInput
+                    }
+                    ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed => {
+                        std::io::ErrorKind::NotConnected
+                    }
+                    _ => std::io::ErrorKind::Other,
+                };
+                return Err(std::io::Error::new(kind, e.to_string()).into());
+            }
+        };
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name.to_ascii_lowercase(), value))
+            })
+            .collect();
+        Ok(Reply {
+            status: response.status(),
+            headers,
+            body: Box::new(response.into_reader()),
+        })
+    }
+
+    /// Get the `range` of bytes of the file at `url`
+    #[cfg(not(feature = "https"))]
+    fn get(&self, url: &str, range: &str) -> Result<Reply, StorageError> {
+        use std::io::{BufReader, Write};
+
+        let mut url = Url::parse(url)?;
+        for _ in 0..=MAX_REDIRECTS {
+            let mut stream = url.connect(self.timeout)?;
+            write!(
+                stream,
+                "GET {} HTTP/1.1\r\nHost: {}\r\nRange: {range}\r\nConnection: close\r\n\r\n",
+                url.path, url.authority,
+            )?;
+            let mut body = BufReader::new(stream);
+            let (status, headers) = read_head(&mut body)?;
+            if let (301 | 302 | 303 | 307 | 308, Some(location)) = (status, headers.get("location"))
+            {
+                url = url.join(location)?;
+                continue;
+            }
+            if headers.get("transfer-encoding").map(|e| e.as_str()) == Some("chunked") {
+                return Err(invalid("chunked HTTP responses are not supported".to_string()).into());
+            }
+            return Ok(Reply {
+                status,
+                headers,
+                body: Box::new(body),
+            });
+        }
+        Err(invalid(format!("too many redirects from {}", url.path)).into())
+    }
+}
+
+/// Where a file is served from, when it is fetched without ureq
+#[cfg(not(feature = "https"))]
+#[derive(Debug)]
+struct Url {
+    /// The host and port, as given in the URL
+    authority: String,
+    path: String,
+}
+
+#[cfg(not(feature = "https"))]
+impl Url {
+    fn parse(url: &str) -> Result<Self, StorageError> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("only http:// URLs can be read without the https feature: {url}"),
+            )
+            .into());
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            return Err(invalid(format!("no host in {url}")).into());
+        }
+        Ok(Url {
+            authority: authority.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// The URL a redirect to `location` leads to
+    fn join(&self, location: &str) -> Result<Self, StorageError> {
+        if location.contains("://") {
+            return Url::parse(location);
+        }
+        let path = if location.starts_with('/') {
+            location.to_string()
+        } else {
+            let dir = &self.path[..self.path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{dir}{location}")
+        };
+        Ok(Url {
+            authority: self.authority.clone(),
+            path,
+        })
+    }
+
+    /// Connect to the server, trying each of its addresses in turn, where
+    /// the port defaults to 80
+    fn connect(&self, timeout: Duration) -> Result<std::net::TcpStream, StorageError> {
+        use std::net::{TcpStream, ToSocketAddrs};
+
+        let address = if self.authority.ends_with(']') || !self.authority.contains(':') {
+            format!("{}:80", self.authority)
+        } else {
+            self.authority.clone()
+        };
+        let mut error = None;
+        for address in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error
+            .unwrap_or_else(|| invalid(format!("no address for {}", self.authority)))
+            .into())
+    }
+}
+
+/// Read the status and headers of a response, refusing a head of more than
+/// 64 KiB
+#[cfg(not(feature = "https"))]
+fn read_head(
+    reader: &mut impl std::io::BufRead,
+) -> Result<(u16, HashMap<String, String>), StorageError> {
+    use std::io::BufRead;
+
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let room = (64 << 10) - head.len() as u64;
+        if room == 0 || reader.take(room).read_until(b'\n', &mut head)? == 0 {
+            return Err(invalid("truncated HTTP response".to_string()).into());
+        }
+    }
+    let head = std::str::from_utf8(&head)
+        .map_err(|_| invalid("HTTP response head is not UTF-8".to_string()))?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|l| l.split(' ').nth(1))
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| invalid(format!("bad HTTP status line in {head:?}")))?;
+    let mut headers = HashMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok((status, headers))
+}
+
+/// The answer to a range request
+struct Response {
+    /// Where the body starts within the file
+    start: u64,
+    /// The bytes from `start`
+    body: Vec<u8>,
+    /// The length of the whole file
+    length: u64,
+    /// Identifies the version of the file, if the server says
+    version: String,
+}
+
+/// Fetch the bytes from `start` up to `end` of the file at `url`, or fewer
+/// if the file ends first.
+///
+/// A server that ignores the range sends the file from its start, of which
+/// at most `keep` bytes, or `end` if that is more, are read.
+fn get_range(
+    client: &Client,
+    url: &str,
+    start: u64,
+    end: u64,
+    keep: u64,
+) -> Result<Response, StorageError> {
+    let reply = client.get(url, &format!("bytes={}-{}", start, end - 1))?;
+    let headers = &reply.headers;
+    let version = ["etag", "last-modified"]
+        .iter()
+        .filter_map(|h| headers.get(*h))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let content_length = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<u64>().ok());
+    // The length of the file follows the slash of the Content-Range, which
+    // starts with the range sent.
+    let content_range = headers.get("content-range").and_then(|r| {
+        let (range, total) = r.strip_prefix("bytes ")?.split_once('/')?;
+        let first = range.split_once('-')?.0.parse::<u64>().ok()?;
+        Some((first, total.parse::<u64>().ok()?))
+    });
+    let read = |limit: u64| -> Result<Vec<u8>, StorageError> {
+        let limit = content_length.map_or(limit, |l| l.min(limit));
+        let mut body = Vec::new();
+        reply.body.take(limit).read_to_end(&mut body)?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            target: "equilia::io",
+            bytes = body.len(),
+            start,
+            end,
+            url,
+            "fetched"
+        );
+        Ok(body)
+    };
+    match (reply.status, content_range) {
+        (206, Some((first, length))) if first == start => Ok(Response {
+            start,
+            body: read(end - start)?,
+            length,
+            version,
+        }),
+        // A server ignoring the range sends the whole file.
+        (200, _) => {
+            let keep = keep.max(end);
+            let body = read(keep)?;
+            let length = match content_length {
+                Some(length) => length,
+                None if (body.len() as u64) < keep => body.len() as u64,
+                None => return Err(invalid(format!("no length given for {url}")).into()),
+            };
+            Ok(Response {
+                start: 0,
+                body,
+                length,
+                version,
+            })
+        }
+        // Nothing of an empty file can be in range.
+        (416, Some((_, 0))) => Ok(Response {
+            start,
+            body: Vec::new(),
+            length: 0,
+            version,
+        }),
+        (status, _) => {
+            let kind = match status {
+                404 | 410 => std::io::ErrorKind::NotFound,
+                401 | 403 => std::io::ErrorKind::PermissionDenied,
+                _ => std::io::ErrorKind::InvalidData,
+            };
+            let message = format!("HTTP status {status} reading {url}");
+            Err(std::io::Error::new(kind, message).into())
+        }
+    }
+}
+
+/// A least recently used cache of fetched blocks
+#[derive(Debug, Default)]
+struct Blocks {
+    /// The bytes the blocks hold
+    used: u64,
+    /// Counts the uses of blocks, to order them by when they were last used
+    tick: u64,
+    /// Each block, by its index, and when it was last used
+    blocks: HashMap<u64, (Arc<[u8]>, u64)>,
+    /// The index of each block, by when it was last used
+    by_use: BTreeMap<u64, u64>,
+}
+
+impl Blocks {
+    fn get(&mut self, index: u64) -> Option<Arc<[u8]>> {
+        let (block, last_used) = self.blocks.get_mut(&index)?;
+        self.by_use.remove(last_used);
+        self.tick += 1;
+        *last_used = self.tick;
+        self.by_use.insert(self.tick, index);
+        Some(block.clone())
+    }
+
+    fn insert(&mut self, index: u64, block: Arc<[u8]>, capacity: u64) {
+        self.tick += 1;
+        self.used += block.len() as u64;
+        if let Some((old, last_used)) = self.blocks.insert(index, (block, self.tick)) {
+            self.used -= old.len() as u64;
+            self.by_use.remove(&last_used);
+        }
+        self.by_use.insert(self.tick, index);
+        while self.used > capacity && self.by_use.len() > 1 {
+            let Some((&tick, &oldest)) = self.by_use.iter().next() else {
+                break;
+            };
+            self.by_use.remove(&tick);
+            if let Some((old, _)) = self.blocks.remove(&oldest) {
+                self.used -= old.len() as u64;
+            }
+        }
+    }
+}
+
+/// A read-only file served over HTTP
+#[derive(Debug, Clone)]
+pub struct Http {
+    url: Arc<str>,
+    options: HttpOptions,
+    client: Client,
+    blocks: Arc<Mutex<Blocks>>,
+    offset: u64,
+    length: u64,
+    /// The ETag and modification time the server gave for the file
+    version: Arc<str>,
+    /// Identifies the file, see [`Http::id`]
+    id: u64,
+}
+
+impl Http {
+    /// Open the file at `url`, fetching its first block
+    pub fn open(url: &str, options: HttpOptions) -> Result<Self, StorageError> {
+        use std::hash::{Hash, Hasher};
+
+        let client = Client::new(options.timeout);
+        let first = get_range(&client, url, 0, options.read_ahead, options.cache_bytes)?;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (url, first.length, &first.version).hash(&mut hasher);
+        let http = Http {
+            url: url.into(),
+            options,
+            client,
+            blocks: Arc::default(),
+            offset: 0,
+            length: first.length,
+            version: first.version.as_str().into(),
+            id: hasher.finish(),
+        };
+        http.cache(first, 0);
+        Ok(http)
+    }
+
+    /// An id of the file, made from its URL, size and any version the
+    /// server gives, such as an ETag
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The number of bytes in the file
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Blocks> {
+        self.blocks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The number of bytes in the block at `index`
+    fn block_len(&self, index: u64) -> u64 {
+        let size = self.options.read_ahead;
+        size.min(self.length.saturating_sub(index * size))
+    }
+
+    /// Cache the whole blocks of `fetched`, returning those from `first` on
+    fn cache(&self, fetched: Response, first: u64) -> Vec<Arc<[u8]>> {
+        let size = self.options.read_ahead;
+        let mut out = Vec::new();
+        let mut cache = self.lock();
+        for (i, block) in fetched.body.chunks(size as usize).enumerate() {
+            let index = fetched.start / size + i as u64;
+            if block.len() as u64 != self.block_len(index) {
+                break;
+            }
+            let block: Arc<[u8]> = block.into();
+            cache.insert(index, block.clone(), self.options.cache_bytes);
+            if index >= first {
+                out.push(block);
+            }
+        }
+        out
+    }
+
+    /// The blocks from `first` to `last`, fetching with one request those
+    /// from the first that is not cached.
+    ///
+    /// The cache is not locked while fetching, so two readers may fetch the
+    /// same block, but neither waits on the other's request.
+    fn blocks(&self, first: u64, last: u64) -> Result<Vec<Arc<[u8]>>, StorageError> {
+        let size = self.options.read_ahead;
+        let mut out = Vec::new();
+        {
+            let mut cache = self.lock();
+            for index in first..=last {
+                match cache.get(index) {
+                    Some(block) => out.push(block),
+                    None => break,
+                }
+            }
+        }
+        let missing = first + out.len() as u64;
+        if missing <= last {
+            let start = missing * size;
+            let end = ((last + 1) * size).min(self.length);
+            let fetched = get_range(
+                &self.client,
+                &self.url,
+                start,
+                end,
+                self.options.cache_bytes,
+            )?;
+            if fetched.length != self.length || *fetched.version != *self.version {
+                let message = format!("{} changed since it was opened", self.url);
+                return Err(invalid(message).into());
+            }
+            out.extend(self.cache(fetched, missing));
+            out.truncate((last + 1 - first) as usize);
+            if (out.len() as u64) < last + 1 - first {
+                return Err(invalid(format!("short HTTP response for {}", self.url)).into());
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl crate::column::encoding::ReadEncoded for Http {
+    fn seek(&mut self, offset: u64) -> Result<(), StorageError> {
+        if offset <= self.length {
+            self.offset = offset;
+            Ok(())
+        } else {
+            Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "failed to seek").into())
+        }
+    }
+    fn tell(&self) -> Result<u64, StorageError> {
+        Ok(self.offset)
+    }
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<(), StorageError> {
+        if offset + buf.len() as u64 > self.length {
+            return Err(StorageError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "failed to read_exact",
+            )));
+        }
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let size = self.options.read_ahead;
+        let first = offset / size;
+        let last = (offset + buf.len() as u64 - 1) / size;
+        let mut filled = 0;
+        for (index, block) in (first..=last).zip(self.blocks(first, last)?) {
+            let from = (offset + filled as u64 - index * size) as usize;
+            let n = (block.len() - from).min(buf.len() - filled);
+            buf[filled..filled + n].copy_from_slice(&block[from..from + n]);
+            filled += n;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn http() {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::RawColumn;
+
+    let values = (0..20_000u64).map(|i| i * i % 1000).collect::<Vec<_>>();
+    let mut file = Vec::new();
+    RawColumn::write_u64(&mut file, &values).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests = Arc::new(AtomicU64::new(0));
+    let counted = requests.clone();
+    let etag = Arc::new(AtomicU64::new(1));
+    let tagged = etag.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            counted.fetch_add(1, Ordering::Relaxed);
+            let request = String::from_utf8(request).unwrap();
+            if request.starts_with("GET /moved ") {
+                write!(
+                    stream,
+                    "HTTP/1.1 302 Found\r\nLocation: /column\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                continue;
+            }
+            // This route ignores ranges, sending the whole file.
+            if request.starts_with("GET /whole ") {
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    file.len()
+                )
+                .unwrap();
+                stream.write_all(&file).unwrap();
+                continue;
+            }
+            if !request.starts_with("GET /column ") {
+                write!(
+                    stream,
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                continue;
+            }
+            let range = request.split("Range: bytes=").nth(1).unwrap();
+            let range = range.split("\r\n").next().unwrap();
+            let (start, end) = range.split_once('-').unwrap();
+            let start = start.parse::<usize>().unwrap();
+            let end = end.parse::<usize>().unwrap().min(file.len() - 1);
+            let body = &file[start..=end];
+            write!(
+                stream,
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {start}-{end}/{}\r\nETag: \"{}\"\r\nConnection: close\r\n\r\n",
+                body.len(),
+                file.len(),
+                tagged.load(Ordering::Relaxed)
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        }
+    });
+
+    let url = format!("http://{address}/column");
+    let column = RawColumn::open_url(&url, HttpOptions::default().read_ahead(1024)).unwrap();
+    assert_eq!(column.read_u64().unwrap(), values);
+    let fetched = requests.load(Ordering::Relaxed);
+    assert!(
+        fetched <= column.byte_len() / 1024 + 1,
+        "{fetched} requests"
+    );
+    // Every block is cached, so reading again fetches nothing.
+    assert_eq!(column.read_u64().unwrap(), values);
+    assert_eq!(requests.load(Ordering::Relaxed), fetched);
+
+    // With a small cache, blocks are fetched again.
+    let options = HttpOptions::default().read_ahead(100).cache_bytes(200);
+    let column = RawColumn::open_url(&url, options).unwrap();
+    assert_eq!(column.read_u64().unwrap(), values);
+
+    assert!(RawColumn::open_url(&format!("http://{address}/missing"), options).is_err());
+    assert!(RawColumn::open_url("ftp://example.com/column", options).is_err());
+
+    // Redirects are followed.
+    let moved = format!("http://{address}/moved");
+    let column = RawColumn::open_url(&moved, options).unwrap();
+    assert_eq!(column.read_u64().unwrap(), values);
+
+    // A whole file sent without ranges is cached as blocks when it is
+    // opened, so it is fetched only once.
+    let whole = format!("http://{address}/whole");
+    let options = HttpOptions::default().read_ahead(1024);
+    let fetched = requests.load(Ordering::Relaxed);
+    let column = RawColumn::open_url(&whole, options).unwrap();
+    assert_eq!(column.read_u64().unwrap(), values);
+    assert_eq!(requests.load(Ordering::Relaxed), fetched + 1);
+    // Past the cache, each request is still bounded by the cache size.
+    let options = options.read_ahead(100).cache_bytes(200);
+    let column = RawColumn::open_url(&whole, options).unwrap();
+    assert_eq!(column.read_u64().unwrap(), values);
+
+    // A file that changes after it is opened cannot be read any further.
+    let column = RawColumn::open_url(&url, options).unwrap();
+    etag.store(2, Ordering::Relaxed);
+    let error = column.read_u64().unwrap_err();
+    assert!(error.to_string().contains("changed since"), "{error}");
+
+    // A server that never answers is given up on.
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/column", silent.local_addr().unwrap());
+    let options = options.timeout(Duration::from_millis(50));
+    assert!(RawColumn::open_url(&url, options).is_err());
+    drop(silent);
+}
@@ -1,7 +1,7 @@
 //! Will be private
 use super::{
-    encoding::BitWidth, Chunk, IsRawColumn, ReadEncoded, Storage, StorageError, WriteEncoded,
-    U64_GENERIC_MAGIC,
+    decode_chunks, encoding::BitWidth, Chunk, DecodeBlock, IsRawColumn, ReadEncoded, Storage,
+    StorageError, WriteEncoded, U64_GENERIC_MAGIC,
 };
 
 #[derive(Clone)]
@@ -12,6 +12,8 @@ pub(crate) struct U64<const F: u64> {
     n_chunks: u64,
     v_max: u64,
     v_min: u64,
+    /// The rest of a chunk only partly decoded by [`DecodeBlock::decode_block`]
+    carry: Option<Chunk<u64>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -153,14 +155,20 @@ impl<const F: u64> Iterator for U64<F> {
 impl<const F: u64> U64<F> {
     pub(crate) const MAGIC: u64 = F + U64_GENERIC_MAGIC;
     fn transposed_next(&mut self) -> Result<Option<Chunk<u64>>, StorageError> {
+        if let Some(chunk) = self.carry.take() {
+            return Ok(Some(chunk));
+        }
         if self.current_row == self.n_rows {
             return Ok(None);
         }
         let format = Format::from_bytes(F)?;
         let num = self.storage.read_bitwidth(format.runlength)?;
-        let value = self.v_min + self.storage.read_bitwidth(format.value)? as u64;
+        let value = self
+            .v_min
+            .checked_add(self.storage.read_bitwidth(format.value)?)
+            .ok_or(StorageError::OutOfBounds("value overflows"))?;
         let current_row = self.current_row;
-        self.current_row = current_row + num;
+        self.current_row = super::chunk_end(current_row, num, self.n_rows)?;
 
         Ok(Some(Chunk {
             value,
@@ -225,6 +233,7 @@ impl<const F: u64> IsRawColumn for U64<F> {
             n_rows,
             v_max,
             v_min,
+            carry: None,
         })
     }
 
@@ -239,10 +248,72 @@ impl<const F: u64> IsRawColumn for U64<F> {
         _value: impl AsRef<Self::Element>,
     ) -> Result<(), StorageError> {
         self.current_row = row_number;
+        self.carry = None;
         self.storage.seek(offset)
     }
 }
 
+/// Add `base` to each big endian value of `width` bytes in `bytes`, writing
+/// the results to `out`, or fail if a sum overflows.
+///
+/// Each width gets a loop of its own over fixed size lanes.
+fn unpack_be(out: &mut [u64], bytes: &[u8], width: usize, base: u64) -> Result<(), StorageError> {
+    let add = |v: u64| {
+        base.checked_add(v)
+            .ok_or(StorageError::OutOfBounds("value overflows"))
+    };
+    match width {
+        1 => {
+            for (v, b) in out.iter_mut().zip(bytes) {
+                *v = add(*b as u64)?;
+            }
+        }
+        2 => {
+            for (v, b) in out.iter_mut().zip(bytes.chunks_exact(2)) {
+                *v = add(u16::from_be_bytes([b[0], b[1]]) as u64)?;
+            }
+        }
+        4 => {
+            for (v, b) in out.iter_mut().zip(bytes.chunks_exact(4)) {
+                *v = add(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64)?;
+            }
+        }
+        _ => {
+            for (v, b) in out.iter_mut().zip(bytes.chunks_exact(8)) {
+                let mut be = [0; 8];
+                be.copy_from_slice(b);
+                *v = add(u64::from_be_bytes(be))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<const F: u64> DecodeBlock for U64<F> {
+    fn decode_block(&mut self, out: &mut [u64]) -> Result<usize, StorageError> {
+        let format = Format::from_bytes(F)?;
+        let width = match format.value {
+            BitWidth::U8 => 1,
+            BitWidth::U16 => 2,
+            BitWidth::U32 => 4,
+            BitWidth::U64 => 8,
+            _ => 0,
+        };
+        if format.runlength != BitWidth::IsOne || width == 0 || self.carry.is_some() {
+            let mut carry = self.carry.take();
+            let n = decode_chunks(self, &mut carry, out)?;
+            self.carry = carry;
+            return Ok(n);
+        }
+        let n = out.len().min((self.n_rows - self.current_row) as usize);
+        let mut bytes = vec![0; n * width];
+        self.storage.read_exact(&mut bytes)?;
+        unpack_be(&mut out[..n], &bytes, width, self.v_min)?;
+        self.current_row += n as u64;
+        Ok(n)
+    }
+}
+
 impl<const F: u64> TryFrom<Storage> for U64<F> {
     type Error = StorageError;
     fn try_from(storage: Storage) -> Result<Self, Self::Error> {
@@ -521,3 +592,62 @@ fn encode_u8_1() {
     let c = RawColumn::try_from(f).unwrap();
     assert_eq!(c.read_u64().unwrap().as_slice(), &bools);
 }
+
+#[test]
+fn decode_blocks() {
+    use super::{bitpacked::BitPacked, sparse::SparseU64};
+
+    fn check<C: DecodeBlock + for<'a> From<&'a [u64]>>(vals: &[u64]) {
+        let column = C::from(vals);
+        for size in [1, 3, 1000] {
+            let mut c = column.clone();
+            let mut block = vec![0; size];
+            let mut decoded = Vec::new();
+            loop {
+                let n = c.decode_block(&mut block).unwrap();
+                decoded.extend_from_slice(&block[..n]);
+                if n < size {
+                    break;
+                }
+            }
+            assert_eq!(decoded, vals, "blocks of {size}");
+        }
+        // Iterating picks up where decoding a block left off.
+        let mut c = column;
+        let mut block = [0; 5];
+        c.decode_block(&mut block).unwrap();
+        let rest = c
+            .flat_map(|chunk| {
+                let chunk = chunk.unwrap();
+                chunk.range.map(move |_| chunk.value)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rest, vals[5..]);
+    }
+
+    let runs = [3, 3, 3, 3, 3, 3, 7, 7, 1, 1, 1, 1, 1, 9];
+    let distinct = [3, 1 << 12, 7, 1, 9, 40, 2, 600, 5];
+    check::<U8Variable>(&runs);
+    check::<U8One>(&distinct.map(|v| v % 256));
+    check::<U16One>(&distinct);
+    check::<U32Variable>(&runs);
+    check::<U32One>(&distinct);
+    check::<VariableOne>(&distinct.map(|v| v << 40));
+    check::<VariableVariable>(&runs);
+    check::<BitPacked>(&(0..3000).map(|i| i * i % 77).collect::<Vec<_>>());
+    let mut sparse = [0; 40];
+    sparse[3] = 8;
+    sparse[30] = 2;
+    check::<SparseU64>(&sparse);
+}
+
+#[test]
+fn unpack_overflow() {
+    let mut out = [0; 2];
+    unpack_be(&mut out, &[1, 2], 1, 7).unwrap();
+    assert_eq!(out, [8, 9]);
+    assert!(matches!(
+        unpack_be(&mut out, &[0, 1, 0, 2], 2, u64::MAX - 1),
+        Err(StorageError::OutOfBounds(_))
+    ));
+}
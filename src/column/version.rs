@@ -0,0 +1,150 @@
+//! Versions of the encoding of columns.
+//!
+//! Every column starts with a header holding the version of the encoding it
+//! was written with, ahead of the magic of its format:
+//!
+//! ```text
+//! COLUMN_MAGIC version FORMAT_MAGIC ...
+//! ```
+//!
+//! Columns written before versions were recorded start with the magic of
+//! their format, and are read as [`FormatVersion::Unversioned`].  Changing
+//! how any format is encoded needs a new version, so that columns written
+//! in an older one are still read the way they were written, or at least
+//! fail to open rather than decoding garbage.  [`migrate_column`] rewrites a
+//! column file in the current version.
+
+use std::path::Path;
+
+use super::encoding::{ReadEncoded, StorageError, WriteEncoded};
+use super::storage::Storage;
+use super::{EncodeOptions, Metrics, RawColumn};
+
+/// The magic starting the header of a versioned column
+pub(crate) const COLUMN_MAGIC: u64 = u64::from_be_bytes(*b"eqcolumn");
+
+/// The number of bytes in the header of a versioned column
+pub(crate) const HEADER_LEN: u64 = 16;
+
+/// A version of the encoding of columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FormatVersion {
+    /// Columns written before versions were recorded, which have no header
+    Unversioned,
+    /// The first version recorded in the header of columns
+    V1,
+}
+
+impl FormatVersion {
+    /// The version in which columns are written
+    pub const CURRENT: FormatVersion = FormatVersion::V1;
+
+    /// Every version that can be read
+    pub const ALL: [FormatVersion; 2] = [FormatVersion::Unversioned, FormatVersion::V1];
+
+    /// The number recording this version in the header of a column
+    pub fn number(self) -> u64 {
+        match self {
+            FormatVersion::Unversioned => 0,
+            FormatVersion::V1 => 1,
+        }
+    }
+
+    /// The version recorded as `number`, if it is one that can be read
+    pub fn from_number(number: u64) -> Option<Self> {
+        FormatVersion::ALL
+            .iter()
+            .copied()
+            .find(|v| v.number() == number)
+    }
+}
+
+/// Write the header of a column in the current version
+pub(crate) fn write_header<W: WriteEncoded>(out: &mut W) -> Result<(), StorageError> {
+    out.write_u64(COLUMN_MAGIC)?;
+    out.write_u64(FormatVersion::CURRENT.number())
+}
+
+/// Read the version from the header of a column starting with
+/// [`COLUMN_MAGIC`], leaving `storage` at the magic of its format
+pub(crate) fn read_version(storage: &mut Storage) -> Result<FormatVersion, StorageError> {
+    let mut number = [0; 8];
+    storage.read_exact_at(&mut number, 8)?;
+    let number = u64::from_be_bytes(number);
+    let version = FormatVersion::from_number(number)
+        .filter(|v| *v != FormatVersion::Unversioned)
+        .ok_or(StorageError::UnsupportedVersion(number))?;
+    storage.advance(HEADER_LEN)?;
+    Ok(version)
+}
+
+/// Rewrite the column file at `path` in the current version, if it was
+/// written in an older one, returning the version it was written in.
+///
+/// The column keeps its format and values.  The new file replaces the old
+/// one only once it is complete, but the column must not be read by a
+/// database while it is migrated.
+pub fn migrate_column<P: AsRef<Path>>(path: P) -> Result<FormatVersion, StorageError> {
+    let path = path.as_ref();
+    let column = crate::fs::open_column(path, &Metrics::default())?;
+    let version = column.version();
+    if version == FormatVersion::CURRENT {
+        return Ok(version);
+    }
+    let values = column.read_values()?;
+    let mut out = Vec::new();
+    RawColumn::write_values_with(
+        &mut out,
+        column.kind(),
+        &values,
+        EncodeOptions::default().format(column.format()),
+    )?;
+    let tmp = path.with_extension("migrating");
+    crate::fs::write_synced(&tmp, &out)?;
+    crate::fs::rename(&tmp, path)?;
+    Ok(version)
+}
+
+#[test]
+fn migrate_unversioned() {
+    use super::{ColumnFormat, IsRawColumn};
+    use crate::{RawKind, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let values = (0..1000).map(|i| RawValue::U64(i / 10)).collect::<Vec<_>>();
+    let runs = super::run_length_encode(&(0..1000).map(|i| i / 10).collect::<Vec<u64>>());
+
+    // A column written before versions were recorded has no header.
+    let mut old = Vec::new();
+    super::u64_generic::U8Variable::encode(&mut old, &runs).unwrap();
+    let path = dir.path().join("old.col");
+    std::fs::write(&path, &old).unwrap();
+    let column = RawColumn::open(&path).unwrap();
+    assert_eq!(column.version(), FormatVersion::Unversioned);
+    assert_eq!(column.read_values().unwrap(), values);
+
+    assert_eq!(migrate_column(&path).unwrap(), FormatVersion::Unversioned);
+    let column = RawColumn::open(&path).unwrap();
+    assert_eq!(column.version(), FormatVersion::CURRENT);
+    assert_eq!(column.format(), ColumnFormat::U8Runs);
+    assert_eq!(column.read_values().unwrap(), values);
+    assert_eq!(std::fs::read(&path).unwrap()[16..], old[..]);
+
+    // Migrating again leaves the column as it is.
+    assert_eq!(migrate_column(&path).unwrap(), FormatVersion::CURRENT);
+    assert_eq!(std::fs::read(&path).unwrap()[16..], old[..]);
+
+    // Every column written now has a header, and a version from the future
+    // is refused rather than decoded.
+    let mut new = Vec::new();
+    RawColumn::write_values(&mut new, RawKind::U64, &values).unwrap();
+    assert_eq!(
+        RawColumn::decode(new.clone()).unwrap().version(),
+        FormatVersion::CURRENT
+    );
+    new[15] = 99;
+    assert!(matches!(
+        RawColumn::decode(new),
+        Err(StorageError::UnsupportedVersion(99))
+    ));
+}
@@ -0,0 +1,1898 @@
+//! A database, stored as a directory of tables.
+//!
+//! The schemas of the tables are themselves stored in two tables, described
+//! by [`db_schema_schema`] and [`table_schema_schema`].  Changing a schema
+//! never rewrites these tables: it saves new rows with a later `modified`
+//! time, which win when the rows are merged.
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+mod backup;
+mod catalog;
+mod changelog;
+mod counters;
+mod ingest;
+mod references;
+mod symbols;
+mod typed;
+pub use backup::BackupManifest;
+pub use catalog::Catalog;
+pub use changelog::Change;
+use ingest::Memtables;
+pub use ingest::{IngestOptions, Ingestor};
+pub use references::DanglingReference;
+pub use typed::{IsRow, NamedRow, TypedTable};
+
+use crate::column::cache::DEFAULT_CAPACITY;
+use crate::column::encoding::StorageError;
+use crate::column::ColumnCache;
+use crate::fs;
+use crate::lens::{ColumnId, TableId};
+use crate::query::Running;
+use crate::schema::catalog::{
+    CatalogColumn, CHANGELOG_TABLE, COLUMNS_TABLE, COUNTER_TABLE, PURGED, PURGED_TABLE, PURGE_ROWS,
+    PURGE_SEGMENTS, PURGE_SINCE, PURGE_TABLE, SCRUBBED, SCRUBBED_TABLE, SCRUB_COLUMNS,
+    SCRUB_CORRUPTIONS, SCRUB_PROBLEMS, SCRUB_SEGMENTS, SCRUB_TABLE, SYMBOL_TABLE, TABLES_TABLE,
+    WATERMARKED_TABLE, WATERMARK_SEQUENCE, WATERMARK_SHARD, WATERMARK_TABLE,
+};
+use crate::schema::Aggregation;
+use crate::table::{PinnedFiles, Segment};
+use crate::{
+    changelog_schema, counter_schema, db_schema_schema, purge_schema, scrub_schema, symbol_schema,
+    table_schema_schema, watermark_schema, AsOf, Comparison, CsvLoader, DbLayout, Encoding, Expr,
+    FlatLayout, Inconsistency, InconsistencyKind, JsonLoader, RawColumnSchema, RawRow, RawValue,
+    SalvageReport, ScrubReport, Table, TableBuilder, TableSchema, TableStats, VerifyReport,
+};
+
+/// The tables a database keeps for itself, which are named by their ids
+const SYSTEM_TABLES: [TableId; 8] = [
+    TABLES_TABLE,
+    COLUMNS_TABLE,
+    SCRUB_TABLE,
+    PURGE_TABLE,
+    WATERMARK_TABLE,
+    CHANGELOG_TABLE,
+    SYMBOL_TABLE,
+    COUNTER_TABLE,
+];
+
+fn table_dir(dir: &Path, id: TableId) -> PathBuf {
+    dir.join(format!("{:032x}", u128::from_be_bytes(id.0)))
+}
+
+/// Save the schemas of new tables into the schema tables of the database in
+/// `dir`.
+///
+/// This takes the same lock as [`Database::open_writable`], so it fails if
+/// the database is open for writing.
+pub fn save_db_schema<P: AsRef<Path>>(dir: P, schemas: &[TableSchema]) -> Result<(), StorageError> {
+    fs::create_dir_all(dir.as_ref())?;
+    let _lock = fs::lock(dir.as_ref())?;
+    let now = SystemTime::now();
+    let tables = schemas
+        .iter()
+        .map(|s| s.catalog_row(now, now, false))
+        .collect::<Vec<_>>();
+    let columns = schemas
+        .iter()
+        .flat_map(|s| s.catalog_columns())
+        .collect::<Vec<_>>();
+    save_catalog(dir.as_ref(), &tables, &columns, now)
+}
+
+/// Load the schemas of all tables in the database in `dir`.
+pub fn load_db_schema<P: AsRef<Path>>(dir: P) -> Result<Vec<TableSchema>, StorageError> {
+    Ok(load_catalog(dir.as_ref())?
+        .into_iter()
+        .map(|(_, s)| s)
+        .collect())
+}
+
+/// Check that the files of every table of the database in `dir`, including
+/// the tables it keeps for itself, agree with each other and with the
+/// schemas of the tables, see [`Table::verify`].
+///
+/// The directories of the tables are named by `layout`, which must be the
+/// layout the database is opened with.  Nothing is written, so a
+/// verification may run while the database is open, even for writing.
+pub fn verify_db<P: AsRef<Path>>(dir: P, layout: &dyn DbLayout) -> VerifyReport {
+    let dir = dir.as_ref();
+    let mut report = VerifyReport::default();
+    let system = [
+        db_schema_schema(),
+        table_schema_schema(),
+        scrub_schema(),
+        purge_schema(),
+        watermark_schema(),
+        changelog_schema(),
+        symbol_schema(),
+        counter_schema(),
+    ];
+    for schema in system.iter() {
+        report.extend(Table::verify(table_dir(dir, schema.id()), schema));
+    }
+    match load_db_schema(dir) {
+        Ok(schemas) => {
+            for schema in schemas.iter() {
+                report.extend(Table::verify(dir.join(layout.table_dir(schema)), schema));
+            }
+        }
+        Err(e) => report.push(Inconsistency::new(
+            system[0].name(),
+            None,
+            None,
+            InconsistencyKind::Catalog(e.to_string()),
+        )),
+    }
+    report
+}
+
+fn load_catalog(dir: &Path) -> Result<Vec<(SystemTime, TableSchema)>, StorageError> {
+    let tables = Table::read(table_dir(dir, TABLES_TABLE), &db_schema_schema())?.to_rows()?;
+    let columns = Table::read(table_dir(dir, COLUMNS_TABLE), &table_schema_schema())?.to_rows()?;
+    Ok(TableSchema::from_catalog(&tables, &columns)?)
+}
+
+/// Record new versions of table rows and of columns in the schema tables.
+fn save_catalog(
+    dir: &Path,
+    tables: &[RawRow],
+    columns: &[CatalogColumn],
+    modified: SystemTime,
+) -> Result<(), StorageError> {
+    // The columns are saved first, so a crash in between leaves at worst
+    // columns belonging to no table, which are ignored.
+    let schema = table_schema_schema();
+    let mut builder = TableBuilder::new(&schema);
+    for c in columns {
+        builder.insert_raw_row(c.to_row(modified))?;
+    }
+    builder.save(table_dir(dir, COLUMNS_TABLE))?;
+    if tables.is_empty() {
+        return Ok(());
+    }
+
+    let mut builder = TableBuilder::new(&db_schema_schema());
+    for row in tables {
+        builder.insert_raw_row(row.clone())?;
+    }
+    builder.save(table_dir(dir, TABLES_TABLE))
+}
+
+/// A change to the schema of a table
+#[derive(Debug, Clone)]
+pub enum Alteration {
+    /// Add the raw columns of a new logical column, with the given aggregation.
+    ///
+    /// Rows saved before the column was added read as its default.
+    AddColumn {
+        /// The raw columns to add
+        columns: Vec<RawColumnSchema>,
+        /// How the new column is aggregated
+        aggregation: Aggregation,
+    },
+    /// Drop a column that is not part of the primary key
+    DropColumn(String),
+    /// Rename a column
+    RenameColumn {
+        /// The current name of the column
+        from: String,
+        /// The new name of the column
+        to: String,
+    },
+    /// Index a column in the segments saved from now on, see
+    /// [`TableSchema::add_index`]
+    AddIndex(String),
+    /// Stop indexing a column
+    DropIndex(String),
+    /// Encode a column in the segments saved from now on, see
+    /// [`TableSchema::set_encoding`]
+    SetEncoding {
+        /// The name of the column
+        column: String,
+        /// How it is encoded
+        encoding: Encoding,
+    },
+}
+
+/// A database stored in a directory.
+///
+/// The database keeps the schemas of its tables, and decides where each
+/// table is stored.
+pub struct Database {
+    dir: PathBuf,
+    tables: Vec<(SystemTime, TableSchema)>,
+    last_modified: SystemTime,
+    /// The files of a database kept in memory, which are forgotten when it
+    /// is dropped
+    _memory: Option<fs::MemoryRoot>,
+    /// The lock held by a writer, released when the database is dropped
+    _lock: Option<std::fs::File>,
+    read_only: bool,
+    /// The columns most recently decoded by reading its tables
+    cache: ColumnCache,
+    /// Where the files of its tables are kept
+    layout: Arc<dyn DbLayout>,
+    /// The SQL statements being run against it
+    running: Running,
+    /// The rows accepted by ingestors of its tables but not yet saved
+    memtables: Memtables,
+}
+
+impl Database {
+    /// Open the database in `dir` for writing, creating it if needed, see
+    /// [`Database::open_writable`].
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, StorageError> {
+        Database::open_writable(dir)
+    }
+
+    /// Open the database in `dir` for writing, creating it if needed.
+    ///
+    /// Only one writer may have a database open at a time, which is enforced
+    /// with an advisory lock on a file in `dir`.  If another writer holds it,
+    /// this fails with [`StorageError::Locked`].
+    pub fn open_writable<P: AsRef<Path>>(dir: P) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let lock = fs::lock(&dir)?;
+        Database::open_locked(dir, lock)
+    }
+
+    /// Open the database in `dir` for writing, holding its `lock`
+    fn open_locked(dir: PathBuf, lock: Option<std::fs::File>) -> Result<Self, StorageError> {
+        let tables = load_catalog(&dir)?;
+        Ok(Database {
+            dir,
+            tables,
+            last_modified: SystemTime::UNIX_EPOCH,
+            _memory: None,
+            _lock: lock,
+            read_only: false,
+            cache: ColumnCache::new(DEFAULT_CAPACITY),
+            layout: Arc::new(FlatLayout),
+            running: Running::default(),
+            memtables: Memtables::default(),
+        })
+    }
+
+    /// Open the database in `dir` for reading only.
+    ///
+    /// No lock is taken, since segments are never modified and manifests are
+    /// replaced atomically, so this works while a writer has the database
+    /// open.  Any change fails with [`StorageError::ReadOnly`].  The schemas
+    /// are those at the time the database was opened.
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        let tables = load_catalog(&dir)?;
+        Ok(Database {
+            dir,
+            tables,
+            last_modified: SystemTime::UNIX_EPOCH,
+            _memory: None,
+            _lock: None,
+            read_only: true,
+            cache: ColumnCache::new(DEFAULT_CAPACITY),
+            layout: Arc::new(FlatLayout),
+            running: Running::default(),
+            memtables: Memtables::default(),
+        })
+    }
+
+    fn check_writable(&self) -> Result<(), StorageError> {
+        if self.read_only {
+            Err(StorageError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Create an empty database that is kept entirely in memory.
+    ///
+    /// This is meant for tests, which then need no temporary directory.  The
+    /// database behaves just like one on disk, except that its tables are
+    /// forgotten when it is dropped.
+    pub fn in_memory() -> Self {
+        let memory = fs::MemoryRoot::new();
+        Database {
+            dir: memory.path().to_path_buf(),
+            tables: Vec::new(),
+            last_modified: SystemTime::UNIX_EPOCH,
+            _memory: Some(memory),
+            _lock: None,
+            read_only: false,
+            cache: ColumnCache::new(DEFAULT_CAPACITY),
+            layout: Arc::new(FlatLayout),
+            running: Running::default(),
+            memtables: Memtables::default(),
+        }
+    }
+
+    /// Limit the cache of decoded columns to roughly `bytes` of memory,
+    /// evicting the columns used least recently to make room.
+    ///
+    /// Reading a table through a [`TableHandle`] takes the values of each
+    /// column decoded before from the cache, so repeated queries need not
+    /// decode the same columns again.  The cache is shared by every handle of
+    /// the database, and a capacity of zero turns it off.
+    pub fn set_cache_capacity(&self, bytes: usize) {
+        self.cache.set_capacity(bytes);
+    }
+
+    /// The most bytes of decoded columns the cache holds, see
+    /// [`Database::set_cache_capacity`]
+    pub fn cache_capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// The schemas of all the tables
+    pub fn schemas(&self) -> impl Iterator<Item = &TableSchema> {
+        self.tables.iter().map(|(_, s)| s)
+    }
+
+    /// The schema of the table called `name`
+    pub fn schema(&self, name: &str) -> Option<&TableSchema> {
+        self.schemas().find(|s| s.name() == name)
+    }
+
+    /// The table called `name`
+    pub fn table(&self, name: &str) -> Result<TableHandle, StorageError> {
+        let schema = self
+            .schema(name)
+            .ok_or_else(|| StorageError::Schema(format!("no table {name}")))?;
+        let rollups = self
+            .schemas()
+            .filter(|s| s.rollup_of() == Some(schema.id()))
+            .map(|s| (self.table_dir(s), s.clone()))
+            .collect();
+        let referenced = schema.referencing_columns();
+        let references = self
+            .schemas()
+            .filter(|s| referenced.iter().any(|(_, t, _)| *t == s.id()))
+            .map(|s| (self.table_dir(s), s.clone()))
+            .collect();
+        Ok(TableHandle {
+            dir: self.table_dir(schema),
+            schema: schema.clone(),
+            read_only: self.read_only,
+            rollups,
+            references,
+            db_dir: Some(self.dir.clone()),
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        })
+    }
+
+    /// The table called `name` as it was at an earlier version, see
+    /// [`Table::read_at`].
+    ///
+    /// The table is read through its current schema.
+    pub fn table_at(&self, name: &str, as_of: AsOf) -> Result<Table, StorageError> {
+        self.table(name)?.read_at(as_of)
+    }
+
+    /// Check every table, including the schema tables, for corruption, see
+    /// [`Table::scrub`].
+    ///
+    /// A row for each table is recorded in the [`scrub_schema`] table, which
+    /// can be read through [`Database::scrub_results`], so the database must
+    /// be writable.  Segments are never modified, so a scrub can run on a
+    /// thread of its own sharing the `Database`, while other threads read and
+    /// write, or from a [`Database::open_read_only`] in another process
+    /// through [`Table::scrub`].
+    pub fn scrub(&self) -> Result<Vec<(String, ScrubReport)>, StorageError> {
+        self.check_writable()?;
+        let checked = SystemTime::now();
+        let catalog = [db_schema_schema(), table_schema_schema()];
+        let mut reports = Vec::new();
+        let mut rows = Vec::new();
+        for schema in catalog.iter().chain(self.schemas()) {
+            let dir = if catalog.iter().any(|c| c.id() == schema.id()) {
+                table_dir(&self.dir, schema.id())
+            } else {
+                self.table_dir(schema)
+            };
+            let report = Table::scrub(dir, schema)?;
+            let problems = report
+                .corruptions()
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            rows.push(scrub_schema().row(vec![
+                (SCRUBBED_TABLE, schema.id().into()),
+                (SCRUBBED, checked.into()),
+                (SCRUB_SEGMENTS, report.segments().into()),
+                (SCRUB_COLUMNS, report.columns().into()),
+                (
+                    SCRUB_CORRUPTIONS,
+                    (report.corruptions().len() as u64).into(),
+                ),
+                (SCRUB_PROBLEMS, problems.into()),
+            ]));
+            reports.push((schema.name().to_string(), report));
+        }
+        self.scrub_results().insert_raw_rows(rows)?;
+        Ok(reports)
+    }
+
+    /// The size of every table, without decoding any of them, so the growth
+    /// of the tables can be watched cheaply
+    pub fn stats(&self) -> Result<Vec<(String, TableStats)>, StorageError> {
+        self.schemas()
+            .map(|s| Ok((s.name().to_string(), self.table(s.name())?.stats()?)))
+            .collect()
+    }
+
+    /// The table recording the results of every [`Database::scrub`]
+    pub fn scrub_results(&self) -> TableHandle {
+        TableHandle {
+            dir: table_dir(&self.dir, SCRUB_TABLE),
+            schema: scrub_schema(),
+            read_only: self.read_only,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: None,
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+
+    /// The table recording the rows purged from each table by compaction,
+    /// see [`TableHandle::compact`]
+    pub fn purge_results(&self) -> TableHandle {
+        TableHandle {
+            dir: table_dir(&self.dir, PURGE_TABLE),
+            schema: purge_schema(),
+            read_only: self.read_only,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: None,
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+
+    /// The table recording the watermark of each shard of each table with a
+    /// sequence column, see [`TableSchema::set_sequence_column`]
+    pub fn watermarks(&self) -> TableHandle {
+        TableHandle {
+            dir: table_dir(&self.dir, WATERMARK_TABLE),
+            schema: watermark_schema(),
+            read_only: self.read_only,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: None,
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+
+    /// Create a new table, which is empty unless it is a rollup of another,
+    /// see [`TableSchema::rollup`].
+    pub fn create_table(&mut self, schema: TableSchema) -> Result<TableHandle, StorageError> {
+        self.check_writable()?;
+        if crate::query::is_system_table(schema.name()) {
+            return Err(StorageError::Schema(format!(
+                "table {} would hide a system table",
+                schema.name()
+            )));
+        }
+        if self.schema(schema.name()).is_some() {
+            return Err(StorageError::Schema(format!(
+                "table {} already exists",
+                schema.name()
+            )));
+        }
+        if SYSTEM_TABLES.contains(&schema.id()) || self.schemas().any(|s| s.id() == schema.id()) {
+            return Err(StorageError::Schema(format!(
+                "table id {} is already used",
+                schema.id()
+            )));
+        }
+        for (_, table, _) in schema.referencing_columns() {
+            if table != schema.id() && !self.schemas().any(|s| s.id() == table) {
+                return Err(StorageError::Schema(format!(
+                    "no table {table} to refer to"
+                )));
+            }
+        }
+        let base = match schema.rollup_of() {
+            Some(id) => Some(
+                self.schemas()
+                    .find(|s| s.id() == id)
+                    .ok_or_else(|| StorageError::Schema(format!("no table {id} to roll up")))?
+                    .clone(),
+            ),
+            None => None,
+        };
+        if let Some(base) = base {
+            let rows = Table::read(self.table_dir(&base), &base)?.to_rows()?;
+            let dir = self.table_dir(&schema);
+            Table::roll_up(&dir, &*self.layout, &schema, &base, &rows)?;
+        }
+        let created = self.next_modified();
+        save_catalog(
+            &self.dir,
+            &[schema.catalog_row(created, created, false)],
+            &schema.catalog_columns(),
+            created,
+        )?;
+        self.tables.push((created, schema));
+        self.table(self.tables[self.tables.len() - 1].1.name())
+    }
+
+    /// Drop the table called `name`, deleting its rows
+    pub fn drop_table(&mut self, name: &str) -> Result<(), StorageError> {
+        self.check_writable()?;
+        let index = self.index(name)?;
+        let modified = self.next_modified();
+        let (created, schema) = &self.tables[index];
+        let referring = self.schemas().find(|s| {
+            s.id() != schema.id()
+                && s.referencing_columns()
+                    .iter()
+                    .any(|(_, t, _)| *t == schema.id())
+        });
+        if let Some(referring) = referring {
+            return Err(StorageError::Schema(format!(
+                "table {name} is referred to by table {}",
+                referring.name()
+            )));
+        }
+        save_catalog(
+            &self.dir,
+            &[schema.catalog_row(*created, modified, true)],
+            &[],
+            modified,
+        )?;
+        let dir = self.table_dir(schema);
+        self.tables.remove(index);
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Create a table called `dst` holding the rows of the table called
+    /// `src`, with the same columns, as a branch of it that is cheap to make
+    /// however large `src` is.
+    ///
+    /// The new table starts from the current version of `src`, sharing its
+    /// segments, whose column files are hard links to those of `src` rather
+    /// than copies, or copies where files cannot be linked.  Segments never
+    /// change once saved, so writes to either table from then on change only
+    /// that table.  The new table remembers no earlier versions, and is no
+    /// rollup even if `src` is one.
+    pub fn clone_table(&mut self, src: &str, dst: &str) -> Result<TableHandle, StorageError> {
+        self.check_writable()?;
+        if crate::query::is_system_table(dst) {
+            return Err(StorageError::Schema(format!(
+                "table {dst} would hide a system table"
+            )));
+        }
+        if self.schema(dst).is_some() {
+            return Err(StorageError::Schema(format!("table {dst} already exists")));
+        }
+        let from = self
+            .schema(src)
+            .ok_or_else(|| StorageError::Schema(format!("no table {src}")))?;
+        let schema = from.cloned_as(dst);
+        let dir = self.table_dir(from);
+        PinnedFiles::pin(&dir)?.link(&dir, &self.table_dir(&schema))?;
+        // The symbols and counters of the clone are saved under its own id,
+        // before the catalog makes the clone visible.
+        let symbols = symbols::read_symbols(&self.dir, from.id())?;
+        symbols::save_symbols(&self.dir, schema.id(), &symbols)?;
+        let counters = counters::read_counters(&self.dir, from.id())?;
+        counters::save_counters(&self.dir, schema.id(), &counters)?;
+        let created = self.next_modified();
+        save_catalog(
+            &self.dir,
+            &[schema.catalog_row(created, created, false)],
+            &schema.catalog_columns(),
+            created,
+        )?;
+        self.tables.push((created, schema));
+        self.table(dst)
+    }
+
+    /// Name the directories of tables, and the column files of the segments
+    /// saved from now on, by `layout` rather than by [`FlatLayout`].
+    ///
+    /// The directories of tables are not moved, so a database must always be
+    /// opened with the same layout.  The tables the database keeps for
+    /// itself are always named by their ids, so that the schemas of the other
+    /// tables can be found.
+    pub fn with_layout(mut self, layout: impl DbLayout + 'static) -> Self {
+        self.layout = Arc::new(layout);
+        self
+    }
+
+    /// The SQL statements being run against the database
+    pub(crate) fn running(&self) -> &Running {
+        &self.running
+    }
+
+    /// The directory of a table of the database
+    fn table_dir(&self, schema: &TableSchema) -> PathBuf {
+        self.dir.join(self.layout.table_dir(schema))
+    }
+
+    fn index(&self, name: &str) -> Result<usize, StorageError> {
+        self.tables
+            .iter()
+            .position(|(_, s)| s.name() == name)
+            .ok_or_else(|| StorageError::Schema(format!("no table {name}")))
+    }
+
+    /// A modification time later than any this handle has used, so that the
+    /// latest change to a schema always wins.
+    fn next_modified(&mut self) -> SystemTime {
+        let now = SystemTime::now();
+        self.last_modified = std::cmp::max(now, self.last_modified + Duration::from_nanos(1));
+        self.last_modified
+    }
+
+    /// Change the schema of the table called `table`.
+    ///
+    /// Only the rows describing the changed columns are saved, and no data
+    /// of the table itself is rewritten.
+    pub fn alter_table(&mut self, table: &str, alteration: Alteration) -> Result<(), StorageError> {
+        self.check_writable()?;
+        let index = self.index(table)?;
+        let (created, old) = self.tables[index].clone();
+        let mut schema = old.clone();
+        let changed = match alteration {
+            Alteration::AddColumn {
+                columns,
+                aggregation,
+            } => {
+                let ids = columns.iter().map(|c| c.id()).collect::<Vec<_>>();
+                schema
+                    .add_columns(columns, aggregation)
+                    .map_err(StorageError::Schema)?;
+                schema
+                    .catalog_columns()
+                    .into_iter()
+                    .filter(|c| ids.contains(&c.column.id()))
+                    .collect::<Vec<_>>()
+            }
+            Alteration::DropColumn(name) => {
+                let dropped = schema.drop_column(&name).map_err(StorageError::Schema)?;
+                old.catalog_columns()
+                    .into_iter()
+                    .filter(|c| dropped.contains(&c.column))
+                    .map(|c| CatalogColumn {
+                        is_deleted: true,
+                        ..c
+                    })
+                    .collect()
+            }
+            Alteration::RenameColumn { from, to } => {
+                schema
+                    .rename_column(&from, &to)
+                    .map_err(StorageError::Schema)?;
+                schema
+                    .catalog_columns()
+                    .into_iter()
+                    .filter(|c| c.column.name() == to)
+                    .collect()
+            }
+            Alteration::AddIndex(ref name) | Alteration::DropIndex(ref name) => {
+                let indexed = matches!(alteration, Alteration::AddIndex(_));
+                let changed = schema
+                    .set_indexed(name, indexed)
+                    .map_err(StorageError::Schema)?;
+                schema
+                    .catalog_columns()
+                    .into_iter()
+                    .filter(|c| changed.contains(&c.column))
+                    .collect()
+            }
+            Alteration::SetEncoding { column, encoding } => {
+                let changed = schema
+                    .set_encoding_by_name(&column, encoding)
+                    .map_err(StorageError::Schema)?;
+                schema
+                    .catalog_columns()
+                    .into_iter()
+                    .filter(|c| changed.contains(&c.column))
+                    .collect()
+            }
+        };
+        let modified = self.next_modified();
+        // Dropping the time column changes the row of the table itself.
+        let tables = if schema.time_index().is_some() == old.time_index().is_some() {
+            Vec::new()
+        } else {
+            vec![schema.catalog_row(created, modified, false)]
+        };
+        save_catalog(&self.dir, &tables, &changed, modified)?;
+        self.tables[index] = (created, schema);
+        Ok(())
+    }
+}
+
+/// A table of a [`Database`]
+#[derive(Debug, Clone)]
+pub struct TableHandle {
+    dir: PathBuf,
+    schema: TableSchema,
+    read_only: bool,
+    /// The directories and schemas of the rollups of the table
+    rollups: Vec<(PathBuf, TableSchema)>,
+    /// The directories and schemas of the tables referred to by columns of
+    /// the table, see [`TableSchema::add_reference`]
+    references: Vec<(PathBuf, TableSchema)>,
+    /// The directory of the database, unless this is one of the tables the
+    /// database keeps for itself, whose purges and watermarks are not
+    /// recorded
+    db_dir: Option<PathBuf>,
+    /// The cache of the database
+    cache: ColumnCache,
+    /// The layout of the database, which names new column files
+    layout: Arc<dyn DbLayout>,
+    /// The rows accepted by ingestors of the tables of the database but not
+    /// yet saved, which are read along with the saved rows
+    memtables: Memtables,
+}
+
+impl TableHandle {
+    /// The schema of the table
+    pub fn schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn check_writable(&self) -> Result<(), StorageError> {
+        if self.read_only {
+            Err(StorageError::ReadOnly)
+        } else if self.schema.rollup_of().is_some() {
+            Err(StorageError::Schema(format!(
+                "table {} is a rollup, so only compacting the table it rolls up changes it",
+                self.schema.name()
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Save `rows` as a new segment of the table
+    pub fn insert_raw_rows(
+        &self,
+        rows: impl IntoIterator<Item = RawRow>,
+    ) -> Result<(), StorageError> {
+        self.insert_returning(rows).map(|_| ())
+    }
+
+    /// Save `rows` as a new segment of the table, returning them as they
+    /// were saved, with their computed defaults
+    pub(crate) fn insert_returning(
+        &self,
+        rows: impl IntoIterator<Item = RawRow>,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        self.check_writable()?;
+        let mut builder = self.builder()?;
+        for row in rows {
+            builder.insert_raw_row(row)?;
+        }
+        self.save(builder)
+    }
+
+    /// Check the rows of `builder` against the unique and referring columns
+    /// of the table, save the strings it interned and the counters it moved
+    /// and then its rows, returning them, record the rows in the changelog,
+    /// and then advance the watermarks of the shards they came from.
+    ///
+    /// A crash before the watermarks are advanced leaves the rows saved but
+    /// past the watermark, until more rows from their shards are saved.
+    fn save(&self, builder: TableBuilder) -> Result<Vec<RawRow>, StorageError> {
+        let watermarks = builder.watermarks().clone();
+        let rows = builder.rows().to_vec();
+        Table::check_unique(&self.dir, &self.schema, &rows)?;
+        self.check_references(&rows)?;
+        self.save_symbols(builder.symbols())?;
+        self.save_counters(builder.counters())?;
+        builder.layout(self.layout.clone()).save(&self.dir)?;
+        self.record_changes(false, &rows)?;
+        self.advance_watermarks(&watermarks)?;
+        Ok(rows)
+    }
+
+    /// Record the largest sequence number saved from each shard
+    fn advance_watermarks(&self, watermarks: &BTreeMap<u64, u64>) -> Result<(), StorageError> {
+        let Some(db_dir) = self.db_dir.as_ref().filter(|_| !watermarks.is_empty()) else {
+            return Ok(());
+        };
+        // The sequence numbers are aggregated by max, so a watermark never
+        // goes backwards.
+        let schema = watermark_schema();
+        let mut builder = TableBuilder::new(&schema);
+        for (&shard, &sequence) in watermarks.iter() {
+            builder.insert_raw_row(schema.row(vec![
+                (WATERMARKED_TABLE, self.schema.id().into()),
+                (WATERMARK_SHARD, shard.into()),
+                (WATERMARK_SEQUENCE, sequence.into()),
+            ]))?;
+        }
+        builder.save(table_dir(db_dir, WATERMARK_TABLE))
+    }
+
+    /// The largest sequence number saved from each shard, see
+    /// [`TableSchema::set_sequence_column`]
+    pub fn shard_watermarks(&self) -> Result<BTreeMap<u64, u64>, StorageError> {
+        let Some(db_dir) = &self.db_dir else {
+            return Ok(BTreeMap::new());
+        };
+        let this_table = Expr::Compare(
+            "table".to_string(),
+            Comparison::Equal,
+            RawValue::Bytes(self.schema.id().0.to_vec()),
+        );
+        let dir = table_dir(db_dir, WATERMARK_TABLE);
+        let rows = Table::read(dir, &watermark_schema())?.select(&this_table)?;
+        Ok(rows
+            .iter()
+            .filter_map(|r| match r.values[..] {
+                [_, RawValue::U64(shard), RawValue::U64(sequence)] => Some((shard, sequence)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Read the rows saved up to the watermark of each shard, along with the
+    /// watermarks.
+    ///
+    /// The watermarks are read before the rows, and rows whose sequence
+    /// number is past the watermark of their shard are left out.  So the rows
+    /// are those saved by the time of the watermarks, even while rows from
+    /// several shards are being saved at once, and a reader that remembers
+    /// the watermarks knows exactly which rows it has seen.
+    pub fn read_to_watermarks(&self) -> Result<(BTreeMap<u64, u64>, Vec<RawRow>), StorageError> {
+        let watermarks = self.shard_watermarks()?;
+        let mut rows = self.read()?.to_rows()?;
+        rows.retain(|r| match self.schema.shard_sequence(r) {
+            Some(s) => matches!(watermarks.get(&s.shard), Some(&w) if s.sequence <= w),
+            None => true,
+        });
+        Ok((watermarks, rows))
+    }
+
+    /// Load the rows of a CSV file as a new segment of the table, returning
+    /// the number of rows loaded, see [`CsvLoader::load`].
+    ///
+    /// Nothing is saved unless the whole file loads.
+    pub fn load_csv(&self, loader: &CsvLoader, reader: impl BufRead) -> Result<u64, StorageError> {
+        self.check_writable()?;
+        let mut builder = self.builder()?;
+        let rows = loader.load(reader, &mut builder)?;
+        self.save(builder)?;
+        Ok(rows)
+    }
+
+    /// Load the rows of newline-delimited JSON as a new segment of the table,
+    /// returning the number of rows loaded, see [`JsonLoader::load`].
+    ///
+    /// Nothing is saved unless all the JSON loads.
+    pub fn load_json(
+        &self,
+        loader: &JsonLoader,
+        reader: impl BufRead,
+    ) -> Result<u64, StorageError> {
+        self.check_writable()?;
+        let mut builder = self.builder()?;
+        let rows = loader.load(reader, &mut builder)?;
+        self.save(builder)?;
+        Ok(rows)
+    }
+
+    /// Merge `rows` into the rows already saved, returning the merged row for
+    /// each primary key given, see [`Table::upsert`].
+    pub fn upsert_rows(
+        &self,
+        rows: impl IntoIterator<Item = RawRow>,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        self.check_writable()?;
+        let rows = rows.into_iter().collect::<Vec<_>>();
+        let mut watermarks = BTreeMap::new();
+        for s in rows.iter().filter_map(|r| self.schema.shard_sequence(r)) {
+            let watermark = watermarks.entry(s.shard).or_default();
+            *watermark = std::cmp::max(*watermark, s.sequence);
+        }
+        self.check_references(&rows)?;
+        let merged =
+            Table::upsert_with_layout(&self.dir, &*self.layout, &self.schema, rows.clone())?;
+        self.record_changes(false, &rows)?;
+        self.advance_watermarks(&watermarks)?;
+        Ok(merged)
+    }
+
+    /// Read the table, along with the rows its ingestors have accepted but
+    /// not yet saved, see [`Ingestor`]
+    pub fn read(&self) -> Result<Table, StorageError> {
+        self.with_memtables(|| Table::read(&self.dir, &self.schema))
+    }
+
+    /// Read only the rows of the table that are saved
+    fn read_saved(&self) -> Result<Table, StorageError> {
+        Ok(Table::read(&self.dir, &self.schema)?.with_cache(&self.cache))
+    }
+
+    /// Read the table to select the rows matching `expr`, opening only the
+    /// partitions that can hold them, see [`Table::read_where`].
+    pub fn read_where(&self, expr: &Expr) -> Result<Table, StorageError> {
+        self.with_memtables(|| Table::read_where(&self.dir, &self.schema, expr))
+    }
+
+    /// Read only some columns of the table, see [`Table::read_projected`].
+    pub fn read_projected(&self, columns: &[ColumnId]) -> Result<Table, StorageError> {
+        self.with_memtables(|| Table::read_projected(&self.dir, &self.schema, columns))
+    }
+
+    /// The ingestion watermark of the table as it is now, see
+    /// [`Table::ingestion_watermark`].
+    pub fn ingestion_watermark(&self) -> Result<Option<u64>, StorageError> {
+        Ok(self.read_projected(&[])?.ingestion_watermark())
+    }
+
+    /// Read the table as it was at an earlier version, see [`Table::read_at`].
+    pub fn read_at(&self, as_of: AsOf) -> Result<Table, StorageError> {
+        Ok(Table::read_at(&self.dir, &self.schema, as_of)?.with_cache(&self.cache))
+    }
+
+    /// The versions of the table that can still be read, see
+    /// [`Table::versions`].
+    pub fn versions(&self) -> Result<Vec<(u64, SystemTime)>, StorageError> {
+        Table::versions(&self.dir)
+    }
+
+    /// Forget every version before `version`, see [`Table::forget_versions`].
+    pub fn forget_versions(&self, version: u64) -> Result<usize, StorageError> {
+        self.check_writable()?;
+        Table::forget_versions(&self.dir, version)
+    }
+
+    /// Merge all the segments of the table into one, purging rows that are
+    /// too old to keep, see [`Table::compact`], and then rebuild each rollup
+    /// of the table from the merged rows.
+    ///
+    /// This fails without changing anything if a value of a column refers to
+    /// no row, see [`TableSchema::add_reference`].
+    ///
+    /// Any purge is recorded in the [`purge_schema`] table, which can be read
+    /// through [`Database::purge_results`].
+    pub fn compact(&self) -> Result<(), StorageError> {
+        self.check_writable()?;
+        if !self.schema.referencing_columns().is_empty() {
+            self.check_references(&self.read_saved()?.to_rows()?)?;
+        }
+        let purge = Table::compact_with_layout(&self.dir, &*self.layout, &self.schema)?;
+        if let (Some(db_dir), Some(since)) = (&self.db_dir, purge.since()) {
+            if purge.rows() > 0 {
+                let schema = purge_schema();
+                let mut builder = TableBuilder::new(&schema);
+                builder.insert_raw_row(schema.row(vec![
+                    (PURGED_TABLE, self.schema.id().into()),
+                    (PURGED, SystemTime::now().into()),
+                    (PURGE_SINCE, since.into()),
+                    (PURGE_SEGMENTS, purge.segments().into()),
+                    (PURGE_ROWS, purge.rows().into()),
+                ]))?;
+                builder.save(table_dir(db_dir, PURGE_TABLE))?;
+            }
+        }
+        if !self.rollups.is_empty() {
+            let rows = self.read_saved()?.to_rows()?;
+            for (dir, rollup) in self.rollups.iter() {
+                Table::roll_up(dir, &*self.layout, rollup, &self.schema, &rows)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop every segment whose rows are all older than `before`, see
+    /// [`Table::expire`].
+    pub fn expire(&self, before: u64) -> Result<u64, StorageError> {
+        self.check_writable()?;
+        Table::expire(&self.dir, before)
+    }
+
+    /// Replace each segment with a column that cannot be read completely by
+    /// one holding the rows that can be, see [`Table::salvage`], and then
+    /// rebuild each rollup of the table without the rows lost.
+    pub fn salvage(&self) -> Result<SalvageReport, StorageError> {
+        self.check_writable()?;
+        let report = Table::salvage_with_layout(&self.dir, &*self.layout, &self.schema)?;
+        if !report.is_intact() && !self.rollups.is_empty() {
+            let rows = self.read_saved()?.to_rows()?;
+            for (dir, rollup) in self.rollups.iter() {
+                Table::roll_up(dir, &*self.layout, rollup, &self.schema, &rows)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// The segments of the table, as listed in its manifest
+    pub(crate) fn segments(&self) -> Result<Vec<Segment>, StorageError> {
+        Table::segments(&self.dir)
+    }
+
+    /// The size of the table, see [`Table::stats`]
+    pub fn stats(&self) -> Result<TableStats, StorageError> {
+        Table::stats(&self.dir)
+    }
+
+    /// Remove the rows for which `delete` is true, returning them.
+    ///
+    /// The remaining rows are rewritten as a single segment.
+    pub(crate) fn delete_rows(
+        &self,
+        mut delete: impl FnMut(&RawRow) -> bool,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        self.check_writable()?;
+        let (deleted, kept): (Vec<RawRow>, Vec<RawRow>) = self
+            .read_saved()?
+            .to_rows()?
+            .into_iter()
+            .partition(|r| delete(r));
+        if !deleted.is_empty() {
+            Table::rewrite(&self.dir, &*self.layout, &self.schema, &kept)?;
+            self.record_changes(true, &deleted)?;
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+fn test_schema() -> TableSchema {
+    use crate::ColumnSchema;
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::<u64>::new("age")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("happy").raw()),
+    );
+    schema.add_sum(ColumnSchema::<u64>::new("visits").raw());
+    schema
+}
+
+#[test]
+fn save_and_load_schema() {
+    use crate::{Collation, ColumnSchema, SumOverflow};
+
+    let dir = tempfile::tempdir().unwrap();
+    assert!(load_db_schema(dir.path()).unwrap().is_empty());
+
+    let mut schema = test_schema();
+    let (errors, total) = (
+        ColumnSchema::<u64>::new("errors"),
+        ColumnSchema::<u128>::new("total"),
+    );
+    schema
+        .add_sum_with_overflow(&errors, SumOverflow::Error)
+        .unwrap();
+    schema
+        .add_sum_with_overflow(&total, SumOverflow::Widen)
+        .unwrap();
+    schema.add_count(&ColumnSchema::new("logins"));
+    schema.add_avg(&ColumnSchema::new("session"));
+    let nickname = ColumnSchema::<String>::new("nickname");
+    schema.add_max(nickname.raw());
+    schema
+        .set_collation(&nickname, Collation::CaseInsensitive)
+        .unwrap();
+    let seen = ColumnSchema::<u64>::new("seen");
+    let status = ColumnSchema::<String>::new("status");
+    schema.add_last_by(&seen, status.raw()).unwrap();
+    schema.add_sketch(&ColumnSchema::new("latency"));
+    schema
+        .add_first_by(
+            &ColumnSchema::<u64>::new("joined"),
+            ColumnSchema::<String>::new("first_status").raw(),
+        )
+        .unwrap();
+    save_db_schema(dir.path(), std::slice::from_ref(&schema)).unwrap();
+    let loaded = load_db_schema(dir.path()).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].to_string(), schema.to_string());
+}
+
+#[test]
+fn alter_table() {
+    use crate::{ColumnSchema, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    save_db_schema(dir.path(), &[test_schema()]).unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    db.table("people")
+        .unwrap()
+        .insert_raw_rows([[
+            RawValue::Bytes(b"David".to_vec()),
+            RawValue::U64(48),
+            RawValue::Bool(true),
+            RawValue::U64(1),
+        ]
+        .into_iter()
+        .collect()])
+        .unwrap();
+
+    let height = ColumnSchema::with_default("height", 170u64);
+    db.alter_table(
+        "people",
+        Alteration::AddColumn {
+            columns: height.raw().collect(),
+            aggregation: Aggregation::Max,
+        },
+    )
+    .unwrap();
+    db.alter_table("people", Alteration::DropColumn("happy".to_string()))
+        .unwrap();
+    db.alter_table(
+        "people",
+        Alteration::RenameColumn {
+            from: "age".to_string(),
+            to: "years".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(db
+        .alter_table("people", Alteration::DropColumn("name".to_string()))
+        .is_err());
+    assert!(db
+        .alter_table("people", Alteration::DropColumn("happy".to_string()))
+        .is_err());
+    assert!(db
+        .alter_table("nobody", Alteration::DropColumn("age".to_string()))
+        .is_err());
+    db.alter_table("people", Alteration::AddIndex("years".to_string()))
+        .unwrap();
+    assert!(db
+        .alter_table("people", Alteration::AddIndex("years".to_string()))
+        .is_err());
+    assert!(db
+        .alter_table("people", Alteration::AddIndex("visits".to_string()))
+        .is_err());
+    assert!(db
+        .schema("people")
+        .unwrap()
+        .to_string()
+        .contains("INDEX ( years )"));
+    let encode = |column: &str, encoding| Alteration::SetEncoding {
+        column: column.to_string(),
+        encoding,
+    };
+    db.alter_table("people", encode("name", Encoding::Dictionary))
+        .unwrap();
+    assert!(db
+        .alter_table("people", encode("years", Encoding::Dictionary))
+        .is_err());
+    assert!(db
+        .alter_table("people", encode("nothing", Encoding::Plain))
+        .is_err());
+    assert!(db
+        .schema("people")
+        .unwrap()
+        .to_string()
+        .contains("ENCODE Dictionary ( name )"));
+
+    let schema = db.schema("people").unwrap().clone();
+    let names: Vec<&str> = schema.raw_columns().map(|c| c.name()).collect();
+    assert_eq!(names.len(), 4);
+    for name in ["name", "years", "visits", "height"] {
+        assert!(names.contains(&name));
+    }
+
+    // The schema survives reopening the database.
+    let db = Database::open_read_only(dir.path()).unwrap();
+    assert_eq!(db.schema("people").unwrap().to_string(), schema.to_string());
+
+    // The old segment reads the default for the new column.
+    let table = db.table("people").unwrap().read().unwrap();
+    let expected: RawRow = schema
+        .raw_columns()
+        .map(|c| match c.name() {
+            "name" => RawValue::Bytes(b"David".to_vec()),
+            "years" => RawValue::U64(48),
+            "visits" => RawValue::U64(1),
+            _ => RawValue::U64(170),
+        })
+        .collect();
+    assert_eq!(table.to_rows().unwrap(), vec![expected]);
+}
+
+#[test]
+fn create_and_drop_tables() {
+    use crate::{ColumnSchema, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let people = db.create_table(test_schema()).unwrap();
+    assert!(db.create_table(test_schema()).is_err());
+    let mut pets = TableSchema::new("pets");
+    pets.add_primary(ColumnSchema::<String>::new("name").raw());
+    pets.set_conflict_policy(crate::ConflictPolicy::FirstWriteWins);
+    db.create_table(pets.clone()).unwrap();
+
+    let row = [
+        RawValue::Bytes(b"David".to_vec()),
+        RawValue::U64(48),
+        RawValue::Bool(true),
+        RawValue::U64(1),
+    ]
+    .into_iter()
+    .collect::<RawRow>();
+    people.insert_raw_rows([row.clone()]).unwrap();
+
+    drop(db);
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut names: Vec<&str> = db.schemas().map(|s| s.name()).collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["people", "pets"]);
+    assert_eq!(db.schema("pets").unwrap().to_string(), pets.to_string());
+    assert_eq!(
+        db.table("people")
+            .unwrap()
+            .read()
+            .unwrap()
+            .to_rows()
+            .unwrap(),
+        vec![row]
+    );
+
+    db.drop_table("people").unwrap();
+    assert!(db.table("people").is_err());
+    assert!(db.drop_table("people").is_err());
+    drop(db);
+    let db = Database::open(dir.path()).unwrap();
+    assert!(db.table("people").is_err());
+    assert_eq!(db.schemas().count(), 1);
+
+    // A new table may reuse the name of a dropped one.
+    let mut db = db;
+    let people = db.create_table(test_schema()).unwrap();
+    assert!(people.read().unwrap().to_rows().unwrap().is_empty());
+}
+
+#[test]
+fn clone_tables() {
+    use crate::RawValue;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let people = db.create_table(test_schema()).unwrap();
+    let person = |i: u64, visits: u64| {
+        [
+            RawValue::Bytes(format!("person {i}").into_bytes()),
+            RawValue::U64(i % 90),
+            RawValue::Bool(true),
+            RawValue::U64(visits),
+        ]
+        .into_iter()
+        .collect::<RawRow>()
+    };
+    people
+        .insert_raw_rows((0..2000).map(|i| person(i, 1)))
+        .unwrap();
+    let before = people.read().unwrap().to_rows().unwrap();
+
+    let branch = db.clone_table("people", "branch").unwrap();
+    assert!(db.clone_table("people", "branch").is_err());
+    assert!(db.clone_table("nobody", "others").is_err());
+    assert_ne!(branch.schema().id(), people.schema().id());
+    assert_eq!(branch.read().unwrap().to_rows().unwrap(), before);
+    assert_eq!(branch.versions().unwrap().len(), 1);
+
+    // Writes to either table leave the other as it was.
+    branch.insert_raw_rows([person(0, 10)]).unwrap();
+    branch.compact().unwrap();
+    people.insert_raw_rows([person(1, 5)]).unwrap();
+    people.compact().unwrap();
+    people.forget_versions(u64::MAX).unwrap();
+    let people_rows = people.read().unwrap().to_rows().unwrap();
+    let branch_rows = branch.read().unwrap().to_rows().unwrap();
+    assert_eq!(people_rows[0], before[0]);
+    assert_eq!(branch_rows[0].values()[3], RawValue::U64(11));
+    assert_eq!(branch_rows[1], before[1]);
+    assert!(db.scrub().unwrap().iter().all(|(_, r)| r.is_clean()));
+
+    drop(db);
+    let db = Database::open(dir.path()).unwrap();
+    assert_eq!(
+        db.table("branch")
+            .unwrap()
+            .read()
+            .unwrap()
+            .to_rows()
+            .unwrap(),
+        branch_rows
+    );
+}
+
+#[test]
+fn clone_symbols_and_counters() {
+    use crate::lens::Symbol;
+    use crate::{ColumnSchema, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let id = ColumnSchema::<u64>::new("id");
+    let mut schema = TableSchema::new("requests");
+    schema.add_primary(id.raw());
+    schema.add_max(ColumnSchema::<Symbol>::new("agent").raw());
+    schema.auto_increment(&id).unwrap();
+    let requests = db.create_table(schema).unwrap();
+    requests.intern(["curl", "wget"]).unwrap();
+    db.execute("insert into requests (agent) values ('curl'), ('wget')")
+        .unwrap();
+
+    // The clone resolves the symbols of the rows it shares, and counts on
+    // from where the table it was cloned from had got to.
+    let branch = db.clone_table("requests", "branch").unwrap();
+    assert_eq!(branch.symbols().unwrap(), requests.symbols().unwrap());
+    assert_eq!(
+        branch.read_resolved().unwrap(),
+        requests.read_resolved().unwrap()
+    );
+    branch.intern(["lynx"]).unwrap();
+    db.execute("insert into branch (agent) values ('lynx')")
+        .unwrap();
+    requests.intern(["links"]).unwrap();
+    db.execute("insert into requests (agent) values ('links')")
+        .unwrap();
+    let text = |s: &str| RawValue::Bytes(s.as_bytes().to_vec());
+    let rows = branch.read_resolved().unwrap();
+    assert_eq!(rows[2].values(), [RawValue::U64(3), text("lynx")]);
+    let rows = requests.read_resolved().unwrap();
+    assert_eq!(rows[2].values(), [RawValue::U64(3), text("links")]);
+    assert_eq!(branch.symbols().unwrap().len(), 3);
+}
+
+#[test]
+fn rollups() {
+    use crate::RawValue;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let people = db.create_table(test_schema()).unwrap();
+    let person = |name: &str, age, happy, visits| -> RawRow {
+        [
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(age),
+            RawValue::Bool(happy),
+            RawValue::U64(visits),
+        ]
+        .into_iter()
+        .collect()
+    };
+    people
+        .insert_raw_rows([person("David", 48, true, 1), person("Alice", 30, false, 2)])
+        .unwrap();
+
+    // A rollup with no key columns totals the whole table.
+    let totals = people.schema().rollup("totals", 0).unwrap();
+    let totals = db.create_table(totals).unwrap();
+    let total = |age, happy, visits| -> RawRow {
+        [
+            RawValue::U64(age),
+            RawValue::Bool(happy),
+            RawValue::U64(visits),
+        ]
+        .into_iter()
+        .collect()
+    };
+    assert_eq!(
+        totals.read().unwrap().to_rows().unwrap(),
+        [total(48, true, 3)]
+    );
+    assert!(totals.insert_raw_rows([total(1, true, 1)]).is_err());
+    assert!(totals.compact().is_err());
+
+    // The rollup changes when the table it rolls up is compacted.
+    let people = db.table("people").unwrap();
+    people
+        .insert_raw_rows([person("Bob", 60, false, 4)])
+        .unwrap();
+    assert_eq!(
+        totals.read().unwrap().to_rows().unwrap(),
+        [total(48, true, 3)]
+    );
+    people.compact().unwrap();
+    assert_eq!(
+        totals.read().unwrap().to_rows().unwrap(),
+        [total(60, false, 7)]
+    );
+
+    drop(db);
+    let db = Database::open(dir.path()).unwrap();
+    let schema = db.schema("totals").unwrap();
+    assert_eq!(schema.rollup_of(), db.schema("people").map(|s| s.id()));
+    db.table("people")
+        .unwrap()
+        .insert_raw_rows([person("Carol", 70, true, 1)])
+        .unwrap();
+    db.table("people").unwrap().compact().unwrap();
+    assert_eq!(
+        db.table("totals")
+            .unwrap()
+            .read()
+            .unwrap()
+            .to_rows()
+            .unwrap(),
+        [total(70, true, 8)]
+    );
+}
+
+#[test]
+fn record_purges() {
+    use crate::{ColumnSchema, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("events");
+    let time = ColumnSchema::<u64>::new("time");
+    schema.set_time_column(&time).unwrap();
+    schema.add_primary(time.raw());
+    schema.set_retention(Duration::from_secs(60)).unwrap();
+    let events = db.create_table(schema.clone()).unwrap();
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let event = |t| [RawValue::U64(t)].into_iter().collect::<RawRow>();
+    events.insert_raw_rows([event(1), event(2)]).unwrap();
+    events.insert_raw_rows([event(3), event(now)]).unwrap();
+    events.compact().unwrap();
+    assert_eq!(events.read().unwrap().to_rows().unwrap(), [event(now)]);
+    // Nothing is recorded when nothing is purged.
+    events.compact().unwrap();
+
+    drop(db);
+    let db = Database::open(dir.path()).unwrap();
+    assert_eq!(db.schema("events").unwrap().to_string(), schema.to_string());
+    let purges = db.purge_results().read().unwrap().to_rows().unwrap();
+    assert_eq!(purges.len(), 1);
+    let purged = &purges[0].values;
+    assert_eq!(purged[0], RawValue::Bytes(schema.id().0.to_vec()));
+    assert_eq!(purged[4..], [RawValue::U64(1), RawValue::U64(3)]);
+}
+
+#[test]
+fn partitioned_table() {
+    use crate::{ColumnSchema, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("visits");
+    let site = ColumnSchema::<String>::new("site");
+    schema.add_primary(site.raw());
+    schema.add_sum(ColumnSchema::<u64>::new("visits").raw());
+    schema.partition_by(&site, 3).unwrap();
+    db.create_table(schema.clone()).unwrap();
+    db.execute("insert into visits (site, visits) values ('a.org', 3), ('b.org', 4)")
+        .unwrap();
+    db.execute("insert into visits (site, visits) values ('a.org', 2)")
+        .unwrap();
+
+    drop(db);
+    let db = Database::open(dir.path()).unwrap();
+    assert_eq!(db.schema("visits").unwrap().to_string(), schema.to_string());
+    let result = db
+        .execute("select visits from visits where site = 'a.org'")
+        .unwrap();
+    assert_eq!(result.rows(), [[RawValue::U64(5)]]);
+}
+
+#[test]
+fn shard_watermarks() {
+    use crate::{ColumnSchema, ShardSequence};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("events");
+    let position = ColumnSchema::<ShardSequence>::new("position");
+    schema.add_primary(position.raw());
+    schema.add_max(ColumnSchema::<String>::new("payload").raw());
+    schema.set_sequence_column(&position);
+    let events = db.create_table(schema.clone()).unwrap();
+    let event = |shard, sequence| -> RawRow {
+        let s = ShardSequence { shard, sequence };
+        let payload = format!("event {s}");
+        [s.into(), crate::lens::RawValues::from(payload)]
+            .into_iter()
+            .flat_map(|v: crate::lens::RawValues| v.0)
+            .collect()
+    };
+    events.insert_raw_rows([event(0, 1), event(0, 2)]).unwrap();
+    events.insert_raw_rows([event(1, 5)]).unwrap();
+    let csv = "position,payload\n1:3,event 1:3\n0:4,event 0:4\n";
+    events
+        .load_csv(&CsvLoader::new(&schema), csv.as_bytes())
+        .unwrap();
+    let expected = BTreeMap::from([(0, 4), (1, 5)]);
+    assert_eq!(events.shard_watermarks().unwrap(), expected);
+
+    // Rows saved before their watermark is advanced are not read up to the
+    // watermarks.
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(event(1, 9)).unwrap();
+    builder.save(table_dir(dir.path(), schema.id())).unwrap();
+    let (watermarks, rows) = events.read_to_watermarks().unwrap();
+    assert_eq!(watermarks, expected);
+    let all = events.read().unwrap().to_rows().unwrap();
+    assert_eq!(rows, &all[..all.len() - 1]);
+    assert_eq!(all.last(), Some(&event(1, 9)));
+
+    drop(events);
+    drop(db);
+    let db = Database::open(dir.path()).unwrap();
+    assert_eq!(db.schema("events").unwrap().to_string(), schema.to_string());
+    let events = db.table("events").unwrap();
+    events.upsert_rows([event(1, 9)]).unwrap();
+    assert_eq!(events.read_to_watermarks().unwrap().1, all);
+    assert_eq!(db.watermarks().read().unwrap().to_rows().unwrap().len(), 2);
+}
+
+#[test]
+fn scrub_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    db.create_table(test_schema()).unwrap();
+    let reports = db.scrub().unwrap();
+    let names = reports.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["tables", "columns", "people"]);
+    assert!(reports.iter().all(|(_, r)| r.is_clean()));
+
+    let results = db.scrub_results().read().unwrap().to_rows().unwrap();
+    assert_eq!(results.len(), 3);
+    let schema = scrub_schema();
+    let segments = results
+        .iter()
+        .map(|row| schema.get::<u64>(row, SCRUB_SEGMENTS).unwrap())
+        .sum::<u64>();
+    assert_eq!(
+        segments,
+        reports.iter().map(|(_, r)| r.segments()).sum::<u64>()
+    );
+    for row in results.iter() {
+        let corruptions: u64 = schema.get(row, SCRUB_CORRUPTIONS).unwrap();
+        assert_eq!(corruptions, 0);
+    }
+}
+
+#[test]
+fn verify_database() {
+    use crate::NestedLayout;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path())
+        .unwrap()
+        .with_layout(NestedLayout);
+    let people = db.create_table(test_schema()).unwrap();
+    db.execute(
+        "insert into people (name, age, happy) values ('David', 48, true), ('Alice', 30, false)",
+    )
+    .unwrap();
+    db.scrub().unwrap();
+    let report = verify_db(dir.path(), &NestedLayout);
+    assert!(report.is_clean(), "{:?}", report.inconsistencies());
+    assert_eq!(report.tables(), 9);
+    assert!(report.segments() >= 4);
+
+    // Under another layout, the people are nowhere to be found, which is no
+    // inconsistency, as the table could just be empty.
+    assert_eq!(
+        verify_db(dir.path(), &FlatLayout).segments() + 1,
+        report.segments()
+    );
+
+    let people_dir = dir.path().join(NestedLayout.table_dir(people.schema()));
+    std::fs::write(people_dir.join("MANIFEST"), b"garbage").unwrap();
+    let report = verify_db(dir.path(), &NestedLayout);
+    let found = report
+        .inconsistencies()
+        .iter()
+        .map(|i| (i.table(), i.segment()))
+        .collect::<Vec<_>>();
+    assert_eq!(found, [("people", None)]);
+    assert!(matches!(
+        report.inconsistencies()[0].kind(),
+        InconsistencyKind::Manifest(_)
+    ));
+
+    std::fs::write(table_dir(dir.path(), COLUMNS_TABLE).join("MANIFEST"), b"").unwrap();
+    let report = verify_db(dir.path(), &NestedLayout);
+    let kinds = report
+        .inconsistencies()
+        .iter()
+        .map(|i| matches!(i.kind(), InconsistencyKind::Catalog(_)))
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, [false, true]);
+}
+
+#[test]
+fn in_memory_database() {
+    use crate::RawValue;
+
+    let mut db = Database::in_memory();
+    let people = db.create_table(test_schema()).unwrap();
+    for age in [48, 49] {
+        people
+            .insert_raw_rows([[
+                RawValue::Bytes(b"David".to_vec()),
+                RawValue::U64(age),
+                RawValue::Bool(true),
+                RawValue::U64(1),
+            ]
+            .into_iter()
+            .collect()])
+            .unwrap();
+    }
+    people.compact().unwrap();
+    let rows = people.read().unwrap().to_rows().unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].values()[1], RawValue::U64(49));
+    assert_eq!(rows[0].values()[3], RawValue::U64(2));
+    let first = db.table_at("people", AsOf::Version(1)).unwrap();
+    assert_eq!(first.to_rows().unwrap()[0].values()[1], RawValue::U64(48));
+    assert_eq!(people.versions().unwrap().len(), 4);
+    assert!(db.scrub().unwrap().iter().all(|(_, r)| r.is_clean()));
+    assert!(!db.dir.exists());
+
+    let dir = db.dir.clone();
+    drop(db);
+    assert!(
+        Table::read(table_dir(&dir, people.schema().id()), people.schema())
+            .unwrap()
+            .to_rows()
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn single_writer() {
+    use crate::RawValue;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open_writable(dir.path()).unwrap();
+    let people = db.create_table(test_schema()).unwrap();
+    let row: RawRow = [
+        RawValue::Bytes(b"David".to_vec()),
+        RawValue::U64(48),
+        RawValue::Bool(true),
+        RawValue::U64(1),
+    ]
+    .into_iter()
+    .collect();
+    people.insert_raw_rows([row.clone()]).unwrap();
+
+    assert!(matches!(
+        Database::open_writable(dir.path()),
+        Err(StorageError::Locked(_))
+    ));
+    assert!(matches!(
+        save_db_schema(dir.path(), &[]),
+        Err(StorageError::Locked(_))
+    ));
+
+    // Readers may open the database while it is being written.
+    let mut reader = Database::open_read_only(dir.path()).unwrap();
+    let readable = reader.table("people").unwrap();
+    assert_eq!(
+        readable.read().unwrap().to_rows().unwrap(),
+        vec![row.clone()]
+    );
+    assert!(matches!(
+        readable.insert_raw_rows([row.clone()]),
+        Err(StorageError::ReadOnly)
+    ));
+    assert!(matches!(readable.compact(), Err(StorageError::ReadOnly)));
+    assert!(matches!(
+        reader.drop_table("people"),
+        Err(StorageError::ReadOnly)
+    ));
+    assert!(matches!(reader.scrub(), Err(StorageError::ReadOnly)));
+
+    drop(people);
+    drop(db);
+    let db = Database::open_writable(dir.path()).unwrap();
+    db.table("people").unwrap().insert_raw_rows([row]).unwrap();
+}
+
+#[test]
+fn table_stats() {
+    use crate::{ColumnSchema, RawColumn, RawKind, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("hits");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_sum(ColumnSchema::<u64>::new("hits").raw());
+    let hits = db.create_table(schema).unwrap();
+    assert_eq!(hits.stats().unwrap(), TableStats::default());
+
+    let rows = |ids: std::ops::Range<u64>| {
+        ids.map(|i| [RawValue::U64(i), RawValue::U64(1)].into_iter().collect())
+    };
+    hits.insert_raw_rows(rows(0..5_000)).unwrap();
+    hits.insert_raw_rows(rows(4_000..6_000)).unwrap();
+
+    // Rows sharing a key are counted once for each segment holding one.
+    let stats = hits.stats().unwrap();
+    assert_eq!((stats.segments(), stats.rows()), (2, 7_000));
+    assert!(stats.on_disk_size() > 0);
+    let table = hits.read().unwrap();
+    assert_eq!(table.num_rows(), 7_000);
+    assert_eq!(table.on_disk_size(), stats.on_disk_size());
+    assert_eq!(table.to_rows().unwrap().len(), 6_000);
+    assert_eq!(db.stats().unwrap(), vec![("hits".to_string(), stats)]);
+
+    let mut encoded = Vec::new();
+    let values = (0..100).map(RawValue::U64).collect::<Vec<_>>();
+    RawColumn::write_values(&mut encoded, RawKind::U64, &values).unwrap();
+    let column = RawColumn::decode(encoded.clone()).unwrap();
+    assert_eq!(column.byte_len(), encoded.len() as u64);
+    assert_eq!((column.num_rows(), column.num_chunks()), (100, 100));
+}
+
+#[test]
+fn nested_layout() {
+    use crate::{NestedLayout, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path())
+        .unwrap()
+        .with_layout(NestedLayout);
+    let people = db.create_table(test_schema()).unwrap();
+    let rows = |range: std::ops::Range<u64>| {
+        range
+            .map(|i| {
+                let name = RawValue::Bytes(format!("person {i}").into_bytes());
+                [
+                    name,
+                    RawValue::U64(i % 90),
+                    RawValue::Bool(true),
+                    RawValue::U64(1),
+                ]
+                .into_iter()
+                .collect::<RawRow>()
+            })
+            .collect::<Vec<_>>()
+    };
+    people.insert_raw_rows(rows(0..2000)).unwrap();
+    people.insert_raw_rows(rows(2000..4000)).unwrap();
+
+    // The table is named for people, and each segment has a directory.
+    let table_dir = dir.path().join(NestedLayout.table_dir(people.schema()));
+    assert!(table_dir
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("people-"));
+    let segment_dirs = || {
+        let mut dirs = fs::list_dir(&table_dir)
+            .unwrap()
+            .into_iter()
+            .filter(|p| p.is_dir())
+            .map(|p| p.file_name().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs
+    };
+    assert_eq!(segment_dirs(), ["segment-00000000", "segment-00000001"]);
+
+    // Directories of segments that are forgotten are removed.
+    people.compact().unwrap();
+    let (latest, _) = *people.versions().unwrap().last().unwrap();
+    people.forget_versions(latest).unwrap();
+    assert_eq!(segment_dirs(), ["segment-00000002"]);
+
+    drop(db);
+    let db = Database::open(dir.path())
+        .unwrap()
+        .with_layout(NestedLayout);
+    let read = db
+        .table("people")
+        .unwrap()
+        .read()
+        .unwrap()
+        .to_rows()
+        .unwrap();
+    let mut expected = rows(0..4000);
+    expected.sort();
+    assert_eq!(read, expected);
+}
+
+#[test]
+fn column_constraints() {
+    use crate::{ColumnSchema, Constraint, RawValue};
+
+    let id = ColumnSchema::<u64>::new("id");
+    let email = ColumnSchema::<String>::new("email");
+    let name = ColumnSchema::<String>::new("name");
+    let mut schema = TableSchema::new("users");
+    schema.add_primary(id.raw());
+    schema.add_max(email.raw().chain(name.raw()));
+    schema.add_sum(ColumnSchema::<u64>::new("logins").raw());
+    schema.add_constraint(&name, Constraint::NotNull).unwrap();
+    schema.add_constraint(&email, Constraint::Unique).unwrap();
+    assert!(schema.add_constraint(&id, Constraint::Unique).is_err());
+    assert!(schema
+        .add_constraint(&ColumnSchema::<u64>::new("logins"), Constraint::NotNull)
+        .is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    db.create_table(schema.clone()).unwrap();
+    db.execute("insert into users (id, email, name) values (1, 'a@x', 'Ann'), (2, '', 'Bo')")
+        .unwrap();
+    drop(db);
+
+    let db = Database::open(dir.path()).unwrap();
+    let users = db.table("users").unwrap();
+    assert_eq!(users.schema().to_string(), schema.to_string());
+    let broken = |sql: &str| match db.execute(sql) {
+        Err(StorageError::Constraint {
+            column,
+            constraint,
+            key,
+            ..
+        }) => (column, constraint, key),
+        other => panic!("{sql} gave {other:?}"),
+    };
+    assert_eq!(
+        broken("insert into users (id, email) values (3, 'c@x')"),
+        (
+            "name".to_string(),
+            Constraint::NotNull,
+            vec![RawValue::U64(3)]
+        )
+    );
+    assert_eq!(
+        broken("insert into users (id, email, name) values (3, 'a@x', 'Cy')"),
+        (
+            "email".to_string(),
+            Constraint::Unique,
+            vec![RawValue::U64(3)]
+        )
+    );
+    assert_eq!(
+        broken("insert into users (id, email, name) values (4, 'd@x', 'Di'), (5, 'd@x', 'Ed')"),
+        (
+            "email".to_string(),
+            Constraint::Unique,
+            vec![RawValue::U64(5)]
+        )
+    );
+
+    // Rows without an email share no value, and a row may keep its own.
+    db.execute("insert into users (id, name) values (6, 'Fay')")
+        .unwrap();
+    db.execute("insert into users (id, email, name, logins) values (1, 'a@x', 'Ann', 1)")
+        .unwrap();
+    assert_eq!(users.read().unwrap().to_rows().unwrap().len(), 3);
+
+    // Rows saved around the handle are caught by compacting.
+    let mut builder = TableBuilder::new(&schema);
+    builder
+        .insert_raw_row(schema.row(vec![
+            (id.id(), 7u64.into()),
+            (email.id(), "a@x".to_string().into()),
+            (name.id(), "Gus".to_string().into()),
+        ]))
+        .unwrap();
+    builder.save(&users.dir).unwrap();
+    assert!(matches!(
+        users.compact(),
+        Err(StorageError::Constraint { key, .. }) if key == [RawValue::U64(7)]
+    ));
+}
@@ -0,0 +1,517 @@
+//! Backing up a database to a single archive, and restoring it.
+//!
+//! An archive holds the files of every table, including the tables that
+//! keep the schemas, so it describes itself.  Each table is copied as it is
+//! at its current version, see [`Database::backup_to`], and its column files
+//! come before its manifest, which is checked against them when it is
+//! restored.
+//!
+//! Segments never change once saved, so an incremental backup, made by
+//! [`Database::backup_since`], holds only the column files that an earlier
+//! backup does not, along with the manifest of every table.  Restoring a
+//! full backup and then each incremental one after it, with
+//! [`Database::restore_chain`], gives the database as of the last.
+//!
+//! An archive starts with a magic number, which tells a full backup from an
+//! incremental one, followed by entries that each start with a kind byte
+//! and the directory of their table within that of the database.  A file
+//! entry then has the name of the file within the directory of the table
+//! and its bytes, and a manifest entry the bytes of the manifest.  An end
+//! byte follows the last entry, so an archive that is cut short is not
+//! mistaken for a smaller database.  Every name and sequence of bytes is
+//! written as its length in eight bytes followed by the bytes.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::{table_dir, Database, SYSTEM_TABLES};
+use crate::column::encoding::{StorageError, WriteEncoded};
+use crate::lens::TableId;
+use crate::schema::catalog::{COLUMNS_TABLE, TABLES_TABLE};
+use crate::table::{manifest_files, PinnedFiles, Restoring};
+use crate::{db_schema_schema, fs, table_schema_schema, AsOf, Table, TableSchema};
+
+const BACKUP_MAGIC: u64 = u64::from_be_bytes(*b"eqbackup");
+const INCREMENTAL_MAGIC: u64 = u64::from_be_bytes(*b"eqbackin");
+
+const END: u8 = 0;
+const FILE: u8 = 1;
+const MANIFEST: u8 = 2;
+
+/// A name of a file or directory in an archive, with `/` between the
+/// directories holding it
+fn archive_name(path: &Path) -> Result<String, StorageError> {
+    path.components()
+        .map(|c| match c {
+            Component::Normal(c) => c.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|names| names.join("/"))
+        .ok_or_else(|| StorageError::Backup(format!("bad path {}", path.display())))
+}
+
+fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> Result<(), StorageError> {
+    out.write_u64(bytes.len() as u64)?;
+    out.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_u8<R: Read>(input: &mut R) -> Result<u8, StorageError> {
+    let mut v = [0];
+    input.read_exact(&mut v)?;
+    Ok(v[0])
+}
+
+fn read_u64<R: Read>(input: &mut R) -> Result<u64, StorageError> {
+    let mut v = [0; 8];
+    input.read_exact(&mut v)?;
+    Ok(u64::from_be_bytes(v))
+}
+
+/// Read bytes written by [`write_bytes`], growing the vector as they are
+/// read rather than trusting the length with one big allocation
+fn read_bytes<R: Read>(input: &mut R) -> Result<Vec<u8>, StorageError> {
+    let len = read_u64(input)?;
+    let mut v = Vec::new();
+    if input.take(len).read_to_end(&mut v)? as u64 != len {
+        return Err(StorageError::Backup("archive is cut short".to_string()));
+    }
+    Ok(v)
+}
+
+fn read_name<R: Read>(input: &mut R) -> Result<String, StorageError> {
+    String::from_utf8(read_bytes(input)?)
+        .map_err(|_| StorageError::Backup("bad name in archive".to_string()))
+}
+
+/// The column files held by a backup and those before it, so that a later
+/// backup can leave them out, see [`Database::backup_since`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupManifest {
+    /// The files of each table, with their checksums, by the directory of
+    /// the table
+    tables: BTreeMap<String, BTreeMap<String, Option<u64>>>,
+}
+
+impl BackupManifest {
+    /// Read the manifest of the backup in `archive`, as returned by the
+    /// [`Database::backup_to`] or [`Database::backup_since`] that wrote it
+    pub fn read<R: Read>(mut archive: R) -> Result<Self, StorageError> {
+        let magic = read_u64(&mut archive)?;
+        if ![BACKUP_MAGIC, INCREMENTAL_MAGIC].contains(&magic) {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let mut manifest = BackupManifest::default();
+        loop {
+            match read_u8(&mut archive)? {
+                END => return Ok(manifest),
+                FILE => {
+                    read_name(&mut archive)?;
+                    read_name(&mut archive)?;
+                    let len = read_u64(&mut archive)?;
+                    if std::io::copy(&mut (&mut archive).take(len), &mut std::io::sink())? != len {
+                        return Err(StorageError::Backup("archive is cut short".to_string()));
+                    }
+                }
+                MANIFEST => {
+                    let table = read_name(&mut archive)?;
+                    let files = manifest_files(&read_bytes(&mut archive)?)?;
+                    manifest.tables.insert(table, files);
+                }
+                kind => return Err(StorageError::Backup(format!("unknown entry kind {kind}"))),
+            }
+        }
+    }
+
+    /// The number of column files
+    pub fn num_files(&self) -> usize {
+        self.tables.values().map(|files| files.len()).sum()
+    }
+}
+
+impl Database {
+    /// Write a backup of every table to `out`, as a single archive that
+    /// [`Database::restore_from`] can restore, returning the manifest of the
+    /// backup for [`Database::backup_since`].
+    ///
+    /// The current version of every table is pinned before any file is
+    /// copied, see [`Table::read_at`], so writers can keep saving to them.
+    /// The tables are pinned one after another, so each is backed up as it
+    /// was at one moment, but a table saved to while others were pinned may
+    /// be backed up as it was a little later than they were.  Earlier
+    /// versions are left out, and so are rows given to an
+    /// [`Ingestor`](crate::Ingestor) that it has not yet saved.
+    ///
+    /// Column files are already encoded compactly, so the archive is not
+    /// compressed, but `out` may compress it further.
+    pub fn backup_to<W: Write>(&self, out: W) -> Result<BackupManifest, StorageError> {
+        self.backup(out, BACKUP_MAGIC, &BackupManifest::default())
+    }
+
+    /// Write an incremental backup to `out`, holding only the column files
+    /// that are not in `since`, the manifest of an earlier backup.
+    ///
+    /// The returned manifest lists the files of this backup and those before
+    /// it, so the next incremental backup can build on it.  A table
+    /// compacted since the earlier backup has all of its segments copied
+    /// again, since compacting saves them anew.  See
+    /// [`Database::backup_to`] for what is backed up.
+    pub fn backup_since<W: Write>(
+        &self,
+        out: W,
+        since: &BackupManifest,
+    ) -> Result<BackupManifest, StorageError> {
+        self.backup(out, INCREMENTAL_MAGIC, since)
+    }
+
+    fn backup<W: Write>(
+        &self,
+        mut out: W,
+        magic: u64,
+        since: &BackupManifest,
+    ) -> Result<BackupManifest, StorageError> {
+        let pin = |relative: PathBuf| -> Result<_, StorageError> {
+            let files = PinnedFiles::pin(&self.dir.join(&relative))?;
+            Ok((relative, files))
+        };
+        let system_dir = |id: TableId| PathBuf::from(format!("{:032x}", u128::from_be_bytes(id.0)));
+        // The schemas are read as of the versions pinned, so that no table is
+        // missed that was created since the database was opened.
+        let mut pinned = vec![
+            pin(system_dir(TABLES_TABLE))?,
+            pin(system_dir(COLUMNS_TABLE))?,
+        ];
+        let tables = Table::read_at(
+            table_dir(&self.dir, TABLES_TABLE),
+            &db_schema_schema(),
+            AsOf::Version(pinned[0].1.version()),
+        )?
+        .to_rows()?;
+        let columns = Table::read_at(
+            table_dir(&self.dir, COLUMNS_TABLE),
+            &table_schema_schema(),
+            AsOf::Version(pinned[1].1.version()),
+        )?
+        .to_rows()?;
+        for id in SYSTEM_TABLES.iter() {
+            if ![TABLES_TABLE, COLUMNS_TABLE].contains(id) {
+                pinned.push(pin(system_dir(*id))?);
+            }
+        }
+        for (_, schema) in TableSchema::from_catalog(&tables, &columns)?.iter() {
+            pinned.push(pin(self.layout.table_dir(schema))?);
+        }
+
+        let mut backed_up = BackupManifest::default();
+        let none = BTreeMap::new();
+        out.write_u64(magic)?;
+        for (relative, files) in pinned.iter_mut() {
+            let name = archive_name(relative)?;
+            let copied = since.tables.get(&name).unwrap_or(&none);
+            let manifest = files.copy(&self.dir.join(&relative), copied, |filename, bytes| {
+                out.write_u8(FILE)?;
+                write_bytes(&mut out, name.as_bytes())?;
+                write_bytes(&mut out, filename.as_bytes())?;
+                write_bytes(&mut out, bytes)
+            })?;
+            out.write_u8(MANIFEST)?;
+            write_bytes(&mut out, name.as_bytes())?;
+            write_bytes(&mut out, &manifest)?;
+            backed_up.tables.insert(name, manifest_files(&manifest)?);
+        }
+        out.write_u8(END)?;
+        out.flush()?;
+        Ok(backed_up)
+    }
+
+    /// Restore the archive written by [`Database::backup_to`] into `dir`,
+    /// which must be empty or not exist, and open the database for writing.
+    ///
+    /// Each column file is checked against the checksum in its manifest.  If
+    /// the archive is cut short or corrupt, this fails and leaves `dir`
+    /// partly restored, so it must be emptied before trying again.  The
+    /// tables are restored in the directories they had, so the database
+    /// must be opened with the same [`DbLayout`](crate::DbLayout) as the one
+    /// backed up.
+    pub fn restore_from<P: AsRef<Path>, R: Read>(dir: P, backup: R) -> Result<Self, StorageError> {
+        Database::restore_chain(dir, [backup])
+    }
+
+    /// Restore a full backup written by [`Database::backup_to`] followed by
+    /// incremental backups written by [`Database::backup_since`], each made
+    /// since the one before it, into `dir`, see [`Database::restore_from`].
+    ///
+    /// After each backup, every table must have all the column files its
+    /// manifest lists, from that backup or an earlier one, so a chain with a
+    /// backup missing or out of order fails.  Tables dropped between backups
+    /// are removed.
+    pub fn restore_chain<P: AsRef<Path>, R: Read>(
+        dir: P,
+        backups: impl IntoIterator<Item = R>,
+    ) -> Result<Self, StorageError> {
+        let dir = dir.as_ref();
+        if !fs::list_dir(dir)?.is_empty() {
+            return Err(StorageError::Backup(format!(
+                "{} is not empty",
+                dir.display()
+            )));
+        }
+        fs::create_dir_all(dir)?;
+        let lock = fs::lock(dir)?;
+        let table_dir = |name: &str| -> Result<PathBuf, StorageError> {
+            let relative = Path::new(name);
+            if relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                Ok(dir.join(relative))
+            } else {
+                Err(StorageError::Backup(format!(
+                    "bad table directory {name:?}"
+                )))
+            }
+        };
+        // The tables restored by the backups so far
+        let mut restored = BTreeMap::<String, Restoring>::new();
+        let mut backups = backups.into_iter().peekable();
+        if backups.peek().is_none() {
+            return Err(StorageError::Backup("no backup to restore".to_string()));
+        }
+        for (i, mut backup) in backups.enumerate() {
+            let magic = read_u64(&mut backup)?;
+            match magic {
+                BACKUP_MAGIC if i == 0 => (),
+                INCREMENTAL_MAGIC if i > 0 => (),
+                BACKUP_MAGIC | INCREMENTAL_MAGIC => {
+                    return Err(StorageError::Backup(
+                        "a chain of backups must start with its only full backup".to_string(),
+                    ))
+                }
+                _ => return Err(StorageError::BadMagic(magic)),
+            }
+            let mut earlier = std::mem::take(&mut restored);
+            let mut restoring = BTreeMap::<String, Restoring>::new();
+            loop {
+                match read_u8(&mut backup)? {
+                    END => break,
+                    FILE => {
+                        let table = read_name(&mut backup)?;
+                        let filename = read_name(&mut backup)?;
+                        let bytes = read_bytes(&mut backup)?;
+                        let tdir = table_dir(&table)?;
+                        if !restoring.contains_key(&table) {
+                            let files = earlier.remove(&table).unwrap_or_default();
+                            restoring.insert(table.clone(), files);
+                        }
+                        restoring
+                            .get_mut(&table)
+                            .expect("just inserted")
+                            .file(&tdir, &filename, &bytes)?;
+                    }
+                    MANIFEST => {
+                        let table = read_name(&mut backup)?;
+                        let manifest = read_bytes(&mut backup)?;
+                        let tdir = table_dir(&table)?;
+                        let mut files = restoring
+                            .remove(&table)
+                            .or_else(|| earlier.remove(&table))
+                            .unwrap_or_default();
+                        files.finish(&tdir, manifest)?;
+                        restored.insert(table, files);
+                    }
+                    kind => return Err(StorageError::Backup(format!("unknown entry kind {kind}"))),
+                }
+            }
+            if let Some(table) = restoring.keys().next() {
+                return Err(StorageError::Backup(format!("{table} has no manifest")));
+            }
+            // Every table is in every backup, so those left were dropped.
+            for table in earlier.keys() {
+                fs::remove_dir_all(&table_dir(table)?)?;
+            }
+        }
+        Database::open_locked(dir.to_path_buf(), lock)
+    }
+}
+
+#[test]
+fn backup_and_restore() {
+    use crate::{ColumnSchema, NestedLayout, RawRow, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path().join("db"))
+        .unwrap()
+        .with_layout(NestedLayout);
+    let people = db.create_table(super::test_schema()).unwrap();
+    let person = |i: u64| {
+        let name = RawValue::Bytes(format!("person {i}").into_bytes());
+        [
+            name,
+            RawValue::U64(i % 90),
+            RawValue::Bool(true),
+            RawValue::U64(1),
+        ]
+        .into_iter()
+        .collect::<RawRow>()
+    };
+    people.insert_raw_rows((0..3000).map(person)).unwrap();
+    people.insert_raw_rows((2000..4000).map(person)).unwrap();
+    let mut schema = TableSchema::new("tiny");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    let tiny = db.create_table(schema).unwrap();
+    tiny.insert_raw_rows([[RawValue::U64(7)].into_iter().collect()])
+        .unwrap();
+
+    let mut archive = Vec::new();
+    db.backup_to(&mut archive).unwrap();
+    // Rows saved after the backup are not in it.
+    let backed_up = people.read().unwrap().to_rows().unwrap();
+    people.insert_raw_rows([person(5000)]).unwrap();
+    people.compact().unwrap();
+
+    let restored = Database::restore_from(dir.path().join("restored"), &archive[..])
+        .unwrap()
+        .with_layout(NestedLayout);
+    let mut names = restored.schemas().map(|s| s.name()).collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(names, ["people", "tiny"]);
+    let people = restored.table("people").unwrap();
+    assert_eq!(people.read().unwrap().to_rows().unwrap(), backed_up);
+    assert_eq!(people.versions().unwrap().len(), 1);
+    assert_eq!(
+        restored
+            .table("tiny")
+            .unwrap()
+            .read()
+            .unwrap()
+            .to_rows()
+            .unwrap(),
+        tiny.read().unwrap().to_rows().unwrap()
+    );
+    assert!(restored.scrub().unwrap().iter().all(|(_, r)| r.is_clean()));
+    drop(restored);
+
+    // An archive is only restored into an empty directory.
+    assert!(Database::restore_from(dir.path().join("restored"), &archive[..]).is_err());
+    // An archive that is cut short or corrupt is not restored.
+    let short = &archive[..archive.len() - 1];
+    assert!(Database::restore_from(dir.path().join("short"), short).is_err());
+    // A column file whose bytes are changed fails its checksum.
+    fn largest_file(dir: &Path) -> Vec<u8> {
+        let mut largest = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let bytes = if path.is_dir() {
+                largest_file(&path)
+            } else if path.ends_with("MANIFEST") {
+                continue;
+            } else {
+                std::fs::read(&path).unwrap()
+            };
+            if bytes.len() > largest.len() {
+                largest = bytes;
+            }
+        }
+        largest
+    }
+    let column = largest_file(&dir.path().join("restored"));
+    let at = archive
+        .windows(column.len())
+        .position(|w| w == column)
+        .unwrap();
+    let mut corrupt = archive.clone();
+    corrupt[at + column.len() / 2] ^= 0xff;
+    assert!(Database::restore_from(dir.path().join("corrupt"), &corrupt[..]).is_err());
+}
+
+#[test]
+fn incremental_backups() {
+    use crate::{ColumnSchema, RawRow, RawValue};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path().join("db")).unwrap();
+    let hits = |ids: std::ops::Range<u64>| {
+        ids.map(|i| {
+            [RawValue::U64(i), RawValue::U64(1)]
+                .into_iter()
+                .collect::<RawRow>()
+        })
+    };
+    let schema = |name: &str| {
+        let mut schema = TableSchema::new(name);
+        schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+        schema.add_sum(ColumnSchema::<u64>::new("hits").raw());
+        schema
+    };
+    let table = db.create_table(schema("hits")).unwrap();
+    let others = db.create_table(schema("others")).unwrap();
+    table.insert_raw_rows(hits(0..5_000)).unwrap();
+    others.insert_raw_rows(hits(0..5_000)).unwrap();
+
+    let mut full = Vec::new();
+    let manifest = db.backup_to(&mut full).unwrap();
+    assert_eq!(BackupManifest::read(&full[..]).unwrap(), manifest);
+
+    table.insert_raw_rows(hits(4_000..8_000)).unwrap();
+    let mut first = Vec::new();
+    let manifest = db.backup_since(&mut first, &manifest).unwrap();
+    assert_eq!(BackupManifest::read(&first[..]).unwrap(), manifest);
+    // Only the files of the new segment are copied, along with the manifests.
+    let copied = |mut archive: &[u8]| {
+        let mut files = Vec::new();
+        read_u64(&mut archive).unwrap();
+        loop {
+            match read_u8(&mut archive).unwrap() {
+                END => return files,
+                FILE => files.push((
+                    read_name(&mut archive).unwrap(),
+                    read_name(&mut archive).unwrap(),
+                )),
+                _ => {
+                    read_name(&mut archive).unwrap();
+                }
+            }
+            read_bytes(&mut archive).unwrap();
+        }
+    };
+    assert_eq!(copied(&full).len(), 4);
+    let new = copied(&first);
+    assert_eq!(new.len(), 2);
+    assert!(new.iter().all(|f| !copied(&full).contains(f)));
+
+    table.insert_raw_rows(hits(7_000..9_000)).unwrap();
+    db.drop_table("others").unwrap();
+    let mut second = Vec::new();
+    db.backup_since(&mut second, &manifest).unwrap();
+
+    let restored =
+        Database::restore_chain(dir.path().join("restored"), [&full[..], &first, &second]).unwrap();
+    assert_eq!(
+        restored.schemas().map(|s| s.name()).collect::<Vec<_>>(),
+        ["hits"]
+    );
+    assert_eq!(
+        restored
+            .table("hits")
+            .unwrap()
+            .read()
+            .unwrap()
+            .to_rows()
+            .unwrap(),
+        table.read().unwrap().to_rows().unwrap()
+    );
+    assert!(restored.scrub().unwrap().iter().all(|(_, r)| r.is_clean()));
+    drop(restored);
+
+    // A chain that misses a backup, or does not start with a full one, is
+    // not restored.
+    let restore = |name: &str, chain: &[&[u8]]| {
+        Database::restore_chain(dir.path().join(name), chain.iter().copied())
+    };
+    assert!(restore("missing", &[&full, &second]).is_err());
+    assert!(restore("no full", &[&first, &second]).is_err());
+    assert!(restore("two full", &[&full, &full]).is_err());
+    assert!(restore("empty", &[]).is_err());
+}
@@ -0,0 +1,216 @@
+//! Several named databases kept under one root directory.
+//!
+//! The databases are listed in a table of the root, described by
+//! [`databases_schema`], and each is kept in a directory of the root named
+//! after it.  Dropping a database saves a new row for it with a later
+//! `modified` time, which wins when the rows are merged.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::{table_dir, Database};
+use crate::column::encoding::StorageError;
+use crate::fs;
+use crate::query::QueryResult;
+use crate::schema::catalog::{DATABASES_TABLE, DATABASE_DELETED, DATABASE_MODIFIED, DATABASE_NAME};
+use crate::{databases_schema, Table, TableBuilder};
+
+/// The named databases kept under one root directory.
+///
+/// SQL run with [`Catalog::execute`] may name a table of another database
+/// of the catalog as `database.table`.
+pub struct Catalog {
+    dir: PathBuf,
+    databases: BTreeMap<String, Database>,
+    last_modified: SystemTime,
+    /// The lock held while the catalog is open, released when it is dropped
+    _lock: Option<std::fs::File>,
+}
+
+impl Catalog {
+    /// Open the catalog in `dir` for writing, creating it if needed, along
+    /// with each of its databases.
+    ///
+    /// Only one writer may have a catalog open at a time, as for
+    /// [`Database::open_writable`].
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let lock = fs::lock(&dir)?;
+        let schema = databases_schema();
+        let rows = Table::read(table_dir(&dir, DATABASES_TABLE), &schema)?.to_rows()?;
+        let mut databases = BTreeMap::new();
+        for row in rows {
+            if !schema.get::<bool>(&row, DATABASE_DELETED)? {
+                let name = schema.get::<String>(&row, DATABASE_NAME)?;
+                let db = Database::open(dir.join(&name))?;
+                databases.insert(name, db);
+            }
+        }
+        Ok(Catalog {
+            dir,
+            databases,
+            last_modified: SystemTime::UNIX_EPOCH,
+            _lock: lock,
+        })
+    }
+
+    /// The names of the databases, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.databases.keys().map(|n| n.as_str())
+    }
+
+    /// The database called `name`
+    pub fn database(&self, name: &str) -> Result<&Database, StorageError> {
+        self.databases
+            .get(name)
+            .ok_or_else(|| StorageError::Schema(format!("no database {name}")))
+    }
+
+    /// The database called `name`, to create or alter its tables
+    pub fn database_mut(&mut self, name: &str) -> Result<&mut Database, StorageError> {
+        self.databases
+            .get_mut(name)
+            .ok_or_else(|| StorageError::Schema(format!("no database {name}")))
+    }
+
+    /// Create an empty database called `name`.
+    ///
+    /// The name may hold only ASCII letters, digits and `_`, so that it is
+    /// the name of its directory and is never mistaken for part of the name
+    /// of a table.
+    pub fn create(&mut self, name: &str) -> Result<&mut Database, StorageError> {
+        let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if name.is_empty() || !valid || name == "information_schema" {
+            return Err(StorageError::Schema(format!(
+                "{name:?} cannot name a database"
+            )));
+        }
+        if self.databases.contains_key(name) {
+            return Err(StorageError::Schema(format!(
+                "database {name} already exists"
+            )));
+        }
+        let db = Database::open(self.dir.join(name))?;
+        self.save(name, false)?;
+        Ok(self.databases.entry(name.to_string()).or_insert(db))
+    }
+
+    /// Drop the database called `name`, deleting its tables
+    pub fn drop_database(&mut self, name: &str) -> Result<(), StorageError> {
+        let db = self
+            .databases
+            .remove(name)
+            .ok_or_else(|| StorageError::Schema(format!("no database {name}")))?;
+        self.save(name, true)?;
+        drop(db);
+        match fs::remove_dir_all(&self.dir.join(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Run a single SQL statement in the database called `database`, see
+    /// [`Database::execute`].
+    ///
+    /// A table named `other.table`, where `other` is a database of the
+    /// catalog, is the table of that database, so one statement may join
+    /// tables of different databases.
+    pub fn execute(&self, database: &str, sql: &str) -> Result<QueryResult, StorageError> {
+        let current = self.database(database)?;
+        crate::query::execute(
+            current,
+            &|table| match table.split_once('.') {
+                Some((name, rest)) if self.databases.contains_key(name) => {
+                    Ok((&self.databases[name], rest.to_string()))
+                }
+                _ => Ok((current, table.to_string())),
+            },
+            sql,
+        )
+    }
+
+    /// Record whether the database called `name` exists
+    fn save(&mut self, name: &str, is_deleted: bool) -> Result<(), StorageError> {
+        let now = SystemTime::now();
+        self.last_modified = std::cmp::max(now, self.last_modified + Duration::from_nanos(1));
+        let schema = databases_schema();
+        let mut builder = TableBuilder::new(&schema);
+        builder.insert_raw_row(schema.row(vec![
+            (DATABASE_NAME, name.to_string().into()),
+            (DATABASE_MODIFIED, self.last_modified.into()),
+            (DATABASE_DELETED, is_deleted.into()),
+        ]))?;
+        builder.save(table_dir(&self.dir, DATABASES_TABLE))
+    }
+}
+
+#[test]
+fn databases_of_catalog() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut catalog = Catalog::open(dir.path()).unwrap();
+    for name in ["analytics", "sales", "scratch"] {
+        catalog.create(name).unwrap();
+    }
+    for bad in ["", "a.b", "../up", "information_schema", "sales"] {
+        assert!(catalog.create(bad).is_err(), "{bad:?}");
+    }
+    catalog.drop_database("scratch").unwrap();
+    catalog
+        .database_mut("analytics")
+        .unwrap()
+        .create_table(super::test_schema())
+        .unwrap();
+    let mut orders = crate::TableSchema::new("orders");
+    orders.add_primary(crate::ColumnSchema::<u64>::new("id").raw());
+    orders.add_max(crate::ColumnSchema::<String>::new("buyer").raw());
+    catalog
+        .database_mut("sales")
+        .unwrap()
+        .create_table(orders)
+        .unwrap();
+    drop(catalog);
+
+    let catalog = Catalog::open(dir.path()).unwrap();
+    assert_eq!(catalog.names().collect::<Vec<_>>(), ["analytics", "sales"]);
+    assert!(!dir.path().join("scratch").exists());
+    catalog
+        .execute(
+            "analytics",
+            "INSERT INTO people (name, age, happy, visits) VALUES ('Ann', 30, true, 1)",
+        )
+        .unwrap();
+    catalog
+        .execute("sales", "INSERT INTO orders (id, buyer) VALUES (7, 'Ann')")
+        .unwrap();
+
+    // Tables of the current database need no prefix, and those of others
+    // are named by their database.
+    let result = catalog
+        .execute(
+            "sales",
+            "SELECT id, age FROM orders o JOIN analytics.people p ON o.buyer = p.name",
+        )
+        .unwrap();
+    assert_eq!(
+        result.rows(),
+        [[crate::RawValue::U64(7), crate::RawValue::U64(30)]]
+    );
+    let result = catalog
+        .execute("analytics", "SELECT id FROM sales.orders")
+        .unwrap();
+    assert_eq!(result.rows().len(), 1);
+    assert!(catalog
+        .execute("analytics", "SELECT id FROM orders")
+        .is_err());
+    let result = catalog
+        .execute(
+            "sales",
+            "SELECT table_name FROM analytics.information_schema.tables",
+        )
+        .unwrap();
+    assert!(result
+        .rows()
+        .contains(&vec![crate::RawValue::Bytes(b"people".to_vec())]));
+}
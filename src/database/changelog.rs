@@ -0,0 +1,260 @@
+//! The changelog, recording every batch of rows inserted into or deleted
+//! from the tables that record their changes.
+//!
+//! Each batch is given a commit id, which is the version of the changelog
+//! table made by saving it, so the ids of later batches are always larger.
+//! A reader that remembers the last commit it has seen can then ask for just
+//! the changes since, see [`Database::changes_since`].
+//!
+//! The rows of a batch are stored with their values encoded as bytes, since
+//! the tables they come from have different columns.
+
+use std::path::Path;
+
+use super::{table_dir, Database, TableHandle};
+use crate::column::encoding::{ReadEncoded, StorageError, WriteEncoded};
+use crate::column::storage::Storage;
+use crate::lens::TableId;
+use crate::schema::catalog::{
+    CHANGED_TABLE, CHANGELOG_TABLE, CHANGE_DELETED, CHANGE_ROW, CHANGE_VALUES, COMMIT,
+};
+use crate::{changelog_schema, Comparison, Expr, RawRow, RawValue, Table, TableBuilder};
+
+/// A batch of rows inserted into or deleted from a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    commit: u64,
+    table: String,
+    deleted: bool,
+    rows: Vec<RawRow>,
+}
+
+impl Change {
+    /// The commit id of the batch, larger than that of any earlier batch
+    pub fn commit(&self) -> u64 {
+        self.commit
+    }
+
+    /// The name of the table
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Whether the rows were deleted rather than inserted
+    pub fn deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// The rows, as they were inserted or as they were before being deleted
+    pub fn rows(&self) -> &[RawRow] {
+        &self.rows
+    }
+}
+
+const U64_KIND: u8 = 0;
+const BOOL_KIND: u8 = 1;
+const BYTES_KIND: u8 = 2;
+
+fn encode_row(row: &RawRow) -> Result<Vec<u8>, StorageError> {
+    let mut out = Vec::new();
+    out.write_unsigned(row.values.len() as u64)?;
+    for value in row.values.iter() {
+        match value {
+            RawValue::U64(v) => {
+                out.write_u8(U64_KIND)?;
+                out.write_unsigned(*v)?;
+            }
+            RawValue::Bool(v) => {
+                out.write_u8(BOOL_KIND)?;
+                out.write_u8(*v as u8)?;
+            }
+            RawValue::Bytes(v) => {
+                out.write_u8(BYTES_KIND)?;
+                out.write_unsigned(v.len() as u64)?;
+                out.extend_from_slice(v);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn decode_row(bytes: Vec<u8>) -> Result<RawRow, StorageError> {
+    let mut input = Storage::from(bytes);
+    let len = input.read_usigned()?;
+    (0..len)
+        .map(|_| match input.read_u8()? {
+            U64_KIND => Ok(RawValue::U64(input.read_usigned()?)),
+            BOOL_KIND => Ok(RawValue::Bool(input.read_u8()? != 0)),
+            BYTES_KIND => {
+                let len = input.read_usigned()?;
+                Ok(RawValue::Bytes(input.read_vec(len)?))
+            }
+            _ => Err(StorageError::OutOfBounds("unknown kind of changed value")),
+        })
+        .collect()
+}
+
+impl TableHandle {
+    /// Record `rows` as a batch inserted into or deleted from the table, if
+    /// the table records its changes.
+    pub(super) fn record_changes(
+        &self,
+        deleted: bool,
+        rows: &[RawRow],
+    ) -> Result<(), StorageError> {
+        let Some(db_dir) = self.db_dir.as_ref() else {
+            return Ok(());
+        };
+        if !self.schema.records_changes() || rows.is_empty() {
+            return Ok(());
+        }
+        let dir = table_dir(db_dir, CHANGELOG_TABLE);
+        let commit = next_commit(&dir)?;
+        let schema = changelog_schema();
+        let mut builder = TableBuilder::new(&schema);
+        for (i, row) in (0u64..).zip(rows) {
+            builder.insert_raw_row(schema.row(vec![
+                (COMMIT, commit.into()),
+                (CHANGE_ROW, i.into()),
+                (CHANGED_TABLE, self.schema.id().into()),
+                (CHANGE_DELETED, deleted.into()),
+                (CHANGE_VALUES, encode_row(row)?.into()),
+            ]))?;
+        }
+        builder.save(dir)
+    }
+}
+
+/// The commit id of the next batch, which is the version of the changelog
+/// that saving it will make
+fn next_commit(dir: &Path) -> Result<u64, StorageError> {
+    let versions = Table::versions(dir)?;
+    Ok(versions.last().map(|(v, _)| *v).unwrap_or(0) + 1)
+}
+
+impl Database {
+    /// The table recording the rows inserted into or deleted from each table
+    /// that records its changes, see [`TableSchema::record_changes`]
+    ///
+    /// [`TableSchema::record_changes`]: crate::TableSchema::record_changes
+    pub fn changelog(&self) -> TableHandle {
+        TableHandle {
+            dir: table_dir(&self.dir, CHANGELOG_TABLE),
+            schema: changelog_schema(),
+            read_only: self.read_only,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: None,
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+
+    /// Every batch of rows inserted into or deleted from a table with a
+    /// commit id greater than `commit`, in the order they were committed.
+    ///
+    /// Passing the id of the last change seen gives just the changes since,
+    /// and passing 0 gives every change.  Changes to tables that have since
+    /// been dropped are left out.
+    pub fn changes_since(&self, commit: u64) -> Result<Vec<Change>, StorageError> {
+        let since = Expr::Compare(
+            "commit".to_string(),
+            Comparison::Greater,
+            RawValue::U64(commit),
+        );
+        let schema = changelog_schema();
+        let rows = self.changelog().read()?.select(&since)?;
+        let mut changes: Vec<Change> = Vec::new();
+        for row in rows {
+            let commit: u64 = schema.get(&row, COMMIT)?;
+            let table: TableId = schema.get(&row, CHANGED_TABLE)?;
+            let deleted: bool = schema.get(&row, CHANGE_DELETED)?;
+            let values: Vec<u8> = schema.get(&row, CHANGE_VALUES)?;
+            let Some(table) = self.schemas().find(|s| s.id() == table) else {
+                continue;
+            };
+            let row = decode_row(values)?;
+            match changes.last_mut() {
+                Some(change) if change.commit == commit => change.rows.push(row),
+                _ => changes.push(Change {
+                    commit,
+                    table: table.name().to_string(),
+                    deleted,
+                    rows: vec![row],
+                }),
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[test]
+fn changes_since() {
+    use crate::TableSchema;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = super::test_schema();
+    schema.record_changes();
+    let people = db.create_table(schema.clone()).unwrap();
+    let mut unrecorded = TableSchema::new("others");
+    unrecorded.add_primary(crate::ColumnSchema::<String>::new("name").raw());
+    let others = db.create_table(unrecorded).unwrap();
+
+    let person = |name: &str, age: u64| -> RawRow {
+        [
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(age),
+            RawValue::Bool(true),
+            RawValue::U64(1),
+        ]
+        .into_iter()
+        .collect()
+    };
+    people
+        .insert_raw_rows([person("David", 48), person("Alice", 30)])
+        .unwrap();
+    others
+        .insert_raw_rows([[RawValue::Bytes(b"Eve".to_vec())].into_iter().collect()])
+        .unwrap();
+    people.upsert_rows([person("David", 49)]).unwrap();
+    let deleted = people.delete_rows(|r| r == &person("Alice", 30)).unwrap();
+    assert_eq!(deleted, [person("Alice", 30)]);
+    people.delete_rows(|_| false).unwrap();
+
+    let changes = db.changes_since(0).unwrap();
+    let summary = changes
+        .iter()
+        .map(|c| (c.table(), c.deleted(), c.rows().len()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("people", false, 2),
+            ("people", false, 1),
+            ("people", true, 1)
+        ]
+    );
+    assert!(changes.windows(2).all(|w| w[0].commit() < w[1].commit()));
+    assert_eq!(
+        changes[0].rows(),
+        [person("David", 48), person("Alice", 30)]
+    );
+    assert_eq!(changes[2].rows(), deleted);
+
+    // Only later changes are returned, even after reopening the database.
+    drop((people, others, db));
+    let db = Database::open(dir.path()).unwrap();
+    assert_eq!(db.schema("people").unwrap().to_string(), schema.to_string());
+    assert_eq!(
+        db.changes_since(changes[1].commit()).unwrap(),
+        &changes[2..]
+    );
+    let people = db.table("people").unwrap();
+    people.insert_raw_rows([person("Bob", 12)]).unwrap();
+    let later = db.changes_since(changes[2].commit()).unwrap();
+    assert_eq!(later.len(), 1);
+    assert!(later[0].commit() > changes[2].commit());
+    assert_eq!(later[0].rows(), [person("Bob", 12)]);
+}
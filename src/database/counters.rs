@@ -0,0 +1,164 @@
+//! The counters of auto-incremented columns, see
+//! [`TableSchema::auto_increment`](crate::TableSchema::auto_increment).
+//!
+//! The counters moved by a batch of rows are saved before the rows
+//! themselves, so a crash in between skips values rather than using them
+//! twice.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::{table_dir, Database, TableHandle};
+use crate::column::encoding::StorageError;
+use crate::lens::{ColumnId, TableId};
+use crate::schema::catalog::{COUNTED_COLUMN, COUNTED_TABLE, COUNTER_NEXT, COUNTER_TABLE};
+use crate::{counter_schema, Comparison, ComputedDefault, Expr, RawValue, Table, TableBuilder};
+
+impl TableHandle {
+    /// A builder for rows of the table, which counts each auto-incremented
+    /// column on from its saved counter
+    pub(super) fn builder(&self) -> Result<TableBuilder, StorageError> {
+        let builder = TableBuilder::new(&self.schema);
+        let counted = self
+            .schema
+            .computed_columns()
+            .iter()
+            .any(|(_, c, _)| *c == ComputedDefault::AutoIncrement);
+        let Some(db_dir) = self.db_dir.as_ref().filter(|_| counted) else {
+            return Ok(builder);
+        };
+        let counters = read_counters(db_dir, self.schema.id())?;
+        Ok(builder.with_counters(counters))
+    }
+
+    /// Save the next value of each auto-incremented column
+    pub(super) fn save_counters(
+        &self,
+        counters: &BTreeMap<ColumnId, u64>,
+    ) -> Result<(), StorageError> {
+        let Some(db_dir) = &self.db_dir else {
+            return Ok(());
+        };
+        save_counters(db_dir, self.schema.id(), counters)
+    }
+}
+
+/// The next value of each auto-incremented column of `table`, as saved in
+/// the database in `db_dir`
+pub(super) fn read_counters(
+    db_dir: &Path,
+    table: TableId,
+) -> Result<BTreeMap<ColumnId, u64>, StorageError> {
+    let this_table = Expr::Compare(
+        "table".to_string(),
+        Comparison::Equal,
+        RawValue::Bytes(table.0.to_vec()),
+    );
+    let schema = counter_schema();
+    let rows = Table::read(table_dir(db_dir, COUNTER_TABLE), &schema)?.select(&this_table)?;
+    rows.iter()
+        .map(|r| Ok((schema.get(r, COUNTED_COLUMN)?, schema.get(r, COUNTER_NEXT)?)))
+        .collect()
+}
+
+/// Save the next value of each auto-incremented column of `table` in the
+/// database in `db_dir`
+pub(super) fn save_counters(
+    db_dir: &Path,
+    table: TableId,
+    counters: &BTreeMap<ColumnId, u64>,
+) -> Result<(), StorageError> {
+    if counters.is_empty() {
+        return Ok(());
+    }
+    let schema = counter_schema();
+    let mut builder = TableBuilder::new(&schema);
+    for (&column, &next) in counters.iter() {
+        builder.insert_raw_row(schema.row(vec![
+            (COUNTED_TABLE, table.into()),
+            (COUNTED_COLUMN, column.into()),
+            (COUNTER_NEXT, next.into()),
+        ]))?;
+    }
+    builder.save(table_dir(db_dir, COUNTER_TABLE))
+}
+
+impl Database {
+    /// The table holding the counter of each auto-incremented column of each
+    /// table, see [`TableSchema::auto_increment`](crate::TableSchema::auto_increment)
+    pub fn counters(&self) -> TableHandle {
+        TableHandle {
+            dir: table_dir(&self.dir, COUNTER_TABLE),
+            schema: counter_schema(),
+            read_only: self.read_only,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: None,
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+}
+
+#[test]
+fn computed_defaults() {
+    use crate::{ColumnSchema, TableSchema};
+    use std::time::SystemTime;
+
+    let id = ColumnSchema::<u64>::new("id");
+    let created = ColumnSchema::with_default("created", SystemTime::UNIX_EPOCH);
+    let mut schema = TableSchema::new("orders");
+    schema.add_primary(id.raw());
+    schema.add_max(
+        ColumnSchema::<String>::new("item")
+            .raw()
+            .chain(created.raw()),
+    );
+    schema.auto_increment(&id).unwrap();
+    schema.default_to_now(&created).unwrap();
+    assert!(schema
+        .auto_increment(&ColumnSchema::<u64>::new("missing"))
+        .is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let before = SystemTime::now();
+    let mut db = Database::open(dir.path()).unwrap();
+    db.create_table(schema.clone()).unwrap();
+    let result = db
+        .execute("insert into orders (item) values ('tea'), ('cake') returning id, item")
+        .unwrap();
+    assert_eq!(result.rows()[0][0], RawValue::U64(1));
+    assert_eq!(result.rows()[1][0], RawValue::U64(2));
+    drop(db);
+
+    // The counter and the computed defaults outlive the database handle.
+    let db = Database::open(dir.path()).unwrap();
+    let orders = db.table("orders").unwrap();
+    assert!(orders
+        .schema()
+        .to_string()
+        .contains("DEFAULT AutoIncrement ( id )"));
+    db.execute("insert into orders (id, item) values (10, 'jam'), (0, 'scone')")
+        .unwrap();
+    db.execute("insert into orders (item) values ('bun')")
+        .unwrap();
+    let rows = orders.read().unwrap().to_rows().unwrap();
+    let ids = rows
+        .iter()
+        .map(|r| schema.get::<u64>(r, id.id()).unwrap())
+        .collect::<Vec<_>>();
+    // A row given a value moves the counter past it for the rows after it.
+    assert_eq!(ids, [1, 2, 10, 11, 12]);
+    for row in rows.iter() {
+        let stamped: SystemTime = schema.get(row, created.id()).unwrap();
+        assert!(stamped >= before);
+    }
+
+    // A builder used on its own counts from one.
+    let mut builder = TableBuilder::new(&schema);
+    builder
+        .insert_raw_row(schema.row(vec![(id.id(), 0u64.into())]))
+        .unwrap();
+    assert_eq!(builder.rows()[0].values()[0], RawValue::U64(1));
+}
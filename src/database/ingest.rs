@@ -0,0 +1,530 @@
+//! Rows accepted continuously and saved in batches, see [`Ingestor`].
+//!
+//! Rows pushed to an ingestor are kept in memory until enough of them have
+//! arrived, or the first of them has waited long enough, and are then saved
+//! as one segment by a thread of the ingestor.  So pushing a row never waits
+//! for a save, unless too many batches are already waiting to be saved.
+//!
+//! Until they are saved, the rows are kept in a memtable that the database
+//! knows of, so reading the table through any of its [`TableHandle`]s
+//! merges them with the saved rows, and a writer reads back what it pushed.
+//! A batch is never saved while the table is read, so no row is read twice.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::TableHandle;
+use crate::column::encoding::StorageError;
+use crate::lens::TableId;
+use crate::{RawRow, Table, TableBuilder};
+
+/// When an [`Ingestor`] saves the rows it holds, and how many batches of
+/// them it holds before a push must wait
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestOptions {
+    flush_rows: usize,
+    flush_interval: Duration,
+    max_pending: usize,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        IngestOptions {
+            flush_rows: 10_000,
+            flush_interval: Duration::from_secs(1),
+            max_pending: 4,
+        }
+    }
+}
+
+impl IngestOptions {
+    /// Save the rows as a batch once there are `rows` of them
+    pub fn flush_rows(self, rows: usize) -> Self {
+        IngestOptions {
+            flush_rows: rows.max(1),
+            ..self
+        }
+    }
+
+    /// Save the rows as a batch once the first of them has waited `interval`
+    pub fn flush_interval(self, interval: Duration) -> Self {
+        IngestOptions {
+            flush_interval: interval,
+            ..self
+        }
+    }
+
+    /// Make a push wait while `batches` batches are waiting to be saved
+    pub fn max_pending(self, batches: usize) -> Self {
+        IngestOptions {
+            max_pending: batches.max(1),
+            ..self
+        }
+    }
+}
+
+/// The rows an [`Ingestor`] has accepted but not yet saved
+#[derive(Debug, Default)]
+struct Memtable {
+    /// The rows accepted since the last batch was sealed
+    rows: Vec<RawRow>,
+    /// When the first of `rows` was accepted
+    since: Option<Instant>,
+    /// The sealed batches, oldest first, of which the first is being saved
+    batches: VecDeque<Vec<RawRow>>,
+    /// The error saving a batch, kept until it is returned
+    error: Option<StorageError>,
+    /// Whether to save every row and stop
+    closed: bool,
+}
+
+impl Memtable {
+    /// Turn the rows accepted into a batch to be saved
+    fn seal(&mut self) {
+        if !self.rows.is_empty() {
+            self.batches.push_back(std::mem::take(&mut self.rows));
+            self.since = None;
+        }
+    }
+
+    fn take_error(&mut self) -> Result<(), StorageError> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    table: TableId,
+    memtable: Mutex<Memtable>,
+    /// Notified whenever rows are sealed or saved
+    changed: Condvar,
+    /// Held for writing while a batch is saved, and for reading while the
+    /// table is read
+    saving: RwLock<()>,
+}
+
+thread_local! {
+    /// The [`Shared`] whose batch this thread is saving, and so whose
+    /// `saving` lock it holds for writing already
+    static SAVING: Cell<*const Shared> = const { Cell::new(std::ptr::null()) };
+}
+
+impl Shared {
+    /// Whether this thread is saving a batch of these rows
+    fn is_saved_here(&self) -> bool {
+        SAVING.with(|s| std::ptr::eq(s.get(), self))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Memtable> {
+        self.memtable.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait<'a>(
+        &self,
+        memtable: MutexGuard<'a, Memtable>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, Memtable> {
+        match self.changed.wait_timeout(memtable, timeout) {
+            Ok((memtable, _)) => memtable,
+            Err(e) => e.into_inner().0,
+        }
+    }
+}
+
+/// The memtables of the ingestors of the tables of a database
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Memtables(Arc<Mutex<Vec<Weak<Shared>>>>);
+
+impl Memtables {
+    fn lock(&self) -> MutexGuard<'_, Vec<Weak<Shared>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn add(&self, shared: &Arc<Shared>) {
+        let mut memtables = self.lock();
+        memtables.retain(|m| m.strong_count() > 0);
+        memtables.push(Arc::downgrade(shared));
+    }
+
+    /// The memtables of the ingestors of `table` that are still open
+    fn of(&self, table: TableId) -> Vec<Arc<Shared>> {
+        self.lock()
+            .iter()
+            .filter_map(|m| m.upgrade())
+            .filter(|m| m.table == table)
+            .collect()
+    }
+}
+
+/// Accepts rows of a table continuously, saving them in batches, see
+/// [`TableHandle::ingestor`].
+///
+/// An error saving a batch is returned by the next call to the ingestor, and
+/// the rows of that batch are not saved, though they were read until then.
+/// Rows not yet saved are not deleted by `DELETE`, nor merged by
+/// compaction.  Dropping an ingestor saves the rows it holds, but ignores
+/// any error, which [`Ingestor::finish`] returns.
+#[derive(Debug)]
+pub struct Ingestor {
+    /// Checks each row pushed and computes its defaults, but holds no rows
+    builder: TableBuilder,
+    shared: Arc<Shared>,
+    options: IngestOptions,
+    flusher: Option<JoinHandle<()>>,
+}
+
+impl TableHandle {
+    /// Start accepting rows of the table continuously, see [`Ingestor`].
+    ///
+    /// Each batch is saved as by [`TableHandle::insert_raw_rows`].
+    pub fn ingestor(&self, options: IngestOptions) -> Result<Ingestor, StorageError> {
+        self.check_writable()?;
+        let builder = self.builder()?;
+        let shared = Arc::new(Shared {
+            table: self.schema.id(),
+            memtable: Mutex::default(),
+            changed: Condvar::new(),
+            saving: RwLock::new(()),
+        });
+        self.memtables.add(&shared);
+        let flusher = {
+            let (handle, shared) = (self.clone(), shared.clone());
+            std::thread::spawn(move || save_batches(&handle, &shared, options.flush_interval))
+        };
+        Ok(Ingestor {
+            builder,
+            shared,
+            options,
+            flusher: Some(flusher),
+        })
+    }
+
+    /// Read the table with `read`, merging the rows its ingestors have not
+    /// yet saved into it
+    pub(super) fn with_memtables(
+        &self,
+        read: impl FnOnce() -> Result<Table, StorageError>,
+    ) -> Result<Table, StorageError> {
+        let memtables = self.memtables.of(self.schema.id());
+        // Saving a batch reads the table to check its references, with the
+        // lock of the batch held already.
+        let _saving = memtables
+            .iter()
+            .filter(|m| !m.is_saved_here())
+            .map(|m| m.saving.read().unwrap_or_else(|e| e.into_inner()))
+            .collect::<Vec<_>>();
+        let table = read()?.with_cache(&self.cache);
+        let mut rows = Vec::new();
+        for m in memtables.iter() {
+            let memtable = m.lock();
+            rows.extend(memtable.batches.iter().flatten().cloned());
+            rows.extend(memtable.rows.iter().cloned());
+        }
+        table.with_rows(&rows)
+    }
+}
+
+/// Save each batch sealed, and seal the rows once they have waited
+/// `interval`, until the ingestor is closed
+fn save_batches(handle: &TableHandle, shared: &Shared, interval: Duration) {
+    let mut memtable = shared.lock();
+    loop {
+        let waited = memtable.since.map(|s| s.elapsed());
+        if memtable.closed || matches!(waited, Some(w) if w >= interval) {
+            memtable.seal();
+        }
+        if let Some(batch) = memtable.batches.front() {
+            let batch = batch.clone();
+            drop(memtable);
+            let saving = shared.saving.write().unwrap_or_else(|e| e.into_inner());
+            SAVING.with(|s| s.set(shared));
+            let saved = handle.insert_raw_rows(batch);
+            SAVING.with(|s| s.set(std::ptr::null()));
+            memtable = shared.lock();
+            memtable.batches.pop_front();
+            drop(saving);
+            if let Err(e) = saved {
+                memtable.error.get_or_insert(e);
+            }
+            shared.changed.notify_all();
+        } else if memtable.closed {
+            return;
+        } else {
+            let timeout = interval.saturating_sub(waited.unwrap_or_default());
+            memtable = shared.wait(memtable, timeout);
+        }
+    }
+}
+
+impl Ingestor {
+    /// Accept `row`, waiting if the rows must be sealed as a batch while too
+    /// many batches are waiting to be saved.
+    ///
+    /// The computed defaults of the row are computed as it is accepted, so
+    /// readers see it as it will be saved.  A row that does not match the
+    /// schema, or breaks a constraint of one of its columns, is rejected.
+    pub fn push(&mut self, row: RawRow) -> Result<(), StorageError> {
+        let row = self.builder.accept_row(row)?;
+        let mut memtable = self.shared.lock();
+        memtable.take_error()?;
+        memtable.since.get_or_insert_with(Instant::now);
+        memtable.rows.push(row);
+        if memtable.rows.len() >= self.options.flush_rows {
+            while memtable.batches.len() >= self.options.max_pending {
+                memtable = self.shared.wait(memtable, self.options.flush_interval);
+            }
+            memtable.seal();
+            self.shared.changed.notify_all();
+        }
+        Ok(())
+    }
+
+    /// Accept `row` unless that would wait for a batch to be saved, in which
+    /// case `row` is returned
+    pub fn try_push(&mut self, row: RawRow) -> Result<Option<RawRow>, StorageError> {
+        let mut memtable = self.shared.lock();
+        memtable.take_error()?;
+        let full = memtable.rows.len() + 1 >= self.options.flush_rows;
+        if full && memtable.batches.len() >= self.options.max_pending {
+            return Ok(Some(row));
+        }
+        drop(memtable);
+        self.push(row)?;
+        Ok(None)
+    }
+
+    /// The number of rows accepted but not yet saved
+    pub fn pending(&self) -> usize {
+        let memtable = self.shared.lock();
+        memtable.rows.len() + memtable.batches.iter().map(|b| b.len()).sum::<usize>()
+    }
+
+    /// Save every row accepted, waiting until they are saved
+    pub fn flush(&mut self) -> Result<(), StorageError> {
+        let mut memtable = self.shared.lock();
+        memtable.seal();
+        self.shared.changed.notify_all();
+        while !memtable.batches.is_empty() {
+            memtable = self.shared.wait(memtable, self.options.flush_interval);
+        }
+        memtable.take_error()
+    }
+
+    /// Save every row accepted and stop the thread saving them
+    pub fn finish(mut self) -> Result<(), StorageError> {
+        self.close();
+        self.shared.lock().take_error()
+    }
+
+    fn close(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(flusher) = self.flusher.take() {
+            // The thread saving batches only panics if saving does.
+            flusher.join().ok();
+        }
+    }
+}
+
+impl Drop for Ingestor {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[test]
+fn ingestion() {
+    use super::{test_schema, Database};
+    use crate::{ColumnSchema, ConflictPolicy, Constraint, RawValue};
+
+    let person = |name: &str, visits: u64| -> RawRow {
+        [
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(30),
+            RawValue::Bool(true),
+            RawValue::U64(visits),
+        ]
+        .into_iter()
+        .collect()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let people = db.create_table(test_schema()).unwrap();
+    let mut ingestor = people
+        .ingestor(IngestOptions::default().flush_rows(10))
+        .unwrap();
+    for i in 0..25 {
+        ingestor.push(person(&format!("p{}", i % 20), 1)).unwrap();
+    }
+    ingestor.flush().unwrap();
+    assert_eq!(ingestor.pending(), 0);
+    // Each batch of ten rows is a segment, and the last five are one too.
+    assert_eq!(people.stats().unwrap().segments(), 3);
+    let rows = people.read().unwrap().to_rows().unwrap();
+    assert_eq!(rows.len(), 20);
+    assert_eq!(rows[0].values()[3], RawValue::U64(2));
+
+    // Rows are saved once the first has waited long enough.
+    let mut ingestor = people
+        .ingestor(IngestOptions::default().flush_interval(Duration::from_millis(10)))
+        .unwrap();
+    assert_eq!(ingestor.try_push(person("late", 1)).unwrap(), None);
+    let start = Instant::now();
+    while ingestor.pending() > 0 {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(people.read().unwrap().to_rows().unwrap().len(), 21);
+    ingestor.finish().unwrap();
+
+    // A row breaking a constraint is rejected as it is pushed, and a batch
+    // that fails to save fails the next call.
+    let mut schema = crate::TableSchema::new("visits");
+    let page = ColumnSchema::<String>::new("page");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(page.raw());
+    schema.add_constraint(&page, Constraint::NotNull).unwrap();
+    schema.set_conflict_policy(ConflictPolicy::Error);
+    let visits = db.create_table(schema).unwrap();
+    let mut ingestor = visits.ingestor(IngestOptions::default()).unwrap();
+    let visit = |id: u64, page: &str| -> RawRow {
+        [RawValue::U64(id), RawValue::Bytes(page.as_bytes().to_vec())]
+            .into_iter()
+            .collect()
+    };
+    ingestor.push(visit(1, "/")).unwrap();
+    assert!(matches!(
+        ingestor.push(visit(2, "")),
+        Err(StorageError::Constraint { .. })
+    ));
+    ingestor.push(visit(3, "/about")).unwrap();
+    ingestor.flush().unwrap();
+    ingestor.push(visit(3, "/contact")).unwrap();
+    assert!(matches!(
+        ingestor.flush(),
+        Err(StorageError::DuplicateKey(_))
+    ));
+    ingestor.finish().unwrap();
+    let ids = visits
+        .read()
+        .unwrap()
+        .to_rows()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.values()[0].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [RawValue::U64(1), RawValue::U64(3)]);
+}
+
+#[test]
+fn read_your_writes() {
+    use super::{test_schema, Database};
+    use crate::RawValue;
+
+    let person = |name: &str, visits: u64| -> RawRow {
+        [
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(30),
+            RawValue::Bool(true),
+            RawValue::U64(visits),
+        ]
+        .into_iter()
+        .collect()
+    };
+    let visits = |db: &Database| {
+        let result = db
+            .execute("select name, visits from people")
+            .unwrap()
+            .rows()
+            .to_vec();
+        result
+            .into_iter()
+            .map(|r| (r[0].clone(), r[1].clone()))
+            .collect::<Vec<_>>()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let people = db.create_table(test_schema()).unwrap();
+    people.insert_raw_rows([person("Ann", 1)]).unwrap();
+    let options = IngestOptions::default()
+        .flush_rows(3)
+        .flush_interval(Duration::from_secs(3600));
+    let mut ingestor = people.ingestor(options).unwrap();
+    ingestor.push(person("Ann", 2)).unwrap();
+    ingestor.push(person("Bo", 5)).unwrap();
+    assert!(ingestor
+        .push(person("Cy", 1).values()[..2].iter().cloned().collect())
+        .is_err());
+
+    // Rows not yet saved are merged with the saved rows by every reader.
+    let bytes = |s: &str| RawValue::Bytes(s.as_bytes().to_vec());
+    let expected = vec![
+        (bytes("Ann"), RawValue::U64(3)),
+        (bytes("Bo"), RawValue::U64(5)),
+    ];
+    assert_eq!(visits(&db), expected);
+    assert_eq!(people.stats().unwrap().segments(), 1);
+    let bo = crate::Expr::Compare("name".to_string(), crate::Comparison::Equal, bytes("Bo"));
+    let found = db.table("people").unwrap().read_where(&bo).unwrap();
+    assert_eq!(found.select(&bo).unwrap().len(), 1);
+
+    // Once saved, each row is read just once.
+    ingestor.push(person("Bo", 5)).unwrap();
+    ingestor.flush().unwrap();
+    let expected = vec![
+        (bytes("Ann"), RawValue::U64(3)),
+        (bytes("Bo"), RawValue::U64(10)),
+    ];
+    assert_eq!(visits(&db), expected);
+    ingestor.push(person("Cy", 1)).unwrap();
+    drop(ingestor);
+    assert_eq!(visits(&db).len(), 3);
+    assert_eq!(people.stats().unwrap().segments(), 3);
+}
+
+#[test]
+fn read_computed_defaults() {
+    use super::Database;
+    use crate::{ColumnSchema, TableSchema};
+    use std::time::SystemTime;
+
+    let id = ColumnSchema::<u64>::new("id");
+    let item = ColumnSchema::<String>::new("item");
+    let created = ColumnSchema::with_default("created", SystemTime::UNIX_EPOCH);
+    let mut schema = TableSchema::new("orders");
+    schema.add_primary(id.raw());
+    schema.add_max(item.raw().chain(created.raw()));
+    schema.auto_increment(&id).unwrap();
+    schema.default_to_now(&created).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let orders = db.create_table(schema.clone()).unwrap();
+    let order = |name: &str| schema.row(vec![(item.id(), name.to_string().into())]);
+    orders.insert_raw_rows([order("jam")]).unwrap();
+
+    // Rows not yet saved are read with their computed defaults, just as
+    // they are then saved.
+    let before = SystemTime::now();
+    let options = IngestOptions::default().flush_interval(Duration::from_secs(3600));
+    let mut ingestor = orders.ingestor(options).unwrap();
+    ingestor.push(order("tea")).unwrap();
+    ingestor.push(order("cake")).unwrap();
+    let pending = orders.read().unwrap().to_rows().unwrap();
+    let ids = pending
+        .iter()
+        .map(|r| schema.get::<u64>(r, id.id()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [1, 2, 3]);
+    for row in pending[1..].iter() {
+        let stamped: SystemTime = schema.get(row, created.id()).unwrap();
+        assert!(stamped >= before);
+    }
+    ingestor.finish().unwrap();
+    assert_eq!(orders.read().unwrap().to_rows().unwrap(), pending);
+}
@@ -0,0 +1,276 @@
+//! Columns whose values are the primary keys of rows of other tables, see
+//! [`TableSchema::add_reference`](crate::TableSchema::add_reference).
+//!
+//! The values are checked against the primary keys of the rows saved, or
+//! accepted by an ingestor, when rows are saved or the table is compacted.
+//! Nothing stops rows that are referred to from being deleted, so
+//! [`Database::check_integrity`] scans every table for values that refer to
+//! nothing.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::{Database, TableHandle};
+use crate::column::encoding::StorageError;
+use crate::table::keys_condition;
+use crate::{RawRow, RawValue, TableSchema};
+
+/// A value of a column that is not the primary key of any row of the table
+/// the column refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingReference {
+    table: String,
+    column: String,
+    key: Vec<RawValue>,
+    value: Vec<RawValue>,
+    referenced: String,
+}
+
+impl DanglingReference {
+    /// The name of the table holding the value
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// The name of the column holding the value
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// The primary key of the row holding the value
+    pub fn key(&self) -> &[RawValue] {
+        &self.key
+    }
+
+    /// The raw values of the column
+    pub fn value(&self) -> &[RawValue] {
+        &self.value
+    }
+
+    /// The name of the table the column refers to
+    pub fn referenced(&self) -> &str {
+        &self.referenced
+    }
+}
+
+impl std::fmt::Display for DanglingReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "column {} of the row {:?} of table {} holds {:?}, which is no primary key of table {}",
+            self.column, self.key, self.table, self.value, self.referenced
+        )
+    }
+}
+
+impl TableHandle {
+    /// The values of the columns of `rows` that refer to no saved row, where
+    /// `rows` count as saved if a column refers to this table
+    pub(super) fn dangling(&self, rows: &[RawRow]) -> Result<Vec<DanglingReference>, StorageError> {
+        let mut dangling = Vec::new();
+        let columns = self.schema.raw_columns().collect::<Vec<_>>();
+        for (_, table, positions) in self.schema.referencing_columns() {
+            let default = positions
+                .iter()
+                .map(|&i| columns[i].default().clone())
+                .collect::<Vec<_>>();
+            let values = rows
+                .iter()
+                .map(|row| {
+                    let value = positions.iter().map(|&i| row.values[i].clone());
+                    value.collect::<Vec<_>>()
+                })
+                .filter(|value| *value != default)
+                .collect::<BTreeSet<_>>();
+            let mut keys = BTreeSet::new();
+            let mut referenced = table.to_string();
+            if let Some((dir, schema)) = self.references.iter().find(|(_, s)| s.id() == table) {
+                if !values.is_empty() {
+                    keys = self.referenced(dir, schema).saved_keys(&values)?;
+                }
+                referenced = schema.name().to_string();
+            }
+            if table == self.schema.id() {
+                let num_primary = self.schema.num_primary();
+                keys.extend(rows.iter().map(|r| r.values[..num_primary].to_vec()));
+            }
+            for row in rows {
+                let value = positions
+                    .iter()
+                    .map(|&i| row.values[i].clone())
+                    .collect::<Vec<_>>();
+                if value != default && !keys.contains(&value) {
+                    dangling.push(DanglingReference {
+                        table: self.schema.name().to_string(),
+                        column: columns[positions[0]].name().to_string(),
+                        key: row.values[..self.schema.num_primary()].to_vec(),
+                        value,
+                        referenced: referenced.clone(),
+                    });
+                }
+            }
+        }
+        Ok(dangling)
+    }
+
+    /// A handle reading the table in `dir`, which a column of this table
+    /// refers to
+    fn referenced(&self, dir: &Path, schema: &TableSchema) -> TableHandle {
+        TableHandle {
+            dir: dir.to_path_buf(),
+            schema: schema.clone(),
+            read_only: true,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: self.db_dir.clone(),
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+
+    /// Those of `keys` that are the primary key of a row of the table,
+    /// whether saved or accepted by an ingestor, reading only the segments
+    /// and chunks that can hold them
+    fn saved_keys(
+        &self,
+        keys: &BTreeSet<Vec<RawValue>>,
+    ) -> Result<BTreeSet<Vec<RawValue>>, StorageError> {
+        let num_primary = self.schema.num_primary();
+        let expr = keys_condition(&self.schema, keys);
+        let found = self.read_projected(&[])?.select(&expr)?;
+        Ok(found
+            .into_iter()
+            .map(|r| r.values[..num_primary].to_vec())
+            .filter(|k| keys.contains(k))
+            .collect())
+    }
+
+    /// Fail if any value of a column of `rows` refers to no saved row
+    pub(super) fn check_references(&self, rows: &[RawRow]) -> Result<(), StorageError> {
+        match self.dangling(rows)?.into_iter().next() {
+            Some(dangling) => Err(StorageError::DanglingReference(dangling)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Database {
+    /// Scan every table with columns referring to tables for values that
+    /// refer to no row, see
+    /// [`TableSchema::add_reference`](crate::TableSchema::add_reference)
+    pub fn check_integrity(&self) -> Result<Vec<DanglingReference>, StorageError> {
+        let mut dangling = Vec::new();
+        for schema in self.schemas() {
+            if !schema.referencing_columns().is_empty() {
+                let table = self.table(schema.name())?;
+                dangling.extend(table.dangling(&table.read()?.to_rows()?)?);
+            }
+        }
+        Ok(dangling)
+    }
+}
+
+#[test]
+fn references() {
+    use crate::{ColumnSchema, IngestOptions, TableSchema};
+    use std::time::Duration;
+
+    let mut customers = TableSchema::new("customers");
+    customers.add_primary(ColumnSchema::<u64>::new("id").raw());
+    customers.add_max(ColumnSchema::<String>::new("name").raw());
+    let customer = ColumnSchema::<u64>::new("customer");
+    let parent = ColumnSchema::<u64>::new("parent");
+    let mut orders = TableSchema::new("orders");
+    orders.add_primary(ColumnSchema::<u64>::new("id").raw());
+    orders.add_max(customer.raw().chain(parent.raw()));
+    orders.add_reference(&customer, &customers).unwrap();
+    orders.add_reference(&parent, &orders.clone()).unwrap();
+    let name = ColumnSchema::<String>::new("name");
+    assert!(customers.add_reference(&name, &orders).is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    assert!(db.create_table(orders.clone()).is_err());
+    db.create_table(customers).unwrap();
+    db.create_table(orders.clone()).unwrap();
+    drop(db);
+
+    let mut db = Database::open(dir.path()).unwrap();
+    assert_eq!(db.schema("orders").unwrap().to_string(), orders.to_string());
+    db.execute("insert into customers (id, name) values (1, 'Ann'), (2, 'Bo')")
+        .unwrap();
+    // An order may refer to an order saved along with it, and zero refers
+    // to nothing.
+    db.execute("insert into orders (id, customer, parent) values (10, 1, 0), (11, 1, 10)")
+        .unwrap();
+    db.execute("insert into orders (id, customer) values (12, 0)")
+        .unwrap();
+    match db.execute("insert into orders (id, customer, parent) values (13, 3, 10)") {
+        Err(StorageError::DanglingReference(d)) => {
+            assert_eq!(d.key(), [RawValue::U64(13)]);
+            assert_eq!(d.column(), "customer");
+            assert_eq!(d.value(), [RawValue::U64(3)]);
+            assert_eq!(d.referenced(), "customers");
+        }
+        other => panic!("{other:?}"),
+    }
+    assert!(db
+        .execute("insert into orders (id, parent) values (13, 14)")
+        .is_err());
+    assert!(db.drop_table("customers").is_err());
+    assert_eq!(db.check_integrity().unwrap(), []);
+
+    // Deleting a customer leaves its orders referring to nothing.
+    db.execute("delete from customers where id = 1").unwrap();
+    let dangling = db.check_integrity().unwrap();
+    let keys = dangling
+        .iter()
+        .map(|d| d.key().to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, [[RawValue::U64(10)], [RawValue::U64(11)]]);
+    assert!(matches!(
+        db.table("orders").unwrap().compact(),
+        Err(StorageError::DanglingReference(_))
+    ));
+    db.execute("insert into customers (id, name) values (1, 'Ann')")
+        .unwrap();
+    db.table("orders").unwrap().compact().unwrap();
+
+    // A customer an ingestor has accepted but not yet saved may be referred
+    // to.
+    let options = IngestOptions::default().flush_interval(Duration::from_secs(3600));
+    let mut ingestor = db.table("customers").unwrap().ingestor(options).unwrap();
+    let cy = [RawValue::U64(3), RawValue::Bytes(b"Cy".to_vec())];
+    ingestor.push(cy.into_iter().collect()).unwrap();
+    db.execute("insert into orders (id, customer) values (13, 3)")
+        .unwrap();
+    ingestor.finish().unwrap();
+    assert_eq!(db.check_integrity().unwrap(), []);
+
+    // Orders ingested may refer to orders, whether saved or not.
+    let mut ingestor = db
+        .table("orders")
+        .unwrap()
+        .ingestor(IngestOptions::default().flush_rows(2))
+        .unwrap();
+    let order = |id: u64, parent: u64| {
+        [id, 1, parent]
+            .into_iter()
+            .map(RawValue::U64)
+            .collect::<RawRow>()
+    };
+    ingestor.push(order(20, 10)).unwrap();
+    ingestor.push(order(21, 20)).unwrap();
+    ingestor.flush().unwrap();
+    ingestor.push(order(22, 21)).unwrap();
+    ingestor.push(order(23, 99)).unwrap();
+    assert!(matches!(
+        ingestor.flush(),
+        Err(StorageError::DanglingReference(_))
+    ));
+    ingestor.finish().unwrap();
+    assert_eq!(db.check_integrity().unwrap(), []);
+    db.drop_table("orders").unwrap();
+    db.drop_table("customers").unwrap();
+}
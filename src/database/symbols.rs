@@ -0,0 +1,209 @@
+//! The dictionary of symbols, holding the string of each [`Symbol`] that
+//! has been interned for each table.
+//!
+//! The strings of a batch of rows are saved before the rows themselves, so
+//! every symbol a reader finds in a table can be resolved.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::{table_dir, Database, TableHandle};
+use crate::column::encoding::StorageError;
+use crate::lens::{Lens, Symbol, TableId};
+use crate::schema::catalog::{SYMBOL_HASH, SYMBOL_OWNER, SYMBOL_TABLE, SYMBOL_TEXT};
+use crate::{symbol_schema, Comparison, Expr, LensError, RawRow, RawValue, Table, TableBuilder};
+
+impl TableHandle {
+    /// Save the strings of `symbols` in the dictionary of the table
+    pub(super) fn save_symbols(
+        &self,
+        symbols: &BTreeMap<Symbol, String>,
+    ) -> Result<(), StorageError> {
+        let Some(db_dir) = &self.db_dir else {
+            return Ok(());
+        };
+        save_symbols(db_dir, self.schema.id(), symbols)
+    }
+
+    /// Intern `texts` in the dictionary of the table, returning the symbol of
+    /// each, see [`Symbol`].
+    ///
+    /// This is only needed for rows built by hand: rows added through a
+    /// [`TableBuilder`] intern their strings with [`TableBuilder::intern`].
+    pub fn intern<'a>(
+        &self,
+        texts: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Symbol>, StorageError> {
+        self.check_writable()?;
+        let mut symbols = BTreeMap::new();
+        let interned = texts
+            .into_iter()
+            .map(|text| {
+                let symbol = Symbol::of(text);
+                symbols.insert(symbol, text.to_string());
+                symbol
+            })
+            .collect();
+        self.save_symbols(&symbols)?;
+        Ok(interned)
+    }
+
+    /// The string of each symbol interned for the table
+    pub fn symbols(&self) -> Result<BTreeMap<Symbol, String>, StorageError> {
+        let Some(db_dir) = &self.db_dir else {
+            return Ok(BTreeMap::new());
+        };
+        read_symbols(db_dir, self.schema.id())
+    }
+
+    /// Read the rows of the table, with the value of each column of symbols
+    /// replaced by the bytes of its string.
+    pub fn read_resolved(&self) -> Result<Vec<RawRow>, StorageError> {
+        let columns = self
+            .schema
+            .raw_columns()
+            .enumerate()
+            .filter(|(_, c)| c.lens() == Symbol::LENS_ID)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let mut rows = self.read()?.to_rows()?;
+        if columns.is_empty() {
+            return Ok(rows);
+        }
+        let mut symbols = self.symbols()?;
+        symbols.insert(Symbol::default(), String::new());
+        for row in rows.iter_mut() {
+            for &i in columns.iter() {
+                let RawValue::U64(symbol) = row.values[i] else {
+                    continue;
+                };
+                let text = symbols
+                    .get(&Symbol(symbol))
+                    .ok_or(LensError::InvalidValue {
+                        value: format!("symbol {symbol} is not in the dictionary"),
+                    })?;
+                row.values[i] = RawValue::Bytes(text.as_bytes().to_vec());
+            }
+        }
+        Ok(rows)
+    }
+}
+
+/// The string of each symbol interned for `table` in the database in `db_dir`
+pub(super) fn read_symbols(
+    db_dir: &Path,
+    table: TableId,
+) -> Result<BTreeMap<Symbol, String>, StorageError> {
+    let this_table = Expr::Compare(
+        "table".to_string(),
+        Comparison::Equal,
+        RawValue::Bytes(table.0.to_vec()),
+    );
+    let schema = symbol_schema();
+    let rows = Table::read(table_dir(db_dir, SYMBOL_TABLE), &schema)?.select(&this_table)?;
+    rows.iter()
+        .map(|r| Ok((schema.get(r, SYMBOL_HASH)?, schema.get(r, SYMBOL_TEXT)?)))
+        .collect()
+}
+
+/// Save the strings of `symbols` in the dictionary of `table` in the
+/// database in `db_dir`
+pub(super) fn save_symbols(
+    db_dir: &Path,
+    table: TableId,
+    symbols: &BTreeMap<Symbol, String>,
+) -> Result<(), StorageError> {
+    if symbols.is_empty() {
+        return Ok(());
+    }
+    let schema = symbol_schema();
+    let mut builder = TableBuilder::new(&schema);
+    for (symbol, text) in symbols.iter() {
+        builder.insert_raw_row(schema.row(vec![
+            (SYMBOL_OWNER, table.into()),
+            (SYMBOL_HASH, (*symbol).into()),
+            (SYMBOL_TEXT, text.clone().into()),
+        ]))?;
+    }
+    builder.save(table_dir(db_dir, SYMBOL_TABLE))
+}
+
+impl Database {
+    /// The table holding the string of each symbol interned for each table,
+    /// see [`Symbol`]
+    pub fn symbols(&self) -> TableHandle {
+        TableHandle {
+            dir: table_dir(&self.dir, SYMBOL_TABLE),
+            schema: symbol_schema(),
+            read_only: self.read_only,
+            rollups: Vec::new(),
+            references: Vec::new(),
+            db_dir: None,
+            cache: self.cache.clone(),
+            memtables: self.memtables.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+}
+
+#[test]
+fn interned_symbols() {
+    use crate::{ColumnSchema, CsvLoader, JsonLoader, TableSchema};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("requests");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(ColumnSchema::<Symbol>::new("agent").raw());
+    let requests = db.create_table(schema.clone()).unwrap();
+    let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0";
+    let curl = "curl/7.88.1";
+
+    let symbols = requests.intern([firefox, curl, firefox]).unwrap();
+    assert_eq!(symbols[0], symbols[2]);
+    assert_eq!(symbols[0], Symbol::of(firefox));
+    let row =
+        |id: u64, agent: RawValue| -> RawRow { [RawValue::U64(id), agent].into_iter().collect() };
+    requests
+        .insert_raw_rows([row(1, RawValue::U64(symbols[0].0))])
+        .unwrap();
+    let csv = format!("id,agent\n2,{curl}\n3,wget\n");
+    requests
+        .load_csv(&CsvLoader::new(&schema), csv.as_bytes())
+        .unwrap();
+    requests
+        .load_json(&JsonLoader::new(&schema), "{\"id\": 4}\n".as_bytes())
+        .unwrap();
+
+    // The table holds only symbols, which read back as their strings.
+    let raw = requests.read().unwrap().to_rows().unwrap();
+    assert!(raw.iter().all(|r| matches!(r.values[1], RawValue::U64(_))));
+    let text = |s: &str| RawValue::Bytes(s.as_bytes().to_vec());
+    let expected = [
+        row(1, text(firefox)),
+        row(2, text(curl)),
+        row(3, text("wget")),
+        row(4, text("")),
+    ];
+    assert_eq!(requests.read_resolved().unwrap(), expected);
+    assert_eq!(requests.symbols().unwrap().len(), 3);
+
+    // A symbol that was never interned cannot be resolved.
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(row(5, RawValue::U64(5))).unwrap();
+    builder.save(table_dir(dir.path(), schema.id())).unwrap();
+    assert!(requests.read_resolved().is_err());
+    requests
+        .delete_rows(|r| r.values[0] == RawValue::U64(5))
+        .unwrap();
+
+    drop((requests, db));
+    let db = Database::open(dir.path()).unwrap();
+    assert_eq!(
+        db.schema("requests").unwrap().to_string(),
+        schema.to_string()
+    );
+    let requests = db.table("requests").unwrap();
+    assert_eq!(requests.read_resolved().unwrap(), expected);
+    assert_eq!(db.symbols().read().unwrap().to_rows().unwrap().len(), 3);
+}
@@ -0,0 +1,276 @@
+//! Tables whose rows are values of a Rust type.
+//!
+//! A type implementing [`IsRow`] describes the schema of its table and how
+//! its fields are found in a row, by the names of their columns.  Each field
+//! is converted with its [`Lens`], so code using a [`TypedTable`] never sees
+//! a [`RawValue`].
+
+use std::marker::PhantomData;
+
+use super::{Database, TableHandle};
+use crate::column::encoding::StorageError;
+use crate::lens::{Lens, LensError, RawValues};
+use crate::{RawRow, RawValue, TableSchema};
+
+/// A type whose values are the rows of a table.
+///
+/// ```
+/// use equilia::{ColumnSchema, Database, IsRow, LensError, NamedRow, TableSchema};
+///
+/// struct Person {
+///     name: String,
+///     age: u64,
+/// }
+///
+/// impl IsRow for Person {
+///     fn schema() -> TableSchema {
+///         let mut schema = TableSchema::new("people");
+///         schema.add_primary(ColumnSchema::<String>::new("name").raw());
+///         schema.add_max(ColumnSchema::<u64>::new("age").raw());
+///         schema
+///     }
+///     fn to_row(self, row: &mut NamedRow) -> Result<(), LensError> {
+///         row.set("name", self.name)?;
+///         row.set("age", self.age)
+///     }
+///     fn from_row(row: &NamedRow) -> Result<Self, LensError> {
+///         Ok(Person {
+///             name: row.get("name")?,
+///             age: row.get("age")?,
+///         })
+///     }
+/// }
+///
+/// let mut db = Database::in_memory();
+/// let people = db.typed_table::<Person>().unwrap();
+/// people.insert(Person { name: "David".to_string(), age: 48 }).unwrap();
+/// let adults = people.filter(|p| p.age >= 18).unwrap();
+/// assert_eq!(adults[0].name, "David");
+/// ```
+pub trait IsRow: Sized {
+    /// The schema of the table, which is created with it if the database has
+    /// no table of its name
+    fn schema() -> TableSchema;
+
+    /// Set the value of each column of a new row
+    fn to_row(self, row: &mut NamedRow) -> Result<(), LensError>;
+
+    /// Read a row of the table
+    fn from_row(row: &NamedRow) -> Result<Self, LensError>;
+}
+
+/// A row of a table whose values are found by the names of their columns
+pub struct NamedRow<'a> {
+    schema: &'a TableSchema,
+    values: Vec<RawValue>,
+}
+
+impl<'a> NamedRow<'a> {
+    /// The positions in a row of the raw columns of the column `name`, in
+    /// the order its lens converts them
+    fn positions<T: Lens>(&self, name: &str) -> Result<Vec<usize>, LensError> {
+        T::NAMES
+            .iter()
+            .zip(T::RAW_KINDS)
+            .map(|(field, kind)| {
+                self.schema
+                    .raw_columns()
+                    .position(|c| c.name() == name && c.fieldname() == *field && c.kind() == *kind)
+                    .ok_or_else(|| LensError::InvalidKinds {
+                        expected: format!("a column {name} holding {}", T::EXPECTED),
+                    })
+            })
+            .collect()
+    }
+
+    /// The value of the column `name`
+    pub fn get<T: Lens>(&self, name: &str) -> Result<T, LensError> {
+        let values = self.positions::<T>(name)?;
+        T::try_from(RawValues(
+            values.into_iter().map(|i| self.values[i].clone()).collect(),
+        ))
+    }
+
+    /// Set the value of the column `name`
+    pub fn set<T: Lens>(&mut self, name: &str, value: T) -> Result<(), LensError> {
+        let positions = self.positions::<T>(name)?;
+        let values: RawValues = value.into();
+        for (i, v) in positions.into_iter().zip(values.0) {
+            self.values[i] = v;
+        }
+        Ok(())
+    }
+}
+
+/// A table of a database whose rows are values of `T`, see
+/// [`Database::typed_table`]
+pub struct TypedTable<T> {
+    handle: TableHandle,
+    row: PhantomData<fn() -> T>,
+}
+
+impl<T: IsRow> TypedTable<T> {
+    /// The table, for reading or changing its raw rows
+    pub fn handle(&self) -> &TableHandle {
+        &self.handle
+    }
+
+    /// Save `row` as a new segment of the table
+    pub fn insert(&self, row: T) -> Result<(), StorageError> {
+        self.insert_all([row])
+    }
+
+    /// Save `rows` together as a new segment of the table
+    pub fn insert_all(&self, rows: impl IntoIterator<Item = T>) -> Result<(), StorageError> {
+        let schema = self.handle.schema();
+        let rows = rows
+            .into_iter()
+            .map(|r| {
+                let mut row = NamedRow {
+                    schema,
+                    values: schema.raw_columns().map(|c| c.default().clone()).collect(),
+                };
+                r.to_row(&mut row)?;
+                Ok(row.values.into_iter().collect::<RawRow>())
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        self.handle.insert_raw_rows(rows)
+    }
+
+    /// Each row of the table, in the order of their primary keys
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<T, StorageError>>, StorageError> {
+        let schema = self.handle.schema().clone();
+        let rows = self.handle.read()?.to_rows()?;
+        Ok(rows.into_iter().map(move |r| {
+            let row = NamedRow {
+                schema: &schema,
+                values: r.values().to_vec(),
+            };
+            Ok(T::from_row(&row)?)
+        }))
+    }
+
+    /// The rows of the table for which `keep` is true
+    pub fn filter(&self, keep: impl Fn(&T) -> bool) -> Result<Vec<T>, StorageError> {
+        let mut rows = Vec::new();
+        for row in self.iter()? {
+            let row = row?;
+            if keep(&row) {
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+}
+
+impl Database {
+    /// The table holding rows of type `T`, which is created if the database
+    /// has no table called by the name in [`IsRow::schema`].
+    ///
+    /// If the table exists, it must have each column of that schema, though
+    /// it may have others too.
+    pub fn typed_table<T: IsRow>(&mut self) -> Result<TypedTable<T>, StorageError> {
+        let schema = T::schema();
+        let handle = match self.schema(schema.name()) {
+            Some(existing) => {
+                for c in schema.raw_columns() {
+                    let found = existing.raw_columns().any(|e| {
+                        e.name() == c.name()
+                            && e.fieldname() == c.fieldname()
+                            && e.kind() == c.kind()
+                            && e.lens() == c.lens()
+                    });
+                    if !found {
+                        return Err(StorageError::Schema(format!(
+                            "table {} has no column {} of kind {:?}",
+                            schema.name(),
+                            c.display_name(),
+                            c.kind()
+                        )));
+                    }
+                }
+                self.table(schema.name())?
+            }
+            None => self.create_table(schema)?,
+        };
+        Ok(TypedTable {
+            handle,
+            row: PhantomData,
+        })
+    }
+}
+
+#[test]
+fn typed_rows() {
+    use crate::ColumnSchema;
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Visit {
+        page: String,
+        when: SystemTime,
+        bounced: bool,
+    }
+    impl IsRow for Visit {
+        fn schema() -> TableSchema {
+            let mut schema = TableSchema::new("visits");
+            schema.add_primary(ColumnSchema::<String>::new("page").raw());
+            schema.add_max(
+                ColumnSchema::<bool>::new("bounced")
+                    .raw()
+                    .chain(ColumnSchema::with_default("when", SystemTime::UNIX_EPOCH).raw()),
+            );
+            schema
+        }
+        fn to_row(self, row: &mut NamedRow) -> Result<(), LensError> {
+            row.set("page", self.page)?;
+            row.set("when", self.when)?;
+            row.set("bounced", self.bounced)
+        }
+        fn from_row(row: &NamedRow) -> Result<Self, LensError> {
+            Ok(Visit {
+                page: row.get("page")?,
+                when: row.get("when")?,
+                bounced: row.get("bounced")?,
+            })
+        }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let visits = db.typed_table::<Visit>().unwrap();
+    let visit = |page: &str, secs, bounced| Visit {
+        page: page.to_string(),
+        when: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        bounced,
+    };
+    visits
+        .insert_all([visit("/home", 10, false), visit("/about", 20, true)])
+        .unwrap();
+    visits.insert(visit("/home", 30, true)).unwrap();
+    drop(db);
+
+    // The table is found again rather than created.
+    let mut db = Database::open(dir.path()).unwrap();
+    let visits = db.typed_table::<Visit>().unwrap();
+    let all = visits.iter().unwrap().collect::<Result<Vec<_>, _>>();
+    assert_eq!(
+        all.unwrap(),
+        [visit("/about", 20, true), visit("/home", 30, true)]
+    );
+    let late = visits
+        .filter(|v| v.when > visit("", 25, false).when)
+        .unwrap();
+    assert_eq!(late, [visit("/home", 30, true)]);
+    assert_eq!(db.schemas().count(), 1);
+
+    // A table of the same name without the columns of the type is refused.
+    let mut other = TableSchema::new("visits");
+    other.add_primary(ColumnSchema::<u64>::new("page").raw());
+    let mut db = Database::in_memory();
+    db.create_table(other).unwrap();
+    assert!(matches!(
+        db.typed_table::<Visit>(),
+        Err(StorageError::Schema(_))
+    ));
+}
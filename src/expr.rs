@@ -0,0 +1,616 @@
+//! Conditions on the values of rows.
+//!
+//! An [`Expr`] names raw columns, as SQL statements do.  Before it is
+//! evaluated it is bound to the columns of a schema, and is then run against
+//! the columns of each segment a chunk at a time: each run of identical
+//! values is tested once for all its rows, and a column whose minimum and
+//! maximum already settle a test is not decoded at all.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::column::encoding::StorageError;
+use crate::query::column_index;
+use crate::{RawColumn, RawKind, RawValue, TableSchema};
+
+/// How a column is compared with a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// The column equals the value
+    Equal,
+    /// The column is less than the value
+    Less,
+    /// The column is no greater than the value
+    LessOrEqual,
+    /// The column is greater than the value
+    Greater,
+    /// The column is no less than the value
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Whether a column that orders this way against the value passes
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessOrEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+/// A condition on the raw columns of a row, see [`Table::select`](crate::Table::select)
+///
+/// Values of different kinds never compare equal, and comparing the order of
+/// a column with a value of another kind is an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<V = RawValue> {
+    /// The column compares with the value
+    Compare(String, Comparison, V),
+    /// The column equals one of the values
+    In(String, Vec<V>),
+    /// The column is no less than the first value and no greater than the
+    /// second
+    Between(String, V, V),
+    /// Both conditions hold
+    And(Box<Expr<V>>, Box<Expr<V>>),
+    /// Either condition holds
+    Or(Box<Expr<V>>, Box<Expr<V>>),
+    /// The condition does not hold
+    Not(Box<Expr<V>>),
+}
+
+impl<V> Expr<V> {
+    /// The condition that this and `other` both hold
+    pub fn and(self, other: Self) -> Self {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+
+    /// The condition that this or `other` holds
+    pub fn or(self, other: Self) -> Self {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Rename each column the condition tests
+    pub(crate) fn rename(self, f: &impl Fn(String) -> String) -> Self {
+        match self {
+            Expr::Compare(column, comparison, v) => Expr::Compare(f(column), comparison, v),
+            Expr::In(column, values) => Expr::In(f(column), values),
+            Expr::Between(column, low, high) => Expr::Between(f(column), low, high),
+            Expr::And(a, b) => a.rename(f).and(b.rename(f)),
+            Expr::Or(a, b) => a.rename(f).or(b.rename(f)),
+            Expr::Not(a) => !a.rename(f),
+        }
+    }
+
+    /// Replace each value of the condition
+    pub(crate) fn try_map<W, E>(self, f: &mut impl FnMut(V) -> Result<W, E>) -> Result<Expr<W>, E> {
+        Ok(match self {
+            Expr::Compare(column, comparison, v) => Expr::Compare(column, comparison, f(v)?),
+            Expr::In(column, values) => Expr::In(
+                column,
+                values.into_iter().map(&mut *f).collect::<Result<_, _>>()?,
+            ),
+            Expr::Between(column, low, high) => Expr::Between(column, f(low)?, f(high)?),
+            Expr::And(a, b) => Expr::And(Box::new(a.try_map(f)?), Box::new(b.try_map(f)?)),
+            Expr::Or(a, b) => Expr::Or(Box::new(a.try_map(f)?), Box::new(b.try_map(f)?)),
+            Expr::Not(a) => Expr::Not(Box::new(a.try_map(f)?)),
+        })
+    }
+}
+
+impl<V> std::ops::Not for Expr<V> {
+    type Output = Self;
+    fn not(self) -> Self {
+        Expr::Not(Box::new(self))
+    }
+}
+
+/// Finds the index and kind of the column of a name
+pub(crate) type Lookup<'a> = dyn Fn(&str) -> Result<(usize, RawKind), StorageError> + 'a;
+
+impl Expr {
+    /// Look up the columns of the condition in `schema`
+    pub(crate) fn bind(&self, schema: &TableSchema) -> Result<Predicate, StorageError> {
+        self.bind_with(&|name| {
+            let i = column_index(schema, name)?;
+            Ok((
+                i,
+                schema.raw_columns().nth(i).expect("column exists").kind(),
+            ))
+        })
+    }
+
+    /// Look up the columns of the condition with `lookup`
+    pub(crate) fn bind_with(&self, lookup: &Lookup) -> Result<Predicate, StorageError> {
+        let test = |column: &str, test: Test| {
+            let (i, kind) = lookup(column)?;
+            let ordered = match &test {
+                Test::Compare(Comparison::Equal, _) | Test::In(_) => None,
+                Test::Compare(_, v) => Some(v),
+                Test::Between(low, high) => Some(if low.kind() != kind { low } else { high }),
+            };
+            if let Some(v) = ordered.filter(|v| v.kind() != kind) {
+                return Err(StorageError::Query(format!(
+                    "column {column} holds {kind:?}, not {:?}",
+                    v.kind()
+                )));
+            }
+            Ok(Predicate::Test(i, test))
+        };
+        Ok(match self {
+            Expr::Compare(column, comparison, v) => {
+                test(column, Test::Compare(*comparison, v.clone()))?
+            }
+            Expr::In(column, values) => {
+                let mut values = values.clone();
+                values.sort();
+                values.dedup();
+                test(column, Test::In(values))?
+            }
+            Expr::Between(column, low, high) => {
+                test(column, Test::Between(low.clone(), high.clone()))?
+            }
+            Expr::And(a, b) => Predicate::And(
+                Box::new(a.bind_with(lookup)?),
+                Box::new(b.bind_with(lookup)?),
+            ),
+            Expr::Or(a, b) => Predicate::Or(
+                Box::new(a.bind_with(lookup)?),
+                Box::new(b.bind_with(lookup)?),
+            ),
+            Expr::Not(a) => Predicate::Not(Box::new(a.bind_with(lookup)?)),
+        })
+    }
+}
+
+/// A test of the value of a single column
+#[derive(Debug, Clone)]
+pub(crate) enum Test {
+    Compare(Comparison, RawValue),
+    /// One of the values, which are sorted
+    In(Vec<RawValue>),
+    Between(RawValue, RawValue),
+}
+
+impl Test {
+    pub(crate) fn matches(&self, value: &RawValue) -> bool {
+        match self {
+            Test::Compare(comparison, v) => {
+                (*comparison == Comparison::Equal || value.kind() == v.kind())
+                    && comparison.holds(value.cmp(v))
+            }
+            Test::In(values) => values.binary_search(value).is_ok(),
+            Test::Between(low, high) => low <= value && value <= high,
+        }
+    }
+
+    /// Whether every value from `min` to `max` passes, or none of them do,
+    /// or `None` if that depends on the values.
+    pub(crate) fn settled(&self, min: &RawValue, max: &RawValue) -> Option<bool> {
+        if min == max {
+            return Some(self.matches(min));
+        }
+        match self {
+            Test::Compare(comparison, v) => {
+                // Only the order of values matters, so the ends of the range
+                // pass or fail together if the whole range does.
+                let (low, high) = (self.matches(min), self.matches(max));
+                match comparison {
+                    Comparison::Equal if v < min || v > max => Some(false),
+                    Comparison::Equal => None,
+                    _ if low == high => Some(low),
+                    _ => None,
+                }
+            }
+            Test::In(values) => {
+                if values.iter().all(|v| v < min || v > max) {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            Test::Between(low, high) => {
+                if low <= min && max <= high {
+                    Some(true)
+                } else if max < low || min > high {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// The rows of `column` that pass, testing each chunk just once
+    fn select(&self, column: &RawColumn) -> Result<Selection, StorageError> {
+        let num_rows = column.num_rows();
+        match self.settled(&column.min(), &column.max()) {
+            Some(true) => return Ok(Selection::all(num_rows)),
+            Some(false) => return Ok(Selection::default()),
+            None => (),
+        }
+        let mut selection = Selection::default();
+        if column.kind() == RawKind::U64 && column.num_chunks() == num_rows {
+            // Without runs, decoding a block at a time is faster.
+            for (v, row) in column.read_u64()?.into_iter().zip(0..) {
+                if self.matches(&RawValue::U64(v)) {
+                    selection.push(row..row + 1);
+                }
+            }
+            return Ok(selection);
+        }
+        let mut row = 0;
+        for run in column.runs() {
+            let (value, num) = run?;
+            if self.matches(&value) {
+                selection.push(row..row + num);
+            }
+            row += num;
+        }
+        Ok(selection)
+    }
+}
+
+/// An [`Expr`] with its columns looked up in a schema
+#[derive(Debug, Clone)]
+pub(crate) enum Predicate {
+    Test(usize, Test),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Whether a row with these values matches
+    pub(crate) fn matches(&self, values: &[RawValue]) -> bool {
+        match self {
+            Predicate::Test(i, test) => test.matches(&values[*i]),
+            Predicate::And(a, b) => a.matches(values) && b.matches(values),
+            Predicate::Or(a, b) => a.matches(values) || b.matches(values),
+            Predicate::Not(a) => !a.matches(values),
+        }
+    }
+
+    /// Whether every column tested is one of the first `n`
+    pub(crate) fn only_tests_first(&self, n: usize) -> bool {
+        match self {
+            Predicate::Test(i, _) => *i < n,
+            Predicate::And(a, b) | Predicate::Or(a, b) => {
+                a.only_tests_first(n) && b.only_tests_first(n)
+            }
+            Predicate::Not(a) => a.only_tests_first(n),
+        }
+    }
+
+    /// Whether column `column` is tested
+    pub(crate) fn tests(&self, column: usize) -> bool {
+        match self {
+            Predicate::Test(i, _) => *i == column,
+            Predicate::And(a, b) | Predicate::Or(a, b) => a.tests(column) || b.tests(column),
+            Predicate::Not(a) => a.tests(column),
+        }
+    }
+
+    /// The values column `column` must hold one of for a row to match, if
+    /// the predicate requires it to equal one of some values.
+    pub(crate) fn required_values(&self, column: usize) -> Option<&[RawValue]> {
+        match self {
+            Predicate::Test(i, Test::Compare(Comparison::Equal, v)) if *i == column => {
+                Some(std::slice::from_ref(v))
+            }
+            Predicate::Test(i, Test::In(values)) if *i == column => Some(values),
+            Predicate::And(a, b) => a
+                .required_values(column)
+                .or_else(|| b.required_values(column)),
+            _ => None,
+        }
+    }
+
+    /// Reorder the tests joined by `AND` so the most selective is evaluated
+    /// first, and those joined by `OR` so the least selective is, giving the
+    /// estimated fraction of rows that match.
+    ///
+    /// `estimate` gives the fraction of rows estimated to pass a test of a
+    /// column.  Tests are taken to be independent of each other.
+    pub(crate) fn plan(&self, estimate: &impl Fn(usize, &Test) -> f64) -> (Predicate, f64) {
+        match self {
+            Predicate::Test(i, test) => (self.clone(), estimate(*i, test)),
+            Predicate::And(..) => {
+                let mut terms = Vec::new();
+                self.flatten(true, &mut terms);
+                let mut terms = terms.iter().map(|p| p.plan(estimate)).collect::<Vec<_>>();
+                terms.sort_by(|a, b| a.1.total_cmp(&b.1));
+                let fraction = terms.iter().map(|t| t.1).product();
+                let planned = terms
+                    .into_iter()
+                    .map(|t| t.0)
+                    .reduce(|a, b| Predicate::And(Box::new(a), Box::new(b)))
+                    .expect("AND has terms");
+                (planned, fraction)
+            }
+            Predicate::Or(..) => {
+                let mut terms = Vec::new();
+                self.flatten(false, &mut terms);
+                let mut terms = terms.iter().map(|p| p.plan(estimate)).collect::<Vec<_>>();
+                terms.sort_by(|a, b| b.1.total_cmp(&a.1));
+                let fraction = 1.0 - terms.iter().map(|t| 1.0 - t.1).product::<f64>();
+                let planned = terms
+                    .into_iter()
+                    .map(|t| t.0)
+                    .reduce(|a, b| Predicate::Or(Box::new(a), Box::new(b)))
+                    .expect("OR has terms");
+                (planned, fraction)
+            }
+            Predicate::Not(a) => {
+                let (a, fraction) = a.plan(estimate);
+                (Predicate::Not(Box::new(a)), 1.0 - fraction)
+            }
+        }
+    }
+
+    /// Collect the terms of a chain of `AND`, or of `OR` unless `and`
+    fn flatten<'a>(&'a self, and: bool, terms: &mut Vec<&'a Predicate>) {
+        match (self, and) {
+            (Predicate::And(a, b), true) | (Predicate::Or(a, b), false) => {
+                a.flatten(and, terms);
+                b.flatten(and, terms);
+            }
+            _ => terms.push(self),
+        }
+    }
+
+    /// The column of bools and the value it is tested to equal, if this is
+    /// such a test of a column in `columns`
+    fn bool_test<'a>(&self, columns: &'a [Option<RawColumn>]) -> Option<(&'a RawColumn, bool)> {
+        match self {
+            Predicate::Test(i, Test::Compare(Comparison::Equal, RawValue::Bool(v))) => columns[*i]
+                .as_ref()
+                .filter(|c| c.kind() == RawKind::Bool)
+                .map(|c| (c, *v)),
+            _ => None,
+        }
+    }
+
+    /// The rows of a segment that match, given its raw columns, with `None`
+    /// for those that hold their defaults.
+    ///
+    /// The second half of `AND` and `OR` is not evaluated when the first half
+    /// already decides every row.  When both halves test columns of bools,
+    /// the runs of the two columns are combined directly.
+    pub(crate) fn select(
+        &self,
+        schema: &TableSchema,
+        columns: &[Option<RawColumn>],
+        num_rows: u64,
+    ) -> Result<Selection, StorageError> {
+        Ok(match self {
+            Predicate::Test(i, test) => match &columns[*i] {
+                Some(column) => test.select(column)?,
+                None => {
+                    let default = schema
+                        .raw_columns()
+                        .nth(*i)
+                        .expect("column exists")
+                        .default();
+                    if test.matches(default) {
+                        Selection::all(num_rows)
+                    } else {
+                        Selection::default()
+                    }
+                }
+            },
+            Predicate::And(a, b) => {
+                if let Some(((a, x), (b, y))) = a.bool_test(columns).zip(b.bool_test(columns)) {
+                    return a.combine_bools(b, |a, b| a == x && b == y);
+                }
+                let a = a.select(schema, columns, num_rows)?;
+                if a.is_empty() {
+                    return Ok(a);
+                }
+                a.intersection(&b.select(schema, columns, num_rows)?)
+            }
+            Predicate::Or(a, b) => {
+                if let Some(((a, x), (b, y))) = a.bool_test(columns).zip(b.bool_test(columns)) {
+                    return a.combine_bools(b, |a, b| a == x || b == y);
+                }
+                let a = a.select(schema, columns, num_rows)?;
+                if a == Selection::all(num_rows) {
+                    return Ok(a);
+                }
+                a.union(&b.select(schema, columns, num_rows)?)
+            }
+            Predicate::Not(a) => a.select(schema, columns, num_rows)?.complement(num_rows),
+        })
+    }
+}
+
+/// Rows picked out of a segment or column, as sorted ranges of row numbers
+/// that neither overlap nor touch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection(Vec<Range<u64>>);
+
+impl Selection {
+    /// Every one of `num_rows` rows
+    pub fn all(num_rows: u64) -> Self {
+        let mut selection = Selection::default();
+        selection.push(0..num_rows);
+        selection
+    }
+
+    /// Add rows after those already selected
+    pub(crate) fn push(&mut self, rows: Range<u64>) {
+        if rows.is_empty() {
+            return;
+        }
+        match self.0.last_mut() {
+            Some(last) if last.end >= rows.start => last.end = last.end.max(rows.end),
+            _ => self.0.push(rows),
+        }
+    }
+
+    /// Whether no row is selected
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of rows selected
+    pub fn len(&self) -> u64 {
+        self.0.iter().map(|r| r.end - r.start).sum()
+    }
+
+    /// The ranges of row numbers selected, in order
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.0
+    }
+
+    /// The selected row numbers, in order
+    pub fn rows(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.iter().flat_map(|r| r.clone())
+    }
+
+    /// The rows selected by both
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut out = Selection::default();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(a), Some(b)) = (self.0.get(i), other.0.get(j)) {
+            out.push(a.start.max(b.start)..a.end.min(b.end));
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        out
+    }
+
+    /// The rows selected by either
+    pub fn union(&self, other: &Self) -> Self {
+        let mut ranges = self.0.iter().chain(other.0.iter()).collect::<Vec<_>>();
+        ranges.sort_by_key(|r| r.start);
+        let mut out = Selection::default();
+        for r in ranges {
+            out.push(r.clone());
+        }
+        out
+    }
+
+    /// The rows of `num_rows` that are not selected
+    pub fn complement(&self, num_rows: u64) -> Self {
+        let mut out = Selection::default();
+        let mut start = 0;
+        for r in self.0.iter() {
+            out.push(start..r.start);
+            start = r.end;
+        }
+        out.push(start..num_rows);
+        out
+    }
+}
+
+#[test]
+fn select_chunks() {
+    use crate::{ColumnSchema, EncodeOptions, RawKind};
+    use rand::{Rng, SeedableRng};
+
+    let mut schema = TableSchema::new("readings");
+    schema.add_primary(ColumnSchema::<u64>::new("time").raw());
+    schema.add_max(
+        ColumnSchema::<String>::new("sensor")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("ok").raw())
+            .chain(ColumnSchema::<u64>::new("missing").raw()),
+    );
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let rows = (0..1000u64)
+        .map(|i| {
+            vec![
+                RawValue::U64(i / 7 * 3),
+                RawValue::Bytes(format!("sensor {}", rng.gen_range(0..4)).into_bytes()),
+                RawValue::Bool(rng.gen_range(0..10) > 0),
+                RawValue::U64(0),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let column = |i: usize, kind: RawKind| {
+        let values = rows.iter().map(|r| r[i].clone()).collect::<Vec<_>>();
+        let mut bytes = Vec::new();
+        let options = EncodeOptions::default().max_chunk_rows(5);
+        RawColumn::write_values_with(&mut bytes, kind, &values, options).unwrap();
+        Some(RawColumn::decode(bytes).unwrap())
+    };
+    let columns = [
+        column(0, RawKind::U64),
+        column(1, RawKind::Bytes),
+        column(2, RawKind::Bool),
+        None,
+    ];
+
+    let time = |c, v| Expr::Compare("time".to_string(), c, RawValue::U64(v));
+    let sensor = |s: &str| RawValue::Bytes(s.as_bytes().to_vec());
+    let ok = |v| Expr::Compare("ok".to_string(), Comparison::Equal, RawValue::Bool(v));
+    let exprs = [
+        time(Comparison::Less, 10),
+        time(Comparison::GreaterOrEqual, 0),
+        time(Comparison::Greater, 10_000),
+        time(Comparison::Equal, 300).or(time(Comparison::LessOrEqual, 6)),
+        !time(Comparison::Equal, 300),
+        Expr::Between("time".to_string(), RawValue::U64(100), RawValue::U64(200)).and(
+            Expr::Compare("ok".to_string(), Comparison::Equal, RawValue::Bool(false)),
+        ),
+        ok(false).and(ok(false)),
+        ok(true).and(ok(false)),
+        ok(false).or(ok(true)),
+        Expr::In(
+            "sensor".to_string(),
+            vec![sensor("sensor 1"), sensor("sensor 3")],
+        ),
+        Expr::In(
+            "sensor".to_string(),
+            vec![sensor("nobody"), RawValue::U64(1)],
+        ),
+        Expr::Compare("missing".to_string(), Comparison::Equal, RawValue::U64(0)).and(
+            !Expr::Between("sensor".to_string(), sensor("sensor 1"), sensor("sensor 2")),
+        ),
+        Expr::Compare("missing".to_string(), Comparison::Greater, RawValue::U64(0)).or(
+            Expr::Compare("sensor".to_string(), Comparison::Equal, RawValue::U64(1)),
+        ),
+    ];
+    for expr in exprs {
+        let predicate = expr.bind(&schema).unwrap();
+        let selection = predicate.select(&schema, &columns, 1000).unwrap();
+        let expected = (0..1000)
+            .filter(|&i| predicate.matches(&rows[i as usize]))
+            .collect::<Vec<_>>();
+        assert_eq!(selection.rows().collect::<Vec<_>>(), expected, "{expr:?}");
+    }
+
+    let error = |expr: Expr| expr.bind(&schema).unwrap_err().to_string();
+    expect_test::expect!["Query error: no column height in table readings"].assert_eq(&error(
+        time(Comparison::Equal, 1).and(Expr::In("height".to_string(), vec![])),
+    ));
+    expect_test::expect!["Query error: column sensor holds Bytes, not U64"].assert_eq(&error(
+        Expr::Between("sensor".to_string(), sensor("a"), RawValue::U64(1)),
+    ));
+}
+
+#[test]
+fn selection_sets() {
+    let selection = |ranges: &[Range<u64>]| {
+        let mut s = Selection::default();
+        for r in ranges {
+            s.push(r.clone());
+        }
+        s
+    };
+    let a = selection(&[0..3, 5..8, 10..12]);
+    let b = selection(&[2..6, 7..10]);
+    assert_eq!(a.intersection(&b), selection(&[2..3, 5..6, 7..8]));
+    assert_eq!(a.union(&b), Selection::all(12));
+    assert_eq!(a.complement(15), selection(&[3..5, 8..10, 12..15]));
+    assert_eq!(selection(&[0..2, 2..4]), Selection::all(4));
+    assert!(Selection::all(0).is_empty());
+    assert_eq!(Selection::default().complement(3), Selection::all(3));
+}
@@ -0,0 +1,312 @@
+//! The files of a database, which are kept either on disk or in memory.
+//!
+//! A database made by [`Database::in_memory`](crate::Database::in_memory)
+//! holds a [`MemoryRoot`], a root directory below which every file is kept
+//! as shared bytes instead of on disk.  All file access by tables and
+//! databases goes through this module, so the two kinds of database behave
+//! the same way.  Every root lies below one directory that is never used on
+//! disk, so a path elsewhere goes straight to the disk, without looking for
+//! a root in memory.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::column::encoding::StorageError;
+use crate::column::storage::Storage;
+use crate::{Metrics, RawColumn};
+
+/// The directory holding every in-memory root
+const MEMORY: &str = "/equilia-in-memory";
+
+/// The files below one in-memory root
+#[derive(Debug)]
+struct MemoryDir {
+    root: PathBuf,
+    files: Mutex<BTreeMap<PathBuf, Arc<[u8]>>>,
+}
+
+/// An in-memory directory, whose files are forgotten when it is dropped
+#[derive(Debug)]
+pub(crate) struct MemoryRoot(Arc<MemoryDir>);
+
+/// The in-memory roots that have not been dropped
+static ROOTS: Mutex<Vec<Weak<MemoryDir>>> = Mutex::new(Vec::new());
+
+/// The name of the lock file within a database directory
+const LOCK: &str = "LOCK";
+
+/// Run `f` on the in-memory files holding `path`, if it is in memory
+fn in_memory<T>(path: &Path, f: impl FnOnce(&mut BTreeMap<PathBuf, Arc<[u8]>>) -> T) -> Option<T> {
+    if !path.starts_with(MEMORY) {
+        return None;
+    }
+    let dir = ROOTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter_map(Weak::upgrade)
+        .find(|m| path.starts_with(&m.root))?;
+    let mut files = dir.files.lock().unwrap_or_else(|e| e.into_inner());
+    Some(f(&mut files))
+}
+
+fn not_found(path: &Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    )
+}
+
+/// File locks, of which there are none on wasm32, where no file can be
+/// opened in the first place
+#[cfg(target_arch = "wasm32")]
+mod fs2 {
+    fn unsupported() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::Unsupported, "files cannot be locked")
+    }
+
+    pub(super) trait FileExt {
+        fn try_lock_exclusive(&self) -> std::io::Result<()>;
+        fn lock_shared(&self) -> std::io::Result<()>;
+    }
+
+    impl FileExt for std::fs::File {
+        fn try_lock_exclusive(&self) -> std::io::Result<()> {
+            Err(unsupported())
+        }
+        fn lock_shared(&self) -> std::io::Result<()> {
+            Err(unsupported())
+        }
+    }
+
+    pub(super) fn lock_contended_error() -> std::io::Error {
+        std::io::Error::from(std::io::ErrorKind::WouldBlock)
+    }
+}
+
+impl MemoryRoot {
+    /// Register a new, empty, in-memory directory
+    pub(crate) fn new() -> Self {
+        let dir = Arc::new(MemoryDir {
+            root: Path::new(MEMORY).join(format!("{:016x}", rand::random::<u64>())),
+            files: Mutex::new(BTreeMap::new()),
+        });
+        let mut roots = ROOTS.lock().unwrap_or_else(|e| e.into_inner());
+        roots.retain(|r| r.strong_count() > 0);
+        roots.push(Arc::downgrade(&dir));
+        MemoryRoot(dir)
+    }
+
+    /// The directory below which the files are kept
+    pub(crate) fn path(&self) -> &Path {
+        &self.0.root
+    }
+}
+
+pub(crate) fn create_dir_all(dir: &Path) -> std::io::Result<()> {
+    in_memory(dir, |_| Ok(())).unwrap_or_else(|| std::fs::create_dir_all(dir))
+}
+
+/// Read a whole file, which is shared rather than copied if it is in memory
+pub(crate) fn read(path: &Path) -> std::io::Result<Arc<[u8]>> {
+    in_memory(path, |files| {
+        files.get(path).cloned().ok_or_else(|| not_found(path))
+    })
+    .unwrap_or_else(|| Ok(std::fs::read(path)?.into()))
+}
+
+/// The number of bytes in a file
+pub(crate) fn file_len(path: &Path) -> std::io::Result<u64> {
+    in_memory(path, |files| {
+        files
+            .get(path)
+            .map(|bytes| bytes.len() as u64)
+            .ok_or_else(|| not_found(path))
+    })
+    .unwrap_or_else(|| Ok(std::fs::metadata(path)?.len()))
+}
+
+/// Write a file, without waiting for it to reach the disk
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    in_memory(path, |files| {
+        files.insert(path.to_path_buf(), bytes.into());
+        Ok(())
+    })
+    .unwrap_or_else(|| std::fs::write(path, bytes))
+}
+
+/// Write a file and sync it to disk
+pub(crate) fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    in_memory(path, |files| {
+        files.insert(path.to_path_buf(), bytes.into());
+        Ok(())
+    })
+    .unwrap_or_else(|| {
+        let mut f = std::fs::File::create(path)?;
+        f.write_all(bytes)?;
+        f.sync_all()
+    })
+}
+
+/// Make `to` a hard link to the file `from`, or a copy of it where files
+/// cannot be linked, so that either can be removed without the other
+pub(crate) fn hard_link(from: &Path, to: &Path) -> std::io::Result<()> {
+    in_memory(from, |files| {
+        let bytes = files.get(from).cloned().ok_or_else(|| not_found(from))?;
+        files.insert(to.to_path_buf(), bytes);
+        Ok(())
+    })
+    .unwrap_or_else(|| match std::fs::hard_link(from, to) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => std::fs::copy(from, to).map(|_| ()),
+        linked => linked,
+    })
+}
+
+pub(crate) fn rename(from: &Path, to: &Path) -> std::io::Result<()> {
+    in_memory(from, |files| {
+        let bytes = files.remove(from).ok_or_else(|| not_found(from))?;
+        files.insert(to.to_path_buf(), bytes);
+        Ok(())
+    })
+    .unwrap_or_else(|| std::fs::rename(from, to))
+}
+
+pub(crate) fn remove_file(path: &Path) -> std::io::Result<()> {
+    in_memory(path, |files| {
+        files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    })
+    .unwrap_or_else(|| std::fs::remove_file(path))
+}
+
+pub(crate) fn remove_dir_all(dir: &Path) -> std::io::Result<()> {
+    in_memory(dir, |files| {
+        files.retain(|f, _| !f.starts_with(dir));
+        Ok(())
+    })
+    .unwrap_or_else(|| std::fs::remove_dir_all(dir))
+}
+
+/// Remove `dir` if it holds no files.  The files in memory are kept by path
+/// alone, so there is no directory to remove.
+pub(crate) fn remove_dir_if_empty(dir: &Path) -> std::io::Result<()> {
+    if in_memory(dir, |_| ()).is_some() {
+        return Ok(());
+    }
+    let empty = std::fs::read_dir(dir).map(|mut entries| entries.next().is_none());
+    if matches!(empty, Ok(true)) {
+        std::fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// Make renames and file creations within `dir` durable.
+pub(crate) fn sync_dir(dir: &Path) -> Result<(), StorageError> {
+    if in_memory(dir, |_| ()).is_none() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Take the exclusive lock on the database in `dir`, which is released when
+/// the returned file is dropped.
+///
+/// The lock is advisory and taken without waiting, so a second writer fails
+/// at once.  A database in memory needs no lock.
+pub(crate) fn lock(dir: &Path) -> Result<Option<std::fs::File>, StorageError> {
+    use fs2::FileExt;
+
+    if in_memory(dir, |_| ()).is_some() {
+        return Ok(None);
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK))?;
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(Some(file)),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+            Err(StorageError::Locked(dir.display().to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The files directly within `dir`, which are none if it does not exist
+pub(crate) fn list_dir(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    in_memory(dir, |files| {
+        Ok(files
+            .keys()
+            .filter(|f| f.parent() == Some(dir))
+            .cloned()
+            .collect())
+    })
+    .unwrap_or_else(|| match std::fs::read_dir(dir) {
+        Ok(entries) => entries.map(|e| Ok(e?.path())).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    })
+}
+
+/// Create an empty file that is held until the returned file is dropped, so
+/// that [`is_held`] can tell a file left behind by a crash.
+///
+/// The file is locked before it is given its name, so it is never seen
+/// unheld.
+pub(crate) fn create_held(path: &Path) -> std::io::Result<Option<std::fs::File>> {
+    let created = in_memory(path, |files| {
+        files.insert(path.to_path_buf(), Arc::from([]));
+    });
+    if created.is_some() {
+        return Ok(None);
+    }
+    let tmp = path.with_extension("tmp");
+    let file = std::fs::File::create(&tmp)?;
+    // Named in full, since newer versions of std have a method of the same
+    // name.
+    fs2::FileExt::lock_shared(&file)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(Some(file))
+}
+
+/// Whether the file made by [`create_held`] at `path` is still held.
+///
+/// A file in memory is always held, since it is gone once its process is.
+pub(crate) fn is_held(path: &Path) -> bool {
+    use fs2::FileExt;
+
+    if in_memory(path, |_| ()).is_some() {
+        return true;
+    }
+    match std::fs::File::open(path) {
+        Ok(file) => file.try_lock_exclusive().is_err(),
+        Err(_) => false,
+    }
+}
+
+/// Open a column file, counting its reads in `metrics`
+pub(crate) fn open_column(path: &Path, metrics: &Metrics) -> Result<RawColumn, StorageError> {
+    match in_memory(path, |files| files.get(path).cloned()) {
+        Some(Some(bytes)) => RawColumn::open_storage(Storage::from(bytes).with_metrics(metrics)),
+        Some(None) => Err(not_found(path).into()),
+        None => RawColumn::open_with_metrics(path, metrics),
+    }
+}
+
+#[test]
+fn memory_files() {
+    let root = MemoryRoot::new();
+    let path = root.path().join("a");
+    write(&path, b"abc").unwrap();
+    // Reading a file in memory shares its bytes instead of copying them.
+    let (first, second) = (read(&path).unwrap(), read(&path).unwrap());
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(&first[..], b"abc");
+    drop(root);
+    assert!(read(&path).is_err());
+}
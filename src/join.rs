@@ -0,0 +1,166 @@
+//! Joining the rows of two tables.
+//!
+//! Rows from each side are paired up where their key columns are equal.
+//! When both sides are already sorted by their keys, as the rows of a table
+//! are when the keys lead its primary key, the sides are merged in a single
+//! pass.  Otherwise the right side is hashed by its keys and each row of the
+//! left side looks up its matches.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::{RawRow, RawValue};
+
+/// The values of the key columns of a row
+fn key<'a>(row: &'a RawRow, columns: &[usize]) -> Vec<&'a RawValue> {
+    columns.iter().map(|&i| &row.values[i]).collect()
+}
+
+fn is_sorted(rows: &[RawRow], columns: &[usize]) -> bool {
+    rows.windows(2)
+        .all(|w| key(&w[0], columns) <= key(&w[1], columns))
+}
+
+/// The values of `left` followed by those of `right`
+fn pair(left: &RawRow, right: &RawRow) -> RawRow {
+    left.values.iter().chain(&right.values).cloned().collect()
+}
+
+/// Pair up each row of `left` with each row of `right` whose values in the
+/// columns `right_key` equal its own values in the columns `left_key`.
+///
+/// Each pair gives a row of the values of the left row followed by those of
+/// the right.  Pairs come in the order of the left rows, and then in the
+/// order of the right rows.
+///
+/// # Panics
+///
+/// If the keys have different numbers of columns.
+pub fn join(
+    left: &[RawRow],
+    left_key: &[usize],
+    right: &[RawRow],
+    right_key: &[usize],
+) -> Vec<RawRow> {
+    assert_eq!(
+        left_key.len(),
+        right_key.len(),
+        "join keys have different numbers of columns"
+    );
+    if is_sorted(left, left_key) && is_sorted(right, right_key) {
+        merge_join(left, left_key, right, right_key)
+    } else {
+        hash_join(left, left_key, right, right_key)
+    }
+}
+
+/// Join two sides that are both sorted by their keys
+fn merge_join(
+    left: &[RawRow],
+    left_key: &[usize],
+    right: &[RawRow],
+    right_key: &[usize],
+) -> Vec<RawRow> {
+    let mut joined = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        let k = key(&right[j], right_key);
+        match key(&left[i], left_key).cmp(&k) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                let matches = right[j..]
+                    .iter()
+                    .take_while(|r| key(r, right_key) == k)
+                    .collect::<Vec<_>>();
+                while i < left.len() && key(&left[i], left_key) == k {
+                    joined.extend(matches.iter().map(|r| pair(&left[i], r)));
+                    i += 1;
+                }
+                j += matches.len();
+            }
+        }
+    }
+    joined
+}
+
+/// Join two sides in any order
+fn hash_join(
+    left: &[RawRow],
+    left_key: &[usize],
+    right: &[RawRow],
+    right_key: &[usize],
+) -> Vec<RawRow> {
+    let mut by_key = HashMap::<_, Vec<&RawRow>>::new();
+    for r in right {
+        by_key.entry(key(r, right_key)).or_default().push(r);
+    }
+    let mut joined = Vec::new();
+    for l in left {
+        if let Some(matches) = by_key.get(&key(l, left_key)) {
+            joined.extend(matches.iter().map(|r| pair(l, r)));
+        }
+    }
+    joined
+}
+
+#[test]
+fn join_rows() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut rows = |n: usize, label: &str| {
+        (0..n)
+            .map(|i| {
+                [
+                    RawValue::U64(rng.gen_range(0..20)),
+                    RawValue::Bool(rng.gen()),
+                    RawValue::Bytes(format!("{label} {i}").into_bytes()),
+                ]
+                .into_iter()
+                .collect::<RawRow>()
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut left = rows(50, "left");
+    let mut right = rows(40, "right");
+    let expected = |left: &[RawRow], right: &[RawRow], columns: &[usize]| {
+        let mut joined = Vec::new();
+        for l in left {
+            for r in right {
+                if key(l, columns) == key(r, columns) {
+                    joined.push(pair(l, r));
+                }
+            }
+        }
+        joined
+    };
+    for columns in [&[0][..], &[0, 1]] {
+        assert_eq!(
+            join(&left, columns, &right, columns),
+            expected(&left, &right, columns)
+        );
+        left.sort();
+        right.sort();
+        assert!(is_sorted(&left, columns) && is_sorted(&right, columns));
+        assert_eq!(
+            merge_join(&left, columns, &right, columns),
+            expected(&left, &right, columns)
+        );
+        assert_eq!(
+            hash_join(&left, columns, &right, columns),
+            expected(&left, &right, columns)
+        );
+    }
+
+    // Keys may be in different places on each side.
+    let swapped = right
+        .iter()
+        .map(|r| r.values.iter().rev().cloned().collect())
+        .collect::<Vec<RawRow>>();
+    assert_eq!(
+        join(&left, &[0], &swapped, &[2]).len(),
+        expected(&left, &right, &[0]).len()
+    );
+    assert!(join(&left, &[2], &right, &[2]).is_empty());
+}
@@ -0,0 +1,125 @@
+//! Where the files of a database are stored.
+//!
+//! Each table of a [`Database`](crate::Database) has a directory of its own
+//! within that of the database, holding its manifest and the column files of
+//! its segments.  A [`DbLayout`] decides the name of that directory, and the
+//! names of the column files within it, which may place them in
+//! subdirectories.  The manifest records the name of each column file, so
+//! a table reads the same whatever layout wrote it, but a database must be
+//! opened with the layout that chose the directories of its tables.
+
+use std::path::PathBuf;
+
+use crate::{ColumnId, TableSchema};
+
+/// How the directories of tables and their column files are named.
+pub trait DbLayout: std::fmt::Debug + Send + Sync {
+    /// The directory of a table, relative to that of the database
+    fn table_dir(&self, schema: &TableSchema) -> PathBuf;
+
+    /// The column file of the raw column `fieldname` of logical column
+    /// `column` of a segment of a table, relative to the directory of the
+    /// table, with `/` between the directories holding it.
+    ///
+    /// The segment is in `partition` if the table is partitioned.  The name
+    /// must differ for each segment and raw column.
+    fn column_file(
+        &self,
+        segment: u64,
+        partition: Option<u64>,
+        column: ColumnId,
+        fieldname: &str,
+    ) -> String;
+}
+
+/// The layout a database has unless it is given another.
+///
+/// Each table is named by its id, and the column files of all its segments
+/// are kept together, other than those of each partition of a partitioned
+/// table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlatLayout;
+
+impl DbLayout for FlatLayout {
+    fn table_dir(&self, schema: &TableSchema) -> PathBuf {
+        PathBuf::from(format!("{:032x}", u128::from_be_bytes(schema.id().0)))
+    }
+
+    fn column_file(
+        &self,
+        segment: u64,
+        partition: Option<u64>,
+        column: ColumnId,
+        fieldname: &str,
+    ) -> String {
+        let column = u128::from_be_bytes(column.0);
+        let dir = partition.map(partition_dir).unwrap_or_default();
+        if fieldname.is_empty() {
+            format!("{dir}{segment:08x}-{column:032x}")
+        } else {
+            format!("{dir}{segment:08x}-{column:032x}.{fieldname}")
+        }
+    }
+}
+
+/// A layout that is easier to find your way around.
+///
+/// Each table is named by its name as well as its id, and the column files
+/// of each segment are kept in a directory of their own.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NestedLayout;
+
+impl DbLayout for NestedLayout {
+    /// The name of the table comes first, with any character that could
+    /// leave the directory of the database replaced by `_`
+    fn table_dir(&self, schema: &TableSchema) -> PathBuf {
+        let name = schema
+            .name()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        PathBuf::from(format!("{name}-{}", FlatLayout.table_dir(schema).display()))
+    }
+
+    fn column_file(
+        &self,
+        segment: u64,
+        partition: Option<u64>,
+        column: ColumnId,
+        fieldname: &str,
+    ) -> String {
+        let column = u128::from_be_bytes(column.0);
+        let dir = partition.map(partition_dir).unwrap_or_default();
+        if fieldname.is_empty() {
+            format!("{dir}segment-{segment:08x}/{column:032x}")
+        } else {
+            format!("{dir}segment-{segment:08x}/{column:032x}.{fieldname}")
+        }
+    }
+}
+
+/// The directory, relative to that of the table, holding the column files of
+/// a partition, with a trailing slash
+fn partition_dir(partition: u64) -> String {
+    format!("partition-{partition}/")
+}
+
+#[test]
+fn name_files() {
+    let schema = TableSchema::new("page views/2024");
+    let column = ColumnId([7; 16]);
+    let flat = FlatLayout.table_dir(&schema);
+    let nested = NestedLayout.table_dir(&schema);
+    assert_eq!(
+        nested.to_str().unwrap(),
+        format!("page_views_2024-{}", flat.display())
+    );
+
+    expect_test::expect!["00000003-07070707070707070707070707070707.lo"]
+        .assert_eq(&FlatLayout.column_file(3, None, column, "lo"));
+    expect_test::expect!["partition-5/segment-00000003/07070707070707070707070707070707"]
+        .assert_eq(&NestedLayout.column_file(3, Some(5), column, ""));
+}
@@ -1,9 +1,19 @@
 use crate::value::{RawKind, RawValue};
+use crate::Expr;
+
+#[cfg(feature = "chrono")]
+mod datetime;
+#[cfg(feature = "serde_json")]
+mod json;
+mod sketch;
+
+pub use sketch::QuantileSketch;
 
 /// A vec of values
 pub struct RawValues(pub Vec<RawValue>);
 
 /// A conversion error
+#[derive(Debug)]
 pub enum LensError {
     /// The kinds of columns were invalid
     InvalidKinds {
@@ -17,12 +27,25 @@ pub enum LensError {
     },
 }
 
+impl std::fmt::Display for LensError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LensError::InvalidKinds { expected } => write!(f, "invalid kinds, expected {expected}"),
+            LensError::InvalidValue { value } => write!(f, "invalid value {value}"),
+        }
+    }
+}
+impl std::error::Error for LensError {}
+
 macro_rules! define_lens_id {
-    ($tname:ident, $lensid:expr) => {
+    ($(#[$meta:meta])* $tname:ident, $lensid:expr) => {
+        $(#[$meta])*
         #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
         pub struct $tname(pub(crate) [u8; 16]);
 
         impl $tname {
+            /// A new random id
+            #[allow(clippy::new_without_default)]
             pub fn new() -> Self {
                 Self(rand::random())
             }
@@ -33,7 +56,7 @@ macro_rules! define_lens_id {
         }
 
         impl Lens for $tname {
-            const RAW_KINDS: &'static [RawKind] = &[RawKind::U64];
+            const RAW_KINDS: &'static [RawKind] = &[RawKind::Bytes];
             const LENS_ID: LensId = LensId(*$lensid);
             const EXPECTED: &'static str = "[u8;16]";
             const NAMES: &'static [&'static str] = &[""];
@@ -91,9 +114,13 @@ macro_rules! define_lens_id {
     };
 }
 
-define_lens_id! {ColumnId, b"__ColumnId______"}
+define_lens_id! {
+    /// The unique id of a logical column
+    ColumnId, b"__ColumnId______"
+}
 define_lens_id! {TableId, b"__TableId_______"}
 define_lens_id! {LensId, b"__LensId________"}
+define_lens_id! {AggregationId, b"__AggregationId_"}
 
 /// A way of looking at a table or modifying it, a kind of pseudocolumn.
 pub trait Lens: Into<RawValues> + TryFrom<RawValues, Error = LensError> {
@@ -107,6 +134,57 @@ pub trait Lens: Into<RawValues> + TryFrom<RawValues, Error = LensError> {
     const NAMES: &'static [&'static str];
 }
 
+/// The raw values of a lens, checking that there is one for each of its
+/// names, of the kind it declares
+pub(crate) fn raw_values<T: Lens>(value: T) -> Result<RawValues, LensError> {
+    let values: RawValues = value.into();
+    let shape = |what: String| LensError::InvalidKinds {
+        expected: format!("lens {} to {what}", T::LENS_ID),
+    };
+    if T::NAMES.len() != T::RAW_KINDS.len() {
+        return Err(shape(format!(
+            "name each of its {} raw kinds, not {}",
+            T::RAW_KINDS.len(),
+            T::NAMES.len()
+        )));
+    }
+    if values.0.len() != T::RAW_KINDS.len() {
+        return Err(shape(format!(
+            "give {} raw values, not {}",
+            T::RAW_KINDS.len(),
+            values.0.len()
+        )));
+    }
+    let kinds = values.0.iter().map(|v| v.kind()).collect::<Vec<_>>();
+    if kinds != T::RAW_KINDS {
+        return Err(shape(format!(
+            "give raw values of kinds {:?}, not {kinds:?}",
+            T::RAW_KINDS
+        )));
+    }
+    Ok(values)
+}
+
+/// Check that `value` converts to raw values that agree with the
+/// [`Lens::RAW_KINDS`] and [`Lens::NAMES`] of its lens, and converts back.
+///
+/// Columns of a lens whose raw values disagree cannot be made, see
+/// [`ColumnSchema::try_raw`](crate::ColumnSchema::try_raw).
+pub fn check_lens<T: Lens + Clone>(value: &T) -> Result<(), LensError> {
+    let values = raw_values(value.clone())?;
+    T::try_from(values).map(|_| ())
+}
+
+/// Panic unless the default of a lens agrees with its
+/// [`Lens::RAW_KINDS`] and [`Lens::NAMES`], see [`check_lens`], for the
+/// tests of a new lens.
+#[track_caller]
+pub fn assert_lens_consistent<T: Lens + Clone + Default>() {
+    if let Err(e) = check_lens(&T::default()) {
+        panic!("{e}");
+    }
+}
+
 impl Lens for u64 {
     const RAW_KINDS: &'static [RawKind] = &[RawKind::U64];
     const LENS_ID: LensId = LensId(*b"u64_____________");
@@ -131,6 +209,34 @@ impl TryFrom<RawValues> for u64 {
     }
 }
 
+impl Lens for u128 {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64];
+    const LENS_ID: LensId = LensId(*b"u128____________");
+    const EXPECTED: &'static str = "high: u64, low: u64";
+    const NAMES: &'static [&'static str] = &["high", "low"];
+}
+
+impl From<u128> for RawValues {
+    fn from(v: u128) -> Self {
+        RawValues(vec![
+            RawValue::U64((v >> 64) as u64),
+            RawValue::U64(v as u64),
+        ])
+    }
+}
+
+impl TryFrom<RawValues> for u128 {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            &[RawValue::U64(high), RawValue::U64(low)] => Ok((high as u128) << 64 | low as u128),
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
 impl Lens for std::time::SystemTime {
     const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64];
     const LENS_ID: LensId = LensId(*b"time::SystemTime");
@@ -140,7 +246,10 @@ impl Lens for std::time::SystemTime {
 
 impl From<std::time::SystemTime> for RawValues {
     fn from(t: std::time::SystemTime) -> Self {
-        let d = t.duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap();
+        // Times before the epoch cannot be stored, and are taken as the epoch.
+        let d = t
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
         RawValues(vec![
             RawValue::U64(d.as_secs()),
             RawValue::U64(d.subsec_nanos() as u64),
@@ -163,6 +272,494 @@ impl TryFrom<RawValues> for std::time::SystemTime {
     }
 }
 
+impl Lens for std::time::Duration {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64];
+    const LENS_ID: LensId = LensId(*b"time::Duration__");
+    const EXPECTED: &'static str = "seconds: u64, nanos: u64";
+    const NAMES: &'static [&'static str] = &["seconds", "subsecond_nanos"];
+}
+
+impl From<std::time::Duration> for RawValues {
+    fn from(d: std::time::Duration) -> Self {
+        RawValues(vec![
+            RawValue::U64(d.as_secs()),
+            RawValue::U64(d.subsec_nanos() as u64),
+        ])
+    }
+}
+
+impl TryFrom<RawValues> for std::time::Duration {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        use std::time::Duration;
+        match value.0.as_slice() {
+            &[RawValue::U64(secs), RawValue::U64(nanos)] => {
+                Ok(Duration::from_secs(secs) + Duration::from_nanos(nanos))
+            }
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+/// The place of a record in a sharded stream, such as a Kinesis stream: the
+/// shard it came from, and its sequence number, which increases with each
+/// record of that shard.
+///
+/// Sequence numbers of different shards cannot be compared, so how far a
+/// table has got through the stream is a vector clock of the latest sequence
+/// number of each shard, see
+/// [`TableSchema::set_sequence_column`](crate::TableSchema::set_sequence_column).
+/// It is written as `shard:sequence`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShardSequence {
+    /// The shard the record came from
+    pub shard: u64,
+    /// The sequence number of the record within its shard
+    pub sequence: u64,
+}
+
+impl Lens for ShardSequence {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64];
+    const LENS_ID: LensId = LensId(*b"ShardSequence___");
+    const EXPECTED: &'static str = "shard: u64, sequence: u64";
+    const NAMES: &'static [&'static str] = &["shard", "sequence"];
+}
+
+impl From<ShardSequence> for RawValues {
+    fn from(s: ShardSequence) -> Self {
+        RawValues(vec![RawValue::U64(s.shard), RawValue::U64(s.sequence)])
+    }
+}
+
+impl TryFrom<RawValues> for ShardSequence {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            &[RawValue::U64(shard), RawValue::U64(sequence)] => {
+                Ok(ShardSequence { shard, sequence })
+            }
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for ShardSequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.shard, self.sequence)
+    }
+}
+
+impl std::str::FromStr for ShardSequence {
+    type Err = LensError;
+    fn from_str(s: &str) -> Result<Self, LensError> {
+        let invalid = || LensError::InvalidValue {
+            value: format!("{s:?} is not shard:sequence"),
+        };
+        let (shard, sequence) = s.trim().split_once(':').ok_or_else(invalid)?;
+        Ok(ShardSequence {
+            shard: shard.parse().map_err(|_| invalid())?,
+            sequence: sequence.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// The sum and the count of some values, whose mean is their average.
+///
+/// A column of averages is aggregated by adding up the sums and the counts,
+/// see [`TableSchema::add_avg`](crate::TableSchema::add_avg), so each row
+/// saved holds a single value as [`Average::of`], or the sum and count of
+/// several.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Average {
+    /// The sum of the values
+    pub sum: u64,
+    /// The number of values
+    pub count: u64,
+}
+
+impl Average {
+    /// The average of a single value
+    pub fn of(value: u64) -> Self {
+        Average {
+            sum: value,
+            count: 1,
+        }
+    }
+
+    /// The mean of the values, unless there are none
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum as f64 / self.count as f64)
+        }
+    }
+}
+
+impl Lens for Average {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64];
+    const LENS_ID: LensId = LensId(*b"Average_________");
+    const EXPECTED: &'static str = "sum: u64, count: u64";
+    const NAMES: &'static [&'static str] = &["sum", "count"];
+}
+
+impl From<Average> for RawValues {
+    fn from(a: Average) -> Self {
+        RawValues(vec![RawValue::U64(a.sum), RawValue::U64(a.count)])
+    }
+}
+
+impl TryFrom<RawValues> for Average {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            &[RawValue::U64(sum), RawValue::U64(count)] => Ok(Average { sum, count }),
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+/// An exact decimal number with `SCALE` digits after the point, stored as a
+/// whole number of units of `10^-SCALE`, so `Decimal::<2>(-1234)` is
+/// `-12.34`.
+///
+/// The units are stored as a `u64` with the sign bit flipped, so decimals
+/// sort in order.  A decimal sums exactly, and a sum that overflows is
+/// refused unless another policy is given with
+/// [`TableSchema::add_sum_with_overflow`](crate::TableSchema::add_sum_with_overflow).
+/// The scale is part of the lens, so it is at most 19.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Decimal<const SCALE: u32>(pub i64);
+
+/// The sign bit, which is flipped in the raw value of a decimal
+const DECIMAL_SIGN: u64 = 1 << 63;
+
+/// The raw value of a decimal of `units`
+pub(crate) fn decimal_to_raw(units: i64) -> u64 {
+    units as u64 ^ DECIMAL_SIGN
+}
+
+/// The units of a decimal stored as `raw`
+pub(crate) fn decimal_from_raw(raw: u64) -> i64 {
+    (raw ^ DECIMAL_SIGN) as i64
+}
+
+/// The lens id of a decimal with this scale, such as `Decimal(02)`
+const fn decimal_lens_id(scale: u32) -> LensId {
+    assert!(scale <= 19, "the scale of a decimal is at most 19");
+    let mut id = *b"Decimal(__)_____";
+    id[8] = b'0' + (scale / 10) as u8;
+    id[9] = b'0' + (scale % 10) as u8;
+    LensId(id)
+}
+
+/// The scale of a decimal lens, if `lens` is one
+pub(crate) fn decimal_scale(lens: LensId) -> Option<u32> {
+    match lens.0 {
+        [b'D', b'e', b'c', b'i', b'm', b'a', b'l', b'(', tens, ones, b')', ..]
+            if tens.is_ascii_digit() && ones.is_ascii_digit() =>
+        {
+            let scale = ((tens - b'0') * 10 + (ones - b'0')) as u32;
+            // The rest of the id must match too.
+            Some(scale).filter(|&s| s <= 19 && decimal_lens_id(s) == lens)
+        }
+        _ => None,
+    }
+}
+
+/// Parse a decimal with `scale` digits after the point into its units,
+/// refusing any that would be rounded
+pub(crate) fn parse_decimal(s: &str, scale: u32) -> Result<u64, LensError> {
+    decimal_units(s, s.trim(), scale)
+}
+
+/// Parse a decimal that may be negative, see [`parse_decimal`]
+pub(crate) fn parse_signed_decimal(s: &str, scale: u32) -> Result<i64, LensError> {
+    let trimmed = s.trim();
+    let units = match trimmed.strip_prefix('-') {
+        Some(magnitude) => -(decimal_units(s, magnitude, scale)? as i128),
+        None => decimal_units(s, trimmed, scale)? as i128,
+    };
+    i64::try_from(units).map_err(|_| LensError::InvalidValue {
+        value: format!("{s:?} is too large"),
+    })
+}
+
+/// The units of the unsigned decimal `digits` within `s`
+fn decimal_units(s: &str, digits: &str, scale: u32) -> Result<u64, LensError> {
+    let invalid = |why: &str| LensError::InvalidValue {
+        value: format!("{s:?} {why}"),
+    };
+    let is_digits = |d: &str| !d.is_empty() && d.bytes().all(|b| b.is_ascii_digit());
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if !is_digits(whole) || !(fraction.is_empty() || is_digits(fraction)) {
+        return Err(invalid("is not a decimal"));
+    }
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > scale as usize {
+        return Err(invalid(&format!("has more than {scale} decimal places")));
+    }
+    let padded = format!("{whole}{fraction:0<width$}", width = scale as usize);
+    padded.parse().map_err(|_| invalid("is too large"))
+}
+
+/// Write `units` of `10^-scale` as a decimal
+pub(crate) fn format_decimal(units: u64, scale: u32) -> String {
+    let digits = format!("{units:0>width$}", width = scale as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale as usize);
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
+/// Write `units` of `10^-scale` as a decimal that may be negative
+pub(crate) fn format_signed_decimal(units: i64, scale: u32) -> String {
+    let magnitude = format_decimal(units.unsigned_abs(), scale);
+    if units < 0 {
+        format!("-{magnitude}")
+    } else {
+        magnitude
+    }
+}
+
+impl<const SCALE: u32> Lens for Decimal<SCALE> {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64];
+    const LENS_ID: LensId = decimal_lens_id(SCALE);
+    const EXPECTED: &'static str = "i64 units with the sign bit flipped";
+    const NAMES: &'static [&'static str] = &[""];
+}
+
+impl<const SCALE: u32> From<Decimal<SCALE>> for RawValues {
+    fn from(d: Decimal<SCALE>) -> Self {
+        RawValues(vec![RawValue::U64(decimal_to_raw(d.0))])
+    }
+}
+
+impl<const SCALE: u32> TryFrom<RawValues> for Decimal<SCALE> {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            &[RawValue::U64(v)] => Ok(Decimal(decimal_from_raw(v))),
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+impl<const SCALE: u32> std::fmt::Display for Decimal<SCALE> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format_signed_decimal(self.0, SCALE))
+    }
+}
+
+impl<const SCALE: u32> std::str::FromStr for Decimal<SCALE> {
+    type Err = LensError;
+    fn from_str(s: &str) -> Result<Self, LensError> {
+        parse_signed_decimal(s, SCALE).map(Decimal)
+    }
+}
+
+/// A point on the globe, in degrees of latitude and longitude.
+///
+/// A point is stored in three raw columns: `lat` and `lon`, each quantized
+/// to 32 bits, which is finer than a centimetre, and first `zorder`, which
+/// interleaves their bits.  Points that are near each other tend to have
+/// near Z-orders, so a table whose primary key leads with a point keeps
+/// nearby points in the same segments, and
+/// [`GeoPoint::bounding_box`] can skip segments by the minimum and maximum of
+/// their Z-orders.  It is written as `lat,lon`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct GeoPoint {
+    /// Degrees north of the equator, from -90 to 90
+    pub lat: f64,
+    /// Degrees east of the prime meridian, from -180 to 180
+    pub lon: f64,
+}
+
+/// Quantize `degrees` from `-range` to `range` into 32 bits
+fn quantize(degrees: f64, range: f64) -> u64 {
+    let fraction = (degrees.clamp(-range, range) + range) / (2.0 * range);
+    (fraction * u32::MAX as f64).round() as u64
+}
+
+fn dequantize(q: u64, range: f64) -> f64 {
+    q as f64 / u32::MAX as f64 * (2.0 * range) - range
+}
+
+/// Spread the low 32 bits of `v` out to the even bits of the result
+fn spread_bits(v: u64) -> u64 {
+    let mut v = v & 0xffff_ffff;
+    v = (v | v << 16) & 0x0000_ffff_0000_ffff;
+    v = (v | v << 8) & 0x00ff_00ff_00ff_00ff;
+    v = (v | v << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    v = (v | v << 2) & 0x3333_3333_3333_3333;
+    (v | v << 1) & 0x5555_5555_5555_5555
+}
+
+impl GeoPoint {
+    /// The quantized latitude and longitude, which is all that is stored
+    fn quantized(self) -> (u64, u64) {
+        (quantize(self.lat, 90.0), quantize(self.lon, 180.0))
+    }
+
+    /// The Z-order of the point, which interleaves the bits of its quantized
+    /// latitude and longitude
+    pub fn zorder(self) -> u64 {
+        let (lat, lon) = self.quantized();
+        spread_bits(lat) << 1 | spread_bits(lon)
+    }
+
+    /// The condition that the point in `column` lies within the box with
+    /// corners `a` and `b`, edges included.
+    ///
+    /// The Z-order of every point within the box lies between those of its
+    /// south-west and north-east corners, so segments whose Z-orders all lie
+    /// outside that range are skipped without decoding their points.  A box
+    /// cannot cross the antimeridian, so one that should must be split in
+    /// two.
+    pub fn bounding_box(column: &str, a: GeoPoint, b: GeoPoint) -> Expr {
+        let south_west = GeoPoint {
+            lat: a.lat.min(b.lat),
+            lon: a.lon.min(b.lon),
+        };
+        let north_east = GeoPoint {
+            lat: a.lat.max(b.lat),
+            lon: a.lon.max(b.lon),
+        };
+        let (south, west) = south_west.quantized();
+        let (north, east) = north_east.quantized();
+        let field = |name: &str| format!("{column}.{name}");
+        let between = |name: &str, low: u64, high: u64| {
+            Expr::Between(field(name), RawValue::U64(low), RawValue::U64(high))
+        };
+        between("zorder", south_west.zorder(), north_east.zorder())
+            .and(between("lat", south, north))
+            .and(between("lon", west, east))
+    }
+}
+
+impl Lens for GeoPoint {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64, RawKind::U64];
+    const LENS_ID: LensId = LensId(*b"GeoPoint________");
+    const EXPECTED: &'static str = "zorder: u64, lat: u32, lon: u32";
+    const NAMES: &'static [&'static str] = &["zorder", "lat", "lon"];
+}
+
+impl From<GeoPoint> for RawValues {
+    fn from(p: GeoPoint) -> Self {
+        let (lat, lon) = p.quantized();
+        RawValues(vec![
+            RawValue::U64(p.zorder()),
+            RawValue::U64(lat),
+            RawValue::U64(lon),
+        ])
+    }
+}
+
+impl TryFrom<RawValues> for GeoPoint {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match *value.0.as_slice() {
+            [RawValue::U64(_), RawValue::U64(lat), RawValue::U64(lon)]
+                if lat <= u32::MAX as u64 && lon <= u32::MAX as u64 =>
+            {
+                Ok(GeoPoint {
+                    lat: dequantize(lat, 90.0),
+                    lon: dequantize(lon, 180.0),
+                })
+            }
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for GeoPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.lat, self.lon)
+    }
+}
+
+impl std::str::FromStr for GeoPoint {
+    type Err = LensError;
+    fn from_str(s: &str) -> Result<Self, LensError> {
+        let invalid = || LensError::InvalidValue {
+            value: format!("{s:?} is not lat,lon"),
+        };
+        let (lat, lon) = s.split_once(',').ok_or_else(invalid)?;
+        let point = GeoPoint {
+            lat: lat.trim().parse().map_err(|_| invalid())?,
+            lon: lon.trim().parse().map_err(|_| invalid())?,
+        };
+        if (-90.0..=90.0).contains(&point.lat) && (-180.0..=180.0).contains(&point.lon) {
+            Ok(point)
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+/// A string interned in the dictionary of its table, so that a column of
+/// strings that repeat costs a `u64` per row.
+///
+/// A symbol is a hash of its string, so the same string gets the same symbol
+/// wherever it is interned, and rows holding symbols merge just as rows
+/// holding strings would.  Symbols are interned and resolved back to their
+/// strings through a [`TableHandle`](crate::TableHandle).
+///
+/// The default symbol is that of the empty string, which always resolves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(pub u64);
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Symbol::of("")
+    }
+}
+
+impl Symbol {
+    /// The symbol of `text`
+    pub fn of(text: &str) -> Self {
+        use crate::column::digest::{fnv, FNV_OFFSET};
+        Symbol(fnv(FNV_OFFSET, text.as_bytes()))
+    }
+}
+
+impl Lens for Symbol {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::U64];
+    const LENS_ID: LensId = LensId(*b"Symbol__________");
+    const EXPECTED: &'static str = "u64 symbol";
+    const NAMES: &'static [&'static str] = &[""];
+}
+
+impl From<Symbol> for RawValues {
+    fn from(s: Symbol) -> Self {
+        RawValues(vec![RawValue::U64(s.0)])
+    }
+}
+
+impl TryFrom<RawValues> for Symbol {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            &[RawValue::U64(v)] => Ok(Symbol(v)),
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
 impl Lens for String {
     const RAW_KINDS: &'static [RawKind] = &[RawKind::Bytes];
     const LENS_ID: LensId = LensId(*b"String__________");
@@ -216,3 +813,202 @@ impl TryFrom<RawValues> for bool {
         }
     }
 }
+
+impl Lens for Vec<u8> {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::Bytes];
+    const LENS_ID: LensId = LensId(*b"Vec<u8>_________");
+    const EXPECTED: &'static str = "bytes";
+    const NAMES: &'static [&'static str] = &[""];
+}
+
+impl From<Vec<u8>> for RawValues {
+    fn from(v: Vec<u8>) -> Self {
+        RawValues(vec![RawValue::Bytes(v)])
+    }
+}
+
+impl TryFrom<RawValues> for Vec<u8> {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            [RawValue::Bytes(b)] => Ok(b.clone()),
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+#[test]
+fn decimals() {
+    let cents = |s: &str| s.parse::<Decimal<2>>().map(|d| d.0);
+    assert_eq!(cents("12.34").unwrap(), 1234);
+    assert_eq!(cents(" 7 ").unwrap(), 700);
+    assert_eq!(cents("0.5").unwrap(), 50);
+    assert_eq!(cents("3.100").unwrap(), 310);
+    assert_eq!(cents("-1").unwrap(), -100);
+    assert_eq!(cents(" -0.05").unwrap(), -5);
+    assert_eq!(cents("-92233720368547758.08").unwrap(), i64::MIN);
+    assert!(cents("1.234").is_err());
+    assert!(cents("--1").is_err());
+    assert!(cents("- 1").is_err());
+    assert!(cents(".5").is_err());
+    assert!(cents("1e3").is_err());
+    assert!(cents("92233720368547758.08").is_err());
+    assert_eq!(Decimal::<2>(5).to_string(), "0.05");
+    assert_eq!(Decimal::<2>(-5).to_string(), "-0.05");
+    assert_eq!(Decimal::<2>(123456).to_string(), "1234.56");
+    assert_eq!(Decimal::<0>(42).to_string(), "42");
+    for d in [Decimal::<0>(42), Decimal(i64::MIN), Decimal(i64::MAX)] {
+        assert_eq!(d.to_string().parse::<Decimal<0>>().unwrap(), d);
+    }
+    // Negative decimals sort first.
+    let raw = |units| RawValues::from(Decimal::<2>(units)).0;
+    assert!(raw(i64::MIN) < raw(-1) && raw(-1) < raw(0) && raw(0) < raw(i64::MAX));
+
+    assert_eq!(decimal_scale(Decimal::<2>::LENS_ID), Some(2));
+    assert_eq!(decimal_scale(Decimal::<19>::LENS_ID), Some(19));
+    assert_eq!(Decimal::<19>::LENS_ID.to_string(), "Decimal(19)");
+    assert_eq!(decimal_scale(u64::LENS_ID), None);
+
+    // Decimals load from text and sum exactly, refusing to overflow even
+    // when no overflow policy is given.
+    use crate::{ColumnSchema, CsvLoader, Table, TableBuilder, TableSchema};
+    let mut schema = TableSchema::new("orders");
+    schema.add_primary(ColumnSchema::<String>::new("customer").raw());
+    schema.add_sum(ColumnSchema::<Decimal<2>>::new("total").raw());
+    let dir = tempfile::tempdir().unwrap();
+    let load = |csv: &str| {
+        let mut builder = TableBuilder::new(&schema);
+        CsvLoader::new(&schema).load(csv.as_bytes(), &mut builder)?;
+        builder.save(dir.path())?;
+        Table::read(dir.path(), &schema)?.to_rows()
+    };
+    let rows = load("customer,total\nann,0.10\nann,-0.30\nbob,3\n").unwrap();
+    let totals = rows
+        .iter()
+        .map(|r| Decimal::<2>::try_from(RawValues(r.values[1..].to_vec())).unwrap())
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(totals, ["-0.20", "3.00"]);
+    assert!(load("customer,total\nann,0.001\n").is_err());
+    assert!(matches!(
+        load("customer,total\nbob,92233720368547756\n"),
+        Err(crate::column::encoding::StorageError::Overflow(_))
+    ));
+    assert!(matches!(
+        load("customer,total\nann,-92233720368547758\n"),
+        Err(crate::column::encoding::StorageError::Overflow(_))
+    ));
+}
+
+#[test]
+fn geo_points() {
+    use crate::{ColumnSchema, Table, TableBuilder, TableSchema};
+    use rand::{Rng, SeedableRng};
+
+    let boston: GeoPoint = "42.36, -71.06".parse().unwrap();
+    let stored = GeoPoint::try_from(RawValues::from(boston)).unwrap();
+    assert!((stored.lat - boston.lat).abs() < 1e-7 && (stored.lon - boston.lon).abs() < 1e-7);
+    assert!("91,0".parse::<GeoPoint>().is_err());
+    assert!("42.36".parse::<GeoPoint>().is_err());
+    let corner = |lat, lon| GeoPoint { lat, lon };
+    assert_eq!(corner(-90.0, -180.0).zorder(), 0);
+    assert_eq!(corner(90.0, 180.0).zorder(), u64::MAX);
+
+    let mut schema = TableSchema::new("sightings");
+    let location = ColumnSchema::<GeoPoint>::new("location");
+    schema.add_primary(location.raw());
+    schema.add_sum(ColumnSchema::<u64>::new("count").raw());
+    let dir = tempfile::tempdir().unwrap();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut points = Vec::new();
+    for _ in 0..4 {
+        let mut builder = TableBuilder::new(&schema);
+        for _ in 0..200 {
+            let p = corner(rng.gen_range(-90.0..90.0), rng.gen_range(-180.0..180.0));
+            let mut values = RawValues::from(p).0;
+            values.push(RawValue::U64(1));
+            builder
+                .insert_raw_row(values.into_iter().collect())
+                .unwrap();
+            points.push(GeoPoint::try_from(RawValues::from(p)).unwrap());
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let table = Table::read(dir.path(), &schema).unwrap();
+    for (a, b) in [
+        (corner(40.0, -75.0), corner(45.0, -70.0)),
+        (corner(10.0, 50.0), corner(-30.0, -20.0)),
+        (corner(-90.0, -180.0), corner(90.0, 180.0)),
+    ] {
+        let selected = table
+            .select(&GeoPoint::bounding_box("location", a, b))
+            .unwrap()
+            .into_iter()
+            .map(|r| GeoPoint::try_from(RawValues(r.values[..3].to_vec())).unwrap())
+            .collect::<Vec<_>>();
+        let (south, north) = (a.lat.min(b.lat), a.lat.max(b.lat));
+        let (west, east) = (a.lon.min(b.lon), a.lon.max(b.lon));
+        let mut expected = points
+            .iter()
+            .filter(|p| (south..=north).contains(&p.lat) && (west..=east).contains(&p.lon))
+            .copied()
+            .collect::<Vec<_>>();
+        expected.sort_by_key(|p| p.zorder());
+        assert_eq!(selected, expected);
+    }
+}
+
+#[test]
+fn consistent_lenses() {
+    use crate::ColumnSchema;
+    use std::time::{Duration, SystemTime};
+
+    assert_lens_consistent::<u64>();
+    assert_lens_consistent::<u128>();
+    assert_lens_consistent::<bool>();
+    assert_lens_consistent::<String>();
+    assert_lens_consistent::<Vec<u8>>();
+    assert_lens_consistent::<Duration>();
+    assert_lens_consistent::<ShardSequence>();
+    assert_lens_consistent::<Average>();
+    assert_lens_consistent::<Decimal<2>>();
+    assert_lens_consistent::<GeoPoint>();
+    assert_lens_consistent::<Symbol>();
+    assert_lens_consistent::<QuantileSketch>();
+    check_lens(&SystemTime::UNIX_EPOCH).unwrap();
+    check_lens(&ColumnId::new()).unwrap();
+    #[cfg(feature = "chrono")]
+    assert_lens_consistent::<chrono::NaiveDate>();
+    #[cfg(feature = "serde_json")]
+    assert_lens_consistent::<serde_json::Value>();
+
+    /// A lens naming a field it does not store
+    #[derive(Debug, Default, Clone)]
+    struct Broken;
+    impl Lens for Broken {
+        const RAW_KINDS: &'static [RawKind] = &[RawKind::U64, RawKind::U64];
+        const LENS_ID: LensId = LensId(*b"Broken__________");
+        const EXPECTED: &'static str = "a: u64, b: u64";
+        const NAMES: &'static [&'static str] = &["a", "b"];
+    }
+    impl From<Broken> for RawValues {
+        fn from(_: Broken) -> Self {
+            RawValues(vec![RawValue::U64(0)])
+        }
+    }
+    impl TryFrom<RawValues> for Broken {
+        type Error = LensError;
+        fn try_from(_: RawValues) -> Result<Self, LensError> {
+            Ok(Broken)
+        }
+    }
+    let e = check_lens(&Broken).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "invalid kinds, expected lens Broken to give 2 raw values, not 1"
+    );
+    assert!(ColumnSchema::<Broken>::new("broken").try_raw().is_err());
+    assert!(std::panic::catch_unwind(assert_lens_consistent::<Broken>).is_err());
+}
@@ -0,0 +1,88 @@
+//! Lenses for the dates and times of the `chrono` crate, enabled by its
+//! feature.
+//!
+//! They are stored just as a [`SystemTime`] is, as seconds and nanoseconds
+//! since the Unix epoch, and a `DateTime<Utc>` even shares its lens, so a
+//! column of either can be the time column of a table.
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+
+use super::{Lens, LensError, LensId, RawValues};
+use crate::value::{RawKind, RawValue};
+
+impl Lens for DateTime<Utc> {
+    const RAW_KINDS: &'static [RawKind] = SystemTime::RAW_KINDS;
+    const LENS_ID: LensId = SystemTime::LENS_ID;
+    const EXPECTED: &'static str = SystemTime::EXPECTED;
+    const NAMES: &'static [&'static str] = SystemTime::NAMES;
+}
+
+/// # Panics
+///
+/// If the time is before the Unix epoch, just as for a [`SystemTime`].
+impl From<DateTime<Utc>> for RawValues {
+    fn from(t: DateTime<Utc>) -> Self {
+        SystemTime::from(t).into()
+    }
+}
+
+impl TryFrom<RawValues> for DateTime<Utc> {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        SystemTime::try_from(value).map(DateTime::from)
+    }
+}
+
+impl Lens for NaiveDate {
+    const RAW_KINDS: &'static [RawKind] = SystemTime::RAW_KINDS;
+    const LENS_ID: LensId = LensId(*b"chrono::NaiveDat");
+    const EXPECTED: &'static str = "seconds: u64, nanos: u64 of midnight UTC";
+    const NAMES: &'static [&'static str] = SystemTime::NAMES;
+}
+
+/// # Panics
+///
+/// If the date is before the Unix epoch.
+impl From<NaiveDate> for RawValues {
+    fn from(d: NaiveDate) -> Self {
+        d.and_time(NaiveTime::MIN).and_utc().into()
+    }
+}
+
+impl TryFrom<RawValues> for NaiveDate {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        let invalid = |secs| LensError::InvalidValue {
+            value: format!("{secs} seconds is not midnight UTC of a date"),
+        };
+        match *value.0.as_slice() {
+            [RawValue::U64(secs), RawValue::U64(0)] if secs % 86400 == 0 => {
+                DateTime::from_timestamp(secs as i64, 0)
+                    .map(|t| t.date_naive())
+                    .ok_or_else(|| invalid(secs))
+            }
+            [RawValue::U64(secs), RawValue::U64(_)] => Err(invalid(secs)),
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+#[test]
+fn chrono_lenses() {
+    use chrono::TimeZone;
+
+    let time = Utc.with_ymd_and_hms(2023, 1, 31, 12, 0, 5).unwrap();
+    let raw = RawValues::from(time);
+    assert_eq!(raw.0, [RawValue::U64(1_675_166_405), RawValue::U64(0)]);
+    assert_eq!(DateTime::<Utc>::try_from(raw).unwrap(), time);
+
+    let date = NaiveDate::from_ymd_opt(2023, 1, 31).unwrap();
+    let raw = RawValues::from(date);
+    assert_eq!(raw.0, [RawValue::U64(1_675_123_200), RawValue::U64(0)]);
+    assert_eq!(NaiveDate::try_from(raw).unwrap(), date);
+    assert!(NaiveDate::try_from(RawValues::from(time)).is_err());
+}
@@ -0,0 +1,68 @@
+//! A lens for the JSON values of the `serde_json` crate, enabled by its
+//! feature.
+//!
+//! A value is stored as its compact JSON text, so a column of them can hold
+//! semi-structured payloads next to typed columns, and SQL can pick values
+//! out of it with `->`.
+
+use serde_json::Value;
+
+use super::{Lens, LensError, LensId, RawValues};
+use crate::value::{RawKind, RawValue};
+
+impl Lens for Value {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::Bytes];
+    const LENS_ID: LensId = LensId(*b"Json____________");
+    const EXPECTED: &'static str = "JSON text";
+    const NAMES: &'static [&'static str] = &[""];
+}
+
+impl From<Value> for RawValues {
+    fn from(v: Value) -> Self {
+        RawValues(vec![RawValue::Bytes(v.to_string().into_bytes())])
+    }
+}
+
+impl TryFrom<RawValues> for Value {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            [RawValue::Bytes(b)] => {
+                serde_json::from_slice(b).map_err(|e| LensError::InvalidValue {
+                    value: e.to_string(),
+                })
+            }
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+#[test]
+fn json_lens() {
+    use crate::{ColumnSchema, Database, TableSchema};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("events");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(ColumnSchema::<Value>::new("payload").raw());
+    let events = db.create_table(schema).unwrap();
+    let payload = serde_json::json!({"user": {"name": "David", "tags": ["a", "b"]}});
+    events
+        .insert_raw_rows([[RawValue::U64(1)]
+            .into_iter()
+            .chain(RawValues::from(payload.clone()).0)
+            .collect()])
+        .unwrap();
+    let rows = events.read().unwrap().to_rows().unwrap();
+    let stored = Value::try_from(RawValues(rows[0].values[1..].to_vec())).unwrap();
+    assert_eq!(stored, payload);
+
+    let result = db
+        .execute("select payload->'user'->'tags'->1 from events")
+        .unwrap();
+    assert_eq!(result.rows(), [vec![RawValue::Bytes(b"\"b\"".to_vec())]]);
+    assert!(Value::try_from(RawValues(vec![RawValue::Bytes(b"{".to_vec())])).is_err());
+}
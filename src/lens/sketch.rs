@@ -0,0 +1,213 @@
+//! A sketch of the distribution of `u64` values, from which quantiles such
+//! as the median or the 99th percentile can be estimated.
+//!
+//! The sketch is a DDSketch: each value is counted in a bucket of values
+//! within [`QuantileSketch::RELATIVE_ACCURACY`] of each other, so two
+//! sketches merge exactly by adding up their buckets, in any order, and a
+//! column of them can be aggregated at compaction, see
+//! [`TableSchema::add_sketch`](crate::TableSchema::add_sketch).
+//!
+//! A sketch is stored as bytes: the count of zeros, followed by the index of
+//! each bucket that is not empty, as a difference from the one before, and
+//! its count, all as LEB128 numbers.  An empty sketch is no bytes at all.
+
+use std::collections::BTreeMap;
+
+use super::{Lens, LensError, LensId, RawValues};
+use crate::value::{RawKind, RawValue};
+
+/// A mergeable sketch of a distribution of `u64` values, see the
+/// [module](self) docs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct QuantileSketch {
+    zeros: u64,
+    buckets: BTreeMap<u32, u64>,
+}
+
+impl QuantileSketch {
+    /// The largest error of an estimated quantile, relative to the value
+    pub const RELATIVE_ACCURACY: f64 = 0.01;
+
+    /// The ratio of the largest to the smallest value of a bucket
+    fn gamma() -> f64 {
+        (1.0 + Self::RELATIVE_ACCURACY) / (1.0 - Self::RELATIVE_ACCURACY)
+    }
+
+    /// A sketch of a single value
+    pub fn of(value: u64) -> Self {
+        let mut sketch = QuantileSketch::default();
+        sketch.insert(value);
+        sketch
+    }
+
+    /// Count one more value
+    pub fn insert(&mut self, value: u64) {
+        if value == 0 {
+            self.zeros = self.zeros.saturating_add(1);
+        } else {
+            let index = ((value as f64).ln() / Self::gamma().ln()).ceil() as u32;
+            let count = self.buckets.entry(index).or_default();
+            *count = count.saturating_add(1);
+        }
+    }
+
+    /// Add the values counted by `other`
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        self.zeros = self.zeros.saturating_add(other.zeros);
+        for (&index, &count) in other.buckets.iter() {
+            let old = self.buckets.entry(index).or_default();
+            *old = old.saturating_add(count);
+        }
+    }
+
+    /// The number of values counted
+    pub fn count(&self) -> u64 {
+        self.buckets
+            .values()
+            .fold(self.zeros, |total, &c| total.saturating_add(c))
+    }
+
+    /// An estimate of the `q` quantile of the values, where `q` is between
+    /// zero and one, unless there are none
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (count - 1) as f64) as u64;
+        if rank < self.zeros {
+            return Some(0.0);
+        }
+        let mut seen = self.zeros;
+        let gamma = Self::gamma();
+        for (&index, &c) in self.buckets.iter() {
+            seen = seen.saturating_add(c);
+            if rank < seen {
+                return Some(2.0 * gamma.powi(index as i32) / (gamma + 1.0));
+            }
+        }
+        None
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if self.count() == 0 {
+            return bytes;
+        }
+        write_leb128(&mut bytes, self.zeros);
+        let mut previous = 0;
+        for (&index, &count) in self.buckets.iter() {
+            write_leb128(&mut bytes, (index - previous) as u64);
+            write_leb128(&mut bytes, count);
+            previous = index;
+        }
+        bytes
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut sketch = QuantileSketch::default();
+        if bytes.is_empty() {
+            return Some(sketch);
+        }
+        sketch.zeros = read_leb128(&mut bytes)?;
+        let mut index = 0u32;
+        while !bytes.is_empty() {
+            index = index.checked_add(u32::try_from(read_leb128(&mut bytes)?).ok()?)?;
+            sketch.buckets.insert(index, read_leb128(&mut bytes)?);
+        }
+        Some(sketch)
+    }
+
+    /// Merge two sketches stored as bytes, unless either is not a sketch
+    pub(crate) fn merge_bytes(a: &[u8], b: &[u8]) -> Option<Vec<u8>> {
+        let mut sketch = Self::from_bytes(a)?;
+        sketch.merge(&Self::from_bytes(b)?);
+        Some(sketch.to_bytes())
+    }
+}
+
+fn write_leb128(bytes: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        bytes.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    bytes.push(v as u8);
+}
+
+fn read_leb128(bytes: &mut &[u8]) -> Option<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = bytes.split_first()?;
+        *bytes = rest;
+        v |= ((b & 0x7f) as u64) << shift;
+        if b < 0x80 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+impl Lens for QuantileSketch {
+    const RAW_KINDS: &'static [RawKind] = &[RawKind::Bytes];
+    const LENS_ID: LensId = LensId(*b"QuantileSketch__");
+    const EXPECTED: &'static str = "a quantile sketch";
+    const NAMES: &'static [&'static str] = &[""];
+}
+
+impl From<QuantileSketch> for RawValues {
+    fn from(s: QuantileSketch) -> Self {
+        RawValues(vec![RawValue::Bytes(s.to_bytes())])
+    }
+}
+
+impl TryFrom<RawValues> for QuantileSketch {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, Self::Error> {
+        match value.0.as_slice() {
+            [RawValue::Bytes(b)] => {
+                QuantileSketch::from_bytes(b).ok_or_else(|| LensError::InvalidValue {
+                    value: format!("{b:?}"),
+                })
+            }
+            _ => Err(LensError::InvalidKinds {
+                expected: Self::EXPECTED.to_string(),
+            }),
+        }
+    }
+}
+
+#[test]
+fn sketch() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let mut values = (0..10_000)
+        .map(|_| rng.gen_range(0..1_000_000u64))
+        .collect::<Vec<_>>();
+    let (mut a, mut b) = (QuantileSketch::default(), QuantileSketch::default());
+    for (i, &v) in values.iter().enumerate() {
+        if i % 3 == 0 {
+            a.insert(v);
+        } else {
+            b.insert(v);
+        }
+    }
+    a.merge(&b);
+    values.sort_unstable();
+    assert_eq!(a.count(), values.len() as u64);
+    for q in [0.0, 0.5, 0.9, 0.99, 1.0] {
+        let exact = values[(q * (values.len() - 1) as f64) as usize] as f64;
+        let estimate = a.quantile(q).unwrap();
+        assert!(
+            (estimate - exact).abs() <= exact * QuantileSketch::RELATIVE_ACCURACY,
+            "{q}: {estimate} is not {exact}"
+        );
+    }
+
+    let raw: RawValues = a.clone().into();
+    assert_eq!(QuantileSketch::try_from(raw).unwrap(), a);
+    assert_eq!(QuantileSketch::of(0).quantile(0.5), Some(0.0));
+    assert_eq!(QuantileSketch::default().quantile(0.5), None);
+    assert_eq!(QuantileSketch::default().to_bytes(), b"");
+    assert!(QuantileSketch::from_bytes(&[0x80]).is_none());
+}
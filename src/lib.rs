@@ -2,17 +2,52 @@
 //! A nice columnar data store.
 
 pub mod column;
+mod database;
+mod expr;
+mod fs;
+mod join;
+mod layout;
 mod lens;
+mod load;
 mod parser;
+mod query;
 mod schema;
+mod table;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 mod value;
 
-pub use column::RawColumn;
-pub use lens::{Lens, LensError};
+pub use column::digest::ColumnDigest;
+pub use column::{
+    migrate_column, register_format, ColumnFormat, CustomFormat, EncodeOptions, FormatVersion,
+    HttpOptions, Metrics, RawColumn,
+};
+pub use database::{
+    load_db_schema, save_db_schema, verify_db, Alteration, BackupManifest, Catalog, Change,
+    DanglingReference, Database, IngestOptions, Ingestor, IsRow, NamedRow, TableHandle, TypedTable,
+};
+pub use expr::{Comparison, Expr, Selection};
+pub use join::join;
+pub use layout::{DbLayout, FlatLayout, NestedLayout};
+pub use lens::{
+    assert_lens_consistent, check_lens, Average, ColumnId, Decimal, GeoPoint, Lens, LensError,
+    QuantileSketch, ShardSequence, Symbol,
+};
+pub use load::{CsvLoader, JsonLoader};
+pub use query::{write_arrow_ipc, QueryResult};
 pub use schema::{
-    db_schema_schema, table_schema_schema, ColumnSchema, RawColumnSchema, TableSchema,
+    changelog_schema, counter_schema, databases_schema, db_schema_schema, purge_schema,
+    scrub_schema, symbol_schema, table_schema_schema, watermark_schema, Aggregation, Collation,
+    ColumnSchema, ComputedDefault, ConflictPolicy, Constraint, Encoding, RawColumnSchema,
+    SumOverflow, TableSchema, INGESTED_AT_NAME,
+};
+pub use table::{
+    AsOf, BoolBuilder, BytesBuilder, ColumnBuilder, Corruption, DisplayRows, Inconsistency,
+    InconsistencyKind, Purge, RowId, SalvageReport, SalvagedSegment, ScrubReport, SegmentColumn,
+    SegmentFiles, Table, TableBuilder, TableFiles, TableStats, TypedTableBuilder, U64Builder,
+    VerifyReport,
 };
-use value::RawValue;
+pub use value::{RawKind, RawValue};
 
 /// A "raw" row, as it will be sorted and stored.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -20,6 +55,13 @@ pub struct RawRow {
     values: Vec<RawValue>,
 }
 
+impl RawRow {
+    /// The values in this row, in schema order
+    pub fn values(&self) -> &[RawValue] {
+        &self.values
+    }
+}
+
 impl FromIterator<RawValue> for RawRow {
     fn from_iter<T: IntoIterator<Item = RawValue>>(iter: T) -> Self {
         RawRow {
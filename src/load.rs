@@ -0,0 +1,257 @@
+//! Loading rows from text files.
+//!
+//! Each field of a file loads into one logical column of a table, which is
+//! parsed through its lens, so a `SystemTime` column is filled from a single
+//! timestamp, a `Duration` column from a number of seconds, a `ShardSequence`
+//! column from `shard:sequence`, and a `GeoPoint` column from `lat,lon`.  A
+//! `Decimal` column is filled from a number with no more decimal places than
+//! its scale, and a `Symbol` column by interning the field in the builder,
+//! see [`TableBuilder::intern`].  With the `chrono` feature, a `NaiveDate`
+//! column is filled from a date such as `2023-01-31`.  Columns of the table
+//! that a file does not mention hold their defaults.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::lens::{
+    decimal_scale, decimal_to_raw, parse_decimal, parse_signed_decimal, GeoPoint, Lens, LensError,
+    LensId, ShardSequence, Symbol,
+};
+use crate::{RawKind, RawRow, RawValue, TableBuilder, TableSchema};
+
+mod csv;
+mod json;
+
+pub use csv::CsvLoader;
+pub(crate) use json::json_value;
+pub use json::JsonLoader;
+
+/// Which logical column of a table each named field of a file loads into
+#[derive(Debug, Clone)]
+struct Mapping {
+    schema: TableSchema,
+    /// The column each field loads into, where it is not the column of the
+    /// same name
+    renamed: BTreeMap<String, String>,
+}
+
+impl Mapping {
+    fn new(schema: &TableSchema) -> Self {
+        Mapping {
+            schema: schema.clone(),
+            renamed: BTreeMap::new(),
+        }
+    }
+
+    fn rename(&mut self, field: &str, column: &str) {
+        self.renamed.insert(field.to_string(), column.to_string());
+    }
+
+    /// A row holding the default of every column
+    fn defaults(&self) -> RawRow {
+        self.schema
+            .raw_columns()
+            .map(|c| c.default().clone())
+            .collect()
+    }
+
+    /// The logical column that `field` loads into
+    fn column(&self, field: &str) -> Result<LogicalColumn, String> {
+        let name = self.renamed.get(field).map(|s| s.as_str()).unwrap_or(field);
+        let mut lens = None;
+        let indices = self
+            .schema
+            .raw_columns()
+            .enumerate()
+            .filter(|(_, c)| c.name() == name)
+            .map(|(i, c)| {
+                lens = Some(c.lens());
+                i
+            })
+            .collect::<Vec<_>>();
+        match lens {
+            Some(lens) => Ok(LogicalColumn {
+                name: name.to_string(),
+                lens,
+                indices,
+            }),
+            None if name == field => Err(format!("no column {name}")),
+            None => Err(format!("no column {name} for {field}")),
+        }
+    }
+}
+
+/// The raw columns of one logical column, in the order they are stored in a
+/// row
+#[derive(Debug, Clone)]
+struct LogicalColumn {
+    name: String,
+    lens: LensId,
+    indices: Vec<usize>,
+}
+
+impl LogicalColumn {
+    /// Set the raw values of this column in `row` by parsing `field`,
+    /// interning it in `builder` if this is a column of symbols
+    fn set(&self, row: &mut RawRow, field: &str, builder: &mut TableBuilder) -> Result<(), String> {
+        if self.lens == Symbol::LENS_ID {
+            let symbol = builder.intern(field);
+            for (&i, v) in self
+                .indices
+                .iter()
+                .zip(crate::lens::RawValues::from(symbol).0)
+            {
+                row.values[i] = v;
+            }
+            return Ok(());
+        }
+        let kinds = self
+            .indices
+            .iter()
+            .map(|&i| row.values[i].kind())
+            .collect::<Vec<_>>();
+        let values = parse_field(self.lens, &kinds, field)
+            .map_err(|e| format!("column {}: {e}", self.name))?;
+        for (&i, v) in self.indices.iter().zip(values) {
+            row.values[i] = v;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a field into the raw values of a logical column with this lens
+pub(crate) fn parse_field(
+    lens: LensId,
+    kinds: &[RawKind],
+    field: &str,
+) -> Result<Vec<RawValue>, String> {
+    if lens == SystemTime::LENS_ID {
+        return Ok(crate::lens::RawValues::from(parse_time(field)?).0);
+    }
+    if lens == ShardSequence::LENS_ID {
+        let s: ShardSequence = field.parse().map_err(|e: LensError| e.to_string())?;
+        return Ok(crate::lens::RawValues::from(s).0);
+    }
+    #[cfg(feature = "chrono")]
+    if lens == chrono::NaiveDate::LENS_ID {
+        let date: chrono::NaiveDate = field
+            .trim()
+            .parse()
+            .map_err(|_| format!("{field:?} is not a date"))?;
+        return Ok(crate::lens::RawValues::from(date).0);
+    }
+    if lens == GeoPoint::LENS_ID {
+        let p: GeoPoint = field.parse().map_err(|e: LensError| e.to_string())?;
+        return Ok(crate::lens::RawValues::from(p).0);
+    }
+    if lens == Duration::LENS_ID {
+        let nanos = parse_decimal(field, 9).map_err(|e| e.to_string())?;
+        return Ok(crate::lens::RawValues::from(Duration::from_nanos(nanos)).0);
+    }
+    if let Some(scale) = decimal_scale(lens) {
+        let units = parse_signed_decimal(field, scale).map_err(|e| e.to_string())?;
+        return Ok(vec![RawValue::U64(decimal_to_raw(units))]);
+    }
+    if lens == u128::LENS_ID {
+        let v: u128 = field
+            .trim()
+            .parse()
+            .map_err(|_| format!("{field:?} is not a number"))?;
+        return Ok(crate::lens::RawValues::from(v).0);
+    }
+    match kinds {
+        [RawKind::U64] => field
+            .trim()
+            .parse()
+            .map(|v| vec![RawValue::U64(v)])
+            .map_err(|_| format!("{field:?} is not a number")),
+        [RawKind::Bool] => match field.trim().to_ascii_lowercase().as_str() {
+            "true" | "t" | "yes" | "1" => Ok(vec![RawValue::Bool(true)]),
+            "false" | "f" | "no" | "0" => Ok(vec![RawValue::Bool(false)]),
+            _ => Err(format!("{field:?} is not true or false")),
+        },
+        [RawKind::Bytes] => Ok(vec![RawValue::Bytes(field.as_bytes().to_vec())]),
+        _ => Err(format!("cannot load lens {lens} from text")),
+    }
+}
+
+/// Parse a time, either as seconds since the epoch or as an RFC 3339
+/// timestamp such as `2023-01-31T12:00:00.5Z`.
+fn parse_time(field: &str) -> Result<SystemTime, String> {
+    let field = field.trim();
+    let invalid = || format!("{field:?} is not a time");
+    let digits = |s: &str| -> Result<u64, String> {
+        if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse().map_err(|_| invalid())
+        } else {
+            Err(invalid())
+        }
+    };
+    // Fractions of a second are given to at most nanosecond precision.
+    let nanos = |s: &str| -> Result<u64, String> {
+        if s.len() > 9 {
+            return Err(invalid());
+        }
+        Ok(digits(s)? * 10u64.pow(9 - s.len() as u32))
+    };
+    let split_fraction = |s: &'_ str| -> Result<(u64, u64), String> {
+        match s.split_once('.') {
+            Some((whole, fraction)) => Ok((digits(whole)?, nanos(fraction)?)),
+            None => Ok((digits(s)?, 0)),
+        }
+    };
+
+    let (seconds, nanos) = if let Ok((seconds, nanos)) = split_fraction(field) {
+        (i64::try_from(seconds).map_err(|_| invalid())?, nanos)
+    } else {
+        // YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)
+        let (date, time) = field.split_once(['T', 't', ' ']).ok_or_else(invalid)?;
+        let [year, month, day]: [&str; 3] = date
+            .splitn(3, '-')
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| invalid())?;
+        let (year, month, day) = (digits(year)?, digits(month)?, digits(day)?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(invalid());
+        }
+        let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+            (time, 0)
+        } else {
+            let at = time.rfind(['+', '-']).ok_or_else(invalid)?;
+            let (hours, minutes) = time[at + 1..].split_once(':').ok_or_else(invalid)?;
+            let offset = (digits(hours)? * 60 + digits(minutes)?) as i64 * 60;
+            let sign = if time.as_bytes()[at] == b'-' { -1 } else { 1 };
+            (&time[..at], sign * offset)
+        };
+        let [hour, minute, second]: [&str; 3] = time
+            .splitn(3, ':')
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| invalid())?;
+        let (hour, minute) = (digits(hour)?, digits(minute)?);
+        let (second, nanos) = split_fraction(second)?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return Err(invalid());
+        }
+        let days = days_from_civil(year as i64, month as i64, day as i64);
+        let seconds = days * 86400 + (hour * 3600 + minute * 60 + second) as i64 - offset;
+        (seconds, nanos)
+    };
+    match u64::try_from(seconds) {
+        Ok(s) => Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(s) + Duration::from_nanos(nanos)),
+        Err(_) => Err(format!("{field:?} is before 1970")),
+    }
+}
+
+/// The number of days from 1970-01-01 to a date in the proleptic Gregorian
+/// calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
@@ -0,0 +1,243 @@
+//! Loading rows from CSV files.
+//!
+//! The first line of a file names its columns, and each later record becomes
+//! a row.
+
+use std::io::BufRead;
+
+use super::Mapping;
+use crate::column::encoding::StorageError;
+use crate::{TableBuilder, TableSchema};
+
+/// Loads CSV files into a table.
+#[derive(Debug, Clone)]
+pub struct CsvLoader {
+    mapping: Mapping,
+}
+
+impl CsvLoader {
+    /// Load CSV files into tables with this schema.
+    ///
+    /// Each CSV column loads into the column of the same name, unless it is
+    /// mapped elsewhere with [`CsvLoader::map`].
+    pub fn new(schema: &TableSchema) -> Self {
+        CsvLoader {
+            mapping: Mapping::new(schema),
+        }
+    }
+
+    /// Load the CSV column named `header` into the logical column `column`
+    pub fn map(mut self, header: &str, column: &str) -> Self {
+        self.mapping.rename(header, column);
+        self
+    }
+
+    /// Read a CSV file, adding a row to `builder` for each record after the
+    /// header, and returning the number of rows added.
+    ///
+    /// The file is read a record at a time.  A record that cannot be parsed
+    /// fails with [`StorageError::Csv`], giving the number of its row, where
+    /// the first row after the header is row 1.
+    pub fn load<R: BufRead>(
+        &self,
+        reader: R,
+        builder: &mut TableBuilder,
+    ) -> Result<u64, StorageError> {
+        let mut records = Records { reader, line: 0 };
+        let error = |row, message: String| StorageError::Csv { row, message };
+        let header = records
+            .next_record()
+            .map_err(|e| error(0, e))?
+            .ok_or_else(|| error(0, "no header".to_string()))?;
+        let columns = header
+            .iter()
+            .map(|h| self.mapping.column(h))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| error(0, e))?;
+        let defaults = self.mapping.defaults();
+        let mut rows = 0;
+        while let Some(record) = records.next_record().map_err(|e| error(rows + 1, e))? {
+            rows += 1;
+            if record.len() != columns.len() {
+                return Err(error(
+                    rows,
+                    format!("{} fields for {} columns", record.len(), columns.len()),
+                ));
+            }
+            let mut row = defaults.clone();
+            for (c, field) in columns.iter().zip(record) {
+                c.set(&mut row, &field, builder)
+                    .map_err(|e| error(rows, e))?;
+            }
+            builder.insert_raw_row(row)?;
+        }
+        Ok(rows)
+    }
+}
+
+/// The records of a CSV file, read a line at a time
+struct Records<R> {
+    reader: R,
+    line: u64,
+}
+
+impl<R: BufRead> Records<R> {
+    /// The fields of the next record, or `None` at the end of the file.
+    ///
+    /// Fields may be quoted with `"`, in which case they may hold commas,
+    /// newlines and `""` for a quote.  Blank lines are skipped.
+    fn next_record(&mut self) -> Result<Option<Vec<String>>, String> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        loop {
+            let mut line = String::new();
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|e| format!("line {}: {e}", self.line + 1))?;
+            if read == 0 {
+                if in_quotes {
+                    return Err("unterminated quoted field".to_string());
+                }
+                if fields.is_empty() && field.is_empty() && !quoted {
+                    return Ok(None);
+                }
+                fields.push(field);
+                return Ok(Some(fields));
+            }
+            self.line += 1;
+            if !in_quotes && fields.is_empty() && field.is_empty() && line.trim().is_empty() {
+                continue;
+            }
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if in_quotes && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' if in_quotes => in_quotes = false,
+                    '"' if field.is_empty() && !quoted => {
+                        quoted = true;
+                        in_quotes = true;
+                    }
+                    _ if in_quotes => field.push(c),
+                    ',' => {
+                        fields.push(std::mem::take(&mut field));
+                        quoted = false;
+                    }
+                    '\r' if chars.peek() == Some(&'\n') => (),
+                    '\n' => {
+                        fields.push(field);
+                        return Ok(Some(fields));
+                    }
+                    '"' => return Err("quote within an unquoted field".to_string()),
+                    _ if quoted => return Err("text after a quoted field".to_string()),
+                    _ => field.push(c),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn load_csv() {
+    use crate::{ColumnSchema, RawRow, RawValue, Table};
+    use std::time::{Duration, SystemTime};
+
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::<u64>::new("age")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("happy").raw())
+            .chain(ColumnSchema::with_default("born", SystemTime::UNIX_EPOCH).raw()),
+    );
+    let loader = CsvLoader::new(&schema).map("years", "age");
+    let csv = "name,years,born,happy
+David,48,1975-01-02T03:04:05.5Z,true
+\"Roundy, \"\"Dave\"\"\",1,86400, no
+
+\"multi
+line\",2,1970-01-02T01:00:00+01:00,1
+";
+    let mut builder = TableBuilder::new(&schema);
+    assert_eq!(loader.load(csv.as_bytes(), &mut builder).unwrap(), 3);
+    let dir = tempfile::tempdir().unwrap();
+    builder.save(dir.path()).unwrap();
+    let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    let time = |s| crate::lens::RawValues::from(SystemTime::UNIX_EPOCH + s).0;
+    let row = |name: &str, age, happy, born: Duration| {
+        let mut values = vec![
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(age),
+            RawValue::Bool(happy),
+        ];
+        values.extend(time(born));
+        values.into_iter().collect::<RawRow>()
+    };
+    assert_eq!(
+        rows,
+        [
+            row("David", 48, true, Duration::new(157_863_845, 500_000_000)),
+            row("Roundy, \"Dave\"", 1, false, Duration::from_secs(86400)),
+            row("multi\nline", 2, true, Duration::from_secs(86400)),
+        ]
+    );
+
+    let error = |csv: &str| {
+        let mut builder = TableBuilder::new(&schema);
+        loader
+            .load(csv.as_bytes(), &mut builder)
+            .unwrap_err()
+            .to_string()
+    };
+    expect_test::expect![[r#"CSV row 2: column age: "old" is not a number"#]]
+        .assert_eq(&error("name,years\nDavid,48\nAlice,old\n"));
+    expect_test::expect!["CSV row 1: 1 fields for 2 columns"].assert_eq(&error("name,age\nDavid"));
+    expect_test::expect!["CSV row 0: no column height"].assert_eq(&error("name,height\n"));
+    expect_test::expect![[r#"CSV row 1: column born: "1975-13-01T00:00:00Z" is not a time"#]]
+        .assert_eq(&error("name,born\nDavid,1975-13-01T00:00:00Z\n"));
+    expect_test::expect!["CSV row 1: unterminated quoted field"]
+        .assert_eq(&error("name\n\"David\n"));
+}
+
+#[test]
+fn load_durations() {
+    use crate::{ColumnSchema, Table};
+    use std::time::Duration;
+
+    let mut schema = TableSchema::new("laps");
+    let lap = ColumnSchema::<Duration>::new("lap");
+    schema.add_primary(ColumnSchema::<u64>::new("runner").raw());
+    schema.add_min(lap.raw());
+    let csv = "runner,lap\n1,62.5\n1,61.25\n2,59\n";
+    let mut builder = TableBuilder::new(&schema);
+    CsvLoader::new(&schema)
+        .load(csv.as_bytes(), &mut builder)
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    builder.save(dir.path()).unwrap();
+    let laps = Table::read(dir.path(), &schema)
+        .unwrap()
+        .to_rows()
+        .unwrap()
+        .into_iter()
+        .map(|r| Duration::try_from(crate::lens::RawValues(r.values[1..].to_vec())).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        laps,
+        [Duration::from_millis(61_250), Duration::from_secs(59)]
+    );
+
+    let mut builder = TableBuilder::new(&schema);
+    let error = CsvLoader::new(&schema)
+        .load("runner,lap\n1,1.0000000001\n".as_bytes(), &mut builder)
+        .unwrap_err();
+    expect_test::expect![[
+        r#"CSV row 1: column lap: invalid value "1.0000000001" has more than 9 decimal places"#
+    ]]
+    .assert_eq(&error.to_string());
+}
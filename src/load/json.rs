@@ -0,0 +1,402 @@
+//! Loading rows from newline-delimited JSON.
+//!
+//! Each line holds one JSON object, whose keys name the columns it loads
+//! into.  Values are strings, numbers, booleans or `null`, which leaves a
+//! column at its default, and each is parsed through the lens of its column
+//! just as a CSV field would be.
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+use super::{LogicalColumn, Mapping};
+use crate::column::encoding::StorageError;
+use crate::parser::JsonKey;
+use crate::{TableBuilder, TableSchema};
+
+/// Loads newline-delimited JSON files into a table.
+#[derive(Debug, Clone)]
+pub struct JsonLoader {
+    mapping: Mapping,
+}
+
+impl JsonLoader {
+    /// Load JSON into tables with this schema.
+    ///
+    /// Each key loads into the column of the same name, unless it is mapped
+    /// elsewhere with [`JsonLoader::map`].
+    pub fn new(schema: &TableSchema) -> Self {
+        JsonLoader {
+            mapping: Mapping::new(schema),
+        }
+    }
+
+    /// Load the values of the key `key` into the logical column `column`
+    pub fn map(mut self, key: &str, column: &str) -> Self {
+        self.mapping.rename(key, column);
+        self
+    }
+
+    /// Read newline-delimited JSON, adding a row to `builder` for each
+    /// object, and returning the number of rows added.
+    ///
+    /// The input is read a line at a time, and blank lines are skipped.  A
+    /// line that cannot be loaded fails with [`StorageError::Json`], giving
+    /// its line number.
+    pub fn load<R: BufRead>(
+        &self,
+        reader: R,
+        builder: &mut TableBuilder,
+    ) -> Result<u64, StorageError> {
+        let defaults = self.mapping.defaults();
+        let mut columns = BTreeMap::<String, LogicalColumn>::new();
+        let mut rows = 0;
+        for (line, text) in (1..).zip(reader.lines()) {
+            let error = |message: String| StorageError::Json { line, message };
+            let text = text?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let mut row = defaults.clone();
+            for (key, value) in parse_object(&text).map_err(error)? {
+                if !columns.contains_key(&key) {
+                    let column = self.mapping.column(&key).map_err(error)?;
+                    columns.insert(key.clone(), column);
+                }
+                if let Some(value) = value {
+                    columns[&key]
+                        .set(&mut row, &value, builder)
+                        .map_err(error)?;
+                }
+            }
+            builder.insert_raw_row(row)?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+}
+
+/// Parse a JSON object holding only scalar values, giving the text of each
+/// value, or `None` for `null`.
+///
+/// Strings are unescaped, and numbers and booleans are given as written.
+fn parse_object(text: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let mut entries = Vec::new();
+    parser.expect(b'{')?;
+    if !parser.optional(b'}') {
+        loop {
+            let key = parser.string()?;
+            parser.expect(b':')?;
+            entries.push((key, parser.scalar()?));
+            if parser.optional(b'}') {
+                break;
+            }
+            parser.expect(b',')?;
+        }
+    }
+    parser.skip_whitespace();
+    if parser.pos < parser.text.len() {
+        return Err("text after the object".to_string());
+    }
+    Ok(entries)
+}
+
+/// The text of the value that `key` picks out of the JSON value `text`, or
+/// `None` if it is not an object with that field or an array with that
+/// element.
+pub(crate) fn json_value<'a>(text: &'a [u8], key: &JsonKey) -> Result<Option<&'a [u8]>, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut found = None;
+    match (parser.peek(), key) {
+        (Some(b'{'), JsonKey::Field(field)) => {
+            parser.expect(b'{')?;
+            if !parser.optional(b'}') {
+                loop {
+                    let key = parser.string()?;
+                    parser.expect(b':')?;
+                    let value = parser.value()?;
+                    if key.as_bytes() == field.as_slice() {
+                        found = Some(value);
+                    }
+                    if parser.optional(b'}') {
+                        break;
+                    }
+                    parser.expect(b',')?;
+                }
+            }
+        }
+        (Some(b'['), JsonKey::Index(index)) => {
+            parser.expect(b'[')?;
+            if !parser.optional(b']') {
+                for i in 0.. {
+                    let value = parser.value()?;
+                    if i == *index {
+                        found = Some(value);
+                    }
+                    if parser.optional(b']') {
+                        break;
+                    }
+                    parser.expect(b',')?;
+                }
+            }
+        }
+        _ => {
+            parser.value()?;
+        }
+    }
+    parser.skip_whitespace();
+    if parser.pos < parser.text.len() {
+        return Err("text after the value".to_string());
+    }
+    Ok(found.map(|range| &text[range]))
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).map(|c| c.is_ascii_whitespace()) == Some(true) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+
+    fn optional(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.optional(c) {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected '{}' at column {}",
+                c as char,
+                self.pos + 1
+            ))
+        }
+    }
+
+    /// Any value, giving where its text lies
+    fn value(&mut self) -> Result<std::ops::Range<usize>, String> {
+        let start = match self.peek() {
+            Some(_) => self.pos,
+            None => return Err("expected a value".to_string()),
+        };
+        match self.text[start] {
+            b'{' => {
+                self.expect(b'{')?;
+                if !self.optional(b'}') {
+                    loop {
+                        self.string()?;
+                        self.expect(b':')?;
+                        self.value()?;
+                        if self.optional(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+            }
+            b'[' => {
+                self.expect(b'[')?;
+                if !self.optional(b']') {
+                    loop {
+                        self.value()?;
+                        if self.optional(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+            }
+            _ => {
+                self.scalar()?;
+            }
+        }
+        Ok(start..self.pos)
+    }
+
+    /// A string, or a number, boolean or `null`
+    fn scalar(&mut self) -> Result<Option<String>, String> {
+        match self.peek() {
+            Some(b'"') => self.string().map(Some),
+            Some(b'{' | b'[') => Err(format!(
+                "nested value at column {} is not supported",
+                self.pos + 1
+            )),
+            _ => {
+                let start = self.pos;
+                while self
+                    .text
+                    .get(self.pos)
+                    .map(|c| c.is_ascii_alphanumeric() || b"+-.".contains(c))
+                    == Some(true)
+                {
+                    self.pos += 1;
+                }
+                let word = std::str::from_utf8(&self.text[start..self.pos]).expect("ascii");
+                match word {
+                    "null" => Ok(None),
+                    "true" | "false" => Ok(Some(word.to_string())),
+                    _ if is_number(word) => Ok(Some(word.to_string())),
+                    _ => Err(format!("expected a value at column {}", start + 1)),
+                }
+            }
+        }
+    }
+
+    /// A string in double quotes, with its escapes replaced
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self
+                .text
+                .get(self.pos)
+                .ok_or_else(|| "unterminated string".to_string())?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self
+                        .text
+                        .get(self.pos)
+                        .ok_or_else(|| "unterminated string".to_string())?;
+                    self.pos += 1;
+                    let unescaped = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(format!("bad escape at column {}", self.pos)),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend(unescaped.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|e| e.to_string())
+    }
+
+    /// The character of a `\u` escape, which may be a surrogate pair
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let hex = |p: &mut Self| -> Result<u32, String> {
+            let digits = p
+                .text
+                .get(p.pos..p.pos + 4)
+                .and_then(|d| std::str::from_utf8(d).ok())
+                .and_then(|d| u32::from_str_radix(d, 16).ok())
+                .ok_or_else(|| format!("bad unicode escape at column {}", p.pos))?;
+            p.pos += 4;
+            Ok(digits)
+        };
+        let first = hex(self)?;
+        let code = if (0xd800..0xdc00).contains(&first) && self.text[self.pos..].starts_with(b"\\u")
+        {
+            self.pos += 2;
+            let second = hex(self)?;
+            0x10000 + ((first - 0xd800) << 10) + (second.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            first
+        };
+        char::from_u32(code).ok_or_else(|| format!("bad unicode escape at column {}", self.pos))
+    }
+}
+
+/// Whether `word` is a JSON number
+fn is_number(word: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let word = word.strip_prefix('-').unwrap_or(word);
+    let (mantissa, exponent) = match word.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e.strip_prefix(['+', '-']).unwrap_or(e))),
+        None => (word, None),
+    };
+    let whole_and_fraction = match mantissa.split_once('.') {
+        Some((whole, fraction)) => digits(whole) && digits(fraction),
+        None => digits(mantissa),
+    };
+    whole_and_fraction && exponent.map(digits).unwrap_or(true)
+}
+
+#[test]
+fn load_json_lines() {
+    use crate::{ColumnSchema, RawRow, RawValue, Table};
+    use std::time::SystemTime;
+
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::<u64>::new("age")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("happy").raw())
+            .chain(ColumnSchema::with_default("born", SystemTime::UNIX_EPOCH).raw()),
+    );
+    let loader = JsonLoader::new(&schema).map("years", "age");
+    let json = r#"{"name": "David", "years": 48, "happy": true, "born": "1970-01-02T00:00:00Z"}
+
+{"name":"Dave \"é😀\"\n","born":1.5,"happy":null}
+{}
+"#;
+    let mut builder = TableBuilder::new(&schema);
+    assert_eq!(loader.load(json.as_bytes(), &mut builder).unwrap(), 3);
+    let dir = tempfile::tempdir().unwrap();
+    builder.save(dir.path()).unwrap();
+    let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    let row = |name: &str, age, happy, seconds, nanos| {
+        [
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::U64(age),
+            RawValue::Bool(happy),
+            RawValue::U64(seconds),
+            RawValue::U64(nanos),
+        ]
+        .into_iter()
+        .collect::<RawRow>()
+    };
+    assert_eq!(
+        rows,
+        [
+            row("", 0, false, 0, 0),
+            row("Dave \"é😀\"\n", 0, false, 1, 500_000_000),
+            row("David", 48, true, 86400, 0),
+        ]
+    );
+
+    let error = |json: &str| {
+        let mut builder = TableBuilder::new(&schema);
+        loader
+            .load(json.as_bytes(), &mut builder)
+            .unwrap_err()
+            .to_string()
+    };
+    expect_test::expect![[r#"JSON line 2: column age: "-1" is not a number"#]]
+        .assert_eq(&error("{\"age\": 1}\n{\"age\": -1}"));
+    expect_test::expect!["JSON line 1: no column height"].assert_eq(&error("{\"height\": 1}"));
+    expect_test::expect!["JSON line 1: nested value at column 9 is not supported"]
+        .assert_eq(&error("{\"age\": [1]}"));
+    expect_test::expect!["JSON line 1: expected a value at column 9"]
+        .assert_eq(&error("{\"age\": 01a}"));
+    expect_test::expect!["JSON line 1: text after the object"].assert_eq(&error("{} {}"));
+    expect_test::expect!["JSON line 1: unterminated string"].assert_eq(&error("{\"name"));
+}
@@ -1,60 +1,140 @@
-struct Lexer<'a> {
+pub(crate) struct Lexer<'a> {
     query: &'a [u8],
     pos: usize,
 }
 
 impl<'a> Lexer<'a> {
-    fn new(query: &'a str) -> Self {
+    pub(crate) fn new(query: &'a str) -> Self {
         Self {
             query: query.as_bytes(),
             pos: 0,
         }
     }
 
-    fn next_token(&mut self) -> TokenType {
-        let ch = self.query.get(self.pos);
+    /// The next token, skipping any whitespace, or `None` at the end.
+    pub(crate) fn next_token(&mut self) -> Result<Option<TokenType>, String> {
+        while self.peek().map(|c| c.is_ascii_whitespace()) == Some(true) {
+            self.pos += 1;
+        }
+        let c = if let Some(c) = self.peek() {
+            c
+        } else {
+            return Ok(None);
+        };
         self.pos += 1;
-        match ch {
-            Some(&c) => {
-                if c == b'*' {
-                    TokenType::Asterisk
-                } else if c.is_ascii_alphabetic() {
-                    self.consume_word()
-                } else if c.is_ascii_whitespace() {
-                    TokenType::WhiteSpace
-                } else {
-                    TokenType::Unknown
-                }
+        Ok(Some(match c {
+            b'*' => TokenType::Asterisk,
+            b',' => TokenType::Comma,
+            b'(' => TokenType::LeftParen,
+            b')' => TokenType::RightParen,
+            b';' => TokenType::Semicolon,
+            b'=' => TokenType::Equals,
+            b'<' if self.peek() == Some(b'=') => {
+                self.pos += 1;
+                TokenType::LessEquals
             }
-            None => TokenType::Unknown,
+            b'<' => TokenType::Less,
+            b'>' if self.peek() == Some(b'=') => {
+                self.pos += 1;
+                TokenType::GreaterEquals
+            }
+            b'>' => TokenType::Greater,
+            b'-' if self.peek() == Some(b'>') => {
+                self.pos += 1;
+                TokenType::Arrow
+            }
+            b'\'' => self.consume_string()?,
+            _ if c.is_ascii_digit() => self.consume_number()?,
+            _ if c.is_ascii_alphabetic() || c == b'_' => self.consume_word(),
+            _ => return Err(format!("unexpected character {:?}", c as char)),
+        }))
+    }
+
+    /// Lex the whole query
+    pub(crate) fn tokens(mut self) -> Result<Vec<TokenType>, String> {
+        let mut tokens = Vec::new();
+        while let Some(t) = self.next_token()? {
+            tokens.push(t);
         }
+        Ok(tokens)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.query.get(self.pos).copied()
     }
 
     fn consume_word(&mut self) -> TokenType {
-        while let Some(ch) = self.query.get(self.pos) {
-            self.pos += 1;
-            if ch.is_ascii_alphabetic() {
-                continue;
+        let start = self.pos - 1;
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'.' {
+                self.pos += 1;
             } else {
                 break;
             }
         }
+        TokenType::Word(String::from_utf8_lossy(&self.query[start..self.pos]).into_owned())
+    }
+
+    fn consume_number(&mut self) -> Result<TokenType, String> {
+        let start = self.pos - 1;
+        while self.peek().map(|c| c.is_ascii_digit()) == Some(true) {
+            self.pos += 1;
+        }
+        let digits = std::str::from_utf8(&self.query[start..self.pos]).expect("digits are ascii");
+        digits
+            .parse()
+            .map(TokenType::Number)
+            .map_err(|_| format!("number {digits} is too large"))
+    }
 
-        TokenType::Word
+    /// A string in single quotes, where `''` stands for a quote.
+    fn consume_string(&mut self) -> Result<TokenType, String> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_string()),
+                Some(b'\'') if self.query.get(self.pos + 1) == Some(&b'\'') => {
+                    bytes.push(b'\'');
+                    self.pos += 2;
+                }
+                Some(b'\'') => {
+                    self.pos += 1;
+                    return Ok(TokenType::String(bytes));
+                }
+                Some(c) => {
+                    bytes.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum TokenType {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TokenType {
     /// Represents '*' used for multiplication or selection all fields.
     Asterisk,
 
     /// A word that can be command or name (of tables/fields/variable).
-    Word,
+    Word(String),
+
+    /// An integer literal
+    Number(u64),
 
-    WhiteSpace,
+    /// A string literal
+    String(Vec<u8>),
 
-    Unknown,
+    Comma,
+    LeftParen,
+    RightParen,
+    Semicolon,
+    Equals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    /// `->`, picking a value out of JSON
+    Arrow,
 }
 
 #[cfg(test)]
@@ -66,10 +146,55 @@ mod test {
         let query = "SELECT * from table;".to_owned();
         let mut lex = Lexer::new(&query);
 
-        assert_eq!(lex.next_token(), TokenType::Word);
-        assert_eq!(lex.next_token(), TokenType::Asterisk);
-        assert_eq!(lex.next_token(), TokenType::WhiteSpace);
-        assert_eq!(lex.next_token(), TokenType::Word);
-        assert_eq!(lex.next_token(), TokenType::Word);
+        let word = |w: &str| Ok(Some(TokenType::Word(w.to_string())));
+        assert_eq!(lex.next_token(), word("SELECT"));
+        assert_eq!(lex.next_token(), Ok(Some(TokenType::Asterisk)));
+        assert_eq!(lex.next_token(), word("from"));
+        assert_eq!(lex.next_token(), word("table"));
+        assert_eq!(lex.next_token(), Ok(Some(TokenType::Semicolon)));
+        assert_eq!(lex.next_token(), Ok(None));
+    }
+
+    #[test]
+    fn literals() {
+        let tokens = Lexer::new("(42, 'it''s', modified.seconds)").tokens();
+        assert_eq!(
+            tokens,
+            Ok(vec![
+                TokenType::LeftParen,
+                TokenType::Number(42),
+                TokenType::Comma,
+                TokenType::String(b"it's".to_vec()),
+                TokenType::Comma,
+                TokenType::Word("modified.seconds".to_string()),
+                TokenType::RightParen,
+            ])
+        );
+        assert_eq!(
+            Lexer::new("a<=1>b<c>=").tokens(),
+            Ok(vec![
+                TokenType::Word("a".to_string()),
+                TokenType::LessEquals,
+                TokenType::Number(1),
+                TokenType::Greater,
+                TokenType::Word("b".to_string()),
+                TokenType::Less,
+                TokenType::Word("c".to_string()),
+                TokenType::GreaterEquals,
+            ])
+        );
+        assert_eq!(
+            Lexer::new("doc->'a'->0").tokens(),
+            Ok(vec![
+                TokenType::Word("doc".to_string()),
+                TokenType::Arrow,
+                TokenType::String(b"a".to_vec()),
+                TokenType::Arrow,
+                TokenType::Number(0),
+            ])
+        );
+        assert!(Lexer::new("a - b").tokens().is_err());
+        assert!(Lexer::new("'oops").tokens().is_err());
+        assert!(Lexer::new("99999999999999999999").tokens().is_err());
     }
 }
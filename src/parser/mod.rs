@@ -1,2 +1,530 @@
-#![allow(dead_code)]
+//! Parsing SQL statements.
+//!
+//! Statements name the raw columns of a table, so a column with fields is
+//! written like `modified.seconds`.  A column of JSON text may be followed
+//! by keys that pick a value out of it, like `payload->'user'->0`.
 mod lexer;
+
+use lexer::{Lexer, TokenType};
+
+use crate::{Comparison, Expr, RawValue};
+
+/// A parsed statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Statement {
+    Select {
+        columns: Columns,
+        table: String,
+        alias: Option<String>,
+        join: Option<Join>,
+        filter: Filter,
+    },
+    Insert {
+        table: String,
+        columns: Vec<String>,
+        rows: Vec<Vec<RawValue>>,
+        returning: Option<Columns>,
+    },
+    Delete {
+        table: String,
+        filter: Filter,
+        returning: Option<Columns>,
+    },
+}
+
+/// A list of columns to produce
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Columns {
+    All,
+    Named(Vec<Column>),
+}
+
+/// A column to produce, with the keys of the JSON value to pick out of it, if
+/// any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) path: Vec<JsonKey>,
+}
+
+impl Column {
+    /// The column as it is written in a statement
+    pub(crate) fn display_name(&self) -> String {
+        let mut name = self.name.clone();
+        for key in self.path.iter() {
+            match key {
+                JsonKey::Field(field) => {
+                    name.push_str(&format!("->'{}'", String::from_utf8_lossy(field)))
+                }
+                JsonKey::Index(i) => name.push_str(&format!("->{i}")),
+            }
+        }
+        name
+    }
+}
+
+/// A key picking a value out of a JSON value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum JsonKey {
+    /// The value of a field of an object
+    Field(Vec<u8>),
+    /// An element of an array
+    Index(u64),
+}
+
+/// A table joined to the one selected from, giving the pairs of columns
+/// that must be equal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Join {
+    pub(crate) table: String,
+    pub(crate) alias: Option<String>,
+    pub(crate) on: Vec<(String, String)>,
+}
+
+/// What a column is compared with in a `WHERE` clause
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Operand {
+    Value(RawValue),
+    /// The ingestion watermark of the table, see
+    /// [`Table::ingestion_watermark`](crate::Table::ingestion_watermark)
+    Watermark,
+}
+
+/// The condition of a `WHERE` clause, if there is one
+pub(crate) type Filter = Option<Expr<Operand>>;
+
+/// Parse a single statement, optionally followed by a semicolon
+pub(crate) fn parse(sql: &str) -> Result<Statement, String> {
+    let mut parser = Parser {
+        tokens: Lexer::new(sql).tokens()?,
+        pos: 0,
+    };
+    let statement = parser.statement()?;
+    parser.optional(&TokenType::Semicolon);
+    if let Some(t) = parser.peek() {
+        return Err(format!("unexpected {t:?} after statement"));
+    }
+    Ok(statement)
+}
+
+struct Parser {
+    tokens: Vec<TokenType>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenType> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<TokenType, String> {
+        let t = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end of statement".to_string())?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn optional(&mut self, token: &TokenType) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &TokenType) -> Result<(), String> {
+        match self.next()? {
+            t if t == *token => Ok(()),
+            t => Err(format!("expected {token:?}, found {t:?}")),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(TokenType::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn optional_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.optional_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("expected {keyword}, found {:?}", self.peek()))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            TokenType::Word(w) => Ok(w),
+            t => Err(format!("expected a name, found {t:?}")),
+        }
+    }
+
+    fn value(&mut self) -> Result<RawValue, String> {
+        match self.next()? {
+            TokenType::Number(n) => Ok(RawValue::U64(n)),
+            TokenType::String(s) => Ok(RawValue::Bytes(s)),
+            TokenType::Word(w) if w.eq_ignore_ascii_case("true") => Ok(RawValue::Bool(true)),
+            TokenType::Word(w) if w.eq_ignore_ascii_case("false") => Ok(RawValue::Bool(false)),
+            t => Err(format!("expected a value, found {t:?}")),
+        }
+    }
+
+    /// A comma separated list in parentheses
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        self.expect(&TokenType::LeftParen)?;
+        let mut items = vec![item(self)?];
+        while self.optional(&TokenType::Comma) {
+            items.push(item(self)?);
+        }
+        self.expect(&TokenType::RightParen)?;
+        Ok(items)
+    }
+
+    fn columns(&mut self) -> Result<Columns, String> {
+        if self.optional(&TokenType::Asterisk) {
+            return Ok(Columns::All);
+        }
+        let mut names = vec![self.column()?];
+        while self.optional(&TokenType::Comma) {
+            names.push(self.column()?);
+        }
+        Ok(Columns::Named(names))
+    }
+
+    fn column(&mut self) -> Result<Column, String> {
+        let name = self.name()?;
+        let mut path = Vec::new();
+        while self.optional(&TokenType::Arrow) {
+            path.push(match self.next()? {
+                TokenType::String(field) => JsonKey::Field(field),
+                TokenType::Number(i) => JsonKey::Index(i),
+                t => return Err(format!("expected a JSON key, found {t:?}")),
+            });
+        }
+        Ok(Column { name, path })
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, String> {
+        match self.next()? {
+            TokenType::Equals => Ok(Comparison::Equal),
+            TokenType::Less => Ok(Comparison::Less),
+            TokenType::LessEquals => Ok(Comparison::LessOrEqual),
+            TokenType::Greater => Ok(Comparison::Greater),
+            TokenType::GreaterEquals => Ok(Comparison::GreaterOrEqual),
+            t => Err(format!("expected a comparison, found {t:?}")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        if self.optional_keyword("watermark") {
+            Ok(Operand::Watermark)
+        } else {
+            self.value().map(Operand::Value)
+        }
+    }
+
+    /// A name for a table, which may follow `AS`
+    fn alias(&mut self) -> Result<Option<String>, String> {
+        if self.optional_keyword("as") {
+            return self.name().map(Some);
+        }
+        let keywords = ["where", "inner", "join", "on", "returning"];
+        if keywords.iter().any(|k| self.is_keyword(k))
+            || !matches!(self.peek(), Some(TokenType::Word(_)))
+        {
+            return Ok(None);
+        }
+        self.name().map(Some)
+    }
+
+    fn join(&mut self) -> Result<Option<Join>, String> {
+        if self.optional_keyword("inner") {
+            self.keyword("join")?;
+        } else if !self.optional_keyword("join") {
+            return Ok(None);
+        }
+        let table = self.name()?;
+        let alias = self.alias()?;
+        self.keyword("on")?;
+        let mut on = Vec::new();
+        loop {
+            let left = self.name()?;
+            self.expect(&TokenType::Equals)?;
+            on.push((left, self.name()?));
+            if !self.optional_keyword("and") {
+                break;
+            }
+        }
+        Ok(Some(Join { table, alias, on }))
+    }
+
+    fn filter(&mut self) -> Result<Filter, String> {
+        if self.optional_keyword("where") {
+            self.condition().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Conditions joined by `OR`, which binds more loosely than `AND`
+    fn condition(&mut self) -> Result<Expr<Operand>, String> {
+        let mut condition = self.conjunction()?;
+        while self.optional_keyword("or") {
+            condition = condition.or(self.conjunction()?);
+        }
+        Ok(condition)
+    }
+
+    fn conjunction(&mut self) -> Result<Expr<Operand>, String> {
+        let mut condition = self.negation()?;
+        while self.optional_keyword("and") {
+            condition = condition.and(self.negation()?);
+        }
+        Ok(condition)
+    }
+
+    fn negation(&mut self) -> Result<Expr<Operand>, String> {
+        if self.optional_keyword("not") {
+            return Ok(!self.negation()?);
+        }
+        if self.optional(&TokenType::LeftParen) {
+            let condition = self.condition()?;
+            self.expect(&TokenType::RightParen)?;
+            return Ok(condition);
+        }
+        let column = self.name()?;
+        let negated = self.optional_keyword("not");
+        let condition = if self.optional_keyword("in") {
+            Expr::In(column, self.list(Self::operand)?)
+        } else if self.optional_keyword("between") {
+            let low = self.operand()?;
+            self.keyword("and")?;
+            Expr::Between(column, low, self.operand()?)
+        } else if negated {
+            return Err(format!("expected IN or BETWEEN, found {:?}", self.peek()));
+        } else {
+            Expr::Compare(column, self.comparison()?, self.operand()?)
+        };
+        Ok(if negated { !condition } else { condition })
+    }
+
+    fn returning(&mut self) -> Result<Option<Columns>, String> {
+        if self.optional_keyword("returning") {
+            self.columns().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        if self.optional_keyword("select") {
+            let columns = self.columns()?;
+            self.keyword("from")?;
+            let table = self.name()?;
+            let alias = self.alias()?;
+            let join = self.join()?;
+            let filter = self.filter()?;
+            Ok(Statement::Select {
+                columns,
+                table,
+                alias,
+                join,
+                filter,
+            })
+        } else if self.optional_keyword("insert") {
+            self.keyword("into")?;
+            let table = self.name()?;
+            let columns = self.list(Self::name)?;
+            self.keyword("values")?;
+            let mut rows = vec![self.list(Self::value)?];
+            while self.optional(&TokenType::Comma) {
+                rows.push(self.list(Self::value)?);
+            }
+            let returning = self.returning()?;
+            Ok(Statement::Insert {
+                table,
+                columns,
+                rows,
+                returning,
+            })
+        } else if self.optional_keyword("delete") {
+            self.keyword("from")?;
+            let table = self.name()?;
+            let filter = self.filter()?;
+            let returning = self.returning()?;
+            Ok(Statement::Delete {
+                table,
+                filter,
+                returning,
+            })
+        } else {
+            Err(format!("unrecognized statement {:?}", self.peek()))
+        }
+    }
+}
+
+#[test]
+fn parse_statements() {
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    let columns = |names: &[&str]| {
+        names
+            .iter()
+            .map(|n| Column {
+                name: n.to_string(),
+                path: Vec::new(),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        parse("select * from people"),
+        Ok(Statement::Select {
+            columns: Columns::All,
+            table: "people".to_string(),
+            alias: None,
+            join: None,
+            filter: None,
+        })
+    );
+    assert_eq!(
+        parse("INSERT INTO people (name, age) VALUES ('David', 48), ('Alice', 30) RETURNING name, happy;"),
+        Ok(Statement::Insert {
+            table: "people".to_string(),
+            columns: names(&["name", "age"]),
+            rows: vec![
+                vec![RawValue::Bytes(b"David".to_vec()), RawValue::U64(48)],
+                vec![RawValue::Bytes(b"Alice".to_vec()), RawValue::U64(30)],
+            ],
+            returning: Some(Columns::Named(columns(&["name", "happy"]))),
+        })
+    );
+    assert_eq!(
+        parse("delete from people where age = 48 and happy = true returning *"),
+        Ok(Statement::Delete {
+            table: "people".to_string(),
+            filter: Some(
+                Expr::Compare(
+                    "age".to_string(),
+                    Comparison::Equal,
+                    Operand::Value(RawValue::U64(48))
+                )
+                .and(Expr::Compare(
+                    "happy".to_string(),
+                    Comparison::Equal,
+                    Operand::Value(RawValue::Bool(true))
+                ))
+            ),
+            returning: Some(Columns::All),
+        })
+    );
+    assert_eq!(
+        parse("select name from people where _ingested_at > 5 and _ingested_at <= watermark"),
+        Ok(Statement::Select {
+            columns: Columns::Named(columns(&["name"])),
+            table: "people".to_string(),
+            alias: None,
+            join: None,
+            filter: Some(
+                Expr::Compare(
+                    "_ingested_at".to_string(),
+                    Comparison::Greater,
+                    Operand::Value(RawValue::U64(5))
+                )
+                .and(Expr::Compare(
+                    "_ingested_at".to_string(),
+                    Comparison::LessOrEqual,
+                    Operand::Watermark
+                ))
+            ),
+        })
+    );
+    let filter = |sql: &str| match parse(&format!("select * from t where {sql}")) {
+        Ok(Statement::Select { filter, .. }) => Ok(filter.unwrap()),
+        Ok(s) => panic!("parsed {s:?}"),
+        Err(e) => Err(e),
+    };
+    let age = |c, n| Expr::Compare("age".to_string(), c, Operand::Value(RawValue::U64(n)));
+    let names = |names: &[&str]| {
+        names
+            .iter()
+            .map(|n| Operand::Value(RawValue::Bytes(n.as_bytes().to_vec())))
+            .collect()
+    };
+    assert_eq!(
+        filter("age = 1 or age = 2 and not age = 3"),
+        Ok(age(Comparison::Equal, 1).or(age(Comparison::Equal, 2).and(!age(Comparison::Equal, 3))))
+    );
+    assert_eq!(
+        filter("(age = 1 OR age = 2) AND name NOT IN ('David', 'Alice')"),
+        Ok(age(Comparison::Equal, 1)
+            .or(age(Comparison::Equal, 2))
+            .and(!Expr::In("name".to_string(), names(&["David", "Alice"]))))
+    );
+    assert_eq!(
+        filter("age between 3 and watermark and age not between 4 and 5"),
+        Ok(Expr::Between(
+            "age".to_string(),
+            Operand::Value(RawValue::U64(3)),
+            Operand::Watermark
+        )
+        .and(!Expr::Between(
+            "age".to_string(),
+            Operand::Value(RawValue::U64(4)),
+            Operand::Value(RawValue::U64(5))
+        )))
+    );
+    assert!(filter("(age = 1").is_err());
+    assert!(filter("age not = 1").is_err());
+    assert!(filter("age in ()").is_err());
+    assert_eq!(
+        parse("select * from people p inner join visits as v on p.name = v.who and x = y"),
+        Ok(Statement::Select {
+            columns: Columns::All,
+            table: "people".to_string(),
+            alias: Some("p".to_string()),
+            join: Some(Join {
+                table: "visits".to_string(),
+                alias: Some("v".to_string()),
+                on: vec![
+                    ("p.name".to_string(), "v.who".to_string()),
+                    ("x".to_string(), "y".to_string())
+                ],
+            }),
+            filter: None,
+        })
+    );
+    let doc = Column {
+        name: "doc".to_string(),
+        path: vec![JsonKey::Field(b"a".to_vec()), JsonKey::Index(2)],
+    };
+    assert_eq!(doc.display_name(), "doc->'a'->2");
+    assert_eq!(
+        parse("select doc->'a'->2, name from t"),
+        Ok(Statement::Select {
+            columns: Columns::Named(vec![doc, columns(&["name"]).remove(0)]),
+            table: "t".to_string(),
+            alias: None,
+            join: None,
+            filter: None,
+        })
+    );
+    assert!(parse("select doc->name from t").is_err());
+    assert!(parse("select * from a join b where x = 1").is_err());
+    assert!(parse("select * from a inner b on x = y").is_err());
+    assert!(parse("select * from people; select").is_err());
+    assert!(parse("insert into people values (1)").is_err());
+    assert!(parse("update people").is_err());
+}
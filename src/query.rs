@@ -0,0 +1,1175 @@
+//! Running SQL statements against a [`Database`].
+//!
+//! Statements work on the raw columns of a table, named as in the schema, so
+//! every value is a [`RawValue`].  A logical column stored in several raw
+//! columns may also be named as a whole, as `modified` for
+//! `modified.seconds` and `modified.subsecond_nanos`: it is selected as its
+//! raw columns, and is inserted or compared with text parsed through its
+//! lens, as a file is loaded, so `WHERE modified < '2023-01-31T12:00:00Z'`
+//! compares the raw columns in turn.  A raw column may likewise be compared
+//! with text its lens parses, as a `Decimal` with `'12.34'`.  The database
+//! can be inspected through the read-only `information_schema.tables`,
+//! `information_schema.columns`, `information_schema.segments`,
+//! `information_schema.statistics` and `information_schema.queries` tables,
+//! the last listing the statements being run.  Each may also be named with a `__` prefix in place of
+//! `information_schema.`, as in `SELECT * FROM __tables`.
+//!
+//! A `WHERE` clause compares columns with values using `=`, `<`, `<=`, `>`,
+//! `>=`, `IN (...)` or `BETWEEN ... AND ...`, combined with `AND`, `OR`,
+//! `NOT` and parentheses, and is evaluated as an [`Expr`](crate::Expr),
+//! using the index of a column it requires to equal some value if there is
+//! one, see [`Table::select`](crate::Table::select).  Only the partitions of
+//! a partitioned table that can hold matching rows are opened, see
+//! [`Table::read_where`](crate::Table::read_where).  The value `watermark`
+//! stands for the [ingestion watermark](crate::Table::ingestion_watermark) of
+//! the table, so `WHERE _ingested_at > earlier AND _ingested_at <= watermark`
+//! reads the rows saved since an earlier watermark.
+//!
+//! `SELECT ... FROM a [x] JOIN b [y] ON x.c = y.d [AND ...]` pairs up the
+//! rows of two tables with [`join`](crate::join).  The columns of a join may
+//! be qualified by the alias or name of their table, and must be if both
+//! tables have a column of that name.  Tables may also be joined with the
+//! `information_schema` tables, or those with each other.  Statements run
+//! through a [`Catalog`](crate::Catalog) may name the tables of its other
+//! databases as `database.table`.
+//!
+//! A selected column holding JSON text may be followed by a path of object
+//! keys and array indices, as in `doc->'user'->'tags'->0`, to pick the JSON
+//! text of the value at that path out of it, or `null` if there is none.
+//!
+//! The rows of a result can be written as an Arrow IPC stream with
+//! [`QueryResult::write_arrow_ipc`], for dataframe libraries to read.  They can
+//! also be shown to people with [`QueryResult::display_rows`], which puts
+//! the raw columns of each logical column selected whole back together.
+
+mod arrow_ipc;
+mod information_schema;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::column::encoding::StorageError;
+use crate::expr::Comparison;
+use crate::lens::{Lens, RawValues, Symbol};
+use crate::load::parse_field;
+use crate::parser::{parse, Column, Columns, Filter, Join, JsonKey, Operand, Statement};
+use crate::{
+    Database, DisplayRows, Expr, Metrics, RawColumnSchema, RawKind, RawRow, RawValue, TableSchema,
+};
+pub use arrow_ipc::write_arrow_ipc;
+
+/// The rows produced by a statement
+#[derive(Debug, Clone, Default)]
+pub struct QueryResult {
+    columns: Vec<String>,
+    rows: Vec<Vec<RawValue>>,
+    metrics: Metrics,
+    /// The raw column of a table that each column holds, unless it holds
+    /// something picked out of one
+    sources: Vec<Option<RawColumnSchema>>,
+}
+
+impl QueryResult {
+    /// The names of the columns
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+    /// The rows, with values in the order of the columns
+    pub fn rows(&self) -> &[Vec<RawValue>] {
+        &self.rows
+    }
+    /// The bytes read, seeks, and chunks decoded in reading the tables the
+    /// statement selected from
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+    /// The rows with the raw columns of each logical column that was
+    /// selected whole written out as one, through its lens, as by
+    /// [`Table::display_rows`](crate::Table::display_rows)
+    pub fn display_rows(&self) -> DisplayRows {
+        DisplayRows::new(&self.columns, &self.sources, &self.rows)
+    }
+}
+
+/// Results are equal when they hold the same rows, however much reading
+/// they took
+impl PartialEq for QueryResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns && self.rows == other.rows
+    }
+}
+
+impl Eq for QueryResult {}
+
+/// The statements being run against a database, which are listed by
+/// `information_schema.queries`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Running {
+    queries: Arc<Mutex<RunningQueries>>,
+}
+
+#[derive(Debug, Default)]
+struct RunningQueries {
+    next_id: u64,
+    /// The time each statement started, and its text
+    started: BTreeMap<u64, (Instant, String)>,
+}
+
+/// A statement that is listed as running until this is dropped
+struct RunningQuery<'a> {
+    running: &'a Running,
+    id: u64,
+}
+
+impl Drop for RunningQuery<'_> {
+    fn drop(&mut self) {
+        self.running.lock().started.remove(&self.id);
+    }
+}
+
+impl Running {
+    fn lock(&self) -> std::sync::MutexGuard<'_, RunningQueries> {
+        self.queries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// List `sql` as running until the returned guard is dropped
+    fn start(&self, sql: &str) -> RunningQuery<'_> {
+        let mut queries = self.lock();
+        let id = queries.next_id;
+        queries.next_id += 1;
+        queries
+            .started
+            .insert(id, (Instant::now(), sql.to_string()));
+        RunningQuery { running: self, id }
+    }
+
+    /// The id of each running statement, with how long it has run and its
+    /// text
+    pub(crate) fn list(&self) -> Vec<(u64, Duration, String)> {
+        self.lock()
+            .started
+            .iter()
+            .map(|(id, (started, sql))| (*id, started.elapsed(), sql.clone()))
+            .collect()
+    }
+}
+
+fn query_error(msg: impl Into<String>) -> StorageError {
+    StorageError::Query(msg.into())
+}
+
+pub(crate) fn column_index(schema: &TableSchema, name: &str) -> Result<usize, StorageError> {
+    schema
+        .raw_columns()
+        .position(|c| c.display_name() == name)
+        .ok_or_else(|| query_error(format!("no column {name} in table {}", schema.name())))
+}
+
+/// The condition of a filter, comparing with `watermark` where the filter
+/// names it
+fn condition(
+    table: &str,
+    filter: Filter,
+    watermark: Option<u64>,
+) -> Result<Option<Expr>, StorageError> {
+    filter
+        .map(|filter| {
+            filter.try_map(&mut |operand| match operand {
+                Operand::Value(v) => Ok(v),
+                Operand::Watermark => watermark.map(RawValue::U64).ok_or_else(|| {
+                    query_error(format!("table {table} does not record ingestion times"))
+                }),
+            })
+        })
+        .transpose()
+}
+
+/// The condition of a filter on a table, comparing raw columns in place of
+/// the logical columns it names, see [`raw_condition`]
+fn table_condition(
+    schema: &TableSchema,
+    filter: Filter,
+    watermark: Option<u64>,
+) -> Result<Option<Expr>, StorageError> {
+    condition(schema.name(), filter, watermark)?
+        .map(|c| raw_condition(schema, c))
+        .transpose()
+}
+
+/// The raw column named `name`, or else the raw columns of the logical column
+/// named `name`
+fn raw_columns_of(schema: &TableSchema, name: &str) -> Result<Vec<usize>, StorageError> {
+    if let Ok(i) = column_index(schema, name) {
+        return Ok(vec![i]);
+    }
+    let indices = schema
+        .raw_columns()
+        .enumerate()
+        .filter(|(_, c)| c.name() == name)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if indices.is_empty() {
+        return Err(query_error(format!(
+            "no column {name} in table {}",
+            schema.name()
+        )));
+    }
+    Ok(indices)
+}
+
+/// The values of the raw columns at `indices` that `value` stands for.
+///
+/// A value of the kind of a single raw column is its value.  Text, or a
+/// number for several raw columns, is parsed through the lens of the logical
+/// column, as a file is loaded, so a time may be written
+/// `'2023-01-31T12:00:00Z'` or as a number of seconds.
+fn decompose(
+    schema: &TableSchema,
+    indices: &[usize],
+    column: &str,
+    value: &RawValue,
+) -> Result<Vec<RawValue>, StorageError> {
+    let raw_columns = schema.raw_columns().collect::<Vec<_>>();
+    let kinds = indices
+        .iter()
+        .map(|&i| raw_columns[i].kind())
+        .collect::<Vec<_>>();
+    if kinds == [value.kind()] {
+        return Ok(vec![value.clone()]);
+    }
+    let text = match (value, kinds.as_slice()) {
+        (RawValue::Bytes(b), _) => String::from_utf8(b.clone())
+            .map_err(|_| query_error(format!("column {column} cannot hold {value}")))?,
+        (v, [kind]) => {
+            return Err(query_error(format!(
+                "column {column} holds {kind:?}, not {:?}",
+                v.kind()
+            )))
+        }
+        (v, _) => v.to_string(),
+    };
+    let lens = raw_columns[indices[0]].lens();
+    if lens == Symbol::LENS_ID {
+        return Ok(RawValues::from(Symbol::of(&text)).0);
+    }
+    parse_field(lens, &kinds, &text).map_err(|e| query_error(format!("column {column}: {e}")))
+}
+
+/// Rewrite a condition to compare only raw columns.
+///
+/// A logical column stored in several raw columns is compared by comparing
+/// them in turn, as tuples are, so `modified < t` is `modified.seconds <
+/// t.seconds OR (modified.seconds = t.seconds AND modified.subsecond_nanos <
+/// t.subsecond_nanos)`.  Values are decomposed by [`decompose`].
+fn raw_condition(schema: &TableSchema, condition: Expr) -> Result<Expr, StorageError> {
+    let names = schema
+        .raw_columns()
+        .map(|c| c.display_name())
+        .collect::<Vec<_>>();
+    let split = |column: &str, value: &RawValue| -> Result<Vec<(String, RawValue)>, StorageError> {
+        let indices = raw_columns_of(schema, column)?;
+        let values = decompose(schema, &indices, column, value)?;
+        Ok(indices
+            .iter()
+            .map(|&i| names[i].clone())
+            .zip(values)
+            .collect())
+    };
+    Ok(match condition {
+        Expr::Compare(column, comparison, v) => compare_tuple(split(&column, &v)?, comparison),
+        Expr::In(column, values) => {
+            let split = values
+                .iter()
+                .map(|v| split(&column, v))
+                .collect::<Result<Vec<_>, _>>()?;
+            match split.first().map(|fields| fields.as_slice()) {
+                Some([(name, _)]) => Expr::In(
+                    name.clone(),
+                    split.into_iter().flatten().map(|(_, v)| v).collect(),
+                ),
+                _ => split
+                    .into_iter()
+                    .map(|fields| compare_tuple(fields, Comparison::Equal))
+                    .reduce(Expr::or)
+                    .ok_or_else(|| query_error(format!("no values for {column} to be in")))?,
+            }
+        }
+        Expr::Between(column, low, high) => match (split(&column, &low)?, split(&column, &high)?) {
+            (mut low, mut high) if low.len() == 1 => {
+                let (name, low) = low.remove(0);
+                Expr::Between(name, low, high.remove(0).1)
+            }
+            (low, high) => compare_tuple(low, Comparison::GreaterOrEqual)
+                .and(compare_tuple(high, Comparison::LessOrEqual)),
+        },
+        Expr::And(a, b) => raw_condition(schema, *a)?.and(raw_condition(schema, *b)?),
+        Expr::Or(a, b) => raw_condition(schema, *a)?.or(raw_condition(schema, *b)?),
+        Expr::Not(a) => !raw_condition(schema, *a)?,
+    })
+}
+
+/// Compare raw columns with their values in turn, as tuples are compared
+fn compare_tuple(mut fields: Vec<(String, RawValue)>, comparison: Comparison) -> Expr {
+    let (name, v) = fields.pop().expect("a logical column has raw columns");
+    let mut condition = Expr::Compare(name, comparison, v);
+    let strictly = match comparison {
+        Comparison::Less | Comparison::LessOrEqual => Comparison::Less,
+        Comparison::Greater | Comparison::GreaterOrEqual => Comparison::Greater,
+        Comparison::Equal => Comparison::Equal,
+    };
+    for (name, v) in fields.into_iter().rev() {
+        let equal = Expr::Compare(name.clone(), Comparison::Equal, v.clone());
+        condition = match comparison {
+            Comparison::Equal => equal.and(condition),
+            _ => Expr::Compare(name, strictly, v).or(equal.and(condition)),
+        };
+    }
+    condition
+}
+
+/// Whether a filter compares with the watermark
+fn uses_watermark(filter: &Filter) -> bool {
+    let Some(filter) = filter.clone() else {
+        return false;
+    };
+    filter
+        .try_map(&mut |operand| match operand {
+            Operand::Watermark => Err(()),
+            Operand::Value(v) => Ok(v),
+        })
+        .is_err()
+}
+
+/// The JSON value at `path` within the value of `column`, which must be JSON
+/// text unless the path is empty, or `null` if there is none
+fn json_path(column: &str, value: &RawValue, path: &[JsonKey]) -> Result<RawValue, StorageError> {
+    if path.is_empty() {
+        return Ok(value.clone());
+    }
+    let RawValue::Bytes(text) = value else {
+        return Err(query_error(format!("column {column} does not hold JSON")));
+    };
+    let mut found = Some(text.as_slice());
+    for key in path {
+        let Some(text) = found else {
+            break;
+        };
+        found = crate::load::json_value(text, key)
+            .map_err(|e| query_error(format!("column {column} does not hold JSON: {e}")))?;
+    }
+    Ok(RawValue::Bytes(found.unwrap_or(b"null").to_vec()))
+}
+
+/// The values of the picked columns of each row, given by their indices,
+/// names and the paths of the JSON values to pick out of them
+fn pick(
+    rows: &[RawRow],
+    picks: &[(usize, String, &[JsonKey])],
+) -> Result<Vec<Vec<RawValue>>, StorageError> {
+    rows.iter()
+        .map(|r| {
+            picks
+                .iter()
+                .map(|(i, name, path)| json_path(name, &r.values[*i], path))
+                .collect()
+        })
+        .collect()
+}
+
+/// Pick the given columns out of rows
+fn project(
+    schema: &TableSchema,
+    columns: &Columns,
+    rows: &[RawRow],
+) -> Result<QueryResult, StorageError> {
+    let names = schema
+        .raw_columns()
+        .map(|c| c.display_name())
+        .collect::<Vec<_>>();
+    let picks = match columns {
+        Columns::All => (0..names.len())
+            .map(|i| (i, names[i].clone(), &[][..]))
+            .collect(),
+        Columns::Named(columns) => {
+            let mut picks = Vec::new();
+            for c in columns.iter() {
+                match raw_columns_of(schema, &c.name)?.as_slice() {
+                    [i] => picks.push((*i, c.display_name(), &c.path[..])),
+                    // A logical column is picked as its raw columns.
+                    indices if c.path.is_empty() => {
+                        picks.extend(indices.iter().map(|&i| (i, names[i].clone(), &[][..])))
+                    }
+                    _ => return Err(query_error(format!("column {} does not hold JSON", c.name))),
+                }
+            }
+            picks
+        }
+    };
+    let raw_columns = schema.raw_columns().collect::<Vec<_>>();
+    Ok(QueryResult {
+        columns: picks.iter().map(|(_, name, _)| name.clone()).collect(),
+        rows: pick(rows, &picks)?,
+        metrics: Metrics::default(),
+        sources: picks
+            .iter()
+            .map(|(i, _, path)| Some(raw_columns[*i].clone()).filter(|_| path.is_empty()))
+            .collect(),
+    })
+}
+
+/// The columns of two joined tables, each with the alias or name of its
+/// table and its kind
+struct JoinedColumns(Vec<(String, String, RawKind)>);
+
+impl JoinedColumns {
+    /// Find a column, which may be qualified by the alias or name of its
+    /// table, and must be if both tables have a column of that name
+    fn index(&self, name: &str) -> Result<usize, StorageError> {
+        let qualified = self.0.iter().position(|(table, column, _)| {
+            name.strip_prefix(table.as_str())
+                .and_then(|n| n.strip_prefix('.'))
+                == Some(column)
+        });
+        if let Some(i) = qualified {
+            return Ok(i);
+        }
+        let found = (0..self.0.len())
+            .filter(|&i| self.0[i].1 == name)
+            .collect::<Vec<_>>();
+        match found.as_slice() {
+            [i] => Ok(*i),
+            [] => Err(query_error(format!(
+                "no column {name} in the joined tables"
+            ))),
+            _ => Err(query_error(format!(
+                "column {name} is in both joined tables"
+            ))),
+        }
+    }
+}
+
+/// Whether `name` is that of one of the `information_schema` tables, which
+/// no table of a database may have
+pub(crate) fn is_system_table(name: &str) -> bool {
+    information_schema::short_name(name).is_some()
+}
+
+/// Finds the database holding a table named in a statement, along with the
+/// name of the table within that database
+pub(crate) type Resolve<'a> = dyn Fn(&str) -> Result<(&'a Database, String), StorageError> + 'a;
+
+/// The schema and rows of a table, which may be one of the
+/// `information_schema` tables, adding the work of reading it to `metrics`
+fn read_rows(
+    resolve: &Resolve,
+    table: &str,
+    metrics: &Metrics,
+) -> Result<(TableSchema, Vec<RawRow>), StorageError> {
+    let (db, table) = resolve(table)?;
+    if let Some(read) = information_schema::read(db, &table)? {
+        return Ok(read);
+    }
+    let table = db.table(&table)?.read()?;
+    let rows = table.to_rows()?;
+    metrics.add(table.metrics());
+    Ok((table.schema().clone(), rows))
+}
+
+/// Run a `SELECT` with a `JOIN`
+fn select_join(
+    resolve: &Resolve,
+    columns: Columns,
+    table: String,
+    alias: Option<String>,
+    join: Join,
+    filter: Filter,
+) -> Result<QueryResult, StorageError> {
+    let metrics = Metrics::default();
+    let (left_schema, left) = read_rows(resolve, &table, &metrics)?;
+    let (right_schema, right) = read_rows(resolve, &join.table, &metrics)?;
+    let num_left = left_schema.raw_columns().count();
+    let mut joined = JoinedColumns(Vec::new());
+    for (schema, name) in [
+        (&left_schema, alias.unwrap_or(table)),
+        (&right_schema, join.alias.unwrap_or(join.table)),
+    ] {
+        for c in schema.raw_columns() {
+            joined.0.push((name.clone(), c.display_name(), c.kind()));
+        }
+    }
+
+    let (mut left_key, mut right_key) = (Vec::new(), Vec::new());
+    for (a, b) in join.on.iter() {
+        let (i, j) = match (joined.index(a)?, joined.index(b)?) {
+            (i, j) if i < num_left && j >= num_left => (i, j),
+            (j, i) if i < num_left && j >= num_left => (i, j),
+            _ => {
+                return Err(query_error(format!(
+                    "{a} = {b} does not compare columns of the two joined tables"
+                )))
+            }
+        };
+        if joined.0[i].2 != joined.0[j].2 {
+            return Err(query_error(format!(
+                "cannot join {a} of {:?} with {b} of {:?}",
+                joined.0[i].2, joined.0[j].2
+            )));
+        }
+        left_key.push(i);
+        right_key.push(j - num_left);
+    }
+    let mut rows = crate::join(&left, &left_key, &right, &right_key);
+
+    let name = format!("{} join {}", left_schema.name(), right_schema.name());
+    if let Some(condition) = condition(&name, filter, None)? {
+        let predicate = condition.bind_with(&|name| {
+            let i = joined.index(name)?;
+            Ok((i, joined.0[i].2))
+        })?;
+        rows.retain(|r| predicate.matches(&r.values));
+    }
+
+    let picks = match &columns {
+        Columns::All => joined
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, (table, column, _))| (i, format!("{table}.{column}"), &[][..]))
+            .collect(),
+        Columns::Named(columns) => columns
+            .iter()
+            .map(|c| Ok((joined.index(&c.name)?, c.display_name(), &c.path[..])))
+            .collect::<Result<Vec<_>, StorageError>>()?,
+    };
+    let raw_columns = left_schema
+        .raw_columns()
+        .chain(right_schema.raw_columns())
+        .collect::<Vec<_>>();
+    Ok(QueryResult {
+        columns: picks.iter().map(|(_, name, _)| name.clone()).collect(),
+        rows: pick(&rows, &picks)?,
+        metrics,
+        sources: picks
+            .iter()
+            .map(|(i, _, path)| Some(raw_columns[*i].clone()).filter(|_| path.is_empty()))
+            .collect(),
+    })
+}
+
+impl Database {
+    /// Run a single SQL statement.
+    ///
+    /// `INSERT` and `DELETE` produce no rows unless they have a `RETURNING`
+    /// clause, in which case they produce the inserted rows (including any
+    /// defaults) or the deleted rows.
+    pub fn execute(&self, sql: &str) -> Result<QueryResult, StorageError> {
+        execute(self, &|table| Ok((self, table.to_string())), sql)
+    }
+}
+
+/// Run a single SQL statement in `current`, finding each table it names
+/// with `resolve`
+pub(crate) fn execute(
+    current: &Database,
+    resolve: &Resolve,
+    sql: &str,
+) -> Result<QueryResult, StorageError> {
+    let _running = current.running().start(sql);
+    match parse(sql).map_err(StorageError::Query)? {
+        Statement::Select {
+            columns,
+            table,
+            alias,
+            join: Some(join),
+            filter,
+        } => select_join(resolve, columns, table, alias, join, filter),
+        Statement::Select {
+            columns,
+            table,
+            alias,
+            join: None,
+            filter,
+        } => {
+            // Columns may be named through the alias of the table.
+            let unalias = |name: String| match alias
+                .as_ref()
+                .and_then(|a| name.strip_prefix(a.as_str())?.strip_prefix('.'))
+            {
+                Some(name) => name.to_string(),
+                None => name,
+            };
+            let columns = match columns {
+                Columns::Named(columns) => Columns::Named(
+                    columns
+                        .into_iter()
+                        .map(|c| Column {
+                            name: unalias(c.name),
+                            path: c.path,
+                        })
+                        .collect(),
+                ),
+                Columns::All => Columns::All,
+            };
+            let filter = filter.map(|f| f.rename(&unalias));
+            let metrics = Metrics::default();
+            let (db, table) = resolve(&table)?;
+            let (schema, rows) = match information_schema::read(db, &table)? {
+                Some((schema, mut rows)) => {
+                    if let Some(condition) = table_condition(&schema, filter, None)? {
+                        let predicate = condition.bind(&schema)?;
+                        rows.retain(|r| predicate.matches(&r.values));
+                    }
+                    (schema, rows)
+                }
+                None => {
+                    let table = db.table(&table)?;
+                    // The watermark is only read when the filter uses it.
+                    let watermark = if uses_watermark(&filter) {
+                        table.ingestion_watermark()?
+                    } else {
+                        None
+                    };
+                    let rows = match table_condition(table.schema(), filter, watermark)? {
+                        Some(condition) => {
+                            let read = table.read_where(&condition)?;
+                            let rows = read.select(&condition)?;
+                            metrics.add(read.metrics());
+                            rows
+                        }
+                        None => {
+                            let read = table.read()?;
+                            let rows = read.to_rows()?;
+                            metrics.add(read.metrics());
+                            rows
+                        }
+                    };
+                    (table.schema().clone(), rows)
+                }
+            };
+            Ok(QueryResult {
+                metrics,
+                ..project(&schema, &columns, &rows)?
+            })
+        }
+        Statement::Insert {
+            table,
+            columns,
+            rows,
+            returning,
+        } => {
+            let (db, table) = resolve(&table)?;
+            let table = db.table(&table)?;
+            let schema = table.schema();
+            let indices = columns
+                .iter()
+                .map(|n| raw_columns_of(schema, n))
+                .collect::<Result<Vec<_>, _>>()?;
+            let defaults = schema
+                .raw_columns()
+                .map(|c| c.default().clone())
+                .collect::<RawRow>();
+            let rows = rows
+                .into_iter()
+                .map(|values| {
+                    if values.len() != indices.len() {
+                        return Err(query_error(format!(
+                            "{} values for {} columns",
+                            values.len(),
+                            indices.len()
+                        )));
+                    }
+                    let mut row = defaults.clone();
+                    for ((indices, name), v) in indices.iter().zip(&columns).zip(values) {
+                        let values = decompose(schema, indices, name, &v)?;
+                        for (&i, v) in indices.iter().zip(values) {
+                            row.values[i] = v;
+                        }
+                    }
+                    Ok(row)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let rows = table.insert_returning(rows)?;
+            match returning {
+                Some(columns) => project(schema, &columns, &rows),
+                None => Ok(QueryResult::default()),
+            }
+        }
+        Statement::Delete {
+            table,
+            filter,
+            returning,
+        } => {
+            let (db, table) = resolve(&table)?;
+            let table = db.table(&table)?;
+            let schema = table.schema();
+            let watermark = table.ingestion_watermark()?;
+            let predicate = table_condition(schema, filter, watermark)?
+                .map(|c| c.bind(schema))
+                .transpose()?;
+            let deleted = table.delete_rows(|r| match &predicate {
+                Some(predicate) => predicate.matches(&r.values),
+                None => true,
+            })?;
+            match returning {
+                Some(columns) => project(schema, &columns, &deleted),
+                None => Ok(QueryResult::default()),
+            }
+        }
+    }
+}
+
+#[test]
+fn insert_and_delete_returning() {
+    use crate::ColumnSchema;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::<u64>::new("age")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("happy").raw()),
+    );
+    db.create_table(schema).unwrap();
+
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    let david = || RawValue::Bytes(b"David".to_vec());
+    let alice = || RawValue::Bytes(b"Alice".to_vec());
+
+    let result = db
+        .execute("insert into people (name, age) values ('David', 48)")
+        .unwrap();
+    assert_eq!(result, QueryResult::default());
+    let result = db
+        .execute("insert into people (age, name) values (30, 'Alice') returning *")
+        .unwrap();
+    assert_eq!(result.columns(), names(&["name", "age", "happy"]));
+    assert_eq!(
+        result.rows(),
+        [vec![alice(), RawValue::U64(30), RawValue::Bool(false)]]
+    );
+
+    let result = db.execute("select name from people").unwrap();
+    assert_eq!(result.columns(), names(&["name"]));
+    assert_eq!(result.rows(), [vec![alice()], vec![david()]]);
+
+    let result = db
+        .execute("DELETE FROM people WHERE age = 48 RETURNING name, age")
+        .unwrap();
+    assert_eq!(result.rows(), [vec![david(), RawValue::U64(48)]]);
+    let result = db.execute("delete from people where age = 48 returning name");
+    assert_eq!(result.unwrap().rows(), Vec::<Vec<RawValue>>::new());
+    let result = db
+        .execute("select * from people where happy = false")
+        .unwrap();
+    assert_eq!(
+        result.rows(),
+        [vec![alice(), RawValue::U64(30), RawValue::Bool(false)]]
+    );
+
+    assert!(db
+        .execute("insert into people (age) values ('old')")
+        .is_err());
+    assert!(db
+        .execute("insert into people (name, age) values ('Bob')")
+        .is_err());
+    assert!(db.execute("delete from people where height = 3").is_err());
+    assert!(db.execute("select * from nobody").is_err());
+
+    db.execute("insert into people (name, age) values ('Bob', 7), ('Carol', 60)")
+        .unwrap();
+    let select = |sql: &str| {
+        let result = db.execute(&format!("select name from people where {sql}"));
+        result.map(|r| {
+            r.rows()
+                .iter()
+                .map(|r| r[0].to_string())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(
+        select("age between 10 and 60 and not happy = true").unwrap(),
+        ["'Alice'", "'Carol'"]
+    );
+    assert_eq!(
+        select("name in ('Bob', 'Dave') or (age > 50 and age <= 60)").unwrap(),
+        ["'Bob'", "'Carol'"]
+    );
+    assert!(select("age between 'young' and 'old'").is_err());
+}
+
+#[test]
+fn display_logical_columns() {
+    use crate::ColumnSchema;
+    use std::time::SystemTime;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("events");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::with_default("at", SystemTime::UNIX_EPOCH).raw());
+    db.create_table(schema).unwrap();
+    db.execute("insert into events (name, at.seconds) values ('launch', 1675166400)")
+        .unwrap();
+
+    let shown = db.execute("select * from events").unwrap().display_rows();
+    assert_eq!(shown.columns(), ["name", "at"]);
+    assert_eq!(shown.rows(), [["launch", "2023-01-31T12:00:00Z"]]);
+    let shown = db
+        .execute("select e.at.seconds, name from events e")
+        .unwrap()
+        .display_rows();
+    assert_eq!(shown.columns(), ["at.seconds", "name"]);
+    assert_eq!(shown.rows(), [["1675166400", "launch"]]);
+}
+
+#[test]
+fn logical_column_names() {
+    use crate::lens::Decimal;
+    use crate::ColumnSchema;
+    use std::time::SystemTime;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = Database::open(dir.path()).unwrap();
+    let mut schema = TableSchema::new("events");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::with_default("at", SystemTime::UNIX_EPOCH)
+            .raw()
+            .chain(ColumnSchema::<Decimal<2>>::new("price").raw()),
+    );
+    db.create_table(schema).unwrap();
+    db.execute(
+        "insert into events (name, at, price) values \
+         ('launch', '2023-01-31T12:00:00Z', '12.34'), \
+         ('landing', '2023-01-31T12:00:00.5Z', 5), \
+         ('lunch', 1675166399, '0.5')",
+    )
+    .unwrap();
+    assert!(db
+        .execute("insert into events (name, at) values ('late', 'never')")
+        .is_err());
+
+    let names = |sql: &str| {
+        let result = db.execute(&format!("select name from events where {sql}"));
+        result.map(|r| {
+            r.rows()
+                .iter()
+                .map(|r| r[0].to_string())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(names("at = '2023-01-31T12:00:00Z'").unwrap(), ["'launch'"]);
+    assert_eq!(names("at > '2023-01-31T12:00:00Z'").unwrap(), ["'landing'"]);
+    assert_eq!(
+        names("at <= '2023-01-31T12:00:00Z'").unwrap(),
+        ["'launch'", "'lunch'"]
+    );
+    assert_eq!(
+        names("at between 1675166400 and '2023-02-01T00:00:00Z'").unwrap(),
+        ["'landing'", "'launch'"]
+    );
+    assert_eq!(
+        names("at in (1675166399, '2023-01-31T12:00:00.5Z')").unwrap(),
+        ["'landing'", "'lunch'"]
+    );
+    assert_eq!(names("price > '1.5'").unwrap(), ["'launch'"]);
+    assert_eq!(names("at.seconds < 1675166400").unwrap(), ["'lunch'"]);
+    assert!(names("at < 'tomorrow'").is_err());
+    assert!(names("price > true").is_err());
+
+    let result = db.execute("select at from events where price = '0.5'");
+    let result = result.unwrap();
+    assert_eq!(result.columns(), ["at.seconds", "at.subsecond_nanos"]);
+    assert_eq!(
+        result.rows(),
+        [[RawValue::U64(1675166399), RawValue::U64(0)]]
+    );
+    assert_eq!(result.display_rows().rows(), [["2023-01-31T11:59:59Z"]]);
+    assert!(db.execute("select at->'x' from events").is_err());
+}
+
+#[test]
+fn information_schema() {
+    use crate::ColumnSchema;
+
+    let mut db = Database::in_memory();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_sum(ColumnSchema::<u64>::new("visits").raw());
+    db.create_table(schema).unwrap();
+    db.execute("insert into people (name, visits) values ('David', 1), ('Alice', 1)")
+        .unwrap();
+    db.execute("insert into people (name, visits) values ('David', 1)")
+        .unwrap();
+
+    let result = db
+        .execute("select * from information_schema.tables")
+        .unwrap();
+    assert_eq!(
+        result.columns(),
+        ["table_name", "columns", "time_column", "conflict_policy"]
+    );
+    assert_eq!(
+        result.rows(),
+        [vec![
+            RawValue::Bytes(b"people".to_vec()),
+            RawValue::U64(2),
+            RawValue::Bytes(Vec::new()),
+            RawValue::Bytes(b"Aggregate".to_vec()),
+        ]]
+    );
+
+    let result = db
+        .execute("select column_name, aggregation from information_schema.columns")
+        .unwrap();
+    assert_eq!(
+        result.rows(),
+        [
+            [b"name".to_vec(), b"Primary".to_vec()].map(RawValue::Bytes),
+            [b"visits".to_vec(), b"Sum".to_vec()].map(RawValue::Bytes),
+        ]
+    );
+
+    let result = db
+        .execute(
+            "select segment, rows from information_schema.segments where table_name = 'people'",
+        )
+        .unwrap();
+    assert_eq!(
+        result.rows(),
+        [
+            [RawValue::U64(0), RawValue::U64(2)],
+            [RawValue::U64(1), RawValue::U64(1)],
+        ]
+    );
+
+    let result = db
+        .execute("select rows, stored_rows, segments from information_schema.statistics")
+        .unwrap();
+    assert_eq!(result.rows(), [[2, 3, 2].map(RawValue::U64)]);
+
+    // The tables may be named as the client names them.
+    assert_eq!(
+        db.execute("select * from __statistics").unwrap(),
+        db.execute("select * from information_schema.statistics")
+            .unwrap()
+    );
+
+    // A statement lists itself while it runs, and no longer once it is done.
+    let sql = "select query_id, sql from __queries";
+    let result = db.execute(sql).unwrap();
+    let id = match result.rows() {
+        [row] if row[1] == RawValue::Bytes(sql.as_bytes().to_vec()) => row[0].clone(),
+        rows => panic!("{rows:?}"),
+    };
+    assert_eq!(db.execute(sql).unwrap().rows().len(), 1);
+    assert_ne!(db.execute(sql).unwrap().rows()[0][0], id);
+    assert!(db.running().list().is_empty());
+
+    assert!(db
+        .execute("select * from information_schema.nothing")
+        .is_err());
+    assert!(db.execute("delete from information_schema.tables").is_err());
+    assert!(db.execute("delete from __tables").is_err());
+    assert!(db.create_table(TableSchema::new("__tables")).is_err());
+    assert!(db.create_table(TableSchema::new("__other")).is_ok());
+}
+
+#[test]
+fn incremental_reads() {
+    use crate::ColumnSchema;
+
+    let mut db = Database::in_memory();
+    let mut schema = TableSchema::new("events");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.record_ingestion_time();
+    db.create_table(schema).unwrap();
+    let events = db.table("events").unwrap();
+    let ids = |result: QueryResult| {
+        result
+            .rows()
+            .iter()
+            .map(|r| r[0].clone())
+            .collect::<Vec<_>>()
+    };
+
+    db.execute("insert into events (id) values (1), (2)")
+        .unwrap();
+    let first = events.ingestion_watermark().unwrap().unwrap();
+    let result = db
+        .execute("select id from events where _ingested_at <= watermark")
+        .unwrap();
+    assert_eq!(ids(result), [RawValue::U64(1), RawValue::U64(2)]);
+
+    db.execute("insert into events (id, _ingested_at) values (3, 0)")
+        .unwrap();
+    let result = db
+        .execute(&format!(
+            "select id from events where _ingested_at > {first} and _ingested_at <= watermark"
+        ))
+        .unwrap();
+    assert_eq!(ids(result), [RawValue::U64(3)]);
+    let result = db
+        .execute(&format!(
+            "delete from events where _ingested_at <= {first} returning id"
+        ))
+        .unwrap();
+    assert_eq!(ids(result), [RawValue::U64(1), RawValue::U64(2)]);
+
+    assert!(db
+        .execute("select * from events where _ingested_at < 'soon'")
+        .is_err());
+    assert!(db
+        .execute("select * from information_schema.tables where columns <= watermark")
+        .is_err());
+}
+
+#[test]
+fn join_tables() {
+    use crate::ColumnSchema;
+
+    let mut db = Database::in_memory();
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(ColumnSchema::<u64>::new("age").raw());
+    db.create_table(schema).unwrap();
+    let mut schema = TableSchema::new("visits");
+    schema.add_primary(
+        ColumnSchema::<String>::new("place")
+            .raw()
+            .chain(ColumnSchema::<String>::new("name").raw()),
+    );
+    schema.add_sum(ColumnSchema::<u64>::new("times").raw());
+    db.create_table(schema).unwrap();
+    db.execute("insert into people (name, age) values ('David', 48), ('Alice', 30), ('Bob', 7)")
+        .unwrap();
+    db.execute(
+        "insert into visits (place, name, times) values
+            ('park', 'Bob', 3), ('park', 'David', 1), ('zoo', 'Bob', 2), ('zoo', 'Eve', 9)",
+    )
+    .unwrap();
+
+    let rows = |result: QueryResult| {
+        result
+            .rows()
+            .iter()
+            .map(|r| {
+                r.iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+    };
+    let result = db
+        .execute("select * from people p join visits v on p.name = v.name where times > 1")
+        .unwrap();
+    assert_eq!(
+        result.columns(),
+        ["p.name", "p.age", "v.place", "v.name", "v.times"]
+    );
+    assert_eq!(
+        rows(result),
+        ["'Bob' 7 'park' 'Bob' 3", "'Bob' 7 'zoo' 'Bob' 2"]
+    );
+    let result = db
+        .execute(
+            "select place, people.name, age from visits inner join people on name = people.name",
+        )
+        .unwrap_err();
+    assert_eq!(
+        result.to_string(),
+        "Query error: column name is in both joined tables"
+    );
+    let result = db
+        .execute("select place, people.name, age from visits join people on visits.name = people.name and place = 'zoo'")
+        .unwrap_err();
+    assert!(result.to_string().contains("expected a name"));
+    let result = db
+        .execute("select place, p.name, age from visits v join people p on p.name = v.name where age < 40 or place = 'zoo'")
+        .unwrap();
+    assert_eq!(result.columns(), ["place", "p.name", "age"]);
+    assert_eq!(rows(result), ["'park' 'Bob' 7", "'zoo' 'Bob' 7"]);
+
+    // The catalog can be joined with itself.
+    let result = db
+        .execute(
+            "select t.table_name, column_name, conflict_policy
+            from information_schema.tables t
+            join information_schema.columns c on t.table_name = c.table_name
+            where kind = 'U64'",
+        )
+        .unwrap();
+    assert_eq!(
+        rows(result),
+        ["'people' 'age' 'Aggregate'", "'visits' 'times' 'Aggregate'"]
+    );
+
+    for bad in [
+        "select * from people join visits on age = place",
+        "select * from people join visits on age = people.age",
+        "select height from people p join visits v on p.name = v.name",
+        "select * from people p join visits v on p.name = v.name where v.age = 1",
+        "select * from people p join visits v on p.name = v.name where times = watermark",
+    ] {
+        assert!(db.execute(bad).is_err(), "{bad}");
+    }
+
+    // A single table may also have an alias.
+    let result = db
+        .execute("select p.name from people as p where p.age between 10 and 40")
+        .unwrap();
+    assert_eq!(rows(result), ["'Alice'"]);
+}
+
+#[test]
+fn json_paths() {
+    use crate::ColumnSchema;
+
+    let mut db = Database::in_memory();
+    let mut schema = TableSchema::new("events");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(ColumnSchema::<String>::new("doc").raw());
+    db.create_table(schema).unwrap();
+    db.execute(
+        r#"insert into events (id, doc) values
+            (1, '{"user": {"name": "Bob", "tags": ["a", "b"]}}'),
+            (2, '{"user": {"name": "Eve"}, "n": 3}')"#,
+    )
+    .unwrap();
+
+    let rows = |result: QueryResult| {
+        result
+            .rows()
+            .iter()
+            .map(|r| {
+                r.iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+    };
+    let result = db
+        .execute("select id, doc->'user'->'name', e.doc->'user'->'tags'->1 from events e")
+        .unwrap();
+    assert_eq!(
+        result.columns(),
+        ["id", "doc->'user'->'name'", "doc->'user'->'tags'->1"]
+    );
+    assert_eq!(rows(result), [r#"1 '"Bob"' '"b"'"#, r#"2 '"Eve"' 'null'"#]);
+    let result = db
+        .execute("select doc->'n', doc->'user' from events where id = 2")
+        .unwrap();
+    assert_eq!(rows(result), [r#"'3' '{"name": "Eve"}'"#]);
+
+    db.execute("insert into events (id, doc) values (3, 'not json')")
+        .unwrap();
+    for bad in ["select doc->'n' from events", "select id->'n' from events"] {
+        let error = db.execute(bad).unwrap_err();
+        assert!(error.to_string().contains("does not hold JSON"), "{bad}");
+    }
+}
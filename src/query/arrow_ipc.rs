@@ -0,0 +1,358 @@
+//! Writing rows as an Apache Arrow IPC stream, which the Arrow libraries of
+//! Python, JavaScript and others read straight into columns.
+//!
+//! The stream is a schema message followed by record batches, and ends with
+//! a message of zero length.  Each message is a flatbuffer describing it,
+//! followed by the buffers of its columns.  The flatbuffers are built by
+//! hand, writing each table before the strings, vectors and tables it
+//! refers to, since offsets only point forwards.
+
+use std::io::Write;
+
+use super::QueryResult;
+use crate::{RawKind, RawValue};
+
+/// The most rows in a record batch
+const BATCH_ROWS: usize = 1 << 16;
+
+/// The version of the metadata, V5
+const METADATA_VERSION: i16 = 4;
+
+/// The kinds of messages, from `MessageHeader` in `Message.fbs`
+const SCHEMA: u8 = 1;
+const RECORD_BATCH: u8 = 3;
+
+/// The types of arrays, from `Type` in `Schema.fbs`
+const INT: u8 = 2;
+const BINARY: u8 = 4;
+const UTF8: u8 = 5;
+const BOOL: u8 = 6;
+
+/// `len` rounded up to a multiple of `align`
+fn padded(len: usize, align: usize) -> usize {
+    len + (align - len % align) % align
+}
+
+/// A flatbuffer object
+enum Object {
+    /// The fields of a table, by their ids
+    Table(Vec<(u16, Field)>),
+    String(String),
+    Tables(Vec<Object>),
+    /// A vector of structs of two longs, which is what a `FieldNode` and a
+    /// `Buffer` both are
+    Pairs(Vec<[i64; 2]>),
+}
+
+/// A field of a flatbuffer table
+enum Field {
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Offset(Object),
+}
+
+/// A flatbuffer being written from the front
+#[derive(Default)]
+struct Flatbuffer {
+    bytes: Vec<u8>,
+}
+
+impl Flatbuffer {
+    /// The bytes of a flatbuffer whose root is `root`, padded to eight bytes
+    fn of(root: Object) -> Vec<u8> {
+        let mut fb = Flatbuffer::default();
+        fb.bytes.extend([0; 4]);
+        fb.point(0, root);
+        fb.pad(8);
+        fb.bytes
+    }
+
+    fn pad(&mut self, align: usize) {
+        self.bytes.resize(padded(self.bytes.len(), align), 0);
+    }
+
+    /// Write `object`, and point the offset at `at` to it
+    fn point(&mut self, at: usize, object: Object) {
+        let position = self.write(object);
+        let offset = (position - at) as u32;
+        self.bytes[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    /// Write `object`, returning where it starts
+    fn write(&mut self, object: Object) -> usize {
+        match object {
+            Object::String(s) => {
+                self.pad(4);
+                let start = self.bytes.len();
+                self.bytes.extend((s.len() as u32).to_le_bytes());
+                self.bytes.extend(s.as_bytes());
+                self.bytes.push(0);
+                start
+            }
+            Object::Pairs(pairs) => {
+                // The structs are aligned to eight bytes, after the length.
+                while self.bytes.len() % 8 != 4 {
+                    self.bytes.push(0);
+                }
+                let start = self.bytes.len();
+                self.bytes.extend((pairs.len() as u32).to_le_bytes());
+                for v in pairs.iter().flatten() {
+                    self.bytes.extend(v.to_le_bytes());
+                }
+                start
+            }
+            Object::Tables(tables) => {
+                self.pad(4);
+                let start = self.bytes.len();
+                self.bytes.extend((tables.len() as u32).to_le_bytes());
+                let offsets = self.bytes.len();
+                self.bytes.resize(offsets + 4 * tables.len(), 0);
+                for (i, table) in tables.into_iter().enumerate() {
+                    self.point(offsets + 4 * i, table);
+                }
+                start
+            }
+            Object::Table(fields) => {
+                let num_fields = fields.iter().map(|(id, _)| *id as usize + 1).max();
+                let vtable_size = 4 + 2 * num_fields.unwrap_or(0);
+                self.pad(4);
+                let vtable = self.bytes.len();
+                self.bytes.resize(vtable + vtable_size, 0);
+                self.pad(4);
+                let start = self.bytes.len();
+                self.bytes.extend(((start - vtable) as i32).to_le_bytes());
+                let mut entries = vec![0u16; num_fields.unwrap_or(0)];
+                let mut children = Vec::new();
+                for (id, field) in fields {
+                    let (align, bytes) = match field {
+                        Field::U8(v) => (1, v.to_le_bytes().to_vec()),
+                        Field::I16(v) => (2, v.to_le_bytes().to_vec()),
+                        Field::I32(v) => (4, v.to_le_bytes().to_vec()),
+                        Field::I64(v) => (8, v.to_le_bytes().to_vec()),
+                        Field::Offset(child) => {
+                            children.push((id, child));
+                            (4, vec![0; 4])
+                        }
+                    };
+                    self.pad(align);
+                    entries[id as usize] = (self.bytes.len() - start) as u16;
+                    self.bytes.extend(bytes);
+                }
+                let mut header = (vtable_size as u16).to_le_bytes().to_vec();
+                header.extend(((self.bytes.len() - start) as u16).to_le_bytes());
+                header.extend(entries.iter().flat_map(|e| e.to_le_bytes()));
+                self.bytes[vtable..vtable + vtable_size].copy_from_slice(&header);
+                for (id, child) in children {
+                    self.point(start + entries[id as usize] as usize, child);
+                }
+                start
+            }
+        }
+    }
+}
+
+/// The type of an array, as the kind of `Type` and its table
+fn array_type(kind: RawKind, utf8: bool) -> (u8, Object) {
+    match kind {
+        RawKind::U64 => (
+            INT,
+            Object::Table(vec![(0, Field::I32(64)), (1, Field::U8(0))]),
+        ),
+        RawKind::Bool => (BOOL, Object::Table(Vec::new())),
+        RawKind::Bytes if utf8 => (UTF8, Object::Table(Vec::new())),
+        RawKind::Bytes => (BINARY, Object::Table(Vec::new())),
+    }
+}
+
+/// Write a message with its flatbuffer and body
+fn write_message(
+    out: &mut impl Write,
+    header: u8,
+    message: Object,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let metadata = Flatbuffer::of(Object::Table(vec![
+        (0, Field::I16(METADATA_VERSION)),
+        (1, Field::U8(header)),
+        (2, Field::Offset(message)),
+        (3, Field::I64(body.len() as i64)),
+    ]));
+    out.write_all(&u32::MAX.to_le_bytes())?;
+    out.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    out.write_all(&metadata)?;
+    out.write_all(body)
+}
+
+/// The buffers of the columns of a record batch, each padded to eight bytes
+#[derive(Default)]
+struct Body {
+    bytes: Vec<u8>,
+    buffers: Vec<[i64; 2]>,
+}
+
+impl Body {
+    fn push(&mut self, buffer: &[u8]) {
+        self.buffers
+            .push([self.bytes.len() as i64, buffer.len() as i64]);
+        self.bytes.extend(buffer);
+        self.bytes.resize(padded(self.bytes.len(), 8), 0);
+    }
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Write `rows`, whose values are in the order of `columns`, as an Arrow
+/// IPC stream, which the Arrow libraries of Python, JavaScript and others
+/// read straight into columns.
+///
+/// A column of `u64` is a `UInt64` array, one of bools a `Bool` array, and
+/// one of bytes a `Utf8` array if every value is UTF-8 and a `Binary` array
+/// otherwise, with no nulls.  The columns of a result with no rows are
+/// `Binary` arrays.  The rows are split into record batches of up to 65536
+/// rows.  This fails if a column holds values of more than one kind.
+pub fn write_arrow_ipc(
+    out: &mut impl Write,
+    columns: &[String],
+    rows: &[Vec<RawValue>],
+) -> std::io::Result<()> {
+    let mut kinds = Vec::with_capacity(columns.len());
+    for (i, name) in columns.iter().enumerate() {
+        let kind = rows.first().map_or(RawKind::Bytes, |r| r[i].kind());
+        if rows.iter().any(|r| r[i].kind() != kind) {
+            return Err(invalid(format!(
+                "column {name} holds values of several kinds"
+            )));
+        }
+        let utf8 = rows.iter().all(|r| match &r[i] {
+            RawValue::Bytes(b) => std::str::from_utf8(b).is_ok(),
+            _ => true,
+        });
+        kinds.push((kind, utf8 && !rows.is_empty()));
+    }
+    let fields = columns
+        .iter()
+        .zip(kinds.iter())
+        .map(|(name, &(kind, utf8))| {
+            let (type_type, array_type) = array_type(kind, utf8);
+            Object::Table(vec![
+                (0, Field::Offset(Object::String(name.clone()))),
+                (1, Field::U8(0)),
+                (2, Field::U8(type_type)),
+                (3, Field::Offset(array_type)),
+                (5, Field::Offset(Object::Tables(Vec::new()))),
+            ])
+        })
+        .collect();
+    let schema = Object::Table(vec![(1, Field::Offset(Object::Tables(fields)))]);
+    write_message(out, SCHEMA, schema, &[])?;
+
+    for batch in rows.chunks(BATCH_ROWS) {
+        let mut body = Body::default();
+        for (i, &(kind, _)) in kinds.iter().enumerate() {
+            // With no nulls, the validity bitmap may be left out.
+            body.push(&[]);
+            let values = batch.iter().map(|r| &r[i]);
+            match kind {
+                RawKind::U64 => {
+                    let bytes = values
+                        .flat_map(|v| match v {
+                            RawValue::U64(v) => v.to_le_bytes(),
+                            _ => unreachable!("checked above"),
+                        })
+                        .collect::<Vec<_>>();
+                    body.push(&bytes);
+                }
+                RawKind::Bool => {
+                    let mut bits = vec![0u8; padded(batch.len(), 8) / 8];
+                    for (j, v) in values.enumerate() {
+                        if v == &RawValue::Bool(true) {
+                            bits[j / 8] |= 1 << (j % 8);
+                        }
+                    }
+                    body.push(&bits);
+                }
+                RawKind::Bytes => {
+                    let (mut offsets, mut data) = (0i32.to_le_bytes().to_vec(), Vec::new());
+                    for v in values {
+                        if let RawValue::Bytes(b) = v {
+                            data.extend(b);
+                        }
+                        let end = i32::try_from(data.len()).map_err(|_| {
+                            invalid(format!("column {} holds too many bytes", columns[i]))
+                        })?;
+                        offsets.extend(end.to_le_bytes());
+                    }
+                    body.push(&offsets);
+                    body.push(&data);
+                }
+            }
+        }
+        let nodes = kinds.iter().map(|_| [batch.len() as i64, 0]).collect();
+        let message = Object::Table(vec![
+            (0, Field::I64(batch.len() as i64)),
+            (1, Field::Offset(Object::Pairs(nodes))),
+            (2, Field::Offset(Object::Pairs(body.buffers))),
+        ]);
+        write_message(out, RECORD_BATCH, message, &body.bytes)?;
+    }
+    out.write_all(&u32::MAX.to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())
+}
+
+impl QueryResult {
+    /// Write the rows as an Arrow IPC stream, see [`write_arrow_ipc`]
+    pub fn write_arrow_ipc(&self, out: &mut impl Write) -> std::io::Result<()> {
+        write_arrow_ipc(out, &self.columns, &self.rows)
+    }
+}
+
+#[test]
+fn arrow_stream() {
+    let columns = ["name", "age", "happy", "blob"].map(|c| c.to_string());
+    let rows = [("Ann", 30, true), ("Bo", 4, false), ("Cy", 70, true)]
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, age, happy))| {
+            vec![
+                RawValue::Bytes(name.as_bytes().to_vec()),
+                RawValue::U64(age),
+                RawValue::Bool(happy),
+                RawValue::Bytes(vec![0xff; i]),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let mut out = Vec::new();
+    write_arrow_ipc(&mut out, &columns, &rows).unwrap();
+    // Each message is a marker, the length of its flatbuffer, the
+    // flatbuffer and then its body, which holds the buffers of the columns.
+    let mut messages = Vec::new();
+    let mut rest = &out[..];
+    while rest[..4] == [0xff; 4] {
+        let length = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        assert_eq!(length % 8, 0);
+        messages.push(length);
+        rest = &rest[8 + length..];
+        if length == 0 {
+            break;
+        }
+        if messages.len() == 2 {
+            let ages = [30u64, 4, 70].map(|a| a.to_le_bytes()).concat();
+            assert!(rest.windows(ages.len()).any(|w| w == ages));
+            assert!(rest.windows(7).any(|w| w == b"AnnBoCy"));
+            rest = &rest[rest.len() - 8..];
+        }
+    }
+    assert_eq!(messages.len(), 3);
+    assert!(rest.is_empty());
+
+    let mixed = vec![vec![RawValue::U64(1)], vec![RawValue::Bool(true)]];
+    let error = write_arrow_ipc(&mut Vec::new(), &columns[..1], &mixed).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "column name holds values of several kinds"
+    );
+}
@@ -0,0 +1,173 @@
+//! The `information_schema` tables, which describe the database.
+//!
+//! These tables are not stored anywhere.  Each statement that reads one
+//! builds its rows from the schemas of the tables and from their manifests.
+
+use crate::column::encoding::StorageError;
+use crate::{ColumnSchema, Database, RawRow, RawValue, TableSchema};
+
+fn bytes(s: impl Into<String>) -> RawValue {
+    RawValue::Bytes(s.into().into_bytes())
+}
+
+/// The tables that may also be named with a `__` prefix
+const TABLES: &[&str] = &["tables", "columns", "segments", "statistics", "queries"];
+
+/// The name of the `information_schema` table called `name`, without its
+/// prefix, if `name` is that of one
+pub(super) fn short_name(name: &str) -> Option<&str> {
+    name.strip_prefix("information_schema.")
+        .or_else(|| name.strip_prefix("__").filter(|n| TABLES.contains(n)))
+}
+
+/// The schema and rows of the table called `name`, if it is one of the
+/// `information_schema` tables.
+pub(super) fn read(
+    db: &Database,
+    name: &str,
+) -> Result<Option<(TableSchema, Vec<RawRow>)>, StorageError> {
+    let Some(short_name) = short_name(name) else {
+        return Ok(None);
+    };
+    let mut schema = TableSchema::new(name);
+    let mut rows: Vec<RawRow> = Vec::new();
+    match short_name {
+        "tables" => {
+            schema.add_primary(ColumnSchema::<String>::new("table_name").raw());
+            schema.add_max(
+                ColumnSchema::<u64>::new("columns")
+                    .raw()
+                    .chain(ColumnSchema::<String>::new("time_column").raw())
+                    .chain(ColumnSchema::<String>::new("conflict_policy").raw()),
+            );
+            for s in db.schemas() {
+                let time_column = s
+                    .time_index()
+                    .and_then(|i| s.raw_columns().nth(i))
+                    .map(|c| c.name().to_string())
+                    .unwrap_or_default();
+                rows.push(
+                    [
+                        bytes(s.name()),
+                        RawValue::U64(s.raw_columns().count() as u64),
+                        bytes(time_column),
+                        bytes(format!("{:?}", s.conflict_policy())),
+                    ]
+                    .into_iter()
+                    .collect(),
+                );
+            }
+        }
+        "columns" => {
+            schema.add_primary(
+                ColumnSchema::<String>::new("table_name")
+                    .raw()
+                    .chain(ColumnSchema::<u64>::new("ordinal").raw()),
+            );
+            schema.add_max(
+                ColumnSchema::<String>::new("column_name")
+                    .raw()
+                    .chain(ColumnSchema::<String>::new("kind").raw())
+                    .chain(ColumnSchema::<String>::new("aggregation").raw())
+                    .chain(ColumnSchema::<bool>::new("indexed").raw()),
+            );
+            for s in db.schemas() {
+                let ranges = s.aggregation_ranges();
+                for (i, c) in s.raw_columns().enumerate() {
+                    let aggregation = ranges
+                        .iter()
+                        .find(|(_, r)| r.contains(&i))
+                        .map(|(a, _)| format!("{:?}", a.aggregation()))
+                        .unwrap_or_else(|| "Primary".to_string());
+                    rows.push(
+                        [
+                            bytes(s.name()),
+                            RawValue::U64(i as u64),
+                            bytes(c.display_name()),
+                            bytes(format!("{:?}", c.kind())),
+                            bytes(aggregation),
+                            RawValue::Bool(s.is_indexed(c)),
+                        ]
+                        .into_iter()
+                        .collect(),
+                    );
+                }
+            }
+        }
+        "segments" => {
+            schema.add_primary(
+                ColumnSchema::<String>::new("table_name")
+                    .raw()
+                    .chain(ColumnSchema::<u64>::new("segment").raw()),
+            );
+            schema.add_max(
+                ColumnSchema::<u64>::new("rows")
+                    .raw()
+                    .chain(ColumnSchema::<u64>::new("max_time").raw())
+                    .chain(ColumnSchema::<bool>::new("inline").raw()),
+            );
+            for s in db.schemas() {
+                for segment in db.table(s.name())?.segments()? {
+                    rows.push(
+                        [
+                            bytes(s.name()),
+                            RawValue::U64(segment.id),
+                            RawValue::U64(segment.num_rows),
+                            RawValue::U64(segment.max_time.unwrap_or_default()),
+                            RawValue::Bool(segment.is_inline()),
+                        ]
+                        .into_iter()
+                        .collect(),
+                    );
+                }
+            }
+        }
+        "statistics" => {
+            schema.add_primary(ColumnSchema::<String>::new("table_name").raw());
+            schema.add_max(
+                ColumnSchema::<u64>::new("rows")
+                    .raw()
+                    .chain(ColumnSchema::<u64>::new("stored_rows").raw())
+                    .chain(ColumnSchema::<u64>::new("segments").raw()),
+            );
+            for s in db.schemas() {
+                let table = db.table(s.name())?;
+                let segments = table.segments()?;
+                // Only the primary key is needed to count the merged rows.
+                let merged = table.read_projected(&[])?.to_rows()?.len();
+                rows.push(
+                    [
+                        bytes(s.name()),
+                        RawValue::U64(merged as u64),
+                        RawValue::U64(segments.iter().map(|s| s.num_rows).sum()),
+                        RawValue::U64(segments.len() as u64),
+                    ]
+                    .into_iter()
+                    .collect(),
+                );
+            }
+        }
+        "queries" => {
+            schema.add_primary(ColumnSchema::<u64>::new("query_id").raw());
+            schema.add_max(
+                ColumnSchema::<String>::new("sql")
+                    .raw()
+                    .chain(ColumnSchema::<u64>::new("elapsed_ms").raw()),
+            );
+            for (id, elapsed, sql) in db.running().list() {
+                rows.push(
+                    [
+                        RawValue::U64(id),
+                        bytes(sql),
+                        RawValue::U64(elapsed.as_millis() as u64),
+                    ]
+                    .into_iter()
+                    .collect(),
+                );
+            }
+        }
+        _ => return Err(StorageError::Query(format!("no table {name}"))),
+    }
+    rows.sort();
+    Ok(Some((schema, rows)))
+}
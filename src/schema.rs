@@ -1,17 +1,46 @@
-use std::collections::BTreeSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 
-use crate::lens::{ColumnId, Lens, LensId, RawValues, TableId};
-use crate::value::RawValue;
-use crate::LensError;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+use crate::column::digest::{fnv, FNV_OFFSET};
+use crate::column::ColumnFormat;
+use crate::lens::{
+    decimal_scale, raw_values, AggregationId, Average, ColumnId, Lens, LensId, QuantileSketch,
+    RawValues, ShardSequence, TableId,
+};
+use crate::value::{RawKind, RawValue};
+use crate::{LensError, RawRow};
+
+pub(crate) mod catalog;
 
 /// A kind of column to aggregate
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(u64)]
 pub enum Aggregation {
+    /// Not aggregated, part of the primary key
     None = 0,
+    /// Keep the smallest value
     Min = 1,
+    /// Keep the largest value
     Max = 2,
+    /// Add the values
     Sum = 3,
+    /// Count the rows, see [`TableSchema::add_count`]
+    Count = 4,
+    /// Add the values and count them, to average them, see
+    /// [`TableSchema::add_avg`]
+    Avg = 5,
+    /// Keep the values of the row with the smallest clock, see
+    /// [`TableSchema::add_first_by`]
+    FirstBy = 6,
+    /// Keep the values of the row with the largest clock, see
+    /// [`TableSchema::add_last_by`]
+    LastBy = 7,
+    /// Merge sketches of the distribution of values, see
+    /// [`TableSchema::add_sketch`]
+    Sketch = 8,
 }
 impl Lens for Aggregation {
     const RAW_KINDS: &'static [crate::value::RawKind] = u64::RAW_KINDS;
@@ -36,6 +65,16 @@ impl TryFrom<RawValues> for Aggregation {
             Ok(Aggregation::Min)
         } else if v == Aggregation::Sum as u64 {
             Ok(Aggregation::Sum)
+        } else if v == Aggregation::Count as u64 {
+            Ok(Aggregation::Count)
+        } else if v == Aggregation::Avg as u64 {
+            Ok(Aggregation::Avg)
+        } else if v == Aggregation::FirstBy as u64 {
+            Ok(Aggregation::FirstBy)
+        } else if v == Aggregation::LastBy as u64 {
+            Ok(Aggregation::LastBy)
+        } else if v == Aggregation::Sketch as u64 {
+            Ok(Aggregation::Sketch)
         } else {
             Err(LensError::InvalidValue {
                 value: format!("Unexpected: {v}"),
@@ -44,6 +83,325 @@ impl TryFrom<RawValues> for Aggregation {
     }
 }
 
+/// What to do with rows that share a primary key
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u64)]
+pub enum ConflictPolicy {
+    /// Combine the rows using the aggregations of the schema
+    #[default]
+    Aggregate = 0,
+    /// Refuse to save, read or compact the rows
+    Error = 1,
+    /// Keep the row that was saved last
+    LastWriteWins = 2,
+    /// Keep the row that was saved first
+    FirstWriteWins = 3,
+}
+impl Lens for ConflictPolicy {
+    const RAW_KINDS: &'static [crate::value::RawKind] = u64::RAW_KINDS;
+    const EXPECTED: &'static str = "An integer indicating which conflict policy";
+    const LENS_ID: LensId = LensId(*b"__ConflictPolicy");
+    const NAMES: &'static [&'static str] = &[""];
+}
+impl From<ConflictPolicy> for RawValues {
+    fn from(p: ConflictPolicy) -> Self {
+        (p as u64).into()
+    }
+}
+impl TryFrom<RawValues> for ConflictPolicy {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, LensError> {
+        let v = u64::try_from(value)?;
+        [
+            ConflictPolicy::Aggregate,
+            ConflictPolicy::Error,
+            ConflictPolicy::LastWriteWins,
+            ConflictPolicy::FirstWriteWins,
+        ]
+        .into_iter()
+        .find(|p| *p as u64 == v)
+        .ok_or_else(|| LensError::InvalidValue {
+            value: format!("Unexpected: {v}"),
+        })
+    }
+}
+
+/// What to do when summing a column overflows a `u64`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u64)]
+pub enum SumOverflow {
+    /// Wrap around past `u64::MAX`, which is how sums have always behaved
+    #[default]
+    Wrap = 0,
+    /// Refuse to save, read or compact the rows
+    Error = 1,
+    /// Stop at `u64::MAX`
+    Saturate = 2,
+    /// Store the sum as a `u128`, in two raw columns
+    Widen = 3,
+}
+impl Lens for SumOverflow {
+    const RAW_KINDS: &'static [crate::value::RawKind] = u64::RAW_KINDS;
+    const EXPECTED: &'static str = "An integer indicating which overflow policy";
+    const LENS_ID: LensId = LensId(*b"__SumOverflow___");
+    const NAMES: &'static [&'static str] = &[""];
+}
+impl From<SumOverflow> for RawValues {
+    fn from(o: SumOverflow) -> Self {
+        (o as u64).into()
+    }
+}
+impl TryFrom<RawValues> for SumOverflow {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, LensError> {
+        let v = u64::try_from(value)?;
+        [
+            SumOverflow::Wrap,
+            SumOverflow::Error,
+            SumOverflow::Saturate,
+            SumOverflow::Widen,
+        ]
+        .into_iter()
+        .find(|o| *o as u64 == v)
+        .ok_or_else(|| LensError::InvalidValue {
+            value: format!("Unexpected: {v}"),
+        })
+    }
+}
+
+/// A default computed for each row saved holding the constant default of a
+/// column, in place of that constant
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u64)]
+pub enum ComputedDefault {
+    /// The constant default is kept
+    #[default]
+    Constant = 0,
+    /// The time the row is saved, see [`TableSchema::default_to_now`]
+    Now = 1,
+    /// The next value of a counter, see [`TableSchema::auto_increment`]
+    AutoIncrement = 2,
+}
+impl Lens for ComputedDefault {
+    const RAW_KINDS: &'static [crate::value::RawKind] = u64::RAW_KINDS;
+    const EXPECTED: &'static str = "An integer indicating how a default is computed";
+    const LENS_ID: LensId = LensId(*b"ComputedDefault_");
+    const NAMES: &'static [&'static str] = &[""];
+}
+impl From<ComputedDefault> for RawValues {
+    fn from(c: ComputedDefault) -> Self {
+        (c as u64).into()
+    }
+}
+impl TryFrom<RawValues> for ComputedDefault {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, LensError> {
+        let v = u64::try_from(value)?;
+        [
+            ComputedDefault::Constant,
+            ComputedDefault::Now,
+            ComputedDefault::AutoIncrement,
+        ]
+        .into_iter()
+        .find(|c| *c as u64 == v)
+        .ok_or_else(|| LensError::InvalidValue {
+            value: format!("Unexpected: {v}"),
+        })
+    }
+}
+
+/// How the bytes of a column are ordered, both in the primary key and when
+/// choosing the greatest or least value under max or min aggregation, see
+/// [`TableSchema::set_collation`].
+///
+/// Values that are equal under the ordering are still different values,
+/// ordered by their bytes, so only equal values share a primary key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u64)]
+pub enum Collation {
+    /// Ordered by their bytes
+    #[default]
+    Binary = 0,
+    /// Ordered by their bytes with ASCII letters in lower case
+    CaseInsensitive = 1,
+    /// Ordered by their bytes, except that runs of ASCII digits are ordered
+    /// by the numbers they spell, so `"9"` comes before `"10"`
+    Numeric = 2,
+}
+impl Collation {
+    /// Compare two values under this ordering
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let collated = match self {
+            Collation::Binary => Ordering::Equal,
+            Collation::CaseInsensitive => a
+                .iter()
+                .map(u8::to_ascii_lowercase)
+                .cmp(b.iter().map(u8::to_ascii_lowercase)),
+            Collation::Numeric => compare_numeric(a, b),
+        };
+        collated.then_with(|| a.cmp(b))
+    }
+}
+impl Lens for Collation {
+    const RAW_KINDS: &'static [crate::value::RawKind] = u64::RAW_KINDS;
+    const EXPECTED: &'static str = "An integer indicating how bytes are ordered";
+    const LENS_ID: LensId = LensId(*b"__Collation_____");
+    const NAMES: &'static [&'static str] = &[""];
+}
+impl From<Collation> for RawValues {
+    fn from(c: Collation) -> Self {
+        (c as u64).into()
+    }
+}
+impl TryFrom<RawValues> for Collation {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, LensError> {
+        let v = u64::try_from(value)?;
+        [
+            Collation::Binary,
+            Collation::CaseInsensitive,
+            Collation::Numeric,
+        ]
+        .into_iter()
+        .find(|c| *c as u64 == v)
+        .ok_or_else(|| LensError::InvalidValue {
+            value: format!("Unexpected: {v}"),
+        })
+    }
+}
+
+/// Compare bytes with runs of digits ordered by their numbers
+fn compare_numeric(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    fn digits(bytes: &[u8]) -> (&[u8], &[u8]) {
+        let end = bytes
+            .iter()
+            .position(|c| !c.is_ascii_digit())
+            .unwrap_or(bytes.len());
+        let (digits, rest) = bytes.split_at(end);
+        let start = digits.iter().position(|&c| c != b'0').unwrap_or(end);
+        (&digits[start..], rest)
+    }
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let ((x, rest_a), (y, rest_b)) = (digits(a), digits(b));
+                let order = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if order.is_ne() {
+                    return order;
+                }
+                (a, b) = (rest_a, rest_b);
+            }
+            (Some(x), Some(y)) if x != y => return x.cmp(y),
+            _ => (a, b) = (&a[1..], &b[1..]),
+        }
+    }
+}
+
+/// Which formats the raw columns of a column are encoded in, as segments
+/// are saved, see [`TableSchema::set_encoding`].
+///
+/// Each is a hint, and a column whose values no format of its encoding can
+/// hold, such as one with runs under [`Encoding::Plain`], is encoded as
+/// under [`Encoding::Auto`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u64)]
+pub enum Encoding {
+    /// In whichever format encodes a sample of the values smallest
+    #[default]
+    Auto = 0,
+    /// In runs of equal values, each stored once
+    Runs = 1,
+    /// Each value stored as it is, for values that are seldom repeated
+    Plain = 2,
+    /// Bytes as codes into a sorted dictionary of the distinct values
+    Dictionary = 3,
+    /// Numbers bit packed in blocks, each only as wide as it needs
+    BitPacked = 4,
+    /// Only the values that differ from the most common one
+    Sparse = 5,
+}
+impl Encoding {
+    /// The formats of the encoding, of whichever kinds they hold
+    pub fn formats(self) -> &'static [ColumnFormat] {
+        match self {
+            Encoding::Auto => &[],
+            Encoding::Runs => &[
+                ColumnFormat::Bools,
+                ColumnFormat::U8Runs,
+                ColumnFormat::U16Runs,
+                ColumnFormat::U32Runs,
+                ColumnFormat::VarintRuns,
+                ColumnFormat::BytesRuns,
+                ColumnFormat::FixedBytesRuns,
+            ],
+            Encoding::Plain => &[
+                ColumnFormat::Bitmap,
+                ColumnFormat::U8,
+                ColumnFormat::U16,
+                ColumnFormat::U32,
+                ColumnFormat::Varint,
+                ColumnFormat::Bytes,
+                ColumnFormat::FixedBytes,
+            ],
+            Encoding::Dictionary => &[ColumnFormat::Dictionary],
+            Encoding::BitPacked => &[ColumnFormat::BitPacked],
+            Encoding::Sparse => &[ColumnFormat::SparseU64, ColumnFormat::SparseBytes],
+        }
+    }
+}
+impl Lens for Encoding {
+    const RAW_KINDS: &'static [crate::value::RawKind] = u64::RAW_KINDS;
+    const EXPECTED: &'static str = "An integer indicating how a column is encoded";
+    const LENS_ID: LensId = LensId(*b"__Encoding______");
+    const NAMES: &'static [&'static str] = &[""];
+}
+impl From<Encoding> for RawValues {
+    fn from(e: Encoding) -> Self {
+        (e as u64).into()
+    }
+}
+impl TryFrom<RawValues> for Encoding {
+    type Error = LensError;
+    fn try_from(value: RawValues) -> Result<Self, LensError> {
+        let v = u64::try_from(value)?;
+        [
+            Encoding::Auto,
+            Encoding::Runs,
+            Encoding::Plain,
+            Encoding::Dictionary,
+            Encoding::BitPacked,
+            Encoding::Sparse,
+        ]
+        .into_iter()
+        .find(|e| *e as u64 == v)
+        .ok_or_else(|| LensError::InvalidValue {
+            value: format!("Unexpected: {v}"),
+        })
+    }
+}
+
+/// A constraint on the values of a column, checked as rows are saved and
+/// as the table is compacted, see [`TableSchema::add_constraint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Constraint {
+    /// Every row holds a value other than the default of the column, which
+    /// stands in for a missing value
+    NotNull,
+    /// No two rows hold the same value, other than the default of the column
+    Unique,
+}
+impl std::fmt::Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constraint::NotNull => write!(f, "NOT NULL"),
+            Constraint::Unique => write!(f, "UNIQUE"),
+        }
+    }
+}
+
 /// A schema for a column
 pub struct ColumnSchema<T> {
     default: T,
@@ -55,13 +413,53 @@ pub struct ColumnSchema<T> {
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct RawColumnSchema {
     default: RawValue,
-    name: &'static str,
+    name: String,
     id: ColumnId,
-    fieldname: &'static str,
+    fieldname: String,
     lens: LensId,
 }
 impl RawColumnSchema {
-    fn display_name(&self) -> String {
+    /// The id of the logical column this is part of
+    pub fn id(&self) -> ColumnId {
+        self.id
+    }
+    /// The name of the logical column this is part of
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The name of the field within the logical column
+    pub fn fieldname(&self) -> &str {
+        &self.fieldname
+    }
+    /// The lens used to interpret the logical column
+    pub fn lens(&self) -> LensId {
+        self.lens
+    }
+    /// The value used when no other is known
+    pub fn default(&self) -> &RawValue {
+        &self.default
+    }
+    /// The kind of values stored in this column
+    pub fn kind(&self) -> RawKind {
+        self.default.kind()
+    }
+    /// A hash of the id, fieldname, lens and kind of the column, which
+    /// together decide how its stored values are read
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let kind = match self.kind() {
+            RawKind::U64 => 0,
+            RawKind::Bool => 1,
+            RawKind::Bytes => 2,
+        };
+        let mut hash = fnv(FNV_OFFSET, &self.id.0);
+        hash = fnv(hash, &(self.fieldname.len() as u64).to_be_bytes());
+        hash = fnv(hash, self.fieldname.as_bytes());
+        hash = fnv(hash, &self.lens.0);
+        fnv(hash, &[kind])
+    }
+    /// The name of the logical column, followed by the field if it has one,
+    /// as in `modified.seconds`
+    pub fn display_name(&self) -> String {
         if self.fieldname.is_empty() {
             self.name.to_owned()
         } else {
@@ -81,9 +479,6 @@ impl std::fmt::Display for RawColumnSchema {
         )
     }
 }
-/// A compound aggregation
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct AggregationId([u8; 16]);
 /// A kind of column to aggregate
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum AggregatingSchema {
@@ -97,8 +492,32 @@ pub enum AggregatingSchema {
         columns: OrderedRawColumns,
         id: AggregationId,
     },
-    /// Summing
-    Sum(OrderedRawColumns),
+    /// Summing a single column, or the two raw columns of a `u128` that is
+    /// widened on overflow
+    Sum {
+        columns: OrderedRawColumns,
+        overflow: SumOverflow,
+    },
+    /// A single column counting the rows
+    Count { columns: OrderedRawColumns },
+    /// The two raw columns of an [`Average`], added up separately
+    Avg { columns: OrderedRawColumns },
+    /// One or more columns, we pick those of the row with the smaller value
+    /// of the clock column, which is one of them
+    FirstBy {
+        columns: OrderedRawColumns,
+        id: AggregationId,
+        clock: ColumnId,
+    },
+    /// One or more columns, we pick those of the row with the larger value of
+    /// the clock column, which is one of them
+    LastBy {
+        columns: OrderedRawColumns,
+        id: AggregationId,
+        clock: ColumnId,
+    },
+    /// A single column of [`QuantileSketch`]es, merged together
+    Sketch { columns: OrderedRawColumns },
 }
 
 impl AggregatingSchema {
@@ -106,30 +525,652 @@ impl AggregatingSchema {
         match self {
             AggregatingSchema::Max { columns, .. } => columns.iter(),
             AggregatingSchema::Min { columns, .. } => columns.iter(),
-            AggregatingSchema::Sum(columns) => columns.iter(),
+            AggregatingSchema::Sum { columns, .. } => columns.iter(),
+            AggregatingSchema::Count { columns } => columns.iter(),
+            AggregatingSchema::Avg { columns } => columns.iter(),
+            AggregatingSchema::FirstBy { columns, .. } => columns.iter(),
+            AggregatingSchema::LastBy { columns, .. } => columns.iter(),
+            AggregatingSchema::Sketch { columns } => columns.iter(),
+        }
+    }
+
+    fn columns_mut(&mut self) -> &mut OrderedRawColumns {
+        match self {
+            AggregatingSchema::Max { columns, .. } => columns,
+            AggregatingSchema::Min { columns, .. } => columns,
+            AggregatingSchema::Sum { columns, .. } => columns,
+            AggregatingSchema::Count { columns } => columns,
+            AggregatingSchema::Avg { columns } => columns,
+            AggregatingSchema::FirstBy { columns, .. } => columns,
+            AggregatingSchema::LastBy { columns, .. } => columns,
+            AggregatingSchema::Sketch { columns } => columns,
+        }
+    }
+
+    pub(crate) fn aggregation(&self) -> Aggregation {
+        match self {
+            AggregatingSchema::Max { .. } => Aggregation::Max,
+            AggregatingSchema::Min { .. } => Aggregation::Min,
+            AggregatingSchema::Sum { .. } => Aggregation::Sum,
+            AggregatingSchema::Count { .. } => Aggregation::Count,
+            AggregatingSchema::Avg { .. } => Aggregation::Avg,
+            AggregatingSchema::FirstBy { .. } => Aggregation::FirstBy,
+            AggregatingSchema::LastBy { .. } => Aggregation::LastBy,
+            AggregatingSchema::Sketch { .. } => Aggregation::Sketch,
+        }
+    }
+
+    /// What to do when the sum overflows, which is only recorded for sums
+    fn overflow(&self) -> SumOverflow {
+        match self {
+            AggregatingSchema::Sum { overflow, .. } => *overflow,
+            _ => SumOverflow::Wrap,
+        }
+    }
+
+    /// The column whose values decide which row wins, which is only
+    /// recorded for first and last by a clock
+    pub(crate) fn clock(&self) -> Option<ColumnId> {
+        match self {
+            AggregatingSchema::FirstBy { clock, .. } | AggregatingSchema::LastBy { clock, .. } => {
+                Some(*clock)
+            }
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> AggregationId {
+        match self {
+            AggregatingSchema::Max { id, .. } => *id,
+            AggregatingSchema::Min { id, .. } => *id,
+            AggregatingSchema::FirstBy { id, .. } => *id,
+            AggregatingSchema::LastBy { id, .. } => *id,
+            AggregatingSchema::Sum { .. }
+            | AggregatingSchema::Count { .. }
+            | AggregatingSchema::Avg { .. }
+            | AggregatingSchema::Sketch { .. } => catalog::NO_GROUP,
         }
     }
 }
 
+/// The name of the column recording when each row was saved
+pub const INGESTED_AT_NAME: &str = "_ingested_at";
+
 type OrderedRawColumns = BTreeSet<(u64, RawColumnSchema)>;
 
 /// The schema of a table
+#[derive(Debug, Clone)]
 pub struct TableSchema {
-    name: &'static str,
+    name: String,
     id: TableId,
     primary: OrderedRawColumns, // must all have AggregationNone
     aggregations: BTreeSet<AggregatingSchema>,
+    time_column: Option<ColumnId>,
+    /// The column holding the shard and sequence number of each row
+    sequence_column: Option<ColumnId>,
+    conflict_policy: ConflictPolicy,
+    /// The columns whose raw columns are indexed in each segment
+    indexes: BTreeSet<ColumnId>,
+    /// The columns whose defaults are computed as rows are saved
+    computed: BTreeMap<ColumnId, ComputedDefault>,
+    /// The constraints on the values of columns
+    constraints: BTreeSet<(ColumnId, Constraint)>,
+    /// The table whose primary keys the values of each column refer to
+    references: BTreeMap<ColumnId, TableId>,
+    /// How the bytes of columns are ordered, unless by their bytes
+    collations: BTreeMap<ColumnId, Collation>,
+    /// The formats columns are encoded in, unless picked automatically
+    encodings: BTreeMap<ColumnId, Encoding>,
+    /// The table this one rolls up, see [`TableSchema::rollup`]
+    rollup_of: Option<TableId>,
+    /// How long rows are kept, by the time in their time column
+    retention: Option<Duration>,
+    /// The column whose hash picks the partition of each row, and the number
+    /// of partitions
+    partitioning: Option<(ColumnId, u64)>,
+    /// Whether inserted and deleted rows are recorded in the changelog
+    records_changes: bool,
 }
 
 impl TableSchema {
     /// Create a new empty table
-    pub fn new(name: &'static str) -> Self {
+    pub fn new(name: &str) -> Self {
         TableSchema {
-            name,
+            name: name.to_string(),
             id: TableId::new(),
             primary: BTreeSet::new(),
             aggregations: BTreeSet::new(),
+            time_column: None,
+            sequence_column: None,
+            conflict_policy: ConflictPolicy::default(),
+            indexes: BTreeSet::new(),
+            computed: BTreeMap::new(),
+            constraints: BTreeSet::new(),
+            references: BTreeMap::new(),
+            collations: BTreeMap::new(),
+            encodings: BTreeMap::new(),
+            rollup_of: None,
+            retention: None,
+            partitioning: None,
+            records_changes: false,
+        }
+    }
+
+    /// Decide what happens to rows that share a primary key, whether they
+    /// are saved together or in different segments.
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.conflict_policy = policy;
+    }
+
+    /// What happens to rows that share a primary key
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy
+    }
+
+    /// Declare the column that holds the time of each row.
+    ///
+    /// Each saved segment records the largest time it holds, so that old
+    /// segments can be expired without reading them.  The first raw column of
+    /// the lens must be a `u64`, which is the case for `SystemTime`.
+    pub fn set_time_column<T: Lens>(&mut self, column: &ColumnSchema<T>) -> Result<(), LensError> {
+        if T::RAW_KINDS.first() != Some(&RawKind::U64) {
+            return Err(LensError::InvalidKinds {
+                expected: "a time column stored as u64".to_string(),
+            });
+        }
+        self.time_column = Some(column.id);
+        Ok(())
+    }
+
+    /// The index within a row of the raw column holding the time
+    pub(crate) fn time_index(&self) -> Option<usize> {
+        let time_column = self.time_column?;
+        self.raw_columns().position(|c| c.id == time_column)
+    }
+
+    /// Declare the column that holds the shard and sequence number of each
+    /// row, for rows ingested from a sharded stream.
+    ///
+    /// The latest sequence number saved from each shard is its watermark,
+    /// which a [`Database`] records in its [`watermark_schema`] table as rows
+    /// are saved.  Reading with [`TableHandle::read_to_watermarks`] then gives
+    /// the rows saved up to those watermarks, however many shards are being
+    /// ingested at once.
+    ///
+    /// [`Database`]: crate::Database
+    /// [`TableHandle::read_to_watermarks`]: crate::TableHandle::read_to_watermarks
+    pub fn set_sequence_column(&mut self, column: &ColumnSchema<ShardSequence>) {
+        self.sequence_column = Some(column.id);
+    }
+
+    /// The shard and sequence number of `row`, if the schema has a sequence
+    /// column
+    pub(crate) fn shard_sequence(&self, row: &RawRow) -> Option<ShardSequence> {
+        let sequence_column = self.sequence_column?;
+        let i = self.raw_columns().position(|c| c.id == sequence_column)?;
+        match row.values[i..] {
+            [RawValue::U64(shard), RawValue::U64(sequence), ..] => {
+                Some(ShardSequence { shard, sequence })
+            }
+            _ => None,
+        }
+    }
+
+    /// Keep rows only while the time in the time column is within
+    /// `retention` of now.
+    ///
+    /// Older rows are purged when the table is compacted, see
+    /// [`Table::compact`].  A `SystemTime` column gives the time in seconds
+    /// since the epoch, and a `u64` column in nanoseconds since the epoch, as
+    /// `_ingested_at` does.  The time column must be set first.
+    ///
+    /// [`Table::compact`]: crate::Table::compact
+    pub fn set_retention(&mut self, retention: Duration) -> Result<(), LensError> {
+        if self.time_index().is_none() || retention.is_zero() {
+            return Err(LensError::InvalidKinds {
+                expected: "a positive retention of a table with a time column".to_string(),
+            });
+        }
+        self.retention = Some(retention);
+        Ok(())
+    }
+
+    /// How long rows are kept, see [`TableSchema::set_retention`]
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention
+    }
+
+    /// The earliest time in the time column of rows that are retained at
+    /// `now`, if the table has a retention
+    pub(crate) fn retained_since(&self, now: SystemTime) -> Option<u64> {
+        let retention = self.retention?;
+        let time = self.raw_columns().nth(self.time_index()?)?;
+        let since = now
+            .checked_sub(retention)
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Some(if time.lens == SystemTime::LENS_ID {
+            since.as_secs()
+        } else {
+            since.as_nanos().try_into().unwrap_or(u64::MAX)
+        })
+    }
+
+    /// Index the values of a column in every segment saved from now on, so
+    /// that rows can be found by value without reading the whole column, see
+    /// [`Table::lookup_by_index`].
+    ///
+    /// The column must already be in the table.  The total of a summed column
+    /// need not be in any one segment, so summed columns cannot be indexed.
+    ///
+    /// [`Table::lookup_by_index`]: crate::Table::lookup_by_index
+    pub fn add_index<T: Lens>(&mut self, column: &ColumnSchema<T>) -> Result<(), LensError> {
+        if !self.can_index(column.id) {
+            return Err(LensError::InvalidKinds {
+                expected: "a column of the table that is not summed".to_string(),
+            });
+        }
+        self.indexes.insert(column.id);
+        Ok(())
+    }
+
+    fn can_index(&self, id: ColumnId) -> bool {
+        let is_summed = self.aggregations.iter().any(|a| {
+            matches!(
+                a.aggregation(),
+                Aggregation::Sum | Aggregation::Count | Aggregation::Avg | Aggregation::Sketch
+            ) && a.columns().any(|(_, c)| c.id == id)
+        });
+        self.raw_columns().any(|c| c.id == id) && !is_summed
+    }
+
+    /// Whether a raw column is indexed in each segment
+    pub(crate) fn is_indexed(&self, column: &RawColumnSchema) -> bool {
+        self.indexes.contains(&column.id)
+    }
+
+    /// Give each row saved holding the default of `column` the time it is
+    /// saved instead.
+    pub fn default_to_now(&mut self, column: &ColumnSchema<SystemTime>) -> Result<(), LensError> {
+        self.compute_default(column.id, ComputedDefault::Now)
+    }
+
+    /// Give each row saved holding the default of `column`, which is zero
+    /// unless it is given another, the next value of a counter kept for the
+    /// column instead.
+    ///
+    /// The counter starts at one and is kept by the database, so rows saved
+    /// through a [`TableHandle`](crate::TableHandle) never share a value,
+    /// while each [`TableBuilder`](crate::TableBuilder) used on its own
+    /// counts from one.  A row given a value of its own moves the counter
+    /// past it.
+    pub fn auto_increment(&mut self, column: &ColumnSchema<u64>) -> Result<(), LensError> {
+        self.compute_default(column.id, ComputedDefault::AutoIncrement)
+    }
+
+    fn compute_default(
+        &mut self,
+        id: ColumnId,
+        computed: ComputedDefault,
+    ) -> Result<(), LensError> {
+        if !self.raw_columns().any(|c| c.id == id) {
+            return Err(LensError::InvalidKinds {
+                expected: "a column of the table".to_string(),
+            });
+        }
+        self.computed.insert(id, computed);
+        Ok(())
+    }
+
+    /// How the default of a raw column is computed
+    pub fn computed_default(&self, column: &RawColumnSchema) -> ComputedDefault {
+        self.computed.get(&column.id).copied().unwrap_or_default()
+    }
+
+    /// The columns whose defaults are computed, with the positions of their
+    /// raw columns in a row
+    pub(crate) fn computed_columns(&self) -> Vec<(ColumnId, ComputedDefault, Vec<usize>)> {
+        self.computed
+            .iter()
+            .map(|(id, computed)| (*id, *computed, self.positions(*id)))
+            .collect()
+    }
+
+    /// The positions in a row of the raw columns of a column
+    pub(crate) fn positions(&self, id: ColumnId) -> Vec<usize> {
+        self.raw_columns()
+            .enumerate()
+            .filter(|(_, c)| c.id == id)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Constrain the values of `column` in every row saved from now on.
+    ///
+    /// There are no nulls, so a column is null when it holds its default,
+    /// and rows holding the default of a unique column do not count as
+    /// sharing a value.  The column must already be in the table and not be
+    /// summed, and a unique column must not be part of the primary key,
+    /// which is unique already.
+    ///
+    /// Each row is checked as it is added to a
+    /// [`TableBuilder`](crate::TableBuilder), and the rows it saves are
+    /// checked against each other, even once spilled to disk.  Rows
+    /// saved through a [`TableHandle`](crate::TableHandle) are checked
+    /// against the rows already in the table too, and compacting checks
+    /// every row of the table.  A row that breaks a constraint is reported
+    /// as a
+    /// [`StorageError::Constraint`](crate::StorageError::Constraint).
+    pub fn add_constraint<T: Lens>(
+        &mut self,
+        column: &ColumnSchema<T>,
+        constraint: Constraint,
+    ) -> Result<(), LensError> {
+        let is_primary = self.primary.iter().any(|(_, c)| c.id == column.id);
+        if !self.can_index(column.id) || (constraint == Constraint::Unique && is_primary) {
+            return Err(LensError::InvalidKinds {
+                expected: format!("a column of the table that may be {constraint}"),
+            });
+        }
+        self.constraints.insert((column.id, constraint));
+        Ok(())
+    }
+
+    /// Whether the values of a raw column are constrained by `constraint`
+    pub fn has_constraint(&self, column: &RawColumnSchema, constraint: Constraint) -> bool {
+        self.constraints.contains(&(column.id, constraint))
+    }
+
+    /// The constrained columns, with the positions of their raw columns in
+    /// a row
+    pub(crate) fn constrained_columns(&self) -> Vec<(ColumnId, Constraint, Vec<usize>)> {
+        self.constraints
+            .iter()
+            .map(|(id, constraint)| (*id, *constraint, self.positions(*id)))
+            .collect()
+    }
+
+    /// Require each value of `column` other than its default to be the
+    /// primary key of a row of `table`, which may be this table.
+    ///
+    /// The raw columns of `column` must be of the same kinds as the primary
+    /// key of `table`, and must not be summed.  The values are checked as
+    /// rows are saved through a [`TableHandle`](crate::TableHandle) and as
+    /// the table is compacted.  Deleting rows of `table` may leave values
+    /// referring to nothing, which
+    /// [`Database::check_integrity`](crate::Database::check_integrity) finds.
+    pub fn add_reference<T: Lens>(
+        &mut self,
+        column: &ColumnSchema<T>,
+        table: &TableSchema,
+    ) -> Result<(), LensError> {
+        let kinds = self
+            .raw_columns()
+            .filter(|c| c.id == column.id)
+            .map(|c| c.kind());
+        if !self.can_index(column.id) || !kinds.eq(table.primary.iter().map(|(_, c)| c.kind())) {
+            return Err(LensError::InvalidKinds {
+                expected: format!("a column holding a primary key of table {}", table.name),
+            });
+        }
+        self.references.insert(column.id, table.id);
+        Ok(())
+    }
+
+    /// The table whose primary keys the values of a raw column refer to
+    pub fn reference(&self, column: &RawColumnSchema) -> Option<TableId> {
+        self.references.get(&column.id).copied()
+    }
+
+    /// The columns referring to tables, with those tables and the positions
+    /// of their raw columns in a row
+    pub(crate) fn referencing_columns(&self) -> Vec<(ColumnId, TableId, Vec<usize>)> {
+        self.references
+            .iter()
+            .map(|(id, table)| (*id, *table, self.positions(*id)))
+            .collect()
+    }
+
+    /// Order the bytes of `column` by `collation`, in the primary key if it
+    /// is part of it, and when aggregating it by max or min.
+    ///
+    /// The ordering of a column should be set before any rows are saved, or
+    /// the rows saved before are read in the wrong order.
+    pub fn set_collation<T: Lens>(
+        &mut self,
+        column: &ColumnSchema<T>,
+        collation: Collation,
+    ) -> Result<(), LensError> {
+        if !self
+            .raw_columns()
+            .any(|c| c.id == column.id && c.kind() == RawKind::Bytes)
+        {
+            return Err(LensError::InvalidKinds {
+                expected: "a column of the table holding bytes".to_string(),
+            });
+        }
+        if collation == Collation::Binary {
+            self.collations.remove(&column.id);
+        } else {
+            self.collations.insert(column.id, collation);
+        }
+        Ok(())
+    }
+
+    /// How the bytes of a raw column are ordered
+    pub fn collation(&self, column: &RawColumnSchema) -> Collation {
+        self.collations.get(&column.id).copied().unwrap_or_default()
+    }
+
+    /// Encode `column` in the formats of `encoding` in the segments saved
+    /// from now on, rather than in whichever format is smallest.
+    ///
+    /// The encoding must have a format holding the kind of some raw column
+    /// of `column`.  Segments saved before keep the formats they were saved
+    /// in until they are compacted.
+    pub fn set_encoding<T: Lens>(
+        &mut self,
+        column: &ColumnSchema<T>,
+        encoding: Encoding,
+    ) -> Result<(), LensError> {
+        self.set_encoding_of(column.id, encoding)
+            .map_err(|expected| LensError::InvalidKinds { expected })
+    }
+
+    /// Set the encoding of the column with the raw columns of `id`
+    fn set_encoding_of(&mut self, id: ColumnId, encoding: Encoding) -> Result<(), String> {
+        let holds = |c: &RawColumnSchema| {
+            encoding == Encoding::Auto || encoding.formats().iter().any(|f| f.kind() == c.kind())
+        };
+        if !self.raw_columns().any(|c| c.id == id && holds(c)) {
+            return Err(format!(
+                "a column of the table that {encoding:?} can encode"
+            ));
         }
+        if encoding == Encoding::Auto {
+            self.encodings.remove(&id);
+        } else {
+            self.encodings.insert(id, encoding);
+        }
+        Ok(())
+    }
+
+    /// Which formats a raw column is encoded in
+    pub fn encoding(&self, column: &RawColumnSchema) -> Encoding {
+        self.encodings.get(&column.id).copied().unwrap_or_default()
+    }
+
+    /// Change the encoding of a column, returning its raw columns.
+    pub(crate) fn set_encoding_by_name(
+        &mut self,
+        name: &str,
+        encoding: Encoding,
+    ) -> Result<Vec<RawColumnSchema>, String> {
+        let Some(id) = self.raw_columns().find(|c| c.name == name).map(|c| c.id) else {
+            return Err(format!("no column {name}"));
+        };
+        self.set_encoding_of(id, encoding)
+            .map_err(|expected| format!("column {name} is not {expected}"))?;
+        Ok(self.raw_columns().filter(|c| c.id == id).cloned().collect())
+    }
+
+    /// Compare the values of consecutive raw columns, starting at position
+    /// `start` in a row, by their collations
+    pub(crate) fn compare_values(&self, start: usize, a: &[RawValue], b: &[RawValue]) -> Ordering {
+        if self.collations.is_empty() {
+            return a.cmp(b);
+        }
+        self.raw_columns()
+            .skip(start)
+            .zip(a.iter().zip(b))
+            .map(|(c, values)| match values {
+                (RawValue::Bytes(a), RawValue::Bytes(b)) => self.collation(c).compare(a, b),
+                (a, b) => a.cmp(b),
+            })
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    }
+
+    /// Split the segments of the table between `partitions` partitions, by
+    /// the hash of the first raw column of `column`.
+    ///
+    /// Each partition keeps its column files in a directory of its own, and
+    /// every segment saved from now on holds the rows of a single partition.
+    /// A table read with [`Table::read_where`] for rows where the column
+    /// equals some values then opens only the partitions that can hold them.
+    /// The column must be in the primary key, so that rows sharing a key are
+    /// always in the same partition, and there must be at least two
+    /// partitions.
+    ///
+    /// [`Table::read_where`]: crate::Table::read_where
+    pub fn partition_by<T: Lens>(
+        &mut self,
+        column: &ColumnSchema<T>,
+        partitions: u64,
+    ) -> Result<(), LensError> {
+        if partitions < 2 || !self.primary.iter().any(|(_, c)| c.id == column.id) {
+            return Err(LensError::InvalidKinds {
+                expected: "at least two partitions by a primary key column".to_string(),
+            });
+        }
+        self.partitioning = Some((column.id, partitions));
+        Ok(())
+    }
+
+    /// The number of partitions of the table, if it is partitioned, see
+    /// [`TableSchema::partition_by`]
+    pub fn partitions(&self) -> Option<u64> {
+        self.partitioning.map(|(_, n)| n)
+    }
+
+    /// The index within a row of the raw column whose hash picks the
+    /// partition, along with the number of partitions
+    pub(crate) fn partition_index(&self) -> Option<(usize, u64)> {
+        let (column, partitions) = self.partitioning?;
+        let i = self.raw_columns().position(|c| c.id == column)?;
+        Some((i, partitions))
+    }
+
+    /// The partition holding rows with `value` in the partition column
+    pub(crate) fn partition_of(&self, value: &RawValue) -> Option<u64> {
+        let (_, partitions) = self.partitioning?;
+        let hash = match value {
+            RawValue::U64(v) => fnv(FNV_OFFSET, &v.to_be_bytes()),
+            RawValue::Bool(b) => fnv(FNV_OFFSET, &[*b as u8]),
+            RawValue::Bytes(b) => fnv(FNV_OFFSET, b),
+        };
+        Some(hash % partitions)
+    }
+
+    /// The schema of a table rolling up this one, whose rows are those of
+    /// this table grouped by the first `key` raw columns of its primary key.
+    ///
+    /// The rollup has every column of this table that is not in its primary
+    /// key, aggregated in the same way, so a sum totals the group and a max
+    /// keeps the largest in the group.  It is created with
+    /// [`Database::create_table`], which fills it from this table, and each
+    /// [`TableHandle::compact`] of this table brings it up to date.  Rows
+    /// cannot be saved to it directly.
+    ///
+    /// [`Database::create_table`]: crate::Database::create_table
+    /// [`TableHandle::compact`]: crate::TableHandle::compact
+    pub fn rollup(&self, name: &str, key: usize) -> Result<TableSchema, LensError> {
+        if key > self.primary.len() {
+            return Err(LensError::InvalidKinds {
+                expected: format!("at most {} key columns", self.primary.len()),
+            });
+        }
+        let mut rollup = TableSchema::new(name);
+        rollup.primary = self.primary.iter().take(key).cloned().collect();
+        rollup.aggregations = self.aggregations.clone();
+        rollup.time_column = self
+            .time_column
+            .filter(|&t| rollup.raw_columns().any(|c| c.id == t));
+        rollup.rollup_of = Some(self.id);
+        Ok(rollup)
+    }
+
+    /// The schema of a copy of this table called `name`, with an id of its
+    /// own, which refers to itself wherever this table does.
+    ///
+    /// The copy is no rollup, even if this table is one, so nothing but its
+    /// own writes change it.
+    pub(crate) fn cloned_as(&self, name: &str) -> TableSchema {
+        let mut clone = self.clone();
+        clone.name = name.to_string();
+        clone.id = TableId::new();
+        for table in clone.references.values_mut() {
+            if *table == self.id {
+                *table = clone.id;
+            }
+        }
+        clone.rollup_of = None;
+        clone
+    }
+
+    /// The id of the table this one rolls up, see [`TableSchema::rollup`]
+    pub fn rollup_of(&self) -> Option<TableId> {
+        self.rollup_of
+    }
+
+    /// Record the time each row was saved, in a `u64` column named
+    /// `_ingested_at` holding nanoseconds since the epoch.
+    ///
+    /// Whatever value a row is given, every row saved together is stamped with
+    /// the time of the version of the table that saved it, and the column is
+    /// aggregated by max, so a row shows when it last changed.  Stamps only
+    /// grow, so rows no later than [`Table::ingestion_watermark`] can be read
+    /// again and again with the same result.
+    ///
+    /// [`Table::ingestion_watermark`]: crate::Table::ingestion_watermark
+    pub fn record_ingestion_time(&mut self) {
+        if self.ingestion_index().is_none() {
+            self.add_max(
+                ColumnSchema::with_default(INGESTED_AT_NAME, 0u64)
+                    .with_id(catalog::INGESTED_AT)
+                    .raw(),
+            );
+        }
+    }
+
+    /// Record every batch of rows inserted into or deleted from the table in
+    /// the changelog of its database, see [`Database::changes_since`].
+    ///
+    /// [`Database::changes_since`]: crate::Database::changes_since
+    pub fn record_changes(&mut self) {
+        self.records_changes = true;
+    }
+
+    /// Whether changes to the table are recorded, see
+    /// [`TableSchema::record_changes`]
+    pub fn records_changes(&self) -> bool {
+        self.records_changes
+    }
+
+    /// The index within a row of the raw column recording when it was saved
+    pub(crate) fn ingestion_index(&self) -> Option<usize> {
+        self.raw_columns()
+            .position(|c| c.id == catalog::INGESTED_AT)
     }
 
     /// Add columns to the primary key
@@ -144,35 +1185,408 @@ impl TableSchema {
         }
     }
 
-    /// Add max aggregating column group
-    pub fn add_max(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
-        self.aggregations.insert(AggregatingSchema::Max {
-            columns: columns.enumerate().map(|(o, c)| (o as u64, c)).collect(),
-            id: AggregationId(rand::random()),
-        });
+    /// Add max aggregating column group
+    pub fn add_max(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
+        self.aggregations.insert(AggregatingSchema::Max {
+            columns: columns.enumerate().map(|(o, c)| (o as u64, c)).collect(),
+            id: AggregationId::new(),
+        });
+    }
+
+    /// Add min aggregating column group
+    pub fn add_min(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
+        self.aggregations.insert(AggregatingSchema::Min {
+            columns: columns.enumerate().map(|(o, c)| (o as u64, c)).collect(),
+            id: AggregationId::new(),
+        });
+    }
+
+    /// Add a group of columns that keeps the values of the row with the
+    /// smallest value of `clock`, which is a new column kept in the group.
+    ///
+    /// Rows with the same clock keep the values of the row written first.
+    pub fn add_first_by<T: Lens + Clone>(
+        &mut self,
+        clock: &ColumnSchema<T>,
+        columns: impl Iterator<Item = RawColumnSchema>,
+    ) -> Result<(), LensError> {
+        self.add_by_clock(Aggregation::FirstBy, clock, columns)
+    }
+
+    /// Add a group of columns that keeps the values of the row with the
+    /// largest value of `clock`, which is a new column kept in the group,
+    /// such as the latest state of each primary key.
+    ///
+    /// Rows with the same clock keep the values of the row written last.
+    pub fn add_last_by<T: Lens + Clone>(
+        &mut self,
+        clock: &ColumnSchema<T>,
+        columns: impl Iterator<Item = RawColumnSchema>,
+    ) -> Result<(), LensError> {
+        self.add_by_clock(Aggregation::LastBy, clock, columns)
+    }
+
+    fn add_by_clock<T: Lens + Clone>(
+        &mut self,
+        aggregation: Aggregation,
+        clock: &ColumnSchema<T>,
+        columns: impl Iterator<Item = RawColumnSchema>,
+    ) -> Result<(), LensError> {
+        let columns: OrderedRawColumns = clock
+            .raw()
+            .chain(columns)
+            .enumerate()
+            .map(|(o, c)| (o as u64, c))
+            .collect();
+        let clocks = columns.iter().filter(|(_, c)| c.id == clock.id).count();
+        if clocks != T::RAW_KINDS.len() || self.raw_columns().any(|c| c.id == clock.id) {
+            return Err(LensError::InvalidKinds {
+                expected: format!("a new clock column {}", clock.name),
+            });
+        }
+        let (id, clock) = (AggregationId::new(), clock.id);
+        self.aggregations
+            .insert(if aggregation == Aggregation::FirstBy {
+                AggregatingSchema::FirstBy { columns, id, clock }
+            } else {
+                AggregatingSchema::LastBy { columns, id, clock }
+            });
+        Ok(())
+    }
+
+    /// Add summing columns, which wrap around when they overflow, other than
+    /// [`Decimal`](crate::Decimal) columns, which refuse to
+    pub fn add_sum(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
+        for c in columns {
+            let overflow = if decimal_scale(c.lens()).is_some() {
+                SumOverflow::Error
+            } else {
+                SumOverflow::Wrap
+            };
+            self.aggregations.insert(AggregatingSchema::Sum {
+                columns: [(0, c)].into_iter().collect(),
+                overflow,
+            });
+        }
+    }
+
+    /// Add a summing column with the given overflow policy.
+    ///
+    /// A column that is widened on overflow must be a `u128`, whose two raw
+    /// columns are summed together.  Any other policy applies to each raw
+    /// column separately, all of which must hold `u64`.
+    pub fn add_sum_with_overflow<T: Lens + Clone>(
+        &mut self,
+        column: &ColumnSchema<T>,
+        overflow: SumOverflow,
+    ) -> Result<(), LensError> {
+        if overflow == SumOverflow::Widen {
+            if T::LENS_ID != u128::LENS_ID {
+                return Err(LensError::InvalidKinds {
+                    expected: "a u128 column to widen".to_string(),
+                });
+            }
+            self.aggregations.insert(AggregatingSchema::Sum {
+                columns: column
+                    .raw()
+                    .enumerate()
+                    .map(|(o, c)| (o as u64, c))
+                    .collect(),
+                overflow,
+            });
+            return Ok(());
+        }
+        if T::RAW_KINDS.iter().any(|k| *k != RawKind::U64) {
+            return Err(LensError::InvalidKinds {
+                expected: "a sum stored as u64".to_string(),
+            });
+        }
+        for c in column.raw() {
+            self.aggregations.insert(AggregatingSchema::Sum {
+                columns: [(0, c)].into_iter().collect(),
+                overflow,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add a column counting the rows saved with each primary key.
+    ///
+    /// The default of the column is one, so each row saved without a count
+    /// counts once, while a row holding a count, such as one copied from
+    /// another table of counts, adds that many.  Counts stop at `u64::MAX`.
+    pub fn add_count(&mut self, column: &ColumnSchema<u64>) {
+        self.add_counts(column.raw());
+    }
+
+    fn add_counts(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
+        for mut c in columns {
+            c.default = RawValue::U64(1);
+            self.aggregations.insert(AggregatingSchema::Count {
+                columns: [(0, c)].into_iter().collect(),
+            });
+        }
+    }
+
+    /// Add a column holding the sum and the count of its values, which are
+    /// added up separately, stopping at `u64::MAX`, so that they can be
+    /// averaged, see [`Average`].
+    pub fn add_avg(&mut self, column: &ColumnSchema<Average>) {
+        self.aggregations.insert(AggregatingSchema::Avg {
+            columns: column
+                .raw()
+                .enumerate()
+                .map(|(o, c)| (o as u64, c))
+                .collect(),
+        });
+    }
+
+    /// Add a column of [`QuantileSketch`]es, which are merged together, so
+    /// that each row holds a sketch of every value saved with its primary
+    /// key, such as the latencies of requests from which to estimate the
+    /// 99th percentile.
+    pub fn add_sketch(&mut self, column: &ColumnSchema<QuantileSketch>) {
+        self.add_sketches(column.raw());
+    }
+
+    fn add_sketches(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
+        for c in columns {
+            self.aggregations.insert(AggregatingSchema::Sketch {
+                columns: [(0, c)].into_iter().collect(),
+            });
+        }
+    }
+
+    /// All the columns
+    fn columns(&self) -> impl Iterator<Item = &(u64, RawColumnSchema)> {
+        self.primary
+            .iter()
+            .chain(self.aggregations.iter().flat_map(|a| a.columns()))
+    }
+
+    /// All the raw columns, in the order they are stored in a row
+    pub fn raw_columns(&self) -> impl Iterator<Item = &RawColumnSchema> {
+        self.columns().map(|(_, c)| c)
+    }
+
+    /// The name of the table
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The unique id of the table
+    pub fn id(&self) -> TableId {
+        self.id
+    }
+
+    /// The number of raw columns in the primary key, which come first in a row
+    pub(crate) fn num_primary(&self) -> usize {
+        self.primary.len()
+    }
+
+    /// Each group of raw columns after the primary key, with the range of the
+    /// row they occupy.
+    pub(crate) fn aggregation_ranges(&self) -> Vec<(&AggregatingSchema, Range<usize>)> {
+        let mut start = self.primary.len();
+        let mut out = Vec::new();
+        for a in self.aggregations.iter() {
+            let end = start + a.columns().count();
+            out.push((a, start..end));
+            start = end;
+        }
+        out
+    }
+
+    /// Build a row from the values of logical columns, using the default for
+    /// any column that is not given.
+    pub(crate) fn row(&self, values: Vec<(ColumnId, RawValues)>) -> RawRow {
+        let mut values: Vec<(ColumnId, std::vec::IntoIter<RawValue>)> = values
+            .into_iter()
+            .map(|(id, v)| (id, v.0.into_iter()))
+            .collect();
+        self.raw_columns()
+            .map(|c| {
+                values
+                    .iter_mut()
+                    .find(|(id, _)| *id == c.id)
+                    .and_then(|(_, v)| v.next())
+                    .unwrap_or_else(|| c.default.clone())
+            })
+            .collect()
+    }
+
+    /// Read the value of a logical column from a row
+    pub(crate) fn get<T: Lens>(&self, row: &RawRow, id: ColumnId) -> Result<T, LensError> {
+        let values = self
+            .raw_columns()
+            .zip(row.values.iter())
+            .filter(|(c, _)| c.id == id)
+            .map(|(_, v)| v.clone())
+            .collect();
+        T::try_from(RawValues(values))
+    }
+
+    fn has_column(&self, name: &str) -> bool {
+        self.raw_columns().any(|c| c.name == name)
     }
 
-    /// Add min aggregating column group
-    pub fn add_min(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
-        self.aggregations.insert(AggregatingSchema::Min {
-            columns: columns.enumerate().map(|(o, c)| (o as u64, c)).collect(),
-            id: AggregationId(rand::random()),
+    /// Add columns with the given aggregation.
+    pub(crate) fn add_columns(
+        &mut self,
+        columns: Vec<RawColumnSchema>,
+        aggregation: Aggregation,
+    ) -> Result<(), String> {
+        for c in columns.iter() {
+            if self.raw_columns().any(|old| old.id == c.id) || self.has_column(&c.name) {
+                return Err(format!("column {} already exists", c.name));
+            }
+        }
+        let is_u64 = columns.iter().all(|c| c.kind() == RawKind::U64);
+        let is_average = columns.len() == 2 && columns.iter().all(|c| c.lens == Average::LENS_ID);
+        match aggregation {
+            Aggregation::Count if !is_u64 => return Err("a count must be a u64".to_string()),
+            Aggregation::Avg if !is_average => {
+                return Err("an average must be an Average".to_string())
+            }
+            Aggregation::FirstBy | Aggregation::LastBy => {
+                return Err(format!("{aggregation:?} needs a clock column"))
+            }
+            Aggregation::Sketch if columns.iter().any(|c| c.lens != QuantileSketch::LENS_ID) => {
+                return Err("a sketch must be a QuantileSketch".to_string())
+            }
+            _ => (),
+        }
+        let columns = columns.into_iter();
+        match aggregation {
+            Aggregation::None => self.add_primary(columns),
+            Aggregation::Max => self.add_max(columns),
+            Aggregation::Min => self.add_min(columns),
+            Aggregation::Sum => self.add_sum(columns),
+            Aggregation::Count => self.add_counts(columns),
+            Aggregation::Avg => {
+                self.aggregations.insert(AggregatingSchema::Avg {
+                    columns: columns.enumerate().map(|(o, c)| (o as u64, c)).collect(),
+                });
+            }
+            Aggregation::Sketch => self.add_sketches(columns),
+            Aggregation::FirstBy | Aggregation::LastBy => unreachable!("refused above"),
+        }
+        Ok(())
+    }
+
+    /// Remove a column that is not part of the primary key, returning its raw
+    /// columns.
+    pub(crate) fn drop_column(&mut self, name: &str) -> Result<Vec<RawColumnSchema>, String> {
+        if self.primary.iter().any(|(_, c)| c.name == name) {
+            return Err(format!("cannot drop primary key column {name}"));
+        }
+        if !self.has_column(name) {
+            return Err(format!("no column {name}"));
+        }
+        let is_clock = self.aggregations.iter().any(|a| {
+            let clock = a
+                .clock()
+                .and_then(|t| self.raw_columns().find(|c| c.id == t));
+            matches!(clock, Some(c) if c.name == name) && a.columns().any(|(_, c)| c.name != name)
         });
+        if is_clock {
+            return Err(format!("column {name} is the clock of other columns"));
+        }
+        let mut dropped = Vec::new();
+        self.aggregations = std::mem::take(&mut self.aggregations)
+            .into_iter()
+            .filter_map(|mut a| {
+                let columns = a.columns_mut();
+                let (gone, kept) = std::mem::take(columns)
+                    .into_iter()
+                    .partition(|(_, c)| c.name == name);
+                *columns = kept;
+                dropped.extend(gone.into_iter().map(|(_, c): (u64, _)| c));
+                if a.columns().count() == 0 {
+                    None
+                } else {
+                    Some(a)
+                }
+            })
+            .collect();
+        if self.time_column.map(|t| dropped.iter().any(|c| c.id == t)) == Some(true) {
+            self.time_column = None;
+            self.retention = None;
+        }
+        for c in dropped.iter() {
+            self.indexes.remove(&c.id);
+            self.computed.remove(&c.id);
+        }
+        self.constraints
+            .retain(|(id, _)| !dropped.iter().any(|c| c.id == *id));
+        self.references
+            .retain(|id, _| !dropped.iter().any(|c| c.id == *id));
+        self.collations
+            .retain(|id, _| !dropped.iter().any(|c| c.id == *id));
+        Ok(dropped)
     }
 
-    /// Add summing columns
-    pub fn add_sum(&mut self, columns: impl Iterator<Item = RawColumnSchema>) {
-        for c in columns {
-            self.aggregations
-                .insert(AggregatingSchema::Sum([(0, c)].into_iter().collect()));
+    /// Start or stop indexing a column, returning its raw columns.
+    pub(crate) fn set_indexed(
+        &mut self,
+        name: &str,
+        indexed: bool,
+    ) -> Result<Vec<RawColumnSchema>, String> {
+        let Some(id) = self.raw_columns().find(|c| c.name == name).map(|c| c.id) else {
+            return Err(format!("no column {name}"));
+        };
+        if indexed && !self.can_index(id) {
+            return Err(format!("cannot index summed column {name}"));
+        }
+        if indexed == self.indexes.contains(&id) {
+            let already = if indexed { "already" } else { "not" };
+            return Err(format!("column {name} is {already} indexed"));
+        }
+        if indexed {
+            self.indexes.insert(id);
+        } else {
+            self.indexes.remove(&id);
         }
+        Ok(self.raw_columns().filter(|c| c.id == id).cloned().collect())
     }
 
-    /// All the columns
-    fn columns(&self) -> impl Iterator<Item = &(u64, RawColumnSchema)> {
-        self.primary
-            .iter()
-            .chain(self.aggregations.iter().flat_map(|a| a.columns()))
+    /// Rename a column, returning its raw columns with their new name.
+    pub(crate) fn rename_column(
+        &mut self,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<RawColumnSchema>, String> {
+        if !self.has_column(from) {
+            return Err(format!("no column {from}"));
+        }
+        if self.has_column(to) {
+            return Err(format!("column {to} already exists"));
+        }
+        let rename = |columns: OrderedRawColumns| -> OrderedRawColumns {
+            columns
+                .into_iter()
+                .map(|(o, mut c)| {
+                    if c.name == from {
+                        c.name = to.to_string();
+                    }
+                    (o, c)
+                })
+                .collect()
+        };
+        self.primary = rename(std::mem::take(&mut self.primary));
+        self.aggregations = std::mem::take(&mut self.aggregations)
+            .into_iter()
+            .map(|mut a| {
+                let columns = a.columns_mut();
+                *columns = rename(std::mem::take(columns));
+                a
+            })
+            .collect();
+        Ok(self
+            .raw_columns()
+            .filter(|c| c.name == to)
+            .cloned()
+            .collect())
     }
 }
 
@@ -187,8 +1601,91 @@ impl std::fmt::Display for TableSchema {
             match a {
                 AggregatingSchema::Max { columns, .. } => column_list("MAX", columns, f)?,
                 AggregatingSchema::Min { columns, .. } => column_list("MIN", columns, f)?,
-                AggregatingSchema::Sum(columns) => column_list("SUM", columns, f)?,
+                AggregatingSchema::Sum { columns, overflow } => {
+                    column_list("SUM", columns, f)?;
+                    if *overflow != SumOverflow::Wrap {
+                        writeln!(f, "    ON OVERFLOW {overflow:?},")?;
+                    }
+                }
+                AggregatingSchema::Count { columns } => column_list("COUNT", columns, f)?,
+                AggregatingSchema::Sketch { columns } => column_list("SKETCH", columns, f)?,
+                AggregatingSchema::Avg { columns } => column_list("AVG", columns, f)?,
+                AggregatingSchema::FirstBy { columns, clock, .. }
+                | AggregatingSchema::LastBy { columns, clock, .. } => {
+                    let order = if a.aggregation() == Aggregation::FirstBy {
+                        "FIRST"
+                    } else {
+                        "LAST"
+                    };
+                    let clock = self.raw_columns().find(|c| c.id == *clock);
+                    let clock = clock.map(|c| c.name.clone()).unwrap_or_default();
+                    column_list(&format!("{order} BY {clock}"), columns, f)?
+                }
+            }
+        }
+        if let Some(c) = self.time_index().and_then(|i| self.raw_columns().nth(i)) {
+            writeln!(f, "    TIME ( {} ),", c.name)?;
+        }
+        if let Some(c) = self
+            .sequence_column
+            .and_then(|s| self.raw_columns().find(|c| c.id == s))
+        {
+            writeln!(f, "    SEQUENCE ( {} ),", c.name)?;
+        }
+        let indexed = self
+            .raw_columns()
+            .filter(|c| self.is_indexed(c))
+            .map(|c| c.display_name())
+            .collect::<Vec<_>>();
+        if !indexed.is_empty() {
+            writeln!(f, "    INDEX ( {} ),", indexed.join(", "))?;
+        }
+        for (id, computed) in self.computed.iter() {
+            if let Some(c) = self.raw_columns().find(|c| c.id == *id) {
+                writeln!(f, "    DEFAULT {computed:?} ( {} ),", c.name)?;
+            }
+        }
+        for constraint in [Constraint::NotNull, Constraint::Unique] {
+            let mut names = Vec::new();
+            for c in self.raw_columns() {
+                if self.has_constraint(c, constraint) && !names.contains(&c.name) {
+                    names.push(c.name.clone());
+                }
             }
+            if !names.is_empty() {
+                writeln!(f, "    {constraint} ( {} ),", names.join(", "))?;
+            }
+        }
+        for (id, collation) in self.collations.iter() {
+            if let Some(c) = self.raw_columns().find(|c| c.id == *id) {
+                writeln!(f, "    COLLATE {collation:?} ( {} ),", c.name)?;
+            }
+        }
+        for (id, encoding) in self.encodings.iter() {
+            if let Some(c) = self.raw_columns().find(|c| c.id == *id) {
+                writeln!(f, "    ENCODE {encoding:?} ( {} ),", c.name)?;
+            }
+        }
+        for (id, table) in self.references.iter() {
+            if let Some(c) = self.raw_columns().find(|c| c.id == *id) {
+                writeln!(f, "    REFERENCES {table} ( {} ),", c.name)?;
+            }
+        }
+        if self.conflict_policy != ConflictPolicy::Aggregate {
+            writeln!(f, "    ON CONFLICT {:?},", self.conflict_policy)?;
+        }
+        if let Some(retention) = self.retention {
+            writeln!(f, "    RETENTION {retention:?},")?;
+        }
+        if let Some(table) = self.rollup_of {
+            writeln!(f, "    ROLLUP OF {table},")?;
+        }
+        if self.records_changes {
+            writeln!(f, "    RECORD CHANGES,")?;
+        }
+        if let Some((i, partitions)) = self.partition_index() {
+            let c = self.raw_columns().nth(i).expect("partition column exists");
+            writeln!(f, "    PARTITION BY ( {} ) INTO {partitions},", c.name)?;
         }
         writeln!(f, "}};")
     }
@@ -230,58 +1727,148 @@ impl<T: Lens + Clone> ColumnSchema<T> {
         }
     }
 
+    /// The id of this column
+    pub fn id(&self) -> ColumnId {
+        self.id
+    }
+
     fn with_id(self, id: ColumnId) -> Self {
         ColumnSchema { id, ..self }
     }
 
     /// Iterate over the raw columns corresponding to this one.
+    ///
+    /// This panics if the lens disagrees with itself, see
+    /// [`ColumnSchema::try_raw`].
     pub fn raw(&self) -> impl Iterator<Item = RawColumnSchema> {
-        let vs: RawValues = self.default.clone().into();
-        let id = self.id;
-        let name = self.name;
-        vs.0.into_iter()
-            .enumerate()
-            .map(move |(idx, default)| RawColumnSchema {
-                name,
+        match self.try_raw() {
+            Ok(columns) => columns.into_iter(),
+            Err(e) => panic!("column {}: {e}", self.name),
+        }
+    }
+
+    /// The raw columns corresponding to this one, unless the raw values of
+    /// its default disagree with the [`Lens::RAW_KINDS`] and [`Lens::NAMES`]
+    /// of its lens
+    pub fn try_raw(&self) -> Result<Vec<RawColumnSchema>, LensError> {
+        let values = raw_values(self.default.clone())?;
+        Ok(values
+            .0
+            .into_iter()
+            .zip(T::NAMES)
+            .map(|(default, fieldname)| RawColumnSchema {
+                name: self.name.to_string(),
                 default,
-                id,
-                fieldname: T::NAMES[idx],
+                id: self.id,
+                fieldname: fieldname.to_string(),
                 lens: T::LENS_ID,
             })
+            .collect())
     }
 }
 
 /// This is he schema for the table that holds schemas of tables
 pub fn table_schema_schema() -> TableSchema {
+    use catalog::*;
     let mut table = TableSchema::new("columns");
-    table.id = TableId::const_new(b"__table_schemas_");
+    table.id = COLUMNS_TABLE;
     table.add_primary(
         ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
-            .with_id(ColumnId::const_new(b"table_id--tables"))
+            .with_id(TABLE)
             .raw(),
     );
     table.add_primary(
         ColumnSchema::with_default("column", ColumnId::const_new(b"COLUMN-NOT-EXIST"))
-            .with_id(ColumnId::const_new(b"column_id-tables"))
+            .with_id(COLUMN)
             .raw(),
     );
     table.add_primary(
         ColumnSchema::with_default("order", 0u64)
-            .with_id(ColumnId::const_new(b"column-sortorder"))
+            .with_id(ORDER)
             .raw(),
     );
     table.add_primary(
         ColumnSchema::with_default("aggregate", Aggregation::None)
-            .with_id(ColumnId::const_new(b"column-aggregate"))
+            .with_id(AGGREGATE)
             .raw(),
     );
     table.add_max(
         ColumnSchema::with_default("modified", std::time::SystemTime::UNIX_EPOCH)
-            .with_id(ColumnId::const_new(b"modified-column!"))
+            .with_id(COLUMN_MODIFIED)
             .raw()
             .chain(
                 ColumnSchema::with_default("column_name", String::default())
-                    .with_id(ColumnId::const_new(b"name-of-column!!"))
+                    .with_id(COLUMN_NAME)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("fieldname", String::default())
+                    .with_id(FIELDNAME)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("lens", LensId::const_new(b"LENS--NOT-EXIST!"))
+                    .with_id(LENS)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("default", Vec::<u8>::new())
+                    .with_id(DEFAULT)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("group", catalog::NO_GROUP)
+                    .with_id(GROUP)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("is_deleted", false)
+                    .with_id(COLUMN_DELETED)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("overflow", SumOverflow::Wrap)
+                    .with_id(SUM_OVERFLOW)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("indexed", false)
+                    .with_id(INDEXED)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("computed", ComputedDefault::Constant)
+                    .with_id(COMPUTED_DEFAULT)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("not_null", false)
+                    .with_id(NOT_NULL)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("unique", false)
+                    .with_id(UNIQUE)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("references", TableId::const_new(b"TABLE--NOT-EXIST"))
+                    .with_id(REFERENCES)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("collation", Collation::Binary)
+                    .with_id(COLLATION)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("clock", ColumnId::const_new(b"COLUMN-NOT-EXIST"))
+                    .with_id(CLOCK)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("encoding", Encoding::Auto)
+                    .with_id(ENCODING)
                     .raw(),
             ),
     );
@@ -290,30 +1877,294 @@ pub fn table_schema_schema() -> TableSchema {
 
 /// This is the schema for the table that holds the schema of the db itself
 pub fn db_schema_schema() -> TableSchema {
+    use catalog::*;
     let mut table = TableSchema::new("tables");
-    table.id = TableId::const_new(b"__db_schema_____");
+    table.id = TABLES_TABLE;
     table.add_primary(
         ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
-            .with_id(ColumnId::const_new(b"table_id--tables"))
+            .with_id(TABLE)
             .raw(),
     );
     table.add_primary(
         ColumnSchema::with_default("created", std::time::SystemTime::UNIX_EPOCH)
-            .with_id(ColumnId::const_new(b"__table_created!"))
+            .with_id(CREATED)
             .raw(),
     );
     table.add_max(
         ColumnSchema::with_default("modified", std::time::SystemTime::UNIX_EPOCH)
-            .with_id(ColumnId::const_new(b"modified-table!!"))
+            .with_id(TABLE_MODIFIED)
             .raw()
             .chain(
                 ColumnSchema::with_default("table_name", String::default())
-                    .with_id(ColumnId::const_new(b"name-of-table!!!"))
+                    .with_id(TABLE_NAME)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("is_deleted", false)
+                    .with_id(TABLE_DELETED)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("time_column", ColumnId::const_new(b"COLUMN-NOT-EXIST"))
+                    .with_id(TIME_COLUMN)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("conflict_policy", ConflictPolicy::Aggregate)
+                    .with_id(CONFLICT_POLICY)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("rollup_of", TableId::const_new(b"TABLE--NOT-EXIST"))
+                    .with_id(ROLLUP_OF)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("retention_nanos", 0u64)
+                    .with_id(RETENTION)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default(
+                    "partition_column",
+                    ColumnId::const_new(b"COLUMN-NOT-EXIST"),
+                )
+                .with_id(PARTITION_COLUMN)
+                .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("partitions", 0u64)
+                    .with_id(PARTITIONS)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default(
+                    "sequence_column",
+                    ColumnId::const_new(b"COLUMN-NOT-EXIST"),
+                )
+                .with_id(SEQUENCE_COLUMN)
+                .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("records_changes", false)
+                    .with_id(RECORDS_CHANGES)
+                    .raw(),
+            ),
+    );
+    table
+}
+
+/// The schema of the table recording the results of scrubbing each table
+///
+/// There is a row for every scrub of every table, see [`crate::Database::scrub`].
+pub fn scrub_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("scrub");
+    table.id = SCRUB_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
+            .with_id(SCRUBBED_TABLE)
+            .raw(),
+    );
+    table.add_primary(
+        ColumnSchema::with_default("checked", std::time::SystemTime::UNIX_EPOCH)
+            .with_id(SCRUBBED)
+            .raw(),
+    );
+    table.add_max(
+        ColumnSchema::with_default("segments", 0u64)
+            .with_id(SCRUB_SEGMENTS)
+            .raw()
+            .chain(
+                ColumnSchema::with_default("columns", 0u64)
+                    .with_id(SCRUB_COLUMNS)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("corruptions", 0u64)
+                    .with_id(SCRUB_CORRUPTIONS)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("problems", String::default())
+                    .with_id(SCRUB_PROBLEMS)
+                    .raw(),
+            ),
+    );
+    table
+}
+
+/// The schema of the table recording the rows purged from each table
+///
+/// There is a row for every compaction that purged rows, see
+/// [`crate::Database::purge_results`].
+pub fn purge_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("purges");
+    table.id = PURGE_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
+            .with_id(PURGED_TABLE)
+            .raw(),
+    );
+    table.add_primary(
+        ColumnSchema::with_default("purged", std::time::SystemTime::UNIX_EPOCH)
+            .with_id(PURGED)
+            .raw(),
+    );
+    table.add_max(
+        ColumnSchema::with_default("since", 0u64)
+            .with_id(PURGE_SINCE)
+            .raw()
+            .chain(
+                ColumnSchema::with_default("segments", 0u64)
+                    .with_id(PURGE_SEGMENTS)
+                    .raw(),
+            )
+            .chain(
+                ColumnSchema::with_default("rows", 0u64)
+                    .with_id(PURGE_ROWS)
+                    .raw(),
+            ),
+    );
+    table
+}
+
+/// The schema of the table recording the watermark of each shard of each
+/// table with a sequence column
+///
+/// The watermark of a shard is the largest sequence number saved from it, see
+/// [`TableSchema::set_sequence_column`].
+pub fn watermark_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("watermarks");
+    table.id = WATERMARK_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
+            .with_id(WATERMARKED_TABLE)
+            .raw(),
+    );
+    table.add_primary(
+        ColumnSchema::with_default("shard", 0u64)
+            .with_id(WATERMARK_SHARD)
+            .raw(),
+    );
+    table.add_max(
+        ColumnSchema::with_default("sequence", 0u64)
+            .with_id(WATERMARK_SEQUENCE)
+            .raw(),
+    );
+    table
+}
+
+/// The schema of the table holding the counter of each auto-incremented
+/// column of each table, see [`TableSchema::auto_increment`]
+///
+/// The counters are aggregated by max, so a counter never goes backwards.
+pub fn counter_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("counters");
+    table.id = COUNTER_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
+            .with_id(COUNTED_TABLE)
+            .raw(),
+    );
+    table.add_primary(
+        ColumnSchema::with_default("column", ColumnId::const_new(b"COLUMN-NOT-EXIST"))
+            .with_id(COUNTED_COLUMN)
+            .raw(),
+    );
+    table.add_max(
+        ColumnSchema::with_default("next", 1u64)
+            .with_id(COUNTER_NEXT)
+            .raw(),
+    );
+    table
+}
+
+/// The schema of the changelog, recording every batch of rows inserted into
+/// or deleted from each table that records its changes
+///
+/// There is a row for each row of each batch, with its values encoded as
+/// bytes, see [`crate::Database::changes_since`].
+pub fn changelog_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("changelog");
+    table.id = CHANGELOG_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("commit", 0u64)
+            .with_id(COMMIT)
+            .raw()
+            .chain(
+                ColumnSchema::with_default("row", 0u64)
+                    .with_id(CHANGE_ROW)
+                    .raw(),
+            ),
+    );
+    table.add_max(
+        ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
+            .with_id(CHANGED_TABLE)
+            .raw()
+            .chain(
+                ColumnSchema::with_default("deleted", false)
+                    .with_id(CHANGE_DELETED)
                     .raw(),
             )
+            .chain(
+                ColumnSchema::with_default("values", Vec::<u8>::new())
+                    .with_id(CHANGE_VALUES)
+                    .raw(),
+            ),
+    );
+    table
+}
+
+/// The schema of the dictionary of symbols, holding the string of each
+/// [`Symbol`](crate::Symbol) interned for each table
+pub fn symbol_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("symbols");
+    table.id = SYMBOL_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("table", TableId::const_new(b"TABLE--NOT-EXIST"))
+            .with_id(SYMBOL_OWNER)
+            .raw()
+            .chain(
+                ColumnSchema::with_default("symbol", 0u64)
+                    .with_id(SYMBOL_HASH)
+                    .raw(),
+            ),
+    );
+    table.add_max(
+        ColumnSchema::with_default("text", String::new())
+            .with_id(SYMBOL_TEXT)
+            .raw(),
+    );
+    table
+}
+
+/// The schema of the table listing the databases of a
+/// [`Catalog`](crate::Catalog)
+///
+/// Dropping a database saves a new row for it with a later `modified` time,
+/// which wins when the rows are merged.
+pub fn databases_schema() -> TableSchema {
+    use catalog::*;
+    let mut table = TableSchema::new("databases");
+    table.id = DATABASES_TABLE;
+    table.add_primary(
+        ColumnSchema::with_default("name", String::new())
+            .with_id(DATABASE_NAME)
+            .raw(),
+    );
+    table.add_max(
+        ColumnSchema::with_default("modified", std::time::SystemTime::UNIX_EPOCH)
+            .with_id(DATABASE_MODIFIED)
+            .raw()
             .chain(
                 ColumnSchema::with_default("is_deleted", false)
-                    .with_id(ColumnId::const_new(b"deleted-table!!!"))
+                    .with_id(DATABASE_DELETED)
                     .raw(),
             ),
     );
@@ -331,8 +2182,22 @@ fn format_db_tables() {
             modified.seconds U64 DEFAULT 0 LENS time::SystemTime,
             modified.subsecond_nanos U64 DEFAULT 0 LENS time::SystemTime,
             column_name Bytes DEFAULT '' LENS String,
+            fieldname Bytes DEFAULT '' LENS String,
+            lens Bytes DEFAULT 'LENS--NOT-EXIST!' LENS __LensId,
+            default Bytes DEFAULT '' LENS Vec<u8>,
+            group Bytes DEFAULT 'NOT-AGGREGATED!!' LENS __AggregationId,
+            is_deleted Bool DEFAULT false LENS bool,
+            overflow U64 DEFAULT 0 LENS __SumOverflow,
+            indexed Bool DEFAULT false LENS bool,
+            computed U64 DEFAULT 0 LENS ComputedDefault,
+            not_null Bool DEFAULT false LENS bool,
+            unique Bool DEFAULT false LENS bool,
+            references Bytes DEFAULT 'TABLE--NOT-EXIST' LENS __TableId,
+            collation U64 DEFAULT 0 LENS __Collation,
+            clock Bytes DEFAULT 'COLUMN-NOT-EXIST' LENS __ColumnId,
+            encoding U64 DEFAULT 0 LENS __Encoding,
             PRIMARY KEY ( table, column, order, aggregate ),
-            MAX ( modified.seconds, modified.subsecond_nanos, column_name ),
+            MAX ( modified.seconds, modified.subsecond_nanos, column_name, fieldname, lens, default, group, is_deleted, overflow, indexed, computed, not_null, unique, references, collation, clock, encoding ),
         };
     "#]];
     expected.assert_eq(table_schema_schema().to_string().as_str());
@@ -346,9 +2211,46 @@ fn format_db_tables() {
             modified.subsecond_nanos U64 DEFAULT 0 LENS time::SystemTime,
             table_name Bytes DEFAULT '' LENS String,
             is_deleted Bool DEFAULT false LENS bool,
+            time_column Bytes DEFAULT 'COLUMN-NOT-EXIST' LENS __ColumnId,
+            conflict_policy U64 DEFAULT 0 LENS __ConflictPolicy,
+            rollup_of Bytes DEFAULT 'TABLE--NOT-EXIST' LENS __TableId,
+            retention_nanos U64 DEFAULT 0 LENS u64,
+            partition_column Bytes DEFAULT 'COLUMN-NOT-EXIST' LENS __ColumnId,
+            partitions U64 DEFAULT 0 LENS u64,
+            sequence_column Bytes DEFAULT 'COLUMN-NOT-EXIST' LENS __ColumnId,
+            records_changes Bool DEFAULT false LENS bool,
             PRIMARY KEY ( table, created.seconds, created.subsecond_nanos ),
-            MAX ( modified.seconds, modified.subsecond_nanos, table_name, is_deleted ),
+            MAX ( modified.seconds, modified.subsecond_nanos, table_name, is_deleted, time_column, conflict_policy, rollup_of, retention_nanos, partition_column, partitions, sequence_column, records_changes ),
         };
     "#]];
     expected.assert_eq(db_schema_schema().to_string().as_str());
+
+    let expected = expect_test::expect![[r#"
+        CREATE TABLE scrub ID __scrub_results {
+            table Bytes DEFAULT 'TABLE--NOT-EXIST' LENS __TableId,
+            checked.seconds U64 DEFAULT 0 LENS time::SystemTime,
+            checked.subsecond_nanos U64 DEFAULT 0 LENS time::SystemTime,
+            segments U64 DEFAULT 0 LENS u64,
+            columns U64 DEFAULT 0 LENS u64,
+            corruptions U64 DEFAULT 0 LENS u64,
+            problems Bytes DEFAULT '' LENS String,
+            PRIMARY KEY ( table, checked.seconds, checked.subsecond_nanos ),
+            MAX ( segments, columns, corruptions, problems ),
+        };
+    "#]];
+    expected.assert_eq(scrub_schema().to_string().as_str());
+
+    let expected = expect_test::expect![[r#"
+        CREATE TABLE purges ID __purge_results {
+            table Bytes DEFAULT 'TABLE--NOT-EXIST' LENS __TableId,
+            purged.seconds U64 DEFAULT 0 LENS time::SystemTime,
+            purged.subsecond_nanos U64 DEFAULT 0 LENS time::SystemTime,
+            since U64 DEFAULT 0 LENS u64,
+            segments U64 DEFAULT 0 LENS u64,
+            rows U64 DEFAULT 0 LENS u64,
+            PRIMARY KEY ( table, purged.seconds, purged.subsecond_nanos ),
+            MAX ( since, segments, rows ),
+        };
+    "#]];
+    expected.assert_eq(purge_schema().to_string().as_str());
 }
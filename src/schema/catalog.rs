@@ -0,0 +1,414 @@
+//! Storing table schemas as rows of the schema tables.
+//!
+//! Every change to a schema is recorded by inserting new rows, and the
+//! `modified` time that leads each max aggregation decides which row wins.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime};
+
+use super::{
+    db_schema_schema, table_schema_schema, AggregatingSchema, Aggregation, Collation,
+    ComputedDefault, Constraint, Encoding, OrderedRawColumns, RawColumnSchema, SumOverflow,
+    TableSchema,
+};
+use crate::lens::{AggregationId, ColumnId, LensId, TableId};
+use crate::value::RawValue;
+use crate::{LensError, RawRow};
+
+pub(crate) const TABLES_TABLE: TableId = TableId::const_new(b"__db_schema_____");
+pub(crate) const COLUMNS_TABLE: TableId = TableId::const_new(b"__table_schemas_");
+
+pub(crate) const TABLE: ColumnId = ColumnId::const_new(b"table_id--tables");
+
+pub(crate) const COLUMN: ColumnId = ColumnId::const_new(b"column_id-tables");
+pub(crate) const ORDER: ColumnId = ColumnId::const_new(b"column-sortorder");
+pub(crate) const AGGREGATE: ColumnId = ColumnId::const_new(b"column-aggregate");
+pub(crate) const COLUMN_MODIFIED: ColumnId = ColumnId::const_new(b"modified-column!");
+pub(crate) const COLUMN_NAME: ColumnId = ColumnId::const_new(b"name-of-column!!");
+pub(crate) const FIELDNAME: ColumnId = ColumnId::const_new(b"column-fieldname");
+pub(crate) const LENS: ColumnId = ColumnId::const_new(b"column-lens-id!!");
+pub(crate) const DEFAULT: ColumnId = ColumnId::const_new(b"column-default!!");
+pub(crate) const GROUP: ColumnId = ColumnId::const_new(b"column-agg-group");
+pub(crate) const COLUMN_DELETED: ColumnId = ColumnId::const_new(b"column-deleted!!");
+pub(crate) const SUM_OVERFLOW: ColumnId = ColumnId::const_new(b"column-overflow!");
+pub(crate) const INDEXED: ColumnId = ColumnId::const_new(b"column-indexed!!");
+pub(crate) const COMPUTED_DEFAULT: ColumnId = ColumnId::const_new(b"column-computed!");
+pub(crate) const NOT_NULL: ColumnId = ColumnId::const_new(b"column-not-null!");
+pub(crate) const UNIQUE: ColumnId = ColumnId::const_new(b"column-unique!!!");
+pub(crate) const REFERENCES: ColumnId = ColumnId::const_new(b"column-refs-tbl!");
+pub(crate) const COLLATION: ColumnId = ColumnId::const_new(b"column-collation");
+pub(crate) const CLOCK: ColumnId = ColumnId::const_new(b"column-agg-clock");
+pub(crate) const ENCODING: ColumnId = ColumnId::const_new(b"column-encoding!");
+
+pub(crate) const CREATED: ColumnId = ColumnId::const_new(b"__table_created!");
+pub(crate) const TABLE_MODIFIED: ColumnId = ColumnId::const_new(b"modified-table!!");
+pub(crate) const TABLE_NAME: ColumnId = ColumnId::const_new(b"name-of-table!!!");
+pub(crate) const TABLE_DELETED: ColumnId = ColumnId::const_new(b"deleted-table!!!");
+pub(crate) const TIME_COLUMN: ColumnId = ColumnId::const_new(b"table-timecolumn");
+pub(crate) const CONFLICT_POLICY: ColumnId = ColumnId::const_new(b"table-onconflict");
+pub(crate) const ROLLUP_OF: ColumnId = ColumnId::const_new(b"table-rollup-of!");
+pub(crate) const RETENTION: ColumnId = ColumnId::const_new(b"table-retention!");
+pub(crate) const PARTITION_COLUMN: ColumnId = ColumnId::const_new(b"table-partition!");
+pub(crate) const PARTITIONS: ColumnId = ColumnId::const_new(b"table-partitions");
+pub(crate) const SEQUENCE_COLUMN: ColumnId = ColumnId::const_new(b"table-seqcolumn!");
+pub(crate) const RECORDS_CHANGES: ColumnId = ColumnId::const_new(b"table-changelog!");
+
+/// The column recording when each row was saved, see
+/// [`TableSchema::record_ingestion_time`]
+pub(crate) const INGESTED_AT: ColumnId = ColumnId::const_new(b"_ingested_at____");
+
+pub(crate) const SCRUB_TABLE: TableId = TableId::const_new(b"__scrub_results_");
+pub(crate) const SCRUBBED_TABLE: ColumnId = ColumnId::const_new(b"scrubbed-table!!");
+pub(crate) const SCRUBBED: ColumnId = ColumnId::const_new(b"scrub-checked-at");
+pub(crate) const SCRUB_SEGMENTS: ColumnId = ColumnId::const_new(b"scrub-segments!!");
+pub(crate) const SCRUB_COLUMNS: ColumnId = ColumnId::const_new(b"scrub-columns!!!");
+pub(crate) const SCRUB_CORRUPTIONS: ColumnId = ColumnId::const_new(b"scrub-corrupted!");
+pub(crate) const SCRUB_PROBLEMS: ColumnId = ColumnId::const_new(b"scrub-problems!!");
+
+pub(crate) const PURGE_TABLE: TableId = TableId::const_new(b"__purge_results_");
+pub(crate) const PURGED_TABLE: ColumnId = ColumnId::const_new(b"purged-table!!!!");
+pub(crate) const PURGED: ColumnId = ColumnId::const_new(b"purged-at!!!!!!!");
+pub(crate) const PURGE_SINCE: ColumnId = ColumnId::const_new(b"purged-since!!!!");
+pub(crate) const PURGE_SEGMENTS: ColumnId = ColumnId::const_new(b"purged-segments!");
+pub(crate) const PURGE_ROWS: ColumnId = ColumnId::const_new(b"purged-rows!!!!!");
+
+pub(crate) const WATERMARK_TABLE: TableId = TableId::const_new(b"__shard_watermks");
+pub(crate) const WATERMARKED_TABLE: ColumnId = ColumnId::const_new(b"watermark-table!");
+pub(crate) const WATERMARK_SHARD: ColumnId = ColumnId::const_new(b"watermark-shard!");
+pub(crate) const WATERMARK_SEQUENCE: ColumnId = ColumnId::const_new(b"watermark-seqnum");
+
+pub(crate) const CHANGELOG_TABLE: TableId = TableId::const_new(b"__changelog_____");
+pub(crate) const COMMIT: ColumnId = ColumnId::const_new(b"changelog-commit");
+pub(crate) const CHANGE_ROW: ColumnId = ColumnId::const_new(b"changelog-rownum");
+pub(crate) const CHANGED_TABLE: ColumnId = ColumnId::const_new(b"changelog-table!");
+pub(crate) const CHANGE_DELETED: ColumnId = ColumnId::const_new(b"changelog-delete");
+pub(crate) const CHANGE_VALUES: ColumnId = ColumnId::const_new(b"changelog-values");
+
+pub(crate) const SYMBOL_TABLE: TableId = TableId::const_new(b"__symbols_______");
+pub(crate) const SYMBOL_OWNER: ColumnId = ColumnId::const_new(b"symbol-table!!!!");
+pub(crate) const SYMBOL_HASH: ColumnId = ColumnId::const_new(b"symbol-hash!!!!!");
+pub(crate) const SYMBOL_TEXT: ColumnId = ColumnId::const_new(b"symbol-text!!!!!");
+
+pub(crate) const COUNTER_TABLE: TableId = TableId::const_new(b"__counters______");
+pub(crate) const COUNTED_TABLE: ColumnId = ColumnId::const_new(b"counter-table!!!");
+pub(crate) const COUNTED_COLUMN: ColumnId = ColumnId::const_new(b"counter-column!!");
+pub(crate) const COUNTER_NEXT: ColumnId = ColumnId::const_new(b"counter-next!!!!");
+
+pub(crate) const DATABASES_TABLE: TableId = TableId::const_new(b"__databases_____");
+pub(crate) const DATABASE_NAME: ColumnId = ColumnId::const_new(b"name-of-database");
+pub(crate) const DATABASE_MODIFIED: ColumnId = ColumnId::const_new(b"modified-databas");
+pub(crate) const DATABASE_DELETED: ColumnId = ColumnId::const_new(b"deleted-database");
+
+/// The group of primary key and summing columns
+pub(crate) const NO_GROUP: AggregationId = AggregationId::const_new(b"NOT-AGGREGATED!!");
+const NO_COLUMN: ColumnId = ColumnId::const_new(b"COLUMN-NOT-EXIST");
+const NO_TABLE: TableId = TableId::const_new(b"TABLE--NOT-EXIST");
+
+/// One raw column, as recorded in the `columns` schema table.
+#[derive(Debug, Clone)]
+pub(crate) struct CatalogColumn {
+    pub(crate) table: TableId,
+    pub(crate) order: u64,
+    pub(crate) aggregation: Aggregation,
+    pub(crate) group: AggregationId,
+    pub(crate) column: RawColumnSchema,
+    pub(crate) is_deleted: bool,
+    pub(crate) overflow: SumOverflow,
+    pub(crate) indexed: bool,
+    pub(crate) computed: ComputedDefault,
+    pub(crate) not_null: bool,
+    pub(crate) unique: bool,
+    pub(crate) references: Option<TableId>,
+    pub(crate) collation: Collation,
+    pub(crate) clock: Option<ColumnId>,
+    pub(crate) encoding: Encoding,
+}
+
+impl CatalogColumn {
+    /// The row recording this column in the `columns` table
+    pub(crate) fn to_row(&self, modified: SystemTime) -> RawRow {
+        table_schema_schema().row(vec![
+            (TABLE, self.table.into()),
+            (COLUMN, self.column.id.into()),
+            (ORDER, self.order.into()),
+            (AGGREGATE, self.aggregation.into()),
+            (COLUMN_MODIFIED, modified.into()),
+            (COLUMN_NAME, self.column.name.clone().into()),
+            (FIELDNAME, self.column.fieldname.clone().into()),
+            (LENS, self.column.lens.into()),
+            (DEFAULT, self.column.default.encode().into()),
+            (GROUP, self.group.into()),
+            (COLUMN_DELETED, self.is_deleted.into()),
+            (SUM_OVERFLOW, self.overflow.into()),
+            (INDEXED, self.indexed.into()),
+            (COMPUTED_DEFAULT, self.computed.into()),
+            (NOT_NULL, self.not_null.into()),
+            (UNIQUE, self.unique.into()),
+            (REFERENCES, self.references.unwrap_or(NO_TABLE).into()),
+            (COLLATION, self.collation.into()),
+            (CLOCK, self.clock.unwrap_or(NO_COLUMN).into()),
+            (ENCODING, self.encoding.into()),
+        ])
+    }
+
+    /// Read a row of the `columns` table
+    pub(crate) fn from_row(row: &RawRow) -> Result<Self, LensError> {
+        let schema = table_schema_schema();
+        let default: Vec<u8> = schema.get(row, DEFAULT)?;
+        let (default, _) = RawValue::decode(&default).map_err(|e| LensError::InvalidValue {
+            value: e.to_string(),
+        })?;
+        Ok(CatalogColumn {
+            table: schema.get(row, TABLE)?,
+            order: schema.get(row, ORDER)?,
+            aggregation: schema.get(row, AGGREGATE)?,
+            group: schema.get(row, GROUP)?,
+            column: RawColumnSchema {
+                default,
+                name: schema.get(row, COLUMN_NAME)?,
+                id: schema.get(row, COLUMN)?,
+                fieldname: schema.get(row, FIELDNAME)?,
+                lens: schema.get::<LensId>(row, LENS)?,
+            },
+            is_deleted: schema.get(row, COLUMN_DELETED)?,
+            overflow: schema.get(row, SUM_OVERFLOW)?,
+            indexed: schema.get(row, INDEXED)?,
+            computed: schema.get(row, COMPUTED_DEFAULT)?,
+            not_null: schema.get(row, NOT_NULL)?,
+            unique: schema.get(row, UNIQUE)?,
+            references: Some(schema.get(row, REFERENCES)?).filter(|t| *t != NO_TABLE),
+            collation: schema.get(row, COLLATION)?,
+            clock: Some(schema.get(row, CLOCK)?).filter(|c| *c != NO_COLUMN),
+            encoding: schema.get(row, ENCODING)?,
+        })
+    }
+}
+
+impl TableSchema {
+    /// The raw columns of this table, as recorded in the `columns` table
+    pub(crate) fn catalog_columns(&self) -> Vec<CatalogColumn> {
+        let primary = self
+            .primary
+            .iter()
+            .map(|(o, c)| (Aggregation::None, NO_GROUP, SumOverflow::Wrap, None, *o, c));
+        let aggregated = self.aggregations.iter().flat_map(|a| {
+            a.columns()
+                .map(move |(o, c)| (a.aggregation(), a.id(), a.overflow(), a.clock(), *o, c))
+        });
+        primary
+            .chain(aggregated)
+            .map(
+                |(aggregation, group, overflow, clock, order, column)| CatalogColumn {
+                    table: self.id,
+                    order,
+                    aggregation,
+                    group,
+                    column: column.clone(),
+                    is_deleted: false,
+                    overflow,
+                    indexed: self.is_indexed(column),
+                    computed: self.computed_default(column),
+                    not_null: self.has_constraint(column, Constraint::NotNull),
+                    unique: self.has_constraint(column, Constraint::Unique),
+                    references: self.reference(column),
+                    collation: self.collation(column),
+                    clock,
+                    encoding: self.encoding(column),
+                },
+            )
+            .collect()
+    }
+
+    /// The row recording this table in the `tables` table
+    pub(crate) fn catalog_row(
+        &self,
+        created: SystemTime,
+        modified: SystemTime,
+        is_deleted: bool,
+    ) -> RawRow {
+        // No retention is recorded as zero, which is never a retention.
+        let retention = self.retention.map(|r| r.as_nanos() as u64).unwrap_or(0);
+        let (partition_column, partitions) = self.partitioning.unwrap_or((NO_COLUMN, 0));
+        db_schema_schema().row(vec![
+            (TABLE, self.id.into()),
+            (CREATED, created.into()),
+            (TABLE_MODIFIED, modified.into()),
+            (TABLE_NAME, self.name.clone().into()),
+            (TABLE_DELETED, is_deleted.into()),
+            (TIME_COLUMN, self.time_column.unwrap_or(NO_COLUMN).into()),
+            (CONFLICT_POLICY, self.conflict_policy.into()),
+            (ROLLUP_OF, self.rollup_of.unwrap_or(NO_TABLE).into()),
+            (RETENTION, retention.into()),
+            (PARTITION_COLUMN, partition_column.into()),
+            (PARTITIONS, partitions.into()),
+            (
+                SEQUENCE_COLUMN,
+                self.sequence_column.unwrap_or(NO_COLUMN).into(),
+            ),
+            (RECORDS_CHANGES, self.records_changes.into()),
+        ])
+    }
+
+    /// Reconstruct the schemas of all tables that have not been deleted, along
+    /// with the time each was created, given the (aggregated) rows of the
+    /// `tables` and `columns` tables.
+    pub(crate) fn from_catalog(
+        tables: &[RawRow],
+        columns: &[RawRow],
+    ) -> Result<Vec<(SystemTime, TableSchema)>, LensError> {
+        let db = db_schema_schema();
+        let mut latest: BTreeMap<TableId, (SystemTime, &RawRow)> = BTreeMap::new();
+        for row in tables {
+            let id = db.get(row, TABLE)?;
+            let modified = db.get(row, TABLE_MODIFIED)?;
+            if latest.get(&id).map(|(m, _)| *m < modified).unwrap_or(true) {
+                latest.insert(id, (modified, row));
+            }
+        }
+
+        let mut schemas = BTreeMap::new();
+        for (id, (_, row)) in latest {
+            if db.get(row, TABLE_DELETED)? {
+                continue;
+            }
+            let time_column: ColumnId = db.get(row, TIME_COLUMN)?;
+            let rollup_of: TableId = db.get(row, ROLLUP_OF)?;
+            let retention: u64 = db.get(row, RETENTION)?;
+            let partition_column: ColumnId = db.get(row, PARTITION_COLUMN)?;
+            let partitions: u64 = db.get(row, PARTITIONS)?;
+            let sequence_column: ColumnId = db.get(row, SEQUENCE_COLUMN)?;
+            schemas.insert(
+                id,
+                (
+                    db.get::<SystemTime>(row, CREATED)?,
+                    TableSchema {
+                        name: db.get(row, TABLE_NAME)?,
+                        id,
+                        primary: OrderedRawColumns::new(),
+                        aggregations: Default::default(),
+                        time_column: Some(time_column).filter(|c| *c != NO_COLUMN),
+                        sequence_column: Some(sequence_column).filter(|c| *c != NO_COLUMN),
+                        conflict_policy: db.get(row, CONFLICT_POLICY)?,
+                        indexes: BTreeSet::new(),
+                        computed: BTreeMap::new(),
+                        constraints: BTreeSet::new(),
+                        references: BTreeMap::new(),
+                        collations: BTreeMap::new(),
+                        encodings: BTreeMap::new(),
+                        rollup_of: Some(rollup_of).filter(|t| *t != NO_TABLE),
+                        retention: Some(Duration::from_nanos(retention)).filter(|r| !r.is_zero()),
+                        partitioning: Some((partition_column, partitions))
+                            .filter(|(c, _)| *c != NO_COLUMN),
+                        records_changes: db.get(row, RECORDS_CHANGES)?,
+                    },
+                    BTreeMap::<(Aggregation, AggregationId, ColumnId), OrderedRawColumns>::new(),
+                    BTreeMap::<(Aggregation, ColumnId), OrderedRawColumns>::new(),
+                ),
+            );
+        }
+
+        for row in columns {
+            let c = CatalogColumn::from_row(row)?;
+            if c.is_deleted {
+                continue;
+            }
+            let Some((_, schema, groups, widened)) = schemas.get_mut(&c.table) else {
+                continue;
+            };
+            if c.indexed {
+                schema.indexes.insert(c.column.id);
+            }
+            if c.computed != ComputedDefault::Constant {
+                schema.computed.insert(c.column.id, c.computed);
+            }
+            if c.not_null {
+                schema
+                    .constraints
+                    .insert((c.column.id, Constraint::NotNull));
+            }
+            if c.unique {
+                schema.constraints.insert((c.column.id, Constraint::Unique));
+            }
+            if let Some(table) = c.references {
+                schema.references.insert(c.column.id, table);
+            }
+            if c.collation != Collation::Binary {
+                schema.collations.insert(c.column.id, c.collation);
+            }
+            if c.encoding != Encoding::Auto {
+                schema.encodings.insert(c.column.id, c.encoding);
+            }
+            match c.aggregation {
+                Aggregation::None => {
+                    schema.primary.insert((c.order, c.column));
+                }
+                // Both raw columns of a widened sum belong to one column.
+                Aggregation::Sum if c.overflow == SumOverflow::Widen => {
+                    widened
+                        .entry((c.aggregation, c.column.id))
+                        .or_default()
+                        .insert((c.order, c.column));
+                }
+                // Both raw columns of an average belong to one column too.
+                Aggregation::Avg => {
+                    widened
+                        .entry((c.aggregation, c.column.id))
+                        .or_default()
+                        .insert((c.order, c.column));
+                }
+                Aggregation::Count => {
+                    schema.aggregations.insert(AggregatingSchema::Count {
+                        columns: [(0, c.column)].into_iter().collect(),
+                    });
+                }
+                Aggregation::Sketch => {
+                    schema.aggregations.insert(AggregatingSchema::Sketch {
+                        columns: [(0, c.column)].into_iter().collect(),
+                    });
+                }
+                Aggregation::Sum => {
+                    schema.aggregations.insert(AggregatingSchema::Sum {
+                        columns: [(0, c.column)].into_iter().collect(),
+                        overflow: c.overflow,
+                    });
+                }
+                Aggregation::Max
+                | Aggregation::Min
+                | Aggregation::FirstBy
+                | Aggregation::LastBy => {
+                    groups
+                        .entry((c.aggregation, c.group, c.clock.unwrap_or(NO_COLUMN)))
+                        .or_default()
+                        .insert((c.order, c.column));
+                }
+            }
+        }
+
+        Ok(schemas
+            .into_values()
+            .map(|(created, mut schema, groups, widened)| {
+                for ((aggregation, id, clock), columns) in groups {
+                    schema.aggregations.insert(match aggregation {
+                        Aggregation::Max => AggregatingSchema::Max { columns, id },
+                        Aggregation::FirstBy => AggregatingSchema::FirstBy { columns, id, clock },
+                        Aggregation::LastBy => AggregatingSchema::LastBy { columns, id, clock },
+                        _ => AggregatingSchema::Min { columns, id },
+                    });
+                }
+                for ((aggregation, _), columns) in widened {
+                    schema
+                        .aggregations
+                        .insert(if aggregation == Aggregation::Avg {
+                            AggregatingSchema::Avg { columns }
+                        } else {
+                            AggregatingSchema::Sum {
+                                columns,
+                                overflow: SumOverflow::Widen,
+                            }
+                        });
+                }
+                (created, schema)
+            })
+            .collect())
+    }
+}
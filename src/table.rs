@@ -0,0 +1,2647 @@
+//! Tables, stored as a set of immutable segments.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::column::encoding::StorageError;
+use crate::column::{ColumnCache, EncodeOptions};
+use crate::expr::{Comparison, Predicate, Selection, Test};
+use crate::fs;
+use crate::lens::{
+    decimal_from_raw, decimal_scale, decimal_to_raw, ColumnId, QuantileSketch, RawValues, Symbol,
+};
+use crate::query::column_index;
+use crate::schema::{AggregatingSchema, ComputedDefault, ConflictPolicy, Constraint, SumOverflow};
+use crate::{DbLayout, Expr, FlatLayout, Metrics, RawColumn, RawRow, RawValue, TableSchema};
+
+mod backup;
+mod display;
+mod histogram;
+mod index;
+mod inspect;
+mod manifest;
+mod plan;
+mod rollup;
+mod row_id;
+mod salvage;
+mod sample;
+mod scan;
+mod scrub;
+mod snapshot;
+mod spill;
+mod typed;
+mod verify;
+
+pub(crate) use backup::{manifest_files, PinnedFiles, Restoring};
+pub use display::DisplayRows;
+pub use inspect::{SegmentColumn, SegmentFiles, TableFiles};
+pub use row_id::RowId;
+pub use salvage::{SalvageReport, SalvagedSegment};
+pub use scrub::{Corruption, ScrubReport};
+pub use snapshot::AsOf;
+use snapshot::Pin;
+pub use typed::{BoolBuilder, BytesBuilder, ColumnBuilder, TypedTableBuilder, U64Builder};
+pub use verify::{Inconsistency, InconsistencyKind, VerifyReport};
+
+use histogram::Histogram;
+use index::SegmentIndex;
+use plan::Plan;
+use spill::Spill;
+
+pub(crate) use manifest::Segment;
+
+use manifest::{
+    checksum, ColumnData, ColumnFile, Manifest, INLINE_MANIFEST_LIMIT, INLINE_SEGMENT_LIMIT,
+};
+
+/// Rows waiting to be saved as a new segment of a table.
+#[derive(Debug)]
+pub struct TableBuilder {
+    schema: TableSchema,
+    rows: Vec<RawRow>,
+    spill: Option<Spill>,
+    options: EncodeOptions,
+    layout: Arc<dyn DbLayout>,
+    /// The largest sequence number of each shard among the rows, if the
+    /// schema has a sequence column
+    watermarks: BTreeMap<u64, u64>,
+    /// The strings interned for the rows, by their symbols
+    symbols: BTreeMap<Symbol, String>,
+    /// The next value of each auto-incremented column
+    counters: BTreeMap<ColumnId, u64>,
+}
+
+impl TableBuilder {
+    /// Start building rows for a table with this schema
+    pub fn new(schema: &TableSchema) -> Self {
+        TableBuilder {
+            schema: schema.clone(),
+            rows: Vec::new(),
+            spill: None,
+            options: EncodeOptions::default(),
+            layout: Arc::new(FlatLayout),
+            watermarks: BTreeMap::new(),
+            symbols: BTreeMap::new(),
+            counters: BTreeMap::new(),
+        }
+    }
+
+    /// Split long runs of identical values into chunks of no more than
+    /// `rows` rows, see [`EncodeOptions::max_chunk_rows`].
+    pub fn max_chunk_rows(mut self, rows: u64) -> Self {
+        self.options = self.options.max_chunk_rows(rows);
+        self
+    }
+
+    /// Name the column files of the new segments by `layout`, rather than
+    /// by [`FlatLayout`]
+    pub fn layout(mut self, layout: Arc<dyn DbLayout>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Hold no more than about `memory_budget` bytes of rows in memory.
+    ///
+    /// Whenever the rows exceed the budget they are sorted and written as a
+    /// run of temporary column files in `dir`, and the runs are merged when
+    /// the builder is saved.  The merged rows are then saved as however many
+    /// segments are needed to keep each within the budget.
+    pub fn spill_to<P: AsRef<Path>>(mut self, dir: P, memory_budget: usize) -> Self {
+        self.spill = Some(Spill::new(dir.as_ref(), memory_budget));
+        self
+    }
+
+    /// Add a row, which must match the schema
+    pub fn insert_raw_row(&mut self, row: RawRow) -> Result<(), StorageError> {
+        let row = self.accept_row(row)?;
+        if let Some(s) = self.schema.shard_sequence(&row) {
+            let watermark = self.watermarks.entry(s.shard).or_default();
+            *watermark = std::cmp::max(*watermark, s.sequence);
+        }
+        if let Some(spill) = &mut self.spill {
+            if spill.add(&row) {
+                let mut rows = std::mem::take(&mut self.rows);
+                rows.push(row);
+                return spill.write_run(&self.schema, rows);
+            }
+        }
+        self.rows.push(row);
+        Ok(())
+    }
+
+    /// Check that `row` matches the schema and keeps the constraints of its
+    /// columns, returning it with its computed defaults, without adding it
+    pub(crate) fn accept_row(&mut self, mut row: RawRow) -> Result<RawRow, StorageError> {
+        check_row(&self.schema, &row)?;
+        self.compute_defaults(&mut row);
+        check_constraints(&self.schema, std::slice::from_ref(&row))?;
+        Ok(row)
+    }
+
+    /// Replace the default of each column whose default is computed, see
+    /// [`ComputedDefault`]
+    fn compute_defaults(&mut self, row: &mut RawRow) {
+        for (id, computed, positions) in self.schema.computed_columns() {
+            let is_default = positions.iter().all(|&i| {
+                let c = self.schema.raw_columns().nth(i).expect("column exists");
+                &row.values[i] == c.default()
+            });
+            match computed {
+                ComputedDefault::Constant => (),
+                ComputedDefault::Now if is_default => {
+                    let now = RawValues::from(SystemTime::now());
+                    for (&i, v) in positions.iter().zip(now.0) {
+                        row.values[i] = v;
+                    }
+                }
+                ComputedDefault::Now => (),
+                ComputedDefault::AutoIncrement => {
+                    let next = self.counters.entry(id).or_insert(1);
+                    if is_default {
+                        row.values[positions[0]] = RawValue::U64(*next);
+                        *next += 1;
+                    } else if let RawValue::U64(v) = row.values[positions[0]] {
+                        *next = std::cmp::max(*next, v.saturating_add(1));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The next value of each auto-incremented column
+    pub(crate) fn counters(&self) -> &BTreeMap<ColumnId, u64> {
+        &self.counters
+    }
+
+    /// Continue counting each auto-incremented column from `counters`
+    pub(crate) fn with_counters(mut self, counters: BTreeMap<ColumnId, u64>) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    /// The symbol of `text`, which is saved in the dictionary of the table
+    /// along with the rows when they are saved through a
+    /// [`TableHandle`](crate::TableHandle), see [`Symbol`].
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        let symbol = Symbol::of(text);
+        self.symbols
+            .entry(symbol)
+            .or_insert_with(|| text.to_string());
+        symbol
+    }
+
+    /// The strings interned for the rows, by their symbols
+    pub(crate) fn symbols(&self) -> &BTreeMap<Symbol, String> {
+        &self.symbols
+    }
+
+    /// The rows added, unless they have been spilled to disk
+    pub(crate) fn rows(&self) -> &[RawRow] {
+        &self.rows
+    }
+
+    /// The largest sequence number of each shard among the rows added, see
+    /// [`TableSchema::set_sequence_column`]
+    pub(crate) fn watermarks(&self) -> &BTreeMap<u64, u64> {
+        &self.watermarks
+    }
+
+    /// Save the rows as a new segment of the table in `dir`.
+    ///
+    /// The column files are written and synced before the table manifest is
+    /// atomically replaced, so a crash part way through leaves the table as it
+    /// was before the save.
+    pub fn save<P: AsRef<Path>>(self, dir: P) -> Result<(), StorageError> {
+        let dir = dir.as_ref();
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("save_table", dir = %dir.display(), rows = self.rows.len())
+                .entered();
+        fs::create_dir_all(dir)?;
+        if let Some(spill) = self.spill.filter(|s| s.has_runs()) {
+            return spill.save(dir, &*self.layout, &self.schema, self.rows, self.options);
+        }
+        let mut manifest = Manifest::read(dir)?;
+        if self.rows.is_empty() {
+            return manifest.write(dir);
+        }
+        let mut rows = merge_rows(&self.schema, self.rows.into_iter().zip(0..).collect())?;
+        check_constraints(&self.schema, &rows)?;
+        check_saved_keys(dir, &self.schema, &manifest, &rows)?;
+
+        manifest.new_version();
+        stamp_ingestion(&self.schema, &mut rows, manifest.time);
+        write_rows(
+            dir,
+            &*self.layout,
+            &mut manifest,
+            &self.schema,
+            &rows,
+            self.options,
+        )?;
+        manifest.write(dir)
+    }
+}
+
+/// Check that `row` has a value of the right kind for every raw column
+pub(crate) fn check_row(schema: &TableSchema, row: &RawRow) -> Result<(), StorageError> {
+    if row.values.len() != schema.raw_columns().count() {
+        return Err(StorageError::InvalidRow("wrong number of values"));
+    }
+    for (v, c) in row.values.iter().zip(schema.raw_columns()) {
+        if v.kind() != c.kind() {
+            return Err(StorageError::InvalidRow("value has the wrong kind"));
+        }
+    }
+    Ok(())
+}
+
+/// Fail if one of `rows` repeats the primary key of a row saved in the
+/// current version of the table in `dir`, as listed in `manifest`, when its
+/// conflict policy rejects that, see [`ConflictPolicy::Error`].  Only the
+/// segments and chunks that can hold the keys of `rows` are read.
+fn check_saved_keys(
+    dir: &Path,
+    schema: &TableSchema,
+    manifest: &Manifest,
+    rows: &[RawRow],
+) -> Result<(), StorageError> {
+    if schema.conflict_policy() != ConflictPolicy::Error
+        || manifest.segments.is_empty()
+        || rows.is_empty()
+    {
+        return Ok(());
+    }
+    let num_primary = schema.num_primary();
+    let keys = rows
+        .iter()
+        .map(|r| r.values[..num_primary].to_vec())
+        .collect::<BTreeSet<_>>();
+    let projection = projection(schema, &[]);
+    let table = Table::read_segments(dir, schema, projection, None, manifest, manifest.version)?;
+    let found = table.select(&keys_condition(schema, &keys))?;
+    if found
+        .iter()
+        .any(|r| keys.contains(&r.values[..num_primary]))
+    {
+        return Err(StorageError::DuplicateKey(schema.name().to_string()));
+    }
+    Ok(())
+}
+
+/// A condition that the rows whose primary keys are among `keys` meet, along
+/// with any others sharing the value of each raw column of the key with one
+/// of them
+pub(crate) fn keys_condition(schema: &TableSchema, keys: &BTreeSet<Vec<RawValue>>) -> Expr {
+    schema
+        .raw_columns()
+        .take(schema.num_primary())
+        .enumerate()
+        .map(|(i, c)| {
+            let values = keys.iter().map(|k| k[i].clone()).collect();
+            Expr::In(c.display_name(), values)
+        })
+        .reduce(Expr::and)
+        .expect("a table has a primary key")
+}
+
+/// Check that merged `rows` keep the constraints of the schema, see
+/// [`TableSchema::add_constraint`]
+fn check_constraints(schema: &TableSchema, rows: &[RawRow]) -> Result<(), StorageError> {
+    let defaults = schema
+        .raw_columns()
+        .map(|c| c.default())
+        .collect::<Vec<_>>();
+    for (id, constraint, positions) in schema.constrained_columns() {
+        if constraint != Constraint::NotNull {
+            continue;
+        }
+        for row in rows {
+            if is_default(&defaults, row, &positions) {
+                return Err(broken_constraint(schema, id, constraint, row));
+            }
+        }
+    }
+    check_unique_values(schema, rows, &mut UniqueValues::new())
+}
+
+/// The values of each unique column among the rows checked so far, see
+/// [`check_unique_values`]
+pub(crate) type UniqueValues = BTreeMap<ColumnId, BTreeSet<Vec<RawValue>>>;
+
+/// Check that no two of merged `rows`, nor one of them and one checked
+/// before into `seen`, share a value of a unique column
+pub(crate) fn check_unique_values(
+    schema: &TableSchema,
+    rows: &[RawRow],
+    seen: &mut UniqueValues,
+) -> Result<(), StorageError> {
+    let defaults = schema
+        .raw_columns()
+        .map(|c| c.default())
+        .collect::<Vec<_>>();
+    for (id, constraint, positions) in schema.constrained_columns() {
+        if constraint != Constraint::Unique {
+            continue;
+        }
+        let seen = seen.entry(id).or_default();
+        for row in rows {
+            if is_default(&defaults, row, &positions) {
+                continue;
+            }
+            let value = positions.iter().map(|&i| row.values[i].clone()).collect();
+            if !seen.insert(value) {
+                return Err(broken_constraint(schema, id, constraint, row));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the raw columns of `row` at `positions` all hold their
+/// `defaults`
+fn is_default(defaults: &[&RawValue], row: &RawRow, positions: &[usize]) -> bool {
+    positions.iter().all(|&i| &row.values[i] == defaults[i])
+}
+
+/// The error for `row` breaking the `constraint` of column `id`
+fn broken_constraint(
+    schema: &TableSchema,
+    id: ColumnId,
+    constraint: Constraint,
+    row: &RawRow,
+) -> StorageError {
+    let column = schema.raw_columns().find(|c| c.id() == id);
+    StorageError::Constraint {
+        table: schema.name().to_string(),
+        column: column.expect("column exists").name().to_string(),
+        constraint,
+        key: row.values[..schema.num_primary()].to_vec(),
+    }
+}
+
+/// Stamp `rows` with the time they are saved, if the schema records it
+fn stamp_ingestion(schema: &TableSchema, rows: &mut [RawRow], time: u64) {
+    if let Some(i) = schema.ingestion_index() {
+        for row in rows.iter_mut() {
+            row.values[i] = RawValue::U64(time);
+        }
+    }
+}
+
+/// Write sorted `rows` as new segments of the table in `dir`, encoded with
+/// `options` into files named by `layout`, and add them to `manifest`
+/// without saving it.
+///
+/// The rows of a partitioned table are split into a segment for each
+/// partition that holds any of them.
+fn write_rows(
+    dir: &Path,
+    layout: &dyn DbLayout,
+    manifest: &mut Manifest,
+    schema: &TableSchema,
+    rows: &[RawRow],
+    options: EncodeOptions,
+) -> Result<(), StorageError> {
+    let Some((i, _)) = schema.partition_index() else {
+        return write_segment(dir, layout, manifest, schema, None, rows, options);
+    };
+    let mut partitions = BTreeMap::<u64, Vec<RawRow>>::new();
+    for row in rows {
+        let partition = schema.partition_of(&row.values[i]).expect("partitioned");
+        partitions.entry(partition).or_default().push(row.clone());
+    }
+    for (partition, rows) in partitions {
+        write_segment(
+            dir,
+            layout,
+            manifest,
+            schema,
+            Some(partition),
+            &rows,
+            options,
+        )?;
+    }
+    Ok(())
+}
+
+/// Write and sync the column files of a segment of sorted `rows`, encoded
+/// with `options`, and add it to `manifest`.
+fn write_segment(
+    dir: &Path,
+    layout: &dyn DbLayout,
+    manifest: &mut Manifest,
+    schema: &TableSchema,
+    partition: Option<u64>,
+    rows: &[RawRow],
+    options: EncodeOptions,
+) -> Result<(), StorageError> {
+    let mut encoded = Vec::new();
+    for (i, c) in schema.raw_columns().enumerate() {
+        let values: Vec<RawValue> = rows.iter().map(|r| r.values[i].clone()).collect();
+        let mut bytes = Vec::new();
+        let options = options.prefer(schema.encoding(c).formats());
+        RawColumn::write_values_with(&mut bytes, c.kind(), &values, options)?;
+        encoded.push(bytes);
+    }
+    let max_time = schema.time_index().and_then(|i| {
+        rows.iter()
+            .filter_map(|r| match r.values[i] {
+                RawValue::U64(t) => Some(t),
+                _ => None,
+            })
+            .max()
+    });
+    write_encoded(
+        dir,
+        layout,
+        manifest,
+        schema,
+        partition,
+        rows.len() as u64,
+        max_time,
+        encoded,
+    )
+}
+
+/// Write and sync the already encoded raw columns of a segment, in schema
+/// order, along with the index of each indexed column, and add the segment
+/// to `manifest` with the histogram of each column.
+///
+/// A small enough segment is kept inline in the manifest instead, provided
+/// the manifest has room for it.  Otherwise the files are named by `layout`,
+/// and any directories it puts them in are created.
+#[allow(clippy::too_many_arguments)]
+fn write_encoded(
+    dir: &Path,
+    layout: &dyn DbLayout,
+    manifest: &mut Manifest,
+    schema: &TableSchema,
+    partition: Option<u64>,
+    num_rows: u64,
+    max_time: Option<u64>,
+    encoded: Vec<Vec<u8>>,
+) -> Result<(), StorageError> {
+    let id = manifest.next_segment;
+    let mut columns = Vec::new();
+    for (c, bytes) in schema.raw_columns().zip(encoded) {
+        if schema.is_indexed(c) {
+            let fieldnames = index::fieldnames(c.fieldname());
+            for (fieldname, bytes) in fieldnames.into_iter().zip(index::encode(&bytes)?) {
+                columns.push((c, fieldname, bytes, None));
+            }
+        }
+        let histogram = Histogram::of(&RawColumn::decode(bytes.clone())?)?;
+        columns.push((c, c.fieldname().to_string(), bytes, histogram));
+    }
+    let size: usize = columns.iter().map(|(_, _, bytes, _)| bytes.len()).sum();
+    let inline =
+        size <= INLINE_SEGMENT_LIMIT && manifest.inline_bytes() + size <= INLINE_MANIFEST_LIMIT;
+
+    // The directories holding the column files, which are synced once the
+    // files are written.
+    let mut subdirs = BTreeSet::new();
+    let mut files = Vec::new();
+    for (c, fieldname, bytes, histogram) in columns {
+        let sum = checksum(&bytes);
+        let data = if inline {
+            ColumnData::Inline(bytes)
+        } else {
+            let filename = layout.column_file(id, partition, c.id(), &fieldname);
+            let path = dir.join(&filename);
+            let subdir = path.parent().unwrap_or(dir).to_path_buf();
+            if subdir != dir && !subdirs.contains(&subdir) {
+                fs::create_dir_all(&subdir)?;
+                for parent in subdir
+                    .ancestors()
+                    .skip(1)
+                    .take_while(|p| p.starts_with(dir))
+                {
+                    fs::sync_dir(parent)?;
+                }
+            }
+            fs::write_synced(&path, &bytes)?;
+            subdirs.insert(subdir);
+            ColumnData::File(filename)
+        };
+        files.push(ColumnFile {
+            column: c.id(),
+            fieldname,
+            data,
+            checksum: Some(sum),
+            fingerprint: Some(c.fingerprint()),
+            histogram,
+        });
+    }
+    for subdir in subdirs {
+        fs::sync_dir(&subdir)?;
+    }
+    manifest.segments.push(Segment {
+        id,
+        num_rows,
+        max_time,
+        partition,
+        files,
+    });
+    manifest.next_segment = id + 1;
+    Ok(())
+}
+
+/// The number of bytes of the encoded columns of `segments`, including their
+/// indexes, whether they are in files or inline in the manifest
+pub(crate) fn segments_size<'a>(
+    dir: &Path,
+    segments: impl IntoIterator<Item = &'a Segment>,
+) -> Result<u64, StorageError> {
+    let mut size = 0;
+    for f in segments.into_iter().flat_map(|s| s.files.iter()) {
+        size += match &f.data {
+            ColumnData::File(filename) => fs::file_len(&dir.join(filename))?,
+            ColumnData::Inline(bytes) => bytes.len() as u64,
+        };
+    }
+    Ok(size)
+}
+
+/// Remove the files of segments that are no longer in the manifest, along
+/// with any directories within that of the table that they leave empty
+fn remove_segment_files(dir: &Path, segments: &[Segment]) -> Result<(), StorageError> {
+    remove_files(
+        dir,
+        segments
+            .iter()
+            .flat_map(|s| s.files.iter())
+            .filter_map(|f| f.filename()),
+    )
+}
+
+/// Remove column files of the table in `dir`, along with any directories
+/// within it that they leave empty
+fn remove_files<'a>(
+    dir: &Path,
+    filenames: impl Iterator<Item = &'a str>,
+) -> Result<(), StorageError> {
+    let mut subdirs = BTreeSet::new();
+    for filename in filenames {
+        let path = dir.join(filename);
+        fs::remove_file(&path)?;
+        subdirs.extend(
+            path.ancestors()
+                .skip(1)
+                .take_while(|p| *p != dir && p.starts_with(dir))
+                .map(Path::to_path_buf),
+        );
+    }
+    // The deepest directories are removed first.
+    for subdir in subdirs.iter().rev() {
+        fs::remove_dir_if_empty(subdir)?;
+    }
+    Ok(())
+}
+
+/// A table that has been saved to disk.
+pub struct Table {
+    schema: TableSchema,
+    /// Which raw columns were read, the rest hold their defaults
+    projection: Vec<bool>,
+    segments: Vec<SegmentColumns>,
+    /// The version that was read
+    version: u64,
+    /// When the version that was read was made, in nanoseconds since the
+    /// epoch
+    time: u64,
+    /// Counts the reads and decoding done on the columns of the table
+    metrics: Metrics,
+    /// The number of bytes of the encoded columns of the segments read
+    on_disk_size: u64,
+    /// Holds the values of columns decoded before, when the table was read
+    /// through a [`Database`](crate::Database)
+    cache: Option<ColumnCache>,
+    /// Keeps the segments of the version from being removed while they are
+    /// read
+    _pin: Option<Pin>,
+}
+
+/// The rows that compacting a table purged, see [`Table::compact`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Purge {
+    since: Option<u64>,
+    segments: u64,
+    rows: u64,
+}
+
+impl Purge {
+    /// The earliest time of the rows that were kept, if the table has a
+    /// retention
+    pub fn since(&self) -> Option<u64> {
+        self.since
+    }
+    /// The number of segments dropped without being read
+    pub fn segments(&self) -> u64 {
+        self.segments
+    }
+    /// The number of rows purged, including those of the segments dropped
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+}
+
+/// Which raw columns of `schema` must be read to give the values of
+/// `columns`, which are those along with the primary key and the whole of any
+/// max or min group holding one of them, since they are needed to merge rows
+fn projection(schema: &TableSchema, columns: &[ColumnId]) -> Vec<bool> {
+    let mut projection: Vec<bool> = schema
+        .raw_columns()
+        .enumerate()
+        .map(|(i, c)| i < schema.num_primary() || columns.contains(&c.id()))
+        .collect();
+    for (_, range) in schema.aggregation_ranges() {
+        if projection[range.clone()].iter().any(|p| *p) {
+            projection[range].iter_mut().for_each(|p| *p = true);
+        }
+    }
+    projection
+}
+
+/// The size of a table, as listed in its manifest, see [`Table::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableStats {
+    segments: u64,
+    rows: u64,
+    on_disk_size: u64,
+}
+
+impl TableStats {
+    /// The number of segments
+    pub fn segments(&self) -> u64 {
+        self.segments
+    }
+    /// The number of rows stored, before rows sharing a primary key are
+    /// merged, see [`Table::num_rows`]
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+    /// The number of bytes of encoded columns, see [`Table::on_disk_size`]
+    pub fn on_disk_size(&self) -> u64 {
+        self.on_disk_size
+    }
+}
+
+/// The raw columns of one segment, with `None` for those that read as their
+/// default.
+struct SegmentColumns {
+    /// The id of the segment, unless its rows are not saved
+    id: Option<u64>,
+    num_rows: u64,
+    columns: Vec<Option<RawColumn>>,
+    /// The index of each raw column that was read, if the segment has one
+    indexes: Vec<Option<SegmentIndex>>,
+    /// The histogram of each raw column that was read, if the segment has one
+    histograms: Vec<Option<Histogram>>,
+}
+
+impl Table {
+    /// Open the table stored in `dir`
+    ///
+    /// A segment saved before a column was added has no file for it, so the
+    /// column reads as its default in every row of that segment.
+    pub fn read<P: AsRef<Path>>(dir: P, schema: &TableSchema) -> Result<Self, StorageError> {
+        let projection = vec![true; schema.raw_columns().count()];
+        Table::read_columns(dir.as_ref(), schema, projection)
+    }
+
+    /// Open the table stored in `dir`, reading only the given columns.
+    ///
+    /// Every other column reads as its default.  The primary key, and the
+    /// whole of any max or min group holding a requested column, are read
+    /// anyway because they are needed to merge rows.
+    pub fn read_projected<P: AsRef<Path>>(
+        dir: P,
+        schema: &TableSchema,
+        columns: &[ColumnId],
+    ) -> Result<Self, StorageError> {
+        Table::read_columns(dir.as_ref(), schema, projection(schema, columns))
+    }
+
+    fn read_columns(
+        dir: &Path,
+        schema: &TableSchema,
+        projection: Vec<bool>,
+    ) -> Result<Self, StorageError> {
+        Table::read_pinned(dir, schema, projection, None, false, |manifest| {
+            Some(manifest.version)
+        })
+    }
+
+    /// Open the table stored in `dir`, to read the rows that match `expr`
+    /// with [`Table::select`].
+    ///
+    /// When the table is [partitioned](TableSchema::partition_by) and `expr`
+    /// requires the partition column to equal one of some values, only the
+    /// segments of the partitions that can hold those values are opened,
+    /// along with any saved before the table was partitioned.  The rows of
+    /// the other partitions are then missing from the table.
+    pub fn read_where<P: AsRef<Path>>(
+        dir: P,
+        schema: &TableSchema,
+        expr: &Expr,
+    ) -> Result<Self, StorageError> {
+        let predicate = expr.bind(schema)?;
+        let partitions = schema.partition_index().and_then(|(i, _)| {
+            let values = predicate.required_values(i)?;
+            Some(
+                values
+                    .iter()
+                    .filter_map(|v| schema.partition_of(v))
+                    .collect::<BTreeSet<_>>(),
+            )
+        });
+        let projection = vec![true; schema.raw_columns().count()];
+        Table::read_pinned(
+            dir.as_ref(),
+            schema,
+            projection,
+            partitions.as_ref(),
+            false,
+            |m| Some(m.version),
+        )
+    }
+
+    /// Open the segments of a version of the table in `dir`, as listed in
+    /// `manifest`, skipping those of partitions other than `partitions`
+    fn read_segments(
+        dir: &Path,
+        schema: &TableSchema,
+        projection: Vec<bool>,
+        partitions: Option<&BTreeSet<u64>>,
+        manifest: &Manifest,
+        version: u64,
+    ) -> Result<Self, StorageError> {
+        let (time, manifest_segments) = manifest
+            .version_time(version)
+            .zip(manifest.version_segments(version))
+            .ok_or_else(|| StorageError::NoSuchVersion(format!("{version}")))?;
+        let metrics = Metrics::default();
+        let mut segments = Vec::new();
+        let is_wanted = |s: &&Segment| match (s.partition, partitions) {
+            (Some(p), Some(partitions)) => partitions.contains(&p),
+            _ => true,
+        };
+        for s in manifest_segments.iter().filter(is_wanted) {
+            s.check_schema(schema)?;
+            let mut columns = Vec::new();
+            let mut indexes = Vec::new();
+            let mut histograms = Vec::new();
+            for (c, wanted) in schema.raw_columns().zip(projection.iter()) {
+                let open_column = |fieldname: &str| {
+                    let Some(file) = s.file(c.id(), fieldname).filter(|_| *wanted) else {
+                        return Ok(None);
+                    };
+                    #[cfg(feature = "tracing")]
+                    let start = std::time::Instant::now();
+                    let column = match &file.data {
+                        ColumnData::File(filename) => {
+                            fs::open_column(&dir.join(filename), &metrics)?
+                        }
+                        ColumnData::Inline(bytes) => {
+                            RawColumn::decode_with_metrics(bytes.clone(), &metrics)?
+                        }
+                    };
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        column = c.name(),
+                        fieldname,
+                        format = ?column.format(),
+                        rows = column.num_rows(),
+                        micros = start.elapsed().as_micros() as u64,
+                        "opened column"
+                    );
+                    if column.num_rows() != s.num_rows {
+                        return Err(StorageError::OutOfBounds("column has wrong number of rows"));
+                    }
+                    Ok(Some(column))
+                };
+                columns.push(open_column(c.fieldname())?);
+                let index = if schema.is_indexed(c) {
+                    let [values, rows] = index::fieldnames(c.fieldname());
+                    open_column(&values)?
+                        .zip(open_column(&rows)?)
+                        .map(|(values, rows)| SegmentIndex::new(values, rows))
+                } else {
+                    None
+                };
+                indexes.push(index);
+                histograms.push(
+                    s.file(c.id(), c.fieldname())
+                        .filter(|_| *wanted)
+                        .and_then(|f| f.histogram.clone()),
+                );
+            }
+            segments.push(SegmentColumns {
+                id: Some(s.id),
+                num_rows: s.num_rows,
+                columns,
+                indexes,
+                histograms,
+            });
+        }
+        Ok(Table {
+            schema: schema.clone(),
+            projection,
+            segments,
+            version,
+            time,
+            metrics,
+            on_disk_size: segments_size(dir, manifest_segments.iter().filter(is_wanted))?,
+            cache: None,
+            _pin: None,
+        })
+    }
+
+    /// The segments of the table in `dir`, as listed in its manifest
+    pub(crate) fn segments(dir: &Path) -> Result<Vec<Segment>, StorageError> {
+        Ok(Manifest::read(dir)?.segments)
+    }
+
+    /// The size of the current version of the table in `dir`, found from its
+    /// manifest without opening any of its columns
+    pub fn stats<P: AsRef<Path>>(dir: P) -> Result<TableStats, StorageError> {
+        let dir = dir.as_ref();
+        let segments = Table::segments(dir)?;
+        Ok(TableStats {
+            segments: segments.len() as u64,
+            rows: segments.iter().map(|s| s.num_rows).sum(),
+            on_disk_size: segments_size(dir, &segments)?,
+        })
+    }
+
+    /// Drop every segment of the table in `dir` whose rows are all older than
+    /// `before`, returning the number of rows dropped.
+    ///
+    /// Only the manifest is rewritten, so no rows are decoded.  Segments saved
+    /// without a time column are never expired.  Expired rows are gone from
+    /// earlier versions of the table too, except for versions pinned by an
+    /// open table, which keep them until they are forgotten.
+    pub fn expire<P: AsRef<Path>>(dir: P, before: u64) -> Result<u64, StorageError> {
+        Ok(Table::expire_segments(dir.as_ref(), before)?.1)
+    }
+
+    /// Expire segments as [`Table::expire`] does, returning the number of
+    /// segments and of rows dropped
+    fn expire_segments(dir: &Path, before: u64) -> Result<(u64, u64), StorageError> {
+        let pinned = snapshot::pinned_versions(dir)?;
+        let mut manifest = Manifest::read(dir)?;
+        let is_expired = |s: &Segment| s.max_time.map(|t| t < before).unwrap_or(false);
+        if !manifest.segments.iter().any(is_expired) {
+            return Ok((0, 0));
+        }
+        manifest.new_version();
+        let (expired, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut manifest.segments)
+            .into_iter()
+            .partition(is_expired);
+        manifest.segments = kept;
+        let num_segments = expired.len() as u64;
+        let num_rows = expired.iter().map(|s| s.num_rows).sum();
+        for h in manifest.history.iter_mut() {
+            if !pinned.contains(&h.version) {
+                h.segments.retain(|id| expired.iter().all(|s| s.id != *id));
+            }
+        }
+        let history = &manifest.history;
+        let (retired, expired): (Vec<_>, Vec<_>) = expired
+            .into_iter()
+            .partition(|s| history.iter().any(|h| h.segments.contains(&s.id)));
+        manifest.retire(retired);
+        manifest.write(dir)?;
+        remove_segment_files(dir, &expired)?;
+        Ok((num_segments, num_rows))
+    }
+
+    /// Replace all the segments of the table in `dir` with a single segment
+    /// holding the merged rows, returning what was purged.
+    ///
+    /// If the schema has a [retention](TableSchema::set_retention), rows that
+    /// are too old to keep are purged first.  Segments that hold only such
+    /// rows are expired without being read, and the rest of them are dropped
+    /// as the remaining rows are merged.  This fails without changing
+    /// anything more if the rows cannot be merged under the conflict policy
+    /// of the schema.
+    pub fn compact<P: AsRef<Path>>(dir: P, schema: &TableSchema) -> Result<Purge, StorageError> {
+        Table::compact_with_layout(dir.as_ref(), &FlatLayout, schema)
+    }
+
+    /// Compact the table in `dir` as [`Table::compact`] does, naming the
+    /// files of the new segment by `layout`
+    pub(crate) fn compact_with_layout(
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+    ) -> Result<Purge, StorageError> {
+        let since = schema.retained_since(SystemTime::now());
+        let mut purge = Purge {
+            since,
+            ..Purge::default()
+        };
+        if let Some(since) = since {
+            (purge.segments, purge.rows) = Table::expire_segments(dir, since)?;
+        }
+        let num_segments = Manifest::read(dir)?.segments.len();
+        if num_segments == 0 || (num_segments < 2 && since.is_none()) {
+            return Ok(purge);
+        }
+        let mut rows = Table::read(dir, schema)?.to_rows()?;
+        let num_rows = rows.len();
+        if let Some((since, i)) = since.zip(schema.time_index()) {
+            rows.retain(|r| !matches!(r.values[i], RawValue::U64(t) if t < since));
+        }
+        purge.rows += (num_rows - rows.len()) as u64;
+        check_constraints(schema, &rows)?;
+        if num_segments > 1 || rows.len() < num_rows {
+            Table::rewrite(dir, layout, schema, &rows)?;
+        }
+        Ok(purge)
+    }
+
+    /// Merge `rows` into the rows already in the table in `dir`, returning
+    /// the merged row for each primary key that was given.
+    ///
+    /// Rows sharing a primary key are combined under the conflict policy of
+    /// the schema, with the new rows written after the old ones, so a sum
+    /// adds to the stored total.  The whole table is then rewritten as a
+    /// single segment, and nothing changes if the rows cannot be merged.
+    pub fn upsert<P: AsRef<Path>>(
+        dir: P,
+        schema: &TableSchema,
+        rows: impl IntoIterator<Item = RawRow>,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        Table::upsert_with_layout(dir.as_ref(), &FlatLayout, schema, rows)
+    }
+
+    /// Merge `rows` into the table in `dir` as [`Table::upsert`] does, naming
+    /// the files of the new segment by `layout`
+    pub(crate) fn upsert_with_layout(
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+        rows: impl IntoIterator<Item = RawRow>,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        let mut rows = rows.into_iter().collect::<Vec<_>>();
+        for row in rows.iter() {
+            check_row(schema, row)?;
+        }
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        fs::create_dir_all(dir)?;
+        let num_primary = schema.num_primary();
+        let keys = rows
+            .iter()
+            .map(|r| r.values[..num_primary].to_vec())
+            .collect::<BTreeSet<_>>();
+        let mut manifest = Manifest::read(dir)?;
+        let old = Table::read_segments(
+            dir,
+            schema,
+            vec![true; schema.raw_columns().count()],
+            None,
+            &manifest,
+            manifest.version,
+        )?
+        .to_rows()?;
+        manifest.new_version();
+        stamp_ingestion(schema, &mut rows, manifest.time);
+        let merged = merge_rows(
+            schema,
+            old.into_iter()
+                .map(|r| (r, 0))
+                .chain(rows.into_iter().zip(1..))
+                .collect(),
+        )?;
+        check_constraints(schema, &merged)?;
+        Table::replace_segments(dir, layout, manifest, schema, &merged)?;
+        Ok(merged
+            .into_iter()
+            .filter(|r| keys.contains(&r.values[..num_primary]))
+            .collect())
+    }
+
+    /// Check that `rows` would keep the unique constraints of the schema if
+    /// they were merged into the rows of the table in `dir`
+    pub(crate) fn check_unique(
+        dir: &Path,
+        schema: &TableSchema,
+        rows: &[RawRow],
+    ) -> Result<(), StorageError> {
+        let unique = schema
+            .constrained_columns()
+            .into_iter()
+            .filter(|(_, c, _)| *c == Constraint::Unique)
+            .map(|(id, _, _)| id)
+            .collect::<Vec<_>>();
+        if rows.is_empty() || unique.is_empty() {
+            return Ok(());
+        }
+        // Only the primary key and the unique columns are needed to merge
+        // the values of the unique columns.
+        let old = Table::read_projected(dir, schema, &unique)?.to_rows()?;
+        let merged = merge_rows(
+            schema,
+            old.into_iter()
+                .map(|r| (r, 0))
+                .chain(rows.iter().cloned().zip(1..))
+                .collect(),
+        )?;
+        check_unique_values(schema, &merged, &mut UniqueValues::new())
+    }
+
+    /// Replace every segment of the table in `dir` with a single segment
+    /// holding `rows`, which must be sorted and merged.
+    ///
+    /// The old segments are kept for earlier versions of the table, until
+    /// they are forgotten by [`Table::forget_versions`].
+    pub(crate) fn rewrite(
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+        rows: &[RawRow],
+    ) -> Result<(), StorageError> {
+        let mut manifest = Manifest::read(dir)?;
+        manifest.new_version();
+        Table::replace_segments(dir, layout, manifest, schema, rows)
+    }
+
+    /// Make the segments of a new version of the table in `dir` a single
+    /// segment holding `rows`, retiring the old ones.
+    fn replace_segments(
+        dir: &Path,
+        layout: &dyn DbLayout,
+        mut manifest: Manifest,
+        schema: &TableSchema,
+        rows: &[RawRow],
+    ) -> Result<(), StorageError> {
+        let old = std::mem::take(&mut manifest.segments);
+        manifest.retire(old);
+        if !rows.is_empty() {
+            let options = EncodeOptions::default();
+            write_rows(dir, layout, &mut manifest, schema, rows, options)?;
+        }
+        manifest.write(dir)
+    }
+
+    /// The schema of this table
+    pub fn schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    /// The number of rows stored in the segments read, found without
+    /// decoding any of them.
+    ///
+    /// Rows sharing a primary key are counted once for each segment holding
+    /// one of them, so this can be more than [`Table::to_rows`] gives.
+    pub fn num_rows(&self) -> u64 {
+        self.segments.iter().map(|s| s.num_rows).sum()
+    }
+
+    /// The number of bytes of the encoded columns of the segments read,
+    /// including those of columns that were not
+    /// [projected](Table::read_projected)
+    pub fn on_disk_size(&self) -> u64 {
+        self.on_disk_size
+    }
+
+    /// The bytes read, seeks, and chunks decoded so far in opening this table
+    /// and reading its rows
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Take the values of columns decoded before from `cache`, and add
+    /// those decoded anew to it
+    pub(crate) fn with_cache(self, cache: &ColumnCache) -> Self {
+        Table {
+            cache: Some(cache.clone()),
+            ..self
+        }
+    }
+
+    /// Add `rows`, which are not saved, as a segment written after those
+    /// read, so that they are merged with the saved rows
+    pub(crate) fn with_rows(mut self, rows: &[RawRow]) -> Result<Self, StorageError> {
+        if rows.is_empty() {
+            return Ok(self);
+        }
+        let columns = self
+            .schema
+            .raw_columns()
+            .enumerate()
+            .map(|(i, c)| {
+                let values = rows.iter().map(|r| r.values[i].clone()).collect::<Vec<_>>();
+                let mut encoded = Vec::new();
+                RawColumn::write_values(&mut encoded, c.kind(), &values)?;
+                Ok(Some(RawColumn::decode(encoded)?))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        self.segments.push(SegmentColumns {
+            id: None,
+            num_rows: rows.len() as u64,
+            indexes: columns.iter().map(|_| None).collect(),
+            histograms: columns.iter().map(|_| None).collect(),
+            columns,
+        });
+        Ok(self)
+    }
+
+    /// The latest ingestion time that will never be given to rows saved
+    /// after this table was read, if the schema records ingestion times.
+    ///
+    /// Rows are stamped when they are saved, with the time of the version
+    /// that saves them, which is later than that of every earlier version.
+    /// So rows that are still being built or saved will be stamped after the
+    /// watermark, and every row with `_ingested_at` no later than the
+    /// watermark has already been read.  A consumer that remembers the
+    /// watermark can next read only the rows after it.
+    pub fn ingestion_watermark(&self) -> Option<u64> {
+        self.schema.ingestion_index().map(|_| self.time)
+    }
+
+    /// Read all the rows of the table, in sorted order.
+    ///
+    /// Rows from different segments that share a primary key are merged
+    /// according to the conflict policy of the schema.  Columns that were not
+    /// read hold their defaults.
+    pub fn to_rows(&self) -> Result<Vec<RawRow>, StorageError> {
+        self.read_rows(|s| Ok(Selection::all(s.num_rows)))
+    }
+
+    /// Read the rows of the table that match `expr`, in sorted order.
+    ///
+    /// When `expr` requires an indexed column to equal one of some values,
+    /// and the histograms of the column show that few rows hold them, only
+    /// the rows whose primary keys the index finds with those values are
+    /// read.  Otherwise, when filtering each segment gives the same rows as
+    /// filtering the merged rows, which it does if there is just one segment
+    /// or if `expr` only tests the primary key, segments are filtered a chunk
+    /// at a time before they are merged, and only the rows that match are
+    /// decoded.  Each segment then tests the columns it estimates to be most
+    /// selective first.  Otherwise the merged rows are filtered one at a
+    /// time.
+    pub fn select(&self, expr: &Expr) -> Result<Vec<RawRow>, StorageError> {
+        let predicate = expr.bind(&self.schema)?;
+        match self.plan(&predicate) {
+            Plan::Index(column, values) => self.select_indexed(&predicate, column, values),
+            Plan::Merged => {
+                let mut rows = self.to_rows()?;
+                rows.retain(|r| predicate.matches(&r.values));
+                Ok(rows)
+            }
+            Plan::Segments => self.read_rows(|s| {
+                let (planned, _) = s.plan(&self.schema, &predicate);
+                planned.select(&self.schema, &s.columns, s.num_rows)
+            }),
+        }
+    }
+
+    /// Read the rows of the table whose indexed raw column `column` holds
+    /// `value`, in sorted order.
+    ///
+    /// The column must have been indexed with [`TableSchema::add_index`].
+    /// Segments saved before then are searched for the value instead.
+    pub fn lookup_by_index(
+        &self,
+        column: &str,
+        value: &RawValue,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        let i = column_index(&self.schema, column)?;
+        let c = self.schema.raw_columns().nth(i).expect("column exists");
+        if !self.schema.is_indexed(c) {
+            return Err(StorageError::Query(format!(
+                "column {column} is not indexed"
+            )));
+        }
+        self.select(&Expr::Compare(
+            column.to_string(),
+            Comparison::Equal,
+            value.clone(),
+        ))
+    }
+
+    /// Read the rows that match `predicate`, which only rows holding one of
+    /// `values` in the indexed column `column` can.
+    ///
+    /// The rows of each segment holding one of the values are found with its
+    /// index, or by searching a segment saved before the column was indexed.
+    /// A row of one segment may be changed by a row with the same primary key
+    /// in another, so when there are several segments, every row sharing a
+    /// primary key with a row that was found is merged before filtering.
+    fn select_indexed(
+        &self,
+        predicate: &Predicate,
+        column: usize,
+        values: &[RawValue],
+    ) -> Result<Vec<RawRow>, StorageError> {
+        let search = Predicate::Test(column, Test::In(values.to_vec()));
+        let found = |s: &SegmentColumns| match &s.indexes[column] {
+            Some(index) => index.select(values),
+            None => search.select(&self.schema, &s.columns, s.num_rows),
+        };
+        let mut rows = if self.segments.len() == 1 {
+            self.read_rows(found)?
+        } else {
+            let mut keys = BTreeSet::new();
+            for s in self.segments.iter() {
+                let selection = found(s)?;
+                if selection.is_empty() {
+                    continue;
+                }
+                match &s.columns[0] {
+                    Some(c) => {
+                        let first = c.read_values_cached(self.cache.as_ref())?;
+                        keys.extend(selection.rows().map(|r| first[r as usize].clone()));
+                    }
+                    None => {
+                        let c = self.schema.raw_columns().next().expect("primary key");
+                        keys.insert(c.default().clone());
+                    }
+                }
+            }
+            let keys = Predicate::Test(0, Test::In(keys.into_iter().collect()));
+            self.read_rows(|s| keys.select(&self.schema, &s.columns, s.num_rows))?
+        };
+        rows.retain(|r| predicate.matches(&r.values));
+        Ok(rows)
+    }
+
+    /// Read the rows `select` picks out of each segment, and merge them
+    fn read_rows(
+        &self,
+        select: impl Fn(&SegmentColumns) -> Result<Selection, StorageError>,
+    ) -> Result<Vec<RawRow>, StorageError> {
+        let mut rows = Vec::new();
+        for (segment, s) in (0..).zip(self.segments.iter()) {
+            let selection = select(s)?;
+            if selection.is_empty() {
+                continue;
+            }
+            let values = s
+                .columns
+                .iter()
+                .map(|c| {
+                    c.as_ref()
+                        .map(|c| c.read_values_cached(self.cache.as_ref()))
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            self.push_rows(&mut rows, segment, &selection, values);
+        }
+        let mut rows = merge_rows(&self.schema, rows)?;
+        self.reset_unread(&mut rows, &self.projection);
+        Ok(rows)
+    }
+
+    /// Add the rows `selection` picks out of the decoded `values` of the
+    /// columns of a segment to `rows`, with `None` for columns holding their
+    /// defaults
+    fn push_rows(
+        &self,
+        rows: &mut Vec<(RawRow, u64)>,
+        segment: u64,
+        selection: &Selection,
+        mut values: Vec<Option<Arc<Vec<RawValue>>>>,
+    ) {
+        for row in selection.rows() {
+            let row = row as usize;
+            // Values are moved out of columns that are not cached.
+            let row = values
+                .iter_mut()
+                .zip(self.schema.raw_columns())
+                .map(|(v, c)| match v {
+                    Some(v) => match Arc::get_mut(v) {
+                        Some(v) => std::mem::replace(&mut v[row], RawValue::Bool(false)),
+                        None => v[row].clone(),
+                    },
+                    None => c.default().clone(),
+                })
+                .collect();
+            rows.push((row, segment));
+        }
+    }
+
+    /// Give every column of merged `rows` that was not read its default,
+    /// since merging may have summed the defaults of such columns
+    fn reset_unread(&self, rows: &mut [RawRow], projection: &[bool]) {
+        for (i, c) in self.schema.raw_columns().enumerate() {
+            if !projection[i] {
+                for row in rows.iter_mut() {
+                    row.values[i] = c.default().clone();
+                }
+            }
+        }
+    }
+}
+
+/// Sort rows and merge those that share a primary key.
+///
+/// Each row comes with a sequence number giving the order in which the rows
+/// were written, which decides the winner under last or first write wins.
+fn merge_rows(
+    schema: &TableSchema,
+    mut rows: Vec<(RawRow, u64)>,
+) -> Result<Vec<RawRow>, StorageError> {
+    let num_primary = schema.num_primary();
+    rows.sort_unstable_by(|(a, i), (b, j)| {
+        schema
+            .compare_values(0, &a.values[..num_primary], &b.values[..num_primary])
+            .then(i.cmp(j))
+    });
+    let ranges = schema.aggregation_ranges();
+    let mut merged: Vec<RawRow> = Vec::with_capacity(rows.len());
+    for (row, _) in rows {
+        merge_row(schema, &ranges, &mut merged, row)?;
+    }
+    Ok(merged)
+}
+
+/// Add `row` to the end of the `merged` rows, or merge it into the last of
+/// them if they share a primary key.
+///
+/// The row must sort no earlier than the rows already merged, and must have
+/// been written after the last of them.
+fn merge_row(
+    schema: &TableSchema,
+    ranges: &[(&AggregatingSchema, Range<usize>)],
+    merged: &mut Vec<RawRow>,
+    row: RawRow,
+) -> Result<(), StorageError> {
+    let num_primary = schema.num_primary();
+    let Some(last) = merged.last_mut() else {
+        merged.push(row);
+        return Ok(());
+    };
+    if last.values[..num_primary] != row.values[..num_primary] {
+        merged.push(row);
+        return Ok(());
+    }
+    match schema.conflict_policy() {
+        ConflictPolicy::Aggregate => aggregate(schema, ranges, last, &row)?,
+        ConflictPolicy::Error => return Err(StorageError::DuplicateKey(schema.name().to_string())),
+        ConflictPolicy::LastWriteWins => *last = row,
+        ConflictPolicy::FirstWriteWins => (),
+    }
+    Ok(())
+}
+
+/// Combine `row` into `last`, which has the same primary key.
+fn aggregate(
+    schema: &TableSchema,
+    ranges: &[(&AggregatingSchema, Range<usize>)],
+    last: &mut RawRow,
+    row: &RawRow,
+) -> Result<(), StorageError> {
+    for (aggregation, range) in ranges.iter() {
+        let clock = aggregation.clock().map(|clock| {
+            let positions = schema.positions(clock);
+            let values = |r: &RawRow| {
+                positions
+                    .iter()
+                    .map(|&i| r.values[i].clone())
+                    .collect::<Vec<_>>()
+            };
+            schema.compare_values(positions[0], &values(row), &values(last))
+        });
+        let (old, new) = (&mut last.values[range.clone()], &row.values[range.clone()]);
+        let order = || schema.compare_values(range.start, new, old);
+        match aggregation {
+            AggregatingSchema::Max { .. } if order().is_gt() => old.clone_from_slice(new),
+            AggregatingSchema::Min { .. } if order().is_lt() => old.clone_from_slice(new),
+            AggregatingSchema::FirstBy { .. } if matches!(clock, Some(c) if c.is_lt()) => {
+                old.clone_from_slice(new)
+            }
+            // Of rows with the same clock, the one written last wins.
+            AggregatingSchema::LastBy { .. } if matches!(clock, Some(c) if c.is_ge()) => {
+                old.clone_from_slice(new)
+            }
+            AggregatingSchema::Sum {
+                overflow: SumOverflow::Widen,
+                ..
+            } => match (&mut *old, new) {
+                (
+                    [RawValue::U64(old_high), RawValue::U64(old_low)],
+                    [RawValue::U64(high), RawValue::U64(low)],
+                ) => {
+                    let sum = ((*old_high as u128) << 64 | *old_low as u128)
+                        .saturating_add((*high as u128) << 64 | *low as u128);
+                    *old_high = (sum >> 64) as u64;
+                    *old_low = sum as u64;
+                }
+                _ => return Err(StorageError::InvalidRow("widened sum is not a u128")),
+            },
+            AggregatingSchema::Sum { overflow, columns } => {
+                let signed = columns
+                    .iter()
+                    .any(|(_, c)| decimal_scale(c.lens()).is_some());
+                for (i, (old, new)) in range.clone().zip(old.iter_mut().zip(new)) {
+                    let overflowed = || {
+                        let column = schema.raw_columns().nth(i).expect("in range");
+                        StorageError::Overflow(column.display_name())
+                    };
+                    match (&mut *old, new) {
+                        // Decimals are signed, with their sign bits flipped.
+                        (RawValue::U64(a), RawValue::U64(b)) if signed => {
+                            let (x, y) = (decimal_from_raw(*a), decimal_from_raw(*b));
+                            *a = decimal_to_raw(match overflow {
+                                SumOverflow::Saturate => x.saturating_add(y),
+                                SumOverflow::Error => x.checked_add(y).ok_or_else(overflowed)?,
+                                _ => x.wrapping_add(y),
+                            })
+                        }
+                        (RawValue::U64(a), RawValue::U64(b)) => {
+                            *a = match overflow {
+                                SumOverflow::Saturate => a.saturating_add(*b),
+                                SumOverflow::Error => a.checked_add(*b).ok_or_else(overflowed)?,
+                                _ => a.wrapping_add(*b),
+                            }
+                        }
+                        (old, new) => {
+                            if new > old {
+                                *old = new.clone();
+                            }
+                        }
+                    }
+                }
+            }
+            AggregatingSchema::Sketch { .. } => {
+                if let ([RawValue::Bytes(a)], [RawValue::Bytes(b)]) = (&mut *old, new) {
+                    *a = QuantileSketch::merge_bytes(a, b)
+                        .ok_or(StorageError::InvalidRow("not a quantile sketch"))?;
+                }
+            }
+            AggregatingSchema::Count { .. } | AggregatingSchema::Avg { .. } => {
+                for (old, new) in old.iter_mut().zip(new) {
+                    if let (RawValue::U64(a), RawValue::U64(b)) = (old, new) {
+                        *a = a.saturating_add(*b);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_schema() -> TableSchema {
+    use crate::ColumnSchema;
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::<u64>::new("age")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("happy").raw()),
+    );
+    schema
+}
+
+#[cfg(test)]
+fn person(name: &str, age: u64, happy: bool) -> RawRow {
+    [
+        RawValue::Bytes(name.as_bytes().to_vec()),
+        RawValue::U64(age),
+        RawValue::Bool(happy),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn save_and_read() {
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(table.to_rows().unwrap(), Vec::new());
+
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.insert_raw_row(person("Alice", 30, false)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("Bob", 7, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(
+        table.to_rows().unwrap(),
+        vec![
+            person("Alice", 30, false),
+            person("Bob", 7, true),
+            person("David", 48, true)
+        ]
+    );
+
+    // A schema with the same names but other columns is refused, rather
+    // than reading every column as its default.
+    assert!(matches!(
+        Table::read(dir.path(), &test_schema()),
+        Err(StorageError::SchemaMismatch(c)) if c == "name"
+    ));
+}
+
+#[test]
+fn merge_segments() {
+    use crate::ColumnSchema;
+
+    let mut schema = test_schema();
+    schema.add_sum(ColumnSchema::<u64>::new("visits").raw());
+    let visit = |name: &str, age, happy, visits| {
+        let mut row = person(name, age, happy);
+        row.values.push(RawValue::U64(visits));
+        row
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for rows in [
+        vec![visit("David", 48, false, 1), visit("Alice", 30, true, 2)],
+        vec![visit("David", 47, true, 3)],
+        vec![visit("David", 48, true, 5)],
+    ] {
+        let mut builder = TableBuilder::new(&schema);
+        for row in rows {
+            builder.insert_raw_row(row).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(
+        table.to_rows().unwrap(),
+        vec![visit("Alice", 30, true, 2), visit("David", 48, true, 9)]
+    );
+
+    // No one segment has as many visits as the merged row.
+    use crate::{Comparison, Expr};
+    let visits = Expr::Compare("visits".to_string(), Comparison::Greater, RawValue::U64(5));
+    assert_eq!(
+        table.select(&visits).unwrap(),
+        [visit("David", 48, true, 9)]
+    );
+    let name = |n: &str| RawValue::Bytes(n.as_bytes().to_vec());
+    let names = Expr::In("name".to_string(), vec![name("David"), name("Bob")]);
+    assert_eq!(table.select(&names).unwrap(), [visit("David", 48, true, 9)]);
+    Table::compact(dir.path(), &schema).unwrap();
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(
+        table.select(&!visits.and(names)).unwrap(),
+        [visit("Alice", 30, true, 2)]
+    );
+}
+
+#[test]
+fn sum_overflow_policies() {
+    use crate::lens::RawValues;
+    use crate::ColumnSchema;
+
+    let big = u64::MAX - 1;
+    let sum = |overflow, total: RawValues| {
+        let mut schema = TableSchema::new("totals");
+        schema.add_primary(ColumnSchema::<u64>::new("key").raw());
+        if overflow == SumOverflow::Widen {
+            let total = ColumnSchema::<u128>::new("total");
+            schema.add_sum_with_overflow(&total, overflow).unwrap();
+        } else {
+            let total = ColumnSchema::<u64>::new("total");
+            schema.add_sum_with_overflow(&total, overflow).unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        for _ in 0..2 {
+            let mut builder = TableBuilder::new(&schema);
+            let mut row = vec![RawValue::U64(0)];
+            row.extend(total.0.iter().cloned());
+            builder.insert_raw_row(row.into_iter().collect()).unwrap();
+            builder.save(dir.path()).unwrap();
+        }
+        let rows = Table::read(dir.path(), &schema).unwrap().to_rows();
+        if rows.is_ok() {
+            Table::compact(dir.path(), &schema).unwrap();
+        } else {
+            assert!(Table::compact(dir.path(), &schema).is_err());
+        }
+        rows.map(|rows| rows[0].values()[1..].to_vec())
+    };
+    assert_eq!(
+        sum(SumOverflow::Wrap, big.into()).unwrap(),
+        [RawValue::U64(big.wrapping_add(big))]
+    );
+    assert_eq!(
+        sum(SumOverflow::Saturate, big.into()).unwrap(),
+        [RawValue::U64(u64::MAX)]
+    );
+    assert!(matches!(
+        sum(SumOverflow::Error, big.into()),
+        Err(StorageError::Overflow(name)) if name == "total"
+    ));
+    assert_eq!(
+        sum(SumOverflow::Error, 3u64.into()).unwrap(),
+        [RawValue::U64(6)]
+    );
+    assert_eq!(
+        u128::try_from(RawValues(
+            sum(SumOverflow::Widen, (big as u128).into()).unwrap()
+        ))
+        .unwrap(),
+        2 * big as u128
+    );
+
+    let mut schema = TableSchema::new("totals");
+    let wrong = ColumnSchema::<u64>::new("total");
+    assert!(schema
+        .add_sum_with_overflow(&wrong, SumOverflow::Widen)
+        .is_err());
+    let wrong = ColumnSchema::<String>::new("total");
+    assert!(schema
+        .add_sum_with_overflow(&wrong, SumOverflow::Saturate)
+        .is_err());
+}
+
+#[test]
+fn upsert_rows() {
+    use crate::ColumnSchema;
+
+    let mut schema = test_schema();
+    schema.add_sum(ColumnSchema::<u64>::new("visits").raw());
+    let visit = |name: &str, age, happy, visits| {
+        let mut row = person(name, age, happy);
+        row.values.push(RawValue::U64(visits));
+        row
+    };
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(
+        Table::upsert(dir.path(), &schema, [visit("David", 48, false, 1)]).unwrap(),
+        vec![visit("David", 48, false, 1)]
+    );
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(visit("Alice", 30, true, 2)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    assert_eq!(
+        Table::upsert(
+            dir.path(),
+            &schema,
+            [
+                visit("David", 47, true, 3),
+                visit("Bob", 7, false, 1),
+                visit("David", 48, true, 5),
+            ]
+        )
+        .unwrap(),
+        vec![visit("Bob", 7, false, 1), visit("David", 48, true, 9)]
+    );
+    assert_eq!(Manifest::read(dir.path()).unwrap().segments.len(), 1);
+    assert_eq!(
+        Table::read(dir.path(), &schema).unwrap().to_rows().unwrap(),
+        vec![
+            visit("Alice", 30, true, 2),
+            visit("Bob", 7, false, 1),
+            visit("David", 48, true, 9)
+        ]
+    );
+    assert!(Table::upsert(dir.path(), &schema, [person("Eve", 1, true)]).is_err());
+}
+
+#[test]
+fn ingestion_watermarks() {
+    let mut schema = test_schema();
+    schema.record_ingestion_time();
+    schema.record_ingestion_time();
+    assert_eq!(schema.raw_columns().count(), 4);
+    let i = schema.ingestion_index().unwrap();
+    let stamped = |name: &str, age, time| {
+        let mut row = person(name, age, true);
+        row.values.insert(i, RawValue::U64(time));
+        row
+    };
+    let ingested = |rows: &[RawRow]| {
+        rows.iter()
+            .map(|r| match r.values[i] {
+                RawValue::U64(t) => t,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    assert!(Table::read(dir.path(), &test_schema())
+        .unwrap()
+        .ingestion_watermark()
+        .is_none());
+    let empty = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(empty.ingestion_watermark(), Some(0));
+
+    // Whatever time a row is given, it is stamped when it is saved.
+    let mut builder = TableBuilder::new(&schema);
+    builder
+        .insert_raw_row(stamped("David", 48, u64::MAX))
+        .unwrap();
+    builder.insert_raw_row(stamped("Alice", 30, 0)).unwrap();
+    builder.save(dir.path()).unwrap();
+    let first = Table::read(dir.path(), &schema).unwrap();
+    let watermark = first.ingestion_watermark().unwrap();
+    let rows = first.to_rows().unwrap();
+    assert_eq!(ingested(&rows), [watermark, watermark]);
+
+    // Rows that are saved later, whether by a builder that was started
+    // before the table was read or by an upsert, are stamped after the
+    // watermark.
+    let mut builder = TypedTableBuilder::new(&schema);
+    for (c, v) in builder
+        .columns_mut()
+        .iter_mut()
+        .zip(stamped("Bob", 7, 0).values)
+    {
+        match (c, v) {
+            (ColumnBuilder::U64(c), RawValue::U64(v)) => c.push(v),
+            (ColumnBuilder::Bool(c), RawValue::Bool(v)) => c.push(v),
+            (ColumnBuilder::Bytes(c), RawValue::Bytes(v)) => c.push(v),
+            _ => unreachable!(),
+        }
+    }
+    builder.save(dir.path()).unwrap();
+    let upserted = Table::upsert(dir.path(), &schema, [stamped("Alice", 31, 0)]).unwrap();
+    let second = Table::read(dir.path(), &schema).unwrap();
+    let rows = second.to_rows().unwrap();
+    let times = ingested(&rows);
+    assert!(times[0] > times[1] && times[1] > watermark);
+    assert_eq!(times[0], second.ingestion_watermark().unwrap());
+    assert_eq!(ingested(&upserted), [times[0]]);
+    assert_eq!(times[2], watermark);
+
+    // Compaction keeps the stamps, and an earlier version reads the same.
+    Table::compact(dir.path(), &schema).unwrap();
+    assert_eq!(
+        ingested(&Table::read(dir.path(), &schema).unwrap().to_rows().unwrap()),
+        times
+    );
+    let again = Table::read_at(dir.path(), &schema, AsOf::Version(1)).unwrap();
+    assert_eq!(again.ingestion_watermark(), Some(watermark));
+    assert_eq!(again.to_rows().unwrap(), first.to_rows().unwrap());
+}
+
+#[test]
+fn insert_wrong_row() {
+    let schema = test_schema();
+    let mut builder = TableBuilder::new(&schema);
+    assert!(builder
+        .insert_raw_row([RawValue::U64(1)].into_iter().collect())
+        .is_err());
+    assert!(builder
+        .insert_raw_row(
+            [RawValue::U64(1), RawValue::U64(2), RawValue::Bool(true)]
+                .into_iter()
+                .collect()
+        )
+        .is_err());
+}
+
+#[test]
+fn interrupted_save_is_invisible() {
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    // Simulate a crash after some column files of the next segment were
+    // written, but before the manifest was replaced.
+    let next = Manifest::read(dir.path()).unwrap().next_segment;
+    for c in schema.raw_columns().take(2) {
+        let filename = FlatLayout.column_file(next, None, c.id(), c.fieldname());
+        std::fs::write(dir.path().join(filename), b"garbage").unwrap();
+    }
+    std::fs::write(dir.path().join("MANIFEST.tmp"), b"half a manifest").unwrap();
+
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(table.to_rows().unwrap(), vec![person("David", 48, true)]);
+
+    // The debris does not get in the way of the next save.
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("Bob", 7, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(
+        table.to_rows().unwrap(),
+        vec![person("Bob", 7, true), person("David", 48, true)]
+    );
+}
+
+#[test]
+fn small_segments_are_inline() {
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let files = || std::fs::read_dir(dir.path()).unwrap().count();
+
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+    assert_eq!(files(), 1);
+
+    let mut builder = TableBuilder::new(&schema);
+    for age in 0..2000 {
+        builder
+            .insert_raw_row(person(&format!("person {age}"), age, true))
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    assert_eq!(files(), 1 + schema.raw_columns().count());
+
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(table.to_rows().unwrap().len(), 2001);
+    assert!(table
+        .to_rows()
+        .unwrap()
+        .contains(&person("David", 48, true)));
+}
+
+#[test]
+fn expire_old_segments() {
+    use crate::ColumnSchema;
+
+    let mut schema = TableSchema::new("events");
+    let time = ColumnSchema::<u64>::new("time");
+    schema.set_time_column(&time).unwrap();
+    schema.add_primary(time.raw());
+    let dir = tempfile::tempdir().unwrap();
+    let event = |t| [RawValue::U64(t)].into_iter().collect::<RawRow>();
+
+    // The segments are big enough to be stored in files of their own.
+    for times in [0..5000, 10_000..15_000] {
+        let mut builder = TableBuilder::new(&schema);
+        for t in times {
+            builder.insert_raw_row(event(t)).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let old_files: Vec<String> = Manifest::read(dir.path()).unwrap().segments[0]
+        .files
+        .iter()
+        .map(|f| f.filename().unwrap().to_string())
+        .collect();
+    assert!(dir.path().join(&old_files[0]).exists());
+
+    // An open table keeps the expired segment until it is dropped.
+    let open = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(Table::expire(dir.path(), 4999).unwrap(), 0);
+    assert_eq!(Table::expire(dir.path(), 5000).unwrap(), 5000);
+    assert_eq!(Table::expire(dir.path(), 5000).unwrap(), 0);
+    assert!(dir.path().join(&old_files[0]).exists());
+    assert_eq!(open.to_rows().unwrap().len(), 10_000);
+    drop(open);
+    Table::forget_versions(dir.path(), u64::MAX).unwrap();
+    for f in old_files {
+        assert!(!dir.path().join(f).exists());
+    }
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(
+        table.to_rows().unwrap(),
+        (10_000..15_000).map(event).collect::<Vec<_>>()
+    );
+
+    // A table without a time column never expires anything.
+    let mut builder = TableBuilder::new(&test_schema());
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    builder.save(dir.path()).unwrap();
+    assert_eq!(Table::expire(dir.path(), u64::MAX).unwrap(), 0);
+}
+
+#[test]
+fn conflict_policies() {
+    use crate::{ColumnSchema, ConflictPolicy};
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    let entry = |k: u64, v: u64| [RawValue::U64(k), RawValue::U64(v)].into_iter().collect();
+    for policy in [
+        ConflictPolicy::Aggregate,
+        ConflictPolicy::Error,
+        ConflictPolicy::LastWriteWins,
+        ConflictPolicy::FirstWriteWins,
+    ] {
+        let mut schema = TableSchema::new("overlaps");
+        schema.add_primary(ColumnSchema::<u64>::new("key").raw());
+        schema.add_sum(ColumnSchema::<u64>::new("value").raw());
+        schema.set_conflict_policy(policy);
+
+        for seed in 0..20 {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let dir = tempfile::tempdir().unwrap();
+            let mut expected = BTreeMap::new();
+            for _ in 0..rng.gen_range(1..5) {
+                let mut builder = TableBuilder::new(&schema);
+                let mut batch = expected.clone();
+                let mut repeated = false;
+                for _ in 0..rng.gen_range(0..10) {
+                    let (k, v) = (rng.gen_range(0..20), rng.gen_range(0..100));
+                    builder.insert_raw_row(entry(k, v)).unwrap();
+                    let old = batch.get(&k).copied();
+                    repeated |= old.is_some();
+                    batch.insert(
+                        k,
+                        match (policy, old) {
+                            (_, None) | (ConflictPolicy::LastWriteWins, _) => v,
+                            (ConflictPolicy::FirstWriteWins, Some(old)) => old,
+                            (_, Some(old)) => old + v,
+                        },
+                    );
+                }
+                let saved = builder.save(dir.path());
+                if repeated && policy == ConflictPolicy::Error {
+                    // A batch repeating a key, whether one of its own or one
+                    // already saved, is never saved.
+                    assert!(matches!(saved, Err(StorageError::DuplicateKey(_))));
+                    continue;
+                }
+                saved.unwrap();
+                expected = batch;
+            }
+            let expected: Vec<RawRow> = expected.into_iter().map(|(k, v)| entry(k, v)).collect();
+            let table = Table::read(dir.path(), &schema).unwrap();
+            assert_eq!(table.to_rows().unwrap(), expected);
+
+            Table::compact(dir.path(), &schema).unwrap();
+            assert!(Manifest::read(dir.path()).unwrap().segments.len() <= 1);
+            let table = Table::read(dir.path(), &schema).unwrap();
+            assert_eq!(table.to_rows().unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn read_projected() {
+    use crate::ColumnSchema;
+
+    let mut schema = test_schema();
+    let visits = ColumnSchema::<u64>::with_default("visits", 1);
+    schema.add_sum(visits.raw());
+    let visit = |name: &str, age, happy, visits| {
+        let mut row = person(name, age, happy);
+        row.values.push(RawValue::U64(visits));
+        row
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for rows in [
+        vec![visit("David", 48, true, 2), visit("Alice", 30, false, 3)],
+        vec![visit("David", 47, false, 4)],
+    ] {
+        let mut builder = TableBuilder::new(&schema);
+        for row in rows {
+            builder.insert_raw_row(row).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+
+    let table = Table::read_projected(dir.path(), &schema, &[visits.id()]).unwrap();
+    assert_eq!(
+        table.to_rows().unwrap(),
+        vec![visit("Alice", 0, false, 3), visit("David", 0, false, 6)]
+    );
+
+    // Reading one column of a max group reads the whole group.
+    let age = schema
+        .raw_columns()
+        .find(|c| c.name() == "age")
+        .unwrap()
+        .id();
+    let table = Table::read_projected(dir.path(), &schema, &[age]).unwrap();
+    assert_eq!(
+        table.to_rows().unwrap(),
+        vec![visit("Alice", 30, false, 1), visit("David", 48, true, 1)]
+    );
+}
+
+#[test]
+fn lookup_by_index() {
+    use crate::{ColumnSchema, Comparison, Expr};
+    use rand::{Rng, SeedableRng};
+
+    let age = ColumnSchema::<u64>::new("age");
+    let mut unindexed = TableSchema::new("people");
+    unindexed.add_primary(ColumnSchema::<String>::new("name").raw());
+    unindexed.add_max(age.raw().chain(ColumnSchema::<bool>::new("happy").raw()));
+    let mut schema = unindexed.clone();
+    schema.add_index(&age).unwrap();
+    assert!(schema.to_string().contains("INDEX ( age )"));
+    let visits = ColumnSchema::<u64>::new("visits");
+    assert!(schema.clone().add_index(&visits).is_err());
+    let mut summed = schema.clone();
+    summed.add_sum(visits.raw());
+    assert!(summed.add_index(&visits).is_err());
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let dir = tempfile::tempdir().unwrap();
+    // The first segment is saved before the index, and the last is small
+    // enough to be inline.
+    for (schema, n) in [(&unindexed, 2000), (&schema, 3000), (&schema, 5)] {
+        let mut builder = TableBuilder::new(schema);
+        for _ in 0..n {
+            let name = format!("person {}", rng.gen_range(0..4000));
+            builder
+                .insert_raw_row(person(&name, rng.gen_range(0..100), rng.gen()))
+                .unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let segments = Table::segments(dir.path()).unwrap();
+    assert_eq!(
+        segments.iter().map(|s| s.files.len()).collect::<Vec<_>>(),
+        [3, 5, 5]
+    );
+
+    let check = |table: &Table| {
+        let rows = table.to_rows().unwrap();
+        let with_age = |ages: &[u64]| {
+            rows.iter()
+                .filter(|r| ages.iter().any(|a| r.values[1] == RawValue::U64(*a)))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        for age in [0, 17, 50, 99, 100] {
+            let found = table.lookup_by_index("age", &RawValue::U64(age)).unwrap();
+            assert_eq!(found, with_age(&[age]));
+            assert!(age == 100 || !found.is_empty());
+        }
+        let ages = Expr::In("age".to_string(), vec![RawValue::U64(3), RawValue::U64(1)]);
+        assert_eq!(table.select(&ages).unwrap(), with_age(&[1, 3]));
+        let happy = Expr::Compare("happy".to_string(), Comparison::Equal, RawValue::Bool(true));
+        let mut expected = with_age(&[1, 3]);
+        expected.retain(|r| r.values[2] == RawValue::Bool(true));
+        assert_eq!(table.select(&happy.and(ages)).unwrap(), expected);
+    };
+    check(&Table::read(dir.path(), &schema).unwrap());
+    assert!(Table::read(dir.path(), &schema)
+        .unwrap()
+        .lookup_by_index("happy", &RawValue::Bool(true))
+        .is_err());
+    assert!(Table::read(dir.path(), &unindexed)
+        .unwrap()
+        .lookup_by_index("age", &RawValue::U64(1))
+        .is_err());
+
+    // Compaction indexes every row.
+    Table::compact(dir.path(), &schema).unwrap();
+    let segments = Table::segments(dir.path()).unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0].files.len(), 5);
+    check(&Table::read(dir.path(), &schema).unwrap());
+    assert!(Table::scrub(dir.path(), &schema)
+        .unwrap()
+        .corruptions()
+        .is_empty());
+}
+
+#[test]
+fn purge_old_rows() {
+    use crate::ColumnSchema;
+    use std::time::Duration;
+
+    let day = Duration::from_secs(24 * 60 * 60);
+    let mut schema = TableSchema::new("events");
+    let time = ColumnSchema::with_default("time", SystemTime::UNIX_EPOCH);
+    assert!(schema.set_retention(30 * day).is_err());
+    schema.set_time_column(&time).unwrap();
+    schema.add_primary(time.raw());
+    assert!(schema.set_retention(Duration::ZERO).is_err());
+    schema.set_retention(30 * day).unwrap();
+    assert!(schema.to_string().contains("RETENTION 2592000s"));
+
+    let now = SystemTime::now();
+    let event = |days_ago: u32, i: u64| {
+        let t = now - days_ago * day + Duration::from_nanos(i);
+        let t = t.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        [
+            RawValue::U64(t.as_secs()),
+            RawValue::U64(t.subsec_nanos().into()),
+        ]
+        .into_iter()
+        .collect::<RawRow>()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (segment, days_ago) in [[40, 35], [40, 1], [2, 1]].into_iter().enumerate() {
+        let mut builder = TableBuilder::new(&schema);
+        for (i, d) in (0..).zip(days_ago) {
+            builder
+                .insert_raw_row(event(d, 10 * segment as u64 + i))
+                .unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let purge = Table::compact(dir.path(), &schema).unwrap();
+    assert_eq!((purge.segments(), purge.rows()), (1, 3));
+    let since = purge.since().unwrap();
+    let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    assert_eq!(rows, [event(2, 20), event(1, 11), event(1, 21)]);
+    assert!(rows.iter().all(|r| r.values[0] >= RawValue::U64(since)));
+
+    // Nothing more is purged until rows grow old.
+    let purge = Table::compact(dir.path(), &schema).unwrap();
+    assert_eq!((purge.segments(), purge.rows()), (0, 0));
+    assert_eq!(Table::segments(dir.path()).unwrap().len(), 1);
+}
+
+#[test]
+fn partitioned_reads() {
+    use crate::ColumnSchema;
+
+    let name = ColumnSchema::<String>::new("name");
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(name.raw());
+    schema.add_max(
+        ColumnSchema::<u64>::new("age")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("happy").raw()),
+    );
+    assert!(schema.partition_by(&name, 1).is_err());
+    assert!(schema
+        .partition_by(&ColumnSchema::<String>::new("name"), 4)
+        .is_err());
+    schema.partition_by(&name, 4).unwrap();
+    assert_eq!(schema.partitions(), Some(4));
+
+    let dir = tempfile::tempdir().unwrap();
+    let people = (0..4000)
+        .map(|i| person(&format!("person number {i}"), i % 100, i % 3 == 0))
+        .collect::<Vec<_>>();
+    for chunk in people.chunks(2000) {
+        let mut builder = TableBuilder::new(&schema);
+        for p in chunk {
+            builder.insert_raw_row(p.clone()).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    // Each save wrote a segment to each partition, in its own directory.
+    let segments = Table::segments(dir.path()).unwrap();
+    assert_eq!(segments.len(), 8);
+    for s in segments.iter() {
+        let subdir = format!("partition-{}/", s.partition.unwrap());
+        assert!(s
+            .files
+            .iter()
+            .filter_map(|f| f.filename())
+            .all(|n| n.starts_with(&subdir)));
+    }
+    assert!(segments.iter().any(|s| !s.is_inline()));
+    let mut sorted = people.clone();
+    sorted.sort();
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(table.to_rows().unwrap(), sorted);
+
+    let value = |i: usize| people[i].values[0].clone();
+    let expr = Expr::In("name".to_string(), vec![value(7), value(1234)]);
+    let table = Table::read_where(dir.path(), &schema, &expr).unwrap();
+    assert!(table.segments.len() <= 4);
+    assert_eq!(
+        table.select(&expr).unwrap(),
+        [people[1234].clone(), people[7].clone()]
+    );
+
+    // Compacting keeps a segment for each partition.
+    Table::compact(dir.path(), &schema).unwrap();
+    assert_eq!(Table::segments(dir.path()).unwrap().len(), 4);
+    let expr = Expr::Compare("name".to_string(), Comparison::Equal, value(7)).and(Expr::Compare(
+        "age".to_string(),
+        Comparison::Less,
+        RawValue::U64(50),
+    ));
+    let table = Table::read_where(dir.path(), &schema, &expr).unwrap();
+    assert_eq!(table.segments.len(), 1);
+    assert_eq!(table.select(&expr).unwrap(), [people[7].clone()]);
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(table.to_rows().unwrap(), sorted);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn traced_reads() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    use crate::ColumnSchema;
+
+    /// Counts the columns opened and the bytes read from files
+    #[derive(Default)]
+    struct Counter {
+        opened: AtomicU64,
+        bytes: AtomicU64,
+    }
+    struct Bytes(u64);
+    impl Visit for Bytes {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "bytes" {
+                self.0 = value;
+            }
+        }
+        fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+    }
+    impl tracing::Subscriber for Counter {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            if event.metadata().target() == "equilia::io" {
+                let mut bytes = Bytes(0);
+                event.record(&mut bytes);
+                self.bytes.fetch_add(bytes.0, Ordering::Relaxed);
+            } else if event.metadata().fields().field("fieldname").is_some() {
+                self.opened.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut schema = TableSchema::new("traced");
+    schema.add_primary(ColumnSchema::<u64>::new("id").raw());
+    schema.add_max(ColumnSchema::<u64>::new("value").raw());
+    let mut builder = TableBuilder::new(&schema);
+    for i in 0..10_000u64 {
+        builder
+            .insert_raw_row(
+                [RawValue::U64(i), RawValue::U64(i * i)]
+                    .into_iter()
+                    .collect(),
+            )
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    assert!(Table::segments(dir.path())
+        .unwrap()
+        .iter()
+        .all(|s| !s.is_inline()));
+
+    let counter = Arc::new(Counter::default());
+    tracing::subscriber::with_default(counter.clone(), || {
+        let table = Table::read(dir.path(), &schema).unwrap();
+        assert_eq!(table.to_rows().unwrap().len(), 10_000);
+    });
+    assert_eq!(counter.opened.load(Ordering::Relaxed), 2);
+    let size = |f: &str| std::fs::metadata(dir.path().join(f)).unwrap().len();
+    let files = Table::segments(dir.path()).unwrap()[0]
+        .files
+        .iter()
+        .filter_map(|f| f.filename().map(size))
+        .sum::<u64>();
+    assert!(counter.bytes.load(Ordering::Relaxed) >= files);
+}
+
+#[test]
+fn collated_order() {
+    use crate::{Collation, ColumnSchema};
+
+    assert!(Collation::Numeric.compare(b"item9", b"item10").is_lt());
+    assert!(Collation::Numeric.compare(b"007", b"7").is_lt());
+    assert!(Collation::Numeric.compare(b"7", b"08").is_lt());
+    assert!(Collation::CaseInsensitive.compare(b"bob", b"Carol").is_lt());
+    assert!(Collation::CaseInsensitive.compare(b"Bob", b"bob").is_lt());
+
+    let name = ColumnSchema::<String>::new("name");
+    let version = ColumnSchema::<String>::new("version");
+    let mut schema = TableSchema::new("releases");
+    schema.add_primary(name.raw());
+    schema.add_max(version.raw());
+    schema
+        .set_collation(&name, Collation::CaseInsensitive)
+        .unwrap();
+    schema.set_collation(&version, Collation::Numeric).unwrap();
+    assert!(schema
+        .set_collation(&ColumnSchema::<u64>::new("version"), Collation::Numeric)
+        .is_err());
+    let row = |name: &str, version: &str| -> RawRow {
+        [
+            RawValue::Bytes(name.as_bytes().to_vec()),
+            RawValue::Bytes(version.as_bytes().to_vec()),
+        ]
+        .into_iter()
+        .collect()
+    };
+    let rows = [
+        row("carol", "1.9"),
+        row("Bob", "2.10"),
+        row("alice", "1.2"),
+        row("carol", "1.10"),
+        row("bob", "3.0"),
+        row("Bob", "2.9"),
+    ];
+    let expected = [
+        row("alice", "1.2"),
+        row("Bob", "2.10"),
+        row("bob", "3.0"),
+        row("carol", "1.10"),
+    ];
+
+    // Rows are sorted and merged alike however they are saved.
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for r in rows.iter() {
+        builder.insert_raw_row(r.clone()).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    assert_eq!(
+        Table::read(dir.path(), &schema).unwrap().to_rows().unwrap(),
+        expected
+    );
+
+    let spill = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema).spill_to(spill.path(), 1);
+    for r in rows.iter() {
+        builder.insert_raw_row(r.clone()).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    assert_eq!(
+        Table::read(dir.path(), &schema).unwrap().to_rows().unwrap(),
+        expected
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TypedTableBuilder::new(&schema);
+    for r in rows.iter() {
+        for (i, v) in r.values().iter().enumerate() {
+            if let RawValue::Bytes(v) = v {
+                builder.bytes_column(i).unwrap().push(v.clone());
+            }
+        }
+    }
+    builder.save(dir.path()).unwrap();
+    assert_eq!(
+        Table::read(dir.path(), &schema).unwrap().to_rows().unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn count_and_average() {
+    use crate::{Average, ColumnSchema};
+
+    let page = ColumnSchema::<String>::new("page");
+    let views = ColumnSchema::<u64>::new("views");
+    let latency = ColumnSchema::<Average>::new("latency");
+    let mut schema = TableSchema::new("pages");
+    schema.add_primary(page.raw());
+    schema.add_count(&views);
+    schema.add_avg(&latency);
+    assert!(schema.to_string().contains("COUNT ( views ),"));
+    assert!(schema
+        .to_string()
+        .contains("AVG ( latency.sum, latency.count ),"));
+    assert!(schema.add_index(&views).is_err());
+
+    let view = |page_name: &str, millis| {
+        schema.row(vec![
+            (page.id(), page_name.to_string().into()),
+            (latency.id(), Average::of(millis).into()),
+        ])
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for rows in [
+        vec![view("/", 10), view("/about", 40)],
+        vec![view("/", 20)],
+        vec![view("/", 60)],
+    ] {
+        let mut builder = TableBuilder::new(&schema);
+        for row in rows {
+            builder.insert_raw_row(row).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    // A row copied from another table of counts adds its own count.
+    let mut builder = TableBuilder::new(&schema);
+    builder
+        .insert_raw_row(schema.row(vec![
+            (page.id(), "/about".to_string().into()),
+            (views.id(), 5u64.into()),
+        ]))
+        .unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let check = || {
+        let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+        let counts = rows
+            .iter()
+            .map(|r| {
+                let average: Average = schema.get(r, latency.id()).unwrap();
+                (schema.get::<u64>(r, views.id()).unwrap(), average.mean())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [(3, Some(30.0)), (6, Some(40.0))]);
+    };
+    check();
+    Table::compact(dir.path(), &schema).unwrap();
+    check();
+    assert_eq!(Average::default().mean(), None);
+}
+
+#[test]
+fn first_and_last_by_clock() {
+    use crate::ColumnSchema;
+
+    let sensor = ColumnSchema::<String>::new("sensor");
+    let seen = ColumnSchema::<u64>::new("seen");
+    let state = ColumnSchema::<String>::new("state");
+    let first = ColumnSchema::<String>::new("first");
+    let first_seen = ColumnSchema::<u64>::new("first_seen");
+    let mut schema = TableSchema::new("sensors");
+    schema.add_primary(sensor.raw());
+    schema.add_last_by(&seen, state.raw()).unwrap();
+    schema.add_first_by(&first_seen, first.raw()).unwrap();
+    // Each group keeps a clock of its own.
+    assert!(schema.add_last_by(&seen, state.raw()).is_err());
+    assert!(schema.to_string().contains("LAST BY seen ( seen, state ),"));
+    assert!(schema
+        .to_string()
+        .contains("FIRST BY first_seen ( first_seen, first ),"));
+    let mut dropped = schema.clone();
+    assert!(dropped.drop_column("seen").is_err());
+
+    let reading = |name: &str, at: u64, value: &str| {
+        schema.row(vec![
+            (sensor.id(), name.to_string().into()),
+            (seen.id(), at.into()),
+            (first_seen.id(), at.into()),
+            (state.id(), value.to_string().into()),
+            (first.id(), value.to_string().into()),
+        ])
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for rows in [
+        vec![reading("door", 20, "open"), reading("hall", 5, "dark")],
+        // A late reading neither replaces the latest state nor the earliest.
+        vec![reading("door", 10, "closed"), reading("door", 30, "ajar")],
+        vec![reading("door", 10, "shut"), reading("hall", 5, "lit")],
+    ] {
+        let mut builder = TableBuilder::new(&schema);
+        for row in rows {
+            builder.insert_raw_row(row).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let check = || {
+        let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+        rows.iter()
+            .map(|r| {
+                (
+                    schema.get::<String>(r, state.id()).unwrap(),
+                    schema.get::<u64>(r, seen.id()).unwrap(),
+                    schema.get::<String>(r, first.id()).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    // Of readings at the same time, the last written is the latest and the
+    // first written is the earliest.
+    let expected = [
+        ("ajar".to_string(), 30, "closed".to_string()),
+        ("lit".to_string(), 5, "dark".to_string()),
+    ];
+    assert_eq!(check(), expected);
+    Table::compact(dir.path(), &schema).unwrap();
+    assert_eq!(check(), expected);
+}
+
+#[test]
+fn quantile_sketches() {
+    use crate::ColumnSchema;
+
+    let endpoint = ColumnSchema::<String>::new("endpoint");
+    let latency = ColumnSchema::<QuantileSketch>::new("latency");
+    let mut schema = TableSchema::new("requests");
+    schema.add_primary(endpoint.raw());
+    schema.add_sketch(&latency);
+    assert!(schema.to_string().contains("SKETCH ( latency ),"));
+    assert!(schema.add_index(&latency).is_err());
+
+    let request = |name: &str, millis| {
+        schema.row(vec![
+            (endpoint.id(), name.to_string().into()),
+            (latency.id(), QuantileSketch::of(millis).into()),
+        ])
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for batch in 0..10 {
+        let mut builder = TableBuilder::new(&schema);
+        for i in 0..100 {
+            builder
+                .insert_raw_row(request("/", batch * 100 + i + 1))
+                .unwrap();
+        }
+        builder.insert_raw_row(request("/slow", 5000)).unwrap();
+        builder.save(dir.path()).unwrap();
+    }
+    // A row saved without a sketch counts nothing.
+    let mut builder = TableBuilder::new(&schema);
+    builder
+        .insert_raw_row(schema.row(vec![(endpoint.id(), "/slow".to_string().into())]))
+        .unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let check = || {
+        let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+        let sketches = rows
+            .iter()
+            .map(|r| schema.get::<QuantileSketch>(r, latency.id()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sketches[0].count(), 1000);
+        assert_eq!(sketches[1].count(), 10);
+        for (q, exact) in [(0.5, 500.0), (0.99, 990.0)] {
+            let estimate = sketches[0].quantile(q).unwrap();
+            assert!((estimate - exact).abs() <= exact * QuantileSketch::RELATIVE_ACCURACY);
+        }
+        let slow = sketches[1].quantile(0.5).unwrap();
+        assert!((slow - 5000.0).abs() <= 50.0);
+    };
+    check();
+    Table::compact(dir.path(), &schema).unwrap();
+    check();
+}
+
+#[test]
+fn encoding_hints() {
+    use crate::column::ColumnFormat;
+    use crate::{ColumnSchema, Encoding};
+
+    let id = ColumnSchema::<u64>::new("id");
+    let city = ColumnSchema::<String>::new("city");
+    let visits = ColumnSchema::<u64>::new("visits");
+    let happy = ColumnSchema::<bool>::new("happy");
+    let mut schema = TableSchema::new("visitors");
+    schema.add_primary(id.raw());
+    schema.add_max(city.raw().chain(visits.raw()).chain(happy.raw()));
+    schema.set_encoding(&city, Encoding::Dictionary).unwrap();
+    schema.set_encoding(&id, Encoding::Plain).unwrap();
+    schema.set_encoding(&happy, Encoding::Plain).unwrap();
+    // No plain format holds runs, so these are encoded as without a hint.
+    schema.set_encoding(&visits, Encoding::Plain).unwrap();
+    assert!(schema.set_encoding(&id, Encoding::Dictionary).is_err());
+    assert_eq!(
+        schema.encoding(&city.raw().next().unwrap()),
+        Encoding::Dictionary
+    );
+
+    let rows = (0..1000u64)
+        .map(|i| {
+            [
+                RawValue::U64(i),
+                RawValue::Bytes(["Paris", "Rome", "Oslo"][i as usize % 3].into()),
+                RawValue::U64(7),
+                RawValue::Bool(i % 3 == 0),
+            ]
+            .into_iter()
+            .collect::<RawRow>()
+        })
+        .collect::<Vec<_>>();
+    let expected = [
+        ColumnFormat::U16,
+        ColumnFormat::Dictionary,
+        ColumnFormat::BitPacked,
+        ColumnFormat::Bitmap,
+    ];
+    let formats = |dir: &Path| {
+        let table = Table::read(dir, &schema).unwrap();
+        assert_eq!(table.to_rows().unwrap(), rows);
+        table.segments[0]
+            .columns
+            .iter()
+            .map(|c| c.as_ref().unwrap().format())
+            .collect::<Vec<_>>()
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for r in rows.iter() {
+        builder.insert_raw_row(r.clone()).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    assert_eq!(formats(dir.path()), expected);
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TypedTableBuilder::new(&schema);
+    for r in rows.iter() {
+        for (i, v) in r.values().iter().enumerate() {
+            match v {
+                RawValue::U64(v) => builder.u64_column(i).unwrap().push(*v),
+                RawValue::Bool(v) => builder.bool_column(i).unwrap().push(*v),
+                RawValue::Bytes(v) => builder.bytes_column(i).unwrap().push(v.clone()),
+            }
+        }
+    }
+    builder.save(dir.path()).unwrap();
+    assert_eq!(formats(dir.path()), expected);
+}
@@ -0,0 +1,243 @@
+//! Copying the files of a table, to back up a database or clone a table.
+//!
+//! A copy holds only the current version of the table, which is pinned while
+//! its files are copied, so writers can keep saving and compacting the table
+//! meanwhile.  The manifest of the copy remembers no earlier versions, so a
+//! table restored from it starts its history at the version copied.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path};
+
+use super::manifest::{checksum, Manifest};
+use super::remove_files;
+use super::snapshot::{Pin, READ_ATTEMPTS};
+use crate::column::encoding::StorageError;
+use crate::column::storage::Storage;
+use crate::fs;
+
+/// The current version of a table, whose files are kept until this is
+/// dropped
+pub(crate) struct PinnedFiles {
+    manifest: Manifest,
+    _pin: Pin,
+}
+
+impl PinnedFiles {
+    /// Pin the current version of the table in `dir`
+    pub(crate) fn pin(dir: &Path) -> Result<Self, StorageError> {
+        let mut manifest = Manifest::read(dir)?;
+        let pin = Pin::held(dir, manifest.version);
+        manifest.history.clear();
+        manifest.retired.clear();
+        Ok(PinnedFiles {
+            manifest,
+            _pin: pin,
+        })
+    }
+
+    /// The version pinned
+    pub(crate) fn version(&self) -> u64 {
+        self.manifest.version
+    }
+
+    /// Pass each column file of the table in `dir` to `copy`, with its name
+    /// relative to `dir`, then return the manifest listing them.
+    ///
+    /// Files in `copied` already, as listed by [`manifest_files`], are not
+    /// passed, since segments never change once saved.  Files that a version
+    /// pinned again shares with the first are not passed again either, see
+    /// [`PinnedFiles::each_file`], so `copy` may have been passed files that
+    /// the manifest does not list.
+    pub(crate) fn copy(
+        &mut self,
+        dir: &Path,
+        copied: &BTreeMap<String, Option<u64>>,
+        mut copy: impl FnMut(&str, &[u8]) -> Result<(), StorageError>,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.each_file(
+            dir,
+            copied,
+            |filename| fs::read(&dir.join(filename)),
+            |filename, bytes| copy(filename, &bytes),
+        )?;
+        let mut bytes = Vec::new();
+        self.manifest.encode(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Make the table in `to` a copy of the version pinned of the table in
+    /// `dir`, whose column files are hard links to those in `dir`.
+    ///
+    /// Neither table ever changes the files, and each removes only its own
+    /// links, so the tables share the bytes of the segments they have in
+    /// common without one changing the other.
+    pub(crate) fn link(&mut self, dir: &Path, to: &Path) -> Result<(), StorageError> {
+        let mut linked = Vec::new();
+        self.each_file(
+            dir,
+            &BTreeMap::new(),
+            |filename| {
+                let path = to.join(filename);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::hard_link(&dir.join(filename), &path)
+            },
+            |filename, ()| {
+                linked.push(filename.to_string());
+                Ok(())
+            },
+        )?;
+        fs::create_dir_all(to)?;
+        self.manifest.write(to)?;
+        let listed = self
+            .manifest
+            .segments
+            .iter()
+            .flat_map(|s| s.files.iter())
+            .filter_map(|f| f.filename())
+            .collect::<BTreeSet<_>>();
+        remove_files(
+            to,
+            linked
+                .iter()
+                .map(String::as_str)
+                .filter(|f| !listed.contains(f)),
+        )
+    }
+
+    /// Call `open` on the name of each column file of the table in `dir`,
+    /// relative to `dir`, other than those in `done`, and pass what it gives
+    /// to `then`.
+    ///
+    /// The version may be forgotten between reading the manifest and pinning
+    /// it, in which case its files may be gone, and the version current by
+    /// then is pinned instead.  Files that version shares with the first are
+    /// not opened again.
+    fn each_file<T>(
+        &mut self,
+        dir: &Path,
+        done: &BTreeMap<String, Option<u64>>,
+        mut open: impl FnMut(&str) -> std::io::Result<T>,
+        mut then: impl FnMut(&str, T) -> Result<(), StorageError>,
+    ) -> Result<(), StorageError> {
+        let mut done = done.clone();
+        let mut attempt = 1;
+        'pinned: loop {
+            let filenames = self
+                .manifest
+                .segments
+                .iter()
+                .flat_map(|s| s.files.iter())
+                .filter_map(|f| Some((f.filename()?, f.checksum)))
+                .filter(|(f, sum)| done.get(*f) != Some(sum))
+                .map(|(f, sum)| (f.to_string(), sum))
+                .collect::<Vec<_>>();
+            for (filename, sum) in filenames {
+                match open(&filename) {
+                    Ok(opened) => then(&filename, opened)?,
+                    Err(e)
+                        if e.kind() == std::io::ErrorKind::NotFound && attempt < READ_ATTEMPTS =>
+                    {
+                        attempt += 1;
+                        *self = PinnedFiles::pin(dir)?;
+                        continue 'pinned;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+                done.insert(filename, sum);
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// The column files listed in a manifest returned by [`PinnedFiles::copy`],
+/// with their checksums, if they have them
+pub(crate) fn manifest_files(
+    manifest: &[u8],
+) -> Result<BTreeMap<String, Option<u64>>, StorageError> {
+    let manifest = Manifest::decode(Storage::from(manifest))?;
+    Ok(manifest
+        .segments
+        .iter()
+        .flat_map(|s| s.files.iter())
+        .filter_map(|f| Some((f.filename()?.to_string(), f.checksum)))
+        .collect())
+}
+
+/// The column files of a table restored so far, with their checksums
+#[derive(Debug, Default)]
+pub(crate) struct Restoring {
+    files: BTreeMap<String, u64>,
+}
+
+impl Restoring {
+    /// Restore a column file passed to [`PinnedFiles::copy`] into `dir`
+    pub(crate) fn file(
+        &mut self,
+        dir: &Path,
+        filename: &str,
+        bytes: &[u8],
+    ) -> Result<(), StorageError> {
+        let relative = Path::new(filename);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(StorageError::Backup(format!("bad file name {filename:?}")));
+        }
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write_synced(&path, bytes)?;
+        self.files.insert(filename.to_string(), checksum(bytes));
+        Ok(())
+    }
+
+    /// Finish restoring the table in `dir` by writing the manifest returned
+    /// by [`PinnedFiles::copy`].
+    ///
+    /// Every file the manifest lists must have been restored, here or by an
+    /// earlier backup, with the checksum it was saved with.  Files it does
+    /// not list are removed, so only those it lists are kept for the next
+    /// backup to build on.
+    pub(crate) fn finish(&mut self, dir: &Path, manifest: Vec<u8>) -> Result<(), StorageError> {
+        let manifest = Manifest::decode(Storage::from(manifest))?;
+        let mut listed = BTreeSet::new();
+        for f in manifest.segments.iter().flat_map(|s| s.files.iter()) {
+            let Some(filename) = f.filename() else {
+                continue;
+            };
+            match self.files.get(filename) {
+                None => {
+                    return Err(StorageError::Backup(format!(
+                        "{} is missing {filename}",
+                        dir.display()
+                    )))
+                }
+                Some(sum) if f.checksum.filter(|c| c != sum).is_some() => {
+                    return Err(StorageError::Backup(format!(
+                        "{filename} of {} is corrupt",
+                        dir.display()
+                    )))
+                }
+                Some(_) => listed.insert(filename),
+            };
+        }
+        fs::create_dir_all(dir)?;
+        manifest.write(dir)?;
+        let unlisted = self
+            .files
+            .keys()
+            .filter(|f| !listed.contains(f.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        remove_files(dir, unlisted.iter().map(String::as_str))?;
+        for f in unlisted {
+            self.files.remove(&f);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+//! Showing rows to people, a logical column at a time.
+//!
+//! A logical column may be stored in several raw columns, as a `SystemTime`
+//! is stored in its seconds and nanoseconds, which mean little apart.  Rows
+//! are shown with the raw columns of each logical column put back together
+//! and written through its lens, so a `SystemTime` is written as a timestamp
+//! such as `2023-01-31T12:00:00.5Z`, a `Duration` as a number of seconds, a
+//! `Decimal` with its decimal point, and a `ShardSequence` or `GeoPoint` as
+//! they are loaded.  Other columns are written as their raw values, with the
+//! fields of a lens that is not known named, as in `high: 1, low: 2`.
+
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+use super::Table;
+use crate::column::encoding::StorageError;
+use crate::lens::{
+    decimal_from_raw, decimal_scale, format_decimal, format_signed_decimal, GeoPoint, Lens,
+    RawValues, ShardSequence,
+};
+use crate::{RawColumnSchema, RawValue};
+
+/// Rows written out a logical column at a time, see the [module](self) docs.
+///
+/// Displaying this writes the rows as a table, with the columns lined up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisplayRows {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl DisplayRows {
+    /// Write out `rows` of raw values, each named in `names` and coming from
+    /// the raw column in `sources`, if it is known.
+    ///
+    /// Consecutive values from the same logical column are written as one.
+    pub(crate) fn new(
+        names: &[String],
+        sources: &[Option<RawColumnSchema>],
+        rows: &[Vec<RawValue>],
+    ) -> Self {
+        let groups = logical_columns(sources);
+        let columns = groups
+            .iter()
+            .map(|g| match &sources[g.start] {
+                Some(c) if g.len() > 1 => {
+                    let suffix = format!(".{}", c.fieldname());
+                    let name = &names[g.start];
+                    name.strip_suffix(suffix.as_str())
+                        .unwrap_or(name)
+                        .to_string()
+                }
+                _ => names[g.start].clone(),
+            })
+            .collect();
+        let rows = rows
+            .iter()
+            .map(|r| {
+                groups
+                    .iter()
+                    .map(|g| {
+                        let sources = sources[g.clone()].iter().flatten().collect::<Vec<_>>();
+                        format_column(&sources, &r[g.clone()])
+                    })
+                    .collect()
+            })
+            .collect();
+        DisplayRows { columns, rows }
+    }
+
+    /// The names of the logical columns
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The rows, with a value for each logical column
+    pub fn rows(&self) -> &[Vec<String>] {
+        &self.rows
+    }
+}
+
+impl std::fmt::Display for DisplayRows {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let widths = (0..self.columns.len())
+            .map(|i| {
+                self.rows
+                    .iter()
+                    .map(|r| r[i].chars().count())
+                    .chain([self.columns[i].chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let line = |f: &mut std::fmt::Formatter<'_>, values: &[String]| {
+            let cells = values
+                .iter()
+                .zip(widths.iter())
+                .map(|(v, &width)| format!("{v:width$}"))
+                .collect::<Vec<_>>();
+            writeln!(f, "{}", cells.join(" | ").trim_end())
+        };
+        line(f, &self.columns)?;
+        let rule = widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>();
+        writeln!(f, "{}", rule.join("-+-"))?;
+        for r in self.rows.iter() {
+            line(f, r)?;
+        }
+        Ok(())
+    }
+}
+
+impl Table {
+    /// Read every row of the table, as [`Table::to_rows`] does, and write
+    /// each logical column out through its lens, see [`DisplayRows`].
+    pub fn display_rows(&self) -> Result<DisplayRows, StorageError> {
+        let rows = self
+            .to_rows()?
+            .into_iter()
+            .map(|r| r.values)
+            .collect::<Vec<_>>();
+        let names = self
+            .schema
+            .raw_columns()
+            .map(|c| c.display_name())
+            .collect::<Vec<_>>();
+        let sources = self
+            .schema
+            .raw_columns()
+            .cloned()
+            .map(Some)
+            .collect::<Vec<_>>();
+        Ok(DisplayRows::new(&names, &sources, &rows))
+    }
+}
+
+/// The ranges of consecutive raw columns from the same logical column
+fn logical_columns(sources: &[Option<RawColumnSchema>]) -> Vec<Range<usize>> {
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    for (i, c) in sources.iter().enumerate() {
+        match (groups.last_mut(), c) {
+            (Some(g), Some(c)) if sources[g.start].as_ref().map(|s| s.id()) == Some(c.id()) => {
+                g.end = i + 1;
+            }
+            _ => groups.push(i..i + 1),
+        }
+    }
+    groups
+}
+
+/// Write the raw values of a logical column through its lens, or as they
+/// are if the lens is not known or they are not all of its raw values
+fn format_column(sources: &[&RawColumnSchema], values: &[RawValue]) -> String {
+    let raw = || RawValues(values.to_vec());
+    if let Some(c) = sources.first().filter(|_| sources.len() == values.len()) {
+        let lens = c.lens();
+        if lens == SystemTime::LENS_ID {
+            if let Ok(t) = SystemTime::try_from(raw()) {
+                return format_time(t);
+            }
+        }
+        if lens == Duration::LENS_ID {
+            if let Ok(d) = Duration::try_from(raw()) {
+                let nanos = d.as_secs() as u128 * 1_000_000_000 + d.subsec_nanos() as u128;
+                if let Ok(nanos) = u64::try_from(nanos) {
+                    return trim_fraction(format_decimal(nanos, 9));
+                }
+            }
+        }
+        if lens == ShardSequence::LENS_ID {
+            if let Ok(s) = ShardSequence::try_from(raw()) {
+                return s.to_string();
+            }
+        }
+        if lens == GeoPoint::LENS_ID {
+            if let Ok(p) = GeoPoint::try_from(raw()) {
+                return p.to_string();
+            }
+        }
+        #[cfg(feature = "chrono")]
+        if lens == chrono::NaiveDate::LENS_ID {
+            if let Ok(date) = chrono::NaiveDate::try_from(raw()) {
+                return date.to_string();
+            }
+        }
+        if lens == u128::LENS_ID {
+            if let Ok(v) = u128::try_from(raw()) {
+                return v.to_string();
+            }
+        }
+        if let (Some(scale), [RawValue::U64(raw)]) = (decimal_scale(lens), values) {
+            return format_signed_decimal(decimal_from_raw(*raw), scale);
+        }
+    }
+    match values {
+        [v] => format_raw(v),
+        _ => values
+            .iter()
+            .zip(sources)
+            .map(|(v, c)| format!("{}: {}", c.fieldname(), format_raw(v)))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Write a raw value, with text as it is
+fn format_raw(value: &RawValue) -> String {
+    match value {
+        RawValue::Bytes(b) => match std::str::from_utf8(b) {
+            Ok(s) => s.to_string(),
+            Err(_) => format!("{b:?}"),
+        },
+        v => v.to_string(),
+    }
+}
+
+/// Drop the zeros ending the fraction of a decimal, and the point if they
+/// are all of it
+fn trim_fraction(decimal: String) -> String {
+    if decimal.contains('.') {
+        decimal
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        decimal
+    }
+}
+
+/// Write a time as an RFC 3339 timestamp in UTC, as loaded by
+/// [`CsvLoader`](crate::CsvLoader)
+fn format_time(t: SystemTime) -> String {
+    let d = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    let fraction = trim_fraction(format!("0.{:09}", d.subsec_nanos()));
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}{}Z",
+        &fraction[1..]
+    )
+}
+
+/// The date in the proleptic Gregorian calendar that is a number of days
+/// from 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[test]
+fn display_rows() {
+    use super::TableBuilder;
+    use crate::lens::Decimal;
+    use crate::{ColumnSchema, TableSchema};
+
+    let name = ColumnSchema::<String>::new("name");
+    let modified = ColumnSchema::with_default("modified", SystemTime::UNIX_EPOCH);
+    let took = ColumnSchema::<Duration>::new("took");
+    let price = ColumnSchema::<Decimal<2>>::new("price");
+    let seq = ColumnSchema::<ShardSequence>::new("seq");
+    let mut schema = TableSchema::new("files");
+    schema.add_primary(name.raw());
+    schema.add_max(
+        modified
+            .raw()
+            .chain(took.raw())
+            .chain(price.raw())
+            .chain(seq.raw()),
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for (n, secs, nanos, cents) in [("a.txt", 1675166400, 500_000_000, 1234), ("b", 0, 0, -5)] {
+        let time = SystemTime::UNIX_EPOCH + Duration::new(secs, nanos);
+        let values = [
+            RawValues::from(n.to_string()),
+            RawValues::from(time),
+            RawValues::from(Duration::new(2, nanos)),
+            RawValues::from(Decimal::<2>(cents)),
+            RawValues::from(ShardSequence {
+                shard: 3,
+                sequence: cents.unsigned_abs(),
+            }),
+        ];
+        let row = values.into_iter().flat_map(|v| v.0).collect();
+        builder.insert_raw_row(row).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+
+    let shown = Table::read(dir.path(), &schema)
+        .unwrap()
+        .display_rows()
+        .unwrap();
+    assert_eq!(
+        shown.columns(),
+        ["name", "modified", "took", "price", "seq"]
+    );
+    expect_test::expect![[r#"
+        name  | modified               | took | price | seq
+        ------+------------------------+------+-------+-------
+        a.txt | 2023-01-31T12:00:00.5Z | 2.5  | 12.34 | 3:1234
+        b     | 1970-01-01T00:00:00Z   | 2    | -0.05 | 3:5
+    "#]]
+    .assert_eq(&shown.to_string());
+
+    // Raw columns missing some of their logical column are shown as they are.
+    let names = ["modified.seconds".to_string(), "name".to_string()];
+    let sources = [schema.raw_columns().nth(1).cloned(), None];
+    let rows = [vec![RawValue::U64(7), RawValue::Bytes(vec![0xff])]];
+    let shown = DisplayRows::new(&names, &sources, &rows);
+    assert_eq!(shown.columns(), names);
+    assert_eq!(shown.rows(), [["7", "[255]"]]);
+
+    for (days, date) in [
+        (0, (1970, 1, 1)),
+        (19388, (2023, 1, 31)),
+        (11016, (2000, 2, 29)),
+    ] {
+        assert_eq!(civil_from_days(days), date);
+    }
+}
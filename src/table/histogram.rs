@@ -0,0 +1,260 @@
+//! Histograms of the values of the raw columns of a segment.
+//!
+//! A histogram splits the sorted values of a column into buckets holding
+//! about as many rows as each other, recording the largest value of each
+//! bucket along with its number of rows and of distinct values.  A value
+//! repeated in many rows then gets a bucket of its own, and the rows passing
+//! a test can be estimated without decoding the column.  Histograms are kept
+//! in the manifest alongside the columns they describe, so planning a query
+//! reads nothing but the manifest.
+
+use crate::column::encoding::{ReadEncoded, StorageError, WriteEncoded};
+use crate::expr::Test;
+use crate::{Comparison, RawColumn, RawValue};
+
+/// The most buckets in a histogram
+const MAX_BUCKETS: usize = 16;
+
+/// The longest bytes value that bounds a bucket, since every bound is kept
+/// in the manifest
+const MAX_BOUND_LEN: usize = 64;
+
+/// The sorted values of a column, in buckets of about equal numbers of rows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Histogram {
+    /// The smallest value, which is in the first bucket
+    min: RawValue,
+    buckets: Vec<Bucket>,
+}
+
+/// The values greater than the bound of the bucket before, and no greater
+/// than `upper`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bucket {
+    upper: RawValue,
+    rows: u64,
+    distinct: u64,
+}
+
+impl Bucket {
+    /// The rows estimated to hold each value of the bucket
+    fn rows_per_value(&self) -> f64 {
+        self.rows as f64 / self.distinct as f64
+    }
+}
+
+/// Where `v` lies between `low` and `high`, from 0 to 1, guessing the middle
+/// for values that are not numbers
+fn interpolate(low: &RawValue, v: &RawValue, high: &RawValue) -> f64 {
+    match (low, v, high) {
+        (RawValue::U64(low), RawValue::U64(v), RawValue::U64(high)) if high > low => {
+            (v.saturating_sub(*low)) as f64 / (high - low) as f64
+        }
+        _ => 0.5,
+    }
+}
+
+impl Histogram {
+    /// The histogram of the values of `column`, unless they are too long to
+    /// bound its buckets
+    pub(crate) fn of(column: &RawColumn) -> Result<Option<Self>, StorageError> {
+        let mut runs = column.runs().collect::<Result<Vec<_>, _>>()?;
+        runs.sort_unstable();
+        let mut counts: Vec<(RawValue, u64)> = Vec::new();
+        for (v, rows) in runs {
+            match counts.last_mut() {
+                Some((last, n)) if *last == v => *n += rows,
+                _ => counts.push((v, rows)),
+            }
+        }
+        Ok(Histogram::new(counts))
+    }
+
+    /// The histogram of distinct sorted values, each with its number of rows
+    fn new(counts: Vec<(RawValue, u64)>) -> Option<Self> {
+        let min = counts.first()?.0.clone();
+        let total: u64 = counts.iter().map(|(_, n)| n).sum();
+        let per_bucket = total / MAX_BUCKETS as u64 + 1;
+        let mut buckets = Vec::<Bucket>::new();
+        let mut full = true;
+        for (upper, rows) in counts {
+            match buckets.last_mut() {
+                Some(b) if !full => {
+                    b.upper = upper;
+                    b.rows += rows;
+                    b.distinct += 1;
+                }
+                _ => buckets.push(Bucket {
+                    upper,
+                    rows,
+                    distinct: 1,
+                }),
+            }
+            full = matches!(buckets.last(), Some(b) if b.rows >= per_bucket);
+        }
+        let too_long = |v: &RawValue| matches!(v, RawValue::Bytes(b) if b.len() > MAX_BOUND_LEN);
+        if too_long(&min) || buckets.iter().any(|b| too_long(&b.upper)) {
+            return None;
+        }
+        Some(Histogram { min, buckets })
+    }
+
+    /// The number of rows the histogram describes
+    pub(crate) fn num_rows(&self) -> u64 {
+        self.buckets.iter().map(|b| b.rows).sum()
+    }
+
+    /// The estimated number of rows holding `v`
+    fn equal(&self, v: &RawValue) -> f64 {
+        if v < &self.min {
+            return 0.0;
+        }
+        match self.buckets.iter().find(|b| v <= &b.upper) {
+            Some(b) => b.rows_per_value(),
+            None => 0.0,
+        }
+    }
+
+    /// The estimated number of rows holding values less than `v`, or no
+    /// greater than it if `inclusive`
+    fn below(&self, v: &RawValue, inclusive: bool) -> f64 {
+        let mut rows = 0.0;
+        let mut low = &self.min;
+        for b in self.buckets.iter() {
+            if &b.upper < v {
+                rows += b.rows as f64;
+            } else {
+                // Only part of the bucket is below `v`.
+                let equal = if inclusive { b.rows_per_value() } else { 0.0 };
+                if v == &b.upper {
+                    rows += b.rows as f64 - b.rows_per_value() + equal;
+                } else if v >= low {
+                    let within = interpolate(low, v, &b.upper) * b.rows as f64;
+                    rows += (within + equal).min(b.rows as f64);
+                }
+                break;
+            }
+            low = &b.upper;
+        }
+        rows
+    }
+
+    /// The estimated number of rows that pass `test`
+    pub(crate) fn estimate(&self, test: &Test) -> f64 {
+        let total = self.num_rows() as f64;
+        let rows = match test {
+            Test::Compare(Comparison::Equal, v) => self.equal(v),
+            Test::Compare(Comparison::Less, v) => self.below(v, false),
+            Test::Compare(Comparison::LessOrEqual, v) => self.below(v, true),
+            Test::Compare(Comparison::Greater, v) => total - self.below(v, true),
+            Test::Compare(Comparison::GreaterOrEqual, v) => total - self.below(v, false),
+            Test::In(values) => values.iter().map(|v| self.equal(v)).sum(),
+            Test::Between(low, high) if low <= high => {
+                self.below(high, true) - self.below(low, false)
+            }
+            Test::Between(..) => 0.0,
+        };
+        rows.clamp(0.0, total)
+    }
+
+    pub(crate) fn encode<W: WriteEncoded>(&self, out: &mut W) -> Result<(), StorageError> {
+        write_value(out, &self.min)?;
+        out.write_unsigned(self.buckets.len() as u64)?;
+        for b in self.buckets.iter() {
+            write_value(out, &b.upper)?;
+            out.write_unsigned(b.rows)?;
+            out.write_unsigned(b.distinct)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn decode<R: ReadEncoded>(storage: &mut R) -> Result<Self, StorageError> {
+        let min = read_value(storage)?;
+        let n_buckets = storage.read_usigned()?;
+        if n_buckets == 0 || n_buckets > MAX_BUCKETS as u64 {
+            return Err(StorageError::OutOfBounds("histogram buckets"));
+        }
+        let mut buckets = Vec::new();
+        for _ in 0..n_buckets {
+            let upper = read_value(storage)?;
+            let rows = storage.read_usigned()?;
+            let distinct = storage.read_usigned()?;
+            if distinct == 0 || distinct > rows {
+                return Err(StorageError::OutOfBounds("histogram bucket"));
+            }
+            buckets.push(Bucket {
+                upper,
+                rows,
+                distinct,
+            });
+        }
+        Ok(Histogram { min, buckets })
+    }
+}
+
+fn write_value<W: WriteEncoded>(out: &mut W, v: &RawValue) -> Result<(), StorageError> {
+    let bytes = v.encode();
+    out.write_unsigned(bytes.len() as u64)?;
+    out.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_value<R: ReadEncoded>(storage: &mut R) -> Result<RawValue, StorageError> {
+    let len = storage.read_usigned()?;
+    let bytes = storage.read_vec(len)?;
+    match RawValue::decode(&bytes)? {
+        (v, []) => Ok(v),
+        _ => Err(StorageError::OutOfBounds("histogram value")),
+    }
+}
+
+#[test]
+fn estimate_rows() {
+    // A thousand rows of ages up to 99, with half of them zero.
+    let values = (0..1000u64)
+        .map(|i| if i % 2 == 0 { 0 } else { i % 100 })
+        .collect::<Vec<_>>();
+    let column = RawColumn::from(values.as_slice());
+    let histogram = Histogram::of(&column).unwrap().unwrap();
+    assert_eq!(histogram.num_rows(), 1000);
+    assert!(histogram.buckets.len() <= MAX_BUCKETS);
+    assert_eq!(histogram.buckets[0].upper, RawValue::U64(0));
+
+    let actual = |test: &Test| {
+        values
+            .iter()
+            .filter(|v| test.matches(&RawValue::U64(**v)))
+            .count()
+    };
+    let compare = |c, v| Test::Compare(c, RawValue::U64(v));
+    let tests = [
+        compare(Comparison::Equal, 0),
+        compare(Comparison::Equal, 51),
+        compare(Comparison::Equal, 500),
+        compare(Comparison::Less, 50),
+        compare(Comparison::LessOrEqual, 0),
+        compare(Comparison::Greater, 90),
+        compare(Comparison::GreaterOrEqual, 1),
+        Test::In(vec![RawValue::U64(3), RawValue::U64(5), RawValue::U64(7)]),
+        Test::Between(RawValue::U64(20), RawValue::U64(29)),
+        Test::Between(RawValue::U64(29), RawValue::U64(20)),
+    ];
+    for test in tests {
+        let (estimate, actual) = (histogram.estimate(&test), actual(&test) as f64);
+        assert!(
+            (estimate - actual).abs() <= 25.0,
+            "{test:?}: {estimate} {actual}"
+        );
+    }
+
+    let mut encoded = Vec::new();
+    histogram.encode(&mut encoded).unwrap();
+    let decoded = Histogram::decode(&mut crate::column::storage::Storage::from(encoded));
+    assert_eq!(decoded.unwrap(), histogram);
+
+    // Long bytes values are not kept in the manifest.
+    let long = vec![vec![b'x'; 100]; 10];
+    assert!(Histogram::of(&RawColumn::from(long.as_slice()))
+        .unwrap()
+        .is_none());
+}
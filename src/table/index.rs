@@ -0,0 +1,65 @@
+//! Secondary indexes of the raw columns of a segment.
+//!
+//! The index of a raw column holds the values of the column in sorted order,
+//! alongside the number of the row holding each, as two more columns of the
+//! segment.  They are written whenever a segment is, so a segment saved by
+//! compaction or an upsert is indexed just like one saved by a builder.
+//! Finding the rows that hold a value then decodes only the index, and
+//! searches its sorted values instead of testing every row of the table.
+
+use crate::column::encoding::StorageError;
+use crate::expr::Selection;
+use crate::{RawColumn, RawValue};
+
+/// The fieldnames under which the sorted values and row numbers of the index
+/// of a raw column are stored
+pub(crate) fn fieldnames(fieldname: &str) -> [String; 2] {
+    [format!("{fieldname}#index"), format!("{fieldname}#rows")]
+}
+
+/// Encode the sorted values and row numbers of the index of an encoded raw
+/// column
+pub(crate) fn encode(column: &[u8]) -> Result<[Vec<u8>; 2], StorageError> {
+    let column = RawColumn::decode(column.to_vec())?;
+    let mut entries = column
+        .read_values()?
+        .into_iter()
+        .zip(0u64..)
+        .collect::<Vec<_>>();
+    entries.sort_unstable();
+    let (values, rows): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+    let mut encoded = [Vec::new(), Vec::new()];
+    RawColumn::write_values(&mut encoded[0], column.kind(), &values)?;
+    RawColumn::write_u64(&mut encoded[1], &rows)?;
+    Ok(encoded)
+}
+
+/// The index of one raw column of a segment
+pub(crate) struct SegmentIndex {
+    values: RawColumn,
+    rows: RawColumn,
+}
+
+impl SegmentIndex {
+    pub(crate) fn new(values: RawColumn, rows: RawColumn) -> Self {
+        SegmentIndex { values, rows }
+    }
+
+    /// The rows holding any of `values`
+    pub(crate) fn select(&self, values: &[RawValue]) -> Result<Selection, StorageError> {
+        let sorted = self.values.read_values()?;
+        let rows = self.rows.read_u64()?;
+        let mut selected = Vec::new();
+        for v in values {
+            let start = sorted.partition_point(|s| s < v);
+            let end = sorted.partition_point(|s| s <= v);
+            selected.extend_from_slice(&rows[start..end]);
+        }
+        selected.sort_unstable();
+        let mut selection = Selection::default();
+        for row in selected {
+            selection.push(row..row + 1);
+        }
+        Ok(selection)
+    }
+}
@@ -0,0 +1,221 @@
+//! Looking inside the files of a table, without knowing its schema.
+//!
+//! The manifest of a table names the logical column and field of each column
+//! file, and each file records its own format, so the raw columns of a table
+//! can be pieced together from its directory alone.  Their names and lenses
+//! are only kept in the catalog of the database, see
+//! [`TableFiles::schema_in`].
+
+use std::path::Path;
+
+use super::manifest::{ColumnData, Manifest, MANIFEST};
+use super::Table;
+use crate::column::encoding::StorageError;
+use crate::column::format::{describe, ColumnDescription};
+use crate::column::storage::Storage;
+use crate::fs;
+use crate::lens::ColumnId;
+use crate::{RawKind, TableSchema};
+
+/// The segments of a table and their column files, see [`Table::inspect`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableFiles {
+    version: u64,
+    segments: Vec<SegmentFiles>,
+}
+
+/// The column files of a segment, see [`TableFiles::segments`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentFiles {
+    id: u64,
+    num_rows: u64,
+    columns: Vec<SegmentColumn>,
+}
+
+/// A raw column of a segment, see [`SegmentFiles::columns`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentColumn {
+    column: ColumnId,
+    fieldname: String,
+    file: Option<String>,
+    description: Result<ColumnDescription, String>,
+}
+
+impl TableFiles {
+    /// The version of the table
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The segments of the current version of the table
+    pub fn segments(&self) -> &[SegmentFiles] {
+        &self.segments
+    }
+
+    /// The raw columns found in the segments, in the order they are first
+    /// found, with the kind of values each holds, if a file of it could be
+    /// read
+    pub fn raw_columns(&self) -> Vec<(ColumnId, &str, Option<RawKind>)> {
+        let mut columns: Vec<(ColumnId, &str, Option<RawKind>)> = Vec::new();
+        for c in self.segments.iter().flat_map(|s| s.columns.iter()) {
+            let kind = c.description.as_ref().ok().map(|d| d.format().kind());
+            match columns
+                .iter_mut()
+                .find(|(id, field, _)| *id == c.column && *field == c.fieldname)
+            {
+                Some(found) => found.2 = found.2.or(kind),
+                None => columns.push((c.column, &c.fieldname, kind)),
+            }
+        }
+        columns
+    }
+
+    /// The schema among `schemas` holding every raw column found in the
+    /// segments, which are those of the table unless it has none
+    pub fn schema_in<'a>(&self, schemas: &'a [TableSchema]) -> Option<&'a TableSchema> {
+        let columns = self.raw_columns();
+        if columns.is_empty() {
+            return None;
+        }
+        schemas.iter().find(|s| {
+            columns.iter().all(|(id, field, _)| {
+                s.raw_columns()
+                    .any(|c| c.id() == *id && c.fieldname() == *field)
+            })
+        })
+    }
+}
+
+impl SegmentFiles {
+    /// The id of the segment
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The number of rows in the segment
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    /// The raw columns saved in the segment
+    pub fn columns(&self) -> &[SegmentColumn] {
+        &self.columns
+    }
+}
+
+impl SegmentColumn {
+    /// The logical column the raw column is part of
+    pub fn column(&self) -> ColumnId {
+        self.column
+    }
+
+    /// The field of the logical column that the raw column holds
+    pub fn fieldname(&self) -> &str {
+        &self.fieldname
+    }
+
+    /// The file holding the raw column, relative to the directory of the
+    /// table, or `None` if it is kept in the manifest
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// How the raw column is laid out, or why it could not be read
+    pub fn description(&self) -> Result<&ColumnDescription, &str> {
+        self.description.as_ref().map_err(String::as_str)
+    }
+}
+
+impl Table {
+    /// Describe the column files of each segment of the table in `dir`,
+    /// see [`TableFiles`].
+    ///
+    /// A column file that cannot be read or described is reported in its
+    /// [`SegmentColumn::description`], and only a manifest that cannot be
+    /// read is an error, including one that is missing.
+    pub fn inspect<P: AsRef<Path>>(dir: P) -> Result<TableFiles, StorageError> {
+        let dir = dir.as_ref();
+        let manifest = Manifest::decode(Storage::from(fs::read(&dir.join(MANIFEST))?))?;
+        let segments = manifest
+            .segments
+            .iter()
+            .map(|s| SegmentFiles {
+                id: s.id,
+                num_rows: s.num_rows,
+                columns: s
+                    .files
+                    .iter()
+                    .map(|f| {
+                        let bytes = match &f.data {
+                            ColumnData::File(filename) => fs::read(&dir.join(filename))
+                                .map_err(|e| format!("cannot read {filename}: {e}")),
+                            ColumnData::Inline(bytes) => Ok(bytes.as_slice().into()),
+                        };
+                        SegmentColumn {
+                            column: f.column,
+                            fieldname: f.fieldname.clone(),
+                            file: f.filename().map(str::to_string),
+                            description: bytes
+                                .and_then(|b| describe(&b).map_err(|e| e.to_string())),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(TableFiles {
+            version: manifest.version,
+            segments,
+        })
+    }
+}
+
+#[test]
+fn inspect_files() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    assert!(Table::inspect(dir.path()).is_err());
+    let mut builder = TableBuilder::new(&schema);
+    for i in 0..3000 {
+        builder
+            .insert_raw_row(person(&format!("person {i}"), i, i % 3 == 0))
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let files = Table::inspect(dir.path()).unwrap();
+    assert_eq!(files.segments().len(), 2);
+    assert_eq!(files.segments()[0].num_rows(), 3000);
+    let column = &files.segments()[0].columns()[1];
+    let description = column.description().unwrap();
+    assert_eq!(description.num_rows(), 3000);
+    assert_eq!(description.format().kind(), RawKind::U64);
+    assert!(column.file().is_some());
+    let kinds = files
+        .raw_columns()
+        .into_iter()
+        .map(|(_, _, kind)| kind)
+        .collect::<Vec<_>>();
+    let expected = schema
+        .raw_columns()
+        .map(|c| Some(c.kind()))
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, expected);
+    let other = TableSchema::new("other");
+    let schemas = [other, schema.clone()];
+    let found = files.schema_in(&schemas).unwrap();
+    assert_eq!(found.name(), schema.name());
+    assert!(files.schema_in(&schemas[..1]).is_none());
+
+    // A column file that is gone is reported, and the rest still described.
+    let filename = column.file().unwrap().to_string();
+    std::fs::remove_file(dir.path().join(&filename)).unwrap();
+    let files = Table::inspect(dir.path()).unwrap();
+    let columns = files.segments()[0].columns();
+    assert!(columns[1].description().unwrap_err().contains(&filename));
+    assert!(columns[0].description().is_ok());
+}
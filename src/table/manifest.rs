@@ -0,0 +1,556 @@
+//! The manifest lists the segments that make up a table.
+//!
+//! Segments are immutable once written, so the manifest is the only file that
+//! is ever replaced, and it is replaced atomically.  A reader that opens the
+//! manifest will only ever see segments that were completely written.
+//!
+//! Columns of very small segments are stored inline in the manifest instead
+//! of in files of their own, so a tiny table such as a schema table can be
+//! read with a single file read.
+//!
+//! Each column records a checksum of its encoded bytes, so that corruption
+//! can be found by [`Table::scrub`](crate::Table::scrub), and a fingerprint
+//! of the raw column schema it was saved with, so that a table is not read
+//! with a schema that would decode its columns as something else.  Each
+//! column also records a [`Histogram`] of its values, for planning queries.
+//!
+//! Every change to the list of segments makes a new version of the table.
+//! The manifest remembers the segments of each earlier version, along with
+//! any segments that only those versions still use, so that a table can be
+//! read as it was, see [`Table::read_at`](crate::Table::read_at).
+//!
+//! Each segment of a partitioned table records its partition, and keeps its
+//! column files in the directory of that partition, see
+//! [`TableSchema::partition_by`](crate::TableSchema::partition_by).
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::column::digest::{fnv, FNV_OFFSET};
+use crate::column::encoding::{ReadEncoded, StorageError, WriteEncoded};
+use crate::column::storage::Storage;
+use crate::fs;
+use crate::lens::ColumnId;
+use crate::TableSchema;
+
+use super::histogram::Histogram;
+
+/// Manifests written before columns could be inline
+const MANIFEST_MAGIC_V1: u64 = u64::from_be_bytes(*b"manifest");
+/// Manifests written before columns had checksums
+const MANIFEST_MAGIC_V2: u64 = u64::from_be_bytes(*b"manifes2");
+/// Manifests written before tables had versions
+const MANIFEST_MAGIC_V3: u64 = u64::from_be_bytes(*b"manifes3");
+/// Manifests written before tables could be partitioned
+const MANIFEST_MAGIC_V4: u64 = u64::from_be_bytes(*b"manifes4");
+/// Manifests written before columns had fingerprints
+const MANIFEST_MAGIC_V5: u64 = u64::from_be_bytes(*b"manifes5");
+/// Manifests written before columns had histograms
+const MANIFEST_MAGIC_V6: u64 = u64::from_be_bytes(*b"manifes6");
+const MANIFEST_MAGIC: u64 = u64::from_be_bytes(*b"manifes7");
+
+/// The largest segment whose columns are stored inline
+pub(crate) const INLINE_SEGMENT_LIMIT: usize = 4096;
+/// The most bytes of inline columns a manifest may hold
+pub(crate) const INLINE_MANIFEST_LIMIT: usize = 4 * INLINE_SEGMENT_LIMIT;
+
+/// The name of the manifest file within a table directory
+pub(crate) const MANIFEST: &str = "MANIFEST";
+const MANIFEST_TMP: &str = "MANIFEST.tmp";
+
+/// The list of segments in a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Manifest {
+    /// The id to be given to the next segment
+    pub(crate) next_segment: u64,
+    pub(crate) segments: Vec<Segment>,
+    /// The version of the table that `segments` make up
+    pub(crate) version: u64,
+    /// When this version was made, in nanoseconds since the epoch
+    pub(crate) time: u64,
+    /// The earlier versions that are still remembered, oldest first
+    pub(crate) history: Vec<Snapshot>,
+    /// Segments that are only part of earlier versions
+    pub(crate) retired: Vec<Segment>,
+}
+
+/// The segments of an earlier version of a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+    pub(crate) version: u64,
+    /// When the version was made, in nanoseconds since the epoch
+    pub(crate) time: u64,
+    /// The ids of its segments, in order
+    pub(crate) segments: Vec<u64>,
+}
+
+/// Convert a time in nanoseconds since the epoch
+fn system_time(nanos: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos)
+}
+
+/// An immutable set of rows, stored as one file per raw column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Segment {
+    pub(crate) id: u64,
+    pub(crate) num_rows: u64,
+    /// The largest value in the table's time column, if it has one
+    pub(crate) max_time: Option<u64>,
+    /// The partition holding every row, if the table was partitioned when the
+    /// segment was saved
+    pub(crate) partition: Option<u64>,
+    pub(crate) files: Vec<ColumnFile>,
+}
+
+/// Where one raw column of a segment is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ColumnFile {
+    pub(crate) column: ColumnId,
+    pub(crate) fieldname: String,
+    pub(crate) data: ColumnData,
+    /// The [`checksum`] of the encoded column, unless it was saved before
+    /// columns had checksums
+    pub(crate) checksum: Option<u64>,
+    /// The fingerprint of the schema of the raw column it was saved as,
+    /// unless it was saved before columns had fingerprints
+    pub(crate) fingerprint: Option<u64>,
+    /// The histogram of the values of the column, unless it was saved before
+    /// columns had histograms, or is an index, or its values are too long
+    pub(crate) histogram: Option<Histogram>,
+}
+
+/// The encoded bytes of a column, or the file holding them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ColumnData {
+    File(String),
+    Inline(Vec<u8>),
+}
+
+/// The 64-bit FNV-1a hash of `bytes`
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    fnv(FNV_OFFSET, bytes)
+}
+
+impl ColumnFile {
+    /// The name of the file holding the column, unless it is inline
+    pub(crate) fn filename(&self) -> Option<&str> {
+        match &self.data {
+            ColumnData::File(f) => Some(f),
+            ColumnData::Inline(_) => None,
+        }
+    }
+}
+
+impl Segment {
+    /// Whether the columns are stored inline in the manifest
+    pub(crate) fn is_inline(&self) -> bool {
+        self.files
+            .iter()
+            .all(|f| matches!(f.data, ColumnData::Inline(_)))
+    }
+
+    /// Find the file holding a given raw column
+    pub(crate) fn file(&self, column: ColumnId, fieldname: &str) -> Option<&ColumnFile> {
+        self.files
+            .iter()
+            .find(|f| f.column == column && f.fieldname == fieldname)
+    }
+
+    /// Check that the columns of the segment were saved as the raw columns
+    /// of `schema`, so that reading it does not decode garbage.
+    ///
+    /// Columns added to the schema since the segment was saved, and columns
+    /// of the segment since dropped from the schema, are no mismatch, but
+    /// every column of the primary key must be in the segment.
+    pub(crate) fn check_schema(&self, schema: &TableSchema) -> Result<(), StorageError> {
+        for (i, c) in schema.raw_columns().enumerate() {
+            let matches = match self.file(c.id(), c.fieldname()) {
+                Some(file) => file.fingerprint.filter(|f| *f != c.fingerprint()).is_none(),
+                None => i >= schema.num_primary(),
+            };
+            if !matches {
+                return Err(StorageError::SchemaMismatch(c.display_name()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Manifest {
+    /// The number of bytes of inline columns, including those of retired
+    /// segments
+    pub(crate) fn inline_bytes(&self) -> usize {
+        self.segments
+            .iter()
+            .chain(self.retired.iter())
+            .flat_map(|s| s.files.iter())
+            .map(|f| match &f.data {
+                ColumnData::File(_) => 0,
+                ColumnData::Inline(bytes) => bytes.len(),
+            })
+            .sum()
+    }
+
+    /// Remember the current version, and start a new one made now.
+    ///
+    /// Any segment removed from the new version must be passed to
+    /// [`Manifest::retire`], so the old version can still be read.  The
+    /// history keeps growing until [`Manifest::forget_before`] is called.
+    pub(crate) fn new_version(&mut self) {
+        self.history.push(Snapshot {
+            version: self.version,
+            time: self.time,
+            segments: self.segments.iter().map(|s| s.id).collect(),
+        });
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        // Versions are ordered in time, even if the clock goes backwards.
+        self.time = std::cmp::max(now, self.time + 1);
+        self.version += 1;
+    }
+
+    /// Keep segments that are no longer current, for earlier versions
+    pub(crate) fn retire(&mut self, segments: Vec<Segment>) {
+        self.retired.extend(segments);
+    }
+
+    /// Forget every earlier version before `version`, returning the segments
+    /// that no remaining version uses.
+    pub(crate) fn forget_before(&mut self, version: u64) -> Vec<Segment> {
+        self.history.retain(|s| s.version >= version);
+        let history = &self.history;
+        let (kept, forgotten) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|s| history.iter().any(|h| h.segments.contains(&s.id)));
+        self.retired = kept;
+        forgotten
+    }
+
+    /// Every version that is still remembered, oldest first, with the time
+    /// it was made
+    pub(crate) fn versions(&self) -> Vec<(u64, SystemTime)> {
+        self.history
+            .iter()
+            .map(|h| (h.version, system_time(h.time)))
+            .chain(std::iter::once((self.version, system_time(self.time))))
+            .collect()
+    }
+
+    /// When a version of the table was made, in nanoseconds since the epoch,
+    /// if it is still remembered
+    pub(crate) fn version_time(&self, version: u64) -> Option<u64> {
+        if version == self.version {
+            return Some(self.time);
+        }
+        self.history
+            .iter()
+            .find(|s| s.version == version)
+            .map(|s| s.time)
+    }
+
+    /// The segments of a version of the table, if it is still remembered
+    pub(crate) fn version_segments(&self, version: u64) -> Option<Vec<Segment>> {
+        if version == self.version {
+            return Some(self.segments.clone());
+        }
+        let snapshot = self.history.iter().find(|s| s.version == version)?;
+        snapshot
+            .segments
+            .iter()
+            .map(|id| {
+                self.segments
+                    .iter()
+                    .chain(self.retired.iter())
+                    .find(|s| s.id == *id)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// Read the manifest of the table in `dir`.
+    ///
+    /// A directory with no manifest holds an empty table.
+    pub(crate) fn read(dir: &Path) -> Result<Self, StorageError> {
+        // The manifest is read in one go, since it may hold inline columns.
+        match fs::read(&dir.join(MANIFEST)) {
+            Ok(bytes) => Self::decode(Storage::from(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Atomically replace the manifest of the table in `dir`.
+    ///
+    /// The new manifest is written to a temporary file and synced before it is
+    /// renamed into place, so a crash leaves either the old or the new
+    /// manifest, never a partial one.
+    pub(crate) fn write(&self, dir: &Path) -> Result<(), StorageError> {
+        let tmp = dir.join(MANIFEST_TMP);
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes)?;
+        fs::write_synced(&tmp, &bytes)?;
+        fs::rename(&tmp, &dir.join(MANIFEST))?;
+        fs::sync_dir(dir)
+    }
+
+    pub(crate) fn encode<W: WriteEncoded>(&self, out: &mut W) -> Result<(), StorageError> {
+        out.write_u64(MANIFEST_MAGIC)?;
+        out.write_unsigned(self.next_segment)?;
+        write_segments(out, &self.segments)?;
+        out.write_unsigned(self.version)?;
+        out.write_unsigned(self.time)?;
+        write_segments(out, &self.retired)?;
+        out.write_unsigned(self.history.len() as u64)?;
+        for h in self.history.iter() {
+            out.write_unsigned(h.version)?;
+            out.write_unsigned(h.time)?;
+            out.write_unsigned(h.segments.len() as u64)?;
+            for id in h.segments.iter() {
+                out.write_unsigned(*id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn decode<R: ReadEncoded>(mut storage: R) -> Result<Self, StorageError> {
+        let magic = storage.read_u64()?;
+        if ![
+            MANIFEST_MAGIC,
+            MANIFEST_MAGIC_V6,
+            MANIFEST_MAGIC_V5,
+            MANIFEST_MAGIC_V4,
+            MANIFEST_MAGIC_V3,
+            MANIFEST_MAGIC_V2,
+            MANIFEST_MAGIC_V1,
+        ]
+        .contains(&magic)
+        {
+            return Err(StorageError::BadMagic(magic));
+        }
+        let next_segment = storage.read_usigned()?;
+        let segments = read_segments(&mut storage, magic)?;
+        if ![
+            MANIFEST_MAGIC,
+            MANIFEST_MAGIC_V6,
+            MANIFEST_MAGIC_V5,
+            MANIFEST_MAGIC_V4,
+        ]
+        .contains(&magic)
+        {
+            return Ok(Manifest {
+                next_segment,
+                segments,
+                ..Manifest::default()
+            });
+        }
+        let version = storage.read_usigned()?;
+        let time = storage.read_usigned()?;
+        let retired = read_segments(&mut storage, magic)?;
+        let mut history = Vec::new();
+        for _ in 0..storage.read_usigned()? {
+            let version = storage.read_usigned()?;
+            let time = storage.read_usigned()?;
+            let segments = (0..storage.read_usigned()?)
+                .map(|_| storage.read_usigned())
+                .collect::<Result<_, _>>()?;
+            history.push(Snapshot {
+                version,
+                time,
+                segments,
+            });
+        }
+        Ok(Manifest {
+            next_segment,
+            segments,
+            version,
+            time,
+            history,
+            retired,
+        })
+    }
+}
+
+fn write_segments<W: WriteEncoded>(out: &mut W, segments: &[Segment]) -> Result<(), StorageError> {
+    out.write_unsigned(segments.len() as u64)?;
+    for s in segments.iter() {
+        out.write_unsigned(s.id)?;
+        out.write_unsigned(s.num_rows)?;
+        if let Some(t) = s.max_time {
+            out.write_u8(1)?;
+            out.write_unsigned(t)?;
+        } else {
+            out.write_u8(0)?;
+        }
+        if let Some(p) = s.partition {
+            out.write_u8(1)?;
+            out.write_unsigned(p)?;
+        } else {
+            out.write_u8(0)?;
+        }
+        out.write_unsigned(s.files.len() as u64)?;
+        for f in s.files.iter() {
+            out.write_all(&f.column.0)?;
+            write_str(out, &f.fieldname)?;
+            match &f.data {
+                ColumnData::File(filename) => {
+                    out.write_u8(0)?;
+                    write_str(out, filename)?;
+                }
+                ColumnData::Inline(bytes) => {
+                    out.write_u8(1)?;
+                    out.write_unsigned(bytes.len() as u64)?;
+                    out.write_all(bytes)?;
+                }
+            }
+            for v in [f.checksum, f.fingerprint] {
+                if let Some(v) = v {
+                    out.write_u8(1)?;
+                    out.write_u64(v)?;
+                } else {
+                    out.write_u8(0)?;
+                }
+            }
+            if let Some(h) = &f.histogram {
+                out.write_u8(1)?;
+                h.encode(out)?;
+            } else {
+                out.write_u8(0)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a list of segments from a manifest with the given magic
+fn read_segments<R: ReadEncoded>(
+    storage: &mut R,
+    magic: u64,
+) -> Result<Vec<Segment>, StorageError> {
+    let n_segments = storage.read_usigned()?;
+    let mut segments = Vec::new();
+    for _ in 0..n_segments {
+        let id = storage.read_usigned()?;
+        let num_rows = storage.read_usigned()?;
+        let max_time = if storage.read_u8()? == 1 {
+            Some(storage.read_usigned()?)
+        } else {
+            None
+        };
+        let has_partition = [MANIFEST_MAGIC, MANIFEST_MAGIC_V6, MANIFEST_MAGIC_V5].contains(&magic);
+        let partition = if has_partition && storage.read_u8()? == 1 {
+            Some(storage.read_usigned()?)
+        } else {
+            None
+        };
+        let n_files = storage.read_usigned()?;
+        let mut files = Vec::new();
+        for _ in 0..n_files {
+            let mut column = [0; 16];
+            storage.read_exact(&mut column)?;
+            let fieldname = read_str(storage)?;
+            let data = if magic == MANIFEST_MAGIC_V1 || storage.read_u8()? == 0 {
+                ColumnData::File(read_str(storage)?)
+            } else {
+                let len = storage.read_usigned()?;
+                ColumnData::Inline(storage.read_vec(len)?)
+            };
+            let has_checksum = magic != MANIFEST_MAGIC_V1 && magic != MANIFEST_MAGIC_V2;
+            let checksum = if has_checksum && storage.read_u8()? == 1 {
+                Some(storage.read_u64()?)
+            } else {
+                None
+            };
+            let has_fingerprint = magic == MANIFEST_MAGIC || magic == MANIFEST_MAGIC_V6;
+            let fingerprint = if has_fingerprint && storage.read_u8()? == 1 {
+                Some(storage.read_u64()?)
+            } else {
+                None
+            };
+            let histogram = if magic == MANIFEST_MAGIC && storage.read_u8()? == 1 {
+                Some(Histogram::decode(storage)?)
+            } else {
+                None
+            };
+            files.push(ColumnFile {
+                column: ColumnId(column),
+                fieldname,
+                data,
+                checksum,
+                fingerprint,
+                histogram,
+            });
+        }
+        segments.push(Segment {
+            id,
+            num_rows,
+            max_time,
+            partition,
+            files,
+        });
+    }
+    Ok(segments)
+}
+
+fn write_str<W: WriteEncoded>(out: &mut W, s: &str) -> Result<(), StorageError> {
+    out.write_unsigned(s.len() as u64)?;
+    out.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+fn read_str<R: ReadEncoded>(storage: &mut R) -> Result<String, StorageError> {
+    let len = storage.read_usigned()?;
+    let buf = storage.read_vec(len)?;
+    String::from_utf8(buf).map_err(|_| StorageError::OutOfBounds("filename is not utf8"))
+}
+
+#[test]
+fn manifest_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(Manifest::read(dir.path()).unwrap(), Manifest::default());
+
+    let mut manifest = Manifest {
+        next_segment: 2,
+        segments: vec![Segment {
+            id: 1,
+            num_rows: 37,
+            max_time: Some(1234),
+            partition: Some(3),
+            files: vec![
+                ColumnFile {
+                    column: ColumnId::const_new(b"modified-column!"),
+                    fieldname: "seconds".to_string(),
+                    data: ColumnData::File("partition-3/00000001-6d6f646966696564".to_string()),
+                    checksum: Some(checksum(b"whatever")),
+                    fingerprint: Some(42),
+                    histogram: None,
+                },
+                ColumnFile {
+                    column: ColumnId::const_new(b"name-of-column!!"),
+                    fieldname: String::new(),
+                    data: ColumnData::Inline(b"some encoded column".to_vec()),
+                    checksum: None,
+                    fingerprint: None,
+                    histogram: None,
+                },
+            ],
+        }],
+        ..Manifest::default()
+    };
+    manifest.write(dir.path()).unwrap();
+    assert_eq!(Manifest::read(dir.path()).unwrap(), manifest);
+
+    manifest.new_version();
+    let old = std::mem::take(&mut manifest.segments);
+    manifest.retire(old);
+    manifest.write(dir.path()).unwrap();
+    let read = Manifest::read(dir.path()).unwrap();
+    assert_eq!(read, manifest);
+    assert_eq!(read.version, 1);
+    assert_eq!(read.version_segments(0).unwrap().len(), 1);
+    assert_eq!(read.version_segments(1).unwrap().len(), 0);
+    assert_eq!(manifest.forget_before(1).len(), 1);
+    assert!(manifest.retired.is_empty());
+    assert!(!dir.path().join(MANIFEST_TMP).exists());
+}
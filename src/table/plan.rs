@@ -0,0 +1,212 @@
+//! Planning how to find the rows of a table that match a predicate.
+//!
+//! The rows of each segment passing a test of a column are estimated from
+//! the [`Histogram`](super::histogram::Histogram) of the column, or from its
+//! minimum and maximum when the test passes every row or none of them.  The
+//! tests joined by `AND` are then evaluated on each segment most selective
+//! first, rather than in the order they were written, since evaluating stops
+//! once no row can match.  The same estimates decide whether searching an
+//! index is cheaper than filtering every segment.
+
+use super::{SegmentColumns, Table};
+use crate::column::encoding::StorageError;
+use crate::expr::{Comparison, Predicate, Test};
+use crate::{Expr, RawValue, TableSchema};
+
+/// Searching an index is chosen when it is estimated to find no more than
+/// this fraction of the rows of the table, since every segment holding a
+/// row that it finds must then be decoded
+const INDEX_FRACTION: f64 = 0.1;
+
+/// How the rows of a table that match a predicate are found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Plan<'a> {
+    /// Search the index of a column for the rows holding one of the values
+    Index(usize, &'a [RawValue]),
+    /// Filter each segment a chunk at a time before merging the rows
+    Segments,
+    /// Filter the merged rows, since rows of one segment may change whether
+    /// rows of another match
+    Merged,
+}
+
+/// A guess at the fraction of rows passing a test of a column without a
+/// histogram
+fn guess(test: &Test) -> f64 {
+    match test {
+        Test::Compare(Comparison::Equal, _) => 0.05,
+        Test::In(values) => (0.05 * values.len() as f64).min(1.0),
+        Test::Between(..) => 0.25,
+        Test::Compare(..) => 1.0 / 3.0,
+    }
+}
+
+impl SegmentColumns {
+    /// The estimated fraction of the rows of the segment in which raw column
+    /// `column` passes `test`
+    fn selectivity(&self, schema: &TableSchema, column: usize, test: &Test) -> f64 {
+        if self.num_rows == 0 {
+            return 0.0;
+        }
+        let Some(c) = &self.columns[column] else {
+            let default = schema
+                .raw_columns()
+                .nth(column)
+                .expect("column exists")
+                .default();
+            return if test.matches(default) { 1.0 } else { 0.0 };
+        };
+        match test.settled(&c.min(), &c.max()) {
+            Some(true) => 1.0,
+            Some(false) => 0.0,
+            None => match &self.histograms[column] {
+                Some(h) => h.estimate(test) / h.num_rows().max(1) as f64,
+                None => guess(test),
+            },
+        }
+    }
+
+    /// `predicate` with its tests ordered to be evaluated on this segment,
+    /// along with the estimated fraction of its rows that match
+    pub(super) fn plan(&self, schema: &TableSchema, predicate: &Predicate) -> (Predicate, f64) {
+        predicate.plan(&|column, test| self.selectivity(schema, column, test))
+    }
+}
+
+impl Table {
+    /// The estimated number of rows of the table that match `expr`.
+    ///
+    /// The estimate is found from histograms of the values of the columns of
+    /// each segment, kept in the manifest, so no column is decoded.  Rows of
+    /// different segments sharing a primary key are counted once for each.
+    pub fn estimate_rows(&self, expr: &Expr) -> Result<u64, StorageError> {
+        let predicate = expr.bind(&self.schema)?;
+        Ok(self.estimate(&predicate).round() as u64)
+    }
+
+    /// The estimated number of rows that match `predicate`
+    fn estimate(&self, predicate: &Predicate) -> f64 {
+        self.segments
+            .iter()
+            .map(|s| s.plan(&self.schema, predicate).1 * s.num_rows as f64)
+            .sum()
+    }
+
+    /// How to find the rows that match `predicate`.
+    ///
+    /// Of the indexed columns that `predicate` requires to equal one of some
+    /// values, the index estimated to find the fewest rows is searched, if it
+    /// finds few enough of them.
+    pub(crate) fn plan<'a>(&self, predicate: &'a Predicate) -> Plan<'a> {
+        let mut searches = Vec::new();
+        self.index_searches(predicate, &mut searches);
+        let total = self.num_rows() as f64;
+        let best = searches
+            .into_iter()
+            .map(|(column, values)| {
+                let search = Predicate::Test(column, Test::In(values.to_vec()));
+                (self.estimate(&search), column, values)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((rows, column, values)) = best {
+            if rows <= INDEX_FRACTION * total {
+                return Plan::Index(column, values);
+            }
+        }
+        if self.segments.len() > 1 && !predicate.only_tests_first(self.schema.num_primary()) {
+            Plan::Merged
+        } else {
+            Plan::Segments
+        }
+    }
+
+    /// Collect each indexed column that was read and the values it must hold
+    /// for a row to pass `predicate`
+    fn index_searches<'a>(
+        &self,
+        predicate: &'a Predicate,
+        searches: &mut Vec<(usize, &'a [RawValue])>,
+    ) {
+        if self.schema.num_primary() == 0 {
+            return;
+        }
+        match predicate {
+            Predicate::Test(i, test) => {
+                let Some(c) = self.schema.raw_columns().nth(*i) else {
+                    return;
+                };
+                if !self.projection[*i] || !self.schema.is_indexed(c) {
+                    return;
+                }
+                match test {
+                    Test::Compare(Comparison::Equal, v) => {
+                        searches.push((*i, std::slice::from_ref(v)))
+                    }
+                    Test::In(values) => searches.push((*i, values)),
+                    _ => (),
+                }
+            }
+            Predicate::And(a, b) => {
+                self.index_searches(a, searches);
+                self.index_searches(b, searches);
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn plan_by_histograms() {
+    use super::{person, TableBuilder};
+    use crate::ColumnSchema;
+
+    let age = ColumnSchema::<u64>::new("age");
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(age.raw().chain(ColumnSchema::<bool>::new("happy").raw()));
+    schema.add_index(&age).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    // Three in four people are of age zero.
+    for i in 0..4000u64 {
+        let age = if i % 4 == 0 { i % 97 + 1 } else { 0 };
+        let row = person(&format!("person {i}"), age, i % 2 == 0);
+        builder.insert_raw_row(row).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let table = Table::read(dir.path(), &schema).unwrap();
+
+    let age = |c, v| Expr::Compare("age".to_string(), c, RawValue::U64(v));
+    let happy = Expr::Compare("happy".to_string(), Comparison::Equal, RawValue::Bool(true));
+    let estimate = |expr: &Expr| table.estimate_rows(expr).unwrap() as f64;
+    assert!((estimate(&age(Comparison::Equal, 0)) - 3000.0).abs() < 100.0);
+    assert!((estimate(&age(Comparison::Greater, 50)) - 500.0).abs() < 100.0);
+    assert_eq!(estimate(&age(Comparison::Greater, 1000)), 0.0);
+    assert_eq!(estimate(&happy), 2000.0);
+
+    // The index is searched for a rare age, but not for a common one.
+    let common = age(Comparison::Equal, 0).bind(&schema).unwrap();
+    assert_eq!(table.plan(&common), Plan::Segments);
+    let rare = age(Comparison::Equal, 50).bind(&schema).unwrap();
+    assert_eq!(table.plan(&rare), Plan::Index(1, &[RawValue::U64(50)]));
+
+    // The age is tested before whether people are happy, which it was
+    // written after.
+    let expr = happy.clone().and(age(Comparison::Greater, 90));
+    let (planned, _) = table.segments[0].plan(&schema, &expr.bind(&schema).unwrap());
+    assert!(
+        matches!(&planned, Predicate::And(a, _) if matches!(**a, Predicate::Test(1, _))),
+        "{planned:?}"
+    );
+    let rows = table.to_rows().unwrap();
+    for expr in [
+        expr,
+        happy.or(age(Comparison::Equal, 0)),
+        !age(Comparison::Less, 3),
+    ] {
+        let predicate = expr.bind(&schema).unwrap();
+        let mut expected = rows.clone();
+        expected.retain(|r| predicate.matches(&r.values));
+        assert_eq!(table.select(&expr).unwrap(), expected, "{expr:?}");
+    }
+}
@@ -0,0 +1,100 @@
+//! Rollup tables, which group the rows of another table by a prefix of its
+//! primary key.
+//!
+//! A rollup is rebuilt from the merged rows of the table it rolls up.  Each
+//! of those rows is cut down to the columns of the rollup, and the rows of
+//! each group are merged under the aggregations of the rollup, just as rows
+//! sharing a primary key are merged when a table is read.
+
+use std::path::Path;
+
+use super::{merge_rows, Table};
+use crate::column::encoding::StorageError;
+use crate::fs;
+use crate::{DbLayout, RawRow, TableSchema};
+
+impl Table {
+    /// Replace the rows of the rollup in `dir` with those rolling up `rows`,
+    /// the merged rows of the table with schema `base`, in files named by
+    /// `layout`.
+    ///
+    /// Each column of the rollup is found in `base` by its id, so a column
+    /// added to the rollup that `base` lacks holds its default.
+    pub(crate) fn roll_up(
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+        base: &TableSchema,
+        rows: &[RawRow],
+    ) -> Result<(), StorageError> {
+        let columns = schema
+            .raw_columns()
+            .map(|c| {
+                base.raw_columns()
+                    .position(|b| b.id() == c.id() && b.fieldname() == c.fieldname())
+                    .ok_or(c.default())
+            })
+            .collect::<Vec<_>>();
+        let grouped = rows
+            .iter()
+            .map(|r| {
+                columns
+                    .iter()
+                    .map(|c| match c {
+                        Ok(i) => r.values[*i].clone(),
+                        Err(default) => (*default).clone(),
+                    })
+                    .collect()
+            })
+            .zip(0..)
+            .collect();
+        let merged = merge_rows(schema, grouped)?;
+        fs::create_dir_all(dir)?;
+        Table::rewrite(dir, layout, schema, &merged)
+    }
+}
+
+#[test]
+fn roll_up_rows() {
+    use crate::{ColumnSchema, RawValue};
+
+    let mut base = TableSchema::new("visits");
+    base.add_primary(
+        ColumnSchema::<String>::new("site")
+            .raw()
+            .chain(ColumnSchema::<String>::new("page").raw()),
+    );
+    base.add_max(ColumnSchema::<u64>::new("latest").raw());
+    base.add_sum(ColumnSchema::<u64>::new("visits").raw());
+    assert!(base.rollup("too_many", 3).is_err());
+    let schema = base.rollup("site_visits", 1).unwrap();
+    assert_eq!(schema.rollup_of(), Some(base.id()));
+    assert_eq!(schema.num_primary(), 1);
+
+    let row = |values: &[&str]| -> RawRow {
+        values
+            .iter()
+            .map(|v| match v.parse() {
+                Ok(n) => RawValue::U64(n),
+                Err(_) => RawValue::Bytes(v.as_bytes().to_vec()),
+            })
+            .collect()
+    };
+    let rows = [
+        row(&["a.org", "/", "10", "3"]),
+        row(&["a.org", "/about", "12", "4"]),
+        row(&["b.org", "/", "7", "1"]),
+    ];
+    let dir = tempfile::tempdir().unwrap();
+    Table::roll_up(dir.path(), &crate::FlatLayout, &schema, &base, &rows).unwrap();
+    let rolled = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    assert_eq!(
+        rolled,
+        [row(&["a.org", "12", "7"]), row(&["b.org", "7", "1"])]
+    );
+
+    // Rolling up again replaces the old rows.
+    Table::roll_up(dir.path(), &crate::FlatLayout, &schema, &base, &rows[2..]).unwrap();
+    let rolled = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    assert_eq!(rolled, [row(&["b.org", "7", "1"])]);
+}
@@ -0,0 +1,177 @@
+//! Addressing the rows of a table as they are stored.
+//!
+//! A [`RowId`] names a row by the id of the segment holding it and its
+//! offset within the segment.  Segments are never changed once saved, so a
+//! row id keeps naming the same row for as long as its segment is part of
+//! the table.  Compacting the table, or deleting rows from it, saves new
+//! segments in place of the old ones, whose row ids then name nothing.
+
+use super::{SegmentColumns, Table};
+use crate::column::encoding::StorageError;
+use crate::expr::{Predicate, Selection, Test};
+use crate::query::column_index;
+use crate::{RawRow, RawValue};
+
+/// Where a row is stored, see the [module](self) docs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RowId {
+    segment: u64,
+    row: u64,
+}
+
+impl RowId {
+    /// The id of the segment holding the row
+    pub fn segment(&self) -> u64 {
+        self.segment
+    }
+
+    /// The offset of the row within its segment
+    pub fn row(&self) -> u64 {
+        self.row
+    }
+}
+
+impl std::fmt::Display for RowId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.segment, self.row)
+    }
+}
+
+impl Table {
+    /// The saved segments, with their ids
+    fn saved_segments(&self) -> impl Iterator<Item = (u64, &SegmentColumns)> {
+        self.segments.iter().filter_map(|s| Some((s.id?, s)))
+    }
+
+    /// Read every row stored in the saved segments of the table, with its
+    /// [`RowId`], in order of segment and row.
+    ///
+    /// Unlike [`Table::to_rows`], rows sharing a primary key are not merged,
+    /// and rows that are not yet saved are left out, since they have no id.
+    pub fn rows_with_ids(&self) -> Result<Vec<(RowId, RawRow)>, StorageError> {
+        let mut rows = Vec::new();
+        for (id, s) in self.saved_segments() {
+            let values = s
+                .columns
+                .iter()
+                .map(|c| {
+                    c.as_ref()
+                        .map(|c| c.read_values_cached(self.cache.as_ref()))
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            for row in 0..s.num_rows {
+                let values = values
+                    .iter()
+                    .zip(self.schema.raw_columns())
+                    .map(|(v, c)| match v {
+                        Some(v) => v[row as usize].clone(),
+                        None => c.default().clone(),
+                    })
+                    .collect();
+                rows.push((RowId { segment: id, row }, values));
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Read the row stored at `id`, if its segment is part of the table.
+    ///
+    /// Each column is read only as far as the row, rather than decoded in
+    /// full, and columns that were not read hold their defaults.
+    pub fn get_row(&self, id: RowId) -> Result<Option<RawRow>, StorageError> {
+        let Some((_, s)) = self
+            .saved_segments()
+            .find(|(segment, s)| *segment == id.segment && id.row < s.num_rows)
+        else {
+            return Ok(None);
+        };
+        s.columns
+            .iter()
+            .zip(self.schema.raw_columns())
+            .map(|(column, c)| match column {
+                Some(column) => column.value_at(id.row),
+                None => Ok(c.default().clone()),
+            })
+            .collect::<Result<RawRow, _>>()
+            .map(Some)
+    }
+
+    /// The ids of the stored rows whose indexed raw column `column` holds
+    /// `value`, found with the index of each segment.
+    ///
+    /// As with [`Table::lookup_by_index`], the column must be indexed, and
+    /// segments saved before it was are searched instead.  Rows are not
+    /// merged, so a row found may since have been changed by another with
+    /// the same primary key.
+    pub fn lookup_row_ids(
+        &self,
+        column: &str,
+        value: &RawValue,
+    ) -> Result<Vec<RowId>, StorageError> {
+        let i = column_index(&self.schema, column)?;
+        let c = self.schema.raw_columns().nth(i).expect("column exists");
+        if !self.schema.is_indexed(c) {
+            return Err(StorageError::Query(format!(
+                "column {column} is not indexed"
+            )));
+        }
+        let values = [value.clone()];
+        let search = Predicate::Test(i, Test::In(values.to_vec()));
+        let mut ids = Vec::new();
+        for (segment, s) in self.saved_segments() {
+            let selection: Selection = match &s.indexes[i] {
+                Some(index) => index.select(&values)?,
+                None => search.select(&self.schema, &s.columns, s.num_rows)?,
+            };
+            ids.extend(selection.rows().map(|row| RowId { segment, row }));
+        }
+        Ok(ids)
+    }
+}
+
+#[test]
+fn row_ids() {
+    use super::{person, TableBuilder};
+    use crate::{ColumnSchema, TableSchema};
+
+    let age = ColumnSchema::<u64>::new("age");
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(age.raw().chain(ColumnSchema::<bool>::new("happy").raw()));
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("Ann", 30, true)).unwrap();
+    builder.insert_raw_row(person("Bo", 40, false)).unwrap();
+    builder.save(dir.path()).unwrap();
+    schema.add_index(&age).unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("Ann", 31, true)).unwrap();
+    builder.insert_raw_row(person("Cy", 40, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let table = Table::read(dir.path(), &schema).unwrap();
+    let rows = table.rows_with_ids().unwrap();
+    assert_eq!(rows.len(), 4);
+    for (id, row) in rows.iter() {
+        assert_eq!(table.get_row(*id).unwrap().as_ref(), Some(row));
+    }
+    let (first, second) = (rows[0].0, rows[2].0);
+    assert_ne!(first.segment(), second.segment());
+    assert_eq!((first.row(), rows[3].0.row()), (0, 1));
+
+    // The older segment has no index, and is searched instead.
+    let found = table.lookup_row_ids("age", &RawValue::U64(40)).unwrap();
+    assert_eq!(found, [rows[1].0, rows[3].0]);
+    assert!(table
+        .lookup_row_ids("happy", &RawValue::Bool(true))
+        .is_err());
+
+    // Compacting replaces the segments, so their ids name nothing.
+    Table::compact(dir.path(), &schema).unwrap();
+    let table = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(table.get_row(first).unwrap(), None);
+    assert_eq!(table.rows_with_ids().unwrap().len(), 3);
+    let unsaved = table.with_rows(&[person("Di", 50, true)]).unwrap();
+    assert_eq!(unsaved.rows_with_ids().unwrap().len(), 3);
+}
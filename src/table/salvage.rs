@@ -0,0 +1,259 @@
+//! Saving what can be read of a table whose column files are cut short.
+//!
+//! A column file cut off in the middle of a chunk, as by a full disk or a
+//! copy that did not finish, fails every read of its table.  A salvage keeps
+//! the complete chunks at the start of each such column, see
+//! [`RawColumn::salvage_values`], and trims the other columns of its segment
+//! to as many rows, so the segment is replaced by one holding its first rows.
+//! The rows of a segment are sorted, so those kept still are, and the
+//! segments that read completely are left as they are.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::manifest::{ColumnData, ColumnFile, Manifest};
+use super::verify::InconsistencyKind;
+use super::{write_segment, Table};
+use crate::column::encoding::StorageError;
+use crate::column::storage::Storage;
+use crate::fs;
+use crate::{DbLayout, EncodeOptions, FlatLayout, RawColumn, RawRow, RawValue, TableSchema};
+
+/// A segment that a salvage replaced, see [`SalvageReport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SalvagedSegment {
+    segment: u64,
+    num_rows: u64,
+    kept_rows: u64,
+    damaged: Vec<(String, InconsistencyKind)>,
+}
+
+impl SalvagedSegment {
+    /// The id the segment had
+    pub fn segment(&self) -> u64 {
+        self.segment
+    }
+    /// The number of rows the segment held
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+    /// The number of rows kept, in a new segment unless there are none
+    pub fn kept_rows(&self) -> u64 {
+        self.kept_rows
+    }
+    /// The raw columns that could not be read completely, with what stopped
+    /// each of them
+    pub fn damaged(&self) -> &[(String, InconsistencyKind)] {
+        &self.damaged
+    }
+}
+
+/// What a salvage of a table did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    segments: Vec<SalvagedSegment>,
+}
+
+impl SalvageReport {
+    /// The segments replaced, in the order they were in the table
+    pub fn segments(&self) -> &[SalvagedSegment] {
+        &self.segments
+    }
+    /// The number of rows lost
+    pub fn rows_lost(&self) -> u64 {
+        self.segments.iter().map(|s| s.num_rows - s.kept_rows).sum()
+    }
+    /// Whether every segment read completely, so nothing was changed
+    pub fn is_intact(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+impl Table {
+    /// Replace each segment of the table in `dir` that has a raw column of
+    /// the schema that cannot be read completely with one holding the rows
+    /// that can be, see the [module](self) docs.
+    ///
+    /// The damaged segments are kept for earlier versions of the table, just
+    /// as [`Table::compact`] keeps the segments it replaces, so the table can
+    /// still be read as it was until they are forgotten.  Only a manifest
+    /// that cannot be read, or a segment that cannot be written, is an error.
+    pub fn salvage<P: AsRef<Path>>(
+        dir: P,
+        schema: &TableSchema,
+    ) -> Result<SalvageReport, StorageError> {
+        Table::salvage_with_layout(dir.as_ref(), &FlatLayout, schema)
+    }
+
+    /// Salvage the table in `dir` as [`Table::salvage`] does, naming the
+    /// files of the new segments by `layout`
+    pub(crate) fn salvage_with_layout(
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+    ) -> Result<SalvageReport, StorageError> {
+        let mut manifest = Manifest::read(dir)?;
+        let mut report = SalvageReport::default();
+        let mut kept = BTreeMap::new();
+        for s in manifest.segments.iter() {
+            let mut columns = Vec::new();
+            let mut damaged = Vec::new();
+            for (i, c) in schema.raw_columns().enumerate() {
+                let values = match s.file(c.id(), c.fieldname()) {
+                    Some(f) => salvage_column(dir, f, s.num_rows),
+                    None if i < schema.num_primary() => {
+                        Err((Vec::new(), InconsistencyKind::Missing))
+                    }
+                    None => Ok(vec![c.default().clone(); s.num_rows as usize]),
+                };
+                columns.push(match values {
+                    Ok(values) => values,
+                    Err((values, kind)) => {
+                        damaged.push((c.display_name(), kind));
+                        values
+                    }
+                });
+            }
+            if damaged.is_empty() {
+                continue;
+            }
+            let num_rows = columns
+                .iter()
+                .map(|c| c.len())
+                .chain([s.num_rows as usize])
+                .min()
+                .unwrap_or(0);
+            let rows = (0..num_rows)
+                .map(|r| columns.iter().map(|c| c[r].clone()).collect::<RawRow>())
+                .collect::<Vec<_>>();
+            report.segments.push(SalvagedSegment {
+                segment: s.id,
+                num_rows: s.num_rows,
+                kept_rows: num_rows as u64,
+                damaged,
+            });
+            kept.insert(s.id, rows);
+        }
+        if report.is_intact() {
+            return Ok(report);
+        }
+        manifest.new_version();
+        let mut retired = Vec::new();
+        for s in std::mem::take(&mut manifest.segments) {
+            let Some(rows) = kept.remove(&s.id) else {
+                manifest.segments.push(s);
+                continue;
+            };
+            if !rows.is_empty() {
+                let options = EncodeOptions::default();
+                write_segment(
+                    dir,
+                    layout,
+                    &mut manifest,
+                    schema,
+                    s.partition,
+                    &rows,
+                    options,
+                )?;
+            }
+            retired.push(s);
+        }
+        manifest.retire(retired);
+        manifest.write(dir)?;
+        Ok(report)
+    }
+}
+
+/// The values of the rows of a column of a segment holding `num_rows`, or
+/// those that can be read of them along with what stopped the rest
+fn salvage_column(
+    dir: &Path,
+    file: &ColumnFile,
+    num_rows: u64,
+) -> Result<Vec<RawValue>, (Vec<RawValue>, InconsistencyKind)> {
+    let bytes = match &file.data {
+        ColumnData::File(filename) => fs::read(&dir.join(filename)).map_err(|e| {
+            let kind = InconsistencyKind::Unreadable {
+                file: filename.clone(),
+                error: e.to_string(),
+            };
+            (Vec::new(), kind)
+        })?,
+        ColumnData::Inline(bytes) => bytes.as_slice().into(),
+    };
+    let raw = RawColumn::open_storage(Storage::from(bytes))
+        .map_err(|e| (Vec::new(), InconsistencyKind::Header(e.to_string())))?;
+    let (values, error) = raw.salvage_values();
+    if let Some(e) = error {
+        return Err((values, InconsistencyKind::Undecodable(e.to_string())));
+    }
+    if values.len() as u64 != num_rows {
+        let rows = values.len() as u64;
+        let kind = InconsistencyKind::RowCount {
+            rows,
+            expected: num_rows,
+        };
+        return Err((values, kind));
+    }
+    Ok(values)
+}
+
+#[test]
+fn salvage_truncated_column() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for i in 0..5000 {
+        builder
+            .insert_raw_row(person(&format!("person {i:04}"), i, i % 3 == 0))
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+    let all = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    assert!(Table::salvage(dir.path(), &schema).unwrap().is_intact());
+
+    // Cut the ages off in the middle of a chunk.
+    let manifest = Manifest::read(dir.path()).unwrap();
+    let ages = dir
+        .path()
+        .join(manifest.segments[0].files[1].filename().unwrap());
+    let bytes = std::fs::read(&ages).unwrap();
+    std::fs::write(&ages, &bytes[..bytes.len() / 2 - 1]).unwrap();
+    assert!(Table::read(dir.path(), &schema)
+        .and_then(|t| t.to_rows())
+        .is_err());
+
+    let report = Table::salvage(dir.path(), &schema).unwrap();
+    assert_eq!(report.segments().len(), 1);
+    let salvaged = &report.segments()[0];
+    assert_eq!(salvaged.segment(), 0);
+    assert_eq!(salvaged.num_rows(), 5000);
+    assert!(salvaged.kept_rows() > 1000 && salvaged.kept_rows() < 5000);
+    assert_eq!(report.rows_lost(), 5000 - salvaged.kept_rows());
+    assert_eq!(salvaged.damaged().len(), 1);
+    assert_eq!(salvaged.damaged()[0].0, "age");
+    assert!(matches!(
+        salvaged.damaged()[0].1,
+        InconsistencyKind::Undecodable(_)
+    ));
+
+    // The rows kept are the first of the segment, with the rest of the table.
+    let kept = salvaged.kept_rows() as usize;
+    let rows = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    // David sorts first, and is in the segment left as it was.
+    assert_eq!(rows, all[..kept + 1]);
+    assert!(Table::verify(dir.path(), &schema).is_clean());
+    assert!(Table::salvage(dir.path(), &schema).unwrap().is_intact());
+
+    // The new segment takes the place of the damaged one, which is kept for
+    // the version before the salvage.
+    let manifest = Manifest::read(dir.path()).unwrap();
+    let ids = manifest.segments.iter().map(|s| s.id).collect::<Vec<_>>();
+    assert_eq!(ids, [2, 1]);
+    assert_eq!(manifest.retired[0].id, 0);
+}
@@ -0,0 +1,109 @@
+//! Reading a random sample of the rows of a table, for approximate queries
+//! and statistics that need not scan every row.
+//!
+//! Rows are picked uniformly from those stored, so each chunk of a column
+//! is as likely to be read as the rows it holds are many.  Each column of a
+//! segment is read only up to its last sampled row, skipping over the
+//! chunks holding no sampled row, rather than decoded in full.
+
+use rand::SeedableRng;
+
+use super::Table;
+use crate::column::encoding::StorageError;
+use crate::RawRow;
+
+impl Table {
+    /// Read `n` rows picked at random from those stored, or every row if
+    /// there are no more than `n`, in order of segment and row.
+    ///
+    /// Rows are sampled as they are stored, before rows sharing a primary
+    /// key are merged, so a key may be sampled more than once.  Columns that
+    /// were not read hold their defaults.
+    pub fn sample(&self, n: u64) -> Result<Vec<RawRow>, StorageError> {
+        self.sample_with(n, &mut rand::rngs::StdRng::from_entropy())
+    }
+
+    /// Read about `fraction` of the rows stored, picked at random, see
+    /// [`Table::sample`]
+    pub fn sample_fraction(&self, fraction: f64) -> Result<Vec<RawRow>, StorageError> {
+        let n = (fraction.clamp(0.0, 1.0) * self.num_rows() as f64).round() as u64;
+        self.sample(n)
+    }
+
+    fn sample_with(&self, n: u64, rng: &mut impl rand::Rng) -> Result<Vec<RawRow>, StorageError> {
+        let total = self.num_rows();
+        let mut picked = rand::seq::index::sample(rng, total as usize, n.min(total) as usize)
+            .into_iter()
+            .map(|i| i as u64)
+            .collect::<Vec<_>>();
+        picked.sort_unstable();
+
+        let mut rows = Vec::with_capacity(picked.len());
+        let mut start = 0;
+        let mut picked = picked.as_slice();
+        for s in self.segments.iter() {
+            let end = start + s.num_rows;
+            let here = picked.partition_point(|&i| i < end);
+            let offsets = picked[..here].iter().map(|i| i - start).collect::<Vec<_>>();
+            picked = &picked[here..];
+            start = end;
+            if offsets.is_empty() {
+                continue;
+            }
+            let columns = s
+                .columns
+                .iter()
+                .zip(self.schema.raw_columns())
+                .map(|(column, c)| match column {
+                    Some(column) => column.values_at(&offsets),
+                    None => Ok(vec![c.default().clone(); offsets.len()]),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            for i in 0..offsets.len() {
+                rows.push(columns.iter().map(|c| c[i].clone()).collect());
+            }
+        }
+        Ok(rows)
+    }
+}
+
+#[test]
+fn sample() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    for segment in 0..3u64 {
+        let mut builder = TableBuilder::new(&schema);
+        for i in 0..1000 {
+            let row = person(&format!("person {i}"), i % 50 + segment, i % 3 == 0);
+            builder.insert_raw_row(row).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let table = Table::read(dir.path(), &schema).unwrap();
+    let stored = table
+        .rows_with_ids()
+        .unwrap()
+        .into_iter()
+        .map(|(_, row)| row)
+        .collect::<Vec<_>>();
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(2618);
+    let sampled = table.sample_with(100, &mut rng).unwrap();
+    assert_eq!(sampled.len(), 100);
+    let mut rest = stored.iter();
+    for row in sampled.iter() {
+        assert!(rest.any(|r| r == row), "{row:?} is not stored in order");
+    }
+    // Roughly a third of the rows are happy, and so of the sample.
+    let happy = sampled
+        .iter()
+        .filter(|r| r.values()[2] == crate::RawValue::Bool(true))
+        .count();
+    assert!((20..50).contains(&happy), "{happy}");
+
+    assert_eq!(table.sample(10_000).unwrap(), stored);
+    assert_eq!(table.sample_fraction(0.01).unwrap().len(), 30);
+    assert_eq!(table.sample(0).unwrap(), []);
+}
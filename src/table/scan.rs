@@ -0,0 +1,186 @@
+//! Scanning a table on many threads at once.
+//!
+//! The segments of a table, and the columns of each segment, are stored
+//! apart, so they can be decoded independently.  A parallel scan first
+//! filters each segment on a thread of its own, and then decodes each column
+//! of each segment that holds rows to read on a thread of its own, before
+//! merging the rows on the calling thread.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::{merge_rows, projection, Table};
+use crate::column::encoding::StorageError;
+use crate::expr::Selection;
+use crate::lens::ColumnId;
+use crate::{Expr, RawRow, RawValue};
+
+/// Apply `f` to each of `items` on up to `threads` threads, each taking the
+/// next item that no thread has yet taken, giving the results in the order
+/// of `items`
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let work = || {
+        let mut done = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(item) = items.get(i) else {
+                return done;
+            };
+            done.push((i, f(item)));
+        }
+    };
+    let mut results = items.iter().map(|_| None).collect::<Vec<_>>();
+    std::thread::scope(|scope| {
+        let workers = (0..threads.clamp(1, items.len().max(1)))
+            .map(|_| scope.spawn(work))
+            .collect::<Vec<_>>();
+        for worker in workers {
+            let done = worker
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e));
+            for (i, r) in done {
+                results[i] = Some(r);
+            }
+        }
+    });
+    results
+        .into_iter()
+        .map(|r| r.expect("every item was taken"))
+        .collect()
+}
+
+impl Table {
+    /// Read the rows of the table that match `expr`, in sorted order, with
+    /// the work spread over a thread for each CPU.
+    ///
+    /// Only the raw columns of `columns` are returned, and every other column
+    /// holds its default, as with [`Table::read_projected`].  The segments
+    /// are filtered as [`Table::select`] would, a segment at a time on each
+    /// thread, and then the columns of the segments holding rows that match
+    /// are decoded a column at a time on each thread.
+    pub fn par_scan(&self, columns: &[ColumnId], expr: &Expr) -> Result<Vec<RawRow>, StorageError> {
+        let predicate = expr.bind(&self.schema)?;
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        // Filtering each segment gives the same rows as filtering the merged
+        // rows, as long as rows of other segments cannot change those tested.
+        let by_segment =
+            self.segments.len() == 1 || predicate.only_tests_first(self.schema.num_primary());
+        let selections = parallel_map(&self.segments, threads, |s| {
+            if by_segment {
+                let (planned, _) = s.plan(&self.schema, &predicate);
+                planned.select(&self.schema, &s.columns, s.num_rows)
+            } else {
+                Ok(Selection::all(s.num_rows))
+            }
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+        // Columns tested after merging must be merged correctly too.
+        let mut needed = columns.to_vec();
+        if !by_segment {
+            needed.extend(
+                self.schema
+                    .raw_columns()
+                    .enumerate()
+                    .filter(|(i, _)| predicate.tests(*i))
+                    .map(|(_, c)| c.id()),
+            );
+        }
+        let decode = projection(&self.schema, &needed);
+        let mut jobs = Vec::new();
+        for (i, (s, selection)) in self.segments.iter().zip(selections.iter()).enumerate() {
+            if selection.is_empty() {
+                continue;
+            }
+            for (c, column) in s.columns.iter().enumerate() {
+                if let Some(column) = column.as_ref().filter(|_| decode[c]) {
+                    jobs.push((i, c, column));
+                }
+            }
+        }
+        let decoded = parallel_map(&jobs, threads, |(_, _, column)| {
+            column.read_values_cached(self.cache.as_ref())
+        });
+        let mut values: Vec<Vec<Option<Arc<Vec<RawValue>>>>> = self
+            .segments
+            .iter()
+            .map(|s| vec![None; s.columns.len()])
+            .collect();
+        for ((s, c, _), v) in jobs.iter().zip(decoded) {
+            values[*s][*c] = Some(v?);
+        }
+
+        let mut rows = Vec::new();
+        for (segment, (selection, values)) in (0..).zip(selections.iter().zip(values)) {
+            self.push_rows(&mut rows, segment, selection, values);
+        }
+        let mut rows = merge_rows(&self.schema, rows)?;
+        if !by_segment {
+            rows.retain(|r| predicate.matches(&r.values));
+        }
+        let read = projection(&self.schema, columns)
+            .into_iter()
+            .zip(self.projection.iter())
+            .map(|(wanted, read)| wanted && *read)
+            .collect::<Vec<_>>();
+        self.reset_unread(&mut rows, &read);
+        Ok(rows)
+    }
+}
+
+#[test]
+fn par_scan_matches_select() {
+    use super::{person, test_schema, TableBuilder};
+    use crate::Comparison;
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    for segment in 0..4u64 {
+        let mut builder = TableBuilder::new(&schema);
+        for i in 0..500 {
+            let name = format!("person {}", (i * 7 + segment * 13) % 700);
+            let row = person(&name, i % 90 + segment, (i + segment) % 3 == 0);
+            builder.insert_raw_row(row).unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    }
+    let table = Table::read(dir.path(), &schema).unwrap();
+    let ids = schema.raw_columns().map(|c| c.id()).collect::<Vec<_>>();
+
+    let exprs = [
+        Expr::Compare(
+            "name".to_string(),
+            Comparison::Less,
+            RawValue::Bytes(b"person 3".to_vec()),
+        ),
+        Expr::Compare("age".to_string(), Comparison::Greater, RawValue::U64(50)),
+        Expr::Compare("happy".to_string(), Comparison::Equal, RawValue::Bool(true)).or(
+            Expr::Compare("age".to_string(), Comparison::Less, RawValue::U64(5)),
+        ),
+    ];
+    for expr in exprs {
+        assert_eq!(
+            table.par_scan(&ids, &expr).unwrap(),
+            table.select(&expr).unwrap()
+        );
+        // Columns that were not asked for hold their defaults.
+        let names = table.par_scan(&ids[..1], &expr).unwrap();
+        let expected = table
+            .select(&expr)
+            .unwrap()
+            .into_iter()
+            .map(|r| {
+                let mut row = person("", 0, false);
+                row.values[0] = r.values[0].clone();
+                row
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, expected);
+    }
+}
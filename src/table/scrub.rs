@@ -0,0 +1,170 @@
+//! Checking the segments of a table for corruption.
+//!
+//! Segments are never modified once written, so a scrub may run alongside
+//! readers and writers.  It reads one column at a time, and keeps going after
+//! finding a problem, so that a single scrub finds all the damage in a table.
+
+use std::path::Path;
+
+use super::manifest::{ColumnFile, Manifest, Segment};
+use super::verify::check_column;
+use super::Table;
+use crate::column::encoding::StorageError;
+use crate::{RawColumnSchema, TableSchema};
+
+/// A problem found in one column of a segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    segment: u64,
+    column: String,
+    problem: String,
+}
+
+impl Corruption {
+    /// The id of the segment
+    pub fn segment(&self) -> u64 {
+        self.segment
+    }
+    /// The name of the raw column
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+    /// What is wrong with the column
+    pub fn problem(&self) -> &str {
+        &self.problem
+    }
+}
+
+impl std::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "segment {} column {}: {}",
+            self.segment, self.column, self.problem
+        )
+    }
+}
+
+/// What a scrub of a table found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    segments: u64,
+    columns: u64,
+    corruptions: Vec<Corruption>,
+}
+
+impl ScrubReport {
+    /// The number of segments checked
+    pub fn segments(&self) -> u64 {
+        self.segments
+    }
+    /// The number of columns checked
+    pub fn columns(&self) -> u64 {
+        self.columns
+    }
+    /// The problems found
+    pub fn corruptions(&self) -> &[Corruption] {
+        &self.corruptions
+    }
+    /// Whether no problems were found
+    pub fn is_clean(&self) -> bool {
+        self.corruptions.is_empty()
+    }
+}
+
+impl Table {
+    /// Check every column of every segment of the table in `dir` against the
+    /// manifest.
+    ///
+    /// Each column must match its checksum, decode completely, hold as many
+    /// rows as its segment, and hold values of the kind the schema expects.
+    /// The time column must agree with the largest time recorded for its
+    /// segment.  Columns saved before there were checksums are checked for
+    /// everything except their checksum.
+    ///
+    /// Problems with columns are reported rather than returned as errors, which
+    /// are kept for a manifest that cannot be read.
+    pub fn scrub<P: AsRef<Path>>(
+        dir: P,
+        schema: &TableSchema,
+    ) -> Result<ScrubReport, StorageError> {
+        let dir = dir.as_ref();
+        let manifest = Manifest::read(dir)?;
+        let mut report = ScrubReport::default();
+        for s in manifest.segments.iter() {
+            report.segments += 1;
+            for f in s.files.iter() {
+                report.columns += 1;
+                let column = schema
+                    .raw_columns()
+                    .enumerate()
+                    .find(|(_, c)| c.id() == f.column && c.fieldname() == f.fieldname);
+                if let Err(problem) = scrub_column(dir, schema, s, f, column) {
+                    report.corruptions.push(Corruption {
+                        segment: s.id,
+                        column: column
+                            .map(|(_, c)| c.display_name())
+                            .unwrap_or_else(|| format!("{}.{}", f.column, f.fieldname)),
+                        problem,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn scrub_column(
+    dir: &Path,
+    schema: &TableSchema,
+    segment: &Segment,
+    file: &ColumnFile,
+    column: Option<(usize, &RawColumnSchema)>,
+) -> Result<(), String> {
+    check_column(dir, schema, segment, file, column)
+        .map(|_| ())
+        .map_err(|kind| kind.to_string())
+}
+
+#[test]
+fn scrub_finds_corruption() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for i in 0..5000 {
+        builder
+            .insert_raw_row(person(&format!("person {i}"), i, i % 3 == 0))
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let report = Table::scrub(dir.path(), &schema).unwrap();
+    assert_eq!(report.segments(), 2);
+    assert_eq!(report.columns(), 6);
+    assert!(report.is_clean());
+
+    let manifest = Manifest::read(dir.path()).unwrap();
+    let mut files = manifest
+        .segments
+        .iter()
+        .flat_map(|s| s.files.iter())
+        .filter_map(|f| f.filename())
+        .map(|f| dir.path().join(f));
+    let flipped = files.next().unwrap();
+    let mut bytes = std::fs::read(&flipped).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&flipped, bytes).unwrap();
+    std::fs::remove_file(files.next().unwrap()).unwrap();
+
+    let report = Table::scrub(dir.path(), &schema).unwrap();
+    assert_eq!(report.corruptions().len(), 2);
+    assert!(report.corruptions()[0].problem().starts_with("checksum is"));
+    assert!(report.corruptions()[1].problem().starts_with("cannot read"));
+    assert!(report.corruptions().iter().all(|c| c.segment() == 0));
+}
@@ -0,0 +1,408 @@
+//! Reading a table as it was at an earlier version.
+//!
+//! Every save, compaction or expiry makes a new version of a table, and the
+//! manifest remembers which segments made up each earlier version.  Segments
+//! that are replaced are kept until their versions are forgotten, so old
+//! versions cost disk space until [`Table::forget_versions`] is called.
+//! Nothing forgets versions on its own, so a table that is saved often needs
+//! it called now and then, or its manifest grows with every save.
+//!
+//! An open [`Table`] pins the version it read until it is dropped.
+//! Segments of a pinned version are never removed, so a table opened before
+//! a compaction keeps reading the rows it opened even when earlier versions
+//! are forgotten.  Pins are kept in memory, so reading a table touches no
+//! file but those of the table.  A table opened with [`Table::read_at`], and
+//! a table being backed up, also pin their version with a file in the table
+//! directory, so that other processes keep it too.  A pin file left behind
+//! by a process that crashed is no longer held, and is removed the next time
+//! pins are listed.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use super::manifest::Manifest;
+use super::{remove_segment_files, Table};
+use crate::column::encoding::StorageError;
+use crate::fs;
+use crate::TableSchema;
+
+/// The start of the name of every pin file
+const PIN: &str = "PIN-";
+
+/// How many times to read a table whose files were removed while it was
+/// opened
+pub(super) const READ_ATTEMPTS: usize = 3;
+
+/// The versions pinned in memory by this process, with the directories of
+/// their tables
+static PINNED: Mutex<Vec<(PathBuf, u64)>> = Mutex::new(Vec::new());
+
+/// A version of a table that is open for reading, whose segments are kept
+/// until the pin is dropped.
+pub(super) struct Pin {
+    dir: PathBuf,
+    version: u64,
+    /// The file pinning the version for other processes, and its lock
+    file: Option<(PathBuf, Option<std::fs::File>)>,
+}
+
+impl Pin {
+    /// Pin `version` of the table in `dir` for this process alone
+    pub(super) fn new(dir: &Path, version: u64) -> Self {
+        PINNED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((dir.to_path_buf(), version));
+        Pin {
+            dir: dir.to_path_buf(),
+            version,
+            file: None,
+        }
+    }
+
+    /// Pin `version` of the table in `dir` for every process, with a file.
+    ///
+    /// The file is written on a best effort basis, so the version is only
+    /// pinned for this process if it cannot be, as in a read-only directory.
+    pub(super) fn held(dir: &Path, version: u64) -> Self {
+        let mut pin = Pin::new(dir, version);
+        let path = dir.join(format!(
+            "{PIN}{version:016x}-{:016x}",
+            rand::random::<u64>()
+        ));
+        if let Ok(held) = fs::create_held(&path) {
+            pin.file = Some((path, held));
+        }
+        pin
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        let mut pinned = PINNED.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = pinned
+            .iter()
+            .position(|(dir, version)| *dir == self.dir && *version == self.version)
+        {
+            pinned.swap_remove(i);
+        }
+        if let Some((path, _)) = &self.file {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// The versions of the table in `dir` that are pinned by open tables,
+/// removing any pin files that are no longer held.
+pub(super) fn pinned_versions(dir: &Path) -> Result<Vec<u64>, StorageError> {
+    let mut versions = PINNED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter(|(d, _)| d == dir)
+        .map(|(_, version)| *version)
+        .collect::<Vec<_>>();
+    for path in fs::list_dir(dir)? {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(version) = name
+            .strip_prefix(PIN)
+            .and_then(|n| n.split_once('-'))
+            .filter(|(_, id)| id.len() == 16 && !id.contains('.'))
+            .and_then(|(v, _)| u64::from_str_radix(v, 16).ok())
+        else {
+            continue;
+        };
+        if fs::is_held(&path) {
+            versions.push(version);
+        } else {
+            fs::remove_file(&path).ok();
+        }
+    }
+    Ok(versions)
+}
+
+/// Which version of a table to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    /// The version with this number, as listed by [`Table::versions`]
+    Version(u64),
+    /// The latest version made no later than this time
+    Time(SystemTime),
+}
+
+impl Table {
+    /// The versions of the table in `dir` that can still be read, oldest
+    /// first, with the time each was made.
+    ///
+    /// Version 0 is the empty table before anything was saved.
+    pub fn versions<P: AsRef<Path>>(dir: P) -> Result<Vec<(u64, SystemTime)>, StorageError> {
+        Ok(Manifest::read(dir.as_ref())?.versions())
+    }
+
+    /// Open the table stored in `dir` as it was at an earlier version.
+    ///
+    /// The rows are read through the current schema, so a column added since
+    /// reads as its default.
+    pub fn read_at<P: AsRef<Path>>(
+        dir: P,
+        schema: &TableSchema,
+        as_of: AsOf,
+    ) -> Result<Self, StorageError> {
+        let projection = vec![true; schema.raw_columns().count()];
+        Table::read_pinned(
+            dir.as_ref(),
+            schema,
+            projection,
+            None,
+            true,
+            |manifest| match as_of {
+                AsOf::Version(v) => Some(v),
+                AsOf::Time(t) => manifest
+                    .versions()
+                    .into_iter()
+                    .rev()
+                    .find(|(_, made)| *made <= t)
+                    .map(|(v, _)| v),
+            },
+        )
+        .map_err(|e| match e {
+            StorageError::NoSuchVersion(_) => StorageError::NoSuchVersion(format!("{as_of:?}")),
+            e => e,
+        })
+    }
+
+    /// Open and pin the version of the table in `dir` chosen from its
+    /// manifest, skipping segments of partitions other than `partitions`.
+    /// The pin is `held` with a file, so that other processes keep it too,
+    /// or else kept in memory.
+    ///
+    /// The version may be forgotten between reading the manifest and pinning
+    /// it, in which case its files may be gone and the manifest is read
+    /// again.
+    pub(super) fn read_pinned(
+        dir: &Path,
+        schema: &TableSchema,
+        projection: Vec<bool>,
+        partitions: Option<&BTreeSet<u64>>,
+        held: bool,
+        choose: impl Fn(&Manifest) -> Option<u64>,
+    ) -> Result<Self, StorageError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("read_table", dir = %dir.display()).entered();
+        let mut attempt = 1;
+        loop {
+            let manifest = Manifest::read(dir)?;
+            let version =
+                choose(&manifest).ok_or_else(|| StorageError::NoSuchVersion("none".to_string()))?;
+            let pin = if held {
+                Pin::held(dir, version)
+            } else {
+                Pin::new(dir, version)
+            };
+            match Table::read_segments(
+                dir,
+                schema,
+                projection.clone(),
+                partitions,
+                &manifest,
+                version,
+            ) {
+                Ok(mut table) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        version,
+                        segments = table.segments.len(),
+                        rows = table.num_rows(),
+                        "read table"
+                    );
+                    table._pin = Some(pin);
+                    return Ok(table);
+                }
+                Err(StorageError::Io(e))
+                    if e.kind() == std::io::ErrorKind::NotFound && attempt < READ_ATTEMPTS =>
+                {
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The version of the table that was read.
+    ///
+    /// It can be read again with [`Table::read_at`] for as long as this table
+    /// is open.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Forget every version of the table in `dir` before `version`, removing
+    /// the segments that no remaining version uses.
+    ///
+    /// Returns the number of segments removed.  The current version is never
+    /// forgotten, and nor is a version pinned by an open table, or any later
+    /// one.  Versions are only ever forgotten by calling this.
+    pub fn forget_versions<P: AsRef<Path>>(dir: P, version: u64) -> Result<usize, StorageError> {
+        let dir = dir.as_ref();
+        let version = pinned_versions(dir)?
+            .into_iter()
+            .fold(version, std::cmp::min);
+        let mut manifest = Manifest::read(dir)?;
+        let forgotten = manifest.forget_before(version);
+        manifest.write(dir)?;
+        remove_segment_files(dir, &forgotten)?;
+        Ok(forgotten.len())
+    }
+}
+
+#[test]
+fn read_earlier_versions() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let files = || std::fs::read_dir(dir.path()).unwrap().count();
+    let rows = |as_of| {
+        Table::read_at(dir.path(), &schema, as_of)
+            .unwrap()
+            .to_rows()
+            .unwrap()
+    };
+
+    // The segments are big enough to be stored in files of their own.
+    let mut builder = TableBuilder::new(&schema);
+    for age in 0..2000 {
+        builder
+            .insert_raw_row(person(&format!("person {age}"), age, true))
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder
+        .insert_raw_row(person("person 7", 70, false))
+        .unwrap();
+    builder.save(dir.path()).unwrap();
+    let before_compaction = Table::read(dir.path(), &schema).unwrap().to_rows().unwrap();
+    let saved = files();
+    Table::compact(dir.path(), &schema).unwrap();
+    assert!(files() > saved);
+
+    let versions = Table::versions(dir.path()).unwrap();
+    assert_eq!(
+        versions.iter().map(|(v, _)| *v).collect::<Vec<_>>(),
+        [0, 1, 2, 3]
+    );
+    assert!(versions.windows(2).all(|w| w[0].1 < w[1].1));
+    assert_eq!(rows(AsOf::Version(0)), Vec::new());
+    assert_eq!(rows(AsOf::Version(1)).len(), 2000);
+    assert_eq!(rows(AsOf::Version(2)), before_compaction);
+    assert_eq!(rows(AsOf::Version(3)), before_compaction);
+    assert_eq!(rows(AsOf::Time(versions[1].1)).len(), 2000);
+    assert_eq!(rows(AsOf::Time(SystemTime::now())), before_compaction);
+    assert!(Table::read_at(dir.path(), &schema, AsOf::Version(4)).is_err());
+
+    assert_eq!(Table::forget_versions(dir.path(), 3).unwrap(), 2);
+    assert_eq!(files(), saved);
+    assert!(Table::read_at(dir.path(), &schema, AsOf::Version(2)).is_err());
+    assert_eq!(rows(AsOf::Version(3)), before_compaction);
+    assert_eq!(
+        Table::read(dir.path(), &schema).unwrap().to_rows().unwrap(),
+        before_compaction
+    );
+}
+
+#[test]
+fn open_tables_pin_their_version() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let save = |age: u64| {
+        let mut builder = TableBuilder::new(&schema);
+        for i in 0..2000 {
+            builder
+                .insert_raw_row(person(&format!("person {i}"), age, true))
+                .unwrap();
+        }
+        builder.save(dir.path()).unwrap();
+    };
+    save(1);
+    save(2);
+    let pin_files = || {
+        std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|f| {
+                f.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(PIN)
+            })
+            .count()
+    };
+    let open = Table::read(dir.path(), &schema).unwrap();
+    assert_eq!(open.version(), 2);
+    // Only a snapshot pins its version with a file.
+    assert_eq!(pin_files(), 0);
+    let snapshot = Table::read_at(dir.path(), &schema, AsOf::Version(2)).unwrap();
+    assert_eq!(pin_files(), 1);
+    drop(snapshot);
+    assert_eq!(pin_files(), 0);
+    let expected = open.to_rows().unwrap();
+
+    // Compacting and forgetting keeps the segments of the open version.
+    Table::compact(dir.path(), &schema).unwrap();
+    assert_eq!(Table::forget_versions(dir.path(), 3).unwrap(), 0);
+    assert_eq!(Table::versions(dir.path()).unwrap().len(), 2);
+    assert_eq!(open.to_rows().unwrap(), expected);
+    assert_eq!(
+        Table::read_at(dir.path(), &schema, AsOf::Version(2))
+            .unwrap()
+            .to_rows()
+            .unwrap(),
+        expected
+    );
+
+    // A pin left behind by a reader that is gone pins nothing.
+    std::fs::write(dir.path().join(format!("{PIN}{:016x}-{:016x}", 0, 0)), b"").unwrap();
+    assert_eq!(pinned_versions(dir.path()).unwrap(), [2]);
+    drop(open);
+    assert!(pinned_versions(dir.path()).unwrap().is_empty());
+    assert_eq!(Table::forget_versions(dir.path(), 3).unwrap(), 2);
+    assert!(Table::read_at(dir.path(), &schema, AsOf::Version(2)).is_err());
+    assert_eq!(
+        Table::read(dir.path(), &schema).unwrap().to_rows().unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn versions_are_kept_until_forgotten() {
+    use super::manifest::MANIFEST;
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let manifest_len = || std::fs::metadata(dir.path().join(MANIFEST)).unwrap().len();
+    let mut lens = Vec::new();
+    for age in 0..20 {
+        let mut builder = TableBuilder::new(&schema);
+        builder.insert_raw_row(person("person", age, true)).unwrap();
+        builder.save(dir.path()).unwrap();
+        Table::compact(dir.path(), &schema).unwrap();
+        lens.push(manifest_len());
+    }
+    // Compacting a single segment makes no new version.
+    let versions = Table::versions(dir.path()).unwrap();
+    assert_eq!(versions.len(), 40);
+    assert!(lens.windows(2).all(|w| w[0] < w[1]));
+
+    let (latest, _) = versions[39];
+    assert_eq!(Table::forget_versions(dir.path(), latest).unwrap(), 38);
+    assert_eq!(Table::versions(dir.path()).unwrap().len(), 1);
+    assert!(manifest_len() < lens[0]);
+}
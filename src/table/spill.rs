@@ -0,0 +1,420 @@
+//! Sorting more rows than fit in memory.
+//!
+//! A [`TableBuilder`](super::TableBuilder) with a memory budget sorts and
+//! merges its rows whenever they exceed the budget, and writes them out as a
+//! run of temporary column files.  Saving the builder merges the runs, reading
+//! each a chunk at a time, into as many segments as are needed to keep each
+//! within the budget.  The segments are added to the manifest all at once, so
+//! a crash part way through leaves the table as it was.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+
+use super::manifest::Manifest;
+use super::{
+    check_saved_keys, check_unique_values, merge_row, merge_rows, remove_segment_files,
+    stamp_ingestion, write_rows, UniqueValues,
+};
+use crate::column::encoding::StorageError;
+use crate::column::{EncodeOptions, Values};
+use crate::fs;
+use crate::{DbLayout, Metrics, RawColumn, RawRow, RawValue, TableSchema};
+
+/// The rows that have been spilled so far
+#[derive(Debug)]
+pub(super) struct Spill {
+    dir: PathBuf,
+    /// Distinguishes the files of this builder from those of any other
+    prefix: String,
+    budget: usize,
+    /// The bytes of rows held in memory
+    memory: usize,
+    /// The column files of each run, in the order they were spilled
+    runs: Vec<Vec<PathBuf>>,
+}
+
+/// Roughly how much memory a row takes
+fn row_size(row: &RawRow) -> usize {
+    row.values
+        .iter()
+        .map(|v| match v {
+            RawValue::Bytes(b) => std::mem::size_of::<RawValue>() + b.len(),
+            _ => std::mem::size_of::<RawValue>(),
+        })
+        .sum()
+}
+
+impl Spill {
+    pub(super) fn new(dir: &Path, budget: usize) -> Self {
+        Spill {
+            dir: dir.to_path_buf(),
+            prefix: format!("spill-{:016x}", rand::random::<u64>()),
+            budget,
+            memory: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub(super) fn has_runs(&self) -> bool {
+        !self.runs.is_empty()
+    }
+
+    /// Count a row being added, returning true if the rows in memory should
+    /// be spilled.
+    pub(super) fn add(&mut self, row: &RawRow) -> bool {
+        self.memory += row_size(row);
+        self.memory > self.budget
+    }
+
+    /// Sort, merge and write out the rows held in memory
+    pub(super) fn write_run(
+        &mut self,
+        schema: &TableSchema,
+        rows: Vec<RawRow>,
+    ) -> Result<(), StorageError> {
+        self.memory = 0;
+        let rows = merge_rows(schema, rows.into_iter().zip(0..).collect())?;
+        fs::create_dir_all(&self.dir)?;
+        let mut files = Vec::new();
+        for (i, c) in schema.raw_columns().enumerate() {
+            let path = self
+                .dir
+                .join(format!("{}-{:04}-{i:04}", self.prefix, self.runs.len()));
+            files.push(path.clone());
+            let values = rows.iter().map(|r| r.values[i].clone()).collect::<Vec<_>>();
+            let mut bytes = Vec::new();
+            RawColumn::write_values(&mut bytes, c.kind(), &values)?;
+            fs::write(&path, &bytes)?;
+        }
+        self.runs.push(files);
+        Ok(())
+    }
+
+    /// Merge the runs and the rows still in memory into new segments of the
+    /// table in `dir`.
+    pub(super) fn save(
+        mut self,
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+        rows: Vec<RawRow>,
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        if !rows.is_empty() {
+            self.write_run(schema, rows)?;
+        }
+        let mut manifest = Manifest::read(dir)?;
+        // The keys of the merged rows are looked up in the table as it was.
+        let saved = manifest.clone();
+        manifest.new_version();
+        if let Err(e) = self.merge(dir, layout, schema, &saved, &mut manifest, options) {
+            // The segments written before the failure are in no manifest.
+            let written = manifest
+                .segments
+                .iter()
+                .filter(|s| s.id >= saved.next_segment)
+                .cloned()
+                .collect::<Vec<_>>();
+            remove_segment_files(dir, &written).ok();
+            return Err(e);
+        }
+        manifest.write(dir)
+    }
+
+    /// Merge the runs into segments added to `manifest`
+    fn merge(
+        &self,
+        dir: &Path,
+        layout: &dyn DbLayout,
+        schema: &TableSchema,
+        saved: &Manifest,
+        manifest: &mut Manifest,
+        options: EncodeOptions,
+    ) -> Result<(), StorageError> {
+        let mut runs = self
+            .runs
+            .iter()
+            .map(|files| Run::open(files))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Rows are taken in order of their primary key, and then in the order
+        // their runs were written, which is the order they are merged in.
+        let mut heap = BinaryHeap::new();
+        for (run, files) in runs.iter_mut().enumerate() {
+            if let Some(row) = files.next_row()? {
+                heap.push(Reverse(Head { schema, run, row }));
+            }
+        }
+
+        let ranges = schema.aggregation_ranges();
+        let mut unique = UniqueValues::new();
+        let mut merged = Vec::new();
+        let mut memory = 0;
+        while let Some(Reverse(Head { run, row, .. })) = heap.pop() {
+            if let Some(next) = runs[run].next_row()? {
+                heap.push(Reverse(Head {
+                    schema,
+                    run,
+                    row: next,
+                }));
+            }
+            let size = row_size(&row);
+            let num_merged = merged.len();
+            merge_row(schema, &ranges, &mut merged, row)?;
+            if merged.len() > num_merged {
+                memory += size;
+            }
+            if memory > self.budget && merged.len() > 1 {
+                // The last row may yet be merged with rows still to come.
+                let last = merged.pop().expect("there are rows");
+                memory = row_size(&last);
+                write_merged(
+                    dir,
+                    layout,
+                    manifest,
+                    schema,
+                    saved,
+                    &mut unique,
+                    &mut merged,
+                    options,
+                )?;
+                merged = vec![last];
+            }
+        }
+        write_merged(
+            dir,
+            layout,
+            manifest,
+            schema,
+            saved,
+            &mut unique,
+            &mut merged,
+            options,
+        )
+    }
+}
+
+/// The next row of a run, ordered by its primary key and then by its run
+struct Head<'a> {
+    schema: &'a TableSchema,
+    run: usize,
+    row: RawRow,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let num_primary = self.schema.num_primary();
+        self.schema
+            .compare_values(
+                0,
+                &self.row.values[..num_primary],
+                &other.row.values[..num_primary],
+            )
+            .then(self.run.cmp(&other.run))
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+/// Write `rows` as a new segment, which is not yet saved in the manifest,
+/// unless one repeats the primary key of a row of the table as listed in the
+/// `saved` manifest, or a value of a unique column among the `unique` values
+/// of the segments written before
+#[allow(clippy::too_many_arguments)]
+fn write_merged(
+    dir: &Path,
+    layout: &dyn DbLayout,
+    manifest: &mut Manifest,
+    schema: &TableSchema,
+    saved: &Manifest,
+    unique: &mut UniqueValues,
+    rows: &mut [RawRow],
+    options: EncodeOptions,
+) -> Result<(), StorageError> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    check_saved_keys(dir, schema, saved, rows)?;
+    check_unique_values(schema, rows, unique)?;
+    stamp_ingestion(schema, rows, manifest.time);
+    write_rows(dir, layout, manifest, schema, rows, options)
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        for f in self.runs.iter().flatten() {
+            fs::remove_file(f).ok();
+        }
+    }
+}
+
+/// The rows of one spilled run, read a chunk at a time
+struct Run {
+    columns: Vec<Values>,
+}
+
+impl Run {
+    fn open(files: &[PathBuf]) -> Result<Self, StorageError> {
+        let columns = files
+            .iter()
+            .map(|f| Ok(fs::open_column(f, &Metrics::default())?.values()))
+            .collect::<Result<_, StorageError>>()?;
+        Ok(Run { columns })
+    }
+
+    fn next_row(&mut self) -> Result<Option<RawRow>, StorageError> {
+        let mut values = Vec::with_capacity(self.columns.len());
+        for c in self.columns.iter_mut() {
+            match c.next() {
+                Some(v) => values.push(v?),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(RawRow { values }))
+    }
+}
+
+#[test]
+fn spilled_rows_match() {
+    use super::{test_schema, Table, TableBuilder};
+    use crate::ConflictPolicy;
+    use rand::{Rng, SeedableRng};
+
+    for policy in [
+        ConflictPolicy::Aggregate,
+        ConflictPolicy::LastWriteWins,
+        ConflictPolicy::FirstWriteWins,
+    ] {
+        let mut schema = test_schema();
+        schema.set_conflict_policy(policy);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let spill = tempfile::tempdir().unwrap();
+        let spilled = tempfile::tempdir().unwrap();
+        let unspilled = tempfile::tempdir().unwrap();
+
+        let mut builder = TableBuilder::new(&schema).spill_to(spill.path(), 10_000);
+        let mut expected = TableBuilder::new(&schema);
+        for _ in 0..3000 {
+            let row = super::person(
+                &format!("person {}", rng.gen_range(0..1000)),
+                rng.gen_range(0..100),
+                rng.gen(),
+            );
+            builder.insert_raw_row(row.clone()).unwrap();
+            expected.insert_raw_row(row).unwrap();
+        }
+        assert!(std::fs::read_dir(spill.path()).unwrap().count() > 0);
+        builder.save(spilled.path()).unwrap();
+        expected.save(unspilled.path()).unwrap();
+
+        assert_eq!(std::fs::read_dir(spill.path()).unwrap().count(), 0);
+        assert!(Manifest::read(spilled.path()).unwrap().segments.len() > 1);
+        assert_eq!(
+            Table::read(spilled.path(), &schema)
+                .unwrap()
+                .to_rows()
+                .unwrap(),
+            Table::read(unspilled.path(), &schema)
+                .unwrap()
+                .to_rows()
+                .unwrap(),
+        );
+    }
+}
+
+#[test]
+fn spilled_unique_values() {
+    use super::{Table, TableBuilder};
+    use crate::{ColumnSchema, Constraint};
+
+    let age = ColumnSchema::<u64>::new("age");
+    let mut schema = TableSchema::new("people");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(age.raw().chain(ColumnSchema::<bool>::new("happy").raw()));
+    schema.add_constraint(&age, Constraint::Unique).unwrap();
+    let spill = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema).spill_to(spill.path(), 1000);
+    for i in 1..100 {
+        let row = super::person(&format!("person {i:02}"), i, true);
+        builder.insert_raw_row(row).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    assert!(Manifest::read(dir.path()).unwrap().segments.len() > 1);
+
+    // The first and last people are saved in different segments, so only
+    // the values of the segments written before can tell they share an age.
+    let mut builder = TableBuilder::new(&schema).spill_to(spill.path(), 1000);
+    for i in 1..100 {
+        let row = super::person(&format!("person {i:02}"), i % 99, true);
+        builder.insert_raw_row(row).unwrap();
+    }
+    builder
+        .insert_raw_row(super::person("zed", 1, true))
+        .unwrap();
+    let other = tempfile::tempdir().unwrap();
+    assert!(matches!(
+        builder.save(other.path()),
+        Err(StorageError::Constraint { .. })
+    ));
+    assert_eq!(
+        Table::read(dir.path(), &schema)
+            .unwrap()
+            .to_rows()
+            .unwrap()
+            .len(),
+        99
+    );
+}
+
+#[test]
+fn spilled_saved_keys() {
+    use super::{test_schema, Table, TableBuilder};
+    use crate::ConflictPolicy;
+
+    let mut schema = test_schema();
+    schema.set_conflict_policy(ConflictPolicy::Error);
+    let spill = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for i in (0..50).chain([9999]) {
+        let row = super::person(&format!("person {i:04}"), i, true);
+        builder.insert_raw_row(row).unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let files = || std::fs::read_dir(dir.path()).unwrap().count();
+    let num_files = files();
+
+    // Only the last of the segments the spilled rows make repeats a key, so
+    // those before it are written and then removed.
+    let mut builder = TableBuilder::new(&schema).spill_to(spill.path(), 1000);
+    for i in (50..3000).chain([9999]) {
+        let row = super::person(&format!("person {i:04}"), i, true);
+        builder.insert_raw_row(row).unwrap();
+    }
+    assert!(matches!(
+        builder.save(dir.path()),
+        Err(StorageError::DuplicateKey(_))
+    ));
+    assert_eq!(files(), num_files);
+    assert_eq!(
+        Table::read(dir.path(), &schema)
+            .unwrap()
+            .to_rows()
+            .unwrap()
+            .len(),
+        51
+    );
+}
@@ -0,0 +1,353 @@
+//! Building a segment a column at a time.
+//!
+//! A [`TableBuilder`](super::TableBuilder) holds every value as a
+//! [`RawValue`], which costs an allocation per row and makes sorting compare
+//! enum tags.  A [`TypedTableBuilder`] instead keeps a plain vector for each
+//! raw column, sorts a permutation of the row indices, and encodes each column
+//! straight from its vector.
+
+use std::cmp::Ordering;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::manifest::Manifest;
+use super::{merge_rows, write_encoded, write_rows};
+use crate::column::encoding::StorageError;
+use crate::column::EncodeOptions;
+use crate::fs;
+use crate::{Collation, DbLayout, FlatLayout, RawColumn, RawKind, RawRow, RawValue, TableSchema};
+
+/// Values for a raw column holding `u64`
+#[derive(Debug, Clone, Default)]
+pub struct U64Builder {
+    values: Vec<u64>,
+}
+
+/// Values for a raw column holding bools
+#[derive(Debug, Clone, Default)]
+pub struct BoolBuilder {
+    values: Vec<bool>,
+}
+
+/// Values for a raw column holding bytes
+#[derive(Debug, Clone, Default)]
+pub struct BytesBuilder {
+    values: Vec<Vec<u8>>,
+}
+
+macro_rules! impl_builder {
+    ($builder:ident, $t:ty) => {
+        impl $builder {
+            /// Add a value
+            pub fn push(&mut self, value: $t) {
+                self.values.push(value);
+            }
+            /// The number of values
+            pub fn len(&self) -> usize {
+                self.values.len()
+            }
+            /// Whether there are no values
+            pub fn is_empty(&self) -> bool {
+                self.values.is_empty()
+            }
+        }
+        impl Extend<$t> for $builder {
+            fn extend<I: IntoIterator<Item = $t>>(&mut self, iter: I) {
+                self.values.extend(iter)
+            }
+        }
+    };
+}
+impl_builder!(U64Builder, u64);
+impl_builder!(BoolBuilder, bool);
+impl_builder!(BytesBuilder, Vec<u8>);
+
+/// The values of one raw column
+#[derive(Debug, Clone)]
+pub enum ColumnBuilder {
+    /// A column of `u64`
+    U64(U64Builder),
+    /// A column of bools
+    Bool(BoolBuilder),
+    /// A column of bytes
+    Bytes(BytesBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(kind: RawKind) -> Self {
+        match kind {
+            RawKind::U64 => ColumnBuilder::U64(U64Builder::default()),
+            RawKind::Bool => ColumnBuilder::Bool(BoolBuilder::default()),
+            RawKind::Bytes => ColumnBuilder::Bytes(BytesBuilder::default()),
+        }
+    }
+
+    /// The number of values
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnBuilder::U64(b) => b.len(),
+            ColumnBuilder::Bool(b) => b.len(),
+            ColumnBuilder::Bytes(b) => b.len(),
+        }
+    }
+
+    /// Whether there are no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The kind of the values
+    pub fn kind(&self) -> RawKind {
+        match self {
+            ColumnBuilder::U64(_) => RawKind::U64,
+            ColumnBuilder::Bool(_) => RawKind::Bool,
+            ColumnBuilder::Bytes(_) => RawKind::Bytes,
+        }
+    }
+
+    fn compare(&self, a: usize, b: usize, collation: Collation) -> Ordering {
+        match self {
+            ColumnBuilder::U64(c) => c.values[a].cmp(&c.values[b]),
+            ColumnBuilder::Bool(c) => c.values[a].cmp(&c.values[b]),
+            ColumnBuilder::Bytes(c) => collation.compare(&c.values[a], &c.values[b]),
+        }
+    }
+
+    fn value(&self, row: usize) -> RawValue {
+        match self {
+            ColumnBuilder::U64(c) => RawValue::U64(c.values[row]),
+            ColumnBuilder::Bool(c) => RawValue::Bool(c.values[row]),
+            ColumnBuilder::Bytes(c) => RawValue::Bytes(c.values[row].clone()),
+        }
+    }
+
+    /// Encode the values in the order given by `order`, with `options`
+    fn encode(self, order: &[usize], options: EncodeOptions) -> Result<Vec<u8>, StorageError> {
+        let mut bytes = Vec::new();
+        match self {
+            ColumnBuilder::U64(c) => {
+                let values = order.iter().map(|&i| c.values[i]).collect::<Vec<_>>();
+                RawColumn::write_u64_with(&mut bytes, &values, options)?;
+            }
+            ColumnBuilder::Bool(c) => {
+                let values = order.iter().map(|&i| c.values[i]).collect::<Vec<_>>();
+                RawColumn::write_bools_with(&mut bytes, &values, options.bool_format())?;
+            }
+            ColumnBuilder::Bytes(mut c) => {
+                let values = order
+                    .iter()
+                    .map(|&i| std::mem::take(&mut c.values[i]))
+                    .collect::<Vec<_>>();
+                RawColumn::write_bytes_with(&mut bytes, &values, options)?;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Rows waiting to be saved as a new segment of a table, stored a column at a
+/// time.
+///
+/// Values are pushed onto each raw column separately, in schema order, and
+/// every column must hold the same number of values when the builder is
+/// saved.
+pub struct TypedTableBuilder {
+    schema: TableSchema,
+    columns: Vec<ColumnBuilder>,
+    layout: Arc<dyn DbLayout>,
+}
+
+impl TypedTableBuilder {
+    /// Start building a segment of a table with this schema
+    pub fn new(schema: &TableSchema) -> Self {
+        TypedTableBuilder {
+            schema: schema.clone(),
+            columns: schema
+                .raw_columns()
+                .map(|c| ColumnBuilder::new(c.kind()))
+                .collect(),
+            layout: Arc::new(FlatLayout),
+        }
+    }
+
+    /// Name the column files of the new segment by `layout`, see
+    /// [`TableBuilder::layout`](super::TableBuilder::layout)
+    pub fn layout(mut self, layout: Arc<dyn DbLayout>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// The builders of all the raw columns, in schema order
+    pub fn columns_mut(&mut self) -> &mut [ColumnBuilder] {
+        &mut self.columns
+    }
+
+    /// The builder of raw column `i`, which must hold `u64`
+    pub fn u64_column(&mut self, i: usize) -> Result<&mut U64Builder, StorageError> {
+        match self.columns.get_mut(i) {
+            Some(ColumnBuilder::U64(b)) => Ok(b),
+            _ => Err(StorageError::InvalidRow("no u64 column")),
+        }
+    }
+
+    /// The builder of raw column `i`, which must hold bools
+    pub fn bool_column(&mut self, i: usize) -> Result<&mut BoolBuilder, StorageError> {
+        match self.columns.get_mut(i) {
+            Some(ColumnBuilder::Bool(b)) => Ok(b),
+            _ => Err(StorageError::InvalidRow("no bool column")),
+        }
+    }
+
+    /// The builder of raw column `i`, which must hold bytes
+    pub fn bytes_column(&mut self, i: usize) -> Result<&mut BytesBuilder, StorageError> {
+        match self.columns.get_mut(i) {
+            Some(ColumnBuilder::Bytes(b)) => Ok(b),
+            _ => Err(StorageError::InvalidRow("no bytes column")),
+        }
+    }
+
+    /// Save the rows as a new segment of the table in `dir`, just as
+    /// [`TableBuilder::save`](super::TableBuilder::save) would.
+    pub fn save<P: AsRef<Path>>(mut self, dir: P) -> Result<(), StorageError> {
+        let dir = dir.as_ref();
+        let num_rows = self.columns.first().map(|c| c.len()).unwrap_or(0);
+        if self.columns.iter().any(|c| c.len() != num_rows) {
+            return Err(StorageError::InvalidRow("columns of different lengths"));
+        }
+        let kinds = self.schema.raw_columns().map(|c| c.kind());
+        if self.columns.iter().map(|c| c.kind()).ne(kinds) {
+            return Err(StorageError::InvalidRow("value has the wrong kind"));
+        }
+        fs::create_dir_all(dir)?;
+        let mut manifest = Manifest::read(dir)?;
+        if num_rows == 0 {
+            return manifest.write(dir);
+        }
+        manifest.new_version();
+        if let Some(i) = self.schema.ingestion_index() {
+            self.columns[i] = ColumnBuilder::U64(U64Builder {
+                values: vec![manifest.time; num_rows],
+            });
+        }
+
+        // A stable sort keeps rows with the same key in the order they were
+        // pushed, which is the order in which they are merged.
+        let primary = self.columns[..self.schema.num_primary()]
+            .iter()
+            .zip(self.schema.raw_columns().map(|c| self.schema.collation(c)))
+            .collect::<Vec<_>>();
+        let mut order = (0..num_rows).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            primary
+                .iter()
+                .map(|(c, collation)| c.compare(a, b, *collation))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        let has_duplicates = order.windows(2).any(|w| {
+            primary
+                .iter()
+                .all(|(c, collation)| c.compare(w[0], w[1], *collation).is_eq())
+        });
+
+        if has_duplicates || self.schema.partitions().is_some() {
+            // Rows sharing a key are rare, so they are merged the slow way,
+            // as are the rows of a partitioned table, which are split up.
+            let rows = order
+                .iter()
+                .zip(0..)
+                .map(|(&i, seq)| (self.columns.iter().map(|c| c.value(i)).collect(), seq))
+                .collect::<Vec<(RawRow, u64)>>();
+            let rows = merge_rows(&self.schema, rows)?;
+            write_rows(
+                dir,
+                &*self.layout,
+                &mut manifest,
+                &self.schema,
+                &rows,
+                EncodeOptions::default(),
+            )?;
+        } else {
+            let max_time = self
+                .schema
+                .time_index()
+                .and_then(|i| match &self.columns[i] {
+                    ColumnBuilder::U64(c) => c.values.iter().copied().max(),
+                    _ => None,
+                });
+            let encoded = self
+                .columns
+                .into_iter()
+                .zip(self.schema.raw_columns())
+                .map(|(c, schema)| {
+                    let formats = self.schema.encoding(schema).formats();
+                    c.encode(&order, EncodeOptions::default().prefer(formats))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            write_encoded(
+                dir,
+                &*self.layout,
+                &mut manifest,
+                &self.schema,
+                None,
+                num_rows as u64,
+                max_time,
+                encoded,
+            )?;
+        }
+        manifest.write(dir)
+    }
+}
+
+#[test]
+fn typed_builder_matches_row_builder() {
+    use super::{person, test_schema, Table, TableBuilder};
+    use rand::{Rng, SeedableRng};
+
+    let schema = test_schema();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut person_numbered = |i| person(&format!("person {i}"), rng.gen_range(0..100), rng.gen());
+    let unique = (0..1500).map(&mut person_numbered).collect::<Vec<_>>();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    let repeated = (0..3000)
+        .map(|_| {
+            let name = format!("person {}", rng.gen_range(0..2000));
+            person(&name, rng.gen_range(0..100), rng.gen())
+        })
+        .collect::<Vec<_>>();
+    let typed_dir = tempfile::tempdir().unwrap();
+    let raw_dir = tempfile::tempdir().unwrap();
+
+    for rows in [&unique[..1], &unique[..], &repeated[..]] {
+        let mut typed = TypedTableBuilder::new(&schema);
+        let mut builder = TableBuilder::new(&schema);
+        for row in rows.iter() {
+            match row.values() {
+                [RawValue::Bytes(name), RawValue::U64(age), RawValue::Bool(happy)] => {
+                    typed.bytes_column(0).unwrap().push(name.clone());
+                    typed.u64_column(1).unwrap().push(*age);
+                    typed.bool_column(2).unwrap().push(*happy);
+                }
+                _ => unreachable!(),
+            }
+            builder.insert_raw_row(row.clone()).unwrap();
+        }
+        typed.save(typed_dir.path()).unwrap();
+        builder.save(raw_dir.path()).unwrap();
+        assert_eq!(
+            Table::read(typed_dir.path(), &schema)
+                .unwrap()
+                .to_rows()
+                .unwrap(),
+            Table::read(raw_dir.path(), &schema)
+                .unwrap()
+                .to_rows()
+                .unwrap(),
+        );
+    }
+
+    let mut typed = TypedTableBuilder::new(&schema);
+    assert!(typed.u64_column(0).is_err());
+    typed.bytes_column(0).unwrap().push(b"Nobody".to_vec());
+    assert!(typed.save(typed_dir.path()).is_err());
+}
@@ -0,0 +1,520 @@
+//! Checking that the files of a table agree with each other.
+//!
+//! A verification goes further than a [scrub](Table::scrub): besides
+//! checking each column of each segment, it checks that the manifest lists
+//! each segment and raw column once, that each column was saved as the raw
+//! column the schema now has, that every segment holds its primary key, and
+//! that the rows of each segment are sorted by their primary keys, with no
+//! two sharing one.  Each problem is reported as an [`Inconsistency`], and a
+//! verification keeps going after finding one.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::manifest::{checksum, ColumnData, ColumnFile, Manifest, Segment};
+use super::Table;
+use crate::column::encoding::StorageError;
+use crate::column::storage::Storage;
+use crate::fs;
+use crate::{RawColumn, RawColumnSchema, RawKind, RawValue, TableSchema};
+
+/// What is wrong with a table, a segment of it or a column of a segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InconsistencyKind {
+    /// The manifest cannot be read
+    Manifest(String),
+    /// The schemas of the tables of a database cannot be loaded
+    Catalog(String),
+    /// The segment is listed more than once
+    DuplicateSegment,
+    /// The segment has an id the manifest has yet to hand out
+    SegmentId {
+        /// The id the manifest will give the next segment
+        next: u64,
+    },
+    /// The segment is part of an earlier version that is still remembered,
+    /// but is no longer kept
+    Forgotten {
+        /// The earlier version
+        version: u64,
+    },
+    /// The column is listed more than once in the segment
+    DuplicateColumn,
+    /// The column is part of the primary key but not of the segment
+    Missing,
+    /// The column file cannot be read
+    Unreadable {
+        /// The file, relative to the directory of the table
+        file: String,
+        /// Why it cannot be read
+        error: String,
+    },
+    /// The bytes of the column do not match its checksum
+    Checksum {
+        /// The checksum recorded in the manifest
+        expected: u64,
+        /// The checksum of the bytes
+        actual: u64,
+    },
+    /// The column does not start with the magic number of any format
+    BadMagic(u64),
+    /// The header of the column cannot be read
+    Header(String),
+    /// The values of the column cannot be decoded
+    Undecodable(String),
+    /// The column was saved as a raw column other than the one in the schema
+    Fingerprint {
+        /// The fingerprint of the raw column in the schema
+        expected: u64,
+        /// The fingerprint recorded in the manifest
+        actual: u64,
+    },
+    /// The column holds a number of rows other than its segment
+    RowCount {
+        /// The rows in the column
+        rows: u64,
+        /// The rows in the segment
+        expected: u64,
+    },
+    /// The column holds values of a kind other than the schema expects
+    Kind {
+        /// The kind of values held
+        kind: RawKind,
+        /// The kind of the raw column in the schema
+        expected: RawKind,
+    },
+    /// The time column disagrees with the latest time recorded for its
+    /// segment
+    MaxTime {
+        /// The latest time in the column
+        time: Option<u64>,
+        /// The latest time recorded in the manifest
+        expected: Option<u64>,
+    },
+    /// A row of the segment sorts before the row ahead of it
+    Unsorted {
+        /// The row, counting from zero
+        row: u64,
+    },
+    /// A row of the segment has the same primary key as the row ahead of it
+    DuplicateKey {
+        /// The row, counting from zero
+        row: u64,
+    },
+}
+
+impl std::fmt::Display for InconsistencyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InconsistencyKind::Manifest(e) => write!(f, "cannot read the manifest: {e}"),
+            InconsistencyKind::Catalog(e) => write!(f, "cannot load the schemas: {e}"),
+            InconsistencyKind::DuplicateSegment => write!(f, "is listed more than once"),
+            InconsistencyKind::SegmentId { next } => {
+                write!(
+                    f,
+                    "has an id no lower than that of the next segment, {next}"
+                )
+            }
+            InconsistencyKind::Forgotten { version } => {
+                write!(f, "is part of version {version} but is not kept")
+            }
+            InconsistencyKind::DuplicateColumn => write!(f, "is listed more than once"),
+            InconsistencyKind::Missing => write!(f, "is missing from the segment"),
+            InconsistencyKind::Unreadable { file, error } => {
+                write!(f, "cannot read {file}: {error}")
+            }
+            InconsistencyKind::Checksum { expected, actual } => {
+                write!(f, "checksum is {actual:016x} instead of {expected:016x}")
+            }
+            InconsistencyKind::BadMagic(magic) => write!(f, "bad magic {magic:016x}"),
+            InconsistencyKind::Header(e) => write!(f, "bad header: {e}"),
+            InconsistencyKind::Undecodable(e) => write!(f, "cannot decode: {e}"),
+            InconsistencyKind::Fingerprint { expected, actual } => {
+                write!(f, "fingerprint is {actual:016x} instead of {expected:016x}")
+            }
+            InconsistencyKind::RowCount { rows, expected } => {
+                write!(f, "holds {rows} rows instead of {expected}")
+            }
+            InconsistencyKind::Kind { kind, expected } => {
+                write!(f, "holds {kind:?} instead of {expected:?}")
+            }
+            InconsistencyKind::MaxTime { time, expected } => {
+                write!(f, "latest time is {time:?} instead of {expected:?}")
+            }
+            InconsistencyKind::Unsorted { row } => {
+                write!(f, "row {row} sorts before the row ahead of it")
+            }
+            InconsistencyKind::DuplicateKey { row } => {
+                write!(f, "row {row} has the primary key of the row ahead of it")
+            }
+        }
+    }
+}
+
+/// A problem found by a verification, see the [module](self) docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inconsistency {
+    table: String,
+    segment: Option<u64>,
+    column: Option<String>,
+    kind: InconsistencyKind,
+}
+
+impl Inconsistency {
+    pub(crate) fn new(
+        table: &str,
+        segment: Option<u64>,
+        column: Option<String>,
+        kind: InconsistencyKind,
+    ) -> Self {
+        Inconsistency {
+            table: table.to_string(),
+            segment,
+            column,
+            kind,
+        }
+    }
+    /// The name of the table
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+    /// The id of the segment, unless the problem is with the whole table
+    pub fn segment(&self) -> Option<u64> {
+        self.segment
+    }
+    /// The name of the raw column, unless the problem is with the whole
+    /// segment or table
+    pub fn column(&self) -> Option<&str> {
+        self.column.as_deref()
+    }
+    /// What is wrong
+    pub fn kind(&self) -> &InconsistencyKind {
+        &self.kind
+    }
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "table {}", self.table)?;
+        if let Some(segment) = self.segment {
+            write!(f, " segment {segment}")?;
+        }
+        if let Some(column) = &self.column {
+            write!(f, " column {column}")?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// What a verification found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    tables: u64,
+    segments: u64,
+    columns: u64,
+    inconsistencies: Vec<Inconsistency>,
+}
+
+impl VerifyReport {
+    /// The number of tables checked
+    pub fn tables(&self) -> u64 {
+        self.tables
+    }
+    /// The number of segments checked
+    pub fn segments(&self) -> u64 {
+        self.segments
+    }
+    /// The number of columns checked
+    pub fn columns(&self) -> u64 {
+        self.columns
+    }
+    /// The problems found
+    pub fn inconsistencies(&self) -> &[Inconsistency] {
+        &self.inconsistencies
+    }
+    /// Whether no problems were found
+    pub fn is_clean(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+
+    /// Add what another verification found
+    pub(crate) fn extend(&mut self, other: VerifyReport) {
+        self.tables += other.tables;
+        self.segments += other.segments;
+        self.columns += other.columns;
+        self.inconsistencies.extend(other.inconsistencies);
+    }
+
+    pub(crate) fn push(&mut self, inconsistency: Inconsistency) {
+        self.inconsistencies.push(inconsistency);
+    }
+}
+
+impl Table {
+    /// Check that the files of the table in `dir` agree with each other and
+    /// with `schema`, see the [module](self) docs.
+    ///
+    /// Only the segments of the current version are read, but every segment
+    /// of an earlier version that is remembered must still be kept.
+    pub fn verify<P: AsRef<Path>>(dir: P, schema: &TableSchema) -> VerifyReport {
+        let dir = dir.as_ref();
+        let table = schema.name();
+        let mut report = VerifyReport {
+            tables: 1,
+            ..VerifyReport::default()
+        };
+        let manifest = match Manifest::read(dir) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                report.push(Inconsistency::new(
+                    table,
+                    None,
+                    None,
+                    InconsistencyKind::Manifest(e.to_string()),
+                ));
+                return report;
+            }
+        };
+        let mut ids = BTreeSet::new();
+        for s in manifest.segments.iter() {
+            let problem = |kind| Inconsistency::new(table, Some(s.id), None, kind);
+            if !ids.insert(s.id) {
+                report.push(problem(InconsistencyKind::DuplicateSegment));
+            }
+            if s.id >= manifest.next_segment {
+                let next = manifest.next_segment;
+                report.push(problem(InconsistencyKind::SegmentId { next }));
+            }
+            report.segments += 1;
+            report.columns += s.files.len() as u64;
+            verify_segment(dir, schema, s, &mut report);
+        }
+        for snapshot in manifest.history.iter() {
+            for &id in snapshot.segments.iter() {
+                let kept = |segments: &[Segment]| segments.iter().any(|s| s.id == id);
+                if !kept(&manifest.segments) && !kept(&manifest.retired) {
+                    report.push(Inconsistency::new(
+                        table,
+                        Some(id),
+                        None,
+                        InconsistencyKind::Forgotten {
+                            version: snapshot.version,
+                        },
+                    ));
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Check each column of a segment, and that its rows are sorted by their
+/// primary keys
+fn verify_segment(dir: &Path, schema: &TableSchema, segment: &Segment, report: &mut VerifyReport) {
+    let num_primary = schema.num_primary();
+    let mut primary = vec![None; num_primary];
+    let mut problem = |column: String, kind| {
+        report.push(Inconsistency::new(
+            schema.name(),
+            Some(segment.id),
+            Some(column),
+            kind,
+        ))
+    };
+    for (i, f) in segment.files.iter().enumerate() {
+        let column = schema
+            .raw_columns()
+            .enumerate()
+            .find(|(_, c)| c.id() == f.column && c.fieldname() == f.fieldname);
+        let name = column
+            .map(|(_, c)| c.display_name())
+            .unwrap_or_else(|| format!("{}.{}", f.column, f.fieldname));
+        if segment.files[..i]
+            .iter()
+            .any(|g| g.column == f.column && g.fieldname == f.fieldname)
+        {
+            problem(name.clone(), InconsistencyKind::DuplicateColumn);
+        }
+        match check_column(dir, schema, segment, f, column) {
+            Ok(values) => {
+                if let Some((i, _)) = column.filter(|(i, _)| *i < num_primary) {
+                    primary[i] = Some(values);
+                }
+            }
+            Err(kind) => problem(name, kind),
+        }
+    }
+    for c in schema.raw_columns().take(num_primary) {
+        if segment.file(c.id(), c.fieldname()).is_none() {
+            problem(c.display_name(), InconsistencyKind::Missing);
+        }
+    }
+    let Some(primary) = primary.into_iter().collect::<Option<Vec<_>>>() else {
+        return;
+    };
+    let key = |row: usize| primary.iter().map(|c| c[row].clone()).collect::<Vec<_>>();
+    for row in 1..segment.num_rows as usize {
+        let kind = match schema.compare_values(0, &key(row - 1), &key(row)) {
+            std::cmp::Ordering::Less => continue,
+            std::cmp::Ordering::Equal => InconsistencyKind::DuplicateKey { row: row as u64 },
+            std::cmp::Ordering::Greater => InconsistencyKind::Unsorted { row: row as u64 },
+        };
+        report.push(Inconsistency::new(
+            schema.name(),
+            Some(segment.id),
+            None,
+            kind,
+        ));
+        return;
+    }
+}
+
+/// Check one column of a segment against the manifest and, if it is one of
+/// its raw columns, against `schema`, returning its values.
+pub(crate) fn check_column(
+    dir: &Path,
+    schema: &TableSchema,
+    segment: &Segment,
+    file: &ColumnFile,
+    column: Option<(usize, &RawColumnSchema)>,
+) -> Result<Vec<RawValue>, InconsistencyKind> {
+    let bytes = match &file.data {
+        ColumnData::File(filename) => {
+            fs::read(&dir.join(filename)).map_err(|e| InconsistencyKind::Unreadable {
+                file: filename.clone(),
+                error: e.to_string(),
+            })?
+        }
+        ColumnData::Inline(bytes) => bytes.as_slice().into(),
+    };
+    if let Some(expected) = file.checksum {
+        let actual = checksum(&bytes);
+        if actual != expected {
+            return Err(InconsistencyKind::Checksum { expected, actual });
+        }
+    }
+    let raw = RawColumn::open_storage(Storage::from(bytes)).map_err(|e| match e {
+        StorageError::BadMagic(magic) => InconsistencyKind::BadMagic(magic),
+        e => InconsistencyKind::Header(e.to_string()),
+    })?;
+    let expected = segment.num_rows;
+    if raw.num_rows() != expected {
+        let rows = raw.num_rows();
+        return Err(InconsistencyKind::RowCount { rows, expected });
+    }
+    let values = raw
+        .read_values()
+        .map_err(|e| InconsistencyKind::Undecodable(e.to_string()))?;
+    if values.len() as u64 != expected {
+        let rows = values.len() as u64;
+        return Err(InconsistencyKind::RowCount { rows, expected });
+    }
+    if let Some((i, c)) = column {
+        if let Some(actual) = file.fingerprint.filter(|f| *f != c.fingerprint()) {
+            let expected = c.fingerprint();
+            return Err(InconsistencyKind::Fingerprint { expected, actual });
+        }
+        if raw.kind() != c.kind() {
+            let (kind, expected) = (raw.kind(), c.kind());
+            return Err(InconsistencyKind::Kind { kind, expected });
+        }
+        if schema.time_index() == Some(i) {
+            let time = values
+                .iter()
+                .filter_map(|v| match v {
+                    RawValue::U64(t) => Some(*t),
+                    _ => None,
+                })
+                .max();
+            if segment.max_time.is_some() && time != segment.max_time {
+                let expected = segment.max_time;
+                return Err(InconsistencyKind::MaxTime { time, expected });
+            }
+        }
+    }
+    Ok(values)
+}
+
+#[test]
+fn verify_finds_inconsistencies() {
+    use super::{person, test_schema, TableBuilder};
+
+    let schema = test_schema();
+    let dir = tempfile::tempdir().unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    for i in 0..5000 {
+        builder
+            .insert_raw_row(person(&format!("person {i}"), i, i % 3 == 0))
+            .unwrap();
+    }
+    builder.save(dir.path()).unwrap();
+    let mut builder = TableBuilder::new(&schema);
+    builder.insert_raw_row(person("David", 48, true)).unwrap();
+    builder.save(dir.path()).unwrap();
+
+    let report = Table::verify(dir.path(), &schema);
+    assert_eq!(report.tables(), 1);
+    assert_eq!(report.segments(), 2);
+    assert_eq!(report.columns(), 6);
+    assert!(report.is_clean(), "{:?}", report.inconsistencies());
+
+    // Swap the names of the first two people, in their file and its checksum.
+    let mut manifest = Manifest::read(dir.path()).unwrap();
+    let names = &mut manifest.segments[0].files[0];
+    let mut values = Table::read(dir.path(), &schema)
+        .unwrap()
+        .to_rows()
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.values[0] != RawValue::Bytes(b"David".to_vec()))
+        .map(|r| r.values[0].clone())
+        .collect::<Vec<_>>();
+    values.swap(0, 1);
+    let mut bytes = Vec::new();
+    RawColumn::write_values(&mut bytes, RawKind::Bytes, &values).unwrap();
+    std::fs::write(dir.path().join(names.filename().unwrap()), &bytes).unwrap();
+    names.checksum = Some(checksum(&bytes));
+    // Lose the ages of the second segment, and claim the happiness of the
+    // first was saved as something else.
+    manifest.segments[1].files.remove(1);
+    manifest.segments[0].files[2].fingerprint = Some(42);
+    manifest.write(dir.path()).unwrap();
+
+    let report = Table::verify(dir.path(), &schema);
+    let fingerprint = schema.raw_columns().nth(2).unwrap().fingerprint();
+    assert_eq!(
+        report.inconsistencies(),
+        [
+            Inconsistency::new(
+                "people",
+                Some(0),
+                Some("happy".to_string()),
+                InconsistencyKind::Fingerprint {
+                    expected: fingerprint,
+                    actual: 42
+                }
+            ),
+            Inconsistency::new(
+                "people",
+                Some(0),
+                None,
+                InconsistencyKind::Unsorted { row: 1 }
+            ),
+        ]
+    );
+    assert_eq!(
+        report.inconsistencies()[1].to_string(),
+        "table people segment 0: row 1 sorts before the row ahead of it"
+    );
+
+    // A name is part of the primary key, so cannot be lost.
+    manifest.segments[1].files.remove(0);
+    manifest.write(dir.path()).unwrap();
+    let report = Table::verify(dir.path(), &schema);
+    assert_eq!(
+        report.inconsistencies()[2].kind(),
+        &InconsistencyKind::Missing
+    );
+    assert_eq!(report.inconsistencies()[2].segment(), Some(1));
+    assert_eq!(report.inconsistencies()[2].column(), Some("name"));
+}
@@ -0,0 +1,258 @@
+//! Generating random rows and columns, and checking that they survive being
+//! encoded and decoded, for the tests of this crate and of crates adding
+//! their own lenses.
+//!
+//! This module is only built with the `test-util` feature.  Values are drawn
+//! in a mix of shapes, such as long runs, mostly defaults, a few distinct
+//! values, or values of one length, so that each [`ColumnFormat`] is given
+//! values it can hold.  Taking an [`Rng`] lets a test seed it, so a failure
+//! can be replayed.
+//!
+//! ```
+//! # #[cfg(feature = "test-util")] {
+//! use equilia::test_util::{assert_round_trips, random_column};
+//! use equilia::RawKind;
+//! use rand::SeedableRng;
+//!
+//! let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+//! for kind in [RawKind::Bool, RawKind::U64, RawKind::Bytes] {
+//!     assert_round_trips(&random_column(&mut rng, kind, 100));
+//! }
+//! # }
+//! ```
+
+use std::fmt::Debug;
+
+use rand::Rng;
+
+use crate::lens::{check_lens, RawValues};
+use crate::{ColumnFormat, EncodeOptions, Lens, RawColumn, RawKind, RawRow, RawValue, TableSchema};
+
+/// A column of `len` random values of `kind`, in a shape picked at random
+pub fn random_column(rng: &mut impl Rng, kind: RawKind, len: usize) -> Vec<RawValue> {
+    let mut values = Vec::with_capacity(len);
+    match kind {
+        RawKind::Bool => {
+            let p = rng.gen_range(0.0..=1.0);
+            while values.len() < len {
+                let run = random_run(rng, len - values.len());
+                let v = rng.gen_bool(p);
+                values.extend((0..run).map(|_| RawValue::Bool(v)));
+            }
+        }
+        RawKind::U64 => {
+            let shape = rng.gen_range(0..5);
+            let mut next = rng.gen_range(0..1 << 20);
+            while values.len() < len {
+                let v = match shape {
+                    // Small numbers
+                    0 => rng.gen_range(0..256),
+                    // Any numbers
+                    1 => rng.gen(),
+                    // Mostly the same number
+                    2 => {
+                        if rng.gen_bool(0.05) {
+                            rng.gen()
+                        } else {
+                            7
+                        }
+                    }
+                    // Increasing numbers
+                    3 => {
+                        next += rng.gen_range(0..1000);
+                        next
+                    }
+                    // Numbers spread over a range of a few bits
+                    _ => {
+                        let bits = rng.gen_range(1..20);
+                        (1 << 40) + rng.gen_range(0..1 << bits)
+                    }
+                };
+                let run = random_run(rng, len - values.len());
+                values.extend((0..run).map(|_| RawValue::U64(v)));
+            }
+        }
+        RawKind::Bytes => {
+            let shape = rng.gen_range(0..4);
+            let width = rng.gen_range(0..12);
+            let distinct = (0..rng.gen_range(1..20))
+                .map(|_| random_bytes(rng, width))
+                .collect::<Vec<_>>();
+            while values.len() < len {
+                let v = match shape {
+                    // Any bytes
+                    0 => {
+                        let width = rng.gen_range(0..40);
+                        random_bytes(rng, width)
+                    }
+                    // Bytes of one length
+                    1 => random_bytes(rng, width),
+                    // A few distinct values
+                    2 => distinct[rng.gen_range(0..distinct.len())].clone(),
+                    // Mostly empty
+                    _ => {
+                        if rng.gen_bool(0.05) {
+                            random_bytes(rng, width)
+                        } else {
+                            Vec::new()
+                        }
+                    }
+                };
+                let run = random_run(rng, len - values.len());
+                values.extend((0..run).map(|_| RawValue::Bytes(v.clone())));
+            }
+        }
+    }
+    values
+}
+
+/// The length of a run of equal values, which is usually one, and at most
+/// `left`
+fn random_run(rng: &mut impl Rng, left: usize) -> usize {
+    let run = match rng.gen_range(0..4) {
+        0 => rng.gen_range(1..1000),
+        1 => rng.gen_range(1..10),
+        _ => 1,
+    };
+    run.min(left)
+}
+
+fn random_bytes(rng: &mut impl Rng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+/// A random row of the raw columns of `schema`
+pub fn random_row(rng: &mut impl Rng, schema: &TableSchema) -> RawRow {
+    schema
+        .raw_columns()
+        .map(|c| random_column(rng, c.kind(), 1).remove(0))
+        .collect()
+}
+
+/// `n` random rows of the raw columns of `schema`, each column in a shape
+/// picked at random, so the rows may share values
+pub fn random_rows(rng: &mut impl Rng, schema: &TableSchema, n: usize) -> Vec<RawRow> {
+    let columns = schema
+        .raw_columns()
+        .map(|c| random_column(rng, c.kind(), n))
+        .collect::<Vec<_>>();
+    (0..n)
+        .map(|i| columns.iter().map(|c| c[i].clone()).collect())
+        .collect()
+}
+
+/// Panic unless `values`, which must all be of one kind, decode as they were
+/// encoded in each format that can hold them, and in the formats picked for
+/// them with and without chunks of at most a few rows
+pub fn assert_round_trips(values: &[RawValue]) {
+    let Some(kind) = values.first().map(|v| v.kind()) else {
+        return;
+    };
+    let decoded = |encoded: Vec<u8>, what: &dyn Debug| {
+        let column =
+            RawColumn::decode(encoded).unwrap_or_else(|e| panic!("cannot decode {what:?}: {e}"));
+        let read = column
+            .read_values()
+            .unwrap_or_else(|e| panic!("cannot read {what:?}: {e}"));
+        assert!(read == values, "{what:?} changes {values:?} to {read:?}");
+        column.format()
+    };
+    for format in ColumnFormat::ALL {
+        if format.kind() != kind || format.estimate_size(values).is_none() {
+            continue;
+        }
+        let mut encoded = Vec::new();
+        format
+            .encode(&mut encoded, values)
+            .unwrap_or_else(|e| panic!("cannot encode in {format:?}: {e}"));
+        assert_eq!(decoded(encoded, &format), format);
+    }
+    for options in [
+        EncodeOptions::default(),
+        EncodeOptions::default().max_chunk_rows(3),
+    ] {
+        let mut encoded = Vec::new();
+        RawColumn::write_values_with(&mut encoded, kind, values, options)
+            .unwrap_or_else(|e| panic!("cannot encode with {options:?}: {e}"));
+        decoded(encoded, &options);
+    }
+}
+
+/// Panic unless each value of `values` agrees with its lens, see
+/// [`check_lens`], and the columns of their raw values round trip, see
+/// [`assert_round_trips`], and convert back to the values
+pub fn assert_lens_round_trips<T: Lens + Clone + PartialEq + Debug>(values: &[T]) {
+    let mut columns = vec![Vec::new(); T::RAW_KINDS.len()];
+    for v in values {
+        if let Err(e) = check_lens(v) {
+            panic!("{v:?}: {e}");
+        }
+        let raw: RawValues = v.clone().into();
+        for (column, raw) in columns.iter_mut().zip(raw.0) {
+            column.push(raw);
+        }
+    }
+    for column in columns.iter() {
+        assert_round_trips(column);
+    }
+    let mut encoded = columns
+        .iter()
+        .zip(T::RAW_KINDS)
+        .map(|(column, &kind)| {
+            let mut bytes = Vec::new();
+            RawColumn::write_values(&mut bytes, kind, column).expect("values round trip");
+            RawColumn::decode(bytes)
+                .and_then(|c| c.read_values())
+                .expect("values round trip")
+                .into_iter()
+        })
+        .collect::<Vec<_>>();
+    for v in values {
+        let raw = RawValues(encoded.iter_mut().filter_map(|c| c.next()).collect());
+        match T::try_from(raw) {
+            Ok(read) => assert!(read == *v, "{v:?} reads back as {read:?}"),
+            Err(e) => panic!("{v:?} does not read back: {e}"),
+        }
+    }
+}
+
+#[test]
+fn random_round_trips() {
+    use crate::lens::{Decimal, ShardSequence};
+    use crate::ColumnSchema;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(2628);
+    for _ in 0..150 {
+        for kind in [RawKind::Bool, RawKind::U64, RawKind::Bytes] {
+            let len = rng.gen_range(1..2000);
+            assert_round_trips(&random_column(&mut rng, kind, len));
+        }
+    }
+
+    let mut schema = TableSchema::new("things");
+    schema.add_primary(ColumnSchema::<String>::new("name").raw());
+    schema.add_max(
+        ColumnSchema::<Duration>::new("took")
+            .raw()
+            .chain(ColumnSchema::<bool>::new("done").raw()),
+    );
+    let rows = random_rows(&mut rng, &schema, 50);
+    assert_eq!(rows.len(), 50);
+    for row in rows.iter().chain([&random_row(&mut rng, &schema)]) {
+        let kinds = row.values().iter().map(|v| v.kind()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [RawKind::Bytes, RawKind::U64, RawKind::U64, RawKind::Bool]
+        );
+    }
+
+    assert_lens_round_trips(&[Duration::new(3, 5), Duration::ZERO, Duration::new(1, 0)]);
+    assert_lens_round_trips(&(-50..50).map(Decimal::<2>).collect::<Vec<_>>());
+    assert_lens_round_trips(&[ShardSequence {
+        shard: 3,
+        sequence: u64::MAX,
+    }]);
+    assert_lens_round_trips(&["a".to_string(), String::new(), "a".to_string()]);
+}
@@ -1,3 +1,5 @@
+use crate::column::encoding::StorageError;
+
 /// The type of data actually stored in a column.
 ///
 /// This is in distinction from a logical [`Kind`], which might
@@ -35,6 +37,7 @@ impl RawValue {
         }
     }
 
+    /// Encode this value as bytes, tagged with its kind
     pub fn encode(&self) -> Vec<u8> {
         let mut v = vec![];
         match self {
@@ -56,26 +59,28 @@ impl RawValue {
         v
     }
 
-    pub fn decode(data: &[u8]) -> Result<(Self, &[u8]), std::io::Error> {
-        if data.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "no data",
-            ));
-        }
-
-        match data[0] {
-            0 => Ok((
-                Self::U64(u64::from_be_bytes(data[1..].try_into().unwrap())),
-                &[],
-            )),
-            1 => Ok((Self::Bool(data[1] != 0), &[])),
+    /// Decode a value, returning the remaining bytes
+    pub fn decode(data: &[u8]) -> Result<(Self, &[u8]), StorageError> {
+        let truncated = || StorageError::TruncatedRow("value ends early");
+        let (&kind, rest) = data
+            .split_first()
+            .ok_or(StorageError::TruncatedRow("no data"))?;
+        match kind {
+            0 => {
+                let mut number = [0; 8];
+                number.copy_from_slice(rest.get(..8).ok_or_else(truncated)?);
+                Ok((Self::U64(u64::from_be_bytes(number)), &rest[8..]))
+            }
+            1 => {
+                let (&b, rest) = rest.split_first().ok_or_else(truncated)?;
+                Ok((Self::Bool(b != 0), rest))
+            }
             2 => {
-                let len = data[1] as usize;
-                let bytes = data[2..2 + len].to_vec();
-                Ok((Self::Bytes(bytes), &data[2 + len..]))
+                let (&len, rest) = rest.split_first().ok_or_else(truncated)?;
+                let bytes = rest.get(..len as usize).ok_or_else(truncated)?;
+                Ok((Self::Bytes(bytes.to_vec()), &rest[len as usize..]))
             }
-            _ => unreachable!(),
+            _ => Err(StorageError::InvalidRow("value of an unknown kind")),
         }
     }
 }
@@ -174,4 +179,31 @@ mod test {
             assert_eq!(expected, output);
         }
     }
+
+    #[test]
+    fn decode_truncated() {
+        use crate::column::encoding::StorageError;
+
+        let data = RawValue::U64(7).encode();
+        for end in 0..data.len() {
+            let err = RawValue::decode(&data[..end]).unwrap_err();
+            assert!(matches!(err, StorageError::TruncatedRow(_)), "{err}");
+        }
+        let data = RawValue::Bytes(b"hello".to_vec()).encode();
+        for end in 0..data.len() {
+            assert!(RawValue::decode(&data[..end]).is_err());
+        }
+        assert!(RawValue::decode(&[1]).is_err());
+        assert!(RawValue::decode(&[3, 0]).is_err());
+
+        // Values that follow one another decode in turn.
+        let mut data = RawValue::U64(7).encode();
+        data.extend(RawValue::Bool(true).encode());
+        let (first, rest) = RawValue::decode(&data).unwrap();
+        assert_eq!(first, RawValue::U64(7));
+        assert_eq!(
+            RawValue::decode(rest).unwrap(),
+            (RawValue::Bool(true), &[][..])
+        );
+    }
 }